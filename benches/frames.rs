@@ -0,0 +1,55 @@
+//! The segmentation and the decoding of the ISO-TP frames.
+//!
+//! Baseline(release, 1 core):
+//!
+//! | bench                     | std2004  | std2016  |
+//! |---------------------------|----------|----------|
+//! | from_data/4 KB            | 1.43 µs  | 1.68 µs  |
+//! | from_data/64 KB           | -        | 23.9 µs  |
+//! | decode/single frame       | 20.5 ns  | 18.9 ns  |
+//! | decode/first frame        | 19.0 ns  | 19.4 ns  |
+//! | decode/consecutive frame  | 20.5 ns  | 19.9 ns  |
+//! | decode/flow control frame | 12.2 ns  | 14.0 ns  |
+//!
+//! Follow-up: `from_data` collects all the frames of the payload into a `Vec` before the first
+//! one is sent, the 64 KB payload is copied into 9363 frames up front.
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion};
+use isotp_rs::IsoTpFrame;
+use isotp_rs::can::CanIsoTpFrame;
+use hex_literal::hex;
+
+fn from_data(c: &mut Criterion) {
+    let mut group = c.benchmark_group("from_data");
+    let mut sizes = vec![("4 KB", 0xFFF)];
+    // the 2004 first frame holds up to 4095 bytes
+    if cfg!(feature = "std2016") {
+        sizes.push(("64 KB", 0x10000));
+    }
+    for (name, size) in sizes {
+        let data: Vec<u8> = (0..size).map(|v| v as u8).collect();
+        group.bench_function(name, |b| b.iter(|| {
+            black_box(CanIsoTpFrame::from_data(black_box(&data)).unwrap());
+        }));
+    }
+    group.finish();
+}
+
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    let frames = [
+        ("single frame", hex!("03 22 F1 90 AA AA AA AA")),
+        ("first frame", hex!("10 14 62 F1 90 4C 56 57")),
+        ("consecutive frame", hex!("21 31 32 33 34 35 36 37")),
+        ("flow control frame", hex!("30 00 00 AA AA AA AA AA")),
+    ];
+    for (name, data) in frames {
+        group.bench_function(name, |b| b.iter(|| {
+            black_box(CanIsoTpFrame::decode(black_box(data)).unwrap());
+        }));
+    }
+    group.finish();
+}
+
+criterion_group!(benches, from_data, decode);
+criterion_main!(benches);
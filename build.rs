@@ -18,4 +18,8 @@ fn main() {
             v
         )
     }
+
+    if std::env::var("CARGO_FEATURE_FFI").is_ok() && std::env::var("CARGO_FEATURE_ASYNC").is_ok() {
+        panic!("***`{}`*** the feature `ffi` wraps `SyncCan`, it can't be enabled with `async`.", crate_name);
+    }
 }
@@ -0,0 +1,91 @@
+//! An async UDS client on the tokio runtime, `AsyncCanIsoTp` is registered to `SyncCan` like
+//! the synchronous one and the request is awaited with its response by `AsyncCanIsoTp::request`.
+//!
+//! ```shell
+//! cargo run --example async_client --features tokio,mock
+//! ```
+
+#[cfg(not(feature = "async"))]
+use std::sync::mpsc::channel;
+#[cfg(not(feature = "async"))]
+use std::thread::spawn;
+#[cfg(not(feature = "async"))]
+use std::time::Duration;
+#[cfg(not(feature = "async"))]
+use isotp_rs::prelude::*;
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::driver::{VirtualBus, MOCK_CHANNEL};
+#[cfg(not(feature = "async"))]
+use isotp_rs::constant::{P2_ISO14229, P2_STAR_ISO14229};
+
+#[cfg(not(feature = "async"))]
+const CLIENT: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+#[cfg(not(feature = "async"))]
+const ECU: Address = Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF };
+
+#[cfg(not(feature = "async"))]
+fn main() -> anyhow::Result<()> {
+    let (client_driver, ecu_driver) = VirtualBus::pair();
+
+    // the ECU echoing the request with the positive response id
+    let mut ecu_can = SyncCan::new(ecu_driver);
+    let (tx, rx) = channel();
+    let ecu = SyncCanIsoTp::new(
+        MOCK_CHANNEL.to_string(),
+        ECU,
+        ecu_can.sender(),
+        <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+            if let Some(data) = event.into_data() {
+                let _ = tx.send(data);
+            }
+        }),
+    );
+    ecu_can.register_listener("ecu".into(), Box::new(ecu.clone()));
+    ecu_can.sync_start(100);
+    spawn(move || {
+        for mut request in rx {
+            request[0] |= 0x40;
+            let _ = ecu.write(false, request);
+        }
+    });
+
+    let mut can = SyncCan::new(client_driver);
+    let client = AsyncCanIsoTp::new(
+        MOCK_CHANNEL.to_string(),
+        CLIENT,
+        can.sender(),
+        <dyn IsoTpEventListener>::from_fn(|_: IsoTpEvent| {}),
+    );
+    can.register_listener("client".into(), Box::new(client.clone()));
+    can.sync_start(100);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    runtime.block_on(async {
+        let p2 = Duration::from_millis(P2_ISO14229 as u64);
+        let p2_star = Duration::from_millis(P2_STAR_ISO14229 as u64);
+        // a single frame and a multi-frame request
+        for request in [vec![0x3E, 0x00], (0..0x40).collect::<Vec<u8>>()] {
+            let response = client.request(request.clone(), p2, p2_star).await?;
+            println!("client <- {}", hex::encode(&response));
+            assert_eq!(response[0], request[0] | 0x40);
+            assert_eq!(response[1..], request[1..]);
+        }
+        Ok::<_, anyhow::Error>(())
+    })?;
+
+    can.stop();
+    ecu_can.stop();
+    Ok(())
+}
+
+/// The example runs on `SyncCan`, which isn't compiled with the feature `async`.
+#[cfg(feature = "async")]
+fn main() {
+    eprintln!("run the example without the feature `async`");
+}
+
+#[cfg(not(feature = "async"))]
+#[test]
+fn run() -> anyhow::Result<()> {
+    main()
+}
@@ -0,0 +1,116 @@
+//! An ECU simulator answering the UDS requests of a tester over the mock bus.
+//!
+//! The ISO-TP listener is called by the receiving thread of `SyncCan`, so the requests are
+//! handed over to a worker thread writing the responses, a blocking `write` in the listener
+//! would wait for the confirmations delivered by the same thread.
+//!
+//! ```shell
+//! cargo run --example ecu_simulator --features mock
+//! ```
+
+#[cfg(not(feature = "async"))]
+use std::sync::mpsc::{channel, Receiver};
+#[cfg(not(feature = "async"))]
+use std::thread::spawn;
+#[cfg(not(feature = "async"))]
+use std::time::Duration;
+#[cfg(not(feature = "async"))]
+use isotp_rs::prelude::*;
+#[cfg(not(feature = "async"))]
+use isotp_rs::FlowControlContext;
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::driver::{MockDriver, VirtualBus, MOCK_CHANNEL};
+
+#[cfg(not(feature = "async"))]
+const TESTER: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+#[cfg(not(feature = "async"))]
+const ECU: Address = Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF };
+#[cfg(not(feature = "async"))]
+const VIN: &[u8] = b"WDD2130041A123456";
+
+/// The response of a UDS request, the negative response 0x11(service not supported) by default.
+#[cfg(not(feature = "async"))]
+fn respond(request: &[u8]) -> Vec<u8> {
+    match request {
+        [0x10, session] => vec![0x50, *session, 0x00, 0x32, 0x01, 0xF4],
+        [0x22, 0xF1, 0x90] => [&[0x62, 0xF1, 0x90], VIN].concat(),
+        [0x3E, 0x00] => vec![0x7E, 0x00],
+        [service, ..] => vec![0x7F, *service, 0x11],
+        [] => vec![],
+    }
+}
+
+/// Start the ECU on `can`, the requests are answered until the bus is stopped.
+#[cfg(not(feature = "async"))]
+fn start_ecu(can: &mut SyncCan<MockDriver, String, CanMessage>) {
+    let (tx, rx) = channel();
+    let ecu = SyncCanIsoTp::new(
+        MOCK_CHANNEL.to_string(),
+        ECU,
+        can.sender(),
+        <dyn IsoTpEventListener>::from_fn(move |event| {
+            if let Some(data) = event.into_data() {
+                let _ = tx.send(data);
+            }
+        }),
+    )
+        .with_flow_control(FlowControlContext::ISO15765_4);
+    can.register_listener("ecu".into(), Box::new(ecu.clone()));
+
+    spawn(move || {
+        for request in rx {
+            println!("ECU    <- {}", hex::encode(&request));
+            let response = respond(&request);
+            if let Err(e) = ecu.write(false, response) {
+                println!("ECU    response failed: {}", e);
+            }
+        }
+    });
+}
+
+#[cfg(not(feature = "async"))]
+fn main() -> anyhow::Result<()> {
+    let (tester_driver, ecu_driver) = VirtualBus::pair();
+    let mut ecu_can = SyncCan::new(ecu_driver);
+    start_ecu(&mut ecu_can);
+    ecu_can.sync_start(100);
+
+    let mut tester_can = SyncCan::new(tester_driver);
+    let (tx, rx) = channel();
+    let tester = SyncCanIsoTp::new(
+        MOCK_CHANNEL.to_string(),
+        TESTER,
+        tester_can.sender(),
+        <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+            if let Some(data) = event.into_data() {
+                let _ = tx.send(data);
+            }
+        }),
+    );
+    tester_can.register_listener("tester".into(), Box::new(tester.clone()));
+    tester_can.sync_start(100);
+
+    let responses: &Receiver<Vec<u8>> = &rx;
+    for request in [vec![0x10, 0x03], vec![0x22, 0xF1, 0x90], vec![0x31, 0x01, 0xFF, 0x00]] {
+        tester.write(false, request.clone())?;
+        let response = responses.recv_timeout(Duration::from_secs(1))?;
+        println!("tester <- {}", hex::encode(&response));
+        assert_eq!(response, respond(&request));
+    }
+
+    tester_can.stop();
+    ecu_can.stop();
+    Ok(())
+}
+
+/// The example runs on `SyncCan`, which isn't compiled with the feature `async`.
+#[cfg(feature = "async")]
+fn main() {
+    eprintln!("run the example without the feature `async`");
+}
+
+#[cfg(not(feature = "async"))]
+#[test]
+fn run() -> anyhow::Result<()> {
+    main()
+}
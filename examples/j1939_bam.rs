@@ -0,0 +1,83 @@
+//! A J1939 receiver of the broadcast messages(BAM), e.g. the DM1 of the active diagnostic
+//! trouble codes, the messages over 8 bytes are reassembled by `J1939Tp`.
+//!
+//! ```shell
+//! cargo run --example j1939_bam --features j1939,mock
+//! ```
+
+#[cfg(not(feature = "async"))]
+use std::sync::mpsc::{channel, Sender};
+#[cfg(not(feature = "async"))]
+use std::time::Duration;
+#[cfg(not(feature = "async"))]
+use isotp_rs::prelude::*;
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::driver::{VirtualBus, MOCK_CHANNEL};
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::j1939::{J1939Tp, J1939TpEvent, J1939TpEventListener, SourceAddress};
+
+/// The PGN of DM1.
+#[cfg(not(feature = "async"))]
+const PGN_DM1: u32 = 0xFECA;
+
+/// Forward the received messages to a channel.
+#[cfg(not(feature = "async"))]
+struct Forward(Sender<J1939TpEvent>);
+
+#[cfg(not(feature = "async"))]
+impl J1939TpEventListener for Forward {
+    fn on_tp_event(&mut self, event: J1939TpEvent) {
+        let _ = self.0.send(event);
+    }
+}
+
+#[cfg(not(feature = "async"))]
+fn main() -> anyhow::Result<()> {
+    let (engine_driver, receiver_driver) = VirtualBus::pair();
+
+    let mut receiver_can = SyncCan::new(receiver_driver);
+    let (tx, rx) = channel();
+    let receiver = J1939Tp::new(MOCK_CHANNEL.to_string(), 0xF9, receiver_can.sender(), Box::new(Forward(tx)));
+    receiver_can.register_listener("receiver".into(), Box::new(receiver));
+    receiver_can.sync_start(100);
+
+    // the engine(0x00) broadcasts the lamp status and 3 DTCs
+    let mut engine_can = SyncCan::new(engine_driver);
+    let (engine_tx, _) = channel();
+    let engine = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x00, engine_can.sender(), Box::new(Forward(engine_tx)));
+    engine_can.register_listener("engine".into(), Box::new(engine.clone()));
+    engine_can.sync_start(100);
+    let dm1 = [
+        0x04, 0xFF,                 // the lamps
+        0x64, 0x00, 0x03, 0x01,     // SPN 100, FMI 3
+        0x6E, 0x00, 0x00, 0x01,     // SPN 110, FMI 0
+        0xBE, 0x00, 0x12, 0x02,     // SPN 190, FMI 18
+    ];
+    engine.broadcast(PGN_DM1, &dm1)?;
+
+    match rx.recv_timeout(Duration::from_secs(1))? {
+        J1939TpEvent::DataReceived(message) => {
+            let id = message.id();
+            println!("PGN {:05X} from {:?}: {}", id.pgn().into_bits(), id.source_address(), hex::encode(message.data()));
+            assert_eq!(id.source_address(), SourceAddress::Some(0x00));
+            assert_eq!(message.data(), dm1);
+        },
+        event => anyhow::bail!("unexpected event: {:?}", event),
+    }
+
+    engine_can.stop();
+    receiver_can.stop();
+    Ok(())
+}
+
+/// The example runs on `SyncCan`, which isn't compiled with the feature `async`.
+#[cfg(feature = "async")]
+fn main() {
+    eprintln!("run the example without the feature `async`");
+}
+
+#[cfg(not(feature = "async"))]
+#[test]
+fn run() -> anyhow::Result<()> {
+    main()
+}
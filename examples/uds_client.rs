@@ -0,0 +1,143 @@
+//! A synchronous UDS client, a request is written and its response is waited within P2,
+//! the response pending(NRC 0x78) extends the time to P2*.
+//!
+//! The ECU is a scripted node on the other end of the mock bus, see `ecu_simulator` for one
+//! built on `SyncCanIsoTp`.
+//!
+//! ```shell
+//! cargo run --example uds_client --features mock
+//! ```
+
+#[cfg(not(feature = "async"))]
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+#[cfg(not(feature = "async"))]
+use std::thread::{sleep, spawn, JoinHandle};
+#[cfg(not(feature = "async"))]
+use std::time::Duration;
+#[cfg(not(feature = "async"))]
+use isotp_rs::prelude::*;
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::driver::{MockDriver, VirtualBus, MOCK_CHANNEL};
+#[cfg(not(feature = "async"))]
+use isotp_rs::can::identifier::Id;
+#[cfg(not(feature = "async"))]
+use isotp_rs::constant::{P2_ISO14229, P2_STAR_ISO14229};
+
+#[cfg(not(feature = "async"))]
+const CLIENT: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+
+/// The UDS client of an ECU.
+#[cfg(not(feature = "async"))]
+struct UdsClient {
+    iso_tp: SyncCanIsoTp<String, CanMessage>,
+    responses: Receiver<Vec<u8>>,
+}
+
+#[cfg(not(feature = "async"))]
+impl UdsClient {
+    fn new(can: &mut SyncCan<MockDriver, String, CanMessage>) -> Self {
+        let (tx, responses) = channel();
+        let iso_tp = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            CLIENT,
+            can.sender(),
+            <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+                if let Some(data) = event.into_data() {
+                    let _ = tx.send(data);
+                }
+            }),
+        );
+        can.register_listener("uds".into(), Box::new(iso_tp.clone()));
+
+        Self { iso_tp, responses }
+    }
+
+    /// Write the request and wait for the final response of the service.
+    fn request(&self, request: &[u8]) -> anyhow::Result<Vec<u8>> {
+        // the stale responses of the timed out requests
+        while self.responses.try_recv().is_ok() {}
+
+        self.iso_tp.write(false, request.to_vec())?;
+        let mut timeout = Duration::from_millis(P2_ISO14229 as u64);
+        loop {
+            let response = match self.responses.recv_timeout(timeout) {
+                Ok(v) => v,
+                Err(RecvTimeoutError::Timeout) =>
+                    return Err(Error::Timeout { value: timeout.as_millis() as u64, unit: "ms" }.into()),
+                Err(e) => return Err(e.into()),
+            };
+            match response.as_slice() {
+                [0x7F, service, 0x78] if *service == request[0] => {
+                    println!("client <- response pending");
+                    timeout = Duration::from_millis(P2_STAR_ISO14229 as u64);
+                },
+                _ => return Ok(response),
+            }
+        }
+    }
+}
+
+/// A scripted ECU transmitting the raw frames, it answers the reading of the VIN after
+/// a response pending.
+#[cfg(not(feature = "async"))]
+fn scripted_ecu(driver: MockDriver) -> JoinHandle<()> {
+    spawn(move || {
+        let mut frames = Vec::new();
+        while frames.is_empty() {
+            frames = driver.receive(MOCK_CHANNEL.into(), Some(1000)).unwrap_or_default();
+        }
+        assert_eq!(frames[0].data()[..4], [0x03, 0x22, 0xF1, 0x90]);
+
+        let send = |frame: CanIsoTpFrame| {
+            let mut frame = CanMessage::from_iso_tp(Id::Standard(0x7E8), frame, None).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            driver.transmit(frame, None).unwrap();
+        };
+        send(CanIsoTpFrame::decode([0x03, 0x7F, 0x22, 0x78, 0xAA, 0xAA, 0xAA, 0xAA]).unwrap());
+        sleep(Duration::from_millis(20));
+
+        let mut frames = CanIsoTpFrame::from_data([&[0x62, 0xF1, 0x90], b"WDD2130041A123456".as_slice()].concat())
+            .unwrap()
+            .into_iter();
+        send(frames.next().unwrap());
+        // the flow control of the client
+        let mut received = Vec::new();
+        while received.is_empty() {
+            received = driver.receive(MOCK_CHANNEL.into(), Some(1000)).unwrap_or_default();
+        }
+        for frame in frames {
+            send(frame);
+            sleep(Duration::from_millis(10));
+        }
+    })
+}
+
+#[cfg(not(feature = "async"))]
+fn main() -> anyhow::Result<()> {
+    let (client_driver, ecu_driver) = VirtualBus::pair();
+    let ecu = scripted_ecu(ecu_driver);
+
+    let mut can = SyncCan::new(client_driver);
+    let client = UdsClient::new(&mut can);
+    can.sync_start(100);
+
+    let response = client.request(&[0x22, 0xF1, 0x90])?;
+    println!("client <- {}", hex::encode(&response));
+    assert_eq!(&response[3..], b"WDD2130041A123456");
+
+    ecu.join().expect("the ECU panicked");
+    can.stop();
+    Ok(())
+}
+
+/// The example runs on `SyncCan`, which isn't compiled with the feature `async`.
+#[cfg(feature = "async")]
+fn main() {
+    eprintln!("run the example without the feature `async`");
+}
+
+#[cfg(not(feature = "async"))]
+#[test]
+fn run() -> anyhow::Result<()> {
+    main()
+}
@@ -0,0 +1,251 @@
+#ifndef ISOTP_RS_H
+#define ISOTP_RS_H
+
+/* Generated with cbindgen:0.27.0 */
+
+/* Generated by cbindgen from src/ffi.rs, don't edit it manually. */
+
+#include <stdbool.h>
+#include <stddef.h>
+#include <stdint.h>
+
+#define ISOTP_EVENT_WAIT 0
+
+#define ISOTP_EVENT_FIRST_FRAME 1
+
+/**
+ * `data` and `len` are the received data.
+ */
+#define ISOTP_EVENT_DATA_RECEIVED 2
+
+/**
+ * `data` is NULL and `len` is the [`Error::code`].
+ */
+#define ISOTP_EVENT_ERROR 3
+
+/**
+ * `data` is NULL and `len` is the count of bytes written, verbose only.
+ */
+#define ISOTP_EVENT_TX_COMPLETED 4
+
+/**
+ * Verbose only.
+ */
+#define ISOTP_EVENT_FLOW_CONTROL_RECEIVED 5
+
+/**
+ * Verbose only.
+ */
+#define ISOTP_EVENT_FLOW_CONTROL_SENT 6
+
+/**
+ * `data` and `len` are the data received before the error, which is reported by the failed `isotp_request`.
+ */
+#define ISOTP_EVENT_RECEPTION_ABORTED 7
+
+/**
+ * `data` is NULL and `len` is N_Br of the delayed flow control in milliseconds.
+ */
+#define ISOTP_EVENT_FLOW_CONTROL_DELAYED 8
+
+/**
+ * The flag of the extended identifiers.
+ */
+#define ISOTP_EFF_FLAG 2147483648
+
+#define ISOTP_ERROR_DEVICE_ERROR 100
+
+#define ISOTP_ERROR_DEVICE 101
+
+#define ISOTP_ERROR_BUS_OFF 102
+
+#define ISOTP_ERROR_EMPTY_PDU 200
+
+#define ISOTP_ERROR_INVALID_PDU 201
+
+#define ISOTP_ERROR_INVALID_ST_MIN 202
+
+#define ISOTP_ERROR_INVALID_SEQUENCE 203
+
+#define ISOTP_ERROR_MIX_FRAMES 204
+
+#define ISOTP_ERROR_OVERLOAD_FLOW 205
+
+#define ISOTP_ERROR_INVALID_DATA_LENGTH 206
+
+#define ISOTP_ERROR_LENGTH_OUT_OF_RANGE 207
+
+#define ISOTP_ERROR_RECEPTION_RESTARTED 208
+
+#define ISOTP_ERROR_INVALID_FLOW_STATUS 209
+
+#define ISOTP_ERROR_FRAME 210
+
+#define ISOTP_ERROR_INVALID_PARAM 300
+
+#define ISOTP_ERROR_CONVERT 301
+
+#define ISOTP_ERROR_UNSUPPORTED 302
+
+#define ISOTP_ERROR_CONTEXT 303
+
+#define ISOTP_ERROR_LISTEN_ONLY 304
+
+#define ISOTP_ERROR_TIMEOUT 400
+
+#define ISOTP_ERROR_ABORTED 401
+
+#define ISOTP_ERROR_SHUTDOWN 402
+
+/**
+ * The driver of a handle.
+ */
+typedef enum IsoTpDriverKind {
+  /**
+   * The frames are transmitted by [`IsoTpConfig::transmit`] and received by [`isotp_receive_frame`].
+   */
+  IsoTpDriverKind_Callback = 0,
+  /**
+   * The endpoint [`IsoTpConfig::mock_endpoint`] of [`IsoTpConfig::mock_bus`], requires the `mock` feature.
+   */
+  IsoTpDriverKind_Mock = 1,
+  /**
+   * The SocketCAN interface [`IsoTpConfig::channel`], requires the `socketcan` feature.
+   */
+  IsoTpDriverKind_SocketCan = 2,
+} IsoTpDriverKind;
+
+/**
+ * The ISO-TP stack created by [`isotp_create`].
+ */
+typedef struct IsoTpHandle IsoTpHandle;
+
+/**
+ * The virtual bus of the mock driver.
+ */
+typedef struct IsoTpMockBus IsoTpMockBus;
+
+/**
+ * Transmit a frame by the user, returns 0 on success.
+ */
+typedef int (*IsoTpTransmitFn)(uint32_t id, const uint8_t *data, size_t len, void *user);
+
+/**
+ * The configuration of [`isotp_create`].
+ */
+typedef struct IsoTpConfig {
+  enum IsoTpDriverKind driver;
+  /**
+   * The channel name, the default channel is used when NULL.
+   */
+  const char *channel;
+  uint32_t tx_id;
+  uint32_t rx_id;
+  uint32_t fid;
+  /**
+   * Whether the transmission and flow control events are reported.
+   */
+  bool verbose;
+  IsoTpTransmitFn transmit;
+  void *transmit_user;
+  const struct IsoTpMockBus *mock_bus;
+  size_t mock_endpoint;
+} IsoTpConfig;
+
+/**
+ * Receive an event, see the `ISOTP_EVENT_*` codes.
+ */
+typedef void (*IsoTpEventFn)(int event, const uint8_t *data, size_t len, void *user);
+
+#ifdef __cplusplus
+extern "C" {
+#endif // __cplusplus
+
+/**
+ * Create a handle with `config`, NULL when the config is invalid or the driver fails to open.
+ *
+ * # Safety
+ *
+ * `config` must point to a valid [`IsoTpConfig`] and its `channel` must be NULL or a NUL-terminated string.
+ */
+struct IsoTpHandle *isotp_create(const struct IsoTpConfig *config);
+
+/**
+ * Write `data`, returns 0 when all frames are sent.
+ *
+ * # Safety
+ *
+ * `handle` must be created by [`isotp_create`] and `data` must be valid for `len` bytes.
+ */
+int isotp_write(struct IsoTpHandle *handle, const uint8_t *data, size_t len, bool functional);
+
+/**
+ * Write the request `req` and wait for the response up to `timeout_ms`,
+ * returns the length of the response copied to `resp_buf`.
+ *
+ * It must not be called from the event callback.
+ *
+ * # Safety
+ *
+ * `handle` must be created by [`isotp_create`], `req` must be valid for `req_len` bytes
+ * and `resp_buf` must be valid for `resp_cap` bytes.
+ */
+int isotp_request(struct IsoTpHandle *handle,
+                  const uint8_t *req,
+                  size_t req_len,
+                  uint8_t *resp_buf,
+                  size_t resp_cap,
+                  uint32_t timeout_ms);
+
+/**
+ * Set the event `callback` with the `user` data, a NULL callback removes it.
+ *
+ * The callback is called from the receiving thread of the handle.
+ *
+ * # Safety
+ *
+ * `handle` must be created by [`isotp_create`].
+ */
+int isotp_set_listener(struct IsoTpHandle *handle, IsoTpEventFn callback, void *user);
+
+/**
+ * Pass a frame received by the user to a handle of [`IsoTpDriverKind::Callback`].
+ *
+ * # Safety
+ *
+ * `handle` must be created by [`isotp_create`] and `data` must be valid for `len` bytes.
+ */
+int isotp_receive_frame(struct IsoTpHandle *handle, uint32_t id, const uint8_t *data, size_t len);
+
+/**
+ * Stop and free a handle, NULL is ignored.
+ *
+ * # Safety
+ *
+ * `handle` must be created by [`isotp_create`] and not used after.
+ */
+void isotp_destroy(struct IsoTpHandle *handle);
+
+#if defined(ISOTP_RS_MOCK)
+/**
+ * Create a virtual bus with `endpoints` endpoints for [`IsoTpDriverKind::Mock`].
+ */
+struct IsoTpMockBus *isotp_mock_bus_create(size_t endpoints);
+#endif
+
+#if defined(ISOTP_RS_MOCK)
+/**
+ * Free a virtual bus after the handles on it are destroyed, NULL is ignored.
+ *
+ * # Safety
+ *
+ * `bus` must be created by [`isotp_mock_bus_create`] and not used after.
+ */
+void isotp_mock_bus_destroy(struct IsoTpMockBus *bus);
+#endif
+
+#ifdef __cplusplus
+}  // extern "C"
+#endif  // __cplusplus
+
+#endif  /* ISOTP_RS_H */
@@ -1,20 +1,38 @@
 mod constant;
 pub use constant::*;
 
+pub mod dlc;
+pub mod error_frame;
+#[cfg(feature = "std")]
 pub mod driver;
 
 pub mod frame;
 pub mod identifier;
+pub mod matcher;
+pub mod message;
 
 pub mod isotp;
 
+#[cfg(feature = "std")]
+pub mod log;
+
 #[cfg(feature = "j1939")]
 pub mod j1939;
 
+#[cfg(feature = "embedded-can")]
+pub mod embedded;
+
+#[cfg(all(test, feature = "std"))]
+pub(crate) mod testing;
+
 mod utils;
 
+use alloc::{vec, vec::Vec};
+use core::fmt::{Debug, Formatter};
+use core::ops::{Deref, DerefMut};
 use crate::{FlowControlContext, FlowControlState, FrameType, IsoTpFrame};
 // use crate::can::constant::{CAN_FRAME_MAX_SIZE, DEFAULT_PADDING};
+use crate::can::identifier::Id;
 use crate::error::Error;
 
 /// ISO-TP address format.
@@ -42,6 +60,120 @@ pub struct Address {
     pub fid: u32,
 }
 
+impl Address {
+    /// The 29-bit normal fixed address from `source` to `target`.
+    ///
+    /// * `tx_id`: physical `0x18DA<target><source>`.
+    /// * `rx_id`: physical `0x18DA<source><target>`.
+    /// * `fid`: functional `0x18DB<target><source>`.
+    pub fn normal_fixed(source: u8, target: u8) -> Self {
+        Self {
+            tx_id: NORMAL_FIXED_PHYSICAL | (target as u32) << 8 | source as u32,
+            rx_id: NORMAL_FIXED_PHYSICAL | (source as u32) << 8 | target as u32,
+            fid: NORMAL_FIXED_FUNCTIONAL | (target as u32) << 8 | source as u32,
+        }
+    }
+
+    /// Parse the 29-bit normal fixed `id` to the source address, target address and address type,
+    /// the priority bits are ignored.
+    pub fn parse_normal_fixed(id: u32) -> Option<(u8, u8, AddressType)> {
+        if id > EFF_MASK {
+            return None;
+        }
+
+        let r#type = match id & 0x03FF_0000 {
+            v if v == NORMAL_FIXED_PHYSICAL & 0x03FF_0000 => AddressType::Physical,
+            v if v == NORMAL_FIXED_FUNCTIONAL & 0x03FF_0000 => AddressType::Functional,
+            _ => return None,
+        };
+
+        Some((id as u8, (id >> 8) as u8, r#type))
+    }
+
+    /// Whether the frame with `id` is received from the peer.
+    ///
+    /// The source and target addresses are matched instead of `rx_id` when the `format`
+    /// is [`AddressFormat::NormalFixed`].
+    pub fn is_rx(&self, id: u32, format: AddressFormat) -> bool {
+        match format {
+            AddressFormat::NormalFixed => match (Self::parse_normal_fixed(id), Self::parse_normal_fixed(self.rx_id)) {
+                (Some((source, target, AddressType::Physical)), Some((rx_source, rx_target, _))) =>
+                    source == rx_source && target == rx_target,
+                _ => false,
+            },
+            _ => id == self.rx_id,
+        }
+    }
+
+    /// Both directions share the identifier, e.g. a single-wire link, the frames echoed by
+    /// the driver are told from the peer ones by their [`Direct`](frame::Direct).
+    #[inline]
+    pub fn is_half_duplex(&self) -> bool {
+        self.tx_id == self.rx_id
+    }
+}
+
+impl AddressFormat {
+    /// The CAN identifier of the `bits` in this format.
+    #[inline]
+    pub fn can_id(&self, bits: u32) -> Id {
+        Id::from_bits(bits, *self == Self::NormalFixed)
+    }
+}
+
+/// The addressing of the ISO-TP frames transmitted, encoded by
+/// [`FrameMut::from_iso_tp_with`](frame::FrameMut::from_iso_tp_with).
+///
+/// * `format`: the address format of the identifier.
+/// * `extension`: the AE byte of the extended or mixed addressing, it precedes the PCI.
+/// * `padding`: the padding byte, [`DEFAULT_PADDING`] when `None`.
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct AddressContext {
+    pub format: AddressFormat,
+    pub extension: Option<u8>,
+    pub padding: Option<u8>,
+}
+
+impl AddressContext {
+    /// The context of `format` without the AE byte.
+    #[inline]
+    pub fn new(format: AddressFormat) -> Self {
+        Self { format, ..Default::default() }
+    }
+
+    #[inline]
+    pub fn with_extension(mut self, extension: u8) -> Self {
+        self.extension = Some(extension);
+        self
+    }
+
+    #[inline]
+    pub fn with_padding(mut self, padding: u8) -> Self {
+        self.padding = Some(padding);
+        self
+    }
+
+    /// The CAN identifier of the `bits` in the format.
+    #[inline]
+    pub fn can_id(&self, bits: u32) -> Id {
+        self.format.can_id(bits)
+    }
+
+    /// Encode `frame` with the AE byte before the PCI.
+    ///
+    /// The padded length of the frame includes the AE byte, so its last byte is dropped, the frame
+    /// is segmented to leave a byte for the AE by the extended or mixed addressing.
+    pub fn encode(&self, frame: impl IsoTpFrame) -> Vec<u8> {
+        let mut data = frame.encode(self.padding);
+        if let Some(extension) = self.extension {
+            let len = data.len();
+            data.insert(0, extension);
+            data.truncate(len);
+        }
+        data
+    }
+}
+
 /// ISO-TP address type.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Default)]
 pub enum AddressType {
@@ -50,15 +182,111 @@ pub enum AddressType {
     Functional,
 }
 
+/// The payload of an ISO-TP frame, stored inline without allocation.
+///
+/// It dereferences to the valid bytes and holds at most [`PAYLOAD_MAX_SIZE`] bytes.
+#[derive(Clone, Copy)]
+pub struct FramePayload {
+    len: u8,
+    buf: [u8; PAYLOAD_MAX_SIZE],
+}
+
+impl FramePayload {
+    /// Copy the `data` into the payload, the caller ensures it fits.
+    #[inline]
+    pub(crate) fn from_slice(data: &[u8]) -> Self {
+        debug_assert!(data.len() <= PAYLOAD_MAX_SIZE);
+        let mut buf = [0; PAYLOAD_MAX_SIZE];
+        buf[..data.len()].copy_from_slice(data);
+        Self { len: data.len() as u8, buf }
+    }
+}
+
+impl Default for FramePayload {
+    fn default() -> Self {
+        Self { len: 0, buf: [0; PAYLOAD_MAX_SIZE] }
+    }
+}
+
+impl Deref for FramePayload {
+    type Target = [u8];
+    #[inline]
+    fn deref(&self) -> &Self::Target {
+        &self.buf[..self.len as usize]
+    }
+}
+
+impl DerefMut for FramePayload {
+    #[inline]
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.buf[..self.len as usize]
+    }
+}
+
+impl AsRef<[u8]> for FramePayload {
+    #[inline]
+    fn as_ref(&self) -> &[u8] {
+        self
+    }
+}
+
+impl Debug for FramePayload {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        Debug::fmt(&**self, f)
+    }
+}
+
+impl PartialEq for FramePayload {
+    fn eq(&self, other: &Self) -> bool {
+        **self == **other
+    }
+}
+
+impl Eq for FramePayload {}
+
+impl PartialEq<[u8]> for FramePayload {
+    fn eq(&self, other: &[u8]) -> bool {
+        **self == *other
+    }
+}
+
+impl<const N: usize> PartialEq<[u8; N]> for FramePayload {
+    fn eq(&self, other: &[u8; N]) -> bool {
+        **self == other[..]
+    }
+}
+
+impl PartialEq<Vec<u8>> for FramePayload {
+    fn eq(&self, other: &Vec<u8>) -> bool {
+        **self == other[..]
+    }
+}
+
+impl TryFrom<&[u8]> for FramePayload {
+    type Error = Error;
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value.len() {
+            ..=PAYLOAD_MAX_SIZE => Ok(Self::from_slice(value)),
+            v => Err(Error::LengthOutOfRange(v)),
+        }
+    }
+}
+
+impl From<FramePayload> for Vec<u8> {
+    fn from(value: FramePayload) -> Self {
+        value.to_vec()
+    }
+}
+
 /// ISO-TP frame define.
 #[derive(Debug, Clone)]
 pub enum CanIsoTpFrame {
     /// The ISO-TP single frame.
-    SingleFrame { data: Vec<u8> },
+    SingleFrame { data: FramePayload },
     /// The ISO-TP first frame.
-    FirstFrame { length: u32, data: Vec<u8> },
+    FirstFrame { length: u32, data: FramePayload },
     /// The ISO-TP consecutive frame.
-    ConsecutiveFrame { sequence: u8, data: Vec<u8> },
+    ConsecutiveFrame { sequence: u8, data: FramePayload },
     /// The ISO-TP flow control frame.
     FlowControlFrame(FlowControlContext)
 }
@@ -76,6 +304,13 @@ impl<'a> From<&'a CanIsoTpFrame> for FrameType {
 
 unsafe impl Send for CanIsoTpFrame {}
 
+impl CanIsoTpFrame {
+    /// Segment `data` as [`IsoTpFrame::from_data`], the consecutive frames start at `sequence`(the low 4 bits).
+    pub fn from_data_with_sequence<T: AsRef<[u8]>>(data: T, sequence: u8) -> Result<Vec<Self>, Error> {
+        utils::from_data(data.as_ref(), sequence & 0x0F)
+    }
+}
+
 impl IsoTpFrame for CanIsoTpFrame {
     fn decode<T: AsRef<[u8]>>(data: T) -> Result<Self, Error> {
         let data = data.as_ref();
@@ -94,13 +329,13 @@ impl IsoTpFrame for CanIsoTpFrame {
                     },
                     FrameType::Consecutive => {
                         let sequence = byte0 & 0x0F;
-                        Ok(Self::ConsecutiveFrame { sequence, data: Vec::from(&data[1..]) })
+                        Ok(Self::ConsecutiveFrame { sequence, data: FramePayload::try_from(&data[1..])? })
                     },
                     FrameType::FlowControl => {
                         // let suppress_positive = (data1 & 0x80) == 0x80;
                         let state = FlowControlState::try_from(byte0 & 0x0F)?;
-                        let fc = FlowControlContext::new(state, data[1], data[2])?;
-                        Ok(Self::FlowControlFrame(fc))
+                        // a reserved STmin received is taken as the max one, as ISO 15765-2
+                        Ok(Self::FlowControlFrame(FlowControlContext::from_raw(state, data[1], data[2])))
                     },
                 }
             }
@@ -116,10 +351,10 @@ impl IsoTpFrame for CanIsoTpFrame {
             Self::FirstFrame { length, data } => {
                 utils::encode_first(length, data)
             },
-            Self::ConsecutiveFrame { sequence, mut data } => {
+            Self::ConsecutiveFrame { sequence, data } => {
                 let mut result = vec![FrameType::Consecutive as u8 | sequence];
-                result.append(&mut data);
-                result.resize(CAN_FRAME_MAX_SIZE, padding.unwrap_or(DEFAULT_PADDING));
+                result.extend_from_slice(&data);
+                utils::pad(&mut result, padding);
                 result
             },
             Self::FlowControlFrame(context) => {
@@ -137,7 +372,7 @@ impl IsoTpFrame for CanIsoTpFrame {
     }
 
     fn from_data<T: AsRef<[u8]>>(data: T) -> Result<Vec<Self>, Error> {
-        utils::from_data(data.as_ref())
+        utils::from_data(data.as_ref(), crate::constant::CONSECUTIVE_SEQUENCE_START)
     }
 
     fn single_frame<T: AsRef<[u8]>>(data: T) -> Result<Self, Error> {
@@ -156,9 +391,37 @@ impl IsoTpFrame for CanIsoTpFrame {
 
 #[cfg(test)]
 mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
     use hex_literal::hex;
-    use crate::can::{CAN_FRAME_MAX_SIZE, CanIsoTpFrame, CONSECUTIVE_FRAME_SIZE, DEFAULT_PADDING, FIRST_FRAME_SIZE_2004};
-    use crate::{FlowControlState, IsoTpFrame};
+    use crate::can::{Address, AddressFormat, AddressType, CAN_FRAME_MAX_SIZE, CanIsoTpFrame, CONSECUTIVE_FRAME_SIZE, DEFAULT_PADDING, FIRST_FRAME_SIZE_2004, FramePayload, PAYLOAD_MAX_SIZE};
+    use crate::can::identifier::Id;
+    use crate::error::Error;
+    use crate::{FlowControlContext, FlowControlState, IsoTpFrame};
+
+    #[test]
+    fn test_normal_fixed() {
+        // tester 0xF1 to engine 0x00
+        let address = Address::normal_fixed(0xF1, 0x00);
+        assert_eq!(address, Address { tx_id: 0x18DA00F1, rx_id: 0x18DAF100, fid: 0x18DB00F1 });
+        assert_eq!(Address::normal_fixed(0x00, 0xF1), Address { tx_id: 0x18DAF100, rx_id: 0x18DA00F1, fid: 0x18DBF100 });
+
+        assert_eq!(Address::parse_normal_fixed(0x18DA00F1), Some((0xF1, 0x00, AddressType::Physical)));
+        assert_eq!(Address::parse_normal_fixed(0x18DB33F1), Some((0xF1, 0x33, AddressType::Functional)));
+        assert_eq!(Address::parse_normal_fixed(0x1CDAF100), Some((0x00, 0xF1, AddressType::Physical)));
+        assert_eq!(Address::parse_normal_fixed(0x18DC00F1), None);
+        assert_eq!(Address::parse_normal_fixed(0x19DA00F1), None);
+        assert_eq!(Address::parse_normal_fixed(0x7E8), None);
+        assert_eq!(Address::parse_normal_fixed(0x38DA00F1), None);
+
+        assert!(address.is_rx(0x18DAF100, AddressFormat::NormalFixed));
+        assert!(address.is_rx(0x1CDAF100, AddressFormat::NormalFixed));
+        assert!(!address.is_rx(0x18DBF100, AddressFormat::NormalFixed));
+        assert!(!address.is_rx(0x18DAF117, AddressFormat::NormalFixed));
+        assert!(!address.is_rx(0x1CDAF100, AddressFormat::Normal));
+
+        assert_eq!(AddressFormat::NormalFixed.can_id(0x7E0), Id::Extended(0x7E0));
+        assert_eq!(AddressFormat::Normal.can_id(0x7E0), Id::Standard(0x7E0));
+    }
 
     #[test]
     fn test_single() -> anyhow::Result<()> {
@@ -174,11 +437,59 @@ mod tests {
         }
         assert_eq!(frame.encode(Some(0x00)), data.to_vec());
 
-        let frame = CanIsoTpFrame::SingleFrame { data: hex!("1001").to_vec() };
+        let frame = CanIsoTpFrame::SingleFrame { data: hex!("1001").as_slice().try_into()? };
         assert_eq!(frame.encode(Some(0x00)), data.to_vec());
         Ok(())
     }
 
+    #[test]
+    fn test_single_empty() -> anyhow::Result<()> {
+        // the fuzz corpus, SF_DL 0 is reserved
+        for data in [
+            hex!("00 aa aa aa aa aa aa aa").as_slice(),
+            hex!("00 00 55 55 55 55 55 55").as_slice(),
+            hex!("00 00 00").as_slice(),
+        ] {
+            assert_eq!(CanIsoTpFrame::decode(data).unwrap_err(), Error::InvalidPdu(data.to_vec()));
+        }
+
+        assert_eq!(CanIsoTpFrame::from_data([]).unwrap_err(), Error::EmptyPdu);
+        assert_eq!(CanIsoTpFrame::single_frame([]).unwrap_err(), Error::EmptyPdu);
+        let frame = CanIsoTpFrame::single_frame(hex!("10 01"))?;
+        assert_eq!(frame.encode(Some(0x00)), hex!("02 10 01 00 00 00 00 00"));
+
+        Ok(())
+    }
+
+    #[cfg(all(feature = "std2016", not(feature = "can-fd")))]
+    #[test]
+    fn test_single_escape() -> anyhow::Result<()> {
+        match CanIsoTpFrame::decode(hex!("00 03 01 02 03 aa aa aa"))? {
+            CanIsoTpFrame::SingleFrame { data } => assert_eq!(data, hex!("01 02 03")),
+            _ => panic!("Invalid frame type"),
+        }
+        let data = hex!("00 07 01 02 03 04 05 06");
+        assert_eq!(CanIsoTpFrame::decode(data).unwrap_err(), Error::InvalidPdu(data.to_vec()));
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_first_min_length() {
+        // the fuzz corpus, the data fits in a single frame
+        for data in [
+            hex!("10 00 00 00 00 00 00 00"),
+            hex!("10 07 01 02 03 04 05 06"),
+            #[cfg(feature = "std2016")]
+            hex!("10 00 00 00 0f ff 30 30"),
+        ] {
+            assert_eq!(CanIsoTpFrame::decode(data).unwrap_err(), Error::InvalidPdu(data.to_vec()));
+        }
+        assert!(CanIsoTpFrame::decode(hex!("10 08 01 02 03 04 05 06")).is_ok());
+    }
+
+    #[cfg(not(feature = "can-fd"))]
     #[test]
     fn test_first() -> anyhow::Result<()> {
         let data = hex!("10 0f 62 f1 87 44 56 43");
@@ -196,13 +507,34 @@ mod tests {
 
         let frame = CanIsoTpFrame::FirstFrame {
             length: 0x0f,
-            data: hex!("62 f1 87 44 56 43").to_vec()
+            data: hex!("62 f1 87 44 56 43").as_slice().try_into()?
         };
         assert_eq!(frame.encode(None), data.to_vec());
 
         Ok(())
     }
 
+    #[cfg(all(feature = "std2016", not(feature = "can-fd")))]
+    #[test]
+    fn test_first_escape() -> anyhow::Result<()> {
+        let frames = CanIsoTpFrame::from_data(vec![0x30; 0x10000])?;
+        let data = frames[0].clone().encode(None);
+        assert_eq!(data, hex!("10 00 00 01 00 00 30 30"));
+        match CanIsoTpFrame::decode(&data)? {
+            CanIsoTpFrame::FirstFrame { length, data } => {
+                assert_eq!(length, 0x10000);
+                assert_eq!(data, hex!("30 30"));
+            },
+            _ => panic!("Invalid frame type"),
+        }
+
+        // no escape up to 4095 bytes
+        let frames = CanIsoTpFrame::from_data(vec![0x30; 0xBB8])?;
+        assert_eq!(frames[0].clone().encode(None), hex!("1B B8 30 30 30 30 30 30"));
+
+        Ok(())
+    }
+
     #[test]
     fn test_consecutive() -> anyhow::Result<()> {
         let data = hex!("21 37 45 32 30 30 30 30");
@@ -220,7 +552,7 @@ mod tests {
 
         let frame = CanIsoTpFrame::ConsecutiveFrame {
             sequence: 1,
-            data: hex!("37 45 32 30 30 30 30").to_vec()
+            data: hex!("37 45 32 30 30 30 30").as_slice().try_into()?
         };
         assert_eq!(frame.encode(None), data.to_vec());
         Ok(())
@@ -243,10 +575,51 @@ mod tests {
         assert_eq!(frame.encode(Some(0x55)), data.to_vec());
 
         let frame = CanIsoTpFrame::default_flow_ctrl_frame();
-        assert_eq!(frame.encode(Some(0x55)), hex!("30 00 0a 55 55 55 55 55"));
+        assert_eq!(frame.encode(Some(0x55)), hex!("30 0a 0a 55 55 55 55 55"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_flow_control_raw() -> anyhow::Result<()> {
+        let frame = CanIsoTpFrame::decode(hex!("30 08 AB 55 55 55 55 55"))?;
+        match frame {
+            CanIsoTpFrame::FlowControlFrame(context) => {
+                assert_eq!(context.raw_st_min(), 0xAB);
+                assert_eq!(context.st_min(), 0x7F);
+                assert_eq!(context.st_min_us(), 127_000);
+                assert_eq!(context.to_string(), "Continues BS: 8 STmin: 7F(raw: AB)");
+            },
+            _ => panic!("Invalid frame type"),
+        }
+
+        let context = FlowControlContext::new(FlowControlState::Continues, 0, 0xF5)?;
+        assert_eq!((context.raw_st_min(), context.st_min_us()), (0xF5, 500));
+        assert_eq!(context.to_string(), "Continues BS: 0 STmin: F5");
+
+        assert!(matches!(CanIsoTpFrame::decode(hex!("33 00 00")), Err(Error::InvalidFlowStatus(0x03))));
+        Ok(())
+    }
+
+    #[test]
+    fn test_payload() -> anyhow::Result<()> {
+        let payload = FramePayload::try_from(hex!("62 f1 87").as_slice())?;
+        assert_eq!(payload.len(), 3);
+        assert_eq!(payload, hex!("62 f1 87"));
+        assert_eq!(Vec::from(payload), hex!("62 f1 87").to_vec());
+        assert_eq!(format!("{:?}", payload), "[98, 241, 135]");
+
+        let data = [0x55; PAYLOAD_MAX_SIZE + 1];
+        assert!(FramePayload::try_from(&data[..PAYLOAD_MAX_SIZE]).is_ok());
+        assert_eq!(FramePayload::try_from(data.as_slice()).unwrap_err(), Error::LengthOutOfRange(PAYLOAD_MAX_SIZE + 1));
+
+        let mut data = vec![0x21];
+        data.extend([0x30; PAYLOAD_MAX_SIZE + 1]);
+        assert!(CanIsoTpFrame::decode(data).is_err());
+
         Ok(())
     }
 
+    #[cfg(not(feature = "can-fd"))]
     #[test]
     fn test_data_to_multi() -> anyhow::Result<()> {
         let data = hex!("62 f1 87 44 56 43 37 45 32 30 30 30 30 30 37").as_slice();
@@ -286,9 +659,7 @@ mod tests {
                     }
                     else {
                         let mut expect = vec![0x20 + (index % 16) as u8];
-                        for _ in 0..size {
-                            expect.push(0x30);
-                        }
+                        expect.extend(std::iter::repeat_n(0x30, size));
                         expect.resize(CAN_FRAME_MAX_SIZE, DEFAULT_PADDING);
                         assert_eq!(frame.encode(None), expect);
                     }
@@ -10,6 +10,7 @@ bitflags! {
     /// SocketCAN users by allowing generation of the all-in-one 32-bit identifier value.
     ///
     /// [socketcan]: https://www.kernel.org/doc/Documentation/networking/can.txt
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
     #[repr(transparent)]
     pub struct IdentifierFlags: u32 {
         /// The frame is using the extended format i.e. 29-bit extended identifiers.
@@ -29,19 +30,32 @@ pub const SFF_MASK: u32 = 0x0000_07FF;
 
 /// Mask for extended identifiers.
 pub const EFF_MASK: u32 = 0x1FFF_FFFF;
+/// The physical identifier base of the normal fixed address(`0x18DA<TA><SA>`).
+pub const NORMAL_FIXED_PHYSICAL: u32 = 0x18DA_0000;
+/// The functional identifier base of the normal fixed address(`0x18DB<TA><SA>`).
+pub const NORMAL_FIXED_FUNCTIONAL: u32 = 0x18DB_0000;
 /// The max sizeof can-frame's data.
 pub const CAN_FRAME_MAX_SIZE: usize = 8;
 /// The max sizeof canfd-frame's data.
 pub const CANFD_FRAME_MAX_SIZE: usize = 64;
+/// The max sizeof an ISO-TP frame's payload.
+#[cfg(not(feature = "can-fd"))]
+pub const PAYLOAD_MAX_SIZE: usize = CAN_FRAME_MAX_SIZE;
+#[cfg(feature = "can-fd")]
+pub const PAYLOAD_MAX_SIZE: usize = CANFD_FRAME_MAX_SIZE;
 /// Default padding value(0b1010_1010).
 pub const DEFAULT_PADDING: u8 = 0xAA;
 
+/// The max data of a single frame by ISO 15765-2:2004, SF_DL has 4 bits and 7 at most.
+///
+/// The CAN-FD frames aren't defined by it, they take the escape sequence of ISO 15765-2:2016.
 #[cfg(not(feature = "can-fd"))]
 pub const SINGLE_FRAME_SIZE_2004: usize = CAN_FRAME_MAX_SIZE - 1;
 #[cfg(feature = "can-fd")]
-pub const SINGLE_FRAME_SIZE_2004: usize = CANFD_FRAME_MAX_SIZE - 1;
+pub const SINGLE_FRAME_SIZE_2004: usize = CANFD_FRAME_MAX_SIZE - 2;
+/// The max data of a single frame by ISO 15765-2:2016, the escape sequence(SF_DL of 8 bits) is for CAN_DL > 8 only.
 #[cfg(not(feature = "can-fd"))]
-pub const SINGLE_FRAME_SIZE_2016: usize = CAN_FRAME_MAX_SIZE - 2;
+pub const SINGLE_FRAME_SIZE_2016: usize = CAN_FRAME_MAX_SIZE - 1;
 #[cfg(feature = "can-fd")]
 pub const SINGLE_FRAME_SIZE_2016: usize = CANFD_FRAME_MAX_SIZE - 2;
 
@@ -50,9 +64,9 @@ pub const FIRST_FRAME_SIZE_2004: usize = CAN_FRAME_MAX_SIZE - 2;
 #[cfg(feature = "can-fd")]
 pub const FIRST_FRAME_SIZE_2004: usize = CANFD_FRAME_MAX_SIZE - 2;
 #[cfg(not(feature = "can-fd"))]
-pub const FIRST_FRAME_SIZE_2016: usize = CAN_FRAME_MAX_SIZE - 5;
+pub const FIRST_FRAME_SIZE_2016: usize = CAN_FRAME_MAX_SIZE - 6;
 #[cfg(feature = "can-fd")]
-pub const FIRST_FRAME_SIZE_2016: usize = CANFD_FRAME_MAX_SIZE - 5;
+pub const FIRST_FRAME_SIZE_2016: usize = CANFD_FRAME_MAX_SIZE - 6;
 
 #[cfg(not(feature = "can-fd"))]
 pub const CONSECUTIVE_FRAME_SIZE: usize = CAN_FRAME_MAX_SIZE - 1;
@@ -0,0 +1,71 @@
+//! The mapping between the DLC code and the data length of the CAN 2.0 and CAN-FD frames.
+
+use crate::can::constant::{CAN_FRAME_MAX_SIZE, CANFD_FRAME_MAX_SIZE};
+
+/// The data lengths of the DLC codes 9 ~ 15 of CAN-FD.
+const FD_LENGTHS: [usize; 7] = [12, 16, 20, 24, 32, 48, 64];
+
+/// Returns the DLC code of the data length, `None` if the length can't be represented exactly.
+#[inline]
+pub fn len_to_dlc(length: usize) -> Option<u8> {
+    match length {
+        0..=CAN_FRAME_MAX_SIZE => Some(length as u8),
+        _ => FD_LENGTHS.iter()
+            .position(|v| *v == length)
+            .map(|v| v as u8 + 9),
+    }
+}
+
+/// Returns the data length of the DLC code, `None` if the code is greater than 15.
+#[inline]
+pub fn dlc_to_len(dlc: u8) -> Option<usize> {
+    match dlc {
+        0..=8 => Some(dlc as usize),
+        9..=15 => Some(FD_LENGTHS[dlc as usize - 9]),
+        _ => None,
+    }
+}
+
+/// Returns the smallest data length that can be represented and holds `length` bytes,
+/// `None` if the length is greater than 64.
+#[inline]
+pub fn pad_len(length: usize) -> Option<usize> {
+    match length {
+        0..=CAN_FRAME_MAX_SIZE => Some(length),
+        9..=CANFD_FRAME_MAX_SIZE => FD_LENGTHS.iter()
+            .find(|v| **v >= length)
+            .copied(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dlc_to_len, len_to_dlc, pad_len};
+
+    #[test]
+    fn test_dlc() {
+        let lengths = [0, 1, 2, 3, 4, 5, 6, 7, 8, 12, 16, 20, 24, 32, 48, 64];
+        for dlc in 0..=15 {
+            let length = lengths[dlc as usize];
+            assert_eq!(dlc_to_len(dlc), Some(length));
+            assert_eq!(len_to_dlc(length), Some(dlc));
+            assert_eq!(pad_len(length), Some(length));
+        }
+        assert_eq!(dlc_to_len(16), None);
+
+        // the lengths between the codes
+        for length in 0..=65 {
+            match lengths.iter().position(|v| *v >= length) {
+                Some(dlc) => {
+                    assert_eq!(pad_len(length), Some(lengths[dlc]));
+                    assert_eq!(len_to_dlc(length).is_some(), lengths[dlc] == length);
+                },
+                None => {
+                    assert_eq!(pad_len(length), None);
+                    assert_eq!(len_to_dlc(length), None);
+                },
+            }
+        }
+    }
+}
@@ -1,188 +1,666 @@
 //! CAN device driver impl.
+//!
+//! `SyncCan` requires the blocking [`Driver`], it is not compiled with the feature `async`.
+#![cfg_attr(feature = "async", allow(dead_code, unused_imports))]
 
+mod cyclic;
+pub use cyclic::CyclicHandle;
+
+mod direct;
+pub use direct::DirectTransmit;
+
+mod generation;
+pub use generation::TxGenerations;
+
+mod offload;
+pub use offload::ExecutionPolicy;
+
+mod priority;
+pub use priority::TxPriority;
+
+mod registry;
+pub(crate) use registry::ListenerRegistry;
+
+#[cfg(not(feature = "async"))]
 mod synchronous;
-pub use synchronous::SyncCan;
+#[cfg(not(feature = "async"))]
+pub use synchronous::{ReceiveMode, ReconnectPolicy, SelfTestReport, SyncCan};
+
+#[cfg(any(test, feature = "mock"))]
+mod mock;
+#[cfg(any(test, feature = "mock"))]
+pub use mock::{LinkConfig, MockDriver, MOCK_CHANNEL, VirtualBus};
+
+#[cfg(any(test, feature = "replay"))]
+mod replay;
+#[cfg(any(test, feature = "replay"))]
+pub use replay::{ReplayDriver, ReplayMode};
+
+#[cfg(all(target_os = "linux", feature = "socketcan"))]
+mod socketcan;
+#[cfg(all(target_os = "linux", feature = "socketcan"))]
+pub use self::socketcan::{SocketCanDriver, SocketCanFrame};
+
+#[cfg(feature = "slcan")]
+mod slcan;
+#[cfg(feature = "slcan")]
+pub use slcan::{SlcanDriver, SlcanError, SLCAN_SERIAL_BAUD_RATE};
+
+#[cfg(feature = "pcan")]
+mod pcan;
+#[cfg(feature = "pcan")]
+pub use pcan::{PcanDriver, PcanError, PCAN_LIBRARY};
 
+use std::any::Any;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
 use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
 use std::sync::mpsc::Receiver;
-use crate::can::frame::Frame;
-use crate::device::{Driver, Listener};
+use std::time::Instant;
+use crate::can::driver::priority::TxQueue;
+use crate::can::error_frame::ErrorInfo;
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::device::{BusState, Channel, Driver, Listener};
+use crate::error::Error;
 
 pub(crate) type ListenerType<C, F> = Box<dyn Listener<C, u32, F>>;
 
-#[inline]
-pub(crate) fn register_listener<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
-    name: String,
-    listener: ListenerType<C, F>,
-) -> bool {
-    match listeners.lock() {
-        Ok(mut v) => {
-            v.insert(name, listener);
-            true
-        },
-        Err(e) => {
-            log::warn!("SyncCAN - mutex error: {:?} when inserting listener", e);
-            false
-        },
-    }
+/// The max frames handed to [`Driver::transmit_batch`] on a loop, the cyclic frames are
+/// transmitted between the batches.
+const TX_BATCH_SIZE: usize = 64;
+
+thread_local! {
+    /// The listeners called by this thread, a listener transmitting by [`DirectTransmit`] from
+    /// its callback isn't called again by the transmitting, it's locked by this thread.
+    static DISPATCHING: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
 }
 
-#[inline]
-pub(crate) fn unregister_listener<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
-    name: String,
-) -> bool {
-    match listeners.lock() {
-        Ok(mut v) => {
-            v.remove(&name).is_some()
-        },
-        Err(e) => {
-            log::warn!("SyncCAN - mutex error: {:?} when removing listener", e);
-            false
-        },
-    }
+/// The panics caught from the listener callbacks.
+#[derive(Debug, Default)]
+pub(crate) struct PanicGuard {
+    count: AtomicU64,
+    /// Unregister a listener after the number of panics, never when 0.
+    max_panics: AtomicUsize,
+    listeners: Mutex<HashMap<String, usize>>,
 }
 
-#[inline]
-pub(crate) fn unregister_all<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
-) -> bool {
-    match listeners.lock() {
-        Ok(mut v) => {
-            v.clear();
-            true
-        },
-        Err(e) => {
-            log::warn!("SyncCAN - mutex error: {:?} when removing all listeners", e);
-            false
-        },
+impl PanicGuard {
+    #[inline]
+    pub(crate) fn count(&self) -> u64 {
+        self.count.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn max_panics(&self) -> Option<usize> {
+        match self.max_panics.load(Ordering::Acquire) {
+            0 => None,
+            v => Some(v),
+        }
+    }
+
+    #[inline]
+    pub(crate) fn set_max_panics(&self, value: Option<usize>) {
+        self.max_panics.store(value.unwrap_or_default(), Ordering::Release);
+    }
+
+    /// Record a panic of the listener, returns true when the listener should be unregistered.
+    fn on_panic(&self, name: &str, callback: &str, payload: &(dyn Any + Send)) -> bool {
+        let message = payload.downcast_ref::<&str>()
+            .map(|v| v.to_string())
+            .or_else(|| payload.downcast_ref::<String>().cloned())
+            .unwrap_or_else(|| "<unknown>".into());
+        log::error!("SyncCAN - listener {} panicked in `{}`: {}", name, callback, message);
+        self.count.fetch_add(1, Ordering::AcqRel);
+
+        let panics = match self.listeners.lock() {
+            Ok(mut v) => {
+                let count = v.entry(name.to_string()).or_default();
+                *count += 1;
+                *count
+            },
+            Err(_) => 0,
+        };
+        matches!(self.max_panics(), Some(max) if panics >= max)
     }
 }
 
-#[inline]
-pub(crate) fn listener_names<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
-) -> Vec<String> {
-    match listeners.lock() {
-        Ok(v) => {
-            v.keys()
-                .into_iter()
-                .map(|f| f.clone())
-                .collect()
-        },
-        Err(e) => {
-            log::warn!("SyncCAN - mutex error: {:?} when removing all listeners", e);
-            vec![]
-        },
+/// Call `f` for each listener, the panics are caught and the listener is unregistered
+/// after the max panics of `guard`.
+///
+/// The listeners are wrapped by [`AssertUnwindSafe`], a listener may be left in an
+/// inconsistent state after panicking, it's the listener's duty to recover or be unregistered.
+fn for_each_listener<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    callback: &str,
+    mut f: impl FnMut(&mut ListenerType<C, F>),
+) {
+    let snapshot = listeners.snapshot();
+    let panicked = snapshot.iter()
+        .filter_map(|(name, o)| {
+            let key = Arc::as_ptr(o) as *const () as usize;
+            if DISPATCHING.with_borrow(|v| v.contains(&key)) {
+                log::trace!("SyncCAN - `{}` of listener {} skipped, it's calling the driver", callback, name);
+                return None;
+            }
+
+            let mut o = registry::lock(o)?;
+            DISPATCHING.with_borrow_mut(|v| v.push(key));
+            let result = catch_unwind(AssertUnwindSafe(|| f(&mut o)));
+            DISPATCHING.with_borrow_mut(|v| v.pop());
+            result
+                .err()
+                .filter(|payload| guard.on_panic(name, callback, payload.as_ref()))
+                .map(|_| name)
+        })
+        .collect::<Vec<_>>();
+    for name in panicked {
+        log::warn!("SyncCAN - unregister listener {} after panics", name);
+        listeners.unregister(name);
     }
 }
 
 #[inline]
 fn on_messages_util<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
-    messages: &Vec<F>,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    messages: &[F],
     channel: C
 )
 where
     F: 'static,
-    C: Clone + 'static
+    C: Channel
 {
-    match listeners.lock() {
-        Ok(mut v) => v.values_mut()
-            .for_each(|o| {
-                o.on_frame_received(channel.clone(), messages);
-            }),
-        Err(e) =>
-            log::error!("SyncCAN - mutex error: {e:?} `on_messages`"),
-    }
+    for_each_listener(listeners, guard, "on_frame_received", |o| {
+        o.on_frame_received(channel.clone(), messages);
+    });
 }
 
 #[inline]
 fn on_transmitting_util<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
     channel: C,
     frame: &F
 )
 where
     F: 'static,
-    C: Clone + 'static
+    C: Channel
 {
-    match listeners.lock() {
-        Ok(mut v) => v.values_mut()
-            .for_each(|o| {
-                o.on_frame_transmitting(channel.clone(), frame);
-            }),
-        Err(e) =>
-            log::error!("SyncCAN - mutex error: {e:?} `on_transmit`"),
-    }
+    for_each_listener(listeners, guard, "on_frame_transmitting", |o| {
+        o.on_frame_transmitting(channel.clone(), frame);
+    });
 }
 
 #[inline]
 fn on_transmitted_util<C, F>(
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    channel: C,
+    frame: &F,
+)
+where
+    F: 'static,
+    C: Channel
+{
+    for_each_listener(listeners, guard, "on_frame_transmitted", |o| {
+        o.on_frame_transmitted(channel.clone(), frame);
+    });
+}
+
+#[inline]
+pub(crate) fn on_bus_state_changed_util<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    channel: C,
+    state: BusState,
+)
+where
+    F: 'static,
+    C: Channel
+{
+    for_each_listener(listeners, guard, "on_bus_state_changed", |o| {
+        o.on_bus_state_changed(channel.clone(), state);
+    });
+}
+
+#[inline]
+pub(crate) fn on_connection_changed_util<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    connected: bool,
+)
+where
+    F: 'static,
+    C: Channel
+{
+    for_each_listener(listeners, guard, "on_connection_changed", |o| {
+        o.on_connection_changed(connected);
+    });
+}
+
+pub(crate) fn on_shutdown_util<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+)
+where
+    F: 'static,
+    C: Channel
+{
+    for_each_listener(listeners, guard, "on_shutdown", |o| {
+        o.on_shutdown();
+    });
+}
+
+#[inline]
+fn on_transmit_failed_util<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
     id: u32,
-    channel: C
+    channel: C,
+    error: &Error,
 )
 where
     F: 'static,
-    C: Clone + 'static
+    C: Channel
 {
-    match listeners.lock() {
-        Ok(mut v) => v.values_mut()
-            .for_each(|o| {
-                o.on_frame_transmitted(channel.clone(), id);
-            }),
-        Err(e) =>
-            log::error!("SyncCAN - mutex error: {e:?} `on_transmit`"),
-    }
+    for_each_listener(listeners, guard, "on_frame_transmit_failed", |o| {
+        o.on_frame_transmit_failed(channel.clone(), id, error);
+    });
 }
 
+#[cfg(not(feature = "async"))]
 #[inline]
 pub(crate) fn transmit_callback<D, C, F>(
     receiver: &Arc<Mutex<Receiver<F>>>,
+    queue: &Mutex<TxQueue<F>>,
+    generations: &TxGenerations,
     device: &D,
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
     timeout: Option<u32>,
 )
 where
     D: Driver<F = F>,
-    C: Clone + Display + 'static,
-    F: Frame<Channel = C> + Display + 'static,
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static,
 {
-    if let Ok(receiver) = receiver.lock() {
-        if let Ok(msg) = receiver.try_recv() {
-            log::debug!("SyncCAN - transmit: {}", msg);
-            let id = msg.id();
-            on_transmitting_util(listeners, msg.channel(), &msg);
-            let channel = msg.channel();
-            if let Ok(_) = device.transmit(msg, timeout) {
-                on_transmitted_util(listeners, id.into_bits(), channel);
+    let Ok(receiver) = receiver.lock() else {
+        return;
+    };
+    let mut frames = match queue.lock() {
+        Ok(mut queue) => {
+            let now = Instant::now();
+            if queue.is_ordered() {
+                // take all the sent frames to order them
+                while let Ok(msg) = receiver.try_recv() {
+                    queue.push(msg, now);
+                }
+                queue.pop(now).into_iter().collect()
             }
+            else {
+                // take the frames sent together, e.g. a block of ISO-TP consecutive frames
+                let mut frames = Vec::new();
+                while frames.len() < TX_BATCH_SIZE {
+                    match queue.pop(now).or_else(|| receiver.try_recv().ok()) {
+                        Some(msg) => frames.push(msg),
+                        None => break,
+                    }
+                }
+                frames
+            }
+        },
+        Err(_) => receiver.try_recv().ok().into_iter().collect::<Vec<_>>(),
+    };
+    frames.retain(|frame| {
+        let current = generations.is_current(frame);
+        if !current {
+            log::debug!("SyncCAN - superseded frame dropped: {}", frame);
         }
+        current
+    });
+    match frames.len() {
+        0 => {},
+        // the failures are notified to the listeners
+        1 => { let _ = transmit_frame(device, listeners, guard, frames.remove(0), timeout); },
+        _ => transmit_frames(device, listeners, guard, frames, timeout),
+    }
+}
+
+/// Transmit `msg` and notify the listeners of the result, returns the frame confirmed.
+#[cfg(not(feature = "async"))]
+pub(crate) fn transmit_frame<D, C, F>(
+    device: &D,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    msg: F,
+    timeout: Option<u32>,
+) -> Result<F, Error>
+where
+    D: Driver<F = F>,
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static,
+{
+    log::debug!("SyncCAN - transmit: {}", msg);
+    on_transmitting_util(listeners, guard, msg.channel(), &msg);
+    let echo = msg.clone();
+    let result = device.transmit(msg, timeout);
+    on_transmit_result(listeners, guard, echo, result)
+}
+
+/// Transmit `frames` by one call of [`Driver::transmit_batch`] and notify the listeners of the results.
+#[cfg(not(feature = "async"))]
+pub(crate) fn transmit_frames<D, C, F>(
+    device: &D,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    frames: Vec<F>,
+    timeout: Option<u32>,
+)
+where
+    D: Driver<F = F>,
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static,
+{
+    for msg in &frames {
+        log::debug!("SyncCAN - transmit: {}", msg);
+        on_transmitting_util(listeners, guard, msg.channel(), msg);
+    }
+    let echoes = frames.clone();
+    let results = device.transmit_batch(frames, timeout);
+    for (echo, result) in echoes.into_iter().zip(results) {
+        let _ = on_transmit_result(listeners, guard, echo, result);
+    }
+}
+
+fn on_transmit_result<C, F, E>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    mut echo: F,
+    result: Result<(), E>,
+) -> Result<F, Error>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static,
+    E: core::error::Error + Send + Sync + 'static,
+{
+    let id = echo.id();
+    let channel = echo.channel();
+    match result {
+        Ok(_) => {
+            // the driver doesn't report the hardware timestamp, use the completion time
+            if echo.timestamp() == 0 {
+                echo.set_timestamp(None);
+            }
+            echo.set_direct(Direct::Transmit);
+            on_transmitted_util(listeners, guard, channel, &echo);
+            Ok(echo)
+        },
+        Err(e) => {
+            log::warn!("SyncCAN - transmit failed: {}", e);
+            let e = Error::device(e);
+            on_transmit_failed_util(listeners, guard, id.into_bits(), channel, &e);
+            Err(e)
+        },
+    }
+}
+
+/// Take the error frames out of `frames` and notify the listeners by [`Listener::on_error_frame`],
+/// returns the last bus state indicated by them.
+fn on_error_frames_util<C, F>(
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    channel: &C,
+    frames: &mut Vec<F>,
+) -> Option<BusState>
+where
+    F: Frame + 'static,
+    C: Channel,
+{
+    if !frames.iter().any(|f| f.is_error_frame()) {
+        return None;
     }
+
+    let (errors, others): (Vec<_>, Vec<_>) = frames.drain(..)
+        .partition(|f| f.is_error_frame());
+    // keep the capacity of `frames` for the next receiving
+    frames.extend(others);
+    let errors = errors.into_iter()
+        .map(|f| {
+            let info = ErrorInfo::parse(f.id().into_bits(), f.data());
+            log::debug!("SyncCAN - error frame received: {}", info);
+            (f, info)
+        })
+        .collect::<Vec<_>>();
+
+    for_each_listener(listeners, guard, "on_error_frame", |o| {
+        errors.iter()
+            .for_each(|(f, info)| o.on_error_frame(channel.clone(), f, info));
+    });
+
+    errors.iter()
+        .filter_map(|(_, info)| info.bus_state())
+        .last()
 }
 
+/// Receive the frames of all opened channels into `buffers`, returns the bus states indicated by the error frames.
+///
+/// The `buffers` are kept by the caller and reused across the polls, one for each opened channel.
+#[cfg(not(feature = "async"))]
 #[inline]
 pub(crate) fn receive_callback<D, C, F>(
     device: &D,
-    listeners: &Arc<Mutex<HashMap<String, ListenerType<C, F>>>>,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    buffers: &mut Vec<(C, Vec<F>)>,
     timeout: Option<u32>,
-)
+) -> Vec<(C, BusState)>
 where
-    F: 'static,
+    F: Frame + 'static,
     D: Driver<C = C, F = F>,
-    C: Clone + 'static,
+    C: Channel,
 {
     let channels = device.opened_channels();
-    channels.into_iter()
-        .for_each(|c| {
-            if let Ok(messages) = device.receive(c.clone(), timeout) {
-                if !messages.is_empty() {
-                    on_messages_util(listeners, &messages, c.clone());
-                }
-            }
-        });
+    buffers.truncate(channels.len());
+    for (i, c) in channels.into_iter().enumerate() {
+        match buffers.get_mut(i) {
+            Some(v) => v.0 = c,
+            None => buffers.push((c, Vec::new())),
+        }
+    }
+
+    // collect all channels first, then the listeners are locked only once per cycle
+    let mut states = vec![];
+    for (c, messages) in buffers.iter_mut() {
+        messages.clear();
+        if device.receive_into(c.clone(), timeout, messages).is_err() {
+            messages.clear();
+            continue;
+        }
+        if let Some(state) = on_error_frames_util(listeners, guard, c, messages) {
+            states.push((c.clone(), state));
+        }
+    }
+
+    // the channels without frames are excluded
+    buffers.sort_by_key(|(_, v)| v.is_empty());
+    let received = buffers.iter()
+        .take_while(|(_, v)| !v.is_empty())
+        .count();
+    if received > 0 {
+        let frames = &buffers[..received];
+        for_each_listener(listeners, guard, "on_frames_received", |o| o.on_frames_received(frames));
+    }
+
+    states
+}
+
+/// Receive the frames of `channel` into `buffer`, returns the bus state indicated by the error frames.
+#[cfg(not(feature = "async"))]
+#[inline]
+pub(crate) fn receive_channel_callback<D, C, F>(
+    device: &D,
+    listeners: &ListenerRegistry<C, F>,
+    guard: &PanicGuard,
+    channel: C,
+    buffer: &mut Vec<F>,
+    timeout: Option<u32>,
+) -> Option<BusState>
+where
+    F: Frame + 'static,
+    D: Driver<C = C, F = F>,
+    C: Channel,
+{
+    buffer.clear();
+    if device.receive_into(channel.clone(), timeout, buffer).is_err() {
+        buffer.clear();
+        return None;
+    }
+    let state = on_error_frames_util(listeners, guard, &channel, buffer);
+    if !buffer.is_empty() {
+        on_messages_util(listeners, guard, buffer, channel);
+    }
+
+    state
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::any::Any;
+    use std::sync::{Arc, Mutex};
+    use crate::can::driver::{receive_callback, receive_channel_callback, ListenerRegistry, MockDriver, PanicGuard, VirtualBus};
+    use crate::can::frame::FrameMut;
+    use crate::can::message::CanMessage;
+    use crate::device::{Driver, Listener};
+
+    type Listeners = ListenerRegistry<String, CanMessage>;
+
+    const CHANNELS: usize = 8;
+    const LISTENERS: usize = 20;
+
+    /// Counts the callbacks and the received frames.
+    #[derive(Clone, Default)]
+    struct Counter(Arc<Mutex<(usize, usize)>>);
+
+    impl Listener<String, u32, CanMessage> for Counter {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            let mut count = self.0.lock().unwrap();
+            count.0 += 1;
+            count.1 += frames.len();
+        }
+
+        fn on_frames_received(&mut self, frames: &[(String, Vec<CanMessage>)]) {
+            let mut count = self.0.lock().unwrap();
+            count.0 += 1;
+            count.1 += frames.iter().map(|(_, v)| v.len()).sum::<usize>();
+        }
+    }
+
+    fn setup() -> (MockDriver, MockDriver, Listeners, Vec<Counter>) {
+        let (mut a, mut b) = VirtualBus::pair();
+        for i in 1..CHANNELS {
+            a.open_channel(format!("mock{}", i), Default::default()).unwrap();
+            b.open_channel(format!("mock{}", i), Default::default()).unwrap();
+        }
+
+        let counters = (0..LISTENERS).map(|_| Counter::default()).collect::<Vec<_>>();
+        let listeners = ListenerRegistry::default();
+        counters.iter()
+            .enumerate()
+            .for_each(|(i, c)| listeners.register(i.to_string(), Box::new(c.clone())));
+        (a, b, listeners, counters)
+    }
+
+    fn transmit_all(driver: &MockDriver) {
+        for channel in driver.opened_channels() {
+            let mut frame = CanMessage::new(0x7E8, &[0x02, 0x50, 0x01]).unwrap();
+            frame.set_channel(channel);
+            driver.transmit(frame, None).unwrap();
+        }
+    }
+
+    /// Micro-benchmark of the receiving with 8 channels and 20 listeners.
+    ///
+    /// The frames of all channels are delivered in one callback per listener, the listeners are
+    /// locked once per cycle instead of once per channel(8 times) and the callbacks are reduced
+    /// from 160 to 20 per cycle.
+    #[test]
+    fn test_grouped_receive() {
+        const CYCLES: usize = 200;
+
+        let (a, b, listeners, counters) = setup();
+        let guard = PanicGuard::default();
+        let mut buffers = Vec::new();
+        for _ in 0..CYCLES {
+            transmit_all(&b);
+            receive_callback(&a, &listeners, &guard, &mut buffers, None);
+        }
+        let grouped = counters.iter()
+            .map(|v| *v.0.lock().unwrap())
+            .collect::<Vec<_>>();
+
+        let (a, b, listeners, counters) = setup();
+        let guard = PanicGuard::default();
+        let mut buffer = Vec::new();
+        for _ in 0..CYCLES {
+            transmit_all(&b);
+            a.opened_channels()
+                .into_iter()
+                .for_each(|c| { receive_channel_callback(&a, &listeners, &guard, c, &mut buffer, None); });
+        }
+        let per_channel = counters.iter()
+            .map(|v| *v.0.lock().unwrap())
+            .collect::<Vec<_>>();
+
+        // the same frames by a callback per cycle instead of per channel
+        for ((grouped_calls, grouped_frames), (calls, frames)) in grouped.into_iter().zip(per_channel) {
+            assert_eq!(grouped_frames, frames);
+            assert_eq!((grouped_calls, calls), (CYCLES, CYCLES * CHANNELS));
+        }
+    }
+
+    #[test]
+    fn test_receive_into() {
+        let (a, b, listeners, counters) = setup();
+        let guard = PanicGuard::default();
+
+        // appended to the buffer
+        transmit_all(&b);
+        let channel = a.opened_channels().remove(0);
+        let mut buffer = vec![CanMessage::default()];
+        assert_eq!(a.receive_into(channel, None, &mut buffer).unwrap(), 1);
+        assert_eq!(buffer.len(), 2);
+
+        // the buffers are reused across the polls
+        let mut buffers = Vec::new();
+        receive_callback(&a, &listeners, &guard, &mut buffers, None);
+        transmit_all(&b);
+        receive_callback(&a, &listeners, &guard, &mut buffers, None);
+        assert_eq!(buffers.len(), CHANNELS);
+        let pointers = buffers.iter()
+            .map(|(_, v)| v.as_ptr())
+            .collect::<Vec<_>>();
+        for _ in 0..10 {
+            transmit_all(&b);
+            receive_callback(&a, &listeners, &guard, &mut buffers, None);
+            let current = buffers.iter()
+                .map(|(_, v)| v.as_ptr())
+                .collect::<Vec<_>>();
+            assert_eq!(current, pointers);
+        }
+        for counter in &counters {
+            assert_eq!(*counter.0.lock().unwrap(), (12, 12 * CHANNELS - 1));
+        }
+    }
 }
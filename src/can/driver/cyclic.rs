@@ -0,0 +1,92 @@
+//! The cyclic frames scheduled by the transmit loop.
+
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+
+/// The handle of a cyclic frame returned by [`crate::can::driver::SyncCan::add_cyclic`].
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct CyclicHandle {
+    id: u64,
+    name: String,
+}
+
+impl CyclicHandle {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+}
+
+#[derive(Debug)]
+struct Cyclic<F> {
+    handle: CyclicHandle,
+    frame: F,
+    period: Duration,
+    due: Instant,
+}
+
+/// The cyclic frames with their next due time.
+#[derive(Debug)]
+pub(crate) struct CyclicScheduler<F> {
+    next_id: u64,
+    items: Vec<Cyclic<F>>,
+}
+
+impl<F> Default for CyclicScheduler<F> {
+    fn default() -> Self {
+        Self { next_id: Default::default(), items: Default::default() }
+    }
+}
+
+impl<F: FrameMut + Clone> CyclicScheduler<F> {
+    /// Add `frame`, the first emission is due immediately.
+    pub(crate) fn add(&mut self, name: String, frame: F, period: Duration) -> CyclicHandle {
+        let period = period.max(Duration::from_micros(1));
+        let handle = CyclicHandle { id: self.next_id, name };
+        self.next_id += 1;
+        self.items.push(Cyclic { handle: handle.clone(), frame, period, due: Instant::now() });
+        handle
+    }
+
+    /// Replace the data of the cyclic frame, returns false when the handle is removed
+    /// or the data is invalid for the frame.
+    pub(crate) fn update(&mut self, handle: &CyclicHandle, data: &[u8]) -> bool {
+        let Some(item) = self.items.iter_mut().find(|v| v.handle == *handle) else {
+            return false;
+        };
+
+        let old = &item.frame;
+        match F::new(old.id(), data) {
+            Some(mut frame) => {
+                let can_fd = old.is_can_fd() || frame.is_can_fd();
+                frame.set_channel(old.channel())
+                    .set_can_fd(can_fd)
+                    .set_bitrate_switch(old.is_bitrate_switch())
+                    .set_esi(old.is_esi());
+                item.frame = frame;
+                true
+            },
+            None => false,
+        }
+    }
+
+    pub(crate) fn remove(&mut self, handle: &CyclicHandle) -> bool {
+        let len = self.items.len();
+        self.items.retain(|v| v.handle != *handle);
+        self.items.len() != len
+    }
+
+    /// Take the frames due at `now` and schedule their next emission.
+    ///
+    /// The due time advances by whole periods to keep the phase, the missed cycles are skipped.
+    pub(crate) fn due(&mut self, now: Instant) -> Vec<F> {
+        self.items.iter_mut()
+            .filter(|v| v.due <= now)
+            .map(|v| {
+                let cycles = (now - v.due).as_nanos() / v.period.as_nanos() + 1;
+                v.due += v.period * cycles as u32;
+                v.frame.clone()
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,40 @@
+//! Transmit by the device in the caller's thread, bypassing the transmit loop.
+
+use std::fmt::{Debug, Formatter};
+use std::sync::Arc;
+use crate::error::Error;
+
+type TransmitFn<F> = dyn Fn(F, Option<u32>) -> Result<F, Error> + Send + Sync;
+
+/// Transmit a frame as [`SyncCan::transmit_now`](crate::can::driver::SyncCan::transmit_now), shared
+/// by a transport and the driver, see [`SyncCan::direct_transmit`](crate::can::driver::SyncCan::direct_transmit).
+///
+/// A listener may transmit by it from its callbacks, e.g. a flow control replied by the receive loop,
+/// the listener itself isn't notified of the frame then, the result is returned to it instead.
+pub struct DirectTransmit<F>(Arc<TransmitFn<F>>);
+
+impl<F> Clone for DirectTransmit<F> {
+    fn clone(&self) -> Self {
+        Self(Arc::clone(&self.0))
+    }
+}
+
+impl<F> Debug for DirectTransmit<F> {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str("DirectTransmit")
+    }
+}
+
+impl<F> DirectTransmit<F> {
+    #[inline]
+    pub(crate) fn new(transmit: impl Fn(F, Option<u32>) -> Result<F, Error> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(transmit))
+    }
+
+    /// Transmit `frame` in `timeout` milliseconds, returns it as confirmed, timestamped by the device
+    /// or by the completion time.
+    #[inline]
+    pub fn transmit(&self, frame: F, timeout: Option<u32>) -> Result<F, Error> {
+        (self.0)(frame, timeout)
+    }
+}
@@ -0,0 +1,124 @@
+//! The generations of the transfers, the frames queued by a superseded transfer are dropped by the transmit loop.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{SendError, Sender};
+use crate::can::frame::Frame;
+
+#[derive(Debug, Default)]
+struct Generation {
+    active: u64,
+    /// The generations of the frames sent and not taken by the transmit loop yet, in order.
+    queued: VecDeque<u64>,
+}
+
+/// The generation of the transfers by identifier, shared by a transport and the transmit loop,
+/// see [`SyncCan::tx_generations`](crate::can::driver::SyncCan::tx_generations).
+///
+/// A frame sent by [`send`](Self::send) is tagged with the active generation of its identifier,
+/// and dropped by the transmit loop instead of transmitted if the generation is superseded by
+/// [`advance`](Self::advance) meanwhile, e.g. the consecutive frames left by an aborted writing.
+///
+/// The tags are matched in the order of the frames, so the frames of an identifier must all be
+/// sent by this.
+#[derive(Debug, Clone, Default)]
+pub struct TxGenerations(Arc<Mutex<HashMap<u32, Generation>>>);
+
+impl TxGenerations {
+    /// Send `frame` by `sender` tagged with the active generation of its identifier.
+    pub fn send<F: Frame>(&self, sender: &Sender<F>, frame: F) -> Result<(), SendError<F>> {
+        let Ok(mut generations) = self.0.lock() else {
+            return sender.send(frame);
+        };
+
+        // the tag and the frame are queued in the same order by holding the lock
+        let generation = generations.entry(frame.id().into_bits()).or_default();
+        generation.queued.push_back(generation.active);
+        let result = sender.send(frame);
+        if result.is_err() {
+            generation.queued.pop_back();
+        }
+        result
+    }
+
+    /// Supersede the active generation of `id`, its frames queued are dropped. Returns the new generation.
+    pub fn advance(&self, id: u32) -> u64 {
+        match self.0.lock() {
+            Ok(mut generations) => {
+                let generation = generations.entry(id).or_default();
+                generation.active += 1;
+                log::debug!("SyncCAN - generation of {:04X} advanced to {}, {} frames dropped",
+                    id, generation.active, generation.queued.len());
+                generation.active
+            },
+            Err(_) => 0,
+        }
+    }
+
+    /// The active generation of `id`.
+    pub fn generation(&self, id: u32) -> u64 {
+        match self.0.lock() {
+            Ok(generations) => generations.get(&id).map_or(0, |v| v.active),
+            Err(_) => 0,
+        }
+    }
+
+    /// The frames of `id` sent and not taken by the transmit loop yet.
+    pub fn queued(&self, id: u32) -> usize {
+        match self.0.lock() {
+            Ok(generations) => generations.get(&id).map_or(0, |v| v.queued.len()),
+            Err(_) => 0,
+        }
+    }
+
+    /// Take the tag of a frame taken by the transmit loop, returns false when it's superseded.
+    pub(crate) fn is_current(&self, frame: &impl Frame) -> bool {
+        let Ok(mut generations) = self.0.lock() else {
+            return true;
+        };
+
+        match generations.get_mut(&frame.id().into_bits()) {
+            Some(generation) => match generation.queued.pop_front() {
+                Some(v) => v == generation.active,
+                // not sent by this
+                None => true,
+            },
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::mpsc::channel;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use super::TxGenerations;
+
+    #[test]
+    fn test_generations() -> anyhow::Result<()> {
+        let generations = TxGenerations::default();
+        let (sender, receiver) = channel();
+        for data in [[0x21], [0x22], [0x23]] {
+            generations.send(&sender, CanMessage::new(Id::Standard(0x7E0), &data).unwrap())?;
+        }
+        // not tagged
+        sender.send(CanMessage::new(Id::Standard(0x7DF), &[0x02, 0x3E, 0x00]).unwrap())?;
+        assert_eq!(generations.queued(0x7E0), 3);
+
+        let frame = receiver.recv()?;
+        assert!(generations.is_current(&frame));
+        assert_eq!(generations.advance(0x7E0), 1);
+        generations.send(&sender, CanMessage::new(Id::Standard(0x7E0), &[0x02, 0x10, 0x01]).unwrap())?;
+
+        let current = receiver.try_iter()
+            .filter(|v| generations.is_current(v))
+            .map(|v| v.data().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(current, vec![vec![0x02, 0x3E, 0x00], vec![0x02, 0x10, 0x01]]);
+        assert_eq!(generations.queued(0x7E0), 0);
+        assert_eq!(generations.generation(0x7E0), 1);
+        Ok(())
+    }
+}
@@ -0,0 +1,924 @@
+//! In-memory virtual CAN bus for testing without hardware.
+//!
+//! Frames transmitted by one [`MockDriver`] are delivered to the receive queue of all other
+//! endpoints on the same [`VirtualBus`], with optional per-link latency and loss rate. The
+//! transmit failures of the device are injected by [`MockDriver::set_tx_failure_rate`].
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::message::CanMessage;
+use crate::device::{BusState, ChannelConfig, Driver, DriverCapabilities, FilterSpec};
+use crate::error::Error;
+
+/// The default channel name of the mock endpoints.
+pub const MOCK_CHANNEL: &str = "mock0";
+
+/// Link behaviour between two endpoints of a [`VirtualBus`].
+#[derive(Debug, Default, Copy, Clone, PartialEq)]
+pub struct LinkConfig {
+    /// The delay before a transmitted frame can be received by the peer.
+    pub latency: Duration,
+    /// The probability(0.0 ~ 1.0) of a transmitted frame being lost.
+    pub loss_rate: f64,
+    /// The probability(0.0 ~ 1.0) of a delivered frame being received twice.
+    pub duplicate_rate: f64,
+}
+
+#[derive(Debug)]
+struct Pending {
+    deliver_at: Instant,
+    frame: CanMessage,
+}
+
+#[derive(Debug)]
+struct Endpoint {
+    queue: Mutex<VecDeque<Pending>>,
+    notify: Condvar,
+    channels: Mutex<Vec<(String, ChannelConfig)>>,
+    bus_states: Mutex<HashMap<String, BusState>>,
+    filters: Mutex<HashMap<String, Vec<FilterSpec>>>,
+    closed: AtomicBool,
+    unplugged: AtomicBool,
+    /// The bits of the `f64` failure rate.
+    tx_failure_rate: AtomicU64,
+    loopback: AtomicBool,
+}
+
+impl Default for Endpoint {
+    fn default() -> Self {
+        Self {
+            queue: Default::default(),
+            notify: Default::default(),
+            channels: Mutex::new(vec![(MOCK_CHANNEL.into(), Default::default())]),
+            bus_states: Default::default(),
+            filters: Default::default(),
+            closed: Default::default(),
+            unplugged: Default::default(),
+            tx_failure_rate: Default::default(),
+            loopback: Default::default(),
+        }
+    }
+}
+
+impl Endpoint {
+    fn channel_config(&self, channel: &str) -> Option<ChannelConfig> {
+        match self.channels.lock() {
+            Ok(channels) => channels.iter()
+                .find(|(name, _)| name == channel)
+                .map(|(_, config)| *config),
+            Err(_) => None,
+        }
+    }
+
+    fn bus_state(&self, channel: &str) -> BusState {
+        if self.channel_config(channel).is_none() {
+            return BusState::Unknown;
+        }
+
+        match self.bus_states.lock() {
+            Ok(states) => states.get(channel)
+                .copied()
+                .unwrap_or(BusState::ErrorActive),
+            Err(_) => BusState::Unknown,
+        }
+    }
+
+    /// Whether `frame` is accepted by the filters of its channel, the error frames are always accepted.
+    fn accepts(&self, frame: &CanMessage) -> bool {
+        if frame.is_error_frame() {
+            return true;
+        }
+
+        match self.filters.lock() {
+            Ok(filters) => match filters.get(&frame.channel()) {
+                Some(filters) if !filters.is_empty() => {
+                    let id = frame.id();
+                    filters.iter().any(|f| f.matches(id.as_raw(), id.is_extended()))
+                },
+                _ => true,
+            },
+            Err(_) => true,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct BusInner {
+    endpoints: Vec<Endpoint>,
+    /// links[from][to]
+    links: Mutex<Vec<Vec<LinkConfig>>>,
+    /// xorshift state used for the loss and duplicate simulation.
+    seed: Mutex<u64>,
+}
+
+impl BusInner {
+    /// A random draw with the probability `rate`.
+    fn chance(&self, rate: f64) -> bool {
+        if rate <= 0. {
+            return false;
+        }
+
+        match self.seed.lock() {
+            Ok(mut seed) => {
+                let mut x = *seed;
+                x ^= x << 13;
+                x ^= x >> 7;
+                x ^= x << 17;
+                *seed = x;
+                ((x >> 11) as f64 / (1u64 << 53) as f64) < rate
+            },
+            Err(_) => false,
+        }
+    }
+}
+
+/// A virtual CAN bus shared by several [`MockDriver`] endpoints.
+///
+/// Each endpoint opens the channel [`MOCK_CHANNEL`] with the default [`ChannelConfig`] at first.
+/// A frame is only delivered to the endpoints which have opened the same channel with the
+/// same bitrate, FD frames are only delivered to the channels with FD enabled.
+#[derive(Debug, Clone)]
+pub struct VirtualBus {
+    inner: Arc<BusInner>,
+}
+
+impl VirtualBus {
+    /// Create a shared bus with `n` endpoints.
+    pub fn new(n: usize) -> Self {
+        Self {
+            inner: Arc::new(BusInner {
+                endpoints: (0..n).map(|_| Default::default()).collect(),
+                links: Mutex::new(vec![vec![Default::default(); n]; n]),
+                seed: Mutex::new(0x2545_F491_4F6C_DD1D),
+            }),
+        }
+    }
+
+    /// Create a bus with two endpoints connected to each other.
+    pub fn pair() -> (MockDriver, MockDriver) {
+        let bus = Self::new(2);
+        (bus.driver(0), bus.driver(1))
+    }
+
+    /// The count of endpoints on this bus.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.inner.endpoints.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.inner.endpoints.is_empty()
+    }
+
+    /// Get the driver of endpoint `index`, drivers of the same endpoint share their state.
+    ///
+    /// # Panics
+    ///
+    /// When `index` is out of range.
+    pub fn driver(&self, index: usize) -> MockDriver {
+        assert!(index < self.len(), "VirtualBus - endpoint index: {} out of range", index);
+        MockDriver {
+            bus: Arc::clone(&self.inner),
+            index,
+        }
+    }
+
+    /// Configure the link of frames sent by endpoint `from` and received by endpoint `to`.
+    pub fn set_link(&self, from: usize, to: usize, config: LinkConfig) {
+        if let Ok(mut links) = self.inner.links.lock() {
+            if let Some(link) = links.get_mut(from).and_then(|v| v.get_mut(to)) {
+                *link = config;
+            }
+        }
+    }
+
+    /// Configure all links of this bus.
+    pub fn set_all_links(&self, config: LinkConfig) {
+        if let Ok(mut links) = self.inner.links.lock() {
+            links.iter_mut()
+                .flat_map(|v| v.iter_mut())
+                .for_each(|link| *link = config);
+        }
+    }
+
+    /// Set the seed of the loss simulation, a zero seed is ignored.
+    pub fn set_seed(&self, seed: u64) {
+        if seed == 0 {
+            return;
+        }
+        if let Ok(mut v) = self.inner.seed.lock() {
+            *v = seed;
+        }
+    }
+}
+
+/// A [`Driver`] endpoint of a [`VirtualBus`].
+///
+/// Clones share the same endpoint, so a clone handed to [`SyncCan`](crate::can::driver::SyncCan)
+/// can be inspected, reconfigured or closed from the test.
+#[derive(Debug, Clone)]
+pub struct MockDriver {
+    bus: Arc<BusInner>,
+    index: usize,
+}
+
+impl MockDriver {
+    /// The index of this endpoint on the bus.
+    #[inline]
+    pub fn index(&self) -> usize {
+        self.index
+    }
+
+    /// Rename the default channel [`MOCK_CHANNEL`] of this endpoint.
+    pub fn with_channel(self, channel: impl Into<String>) -> Self {
+        if let Ok(mut channels) = self.endpoint().channels.lock() {
+            if let Some((name, _)) = channels.iter_mut().find(|(name, _)| name == MOCK_CHANNEL) {
+                *name = channel.into();
+            }
+        }
+        self
+    }
+
+    /// The configuration of an opened `channel`.
+    #[inline]
+    pub fn channel_config(&self, channel: &str) -> Option<ChannelConfig> {
+        self.endpoint().channel_config(channel)
+    }
+
+    /// Simulate the bus `state` of `channel`, the transmitting fails with [`Error::BusOff`] while bus off.
+    pub fn set_bus_state(&self, channel: &str, state: BusState) {
+        log::debug!("MockDriver - endpoint {} channel `{}` bus state: {:?}", self.index, channel, state);
+        if let Ok(mut states) = self.endpoint().bus_states.lock() {
+            states.insert(channel.into(), state);
+        }
+    }
+
+    /// Fail the transmitting with the probability(0.0 ~ 1.0) like a full TX buffer of the adapter,
+    /// the failed frames are not delivered. It's simulated by the seed of [`VirtualBus::set_seed`].
+    pub fn set_tx_failure_rate(&self, rate: f64) {
+        log::debug!("MockDriver - endpoint {} transmit failure rate: {}", self.index, rate);
+        self.endpoint().tx_failure_rate.store(rate.to_bits(), Ordering::Release);
+    }
+
+    /// Receive the frames transmitted by this endpoint too, flagged [`Direct::Transmit`] like
+    /// the own frames looped back by SocketCAN.
+    pub fn set_loopback(&self, loopback: bool) {
+        log::debug!("MockDriver - endpoint {} loopback: {}", self.index, loopback);
+        self.endpoint().loopback.store(loopback, Ordering::Release);
+    }
+
+    /// Simulate unplugging(`false`) and plugging(`true`) the adapter.
+    ///
+    /// The endpoint is closed when unplugged and [`Driver::reopen`] fails until plugged again.
+    pub fn set_connected(&self, connected: bool) {
+        log::debug!("MockDriver - endpoint {} connected: {}", self.index, connected);
+        let endpoint = self.endpoint();
+        endpoint.unplugged.store(!connected, Ordering::Release);
+        if !connected {
+            endpoint.closed.store(true, Ordering::Release);
+            if let Ok(mut queue) = endpoint.queue.lock() {
+                queue.clear();
+            }
+            endpoint.notify.notify_all();
+        }
+    }
+
+    /// The filters of `channel` set by [`Driver::set_filters`], empty when all the frames are received.
+    pub fn filters(&self, channel: &str) -> Vec<FilterSpec> {
+        match self.endpoint().filters.lock() {
+            Ok(filters) => filters.get(channel)
+                .cloned()
+                .unwrap_or_default(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// The count of frames waiting in the receive queue(including the frames still in flight).
+    pub fn pending(&self) -> usize {
+        match self.endpoint().queue.lock() {
+            Ok(queue) => queue.len(),
+            Err(_) => 0,
+        }
+    }
+
+    #[inline]
+    fn endpoint(&self) -> &Endpoint {
+        &self.bus.endpoints[self.index]
+    }
+
+    fn transmit_util(&self, msg: CanMessage) -> Result<(), Error> {
+        let config = self.check_transmit(&msg)?;
+        self.deliver(&[(msg, config)])
+    }
+
+    /// The valid frames are delivered together, each peer is locked and notified once.
+    fn transmit_batch_util(&self, frames: Vec<CanMessage>) -> Vec<Result<(), Error>> {
+        let mut results = Vec::with_capacity(frames.len());
+        let mut valid = Vec::with_capacity(frames.len());
+        for msg in frames {
+            match self.check_transmit(&msg) {
+                Ok(config) => {
+                    valid.push((msg, config));
+                    results.push(Ok(()));
+                },
+                Err(e) => results.push(Err(e)),
+            }
+        }
+
+        if let Err(e) = self.deliver(&valid) {
+            results.iter_mut()
+                .filter(|v| v.is_ok())
+                .for_each(|v| *v = Err(e.clone()));
+        }
+
+        results
+    }
+
+    /// Check `msg` can be transmitted, returns the config of its channel.
+    fn check_transmit(&self, msg: &CanMessage) -> Result<ChannelConfig, Error> {
+        if self.is_closed() {
+            return Err(Error::device(std::io::Error::new(std::io::ErrorKind::NotConnected, "mock endpoint is closed")));
+        }
+
+        let channel = msg.channel();
+        let config = self.endpoint().channel_config(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?;
+        if self.endpoint().bus_state(&channel) == BusState::BusOff {
+            return Err(Error::BusOff);
+        }
+        if config.listen_only {
+            return Err(Error::Unsupported(format!("transmit on listen-only channel `{}`", channel)));
+        }
+        if msg.is_can_fd() && !config.fd {
+            return Err(Error::Unsupported(format!("transmit CAN-FD frame on channel `{}`", channel)));
+        }
+        let rate = f64::from_bits(self.endpoint().tx_failure_rate.load(Ordering::Acquire));
+        if self.bus.chance(rate) {
+            return Err(Error::device(std::io::Error::new(std::io::ErrorKind::WouldBlock, "mock TX buffer is full")));
+        }
+
+        Ok(config)
+    }
+
+    fn deliver(&self, frames: &[(CanMessage, ChannelConfig)]) -> Result<(), Error> {
+        if frames.is_empty() {
+            return Ok(());
+        }
+
+        let links = self.bus.links.lock()
+            .map_err(|_| Error::ContextError("can't get `links`".into()))?;
+        let now = Instant::now();
+        for (index, endpoint) in self.bus.endpoints.iter().enumerate() {
+            let own = index == self.index;
+            if (own && !endpoint.loopback.load(Ordering::Acquire)) || endpoint.closed.load(Ordering::Acquire) {
+                continue;
+            }
+
+            // the own frames are looped back without latency and loss
+            let link = if own { Default::default() } else { links[self.index][index] };
+            if let Ok(mut queue) = endpoint.queue.lock() {
+                for (msg, config) in frames {
+                    match endpoint.channel_config(&msg.channel()) {
+                        Some(peer) if peer.bitrate == config.bitrate
+                            && (peer.fd || !msg.is_can_fd()) => {},
+                        _ => continue,
+                    }
+                    if !endpoint.accepts(msg) {
+                        continue;
+                    }
+
+                    if self.bus.chance(link.loss_rate) {
+                        log::trace!("MockDriver - frame lost from {} to {}", self.index, index);
+                        continue;
+                    }
+
+                    let mut frame = msg.clone();
+                    frame.set_direct(if own { Direct::Transmit } else { Direct::Receive })
+                        .set_timestamp(None);
+                    if self.bus.chance(link.duplicate_rate) {
+                        log::trace!("MockDriver - frame duplicated from {} to {}", self.index, index);
+                        queue.push_back(Pending { deliver_at: now + link.latency, frame: frame.clone() });
+                    }
+                    queue.push_back(Pending { deliver_at: now + link.latency, frame });
+                }
+            }
+            endpoint.notify.notify_all();
+        }
+
+        Ok(())
+    }
+
+    fn receive_util(&self, channel: String, timeout: Option<u32>, buf: &mut Vec<CanMessage>) -> Result<usize, Error> {
+        if self.is_closed() {
+            return Err(Error::device(std::io::Error::new(std::io::ErrorKind::NotConnected, "mock endpoint is closed")));
+        }
+
+        let endpoint = self.endpoint();
+        let deadline = Instant::now() + Duration::from_millis(timeout.unwrap_or_default() as u64);
+        let mut queue = endpoint.queue.lock()
+            .map_err(|_| Error::ContextError("can't get `queue`".into()))?;
+        let start = buf.len();
+        loop {
+            let now = Instant::now();
+            let mut next_due: Option<Instant> = None;
+            queue.retain_mut(|pending| {
+                if pending.frame.channel() != channel {
+                    return true;
+                }
+
+                if pending.deliver_at <= now {
+                    buf.push(std::mem::take(&mut pending.frame));
+                    false
+                }
+                else {
+                    next_due = Some(next_due.map_or(pending.deliver_at, |v| v.min(pending.deliver_at)));
+                    true
+                }
+            });
+
+            if buf.len() > start || now >= deadline || self.is_closed() {
+                return Ok(buf.len() - start);
+            }
+
+            let wait = next_due.map_or(deadline, |v| v.min(deadline)) - now;
+            queue = endpoint.notify.wait_timeout(queue, wait)
+                .map_err(|_| Error::ContextError("can't get `queue`".into()))?
+                .0;
+        }
+    }
+
+    fn shutdown_util(&mut self) {
+        log::info!("MockDriver - endpoint {} shutdown", self.index);
+        let endpoint = self.endpoint();
+        endpoint.closed.store(true, Ordering::Release);
+        if let Ok(mut queue) = endpoint.queue.lock() {
+            queue.clear();
+        }
+        endpoint.notify.notify_all();
+    }
+}
+
+impl Driver for MockDriver {
+    type Error = Error;
+    type C = String;
+    type F = CanMessage;
+
+    fn opened_channels(&self) -> Vec<Self::C> {
+        if self.is_closed() {
+            return vec![];
+        }
+
+        match self.endpoint().channels.lock() {
+            Ok(channels) => channels.iter()
+                .map(|(name, _)| name.clone())
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        log::debug!("MockDriver - endpoint {} open channel `{}` with {:?}", self.index, channel, config);
+        let mut channels = self.endpoint().channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        match channels.iter_mut().find(|(name, _)| *name == channel) {
+            Some((_, v)) => *v = config,
+            None => channels.push((channel, config)),
+        }
+
+        Ok(())
+    }
+
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        log::debug!("MockDriver - endpoint {} close channel `{}`", self.index, channel);
+        let mut channels = self.endpoint().channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        let count = channels.len();
+        channels.retain(|(name, _)| *name != channel);
+        if channels.len() == count {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", channel)));
+        }
+        drop(channels);
+
+        if let Ok(mut queue) = self.endpoint().queue.lock() {
+            queue.retain(|pending| pending.frame.channel() != channel);
+        }
+
+        Ok(())
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.endpoint().closed.load(Ordering::Acquire)
+    }
+
+    fn reopen(&mut self) -> Result<(), Self::Error> {
+        let endpoint = self.endpoint();
+        if endpoint.unplugged.load(Ordering::Acquire) {
+            return Err(Error::device(std::io::Error::new(std::io::ErrorKind::NotConnected, "mock endpoint is unplugged")));
+        }
+
+        log::info!("MockDriver - endpoint {} reopen", self.index);
+        endpoint.closed.store(false, Ordering::Release);
+        Ok(())
+    }
+
+    #[inline]
+    fn bus_state(&self, channel: Self::C) -> BusState {
+        self.endpoint().bus_state(&channel)
+    }
+
+    #[inline]
+    fn is_blocking_receive(&self) -> bool {
+        true
+    }
+
+    /// The loopback is of the endpoint, see [`MockDriver::set_loopback`].
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error> {
+        if self.channel_config(&channel).is_none() {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", channel)));
+        }
+
+        MockDriver::set_loopback(self, enabled);
+        Ok(())
+    }
+
+    #[inline]
+    fn is_loopback(&self, _: Self::C) -> bool {
+        self.endpoint().loopback.load(Ordering::Acquire)
+    }
+
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error> {
+        log::debug!("MockDriver - endpoint {} channel `{}` filters: {:?}", self.index, channel, filters);
+        let mut map = self.endpoint().filters.lock()
+            .map_err(|_| Error::ContextError("can't get `filters`".into()))?;
+        match filters.is_empty() {
+            true => map.remove(&channel),
+            false => map.insert(channel, filters.to_vec()),
+        };
+
+        Ok(())
+    }
+
+    /// All the features, the frames are timestamped when delivered.
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities {
+            fd: true,
+            brs: true,
+            hardware_timestamps: false,
+            blocking_receive: true,
+            error_frames: true,
+            listen_only: true,
+            loopback: true,
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(not(feature = "async"))]
+    fn transmit_batch(&self, frames: Vec<Self::F>, _: Option<u32>) -> Vec<Result<(), Self::Error>> {
+        self.transmit_batch_util(frames)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, timeout, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, timeout, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(not(feature = "async"))]
+    fn receive_into(&self, channel: Self::C, timeout: Option<u32>, buf: &mut Vec<Self::F>) -> Result<usize, Self::Error> {
+        self.receive_util(channel, timeout, buf)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::time::Duration;
+    use hex_literal::hex;
+    use crate::error::Error;
+    use crate::can::Address;
+    use crate::can::driver::SyncCan;
+    use crate::can::frame::{Direct, Frame, FrameMut};
+    use crate::can::isotp::SyncCanIsoTp;
+    use crate::can::isotp::testing::EventListener;
+    use crate::can::message::CanMessage;
+    use crate::can::testing::frame;
+    use crate::device::{BusState, ChannelConfig, Driver, FilterSpec};
+    use super::{LinkConfig, MOCK_CHANNEL, VirtualBus};
+
+    #[test]
+    fn test_pair() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut msg = CanMessage::new(0x7E0, &hex!("02 10 01"))
+            .unwrap();
+        msg.set_channel(MOCK_CHANNEL.into());
+        a.transmit(msg, None)?;
+
+        assert!(a.receive(MOCK_CHANNEL.into(), None)?.is_empty());
+        let frames = b.receive(MOCK_CHANNEL.into(), None)?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), hex!("02 10 01"));
+        assert_eq!(frames[0].channel(), MOCK_CHANNEL);
+        Ok(())
+    }
+
+    #[test]
+    fn test_loopback() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        a.set_loopback(true);
+        a.transmit(frame(0x7E0, &hex!("02 10 01")), None)?;
+
+        let frames = a.receive(MOCK_CHANNEL.into(), None)?;
+        assert!(matches!(frames.as_slice(), [v] if v.direct() == Direct::Transmit && v.data() == hex!("02 10 01")));
+        let frames = b.receive(MOCK_CHANNEL.into(), None)?;
+        assert!(matches!(frames.as_slice(), [v] if v.direct() == Direct::Receive));
+        Ok(())
+    }
+
+    #[test]
+    fn test_shared_bus() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(3);
+        let mut msg = CanMessage::new(0x7DF, &hex!("02 3E 00"))
+            .unwrap();
+        msg.set_channel(MOCK_CHANNEL.into());
+        bus.driver(0).transmit(msg, None)?;
+
+        assert_eq!(bus.driver(1).receive(MOCK_CHANNEL.into(), None)?.len(), 1);
+        assert_eq!(bus.driver(2).receive(MOCK_CHANNEL.into(), None)?.len(), 1);
+        assert_eq!(bus.driver(0).pending(), 0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_latency_and_loss() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(2);
+        bus.set_link(0, 1, LinkConfig { latency: Duration::from_millis(20), ..Default::default() });
+        let (a, b) = (bus.driver(0), bus.driver(1));
+        a.transmit(frame(0x123, &[0x01]), None)?;
+
+        assert!(b.receive(MOCK_CHANNEL.into(), None)?.is_empty());
+        assert_eq!(b.receive(MOCK_CHANNEL.into(), Some(100))?.len(), 1);
+
+        bus.set_all_links(LinkConfig { loss_rate: 1., ..Default::default() });
+        a.transmit(frame(0x123, &[0x01]), None)?;
+        assert_eq!(b.pending(), 0);
+
+        bus.set_all_links(LinkConfig { duplicate_rate: 1., ..Default::default() });
+        a.transmit(frame(0x123, &[0x01]), None)?;
+        assert_eq!(b.pending(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bus_state() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        assert_eq!(a.bus_state(MOCK_CHANNEL.into()), BusState::ErrorActive);
+        assert_eq!(a.bus_state("unknown".into()), BusState::Unknown);
+
+        a.set_bus_state(MOCK_CHANNEL, BusState::BusOff);
+        assert_eq!(a.bus_state(MOCK_CHANNEL.into()), BusState::BusOff);
+        assert_eq!(a.transmit(frame(0x123, &[0x01]), None), Err(Error::BusOff));
+        assert_eq!(b.pending(), 0);
+
+        a.set_bus_state(MOCK_CHANNEL, BusState::ErrorPassive);
+        a.transmit(frame(0x123, &[0x01]), None)?;
+        assert_eq!(b.pending(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_filters() -> anyhow::Result<()> {
+        let (a, mut b) = VirtualBus::pair();
+        let filters = [FilterSpec::new(0x7E8, 0x7FF, false), FilterSpec::new(0x18DA_F100, 0x1FFF_FF00, true)];
+        b.set_filters(MOCK_CHANNEL.into(), &filters)?;
+        assert_eq!(b.filters(MOCK_CHANNEL), filters);
+        assert!(b.filters("unknown").is_empty());
+
+        a.transmit(frame(0x7E8, &[0x01]), None)?;
+        a.transmit(frame(0x7E0, &[0x02]), None)?;
+        a.transmit(frame(0x18DA_F1F1, &[0x03]), None)?;
+        a.transmit(frame(0x18DA_F2F1, &[0x04]), None)?;
+        let data = b.receive(MOCK_CHANNEL.into(), None)?
+            .iter()
+            .map(|v| v.data().to_vec())
+            .collect::<Vec<_>>();
+        assert_eq!(data, [vec![0x01], vec![0x03]]);
+
+        b.set_filters(MOCK_CHANNEL.into(), &[])?;
+        assert!(b.filters(MOCK_CHANNEL).is_empty());
+        a.transmit(frame(0x7E0, &[0x02]), None)?;
+        assert_eq!(b.pending(), 1);
+        Ok(())
+    }
+
+    #[test]
+    fn test_transmit_batch() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut unknown = frame(0x7E0, &hex!("02 10 03"));
+        unknown.set_channel("unknown".into());
+        let results = a.transmit_batch(vec![
+            frame(0x7E0, &hex!("02 10 01")),
+            unknown,
+            frame(0x7E0, &hex!("02 10 02")),
+        ], None);
+        assert!(matches!(results.as_slice(), [Ok(()), Err(Error::InvalidParam(_)), Ok(())]), "{:?}", results);
+
+        let frames = b.receive(MOCK_CHANNEL.into(), None)?;
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data(), hex!("02 10 01"));
+        assert_eq!(frames[1].data(), hex!("02 10 02"));
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_config() -> anyhow::Result<()> {
+        let (mut a, mut b) = VirtualBus::pair();
+        assert_eq!(a.channel_config(MOCK_CHANNEL), Some(ChannelConfig::default()));
+
+        // bitrate mismatch
+        b.open_channel(MOCK_CHANNEL.into(), ChannelConfig::new(250_000))?;
+        a.transmit(frame(0x123, &[0x01]), None)?;
+        assert_eq!(b.pending(), 0);
+
+        a.open_channel(MOCK_CHANNEL.into(), ChannelConfig::new(250_000))?;
+        a.transmit(frame(0x123, &[0x01]), None)?;
+        assert_eq!(b.receive(MOCK_CHANNEL.into(), None)?.len(), 1);
+
+        // FD frame requires FD enabled
+        let fd_frame = frame(0x123, &[0x55; 12]);
+        assert!(a.transmit(fd_frame.clone(), None).is_err());
+        a.open_channel(MOCK_CHANNEL.into(), ChannelConfig::new_fd(250_000, 2_000_000))?;
+        a.transmit(fd_frame.clone(), None)?;
+        assert_eq!(b.pending(), 0);
+        b.open_channel(MOCK_CHANNEL.into(), ChannelConfig::new_fd(250_000, 2_000_000))?;
+        a.transmit(fd_frame, None)?;
+        assert_eq!(b.receive(MOCK_CHANNEL.into(), None)?.len(), 1);
+
+        // listen-only
+        a.open_channel(MOCK_CHANNEL.into(), ChannelConfig { listen_only: true, ..ChannelConfig::new(250_000) })?;
+        assert!(a.transmit(frame(0x123, &[0x01]), None).is_err());
+
+        // more channels
+        a.open_channel("mock1".into(), Default::default())?;
+        b.open_channel("mock1".into(), Default::default())?;
+        assert_eq!(a.opened_channels(), vec![MOCK_CHANNEL.to_string(), "mock1".to_string()]);
+        let mut msg = frame(0x456, &[0x02]);
+        msg.set_channel("mock1".into());
+        a.transmit(msg, None)?;
+        assert!(b.receive(MOCK_CHANNEL.into(), None)?.is_empty());
+        assert_eq!(b.receive("mock1".into(), None)?.len(), 1);
+
+        a.close_channel("mock1".into())?;
+        assert!(a.close_channel("mock1".into()).is_err());
+        assert_eq!(a.opened_channels(), vec![MOCK_CHANNEL.to_string()]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_shutdown() -> anyhow::Result<()> {
+        let (mut a, b) = VirtualBus::pair();
+        a.shutdown();
+        assert!(a.is_closed());
+        assert!(a.opened_channels().is_empty());
+        assert!(a.transmit(frame(0x123, &[0x01]), None).is_err());
+        assert!(!b.is_closed());
+
+        a.reopen()?;
+        assert!(!a.is_closed());
+        a.set_connected(false);
+        assert!(a.is_closed());
+        assert!(a.reopen().is_err());
+        a.set_connected(true);
+        a.reopen()?;
+        assert!(a.transmit(frame(0x123, &[0x01]), None).is_ok());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_iso_tp_round_trip() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+
+        let client_data = EventListener::default();
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(client_data.clone()),
+        );
+        let server_data = EventListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_data.clone()),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server.clone()));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let request = (0..0x20).collect::<Vec<u8>>();
+        client.write(false, request.clone())?;
+        assert_eq!(server_data.wait_data(Duration::from_secs(1)), Some(request));
+
+        let response = hex!("62 F1 90 4C 56 57 31 32 33 34 35 36 37 38 39 30 31 32 33 34").to_vec();
+        server.write(false, response.clone())?;
+        assert_eq!(client_data.wait_data(Duration::from_secs(1)), Some(response));
+
+        client.write(false, hex!("10 01").to_vec())?;
+        assert_eq!(server_data.wait_data(Duration::from_secs(1)), Some(hex!("10 01").to_vec()));
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_iso_tp_half_duplex() -> anyhow::Result<()> {
+        // both directions on 0x7E0, the drivers loop the own frames back like SocketCAN
+        let (a, b) = VirtualBus::pair();
+        a.set_loopback(true);
+        b.set_loopback(true);
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+
+        let address = Address { tx_id: 0x7E0, rx_id: 0x7E0, fid: 0x7DF };
+        let client_data = EventListener::default();
+        let client = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), address, client_can.sender(), Box::new(client_data.clone()));
+        let server_data = EventListener::default();
+        let server = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), address, server_can.sender(), Box::new(server_data.clone()));
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server.clone()));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let request = (0..0x20).collect::<Vec<u8>>();
+        client.write(false, request.clone())?;
+        assert_eq!(server_data.wait_data(Duration::from_secs(1)), Some(request));
+
+        let response = hex!("62 F1 90 4C 56 57 31 32 33 34 35 36 37 38 39 30 31 32 33 34").to_vec();
+        server.write(false, response.clone())?;
+        assert_eq!(client_data.wait_data(Duration::from_secs(1)), Some(response));
+        // the own data isn't received
+        assert_eq!(client_data.wait_data(Duration::from_millis(50)), None);
+        assert_eq!(server_data.wait_data(Duration::from_millis(50)), None);
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_iso_tp_transmit_failed() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut handle = a.clone();
+        let mut can = SyncCan::new(a);
+
+        let events = EventListener::default();
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            can.sender(),
+            Box::new(events.clone()),
+        );
+        can.register_listener("client".into(), Box::new(client.clone()));
+        can.sync_start(100);
+
+        handle.close_channel(MOCK_CHANNEL.to_string())?;
+        client.write(false, hex!("10 01").to_vec())?;
+        let error = events.wait_error(Duration::from_secs(1)).unwrap();
+        assert_eq!(error.to_string(), "ISO-TP - invalid parameter: channel `mock0` is not opened");
+
+        can.stop();
+        Ok(())
+    }
+}
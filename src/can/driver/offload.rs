@@ -0,0 +1,227 @@
+//! The listeners called by the workers of [`SyncCan`](crate::can::driver::SyncCan) instead of the loops,
+//! see [`ExecutionPolicy::Offloaded`].
+
+use std::any::Any;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex, Weak};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::mpsc::{channel, Sender};
+use std::thread::Builder;
+use crate::can::driver::{registry, ListenerRegistry, ListenerType, PanicGuard};
+use crate::can::error_frame::ErrorInfo;
+use crate::device::{BusState, Channel, FilterSpec, Listener};
+use crate::error::Error;
+
+/// The workers of the pool by default.
+const DEFAULT_WORKERS: usize = 2;
+
+type Job = Box<dyn FnOnce() + Send>;
+
+/// How the callbacks of a listener are executed, see [`SyncCan::register_listener_with`](crate::can::driver::SyncCan::register_listener_with).
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum ExecutionPolicy {
+    /// Called by the loops, a slow listener delays the listeners after it.
+    #[default]
+    Inline,
+    /// Called in order by a worker of the pool with the frames cloned, e.g. a logger writing to disk.
+    ///
+    /// The `capacity` callbacks at most are queued, the later frame callbacks are dropped and counted
+    /// instead of stalling the loops. The callbacks of the bus state, the connection, the transmit
+    /// failures and the shutdown are never dropped.
+    Offloaded { capacity: usize },
+}
+
+/// The workers of the offloaded listeners, spawned by the first listener assigned to each.
+pub(crate) struct OffloadPool {
+    size: AtomicUsize,
+    workers: Mutex<Vec<Sender<Job>>>,
+    next: AtomicUsize,
+}
+
+impl Default for OffloadPool {
+    fn default() -> Self {
+        Self {
+            size: AtomicUsize::new(DEFAULT_WORKERS),
+            workers: Default::default(),
+            next: Default::default(),
+        }
+    }
+}
+
+impl OffloadPool {
+    #[inline]
+    pub(crate) fn size(&self) -> usize {
+        self.size.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub(crate) fn set_size(&self, size: usize) {
+        self.size.store(size.max(1), Ordering::Release);
+    }
+
+    /// Assign a worker by round robin, all the callbacks of a listener are executed by it in order.
+    fn worker(&self) -> Option<Sender<Job>> {
+        let index = self.next.fetch_add(1, Ordering::AcqRel) % self.size();
+        let mut workers = self.workers.lock().ok()?;
+        while workers.len() <= index {
+            let (sender, receiver) = channel::<Job>();
+            Builder::new()
+                .name(format!("can-offload-{}", workers.len()))
+                // exits when all the listeners assigned and the pool are dropped
+                .spawn(move || while let Ok(job) = receiver.recv() {
+                    job();
+                })
+                .map_err(|e| log::error!("SyncCAN - spawn offload worker failed: {}", e))
+                .ok()?;
+            workers.push(sender);
+        }
+
+        workers.get(index).cloned()
+    }
+}
+
+/// The listener registered by [`ExecutionPolicy::Offloaded`], the callbacks are queued to its worker.
+pub(crate) struct Offloaded<C, F> {
+    name: String,
+    listener: Arc<Mutex<ListenerType<C, F>>>,
+    worker: Sender<Job>,
+    capacity: usize,
+    pending: Arc<AtomicUsize>,
+    dropped: Arc<AtomicU64>,
+    /// Unregister the listener after the max panics, weak to not keep the registry alive.
+    registry: Weak<ListenerRegistry<C, F>>,
+    guard: Arc<PanicGuard>,
+}
+
+impl<C, F> Offloaded<C, F>
+where
+    C: Channel,
+    F: Send + 'static,
+{
+    pub(crate) fn new(
+        name: String,
+        listener: ListenerType<C, F>,
+        capacity: usize,
+        pool: &OffloadPool,
+        registry: &Arc<ListenerRegistry<C, F>>,
+        guard: &Arc<PanicGuard>,
+    ) -> Option<Self> {
+        Some(Self {
+            name,
+            listener: Arc::new(Mutex::new(listener)),
+            worker: pool.worker()?,
+            capacity,
+            pending: Default::default(),
+            dropped: Default::default(),
+            registry: Arc::downgrade(registry),
+            guard: Arc::clone(guard),
+        })
+    }
+
+    /// The frame callbacks dropped by the full queue.
+    #[inline]
+    pub(crate) fn dropped(&self) -> u64 {
+        self.dropped.load(Ordering::Acquire)
+    }
+
+    /// Call `f` with the listener wrapped, it waits for the callback executed by the worker.
+    #[inline]
+    pub(crate) fn with_inner<R>(&self, f: impl FnOnce(&ListenerType<C, F>) -> R) -> Option<R> {
+        registry::lock(&self.listener).map(|o| f(&o))
+    }
+
+    /// Queue `f` to the worker, it's dropped when `droppable` and the queue is full.
+    fn offload(
+        &self,
+        callback: &'static str,
+        droppable: bool,
+        f: impl FnOnce(&mut ListenerType<C, F>) + Send + 'static,
+    ) {
+        if droppable && self.pending.load(Ordering::Acquire) >= self.capacity {
+            self.dropped.fetch_add(1, Ordering::AcqRel);
+            log::trace!("SyncCAN - `{}` of offloaded listener {} dropped", callback, self.name);
+            return;
+        }
+
+        self.pending.fetch_add(1, Ordering::AcqRel);
+        let (name, listener, pending, registry, guard) = (
+            self.name.clone(),
+            Arc::clone(&self.listener),
+            Arc::clone(&self.pending),
+            Weak::clone(&self.registry),
+            Arc::clone(&self.guard),
+        );
+        let job: Job = Box::new(move || {
+            if let Some(mut o) = registry::lock(&listener) {
+                if let Err(payload) = catch_unwind(AssertUnwindSafe(|| f(&mut o))) {
+                    if guard.on_panic(&name, callback, payload.as_ref()) {
+                        log::warn!("SyncCAN - unregister listener {} after panics", name);
+                        if let Some(registry) = registry.upgrade() {
+                            registry.unregister(&name);
+                        }
+                    }
+                }
+            }
+            pending.fetch_sub(1, Ordering::AcqRel);
+        });
+        if self.worker.send(job).is_err() {
+            self.pending.fetch_sub(1, Ordering::AcqRel);
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for Offloaded<C, F>
+where
+    C: Channel,
+    F: Clone + Send + 'static,
+{
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, channel: C, frame: &F) {
+        let frame = frame.clone();
+        self.offload("on_frame_transmitting", true, move |o| o.on_frame_transmitting(channel, &frame));
+    }
+
+    fn on_frame_transmitted(&mut self, channel: C, frame: &F) {
+        let frame = frame.clone();
+        self.offload("on_frame_transmitted", true, move |o| o.on_frame_transmitted(channel, &frame));
+    }
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        let frames = frames.to_vec();
+        self.offload("on_frame_received", true, move |o| o.on_frame_received(channel, &frames));
+    }
+
+    fn on_frames_received(&mut self, frames: &[(C, Vec<F>)]) {
+        let frames = frames.to_vec();
+        self.offload("on_frames_received", true, move |o| o.on_frames_received(&frames));
+    }
+
+    fn on_frame_transmit_failed(&mut self, channel: C, id: u32, error: &Error) {
+        let error = error.clone();
+        self.offload("on_frame_transmit_failed", false, move |o| o.on_frame_transmit_failed(channel, id, &error));
+    }
+
+    fn on_bus_state_changed(&mut self, channel: C, state: BusState) {
+        self.offload("on_bus_state_changed", false, move |o| o.on_bus_state_changed(channel, state));
+    }
+
+    fn on_error_frame(&mut self, channel: C, frame: &F, info: &ErrorInfo) {
+        let (frame, info) = (frame.clone(), *info);
+        self.offload("on_error_frame", true, move |o| o.on_error_frame(channel, &frame, &info));
+    }
+
+    fn on_connection_changed(&mut self, connected: bool) {
+        self.offload("on_connection_changed", false, move |o| o.on_connection_changed(connected));
+    }
+
+    fn on_shutdown(&mut self) {
+        self.offload("on_shutdown", false, |o| o.on_shutdown());
+    }
+
+    fn rx_filter(&self, channel: &C) -> Option<Vec<FilterSpec>> {
+        registry::lock(&self.listener)?.rx_filter(channel)
+    }
+}
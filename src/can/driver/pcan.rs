@@ -0,0 +1,832 @@
+//! PCAN-Basic backend for the adapters of PEAK-System.
+//!
+//! The PCAN-Basic library(`PCANBasic.dll` on Windows, `libpcanbasic.so` on Linux) is loaded
+//! when the driver is created, so the crate builds without the SDK installed.
+//! The channels are named as the handles of PCAN-Basic, e.g. `PCAN_USBBUS1`.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CString};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use libloading::Library;
+use crate::can::constant::{CAN_FRAME_MAX_SIZE, CANFD_FRAME_MAX_SIZE};
+use crate::can::dlc::{dlc_to_len, len_to_dlc};
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::can::message::CanMessage;
+use crate::device::{BusState, ChannelConfig, Driver, DriverCapabilities};
+use crate::error::Error;
+
+/// The library loaded by [`PcanDriver::new`].
+#[cfg(target_os = "windows")]
+pub const PCAN_LIBRARY: &str = "PCANBasic.dll";
+/// The library loaded by [`PcanDriver::new`].
+#[cfg(target_os = "macos")]
+pub const PCAN_LIBRARY: &str = "libPCBUSB.dylib";
+/// The library loaded by [`PcanDriver::new`].
+#[cfg(not(any(target_os = "windows", target_os = "macos")))]
+pub const PCAN_LIBRARY: &str = "libpcanbasic.so";
+
+const PCAN_ERROR_OK: u32 = 0x00000;
+const PCAN_ERROR_BUSLIGHT: u32 = 0x00004;
+const PCAN_ERROR_BUSHEAVY: u32 = 0x00008;
+const PCAN_ERROR_BUSOFF: u32 = 0x00010;
+const PCAN_ERROR_QRCVEMPTY: u32 = 0x00020;
+const PCAN_ERROR_BUSPASSIVE: u32 = 0x40000;
+/// The field of the handle errors, it's a value instead of the flags.
+const PCAN_ERROR_ILLHANDLE: u32 = 0x01C00;
+/// The bus errors reported with a frame transmitted or received.
+const PCAN_ERROR_BUS_WARNINGS: u32 = PCAN_ERROR_BUSLIGHT | PCAN_ERROR_BUSHEAVY | PCAN_ERROR_BUSPASSIVE;
+
+/// The status flags except the handle errors.
+const STATUS_FLAGS: [(u32, &str); 19] = [
+    (0x00001, "XMTFULL"),
+    (0x00002, "OVERRUN"),
+    (PCAN_ERROR_BUSLIGHT, "BUSLIGHT"),
+    (PCAN_ERROR_BUSHEAVY, "BUSHEAVY"),
+    (PCAN_ERROR_BUSOFF, "BUSOFF"),
+    (PCAN_ERROR_QRCVEMPTY, "QRCVEMPTY"),
+    (0x00040, "QOVERRUN"),
+    (0x00080, "QXMTFULL"),
+    (0x00100, "REGTEST"),
+    (0x00200, "NODRIVER"),
+    (0x02000, "RESOURCE"),
+    (0x04000, "ILLPARAMTYPE"),
+    (0x08000, "ILLPARAMVAL"),
+    (0x10000, "UNKNOWN"),
+    (0x20000, "ILLDATA"),
+    (PCAN_ERROR_BUSPASSIVE, "BUSPASSIVE"),
+    (0x80000, "ILLMODE"),
+    (0x2000000, "CAUTION"),
+    (0x4000000, "INITIALIZE"),
+];
+
+const PCAN_MESSAGE_RTR: u8 = 0x01;
+const PCAN_MESSAGE_EXTENDED: u8 = 0x02;
+const PCAN_MESSAGE_FD: u8 = 0x04;
+const PCAN_MESSAGE_BRS: u8 = 0x08;
+const PCAN_MESSAGE_ESI: u8 = 0x10;
+const PCAN_MESSAGE_ERRFRAME: u8 = 0x40;
+const PCAN_MESSAGE_STATUS: u8 = 0x80;
+
+const PCAN_CHANNEL_CONDITION: u8 = 0x0D;
+const PCAN_LISTEN_ONLY: u8 = 0x08;
+const PCAN_CHANNEL_AVAILABLE: u32 = 0x01;
+const PCAN_PARAMETER_OFF: u32 = 0x00;
+const PCAN_PARAMETER_ON: u32 = 0x01;
+
+/// The clock of the CAN-FD bit timing in MHz.
+const FD_CLOCK_MHZ: u32 = 80;
+
+/// The buses of the adapters and the handles of their channels 1 ~ 16.
+const BUSES: [(&str, [u16; 16]); 3] = [
+    ("PCAN_USBBUS", [0x51, 0x52, 0x53, 0x54, 0x55, 0x56, 0x57, 0x58, 0x509, 0x50A, 0x50B, 0x50C, 0x50D, 0x50E, 0x50F, 0x510]),
+    ("PCAN_PCIBUS", [0x41, 0x42, 0x43, 0x44, 0x45, 0x46, 0x47, 0x48, 0x409, 0x40A, 0x40B, 0x40C, 0x40D, 0x40E, 0x40F, 0x410]),
+    ("PCAN_LANBUS", [0x801, 0x802, 0x803, 0x804, 0x805, 0x806, 0x807, 0x808, 0x809, 0x80A, 0x80B, 0x80C, 0x80D, 0x80E, 0x80F, 0x810]),
+];
+
+/// The error of PCAN-Basic, it is carried by [`Error::Device`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum PcanError {
+    #[error("pcan - load `{0}` failed: {1}")]
+    Library(String, String),
+    #[error("pcan - status {0:#X}: {text}", text = status_text(*.0))]
+    Status(u32),
+    #[error("pcan - unknown channel: {0}")]
+    UnknownChannel(String),
+    #[error("pcan - unsupported bitrate: {0}")]
+    UnsupportedBitrate(u32),
+}
+
+/// The names of the status, e.g. `BUSOFF|QXMTFULL`.
+fn status_text(status: u32) -> String {
+    let handle = match status & PCAN_ERROR_ILLHANDLE {
+        0x0400 => Some("HWINUSE"),
+        0x0800 => Some("NETINUSE"),
+        0x1400 => Some("ILLHW"),
+        0x1800 => Some("ILLNET"),
+        0x1C00 => Some("ILLCLIENT"),
+        _ => None,
+    };
+    let names = STATUS_FLAGS.iter()
+        .filter(|(flag, _)| status & flag != 0)
+        .map(|(_, name)| *name)
+        .chain(handle)
+        .collect::<Vec<_>>();
+    match names.is_empty() {
+        true if status == PCAN_ERROR_OK => "OK".into(),
+        true => "UNDEFINED".into(),
+        false => names.join("|"),
+    }
+}
+
+/// Check the status of a call, the bus warnings are accepted.
+fn check(status: u32) -> Result<(), PcanError> {
+    match status & !PCAN_ERROR_BUS_WARNINGS {
+        PCAN_ERROR_OK => Ok(()),
+        _ => Err(PcanError::Status(status)),
+    }
+}
+
+/// The bus state of the status of `CAN_GetStatus`.
+fn bus_state(status: u32) -> BusState {
+    if status & PCAN_ERROR_BUSOFF != 0 {
+        BusState::BusOff
+    }
+    else if status & PCAN_ERROR_BUSPASSIVE != 0 {
+        BusState::ErrorPassive
+    }
+    else if status & !(PCAN_ERROR_BUSLIGHT | PCAN_ERROR_BUSHEAVY) == PCAN_ERROR_OK {
+        BusState::ErrorActive
+    }
+    else {
+        BusState::Unknown
+    }
+}
+
+/// The handle of the channel name, e.g. `0x51` of `PCAN_USBBUS1`.
+fn channel_handle(name: &str) -> Option<u16> {
+    BUSES.iter()
+        .find_map(|(bus, handles)| {
+            let index = name.strip_prefix(bus)?
+                .parse::<usize>().ok()?;
+            handles.get(index.checked_sub(1)?).copied()
+        })
+}
+
+/// The channel name of the handle.
+fn channel_name(handle: u16) -> Option<String> {
+    BUSES.iter()
+        .find_map(|(bus, handles)| handles.iter()
+            .position(|v| *v == handle)
+            .map(|index| format!("{}{}", bus, index + 1)))
+}
+
+/// The BTR0/BTR1 register value of the classic bitrate.
+fn btr0btr1(bitrate: u32) -> Result<u16, PcanError> {
+    match bitrate {
+        1_000_000 => Ok(0x0014),
+        800_000 => Ok(0x0016),
+        500_000 => Ok(0x001C),
+        250_000 => Ok(0x011C),
+        125_000 => Ok(0x031C),
+        100_000 => Ok(0x432F),
+        95_000 => Ok(0xC34E),
+        83_000 => Ok(0x852B),
+        50_000 => Ok(0x472F),
+        47_000 => Ok(0x1414),
+        33_000 => Ok(0x8B2F),
+        20_000 => Ok(0x532F),
+        10_000 => Ok(0x672F),
+        5_000 => Ok(0x7F7F),
+        _ => Err(PcanError::UnsupportedBitrate(bitrate)),
+    }
+}
+
+/// The prescaler, TSEG1 and TSEG2 of `bitrate` at the FD clock, the most time quanta are preferred.
+fn bit_timing(bitrate: u32, sample_point: f32, max_brp: u32, max_tseg1: u32, max_tseg2: u32) -> Result<(u32, u32, u32), PcanError> {
+    let clock = FD_CLOCK_MHZ as u64 * 1_000_000;
+    (1..=max_brp)
+        .filter(|brp| bitrate > 0 && clock.is_multiple_of(*brp as u64 * bitrate as u64))
+        .find_map(|brp| {
+            let tq = (clock / (brp as u64 * bitrate as u64)) as u32;
+            // the sync segment is 1 time quantum
+            let tseg1 = ((tq as f32 * sample_point).round() as u32).checked_sub(1)?;
+            let tseg2 = tq.checked_sub(1 + tseg1)?;
+            ((1..=max_tseg1).contains(&tseg1) && (1..=max_tseg2).contains(&tseg2))
+                .then_some((brp, tseg1, tseg2))
+        })
+        .ok_or(PcanError::UnsupportedBitrate(bitrate))
+}
+
+/// The bitrate string of `CAN_InitializeFD`, the sample points are 80% and 75% by default.
+fn fd_bitrate(config: &ChannelConfig) -> Result<String, PcanError> {
+    let data_bitrate = config.data_bitrate.unwrap_or(config.bitrate);
+    let (nom_brp, nom_tseg1, nom_tseg2) = bit_timing(config.bitrate, config.sample_point.unwrap_or(0.8), 1024, 256, 128)?;
+    let (data_brp, data_tseg1, data_tseg2) = bit_timing(data_bitrate, config.data_sample_point.unwrap_or(0.75), 1024, 32, 16)?;
+
+    Ok(format!(
+        "f_clock_mhz={},nom_brp={},nom_tseg1={},nom_tseg2={},nom_sjw={},data_brp={},data_tseg1={},data_tseg2={},data_sjw={}",
+        FD_CLOCK_MHZ, nom_brp, nom_tseg1, nom_tseg2, nom_tseg2, data_brp, data_tseg1, data_tseg2, data_tseg2,
+    ))
+}
+
+/// `TPCANMsg` of PCAN-Basic.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct PcanMsg {
+    id: u32,
+    msg_type: u8,
+    len: u8,
+    data: [u8; CAN_FRAME_MAX_SIZE],
+}
+
+/// `TPCANTimestamp` of PCAN-Basic.
+#[repr(C)]
+#[derive(Debug, Default, Clone, Copy)]
+struct PcanTimestamp {
+    millis: u32,
+    millis_overflow: u16,
+    micros: u16,
+}
+
+/// `TPCANMsgFD` of PCAN-Basic.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+struct PcanMsgFd {
+    id: u32,
+    msg_type: u8,
+    dlc: u8,
+    data: [u8; CANFD_FRAME_MAX_SIZE],
+}
+
+impl Default for PcanMsgFd {
+    fn default() -> Self {
+        Self { id: 0, msg_type: 0, dlc: 0, data: [0; CANFD_FRAME_MAX_SIZE] }
+    }
+}
+
+/// The message type flags of a frame.
+fn msg_type(frame: &CanMessage) -> u8 {
+    let mut result = 0;
+    if frame.is_extended() {
+        result |= PCAN_MESSAGE_EXTENDED;
+    }
+    if frame.is_remote() {
+        result |= PCAN_MESSAGE_RTR;
+    }
+    if frame.is_can_fd() {
+        result |= PCAN_MESSAGE_FD;
+        if frame.is_bitrate_switch() {
+            result |= PCAN_MESSAGE_BRS;
+        }
+        if frame.is_esi() {
+            result |= PCAN_MESSAGE_ESI;
+        }
+    }
+    result
+}
+
+/// Convert a classic frame to `TPCANMsg`.
+fn to_msg(frame: &CanMessage) -> Result<PcanMsg, Error> {
+    if frame.is_can_fd() {
+        return Err(Error::Unsupported("CAN-FD frame on classic PCAN channel".into()));
+    }
+
+    let mut result = PcanMsg {
+        id: frame.id().into_bits(),
+        msg_type: msg_type(frame),
+        len: frame.length() as u8,
+        ..Default::default()
+    };
+    let data = frame.data();
+    result.data[..data.len()].copy_from_slice(data);
+    Ok(result)
+}
+
+/// Convert a classic or CAN-FD frame to `TPCANMsgFD`.
+fn to_msg_fd(frame: &CanMessage) -> Result<PcanMsgFd, Error> {
+    let dlc = len_to_dlc(frame.length())
+        .filter(|v| frame.is_can_fd() || *v as usize <= CAN_FRAME_MAX_SIZE)
+        .ok_or(Error::InvalidParam(format!("invalid frame length: {}", frame.length())))?;
+    let mut result = PcanMsgFd {
+        id: frame.id().into_bits(),
+        msg_type: msg_type(frame),
+        dlc,
+        ..Default::default()
+    };
+    let data = frame.data();
+    result.data[..data.len()].copy_from_slice(data);
+    Ok(result)
+}
+
+/// Convert a received frame, `None` for the status and error frames.
+fn from_raw(id: u32, msg_type: u8, data: &[u8], length: usize, channel: &str) -> Option<CanMessage> {
+    if msg_type & (PCAN_MESSAGE_STATUS | PCAN_MESSAGE_ERRFRAME) != 0 {
+        return None;
+    }
+
+    let id = Id::try_from_bits(id, msg_type & PCAN_MESSAGE_EXTENDED != 0)?;
+    let mut frame = if msg_type & PCAN_MESSAGE_RTR != 0 {
+        CanMessage::new_remote(id, length)?
+    }
+    else {
+        let mut frame = CanMessage::new(id, data.get(..length)?)?;
+        frame.set_can_fd(msg_type & PCAN_MESSAGE_FD != 0)
+            .set_bitrate_switch(msg_type & PCAN_MESSAGE_BRS != 0)
+            .set_esi(msg_type & PCAN_MESSAGE_ESI != 0);
+        frame
+    };
+    frame.set_channel(channel.into())
+        .set_direct(Direct::Receive)
+        .set_timestamp(None);
+    Some(frame)
+}
+
+#[inline]
+fn from_msg(msg: &PcanMsg, channel: &str) -> Option<CanMessage> {
+    from_raw(msg.id, msg.msg_type, &msg.data, msg.len as usize, channel)
+}
+
+#[inline]
+fn from_msg_fd(msg: &PcanMsgFd, channel: &str) -> Option<CanMessage> {
+    from_raw(msg.id, msg.msg_type, &msg.data, dlc_to_len(msg.dlc)?, channel)
+}
+
+type InitializeFn = unsafe extern "system" fn(u16, u16, u8, u32, u16) -> u32;
+type InitializeFdFn = unsafe extern "system" fn(u16, *const c_char) -> u32;
+type HandleFn = unsafe extern "system" fn(u16) -> u32;
+type ReadFn = unsafe extern "system" fn(u16, *mut PcanMsg, *mut PcanTimestamp) -> u32;
+type ReadFdFn = unsafe extern "system" fn(u16, *mut PcanMsgFd, *mut u64) -> u32;
+type WriteFn = unsafe extern "system" fn(u16, *mut PcanMsg) -> u32;
+type WriteFdFn = unsafe extern "system" fn(u16, *mut PcanMsgFd) -> u32;
+type ValueFn = unsafe extern "system" fn(u16, u8, *mut c_void, u32) -> u32;
+
+/// The functions of PCAN-Basic, the library is kept loaded with them.
+#[derive(Debug)]
+struct Api {
+    initialize: InitializeFn,
+    initialize_fd: InitializeFdFn,
+    uninitialize: HandleFn,
+    get_status: HandleFn,
+    read: ReadFn,
+    read_fd: ReadFdFn,
+    write: WriteFn,
+    write_fd: WriteFdFn,
+    get_value: ValueFn,
+    set_value: ValueFn,
+    _library: Library,
+}
+
+impl Api {
+    fn load(path: &str) -> Result<Self, PcanError> {
+        let error = |e: libloading::Error| PcanError::Library(path.into(), e.to_string());
+        // SAFETY: the signatures are of PCANBasic.h, the library outlives the functions
+        unsafe {
+            let library = Library::new(path).map_err(error)?;
+            Ok(Self {
+                initialize: *library.get(b"CAN_Initialize\0").map_err(error)?,
+                initialize_fd: *library.get(b"CAN_InitializeFD\0").map_err(error)?,
+                uninitialize: *library.get(b"CAN_Uninitialize\0").map_err(error)?,
+                get_status: *library.get(b"CAN_GetStatus\0").map_err(error)?,
+                read: *library.get(b"CAN_Read\0").map_err(error)?,
+                read_fd: *library.get(b"CAN_ReadFD\0").map_err(error)?,
+                write: *library.get(b"CAN_Write\0").map_err(error)?,
+                write_fd: *library.get(b"CAN_WriteFD\0").map_err(error)?,
+                get_value: *library.get(b"CAN_GetValue\0").map_err(error)?,
+                set_value: *library.get(b"CAN_SetValue\0").map_err(error)?,
+                _library: library,
+            })
+        }
+    }
+
+    fn get_u32(&self, handle: u16, parameter: u8) -> Result<u32, PcanError> {
+        let mut value = 0u32;
+        // SAFETY: the buffer is 4 bytes as its length
+        check(unsafe { (self.get_value)(handle, parameter, &mut value as *mut u32 as *mut c_void, 4) })?;
+        Ok(value)
+    }
+
+    fn set_u32(&self, handle: u16, parameter: u8, mut value: u32) -> Result<(), PcanError> {
+        // SAFETY: the buffer is 4 bytes as its length
+        check(unsafe { (self.set_value)(handle, parameter, &mut value as *mut u32 as *mut c_void, 4) })
+    }
+}
+
+#[derive(Debug)]
+struct Channel {
+    handle: u16,
+    config: ChannelConfig,
+}
+
+/// [`Driver`] over PCAN-Basic.
+///
+/// The reads are non-blocking, [`Driver::receive`] returns the frames queued by the library,
+/// this is suitable for the polling loop of [`crate::can::driver::SyncCan`]. The frames are
+/// timestamped when received.
+#[derive(Debug, Clone)]
+pub struct PcanDriver {
+    api: Arc<Api>,
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl PcanDriver {
+    /// Load [`PCAN_LIBRARY`], the channels are opened by [`Driver::open_channel`].
+    #[inline]
+    pub fn new() -> Result<Self, Error> {
+        Self::with_library(PCAN_LIBRARY)
+    }
+
+    /// Load PCAN-Basic from `path`.
+    pub fn with_library(path: &str) -> Result<Self, Error> {
+        let api = Api::load(path)
+            .map_err(Error::device)?;
+
+        Ok(Self {
+            api: Arc::new(api),
+            channels: Default::default(),
+            closed: Default::default(),
+        })
+    }
+
+    /// Create a driver with the `channels` opened with `config`.
+    pub fn open(channels: &[&str], config: ChannelConfig) -> Result<Self, Error> {
+        let mut driver = Self::new()?;
+        for channel in channels {
+            driver.open_channel(channel.to_string(), config)?;
+        }
+
+        Ok(driver)
+    }
+
+    /// The channels of the adapters attached and not opened by another application.
+    pub fn available_channels(&self) -> Vec<String> {
+        BUSES.iter()
+            .flat_map(|(_, handles)| handles.iter())
+            .filter(|handle| self.api.get_u32(**handle, PCAN_CHANNEL_CONDITION)
+                .is_ok_and(|v| v & PCAN_CHANNEL_AVAILABLE != 0))
+            .filter_map(|handle| channel_name(*handle))
+            .collect()
+    }
+
+    #[inline]
+    fn channels(&self) -> Result<std::sync::MutexGuard<'_, HashMap<String, Channel>>, Error> {
+        self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))
+    }
+
+    fn transmit_util(&self, msg: CanMessage) -> Result<(), Error> {
+        let channels = self.channels()?;
+        let channel = channels.get(&msg.channel())
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", msg.channel())))?;
+        if channel.config.listen_only {
+            return Err(Error::Unsupported(format!("transmit on listen-only channel `{}`", msg.channel())));
+        }
+
+        // SAFETY: the messages are of PCANBasic.h and live through the calls
+        let status = if channel.config.fd {
+            let mut raw = to_msg_fd(&msg)?;
+            unsafe { (self.api.write_fd)(channel.handle, &mut raw) }
+        }
+        else {
+            let mut raw = to_msg(&msg)?;
+            unsafe { (self.api.write)(channel.handle, &mut raw) }
+        };
+        check(status)
+            .map_err(Error::device)
+    }
+
+    fn receive_util(&self, channel: String, buf: &mut Vec<CanMessage>) -> Result<usize, Error> {
+        let channels = self.channels()?;
+        let Channel { handle, config } = channels.get(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?;
+
+        let start = buf.len();
+        loop {
+            // SAFETY: the buffers are of PCANBasic.h and live through the calls
+            let (status, frame) = if config.fd {
+                let mut raw = PcanMsgFd::default();
+                let mut timestamp = 0u64;
+                let status = unsafe { (self.api.read_fd)(*handle, &mut raw, &mut timestamp) };
+                (status, from_msg_fd(&raw, &channel))
+            }
+            else {
+                let mut raw = PcanMsg::default();
+                let mut timestamp = PcanTimestamp::default();
+                let status = unsafe { (self.api.read)(*handle, &mut raw, &mut timestamp) };
+                (status, from_msg(&raw, &channel))
+            };
+
+            if status & PCAN_ERROR_QRCVEMPTY != 0 {
+                break;
+            }
+            if let Err(e) = check(status) {
+                if buf.len() == start {
+                    return Err(Error::device(e));
+                }
+                log::warn!("pcan - receive from `{}` failed: {}", channel, e);
+                break;
+            }
+            match frame {
+                Some(frame) => buf.push(frame),
+                // the bus state is polled by `bus_state`
+                None => log::trace!("pcan - status or error frame of `{}` ignored", channel),
+            }
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    fn shutdown_util(&mut self) {
+        log::info!("pcan - shutdown");
+        self.closed.store(true, Ordering::Release);
+        if let Ok(mut channels) = self.channels() {
+            for (name, channel) in channels.drain() {
+                // SAFETY: the handle is initialized by `open_channel`
+                if let Err(e) = check(unsafe { (self.api.uninitialize)(channel.handle) }) {
+                    log::warn!("pcan - close channel `{}` failed: {}", name, e);
+                }
+            }
+        }
+    }
+}
+
+impl Driver for PcanDriver {
+    type Error = Error;
+    type C = String;
+    type F = CanMessage;
+
+    fn opened_channels(&self) -> Vec<Self::C> {
+        match self.channels() {
+            Ok(channels) => channels.keys()
+                .cloned()
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Initialize the channel by the BTR0/BTR1 of the standard bitrates, or by the bit timing of the
+    /// 80 MHz clock when `fd` is enabled. An opened channel is initialized again.
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        let handle = channel_handle(&channel)
+            .ok_or_else(|| Error::device(PcanError::UnknownChannel(channel.clone())))?;
+        log::debug!("pcan - open channel `{}` with {:?}", channel, config);
+
+        let mut channels = self.channels()?;
+        if channels.remove(&channel).is_some() {
+            // SAFETY: the handle is initialized
+            let _ = unsafe { (self.api.uninitialize)(handle) };
+        }
+        // set before the initialization
+        let listen_only = if config.listen_only { PCAN_PARAMETER_ON } else { PCAN_PARAMETER_OFF };
+        self.api.set_u32(handle, PCAN_LISTEN_ONLY, listen_only)
+            .map_err(Error::device)?;
+        let status = if config.fd {
+            let bitrate = fd_bitrate(&config)
+                .map_err(Error::device)?;
+            let bitrate = CString::new(bitrate)
+                .map_err(|e| Error::InvalidParam(e.to_string()))?;
+            // SAFETY: the string is terminated and lives through the call
+            unsafe { (self.api.initialize_fd)(handle, bitrate.as_ptr()) }
+        }
+        else {
+            let btr0btr1 = btr0btr1(config.bitrate)
+                .map_err(Error::device)?;
+            // the type, port and interrupt are of the non-plug-and-play adapters only
+            unsafe { (self.api.initialize)(handle, btr0btr1, 0, 0, 0) }
+        };
+        check(status)
+            .map_err(Error::device)?;
+
+        channels.insert(channel, Channel { handle, config });
+        self.closed.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        log::debug!("pcan - close channel `{}`", channel);
+        let mut channels = self.channels()?;
+        let Channel { handle, .. } = channels.remove(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?;
+        // SAFETY: the handle is initialized by `open_channel`
+        check(unsafe { (self.api.uninitialize)(handle) })
+            .map_err(Error::device)
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// The state of `CAN_GetStatus`.
+    fn bus_state(&self, channel: Self::C) -> BusState {
+        let handle = match self.channels() {
+            Ok(channels) => channels.get(&channel).map(|v| v.handle),
+            Err(_) => None,
+        };
+        match handle {
+            // SAFETY: the handle is initialized by `open_channel`
+            Some(handle) => bus_state(unsafe { (self.api.get_status)(handle) }),
+            None => BusState::Unknown,
+        }
+    }
+
+    /// The frames are timestamped when received, the error frames are not received.
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities {
+            fd: true,
+            brs: true,
+            listen_only: true,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(not(feature = "async"))]
+    fn receive_into(&self, channel: Self::C, _: Option<u32>, buf: &mut Vec<Self::F>) -> Result<usize, Self::Error> {
+        self.receive_util(channel, buf)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use crate::device::{BusState, ChannelConfig};
+    use super::*;
+
+    const CHANNEL: &str = "PCAN_USBBUS1";
+
+    fn frame(id: impl Into<Id>, data: &[u8]) -> CanMessage {
+        let mut frame = CanMessage::new(id, data).unwrap();
+        frame.set_channel(CHANNEL.into());
+        frame
+    }
+
+    #[test]
+    fn test_channel() {
+        assert_eq!(channel_handle("PCAN_USBBUS1"), Some(0x51));
+        assert_eq!(channel_handle("PCAN_USBBUS9"), Some(0x509));
+        assert_eq!(channel_handle("PCAN_PCIBUS16"), Some(0x410));
+        assert_eq!(channel_handle("PCAN_LANBUS2"), Some(0x802));
+        for name in ["PCAN_USBBUS0", "PCAN_USBBUS17", "PCAN_USBBUS", "PCAN_ISABUS1", "can0"] {
+            assert_eq!(channel_handle(name), None, "{}", name);
+        }
+        assert_eq!(channel_name(0x58).as_deref(), Some("PCAN_USBBUS8"));
+        assert_eq!(channel_name(0x50A).as_deref(), Some("PCAN_USBBUS10"));
+        assert_eq!(channel_name(0x00), None);
+    }
+
+    #[test]
+    fn test_bitrate() -> anyhow::Result<()> {
+        assert_eq!(btr0btr1(500_000)?, 0x001C);
+        assert_eq!(btr0btr1(1_000_000)?, 0x0014);
+        assert_eq!(btr0btr1(33_333), Err(PcanError::UnsupportedBitrate(33_333)));
+
+        assert_eq!(
+            fd_bitrate(&ChannelConfig::new_fd(500_000, 2_000_000))?,
+            "f_clock_mhz=80,nom_brp=1,nom_tseg1=127,nom_tseg2=32,nom_sjw=32,data_brp=1,data_tseg1=29,data_tseg2=10,data_sjw=10",
+        );
+        // the sample point of the arbitration phase is configured
+        let config = ChannelConfig { sample_point: Some(0.875), ..ChannelConfig::new_fd(1_000_000, 5_000_000) };
+        assert_eq!(
+            fd_bitrate(&config)?,
+            "f_clock_mhz=80,nom_brp=1,nom_tseg1=69,nom_tseg2=10,nom_sjw=10,data_brp=1,data_tseg1=11,data_tseg2=4,data_sjw=4",
+        );
+        assert!(fd_bitrate(&ChannelConfig::new_fd(500_000, 3_000_000)).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_status() {
+        assert!(check(PCAN_ERROR_OK).is_ok());
+        assert!(check(PCAN_ERROR_BUSHEAVY).is_ok());
+        assert_eq!(check(PCAN_ERROR_BUSOFF), Err(PcanError::Status(PCAN_ERROR_BUSOFF)));
+
+        assert_eq!(status_text(PCAN_ERROR_OK), "OK");
+        assert_eq!(status_text(0x80 | PCAN_ERROR_BUSOFF), "BUSOFF|QXMTFULL");
+        assert_eq!(status_text(0x1400), "ILLHW");
+        assert_eq!(status_text(0x1C00), "ILLCLIENT");
+        assert_eq!(status_text(0x1000_0000), "UNDEFINED");
+        assert_eq!(PcanError::Status(0x200).to_string(), "pcan - status 0x200: NODRIVER");
+
+        assert_eq!(bus_state(PCAN_ERROR_OK), BusState::ErrorActive);
+        assert_eq!(bus_state(PCAN_ERROR_BUSLIGHT), BusState::ErrorActive);
+        assert_eq!(bus_state(PCAN_ERROR_BUSPASSIVE), BusState::ErrorPassive);
+        assert_eq!(bus_state(PCAN_ERROR_BUSOFF | PCAN_ERROR_BUSPASSIVE), BusState::BusOff);
+        assert_eq!(bus_state(0x4000000), BusState::Unknown);
+    }
+
+    #[test]
+    fn test_msg() -> anyhow::Result<()> {
+        let msg = to_msg(&frame(0x7E0, &hex!("02 10 01")))?;
+        assert_eq!(msg, PcanMsg { id: 0x7E0, msg_type: 0, len: 3, data: hex!("02 10 01 00 00 00 00 00") });
+        let result = from_msg(&msg, CHANNEL).unwrap();
+        assert_eq!(result.id(), Id::Standard(0x7E0));
+        assert_eq!(result.data(), hex!("02 10 01"));
+        assert_eq!(result.channel(), CHANNEL);
+        assert!(!result.is_can_fd());
+
+        let msg = to_msg(&frame(Id::Extended(0x18DA00F1), &hex!("02 10 01")))?;
+        assert_eq!(msg.msg_type, PCAN_MESSAGE_EXTENDED);
+        assert_eq!(from_msg(&msg, CHANNEL).unwrap().id(), Id::Extended(0x18DA00F1));
+
+        let msg = to_msg(&CanMessage::new_remote(0x123, 8).unwrap())?;
+        assert_eq!((msg.msg_type, msg.len), (PCAN_MESSAGE_RTR, 8));
+        let result = from_msg(&msg, CHANNEL).unwrap();
+        assert!(result.is_remote());
+        assert_eq!(result.length(), 8);
+
+        assert!(to_msg(&frame(0x7E0, &[0x55; 12])).is_err());
+        // the bus state is reported by the status frames
+        let msg = PcanMsg { msg_type: PCAN_MESSAGE_STATUS, len: 4, ..Default::default() };
+        assert!(from_msg(&msg, CHANNEL).is_none());
+        let msg = PcanMsg { msg_type: PCAN_MESSAGE_ERRFRAME, ..Default::default() };
+        assert!(from_msg(&msg, CHANNEL).is_none());
+        Ok(())
+    }
+
+    #[test]
+    fn test_msg_fd() -> anyhow::Result<()> {
+        let mut fd = frame(0x7E0, &[0x55; 12]);
+        fd.set_bitrate_switch(true).set_esi(true);
+        let msg = to_msg_fd(&fd)?;
+        assert_eq!(msg.dlc, 9);
+        assert_eq!(msg.msg_type, PCAN_MESSAGE_FD | PCAN_MESSAGE_BRS | PCAN_MESSAGE_ESI);
+        let result = from_msg_fd(&msg, CHANNEL).unwrap();
+        assert!(result.is_can_fd() && result.is_bitrate_switch() && result.is_esi());
+        assert_eq!(result.data(), [0x55; 12]);
+
+        // a classic frame on the FD channel
+        let msg = to_msg_fd(&frame(0x7E0, &hex!("02 10 01")))?;
+        assert_eq!((msg.msg_type, msg.dlc), (0, 3));
+        let result = from_msg_fd(&msg, CHANNEL).unwrap();
+        assert!(!result.is_can_fd());
+        assert_eq!(result.data(), hex!("02 10 01"));
+
+        let msg = PcanMsgFd { dlc: 16, ..Default::default() };
+        assert!(from_msg_fd(&msg, CHANNEL).is_none());
+        Ok(())
+    }
+}
+
+/// Requires a PEAK adapter connected to a bus with another node acknowledging the frames,
+/// the channel is given by `PCAN_TEST_CHANNEL`, e.g. `PCAN_USBBUS1`, and the bitrate by
+/// `PCAN_TEST_BITRATE`(500 kbit/s by default).
+#[cfg(all(test, not(feature = "async")))]
+mod hardware_tests {
+    use std::time::Duration;
+    use crate::can::driver::SyncCan;
+    use crate::can::frame::FrameMut;
+    use crate::can::message::CanMessage;
+    use crate::device::{BusState, ChannelConfig, Driver};
+    use super::PcanDriver;
+
+    fn channel() -> Option<(String, u32)> {
+        let channel = std::env::var("PCAN_TEST_CHANNEL").ok()?;
+        let bitrate = std::env::var("PCAN_TEST_BITRATE").ok()
+            .and_then(|v| v.parse().ok())
+            .unwrap_or(500_000);
+        Some((channel, bitrate))
+    }
+
+    #[test]
+    fn test_pcan() -> anyhow::Result<()> {
+        let Some((channel, bitrate)) = channel() else {
+            println!("skipped: `PCAN_TEST_CHANNEL` is not set");
+            return Ok(());
+        };
+
+        let mut driver = PcanDriver::open(&[&channel], ChannelConfig::new(bitrate))?;
+        assert_eq!(driver.opened_channels(), vec![channel.clone()]);
+        assert_ne!(driver.bus_state(channel.clone()), BusState::Unknown);
+        assert!(!driver.available_channels().contains(&channel));
+
+        let mut frame = CanMessage::new(0x7DF, &[0x02, 0x3E, 0x80]).unwrap();
+        frame.set_channel(channel.clone());
+        driver.transmit(frame, None)?;
+        driver.receive(channel.clone(), None)?;
+
+        let can = SyncCan::new(driver.clone());
+        let report = can.self_test(channel.clone(), Duration::from_secs(1))?;
+        assert!(report.transmitted, "{:?}", report);
+
+        driver.shutdown();
+        assert!(driver.is_closed());
+        assert!(driver.opened_channels().is_empty());
+        Ok(())
+    }
+}
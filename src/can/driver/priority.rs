@@ -0,0 +1,165 @@
+//! The priority order of the queued frames transmitted by the transmit loop.
+
+use std::time::{Duration, Instant};
+use crate::can::frame::Frame;
+
+/// Transmit the queued frames by priority instead of FIFO, see [`crate::can::driver::SyncCan::set_tx_priority`].
+///
+/// The frames of the same priority keep the FIFO order, so the frames of a transport session
+/// are never reordered and their pacing is kept.
+#[derive(Debug)]
+pub struct TxPriority<F> {
+    /// The priority of the frame, 0 is the highest.
+    pub priority: fn(&F) -> u8,
+    /// A queued frame is raised by one priority for each `aging` it waits to avoid starvation,
+    /// `Duration::ZERO` to disable.
+    pub aging: Duration,
+}
+
+impl<F> Clone for TxPriority<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for TxPriority<F> {}
+
+impl<F: Frame> TxPriority<F> {
+    /// Order by the priority bits of the 29-bit J1939 identifier, the standard frames go first.
+    #[cfg(feature = "j1939")]
+    pub fn j1939(aging: Duration) -> Self {
+        Self {
+            priority: |frame| if frame.is_extended() {
+                crate::can::j1939::J1939Id::from(frame.id()).priority()
+            }
+            else {
+                0
+            },
+            aging,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending<F> {
+    priority: u8,
+    seq: u64,
+    queued: Instant,
+    frame: F,
+}
+
+/// The frames taken from the sender and waiting for transmission.
+#[derive(Debug)]
+pub(crate) struct TxQueue<F> {
+    order: Option<TxPriority<F>>,
+    next_seq: u64,
+    items: Vec<Pending<F>>,
+}
+
+impl<F> Default for TxQueue<F> {
+    fn default() -> Self {
+        Self { order: Default::default(), next_seq: Default::default(), items: Default::default() }
+    }
+}
+
+impl<F> TxQueue<F> {
+    #[inline]
+    pub(crate) fn set_order(&mut self, order: Option<TxPriority<F>>) {
+        self.order = order;
+    }
+
+    #[inline]
+    pub(crate) fn is_ordered(&self) -> bool {
+        self.order.is_some()
+    }
+
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.items.is_empty()
+    }
+
+    pub(crate) fn push(&mut self, frame: F, now: Instant) {
+        let priority = self.order.map_or(0, |v| (v.priority)(&frame));
+        self.items.push(Pending { priority, seq: self.next_seq, queued: now, frame });
+        self.next_seq += 1;
+    }
+
+    /// Take the frame of the highest priority after aging at `now`, the earliest one of the same priority.
+    ///
+    /// The queued frames are taken in FIFO order when the priority is disabled.
+    pub(crate) fn pop(&mut self, now: Instant) -> Option<F> {
+        let aging = self.order.map_or(Duration::ZERO, |v| v.aging);
+        let index = self.items.iter()
+            .enumerate()
+            .min_by_key(|(_, v)| {
+                let priority = match (self.order, aging.is_zero()) {
+                    (None, _) => 0,
+                    (Some(_), true) => v.priority,
+                    (Some(_), false) => {
+                        let raised = (now - v.queued).as_nanos() / aging.as_nanos();
+                        v.priority.saturating_sub(raised.min(u8::MAX as u128) as u8)
+                    },
+                };
+                (priority, v.seq)
+            })
+            .map(|(i, _)| i)?;
+
+        Some(self.items.remove(index).frame)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::{Duration, Instant};
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use super::{TxPriority, TxQueue};
+
+    fn priority(frame: &CanMessage) -> u8 {
+        (frame.id().into_bits() >> 26) as u8 & 0x07
+    }
+
+    fn frame(id: u32) -> CanMessage {
+        CanMessage::new(Id::Extended(id), &[id as u8]).unwrap()
+    }
+
+    #[test]
+    fn test_order() {
+        let now = Instant::now();
+        let mut queue = TxQueue::default();
+        queue.set_order(Some(TxPriority { priority, aging: Duration::ZERO }));
+        for id in [0x18FEF100, 0x0CF00400, 0x18FEF101, 0x1CECFF00, 0x0CF00401] {
+            queue.push(frame(id), now);
+        }
+        let ids = std::iter::from_fn(|| queue.pop(now))
+            .map(|v| v.id().into_bits())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, [0x0CF00400, 0x0CF00401, 0x18FEF100, 0x18FEF101, 0x1CECFF00]);
+
+        // FIFO when disabled
+        queue.set_order(None);
+        queue.push(frame(0x18FEF100), now);
+        queue.push(frame(0x0CF00400), now);
+        assert_eq!(queue.pop(now).unwrap().id().into_bits(), 0x18FEF100);
+        assert_eq!(queue.pop(now).unwrap().id().into_bits(), 0x0CF00400);
+        assert!(queue.pop(now).is_none());
+    }
+
+    #[test]
+    fn test_aging() {
+        let now = Instant::now();
+        let aging = Duration::from_millis(10);
+        let mut queue = TxQueue::default();
+        queue.set_order(Some(TxPriority { priority, aging }));
+        queue.push(frame(0x1CECFF00), now);
+        queue.push(frame(0x0CF00400), now + aging * 3);
+        // raised from 7 to 4, still lower than 3
+        assert_eq!(queue.pop(now + aging * 3).unwrap().id().into_bits(), 0x0CF00400);
+
+        queue.push(frame(0x0CF00401), now + aging * 4);
+        // raised to 3 and queued earlier
+        assert_eq!(queue.pop(now + aging * 4).unwrap().id().into_bits(), 0x1CECFF00);
+        assert_eq!(queue.pop(now + aging * 4).unwrap().id().into_bits(), 0x0CF00401);
+    }
+}
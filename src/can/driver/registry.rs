@@ -0,0 +1,94 @@
+use std::sync::{Arc, Mutex, MutexGuard};
+use arc_swap::ArcSwap;
+use crate::can::driver::ListenerType;
+
+pub(crate) type ListenerEntry<C, F> = (String, Arc<Mutex<ListenerType<C, F>>>);
+
+/// The registered listeners of [`SyncCan`](crate::can::driver::SyncCan).
+///
+/// The loops dispatch to a copy-on-write snapshot, so registering never waits for the callbacks
+/// and the callbacks never wait for registering. Each listener has its own mutex because the
+/// callbacks take `&mut self`, it's only contended by the loops calling the same listener.
+pub(crate) struct ListenerRegistry<C, F> {
+    entries: ArcSwap<Vec<ListenerEntry<C, F>>>,
+    /// Serializes the writers, the readers never take it.
+    writer: Mutex<()>,
+}
+
+impl<C, F> Default for ListenerRegistry<C, F> {
+    fn default() -> Self {
+        Self {
+            entries: Default::default(),
+            writer: Default::default(),
+        }
+    }
+}
+
+impl<C, F> ListenerRegistry<C, F> {
+    /// The listeners registered now, the later changes are not visible to it.
+    #[inline]
+    pub(crate) fn snapshot(&self) -> Arc<Vec<ListenerEntry<C, F>>> {
+        self.entries.load_full()
+    }
+
+    /// Register `listener` as `name`, a listener registered as `name` before is replaced.
+    pub(crate) fn register(&self, name: String, listener: ListenerType<C, F>) {
+        let listener = Arc::new(Mutex::new(listener));
+        self.update(|entries| match entries.iter_mut().find(|(v, _)| *v == name) {
+            Some(entry) => entry.1 = listener,
+            None => entries.push((name, listener)),
+        })
+    }
+
+    /// Unregister the listener registered as `name`, returns false when none.
+    pub(crate) fn unregister(&self, name: &str) -> bool {
+        self.update(|entries| {
+            let len = entries.len();
+            entries.retain(|(v, _)| v != name);
+            entries.len() != len
+        })
+    }
+
+    #[inline]
+    pub(crate) fn clear(&self) {
+        self.update(|entries| entries.clear())
+    }
+
+    pub(crate) fn names(&self) -> Vec<String> {
+        self.entries.load()
+            .iter()
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+
+    /// Lock the listener registered as `name` and call `f` with it.
+    pub(crate) fn with<R>(&self, name: &str, f: impl FnOnce(&ListenerType<C, F>) -> R) -> Option<R> {
+        let listener = self.entries.load()
+            .iter()
+            .find(|(v, _)| v == name)
+            .map(|(_, v)| v.clone())?;
+        let guard = lock(&listener)?;
+        Some(f(&guard))
+    }
+
+    fn update<R>(&self, f: impl FnOnce(&mut Vec<ListenerEntry<C, F>>) -> R) -> R {
+        let _writer = self.writer.lock()
+            .unwrap_or_else(|e| e.into_inner());
+        let mut entries = Vec::clone(&self.entries.load());
+        let result = f(&mut entries);
+        self.entries.store(Arc::new(entries));
+        result
+    }
+}
+
+/// Lock a listener, `None` when the mutex is poisoned.
+#[inline]
+pub(crate) fn lock<C, F>(listener: &Mutex<ListenerType<C, F>>) -> Option<MutexGuard<'_, ListenerType<C, F>>> {
+    match listener.lock() {
+        Ok(v) => Some(v),
+        Err(e) => {
+            log::error!("SyncCAN - mutex error: {e:?} when locking listener");
+            None
+        },
+    }
+}
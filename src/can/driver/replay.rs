@@ -0,0 +1,326 @@
+//! Replay the recorded bus traffic through the normal receive path.
+//!
+//! The candump log(`candump -l`) and Vector ASC formats are supported.
+
+use std::collections::{HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Instant;
+use crate::can::frame::{Direct, FrameMut};
+use crate::can::log::{AscReader, CandumpReader, LogItem, LogReadError};
+use crate::can::message::CanMessage;
+use crate::device::Driver;
+use crate::error::Error;
+
+/// How the recorded frames are released by [`ReplayDriver`].
+#[derive(Debug, Copy, Clone, Default, PartialEq)]
+pub enum ReplayMode {
+    /// All the frames are received immediately.
+    #[default]
+    AsFastAsPossible,
+    /// Honour the recorded inter-frame time, scaled by `speed`(2.0 replays twice as fast).
+    Realtime { speed: f64 },
+}
+
+#[derive(Debug, Default)]
+struct ReplayState {
+    /// The recorded frames of each channel with the offset(μs) to the first frame.
+    frames: HashMap<String, VecDeque<(u64, CanMessage)>>,
+    start: Option<Instant>,
+}
+
+/// [`Driver`] that receives the frames recorded in a log file,
+/// the transmitted frames are recorded into a sink for assertions.
+#[derive(Debug, Clone)]
+pub struct ReplayDriver {
+    channels: Vec<String>,
+    mode: ReplayMode,
+    state: Arc<Mutex<ReplayState>>,
+    sink: Arc<Mutex<Vec<CanMessage>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl ReplayDriver {
+    /// Load a candump log or Vector ASC(`.asc`) file.
+    pub fn open(path: impl AsRef<std::path::Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        match path.extension().and_then(|v| v.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("asc") =>
+                Self::from_items(AscReader::open(path).map_err(Error::device)?),
+            _ => Self::from_items(CandumpReader::open(path).map_err(Error::device)?),
+        }
+    }
+
+    /// Parse the candump log format by [`CandumpReader`], e.g. `(1436509052.249713) vcan0 7E0#0210010000000000`.
+    pub fn from_candump(content: &str) -> Result<Self, Error> {
+        Self::from_items(CandumpReader::new(content.as_bytes()))
+    }
+
+    /// Parse the Vector ASC format by [`AscReader`], the channel is the channel number of the file.
+    pub fn from_asc(content: &str) -> Result<Self, Error> {
+        Self::from_items(AscReader::new(content.as_bytes()))
+    }
+
+    /// Set the [`ReplayMode`], [`ReplayMode::AsFastAsPossible`] by default.
+    #[inline]
+    pub fn with_mode(mut self, mode: ReplayMode) -> Self {
+        self.mode = mode;
+        self
+    }
+
+    /// The frames transmitted to the driver.
+    pub fn sent(&self) -> Vec<CanMessage> {
+        match self.sink.lock() {
+            Ok(v) => v.clone(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Whether all the recorded frames have been received.
+    pub fn is_finished(&self) -> bool {
+        match self.state.lock() {
+            Ok(state) => state.frames.values().all(|v| v.is_empty()),
+            Err(_) => true,
+        }
+    }
+
+    /// Load the frames read from a log file, fails on the first invalid line.
+    pub fn from_items(items: impl IntoIterator<Item = Result<LogItem, LogReadError>>) -> Result<Self, Error> {
+        let mut channels = Vec::new();
+        let mut frames: HashMap<String, VecDeque<(u64, CanMessage)>> = HashMap::new();
+        let mut first = None;
+        for item in items {
+            let (timestamp, channel, frame) = item.map_err(|e| Error::InvalidParam(e.to_string()))?;
+            let first = *first.get_or_insert(timestamp);
+            if !channels.contains(&channel) {
+                channels.push(channel.clone());
+            }
+            frames.entry(channel)
+                .or_default()
+                .push_back((timestamp.saturating_sub(first), frame));
+        }
+
+        Ok(Self {
+            channels,
+            mode: Default::default(),
+            state: Arc::new(Mutex::new(ReplayState { frames, start: None })),
+            sink: Default::default(),
+            closed: Default::default(),
+        })
+    }
+
+    fn transmit_util(&self, mut msg: CanMessage) -> Result<(), Error> {
+        msg.set_direct(Direct::Transmit)
+            .set_timestamp(None);
+        self.sink.lock()
+            .map_err(|_| Error::ContextError("can't get `sink`".into()))?
+            .push(msg);
+
+        Ok(())
+    }
+
+    fn receive_util(&self, channel: String) -> Result<Vec<CanMessage>, Error> {
+        let mut state = self.state.lock()
+            .map_err(|_| Error::ContextError("can't get `state`".into()))?;
+        let elapsed = state.start.get_or_insert_with(Instant::now).elapsed();
+        let Some(frames) = state.frames.get_mut(&channel) else {
+            return Ok(vec![]);
+        };
+
+        let due = match self.mode {
+            ReplayMode::AsFastAsPossible => u64::MAX,
+            ReplayMode::Realtime { speed } => (elapsed.as_micros() as f64 * speed) as u64,
+        };
+        let mut results = Vec::new();
+        while let Some((offset, _)) = frames.front() {
+            if *offset > due {
+                break;
+            }
+            if let Some((_, frame)) = frames.pop_front() {
+                results.push(frame);
+            }
+        }
+
+        Ok(results)
+    }
+}
+
+impl Driver for ReplayDriver {
+    type Error = Error;
+    type C = String;
+    type F = CanMessage;
+
+    /// The channels found in the file.
+    fn opened_channels(&self) -> Vec<Self::C> {
+        if self.is_closed() {
+            return vec![];
+        }
+
+        self.channels.clone()
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.closed.store(true, Ordering::Release)
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.closed.store(true, Ordering::Release)
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+    use hex_literal::hex;
+    use crate::can::Address;
+    use crate::can::driver::SyncCan;
+    use crate::can::frame::{Direct, Frame};
+    use crate::can::identifier::Id;
+    use crate::can::isotp::SyncCanIsoTp;
+    use crate::can::isotp::testing::EventListener;
+    use crate::device::Driver;
+    use super::{ReplayDriver, ReplayMode};
+
+    const UDS_SESSION: &str = include_str!("../../../resources/uds_session.log");
+
+    /// Wait until `driver` has been replayed or `timeout`.
+    fn wait_finished(driver: &ReplayDriver, timeout: Duration) -> bool {
+        let start = Instant::now();
+        while !driver.is_finished() {
+            if start.elapsed() > timeout {
+                return false;
+            }
+            sleep(Duration::from_millis(1));
+        }
+        true
+    }
+
+    #[test]
+    fn test_candump() -> anyhow::Result<()> {
+        let driver = ReplayDriver::from_candump(UDS_SESSION)?;
+        assert_eq!(driver.opened_channels(), vec!["vcan0".to_string(), "vcan1".to_string()]);
+
+        let frames = driver.receive("vcan0".into(), None)?;
+        assert_eq!(frames.len(), 5);
+        assert_eq!(frames[0].id(), Id::Standard(0x7E0));
+        assert_eq!(frames[0].data(), hex!("03 22 F1 90 AA AA AA AA"));
+        assert_eq!(frames[0].timestamp(), 1_700_000_000_000);
+        assert!(!driver.is_finished());
+
+        let frames = driver.receive("vcan1".into(), None)?;
+        assert_eq!(frames[0].id(), Id::Extended(0x18FEF100));
+        assert!(driver.is_finished());
+
+        let driver = ReplayDriver::from_candump("(0.000100) can0 123#R\n(0.000200) can0 456##1112233")?;
+        let frames = driver.receive("can0".into(), None)?;
+        assert!(frames[0].is_remote());
+        assert!(frames[1].is_can_fd() && frames[1].is_bitrate_switch());
+        assert_eq!(frames[1].data(), hex!("11 22 33"));
+
+        assert!(ReplayDriver::from_candump("(0.0) can0 XYZ#00").is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_asc() -> anyhow::Result<()> {
+        let content = "date Mon Jan 1 00:00:00 2024\n\
+            base hex  timestamps absolute\n\
+            Begin Triggerblock\n\
+            0.000000 Start of measurement\n\
+            0.010000 1  7E0             Tx   d 8 02 10 01 AA AA AA AA AA\n\
+            0.020000 1  18DAF100x       Rx   d 3 02 50 01\n\
+            0.030000 2  123             Rx   r 4\n\
+            0.040000 CANFD 1 Rx 7E8 1 0 9 12 00 01 02 03 04 05 06 07 08 09 0A 0B\n\
+            End TriggerBlock\n";
+        let driver = ReplayDriver::from_asc(content)?;
+        assert_eq!(driver.opened_channels(), vec!["1".to_string(), "2".to_string()]);
+
+        let frames = driver.receive("1".into(), None)?;
+        assert_eq!(frames.len(), 3);
+        assert_eq!(frames[0].direct(), Direct::Transmit);
+        assert_eq!(frames[1].id(), Id::Extended(0x18DAF100));
+        assert_eq!(frames[1].data(), hex!("02 50 01"));
+        assert!(frames[2].is_can_fd() && frames[2].is_bitrate_switch());
+        assert_eq!(frames[2].length(), 12);
+
+        let frames = driver.receive("2".into(), None)?;
+        assert!(frames[0].is_remote());
+        assert_eq!(frames[0].length(), 4);
+        Ok(())
+    }
+
+    #[test]
+    fn test_realtime() -> anyhow::Result<()> {
+        let driver = ReplayDriver::from_candump(UDS_SESSION)?
+            .with_mode(ReplayMode::Realtime { speed: 2.0 });
+        let start = Instant::now();
+        let mut count = 0;
+        while count < 5 {
+            count += driver.receive("vcan0".into(), None)?.len();
+            assert!(start.elapsed() < Duration::from_secs(1));
+            sleep(Duration::from_micros(100));
+        }
+        // the last frame is recorded at 30ms
+        let elapsed = start.elapsed();
+        assert!(elapsed >= Duration::from_millis(15), "{:?}", elapsed);
+        assert!(elapsed < Duration::from_millis(30), "{:?}", elapsed);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_uds_session() -> anyhow::Result<()> {
+        let driver = ReplayDriver::from_candump(UDS_SESSION)?;
+        let mut can = SyncCan::new(driver.clone());
+        let collector = EventListener::default();
+        let tester = SyncCanIsoTp::new(
+            "vcan0".to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            can.sender(),
+            Box::new(collector.clone()),
+        );
+        can.register_listener("tester".into(), Box::new(tester));
+        can.sync_start(100);
+
+        assert!(wait_finished(&driver, Duration::from_secs(1)));
+        sleep(Duration::from_millis(10));
+        let mut data = hex!("62 F1 90").to_vec();
+        data.extend_from_slice(b"LVW12345678901234");
+        assert_eq!(collector.0.lock().unwrap().iter().filter_map(|v| v.data()).collect::<Vec<_>>(), [data.as_slice()]);
+
+        // the flow control responded to the first frame
+        let sent = driver.sent();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].id(), Id::Standard(0x7E0));
+        assert_eq!(sent[0].data()[0], 0x30);
+
+        can.stop();
+        Ok(())
+    }
+}
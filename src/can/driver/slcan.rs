@@ -0,0 +1,471 @@
+//! slcan(LAWICEL) ASCII protocol driver for the USB-serial CAN adapters.
+//!
+//! Frames are encoded as `tiiildd..`(standard), `Tiiiiiiiildd..`(extended),
+//! `riiil`/`Riiiiiiiil`(remote), each command is terminated by `\r`.
+
+use std::io::{ErrorKind, Read, Write};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+use crate::can::CAN_FRAME_MAX_SIZE;
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::can::message::CanMessage;
+use crate::device::{ChannelConfig, Driver, DriverCapabilities};
+use crate::error::Error;
+
+/// The baud rate of the serial port, most adapters ignore it(USB CDC).
+pub const SLCAN_SERIAL_BAUD_RATE: u32 = 115_200;
+
+const CR: u8 = b'\r';
+const BELL: u8 = 0x07;
+
+/// The protocol error of slcan, it is carried by [`Error::Device`].
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum SlcanError {
+    #[error("slcan - invalid line: {0:?}")]
+    InvalidLine(String),
+    #[error("slcan - command rejected by adapter")]
+    Rejected,
+    #[error("slcan - unsupported bitrate: {0}")]
+    UnsupportedBitrate(u32),
+}
+
+/// The command of the standard bitrate.
+fn bitrate_command(bitrate: u32) -> Result<&'static str, SlcanError> {
+    match bitrate {
+        10_000 => Ok("S0"),
+        20_000 => Ok("S1"),
+        50_000 => Ok("S2"),
+        100_000 => Ok("S3"),
+        125_000 => Ok("S4"),
+        250_000 => Ok("S5"),
+        500_000 => Ok("S6"),
+        800_000 => Ok("S7"),
+        1_000_000 => Ok("S8"),
+        _ => Err(SlcanError::UnsupportedBitrate(bitrate)),
+    }
+}
+
+/// Encode a frame without the terminating `\r`.
+fn encode(frame: &CanMessage) -> Result<String, Error> {
+    if frame.is_can_fd() {
+        return Err(Error::Unsupported("CAN-FD frame on slcan".into()));
+    }
+
+    let id = frame.id().into_bits();
+    let mut result = match (frame.is_extended(), frame.is_remote()) {
+        (false, false) => format!("t{:03X}", id),
+        (false, true) => format!("r{:03X}", id),
+        (true, false) => format!("T{:08X}", id),
+        (true, true) => format!("R{:08X}", id),
+    };
+    result.push_str(&frame.length().to_string());
+    if !frame.is_remote() {
+        result.push_str(&hex::encode_upper(frame.data()));
+    }
+
+    Ok(result)
+}
+
+/// Decode a frame line without the terminating `\r`.
+fn decode(line: &str, channel: &str) -> Result<CanMessage, SlcanError> {
+    let invalid = || SlcanError::InvalidLine(line.into());
+    let (extended, remote) = match line.as_bytes().first() {
+        Some(b't') => (false, false),
+        Some(b'r') => (false, true),
+        Some(b'T') => (true, false),
+        Some(b'R') => (true, true),
+        _ => return Err(invalid()),
+    };
+    let id_len = if extended { 8 } else { 3 };
+    let id = line.get(1..1 + id_len)
+        .and_then(|v| u32::from_str_radix(v, 16).ok())
+        .and_then(|v| Id::try_from_bits(v, extended))
+        .ok_or_else(invalid)?;
+    let length = line.get(1 + id_len..2 + id_len)
+        .and_then(|v| v.parse::<usize>().ok())
+        .filter(|v| *v <= CAN_FRAME_MAX_SIZE)
+        .ok_or_else(invalid)?;
+
+    // an optional timestamp(4 hex digits) is appended when enabled by `Z1`
+    let end = 2 + id_len + if remote { 0 } else { 2 * length };
+    match line.len() - end.min(line.len()) {
+        0 => {},
+        4 if line[end..].chars().all(|c| c.is_ascii_hexdigit()) => {},
+        _ => return Err(invalid()),
+    }
+
+    let mut frame = if remote {
+        CanMessage::new_remote(id, length)
+    }
+    else {
+        let data = line.get(2 + id_len..end)
+            .and_then(|v| hex::decode(v).ok())
+            .ok_or_else(invalid)?;
+        CanMessage::new(id, &data)
+    }
+    .ok_or_else(invalid)?;
+    frame.set_channel(channel.into())
+        .set_direct(Direct::Receive)
+        .set_timestamp(None);
+
+    Ok(frame)
+}
+
+#[derive(Debug)]
+struct Inner<P> {
+    port: P,
+    buffer: Vec<u8>,
+    opened: bool,
+}
+
+impl<P: Write> Inner<P> {
+    fn command(&mut self, command: &str) -> Result<(), Error> {
+        log::trace!("slcan - command: {}", command);
+        self.port.write_all(command.as_bytes())
+            .and_then(|_| self.port.write_all(&[CR]))
+            .and_then(|_| self.port.flush())
+            .map_err(Error::device)
+    }
+}
+
+/// [`Driver`] over a serial port that speaks slcan.
+///
+/// The adapter has only one channel, it is named as the serial port. The reads of the port must be
+/// non-blocking or have a short timeout, partial lines are buffered between the polls.
+#[derive(Debug)]
+pub struct SlcanDriver<P> {
+    channel: String,
+    inner: Arc<Mutex<Inner<P>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl<P> Clone for SlcanDriver<P> {
+    fn clone(&self) -> Self {
+        Self {
+            channel: self.channel.clone(),
+            inner: Arc::clone(&self.inner),
+            closed: Arc::clone(&self.closed),
+        }
+    }
+}
+
+impl SlcanDriver<Box<dyn serialport::SerialPort>> {
+    /// Open the serial port at `path` and the CAN channel with `bitrate`.
+    pub fn open(path: &str, bitrate: u32) -> Result<Self, Error> {
+        let port = serialport::new(path, SLCAN_SERIAL_BAUD_RATE)
+            .timeout(Duration::from_millis(1))
+            .open()
+            .map_err(Error::device)?;
+        let mut driver = Self::new(port, path);
+        driver.open_channel(path.into(), ChannelConfig::new(bitrate))?;
+
+        Ok(driver)
+    }
+}
+
+impl<P: Read + Write + Send> SlcanDriver<P> {
+    /// Create a driver on an opened serial `port`, the CAN channel is opened by [`Driver::open_channel`].
+    pub fn new(port: P, channel: impl Into<String>) -> Self {
+        Self {
+            channel: channel.into(),
+            inner: Arc::new(Mutex::new(Inner { port, buffer: Default::default(), opened: false })),
+            closed: Default::default(),
+        }
+    }
+
+    #[inline]
+    fn inner(&self) -> Result<std::sync::MutexGuard<'_, Inner<P>>, Error> {
+        self.inner.lock()
+            .map_err(|_| Error::ContextError("can't get `port`".into()))
+    }
+
+    fn check_channel(&self, channel: &str) -> Result<(), Error> {
+        if channel != self.channel {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", channel)));
+        }
+
+        Ok(())
+    }
+
+    fn transmit_util(&self, msg: CanMessage) -> Result<(), Error> {
+        self.check_channel(&msg.channel())?;
+        let line = encode(&msg)?;
+        let mut inner = self.inner()?;
+        if !inner.opened {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", self.channel)));
+        }
+
+        inner.command(&line)
+    }
+
+    fn receive_util(&self, channel: String) -> Result<Vec<CanMessage>, Error> {
+        self.check_channel(&channel)?;
+        let mut inner = self.inner()?;
+
+        let mut buffer = [0u8; 256];
+        loop {
+            match inner.port.read(&mut buffer) {
+                Ok(0) => break,
+                Ok(len) => inner.buffer.extend_from_slice(&buffer[..len]),
+                Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => break,
+                Err(e) => return Err(Error::device(e)),
+            }
+        }
+
+        let mut results = Vec::new();
+        let mut error = None;
+        while let Some(pos) = inner.buffer.iter().position(|&v| v == CR || v == BELL) {
+            let line = inner.buffer.drain(..=pos).collect::<Vec<_>>();
+            let (terminator, line) = line.split_last().unwrap_or((&CR, &[]));
+            if *terminator == BELL {
+                error = Some(SlcanError::Rejected);
+                continue;
+            }
+
+            match line.first() {
+                // the acknowledgements of the commands
+                None | Some(b'z') | Some(b'Z') => {},
+                Some(_) => match std::str::from_utf8(line) {
+                    Ok(line) => match decode(line, &channel) {
+                        Ok(frame) => results.push(frame),
+                        Err(e) => error = Some(e),
+                    },
+                    Err(_) => error = Some(SlcanError::InvalidLine(String::from_utf8_lossy(line).into())),
+                },
+            }
+        }
+
+        match error {
+            Some(e) if results.is_empty() => Err(Error::device(e)),
+            Some(e) => {
+                log::warn!("slcan - {}", e);
+                Ok(results)
+            },
+            None => Ok(results),
+        }
+    }
+
+    fn shutdown_util(&mut self) {
+        log::info!("slcan - shutdown");
+        if let Ok(mut inner) = self.inner() {
+            if inner.opened {
+                inner.opened = false;
+                if let Err(e) = inner.command("C") {
+                    log::warn!("slcan - close channel failed: {}", e);
+                }
+            }
+        }
+        self.closed.store(true, Ordering::Release);
+    }
+}
+
+impl<P: Read + Write + Send> Driver for SlcanDriver<P> {
+    type Error = Error;
+    type C = String;
+    type F = CanMessage;
+
+    fn opened_channels(&self) -> Vec<Self::C> {
+        match self.inner() {
+            Ok(inner) if inner.opened => vec![self.channel.clone()],
+            _ => vec![],
+        }
+    }
+
+    /// Set the bitrate(S0 ~ S8) and open the channel, `listen_only` opens with `L`.
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        self.check_channel(&channel)?;
+        if config.fd {
+            return Err(Error::Unsupported("CAN-FD on slcan".into()));
+        }
+        let bitrate = bitrate_command(config.bitrate)
+            .map_err(Error::device)?;
+
+        log::debug!("slcan - open channel `{}` with {:?}", channel, config);
+        let mut inner = self.inner()?;
+        // the channel must be closed before setting the bitrate
+        inner.command("C")?;
+        inner.command(bitrate)?;
+        inner.command(if config.listen_only { "L" } else { "O" })?;
+        inner.buffer.clear();
+        inner.opened = true;
+        self.closed.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        self.check_channel(&channel)?;
+        log::debug!("slcan - close channel `{}`", channel);
+        let mut inner = self.inner()?;
+        inner.opened = false;
+        inner.command("C")
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Classic CAN only, the frames are timestamped when received.
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities {
+            listen_only: true,
+            ..Default::default()
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::VecDeque;
+    use std::io::{ErrorKind, Read, Write};
+    use std::sync::{Arc, Mutex};
+    use hex_literal::hex;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use super::{decode, encode, SlcanError};
+
+    const CHANNEL: &str = "/dev/ttyACM0";
+
+    /// In-memory serial port, the reads return the `rx` chunks one by one.
+    #[derive(Clone, Default)]
+    struct Port {
+        rx: Arc<Mutex<VecDeque<Vec<u8>>>>,
+        tx: Arc<Mutex<Vec<u8>>>,
+    }
+
+    impl Read for Port {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            match self.rx.lock().unwrap().pop_front() {
+                Some(chunk) => {
+                    buf[..chunk.len()].copy_from_slice(&chunk);
+                    Ok(chunk.len())
+                },
+                None => Err(ErrorKind::TimedOut.into()),
+            }
+        }
+    }
+
+    impl Write for Port {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.tx.lock().unwrap().extend_from_slice(buf);
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn frame(id: impl Into<Id>, data: &[u8]) -> CanMessage {
+        let mut frame = CanMessage::new(id, data).unwrap();
+        frame.set_channel(CHANNEL.into());
+        frame
+    }
+
+    #[test]
+    fn test_encode() -> anyhow::Result<()> {
+        assert_eq!(encode(&frame(0x123, &hex!("11 22 33")))?, "t1233112233");
+        assert_eq!(encode(&frame(0x7DF, &[]))?, "t7DF0");
+        assert_eq!(encode(&frame(Id::Extended(0x18DA00F1), &hex!("02 10 01")))?, "T18DA00F13021001");
+        assert_eq!(encode(&CanMessage::new_remote(0x123, 8).unwrap())?, "r1238");
+        assert_eq!(encode(&CanMessage::new_remote(Id::Extended(0x100), 2).unwrap())?, "R000001002");
+        assert!(encode(&frame(0x123, &[0x55; 12])).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_decode() -> anyhow::Result<()> {
+        let result = decode("t1233112233", CHANNEL)?;
+        assert_eq!(result.id(), Id::Standard(0x123));
+        assert_eq!(result.data(), hex!("11 22 33"));
+        assert_eq!(result.channel(), CHANNEL);
+
+        let result = decode("T18DA00F13021001", CHANNEL)?;
+        assert_eq!(result.id(), Id::Extended(0x18DA00F1));
+        assert_eq!(result.data(), hex!("02 10 01"));
+
+        let result = decode("r1238", CHANNEL)?;
+        assert!(result.is_remote());
+        assert_eq!(result.length(), 8);
+
+        let result = decode("t1233112233ABCD", CHANNEL)?;
+        assert_eq!(result.data(), hex!("11 22 33"));
+
+        let result = decode("R000001002", CHANNEL)?;
+        assert!(result.is_remote() && result.is_extended());
+        assert_eq!(result.id(), Id::Extended(0x100));
+
+        for line in ["x123", "t12", "t1239", "t1233112", "T1FFFFFFF0Z", "tXYZ0"] {
+            assert!(matches!(decode(line, CHANNEL), Err(SlcanError::InvalidLine(_))), "{}", line);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "async"))]
+    #[test]
+    fn test_driver() -> anyhow::Result<()> {
+        use crate::device::{ChannelConfig, Driver};
+        use super::SlcanDriver;
+
+        let port = Port::default();
+        let mut driver = SlcanDriver::new(port.clone(), CHANNEL);
+        assert!(driver.opened_channels().is_empty());
+        assert!(driver.open_channel(CHANNEL.into(), ChannelConfig::new(33_333)).is_err());
+        driver.open_channel(CHANNEL.into(), ChannelConfig::new(500_000))?;
+        assert_eq!(driver.opened_channels(), vec![CHANNEL.to_string()]);
+        assert_eq!(port.tx.lock().unwrap().as_slice(), b"C\rS6\rO\r");
+
+        port.tx.lock().unwrap().clear();
+        driver.transmit(frame(0x7E0, &hex!("02 10 01")), None)?;
+        assert_eq!(port.tx.lock().unwrap().as_slice(), b"t7E03021001\r");
+
+        // the lines are split into several reads
+        port.rx.lock().unwrap().extend([b"\rz\rt7E8".to_vec(), b"3500100\rT18DA".to_vec()]);
+        let frames = driver.receive(CHANNEL.into(), None)?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), hex!("50 01 00"));
+
+        port.rx.lock().unwrap().push_back(b"F1003021001\r".to_vec());
+        let frames = driver.receive(CHANNEL.into(), None)?;
+        assert_eq!(frames[0].id(), Id::Extended(0x18DAF100));
+
+        // the adapter rejects with BELL
+        port.rx.lock().unwrap().push_back(vec![0x07]);
+        assert!(driver.receive(CHANNEL.into(), None).is_err());
+
+        driver.shutdown();
+        assert!(driver.is_closed());
+        Ok(())
+    }
+}
@@ -0,0 +1,587 @@
+//! SocketCAN backend(Linux) based on the `socketcan` crate.
+//!
+//! The interfaces are configured externally(e.g. `ip link set can0 type can bitrate 500000`),
+//! [`SocketCanDriver`] only binds the raw sockets of them.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::io::ErrorKind;
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+use ::socketcan::{CanAnyFrame, CanDataFrame, CanErrorFrame, CanFdFrame, CanFdSocket, CanFrame, EmbeddedFrame, ExtendedId, Frame as _, Socket, SocketOptions, StandardId};
+use ::socketcan::timestamp::{SOF_TIMESTAMPING_RX_SOFTWARE, SOF_TIMESTAMPING_SOFTWARE};
+use crate::can::constant::IdentifierFlags;
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::can::dlc::len_to_dlc;
+use crate::device::{ChannelConfig, Driver, DriverCapabilities, FilterSpec};
+use crate::error::Error;
+
+/// A thin wrapper of the `socketcan` frames that implements [`Frame`].
+///
+/// The channel is the interface name(e.g. `can0`).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SocketCanFrame {
+    frame: CanAnyFrame,
+    timestamp: u64,
+    channel: String,
+    direct: Direct,
+}
+
+impl SocketCanFrame {
+    /// Wrap a `socketcan` frame of `channel`.
+    pub fn from_frame(frame: impl Into<CanAnyFrame>, channel: impl Into<String>) -> Self {
+        Self {
+            frame: frame.into(),
+            timestamp: Default::default(),
+            channel: channel.into(),
+            direct: Default::default(),
+        }
+    }
+
+    #[inline]
+    pub fn inner(&self) -> &CanAnyFrame {
+        &self.frame
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> CanAnyFrame {
+        self.frame
+    }
+}
+
+impl From<SocketCanFrame> for CanAnyFrame {
+    #[inline]
+    fn from(val: SocketCanFrame) -> Self {
+        val.frame
+    }
+}
+
+#[inline]
+fn to_hal_id(id: Id) -> Option<::socketcan::Id> {
+    match id {
+        Id::Standard(v) => StandardId::new(v).map(::socketcan::Id::Standard),
+        Id::Extended(v) => ExtendedId::new(v).map(::socketcan::Id::Extended),
+    }
+}
+
+impl Frame for SocketCanFrame {
+    type Channel = String;
+
+    #[inline]
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    #[inline]
+    fn id(&self) -> Id {
+        Id::from_bits(self.frame.raw_id(), EmbeddedFrame::is_extended(&self.frame))
+    }
+
+    #[inline]
+    fn is_can_fd(&self) -> bool {
+        matches!(self.frame, CanAnyFrame::Fd(_))
+    }
+
+    #[inline]
+    fn is_remote(&self) -> bool {
+        matches!(self.frame, CanAnyFrame::Remote(_))
+    }
+
+    #[inline]
+    fn is_extended(&self) -> bool {
+        EmbeddedFrame::is_extended(&self.frame)
+    }
+
+    #[inline]
+    fn direct(&self) -> Direct {
+        self.direct
+    }
+
+    #[inline]
+    fn is_bitrate_switch(&self) -> bool {
+        match self.frame {
+            CanAnyFrame::Fd(frame) => frame.is_brs(),
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn is_error_frame(&self) -> bool {
+        matches!(self.frame, CanAnyFrame::Error(_))
+    }
+
+    #[inline]
+    fn is_esi(&self) -> bool {
+        match self.frame {
+            CanAnyFrame::Fd(frame) => frame.is_esi(),
+            _ => false,
+        }
+    }
+
+    #[inline]
+    fn channel(&self) -> Self::Channel {
+        self.channel.clone()
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        match &self.frame {
+            CanAnyFrame::Remote(_) => &[],
+            frame => EmbeddedFrame::data(frame),
+        }
+    }
+
+    #[inline]
+    fn dlc(&self) -> Option<usize> {
+        len_to_dlc(self.length()).map(usize::from)
+    }
+
+    /// The DLC of embedded-can is the DLC code of CAN-FD, so the byte count is the length of the data.
+    #[inline]
+    fn length(&self) -> usize {
+        match &self.frame {
+            CanAnyFrame::Remote(_) => EmbeddedFrame::dlc(&self.frame),
+            frame => EmbeddedFrame::data(frame).len(),
+        }
+    }
+}
+
+impl FrameMut for SocketCanFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let id = to_hal_id(id.into())?;
+        let frame: CanAnyFrame = match data.len() {
+            0..=8 => CanFrame::new(id, data)?.into(),
+            _ => CanFdFrame::new(id, data)?.into(),
+        };
+
+        Some(Self::from_frame(frame, String::default()))
+    }
+
+    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self> {
+        let id = to_hal_id(id.into())?;
+        let frame = CanFrame::new_remote(id, len)?;
+
+        Some(Self::from_frame(frame, String::default()))
+    }
+
+    /// Set the timestamp, the current system time(ms) is used when `value` is `None`.
+    #[inline]
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self {
+        self.timestamp = value.unwrap_or_else(|| system_time_ms(SystemTime::now()));
+        self
+    }
+
+    /// Convert between classic and CAN-FD data frame,
+    /// a CAN-FD frame with more than 8 bytes is kept as it is.
+    fn set_can_fd(&mut self, value: bool) -> &mut Self {
+        match (value, self.frame) {
+            (true, CanAnyFrame::Normal(frame)) => self.frame = CanFdFrame::from(frame).into(),
+            (false, CanAnyFrame::Fd(frame)) => if let Ok(frame) = CanDataFrame::try_from(frame) {
+                self.frame = frame.into();
+            },
+            _ => {},
+        }
+        self
+    }
+
+    #[inline]
+    fn set_direct(&mut self, direct: Direct) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    /// Only takes effect on the CAN-FD frame.
+    #[inline]
+    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self {
+        if let CanAnyFrame::Fd(frame) = &mut self.frame {
+            frame.set_brs(value);
+        }
+        self
+    }
+
+    /// Convert between data frame and error frame, the error class is taken from the id.
+    fn set_error_frame(&mut self, value: bool) -> &mut Self {
+        let data = EmbeddedFrame::data(&self.frame).to_vec();
+        match (value, self.frame) {
+            (true, CanAnyFrame::Normal(_)) => {
+                if let Ok(frame) = CanErrorFrame::new_error(self.frame.raw_id(), &data) {
+                    self.frame = frame.into();
+                }
+            },
+            (false, CanAnyFrame::Error(_)) => {
+                if let Some(frame) = to_hal_id(self.id()).and_then(|id| CanDataFrame::new(id, &data)) {
+                    self.frame = frame.into();
+                }
+            },
+            _ => {},
+        }
+        self
+    }
+
+    /// Only takes effect on the CAN-FD frame.
+    #[inline]
+    fn set_esi(&mut self, value: bool) -> &mut Self {
+        if let CanAnyFrame::Fd(frame) = &mut self.frame {
+            frame.set_esi(value);
+        }
+        self
+    }
+
+    #[inline]
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self {
+        self.channel = value;
+        self
+    }
+}
+
+impl Display for SocketCanFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        <dyn Frame<Channel = String> as Display>::fmt(self, f)
+    }
+}
+
+#[inline]
+fn system_time_ms(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[derive(Debug)]
+struct Channel {
+    socket: CanFdSocket,
+    config: ChannelConfig,
+    /// `CAN_RAW_RECV_OWN_MSGS` of the socket.
+    loopback: bool,
+}
+
+/// [`Driver`] over one or more SocketCAN interfaces.
+///
+/// The sockets are non-blocking, [`Driver::receive`] returns the frames that have arrived,
+/// this is suitable for the polling loop of [`crate::can::driver::SyncCan`].
+#[derive(Debug, Clone, Default)]
+pub struct SocketCanDriver {
+    channels: Arc<Mutex<HashMap<String, Channel>>>,
+    closed: Arc<AtomicBool>,
+}
+
+impl SocketCanDriver {
+    #[inline]
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Create a driver with the `interfaces` opened with the default configuration.
+    pub fn open(interfaces: &[&str]) -> Result<Self, Error> {
+        let mut driver = Self::new();
+        for interface in interfaces {
+            driver.open_channel(interface.to_string(), Default::default())?;
+        }
+
+        Ok(driver)
+    }
+
+    fn transmit_util(&self, msg: SocketCanFrame) -> Result<(), Error> {
+        let channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        let channel = channels.get(&msg.channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", msg.channel)))?;
+        if channel.config.listen_only {
+            return Err(Error::Unsupported(format!("transmit on listen-only channel `{}`", msg.channel)));
+        }
+        if msg.is_can_fd() && !channel.config.fd {
+            return Err(Error::Unsupported(format!("transmit CAN-FD frame on channel `{}`", msg.channel)));
+        }
+
+        channel.socket.write_frame(&msg.frame)
+            .map_err(Error::device)
+    }
+
+    fn receive_util(&self, channel: String, buf: &mut Vec<SocketCanFrame>) -> Result<usize, Error> {
+        let channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        let socket = &channels.get(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?
+            .socket;
+
+        let start = buf.len();
+        loop {
+            match socket.read_frame_with_timestamps() {
+                Ok((frame, timestamps)) => {
+                    let time = timestamps.sw
+                        .or(timestamps.socket)
+                        .unwrap_or_else(SystemTime::now);
+                    let mut frame = SocketCanFrame::from_frame(frame, channel.clone());
+                    frame.set_timestamp(Some(system_time_ms(time)))
+                        .set_direct(Direct::Receive);
+                    buf.push(frame);
+                },
+                Err(e) if e.kind() == ErrorKind::WouldBlock => break,
+                Err(e) => {
+                    if buf.len() == start {
+                        return Err(Error::device(e));
+                    }
+                    log::warn!("SocketCAN - receive from `{}` failed: {}", channel, e);
+                    break;
+                },
+            }
+        }
+
+        Ok(buf.len() - start)
+    }
+
+    fn shutdown_util(&mut self) {
+        log::info!("SocketCAN - shutdown");
+        self.closed.store(true, Ordering::Release);
+        if let Ok(mut channels) = self.channels.lock() {
+            channels.clear();
+        }
+    }
+}
+
+impl Driver for SocketCanDriver {
+    type Error = Error;
+    type C = String;
+    type F = SocketCanFrame;
+
+    fn opened_channels(&self) -> Vec<Self::C> {
+        match self.channels.lock() {
+            Ok(channels) => channels.keys()
+                .cloned()
+                .collect(),
+            Err(_) => vec![],
+        }
+    }
+
+    /// Bind the interface `channel`, the bitrate and sample points of `config` are not applied,
+    /// `fd` and `listen_only` are checked when transmitting.
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        log::debug!("SocketCAN - open channel `{}` with {:?}", channel, config);
+        let socket = CanFdSocket::open(&channel)
+            .map_err(Error::device)?;
+        socket.set_nonblocking(true)
+            .map_err(Error::device)?;
+        // the timestamps are optional, the receiving time is used when not available
+        if let Err(e) = socket.set_timestamping(SOF_TIMESTAMPING_RX_SOFTWARE | SOF_TIMESTAMPING_SOFTWARE)
+            .or_else(|_| socket.set_recv_timestamp(true)) {
+            log::warn!("SocketCAN - timestamp of `{}` is not available: {}", channel, e);
+        }
+
+        let mut channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        channels.insert(channel, Channel { socket, config, loopback: false });
+        self.closed.store(false, Ordering::Release);
+
+        Ok(())
+    }
+
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        log::debug!("SocketCAN - close channel `{}`", channel);
+        let mut channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        channels.remove(&channel)
+            .map(|_| ())
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Acquire)
+    }
+
+    /// Set `CAN_RAW_RECV_OWN_MSGS` of the socket, the own frames are received as [`Direct::Receive`].
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error> {
+        log::debug!("SocketCAN - channel `{}` loopback: {}", channel, enabled);
+        let mut channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        let channel = channels.get_mut(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?;
+        channel.socket.set_recv_own_msgs(enabled)
+            .map_err(Error::device)?;
+        channel.loopback = enabled;
+        Ok(())
+    }
+
+    fn is_loopback(&self, channel: Self::C) -> bool {
+        match self.channels.lock() {
+            Ok(channels) => channels.get(&channel).is_some_and(|v| v.loopback),
+            Err(_) => false,
+        }
+    }
+
+    /// Set `CAN_RAW_FILTER` of the socket, the format of the frames is matched by `CAN_EFF_FLAG`.
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error> {
+        log::debug!("SocketCAN - channel `{}` filters: {:?}", channel, filters);
+        let channels = self.channels.lock()
+            .map_err(|_| Error::ContextError("can't get `channels`".into()))?;
+        let socket = &channels.get(&channel)
+            .ok_or(Error::InvalidParam(format!("channel `{}` is not opened", channel)))?
+            .socket;
+        if filters.is_empty() {
+            return socket.set_filter_accept_all()
+                .map_err(Error::device);
+        }
+
+        let flag = IdentifierFlags::EXTENDED.bits();
+        let filters = filters.iter()
+            .map(|f| (if f.extended { f.id | flag } else { f.id }, f.mask | flag))
+            .collect::<Vec<_>>();
+        socket.set_filters(&filters)
+            .map_err(Error::device)
+    }
+
+    /// The frames are timestamped by the kernel software(`SOF_TIMESTAMPING_RX_SOFTWARE`), not by the
+    /// hardware, the error frames are not subscribed.
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities {
+            fd: true,
+            brs: true,
+            hardware_timestamps: false,
+            blocking_receive: false,
+            error_frames: false,
+            listen_only: true,
+            loopback: true,
+        }
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, _: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        let mut results = Vec::new();
+        self.receive_util(channel, &mut results)?;
+        Ok(results)
+    }
+    #[cfg(not(feature = "async"))]
+    fn receive_into(&self, channel: Self::C, _: Option<u32>, buf: &mut Vec<Self::F>) -> Result<usize, Self::Error> {
+        self.receive_util(channel, buf)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use super::SocketCanFrame;
+
+    #[test]
+    fn test_frame() {
+        let frame = SocketCanFrame::new(0x7DF, &hex!("02 10 01")).unwrap();
+        assert_eq!(frame.id(), Id::Standard(0x7DF));
+        assert_eq!(frame.data(), hex!("02 10 01"));
+        assert!(!frame.is_can_fd());
+        assert!(!frame.is_extended());
+
+        let mut frame = SocketCanFrame::new(Id::Extended(0x18DA00F1), &[0x55; 12]).unwrap();
+        assert!(frame.is_extended());
+        assert!(frame.is_can_fd());
+        assert_eq!(frame.dlc(), Some(9));
+        frame.set_bitrate_switch(true).set_esi(true);
+        assert!(frame.is_bitrate_switch() && frame.is_esi());
+
+        let frame = SocketCanFrame::new_remote(0x123, 8).unwrap();
+        assert!(frame.is_remote());
+        assert_eq!(frame.length(), 8);
+        assert!(frame.data().is_empty());
+
+        let mut frame = SocketCanFrame::new(0x123, &[0x01]).unwrap();
+        frame.set_can_fd(true);
+        assert!(frame.is_can_fd());
+        frame.set_can_fd(false);
+        assert!(!frame.is_can_fd());
+        assert_eq!(frame.data(), [0x01]);
+    }
+}
+
+/// Requires the virtual CAN interface `vcan0`:
+/// ```shell
+/// sudo modprobe vcan && sudo ip link add dev vcan0 type vcan && sudo ip link set up vcan0
+/// ```
+#[cfg(all(test, not(feature = "async")))]
+mod vcan_tests {
+    use std::time::Duration;
+    use crate::can::Address;
+    use crate::can::driver::SyncCan;
+    use crate::can::isotp::SyncCanIsoTp;
+    use crate::can::isotp::testing::EventListener;
+    use crate::device::Driver;
+    use super::SocketCanDriver;
+
+    const VCAN: &str = "vcan0";
+
+    #[test]
+    fn test_iso_tp_over_vcan() -> anyhow::Result<()> {
+        if !std::path::Path::new("/sys/class/net").join(VCAN).exists() {
+            println!("skipped: `{}` is not available", VCAN);
+            return Ok(());
+        }
+
+        let mut client_can = SyncCan::new(SocketCanDriver::open(&[VCAN])?);
+        let mut server_can = SyncCan::new(SocketCanDriver::open(&[VCAN])?);
+        let client = SyncCanIsoTp::new(
+            VCAN.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EventListener::default()),
+        );
+        let server_data = EventListener::default();
+        let server = SyncCanIsoTp::new(
+            VCAN.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_data.clone()),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let request = (0..0x40).collect::<Vec<u8>>();
+        client.write(false, request.clone())?;
+        assert_eq!(server_data.wait_data(Duration::from_secs(1)), Some(request));
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+    #[test]
+    fn test_self_test_over_vcan() -> anyhow::Result<()> {
+        if !std::path::Path::new("/sys/class/net").join(VCAN).exists() {
+            println!("skipped: `{}` is not available", VCAN);
+            return Ok(());
+        }
+
+        let driver = SocketCanDriver::open(&[VCAN])?;
+        let can = SyncCan::new(driver.clone());
+        let report = can.self_test(VCAN.to_string(), Duration::from_secs(1))?;
+        assert!(report.is_passed(), "{:?}", report);
+        assert_eq!(report.received, Some(true));
+        assert!(!driver.is_loopback(VCAN.to_string()));
+        Ok(())
+    }
+}
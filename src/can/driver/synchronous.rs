@@ -1,53 +1,236 @@
 use std::collections::HashMap;
 use std::fmt::Display;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::mpsc::{channel, Receiver, Sender};
 use std::sync::{Arc, Mutex, MutexGuard, Weak};
 use std::thread::{sleep, spawn, JoinHandle};
-use std::time::Duration;
-use crate::can::driver::{listener_names, receive_callback, register_listener, transmit_callback, unregister_all, unregister_listener};
-use crate::can::frame::Frame;
-use crate::device::{Driver, Listener};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use crate::can::driver::{registry, ListenerRegistry, PanicGuard, on_bus_state_changed_util, on_connection_changed_util, on_shutdown_util, receive_callback, receive_channel_callback, transmit_callback, transmit_frame, DirectTransmit, TxGenerations};
+use crate::can::driver::cyclic::{CyclicHandle, CyclicScheduler};
+use crate::can::driver::offload::{ExecutionPolicy, OffloadPool, Offloaded};
+use crate::can::driver::priority::{TxPriority, TxQueue};
+use crate::can::frame::{Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::device::{BusState, Channel, ChannelConfig, Driver, DriverCapabilities, FilterSpec, Listener};
+use crate::error::Error;
+
+/// How the receive loop reads frames from the device.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub enum ReceiveMode {
+    /// Poll all opened channels on the interval of [`SyncCan::sync_start`].
+    #[default]
+    Polling,
+    /// Read each opened channel in a dedicated thread with a timeout(ms), frames are dispatched
+    /// as soon as they are received.
+    ///
+    /// Falls back to [`ReceiveMode::Polling`] if the device doesn't support blocking receive.
+    Blocking { timeout_ms: u32 },
+}
+
+/// How the loops reopen the device when it's closed unexpectedly, e.g. the USB adapter is unplugged.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct ReconnectPolicy {
+    /// The delay before the first attempt, doubled after each failed attempt.
+    pub initial_delay: Duration,
+    /// The max delay between the attempts.
+    pub max_delay: Duration,
+    /// Stop the loops after the failed attempts, `None` to retry forever.
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        Self {
+            initial_delay: Duration::from_millis(10),
+            max_delay: Duration::from_secs(1),
+            max_attempts: None,
+        }
+    }
+}
+
+/// The identifier of the frame transmitted by [`SyncCan::self_test`].
+const SELF_TEST_ID: u16 = 0x7FF;
+/// The prefix of the listener registered by [`SyncCan::self_test`] while it's running.
+const SELF_TEST_LISTENER: &str = "self-test";
+/// The count of [`SyncCan::self_test`], it numbers the listener of each test.
+static SELF_TESTS: AtomicU64 = AtomicU64::new(0);
+
+/// The result of [`SyncCan::self_test`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct SelfTestReport {
+    pub channel: String,
+    pub capabilities: DriverCapabilities,
+    pub bus_state: BusState,
+    /// The test frame is transmitted by the device.
+    pub transmitted: bool,
+    /// The time the device took to transmit the test frame.
+    pub transmit_time: Option<Duration>,
+    /// The test frame is received back, `None` when the device can't loop back.
+    pub received: Option<bool>,
+    /// The time from transmitting the test frame to receiving it back.
+    pub round_trip: Option<Duration>,
+    /// The error of the transmitting.
+    pub error: Option<String>,
+}
+
+impl SelfTestReport {
+    /// Whether the test frame is transmitted and received back if it can be.
+    #[inline]
+    pub fn is_passed(&self) -> bool {
+        self.transmitted && self.received != Some(false)
+    }
+}
+
+/// Records when the test frame of [`SyncCan::self_test`] is received back.
+#[derive(Clone)]
+struct SelfTestProbe {
+    id: Id,
+    data: Vec<u8>,
+    received: Arc<Mutex<Option<Instant>>>,
+}
+
+impl SelfTestProbe {
+    fn received(&self) -> Option<Instant> {
+        self.received.lock().ok().and_then(|v| *v)
+    }
+
+    fn check<F: Frame>(&self, frames: &[F]) {
+        if frames.iter().any(|v| v.id() == self.id && v.data() == self.data) {
+            if let Ok(mut received) = self.received.lock() {
+                received.get_or_insert_with(Instant::now);
+            }
+        }
+    }
+}
+
+impl<C: Channel, F: Frame + 'static> Listener<C, u32, F> for SelfTestProbe {
+    fn as_any(&self) -> &dyn std::any::Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, _: C, frames: &[F]) {
+        self.check(frames);
+    }
+}
+
+/// The running state shared by the transmit and receive loops.
+#[derive(Debug, Default)]
+struct LoopState {
+    stopped: AtomicBool,
+    tx_paused: AtomicBool,
+    rx_paused: AtomicBool,
+    reconnecting: AtomicBool,
+}
 
 #[derive(Clone)]
 pub struct SyncCan<D, C, F> {
     device: D,
     sender: Sender<F>,
     receiver: Arc<Mutex<Receiver<F>>>,
-    listeners: Arc<Mutex<HashMap<String, Box<dyn Listener<C, u32, F>>>>>,
-    stop_tx: Sender<()>,
-    stop_rx: Arc<Mutex<Receiver<()>>>,
+    listeners: Arc<ListenerRegistry<C, F>>,
+    state: Arc<LoopState>,
     send_task: Weak<JoinHandle<()>>,
-    receive_task: Weak<JoinHandle<()>>,
+    receive_tasks: Vec<Weak<JoinHandle<()>>>,
     interval: Option<u64>,
+    receive_mode: ReceiveMode,
+    reconnect: Option<ReconnectPolicy>,
+    bus_states: Arc<Mutex<HashMap<C, BusState>>>,
+    cyclic: Arc<Mutex<CyclicScheduler<F>>>,
+    tx_queue: Arc<Mutex<TxQueue<F>>>,
+    generations: TxGenerations,
+    panics: Arc<PanicGuard>,
+    offload: Arc<OffloadPool>,
+    auto_filter: Arc<AtomicBool>,
 }
 
 impl<D, C, F> SyncCan<D, C, F>
 where
     D: Driver<C = C, F = F> + Clone + 'static,
-    C: Clone + Display + 'static,
-    F: Frame<Channel = C> + Clone + Send + Display + 'static,
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Send + Display + 'static,
 {
     pub fn new(device: D) -> Self {
         let (tx, rx) = channel();
-        let (stop_tx, stop_rx) = channel();
         Self {
             device,
             sender: tx,
             receiver: Arc::new(Mutex::new(rx)),
-            listeners: Arc::new(Mutex::new(HashMap::new())),
-            stop_tx,
-            stop_rx: Arc::new(Mutex::new(stop_rx)),
+            listeners: Default::default(),
+            state: Default::default(),
             send_task: Default::default(),
-            receive_task: Default::default(),
+            receive_tasks: Default::default(),
             interval: Default::default(),
+            receive_mode: Default::default(),
+            reconnect: Default::default(),
+            bus_states: Default::default(),
+            cyclic: Default::default(),
+            tx_queue: Default::default(),
+            generations: Default::default(),
+            panics: Default::default(),
+            offload: Default::default(),
+            auto_filter: Default::default(),
         }
     }
 
+    /// Set the [`ReceiveMode`], it takes effect on the next [`Self::sync_start`].
+    #[inline]
+    pub fn set_receive_mode(&mut self, mode: ReceiveMode) {
+        self.receive_mode = mode;
+    }
+
+    #[inline]
+    pub fn receive_mode(&self) -> ReceiveMode {
+        self.receive_mode
+    }
+
+    /// Set the [`ReconnectPolicy`], it takes effect on the next [`Self::sync_start`].
+    ///
+    /// The loops exit when the device is closed if `None`(default), otherwise they retry
+    /// [`Driver::reopen`] and resume with the registered listeners and the queued frames.
+    /// The listeners are notified by [`Listener::on_connection_changed`].
+    #[inline]
+    pub fn set_reconnect_policy(&mut self, policy: Option<ReconnectPolicy>) {
+        self.reconnect = policy;
+    }
+
+    #[inline]
+    pub fn reconnect_policy(&self) -> Option<ReconnectPolicy> {
+        self.reconnect
+    }
+
     #[inline]
     pub fn sender(&self) -> Sender<F> {
         self.sender.clone()
     }
 
+    /// The capabilities of the device, see [`Driver::capabilities`].
+    #[inline]
+    pub fn capabilities(&self) -> DriverCapabilities {
+        self.device.capabilities()
+    }
+
+    /// Open or reconfigure `channel` of the device, see [`Driver::open_channel`].
+    ///
+    /// The running loops use clones of the device, so the device must share the channel
+    /// state between its clones for the change to take effect at runtime.
+    #[inline]
+    pub fn open_channel(&mut self, channel: C, config: ChannelConfig) -> Result<(), D::Error> {
+        log::debug!("SyncCAN - open channel: {}", channel);
+        self.device.open_channel(channel, config)
+    }
+
+    /// Close `channel` of the device, see [`Driver::close_channel`].
+    #[inline]
+    pub fn close_channel(&mut self, channel: C) -> Result<(), D::Error> {
+        log::debug!("SyncCAN - close channel: {}", channel);
+        self.device.close_channel(channel)
+    }
+
     #[inline]
     pub fn register_listener(
         &self,
@@ -55,75 +238,596 @@ where
         listener: Box<dyn Listener<C, u32, F>>,
     ) -> bool {
         log::debug!("ISO-TP(CAN sync) - register listener {}", name);
-        register_listener(&self.listeners, name, listener)
+        self.listeners.register(name, listener);
+        if self.is_auto_filter() {
+            self.refresh_filters();
+        }
+        true
+    }
+
+    /// Register `listener` executed by `policy`, the [`ExecutionPolicy::Offloaded`] one isn't
+    /// returned by [`Self::get_listener`] nor [`Self::with_listener`].
+    pub fn register_listener_with(
+        &self,
+        name: String,
+        listener: Box<dyn Listener<C, u32, F>>,
+        policy: ExecutionPolicy,
+    ) -> bool {
+        match policy {
+            ExecutionPolicy::Inline => self.register_listener(name, listener),
+            ExecutionPolicy::Offloaded { capacity } => {
+                match Offloaded::new(name.clone(), listener, capacity, &self.offload, &self.listeners, &self.panics) {
+                    Some(listener) => self.register_listener(name, Box::new(listener)),
+                    None => false,
+                }
+            },
+        }
+    }
+
+    /// Set the workers of the offloaded listeners, 2 by default. It takes effect on the listeners
+    /// registered later.
+    #[inline]
+    pub fn set_offload_workers(&self, size: usize) {
+        self.offload.set_size(size);
+    }
+
+    #[inline]
+    pub fn offload_workers(&self) -> usize {
+        self.offload.size()
+    }
+
+    /// The frame callbacks dropped by the full queue of the offloaded listener registered as `name`,
+    /// `None` when it's not offloaded.
+    pub fn offload_dropped(&self, name: &str) -> Option<u64> {
+        self.listeners.with(name, |listener| listener.as_any().downcast_ref::<Offloaded<C, F>>().map(Offloaded::dropped))
+            .flatten()
     }
 
     #[inline]
     pub fn unregister_listener(&self, name: String) -> bool {
-        unregister_listener(&self.listeners, name)
+        let result = self.listeners.unregister(&name);
+        if result && self.is_auto_filter() {
+            self.refresh_filters();
+        }
+        result
     }
 
     #[inline]
     pub fn unregister_all(&self) -> bool {
-        unregister_all(&self.listeners)
+        self.listeners.clear();
+        if self.is_auto_filter() {
+            self.refresh_filters();
+        }
+        true
+    }
+
+    /// Set the union of [`Listener::rx_filter`] of the listeners to the device by [`Driver::set_filters`]
+    /// whenever a listener is registered or unregistered, disabled by default.
+    ///
+    /// A channel is not filtered when any of the listeners receives all its frames, or none of them
+    /// receives any. Disabling it clears the filters of the opened channels.
+    pub fn auto_filter(&self, enabled: bool) {
+        log::debug!("SyncCAN - auto filter: {}", enabled);
+        self.auto_filter.store(enabled, Ordering::Release);
+        self.refresh_filters();
+    }
+
+    #[inline]
+    pub fn is_auto_filter(&self) -> bool {
+        self.auto_filter.load(Ordering::Acquire)
+    }
+
+    /// Set the filters of the listeners to the opened channels of the device now, e.g. after the
+    /// address of a listener is changed. The filters are cleared when [`Self::auto_filter`] is disabled.
+    ///
+    /// The error of the device is logged only, e.g. it doesn't support the acceptance filters.
+    pub fn refresh_filters(&self) {
+        let enabled = self.is_auto_filter();
+        let listeners = self.listeners.snapshot();
+        let mut device = self.device.clone();
+        for channel in device.opened_channels() {
+            let filters = match enabled {
+                true => Self::union_filters(&listeners, &channel),
+                false => Vec::new(),
+            };
+            log::trace!("SyncCAN - filters of channel {}: {:?}", channel, filters);
+            if let Err(e) = device.set_filters(channel.clone(), &filters) {
+                log::warn!("SyncCAN - set filters of channel {} failed: {}", channel, e);
+            }
+        }
+    }
+
+    /// The union of the filters of `listeners` on `channel`, empty when any of them receives all.
+    fn union_filters(listeners: &[registry::ListenerEntry<C, F>], channel: &C) -> Vec<FilterSpec> {
+        let mut result: Vec<FilterSpec> = Vec::new();
+        for (_, listener) in listeners {
+            let filters = match registry::lock(listener) {
+                Some(listener) => listener.rx_filter(channel),
+                None => None,
+            };
+            match filters {
+                Some(filters) => filters.into_iter()
+                    .for_each(|f| if !result.contains(&f) { result.push(f) }),
+                None => return Vec::new(),
+            }
+        }
+
+        result
     }
 
     #[inline]
     pub fn listener_names(&self) -> Vec<String> {
-        listener_names(&self.listeners)
+        self.listeners.names()
+    }
+
+    /// Unregister a listener after it panicked `value` times, `None`(default) to keep it registered.
+    ///
+    /// The panics of the listener callbacks are always caught to keep the loops running.
+    #[inline]
+    pub fn set_max_listener_panics(&self, value: Option<usize>) {
+        self.panics.set_max_panics(value);
+    }
+
+    #[inline]
+    pub fn max_listener_panics(&self) -> Option<usize> {
+        self.panics.max_panics()
+    }
+
+    /// The number of panics caught from the listener callbacks.
+    #[inline]
+    pub fn listener_panics(&self) -> u64 {
+        self.panics.count()
     }
 
     pub fn listener_callback(&self, name: &str, callback: impl FnOnce(&Box<dyn Listener<C, u32, F>>)) {
-        if let Ok(listeners) = self.listeners.lock() {
-            if let Some(listener) = listeners.get(name) {
-                callback(listener);
+        self.listeners.with(name, callback);
+    }
+
+    /// Get a clone of the listener registered as `name`.
+    ///
+    /// Returns `None` when no listener is registered as `name` or the listener is not a `T`.
+    pub fn get_listener<T>(&self, name: &str) -> Option<T>
+    where
+        T: Listener<C, u32, F> + Clone,
+    {
+        self.with_listener(name, |listener: &T| listener.clone())
+    }
+
+    /// Call `callback` with the listener registered as `name` if the listener is a `T`.
+    ///
+    /// This is useful for a listener that does not implement [`Clone`].
+    /// An offloaded listener is called once the callback executed by its worker returns.
+    pub fn with_listener<T, R>(&self, name: &str, callback: impl FnOnce(&T) -> R) -> Option<R>
+    where
+        T: Listener<C, u32, F>,
+    {
+        self.listeners.with(name, |listener| {
+            let any = listener.as_any();
+            match any.downcast_ref::<Offloaded<C, F>>() {
+                // the wrapper of an offloaded listener, the listener registered is inside it
+                Some(offloaded) => offloaded.with_inner(|o| o.as_any().downcast_ref::<T>().map(callback))
+                    .flatten(),
+                None => any.downcast_ref::<T>().map(callback),
             }
-        }
+        })
+            .flatten()
     }
 
-    pub fn sync_transmit(device: MutexGuard<Self>, interval_us: u64, stopper: Arc<Mutex<Receiver<()>>>) {
-        sync_util(device, interval_us, stopper, |device| {
-            transmit_callback(&device.receiver, &device.device, &device.listeners, None);
-        });
+    /// Pause the transmit loop, and the receive loop too when `receive` is true.
+    ///
+    /// The loops keep running and the listeners stay registered,
+    /// the frames sent while paused are queued and transmitted after [`Self::resume`].
+    pub fn pause(&self, receive: bool) {
+        log::info!("SyncCAN - pause(receive: {})", receive);
+        self.state.tx_paused.store(true, Ordering::Release);
+        self.state.rx_paused.store(receive, Ordering::Release);
+    }
+
+    /// Resume the loops paused by [`Self::pause`].
+    pub fn resume(&self) {
+        log::info!("SyncCAN - resume");
+        self.state.tx_paused.store(false, Ordering::Release);
+        self.state.rx_paused.store(false, Ordering::Release);
+    }
+
+    /// Whether the transmit loop is paused.
+    #[inline]
+    pub fn is_paused(&self) -> bool {
+        self.state.tx_paused.load(Ordering::Acquire)
     }
 
-    pub fn sync_receive(device: MutexGuard<Self>, interval_us: u64, stopper: Arc<Mutex<Receiver<()>>>) {
-        sync_util(device, interval_us, stopper, |device| {
-            receive_callback(&device.device, &device.listeners, None);
+    /// Transmit `frame` by the device in the caller's thread, bypassing the transmit loop,
+    /// e.g. a flow control under a tight N_Br or an emergency frame.
+    ///
+    /// It doesn't interact with the ordering of the queued frames, and it's not held by
+    /// [`Self::pause`]. The listeners are notified as by the transmit loop. The device is called
+    /// from this thread while the loop transmits by its clone, so the clones of the driver must
+    /// be safe to transmit concurrently.
+    pub fn transmit_now(&self, frame: F, timeout: Option<u32>) -> Result<(), Error> {
+        transmit_frame(&self.device, &self.listeners, &self.panics, frame, timeout)
+            .map(|_| ())
+    }
+
+    /// The [`DirectTransmit`] of the device, it transmits as [`Self::transmit_now`] by the thread
+    /// calling it, e.g. a transport replies the flow controls by it from the receive loop,
+    /// see [`SyncCanIsoTp::with_direct_transmit`](crate::can::isotp::SyncCanIsoTp::with_direct_transmit).
+    pub fn direct_transmit(&self) -> DirectTransmit<F> {
+        let device = Mutex::new(self.device.clone());
+        let listeners = Arc::clone(&self.listeners);
+        let panics = Arc::clone(&self.panics);
+        DirectTransmit::new(move |frame, timeout| {
+            // not locked while transmitting, a listener may transmit by it again
+            let device = device.lock()
+                .map_err(|_| Error::ContextError("can't get `device`".into()))?
+                .clone();
+            transmit_frame(&device, &listeners, &panics, frame, timeout)
+        })
+    }
+
+    /// Check the device is alive: transmit a test frame(`0x7FF`) on `channel` and wait `timeout`
+    /// for it to be received back by the loopback of the device, which is enabled while testing.
+    ///
+    /// When the device can't loop back(see [`DriverCapabilities::loopback`]), only the transmitting
+    /// is checked and [`SelfTestReport::received`] is `None`. The frame is received by the receive
+    /// loop when it's running, otherwise by this thread and the frames received are passed to the
+    /// listeners as by the loop.
+    pub fn self_test(&self, channel: C, timeout: Duration) -> Result<SelfTestReport, Error> {
+        if !self.device.opened_channels().contains(&channel) {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", channel)));
+        }
+
+        let capabilities = self.device.capabilities();
+        let mut report = SelfTestReport {
+            channel: channel.to_string(),
+            capabilities,
+            bus_state: self.device.bus_state(channel.clone()),
+            ..Default::default()
+        };
+        // tagged by the time to not match the frame of a previous test
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH)
+            .map(|v| v.subsec_nanos())
+            .unwrap_or_default();
+        let data = [&[0x53, 0x54, 0x45, 0x53], nanos.to_be_bytes().as_slice()].concat();
+        let id = Id::Standard(SELF_TEST_ID);
+        // built before the loopback is enabled, nothing is left to restore when it's invalid
+        let mut frame = F::new(id, &data)
+            .ok_or(Error::InvalidParam("invalid test frame".into()))?;
+        frame.set_channel(channel.clone());
+
+        let mut device = self.device.clone();
+        let enabled = device.is_loopback(channel.clone());
+        let loopback = capabilities.loopback && (enabled || match device.set_loopback(channel.clone(), true) {
+            Ok(()) => true,
+            Err(e) => {
+                log::warn!("SyncCAN - enable loopback of channel {} failed: {}", channel, e);
+                false
+            },
         });
+
+        // unique for each test, a listener registered by the caller or a concurrent test is not replaced
+        let name = format!("{}-{}", SELF_TEST_LISTENER, SELF_TESTS.fetch_add(1, Ordering::AcqRel));
+        let probe = SelfTestProbe { id, data, received: Default::default() };
+        self.register_listener(name.clone(), Box::new(probe.clone()));
+
+        log::info!("SyncCAN - self test of channel {}, loopback: {}", channel, loopback);
+        let start = Instant::now();
+        match self.transmit_now(frame, Some(timeout.as_millis() as u32)) {
+            Ok(()) => {
+                report.transmitted = true;
+                report.transmit_time = Some(start.elapsed());
+            },
+            Err(e) => report.error = Some(e.to_string()),
+        }
+
+        if report.transmitted && loopback {
+            let looping = self.interval.is_some()
+                && !self.state.stopped.load(Ordering::Acquire)
+                && !self.state.rx_paused.load(Ordering::Acquire);
+            let mut buffer = Vec::new();
+            while probe.received().is_none() && start.elapsed() < timeout {
+                if looping {
+                    sleep(Duration::from_millis(1));
+                    continue;
+                }
+
+                // received as by the receive loop, the other frames are passed to the listeners too
+                if let Some(state) = receive_channel_callback(&device, &self.listeners, &self.panics, channel.clone(), &mut buffer, Some(1)) {
+                    self.set_bus_state(channel.clone(), state);
+                }
+                if buffer.is_empty() {
+                    sleep(Duration::from_millis(1));
+                }
+            }
+            report.received = Some(probe.received().is_some());
+            report.round_trip = probe.received().map(|v| v.duration_since(start));
+        }
+
+        self.unregister_listener(name);
+        if loopback && !enabled {
+            if let Err(e) = device.set_loopback(channel.clone(), false) {
+                log::warn!("SyncCAN - disable loopback of channel {} failed: {}", channel, e);
+            }
+        }
+        log::info!("SyncCAN - self test of channel {}: {:?}", channel, report);
+
+        Ok(report)
+    }
+
+    /// Transmit `frame` every `period` by the transmit loop until removed,
+    /// the first emission is on the next loop.
+    ///
+    /// The accuracy of the period is limited by the interval of [`Self::sync_start`].
+    pub fn add_cyclic(&self, name: String, frame: F, period: Duration) -> CyclicHandle {
+        log::debug!("SyncCAN - add cyclic frame {}: {} every {:?}", name, frame, period);
+        match self.cyclic.lock() {
+            Ok(mut v) => v.add(name, frame, period),
+            Err(e) => e.into_inner().add(name, frame, period),
+        }
+    }
+
+    /// Replace the data of the cyclic frame, the next emission carries the new data.
+    ///
+    /// Returns false when the handle is removed or `data` is invalid for the frame.
+    pub fn update_cyclic(&self, handle: &CyclicHandle, data: &[u8]) -> bool {
+        match self.cyclic.lock() {
+            Ok(mut v) => v.update(handle, data),
+            Err(e) => {
+                log::warn!("SyncCAN - mutex error: {:?} when updating cyclic frame", e);
+                false
+            },
+        }
+    }
+
+    /// Stop transmitting the cyclic frame, returns false when the handle is already removed.
+    pub fn remove_cyclic(&self, handle: &CyclicHandle) -> bool {
+        log::debug!("SyncCAN - remove cyclic frame {}", handle.name());
+        match self.cyclic.lock() {
+            Ok(mut v) => v.remove(handle),
+            Err(e) => {
+                log::warn!("SyncCAN - mutex error: {:?} when removing cyclic frame", e);
+                false
+            },
+        }
+    }
+
+    /// Transmit the queued frames by [`TxPriority`] instead of FIFO, `None`(default) to disable.
+    ///
+    /// One frame is transmitted on each loop, so only the frames queued together are reordered,
+    /// e.g. `TxPriority::j1939` keeps the pacing of a BAM session.
+    pub fn set_tx_priority(&self, priority: Option<TxPriority<F>>) {
+        match self.tx_queue.lock() {
+            Ok(mut v) => v.set_order(priority),
+            Err(e) => e.into_inner().set_order(priority),
+        }
+    }
+
+    /// The [`TxGenerations`] of the transmit loop, a transport sends its frames by it to drop
+    /// the ones queued by a superseded transfer, see [`SyncCanIsoTp::with_tx_generations`](crate::can::isotp::SyncCanIsoTp::with_tx_generations).
+    #[inline]
+    pub fn tx_generations(&self) -> TxGenerations {
+        self.generations.clone()
+    }
+
+    /// Transmit a queued frame and the due cyclic frames on each loop.
+    ///
+    /// The loop waits up to `interval_us` for the next sent frame and is woken by it,
+    /// so the frames are transmitted as soon as they are sent.
+    pub fn sync_transmit(mut device: MutexGuard<Self>, interval_us: u64) {
+        let interval = Duration::from_micros(interval_us);
+        while is_running(&mut device) {
+            if device.state.tx_paused.load(Ordering::Acquire) {
+                sleep(interval);
+                continue;
+            }
+
+            transmit_callback(&device.receiver, &device.tx_queue, &device.generations, &device.device, &device.listeners, &device.panics, None);
+
+            let frames = match device.cyclic.lock() {
+                Ok(mut v) => v.due(Instant::now()),
+                Err(_) => vec![],
+            };
+            for frame in frames {
+                // the failures are notified to the listeners
+                let _ = transmit_frame(&device.device, &device.listeners, &device.panics, frame, None);
+            }
+
+            device.wait_sent(interval);
+        }
+    }
+
+    /// Poll all opened channels on each loop, the receive buffers are reused across the polls.
+    pub fn sync_receive(mut device: MutexGuard<Self>, interval_us: u64) {
+        let interval = Duration::from_micros(interval_us);
+        let mut buffers = Vec::new();
+        while is_running(&mut device) {
+            if !device.state.rx_paused.load(Ordering::Acquire) {
+                receive_callback(&device.device, &device.listeners, &device.panics, &mut buffers, None)
+                    .into_iter()
+                    .for_each(|(c, state)| device.set_bus_state(c, state));
+                device.device.opened_channels()
+                    .into_iter()
+                    .for_each(|c| device.update_bus_state(c));
+            }
+
+            sleep(interval);
+        }
+    }
+
+    /// Receive frames of `channel` with blocking read, the `interval_us` is only used when paused
+    /// or receiving failed.
+    pub fn blocking_receive(mut device: MutexGuard<Self>, channel: C, interval_us: u64, timeout_ms: u32) {
+        let mut buffer = Vec::new();
+        while is_running(&mut device) {
+            if device.state.rx_paused.load(Ordering::Acquire) {
+                sleep(Duration::from_micros(interval_us));
+                continue;
+            }
+
+            if let Some(state) = receive_channel_callback(&device.device, &device.listeners, &device.panics, channel.clone(), &mut buffer, Some(timeout_ms)) {
+                device.set_bus_state(channel.clone(), state);
+            }
+            device.update_bus_state(channel.clone());
+        }
+    }
+
+    /// Wait up to `timeout` for a frame sent to the transmit loop and queue it,
+    /// returns at once if any frame is queued.
+    fn wait_sent(&self, timeout: Duration) {
+        let queued = match self.tx_queue.lock() {
+            Ok(v) => !v.is_empty(),
+            Err(_) => false,
+        };
+        if queued {
+            return;
+        }
+
+        let msg = match self.receiver.lock() {
+            Ok(receiver) => receiver.recv_timeout(timeout).ok(),
+            Err(_) => {
+                sleep(timeout);
+                None
+            },
+        };
+        if let Some(msg) = msg {
+            match self.tx_queue.lock() {
+                Ok(mut v) => v.push(msg, Instant::now()),
+                // the poisoned queue is bypassed by the transmit loop
+                Err(_) => { let _ = self.sender.send(msg); },
+            }
+        }
+    }
+
+    /// The bus state of `channel` when last polled or decoded from the error frames by the receive loop.
+    pub fn bus_state(&self, channel: &C) -> BusState {
+        match self.bus_states.lock() {
+            Ok(v) => v.get(channel).copied().unwrap_or_default(),
+            Err(_) => BusState::Unknown,
+        }
+    }
+
+    /// Retry reopening the closed device with `policy`, returns false when stopped or gave up.
+    ///
+    /// Only one loop reopens the device, the others wait for the result.
+    fn reconnect_util(&mut self, policy: ReconnectPolicy) -> bool {
+        let stopped = |state: &LoopState| state.stopped.load(Ordering::Acquire);
+        if self.state.reconnecting.swap(true, Ordering::AcqRel) {
+            while self.state.reconnecting.load(Ordering::Acquire) && !stopped(&self.state) {
+                sleep(Duration::from_millis(1));
+            }
+            return !stopped(&self.state) && !self.device.is_closed();
+        }
+
+        log::warn!("SyncCAN - device is closed, reconnecting");
+        on_connection_changed_util(&self.listeners, &self.panics, false);
+        let mut delay = policy.initial_delay;
+        let mut attempts = 0;
+        let result = loop {
+            if stopped(&self.state) {
+                break false;
+            }
+            if policy.max_attempts.is_some_and(|max| attempts >= max) {
+                log::error!("SyncCAN - gave up reconnecting after {} attempts", attempts);
+                self.state.stopped.store(true, Ordering::Release);
+                break false;
+            }
+
+            attempts += 1;
+            sleep(delay);
+            match self.device.reopen() {
+                Ok(_) => {
+                    log::info!("SyncCAN - reconnected after {} attempts", attempts);
+                    break true;
+                },
+                Err(e) => {
+                    log::debug!("SyncCAN - reconnect attempt {} failed: {}", attempts, e);
+                    delay = (delay * 2).min(policy.max_delay);
+                },
+            }
+        };
+
+        if result {
+            on_connection_changed_util(&self.listeners, &self.panics, true);
+        }
+        self.state.reconnecting.store(false, Ordering::Release);
+        result
+    }
+
+    /// Query the bus state of `channel` and notify the listeners if changed,
+    /// the unknown state reported by the device doesn't override the state decoded from the error frames.
+    fn update_bus_state(&self, channel: C) {
+        let state = self.device.bus_state(channel.clone());
+        if state != BusState::Unknown {
+            self.set_bus_state(channel, state);
+        }
+    }
+
+    /// Save the bus state of `channel` and notify the listeners if changed.
+    fn set_bus_state(&self, channel: C, state: BusState) {
+        let changed = match self.bus_states.lock() {
+            Ok(mut v) => v.insert(channel.clone(), state)
+                .unwrap_or_default() != state,
+            Err(_) => false,
+        };
+        if changed {
+            log::info!("SyncCAN - bus state of channel {} changed to {:?}", channel, state);
+            on_bus_state_changed_util(&self.listeners, &self.panics, channel, state);
+        }
     }
 
     pub fn sync_start(&mut self, interval_us: u64) {
         self.interval = Some(interval_us);
+        self.state.stopped.store(false, Ordering::Release);
 
         let self_arc = Arc::new(Mutex::new(self.clone()));
-        let stop_rx = Arc::clone(&self.stop_rx);
         let tx_task = spawn(move || {
             if let Ok(self_clone) = self_arc.lock() {
-                Self::sync_transmit(self_clone, interval_us, Arc::clone(&stop_rx));
-            }
-        });
-
-        let self_arc = Arc::new(Mutex::new(self.clone()));
-        let stop_rx = Arc::clone(&self.stop_rx);
-        let rx_task = spawn(move || {
-            if let Ok(self_clone) = self_arc.lock() {
-                Self::sync_receive(self_clone, interval_us, Arc::clone(&stop_rx));
+                Self::sync_transmit(self_clone, interval_us);
             }
         });
 
         self.send_task = Arc::downgrade(&Arc::new(tx_task));
-        self.receive_task = Arc::downgrade(&Arc::new(rx_task));
+        self.receive_tasks.clear();
+
+        match self.receive_mode {
+            ReceiveMode::Blocking { timeout_ms } if self.device.is_blocking_receive() => {
+                for channel in self.device.opened_channels() {
+                    log::debug!("SyncCAN - blocking receive on channel: {}", channel);
+                    let self_arc = Arc::new(Mutex::new(self.clone()));
+                    let rx_task = spawn(move || {
+                        if let Ok(self_clone) = self_arc.lock() {
+                            Self::blocking_receive(self_clone, channel, interval_us, timeout_ms);
+                        }
+                    });
+                    self.receive_tasks.push(Arc::downgrade(&Arc::new(rx_task)));
+                }
+            },
+            mode => {
+                if mode != ReceiveMode::Polling {
+                    log::warn!("SyncCAN - blocking receive is not supported by device, fallback to polling");
+                }
+
+                let self_arc = Arc::new(Mutex::new(self.clone()));
+                let rx_task = spawn(move || {
+                    if let Ok(self_clone) = self_arc.lock() {
+                        Self::sync_receive(self_clone, interval_us);
+                    }
+                });
+                self.receive_tasks.push(Arc::downgrade(&Arc::new(rx_task)));
+            },
+        }
     }
 
+    /// Stop the loops, notify the listeners by [`Listener::on_shutdown`] and shut down the device.
     pub fn stop(&mut self) {
         log::info!("SyncCAN - closing(sync)");
 
-        if let Err(e) = self.stop_tx.send(()) {
-            log::warn!("SyncCAN - error: {} when sending stop signal", e);
-        }
+        self.state.stopped.store(true, Ordering::Release);
 
-        sleep(Duration::from_micros(2 * self.interval.unwrap_or(50 * 1000)));
+        let mut wait_us = 2 * self.interval.unwrap_or(50 * 1000);
+        if let ReceiveMode::Blocking { timeout_ms } = self.receive_mode {
+            wait_us = wait_us.max(1000 * timeout_ms as u64);
+        }
+        sleep(Duration::from_micros(wait_us));
 
         if let Some(task) = self.send_task.upgrade() {
             if !task.is_finished() {
@@ -131,45 +835,996 @@ where
             }
         }
 
-        if let Some(task) = self.receive_task.upgrade() {
+        for task in self.receive_tasks.iter().filter_map(Weak::upgrade) {
             if !task.is_finished() {
                 log::warn!("SyncCAN - receive task is running after stop signal");
             }
         }
 
+        // the transfers waiting for the bus fail now instead of by their timeouts
+        on_shutdown_util(&self.listeners, &self.panics);
         self.device.shutdown();
     }
 }
 
 #[inline]
-fn sync_util<D, C, F>(
-    device: MutexGuard<SyncCan<D, C, F>>,
-    interval: u64,
-    stopper: Arc<Mutex<Receiver<()>>>,
-    callback: fn(&MutexGuard<SyncCan<D, C, F>>)
-)
+fn is_running<D, C, F>(device: &mut MutexGuard<SyncCan<D, C, F>>) -> bool
 where D: Driver<C = C, F = F> + Clone + 'static,
-      C: Clone + Display + 'static,
-      F: Frame<Channel = C> + Clone + Send + Display + 'static,
+      C: Channel,
+      F: FrameMut<Channel = C> + Clone + Send + Display + 'static,
 {
-    loop {
-        if !device.device.is_closed() {
-            callback(&device);
+    if device.state.stopped.load(Ordering::Acquire) {
+        log::info!("SyncCAN - stop sync loop.");
+        return false;
+    }
+
+    if device.device.is_closed() {
+        let policy = device.reconnect;
+        match policy {
+            Some(policy) if device.reconnect_util(policy) => return true,
+            _ => {
+                log::info!("SyncCAN - exit sync loop.");
+                return false;
+            },
+        }
+    }
+
+    true
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
+    use std::sync::{Arc, Mutex};
+    use std::thread::{sleep, spawn};
+    use std::any::Any;
+    use std::time::{Duration, Instant};
+    use hex_literal::hex;
+    use crate::{FlowControlContext, FlowControlState, IsoTpEvent, IsoTpState};
+    use crate::constant::TIMEOUT_BS_ISO15765_2;
+    use crate::error::Error;
+    use crate::can::Address;
+    use crate::can::driver::{ExecutionPolicy, MOCK_CHANNEL, MockDriver, ReceiveMode, ReconnectPolicy, SyncCan, TxPriority, VirtualBus};
+    use crate::can::error_frame::{ErrorClass, ErrorInfo};
+    use crate::can::frame::{Direct, Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::isotp::SyncCanIsoTp;
+    use crate::can::isotp::testing::{DataListener, EchoListener, EmptyListener, EventListener};
+    use crate::can::message::CanMessage;
+    use crate::device::{BusState, ChannelConfig, Driver, Listener};
+
+    /// Records the time when frames are received.
+    #[derive(Clone, Default)]
+    struct TimeListener(Arc<Mutex<Vec<Instant>>>);
+
+    impl Listener<String, u32, CanMessage> for TimeListener {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            let now = Instant::now();
+            self.0.lock().unwrap().extend(frames.iter().map(|_| now));
+        }
+    }
+
+    /// Records the connection changes.
+    #[derive(Clone, Default)]
+    struct ConnectionListener(Arc<Mutex<Vec<bool>>>);
+
+    impl Listener<String, u32, CanMessage> for ConnectionListener {
+        fn as_any(&self) -> &dyn Any {
+            self
         }
-        else {
-            log::info!("SyncCAN - exit sync receive.");
-            break;
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, _: &[CanMessage]) {}
+
+        fn on_connection_changed(&mut self, connected: bool) {
+            self.0.lock().unwrap().push(connected);
+        }
+    }
+
+    /// Records the first byte of the received frames slowly, e.g. a logger writing to disk.
+    #[derive(Clone, Default)]
+    struct SlowListener(Arc<Mutex<Vec<u8>>>);
+
+    impl Listener<String, u32, CanMessage> for SlowListener {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            sleep(Duration::from_millis(50));
+            self.0.lock().unwrap().extend(frames.iter().map(|v| v.data()[0]));
+        }
+    }
+
+    /// Records the received frames, the error frames and the bus states.
+    #[derive(Clone, Default)]
+    struct ErrorRecords {
+        frames: Vec<CanMessage>,
+        errors: Vec<ErrorInfo>,
+        states: Vec<BusState>,
+    }
+
+    #[derive(Clone, Default)]
+    struct ErrorListener(Arc<Mutex<ErrorRecords>>);
+
+    impl Listener<String, u32, CanMessage> for ErrorListener {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            self.0.lock().unwrap().frames.extend_from_slice(frames);
+        }
+
+        fn on_error_frame(&mut self, _: String, _: &CanMessage, info: &ErrorInfo) {
+            self.0.lock().unwrap().errors.push(*info);
         }
 
-        if let Ok(stopper) = stopper.lock() {
-            if let Ok(()) = stopper.try_recv() {
-                log::info!("SyncCAN - stop sync receive.");
-                break;
+        fn on_bus_state_changed(&mut self, _: String, state: BusState) {
+            self.0.lock().unwrap().states.push(state);
+        }
+    }
+
+    /// Panics on every received frame.
+    struct PanicListener;
+
+    impl Listener<String, u32, CanMessage> for PanicListener {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, _: &[CanMessage]) {
+            panic!("deliberate panic");
+        }
+    }
+
+    /// Transmit frames from the peer and return the max latency until received.
+    fn max_latency(mode: ReceiveMode) -> anyhow::Result<Duration> {
+        let (a, b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        can.set_receive_mode(mode);
+        let listener = TimeListener::default();
+        can.register_listener("time".into(), Box::new(listener.clone()));
+        can.sync_start(20_000);
+        sleep(Duration::from_millis(5));
+
+        let mut frame = CanMessage::new(0x7E8, &hex!("02 50 01")).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        let mut latency = Duration::ZERO;
+        for i in 0..10 {
+            let start = Instant::now();
+            b.transmit(frame.clone(), None)?;
+            while listener.0.lock().unwrap().len() <= i {
+                assert!(start.elapsed() < Duration::from_secs(1));
+                sleep(Duration::from_micros(50));
             }
+            latency = latency.max(listener.0.lock().unwrap()[i] - start);
+            sleep(Duration::from_micros(3_700));
         }
 
-        sleep(Duration::from_micros(interval));
+        can.stop();
+        Ok(latency)
+    }
+
+    #[test]
+    fn test_get_listener() -> anyhow::Result<()> {
+        let (driver, _) = VirtualBus::pair();
+        let can: SyncCan<MockDriver, String, CanMessage> = SyncCan::new(driver);
+        let isotp = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            can.sender(),
+            Box::new(EmptyListener),
+        );
+        assert!(can.register_listener("UDS".into(), Box::new(isotp)));
+
+        assert!(can.get_listener::<SyncCanIsoTp<String, CanMessage>>("unknown").is_none());
+
+        let isotp = can.get_listener::<SyncCanIsoTp<String, CanMessage>>("UDS")
+            .unwrap();
+        isotp.write(false, hex!("10 01").to_vec())?;
+        let frame = can.receiver.lock().unwrap().try_recv()?;
+        assert_eq!(frame.id().into_bits(), 0x7E0);
+        assert_eq!(frame.data(), hex!("02 10 01 AA AA AA AA AA"));
+
+        let tx_id = can.with_listener("UDS", |isotp: &SyncCanIsoTp<String, CanMessage>| {
+            isotp.address.load().tx_id
+        });
+        assert_eq!(tx_id, Some(0x7E0));
+        Ok(())
+    }
+
+    #[test]
+    fn test_channel_management() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut can: SyncCan<MockDriver, String, CanMessage> = SyncCan::new(a.clone());
+        can.open_channel(MOCK_CHANNEL.into(), ChannelConfig::new_fd(500_000, 2_000_000))?;
+        assert_eq!(a.channel_config(MOCK_CHANNEL), Some(ChannelConfig::new_fd(500_000, 2_000_000)));
+        assert_eq!(b.channel_config(MOCK_CHANNEL), Some(ChannelConfig::default()));
+
+        can.close_channel(MOCK_CHANNEL.into())?;
+        assert!(a.opened_channels().is_empty());
+        assert!(can.close_channel(MOCK_CHANNEL.into()).is_err());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_pause_resume() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        let server_data = DataListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_data.clone()),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        // the server receives the first frame, but its flow control is held while paused
+        server_can.pause(false);
+        assert!(server_can.is_paused());
+        let request = (0..0x40).collect::<Vec<u8>>();
+        let data = request.clone();
+        let writer = spawn(move || client.write(false, data));
+        sleep(Duration::from_millis(50));
+        assert!(!writer.is_finished());
+        assert!(server_data.0.lock().unwrap().is_none());
+
+        server_can.resume();
+        assert!(!server_can.is_paused());
+        writer.join().unwrap()?;
+        sleep(Duration::from_millis(10));
+        assert_eq!(server_data.0.lock().unwrap().take(), Some(request));
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    /// The mock driver without the loopback.
+    #[derive(Clone)]
+    struct NoLoopback(MockDriver);
+
+    impl Driver for NoLoopback {
+        type Error = Error;
+        type C = String;
+        type F = CanMessage;
+
+        fn opened_channels(&self) -> Vec<Self::C> {
+            self.0.opened_channels()
+        }
+
+        fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> Result<(), Self::Error> {
+            self.0.transmit(msg, timeout)
+        }
+
+        fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+            self.0.receive(channel, timeout)
+        }
+
+        fn shutdown(&mut self) {
+            self.0.shutdown()
+        }
+    }
+
+    #[test]
+    fn test_self_test() -> anyhow::Result<()> {
+        let (a, peer) = VirtualBus::pair();
+        let handle = a.clone();
+        let mut can = SyncCan::new(a);
+        assert!(matches!(can.self_test("unknown".into(), Duration::from_secs(1)), Err(Error::InvalidParam(_))));
+
+        // received by this thread, a listener of the same name is kept and the other frames are passed to it
+        let listener = ErrorListener::default();
+        can.register_listener("self-test".into(), Box::new(listener.clone()));
+        let mut frame = CanMessage::new(Id::Standard(0x7E8), &[0x02, 0x10, 0x01]).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        peer.transmit(frame, None)?;
+        let report = can.self_test(MOCK_CHANNEL.into(), Duration::from_secs(1))?;
+        assert!(report.is_passed(), "{:?}", report);
+        assert_eq!(report.channel, MOCK_CHANNEL);
+        assert!(report.capabilities.loopback);
+        assert_eq!(report.bus_state, BusState::ErrorActive);
+        assert_eq!(report.received, Some(true));
+        assert!(report.transmit_time.is_some() && report.round_trip.is_some() && report.error.is_none());
+        assert!(listener.0.lock().unwrap().frames.iter().any(|v| v.id() == Id::Standard(0x7E8)));
+        // the loopback is restored and the test frame is on the bus
+        assert!(!handle.is_loopback(MOCK_CHANNEL.into()));
+        assert_eq!(can.listener_names(), vec!["self-test".to_string()]);
+        can.unregister_listener("self-test".into());
+        let frames = peer.receive(MOCK_CHANNEL.into(), None)?;
+        assert!(matches!(frames.as_slice(), [v] if v.id() == Id::Standard(0x7FF) && v.data()[..4] == *b"STES"));
+
+        // received by the receive loop
+        can.sync_start(100);
+        let report = can.self_test(MOCK_CHANNEL.into(), Duration::from_secs(1))?;
+        assert_eq!(report.received, Some(true));
+        // the device is unplugged
+        handle.set_connected(false);
+        let report = can.self_test(MOCK_CHANNEL.into(), Duration::from_millis(50));
+        assert!(matches!(report, Err(Error::InvalidParam(_))), "{:?}", report);
+        can.stop();
+
+        // the transmitting only
+        let (a, peer) = VirtualBus::pair();
+        let can = SyncCan::new(NoLoopback(a));
+        let report = can.self_test(MOCK_CHANNEL.into(), Duration::from_secs(1))?;
+        assert!(report.is_passed() && report.transmitted);
+        assert!(!report.capabilities.loopback);
+        assert_eq!((report.received, report.round_trip), (None, None));
+        assert_eq!(peer.pending(), 1);
+
+        // the transmitting failed
+        let (a, _peer) = VirtualBus::pair();
+        a.set_bus_state(MOCK_CHANNEL, BusState::BusOff);
+        let can = SyncCan::new(a);
+        let report = can.self_test(MOCK_CHANNEL.into(), Duration::from_millis(50))?;
+        assert!(!report.is_passed() && !report.transmitted);
+        assert_eq!(report.bus_state, BusState::BusOff);
+        assert_eq!(report.error, Some(Error::BusOff.to_string()));
+        assert_eq!(report.received, None);
+        Ok(())
     }
-}
 
+    #[test]
+    fn test_auto_filter() -> anyhow::Result<()> {
+        let (a, peer) = VirtualBus::pair();
+        let handle = a.clone();
+        let can = SyncCan::new(a);
+        let new_iso_tp = |tx_id, rx_id| SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id, rx_id, fid: 0x7DF },
+            can.sender(),
+            Box::new(EmptyListener),
+        );
+        let accepts = |id: u32| {
+            let filters = handle.filters(MOCK_CHANNEL);
+            filters.is_empty() || filters.iter().any(|f| f.matches(id, false))
+        };
+        assert!(!can.is_auto_filter());
+        can.auto_filter(true);
+        assert!(handle.filters(MOCK_CHANNEL).is_empty());
 
+        can.register_listener("ecu1".into(), Box::new(new_iso_tp(0x7E0, 0x7E8)));
+        assert!(!handle.filters(MOCK_CHANNEL).is_empty());
+        assert!(accepts(0x7E8) && !accepts(0x7E9) && !accepts(0x7E0));
+
+        can.register_listener("ecu2".into(), Box::new(new_iso_tp(0x7E1, 0x7E9)));
+        assert!(accepts(0x7E8) && accepts(0x7E9) && !accepts(0x7E0));
+
+        can.unregister_listener("ecu1".into());
+        assert!(!accepts(0x7E8) && accepts(0x7E9));
+        // the filtered frames aren't delivered to the device
+        let mut frame = CanMessage::new(Id::Standard(0x7E8), &[0x02, 0x10, 0x01]).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        peer.transmit(frame, None)?;
+        assert_eq!(handle.pending(), 0);
+
+        // a listener receiving all the frames
+        can.register_listener("echo".into(), Box::new(EchoListener::default()));
+        assert!(handle.filters(MOCK_CHANNEL).is_empty());
+        can.unregister_listener("echo".into());
+        assert!(!accepts(0x7E8) && accepts(0x7E9));
+
+        can.auto_filter(false);
+        assert!(handle.filters(MOCK_CHANNEL).is_empty());
+        can.register_listener("ecu1".into(), Box::new(new_iso_tp(0x7E0, 0x7E8)));
+        assert!(handle.filters(MOCK_CHANNEL).is_empty());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_register_during_transfer() -> anyhow::Result<()> {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        const TRANSFERS: usize = 20;
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        let events = EventListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(events.clone()),
+        );
+        let records = ErrorListener::default();
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server));
+        server_can.register_listener("records".into(), Box::new(records.clone()));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let stopped = Arc::new(AtomicBool::new(false));
+        let churns = [client_can.clone(), server_can.clone()].map(|can| {
+            let stopped = stopped.clone();
+            spawn(move || {
+                let mut count = 0;
+                while !stopped.load(Ordering::Acquire) {
+                    can.register_listener(format!("churn{}", count % 8), Box::new(EchoListener::default()));
+                    can.unregister_listener(format!("churn{}", (count + 4) % 8));
+                    count += 1;
+                    sleep(Duration::from_micros(50));
+                }
+                count
+            })
+        });
+
+        let received = || events.0.lock().unwrap()
+            .iter()
+            .filter(|v| matches!(v, IsoTpEvent::DataReceived { .. }))
+            .count();
+        for i in 0..TRANSFERS {
+            client.write(false, (0..0x20).collect())?;
+            let start = Instant::now();
+            while received() <= i {
+                assert!(start.elapsed() < Duration::from_secs(1), "transfer {} is blocked", i);
+                sleep(Duration::from_micros(100));
+            }
+        }
+
+        stopped.store(true, Ordering::Release);
+        for churn in churns {
+            assert!(churn.join().unwrap() > 0);
+        }
+        // the first frame and 4 consecutive frames of each transfer
+        let frames = records.0.lock().unwrap().frames.iter()
+            .filter(|f| f.id().into_bits() == 0x7E0)
+            .count();
+        assert_eq!(frames, TRANSFERS * 5);
+        assert!(client_can.listener_names().contains(&"client".to_string()));
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_transmitted_echo() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = EchoListener::default();
+        can.register_listener("echo".into(), Box::new(listener.clone()));
+        can.sync_start(100);
+
+        for data in [hex!("02 10 01"), hex!("02 10 03")] {
+            let mut frame = CanMessage::new(0x7E0, &data).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            can.sender().send(frame)?;
+        }
+        let start = Instant::now();
+        while listener.0.lock().unwrap().len() < 2 && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+
+        let frames = listener.0.lock().unwrap().clone();
+        assert_eq!(frames.len(), 2);
+        assert_eq!(frames[0].data(), hex!("02 10 01"));
+        assert_eq!(frames[1].data(), hex!("02 10 03"));
+        assert!(frames.iter().all(|f| f.timestamp() > 0 && f.direct() == Direct::Transmit));
+
+        can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_transmit_wakeup() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = EchoListener::default();
+        can.register_listener("echo".into(), Box::new(listener.clone()));
+        // the loop is waiting for the frames long before the interval
+        can.sync_start(200_000);
+        sleep(Duration::from_millis(10));
+
+        for i in 0..5 {
+            let mut frame = CanMessage::new(0x7E0, &[0x02, 0x3E, i]).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            can.sender().send(frame)?;
+            sleep(Duration::from_millis(5));
+        }
+        sleep(Duration::from_millis(10));
+
+        let frames = listener.0.lock().unwrap().clone();
+        assert_eq!(frames.len(), 5);
+        assert!(frames.iter().enumerate().all(|(i, f)| f.data()[2] == i as u8));
+
+        can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_transmit_now() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = EchoListener::default();
+        can.register_listener("echo".into(), Box::new(listener.clone()));
+        can.sync_start(100);
+
+        // the queue is full while the transmit loop is paused
+        can.pause(false);
+        for i in 0..100 {
+            let mut frame = CanMessage::new(0x7E0, &[0x02, 0x3E, i]).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            can.sender().send(frame)?;
+        }
+        let mut urgent = CanMessage::new(0x7E8, &hex!("30 00 00")).unwrap();
+        urgent.set_channel(MOCK_CHANNEL.into());
+        can.transmit_now(urgent, None)?;
+        assert_eq!(b.pending(), 1);
+        let echoes = listener.0.lock().unwrap().clone();
+        assert!(matches!(echoes.as_slice(), [v] if v.id().into_bits() == 0x7E8 && v.direct() == Direct::Transmit));
+
+        can.resume();
+        let start = Instant::now();
+        while b.pending() < 101 && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+        let frames = b.receive(MOCK_CHANNEL.into(), None)?;
+        assert_eq!(frames.len(), 101);
+        assert_eq!(frames[0].id().into_bits(), 0x7E8);
+        assert!(frames[1..].iter().enumerate().all(|(i, f)| f.data()[2] == i as u8));
+
+        // the failure is returned and notified
+        let mut unknown = CanMessage::new(0x7E8, &hex!("30 00 00")).unwrap();
+        unknown.set_channel("unknown".into());
+        assert!(matches!(can.transmit_now(unknown, None), Err(Error::InvalidParam(_))));
+
+        can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_bus_off() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let handle = a.clone();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(EmptyListener),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        server_can.register_listener("server".into(), Box::new(server));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+        sleep(Duration::from_millis(5));
+        assert_eq!(client_can.bus_state(&MOCK_CHANNEL.to_string()), BusState::ErrorActive);
+
+        // hold the flow control to keep the transfer active
+        server_can.pause(false);
+        let writer = spawn(move || client.write(false, (0..0x40).collect()));
+        sleep(Duration::from_millis(10));
+        handle.set_bus_state(MOCK_CHANNEL, BusState::BusOff);
+        assert_eq!(writer.join().unwrap(), Err(Error::BusOff));
+        assert_eq!(client_can.bus_state(&MOCK_CHANNEL.to_string()), BusState::BusOff);
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_shutdown() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            can.sender(),
+            Box::new(EmptyListener),
+        );
+        can.register_listener("client".into(), Box::new(client.clone()));
+        can.sync_start(100);
+
+        // no flow control is replied, the writing waits for N_Bs
+        let writer = {
+            let client = client.clone();
+            spawn(move || client.write(false, (0..0x40).collect()))
+        };
+        let start = Instant::now();
+        while !client.state().contains(IsoTpState::WaitFlowCtrl) && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+        assert!(client.state().contains(IsoTpState::WaitFlowCtrl));
+
+        let start = Instant::now();
+        spawn(move || can.stop()).join().unwrap();
+        assert_eq!(writer.join().unwrap(), Err(Error::Shutdown));
+        // interrupted instead of waiting N_Bs out
+        assert!(start.elapsed() < Duration::from_millis(TIMEOUT_BS_ISO15765_2 as u64 / 2), "{:?}", start.elapsed());
+
+        // the later writing fails fast instead of queueing to the stopped loops
+        assert_eq!(client.write(false, vec![0x3E, 0x00]), Err(Error::Shutdown));
+        assert!(client.is_idle());
+        Ok(())
+    }
+
+    /// Transfer a request with a [`SlowListener`] called by `policy` before the session of the server,
+    /// returns the time the request took to be received.
+    fn slow_transfer(policy: ExecutionPolicy) -> anyhow::Result<(Duration, SyncCan<MockDriver, String, CanMessage>, SlowListener)> {
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        let logger = SlowListener::default();
+        assert!(server_can.register_listener_with("logger".into(), Box::new(logger.clone()), policy));
+        let server_events = EventListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_events.clone()),
+        )
+            .with_flow_control(FlowControlContext::new(FlowControlState::Continues, 0, 0)?);
+        server_can.register_listener_with("server".into(), Box::new(server), ExecutionPolicy::Inline);
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let request = (0..0x40).collect::<Vec<u8>>();
+        let start = Instant::now();
+        client.write(false, request.clone())?;
+        let data = server_events.wait_data(Duration::from_secs(2));
+        let elapsed = start.elapsed();
+        client_can.stop();
+        assert_eq!(data, Some(request));
+
+        Ok((elapsed, server_can, logger))
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_offloaded_listener() -> anyhow::Result<()> {
+        // the logger delays each receiving by 50ms inline
+        let (inline, mut server_can, _) = slow_transfer(ExecutionPolicy::Inline)?;
+        server_can.stop();
+        let (offloaded, mut server_can, logger) = slow_transfer(ExecutionPolicy::Offloaded { capacity: 2 })?;
+        assert!(offloaded * 2 < inline, "offloaded: {:?}, inline: {:?}", offloaded, inline);
+
+        // the callbacks beyond the capacity are dropped, the ones queued are executed in order
+        assert!(server_can.offload_dropped("logger").is_some_and(|v| v > 0));
+        assert_eq!(server_can.offload_dropped("server"), None);
+        // the listener registered is reached through the wrapper
+        assert!(server_can.get_listener::<SlowListener>("logger").is_some());
+        assert!(server_can.get_listener::<SlowListener>("server").is_none());
+        let start = Instant::now();
+        while logger.0.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+        let logged = logger.0.lock().unwrap().clone();
+        assert_eq!(logged.first(), Some(&0x10));
+        assert!(logged.windows(2).all(|v| v[0] < v[1]), "{:02X?}", logged);
+
+        server_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_error_frame() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = ErrorListener::default();
+        can.register_listener("error".into(), Box::new(listener.clone()));
+        can.sync_start(100);
+        sleep(Duration::from_millis(5));
+
+        let mut frame = CanMessage::from_socketcan_id(0x2000_0040, &[0; 8]).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        b.transmit(frame, None)?;
+        let mut frame = CanMessage::new(0x7E8, &hex!("02 50 01")).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        b.transmit(frame, None)?;
+        sleep(Duration::from_millis(10));
+        can.stop();
+
+        let ErrorRecords { frames, errors, states } = listener.0.lock().unwrap().clone();
+        // the error frames are not passed to `on_frame_received`
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), hex!("02 50 01"));
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].class, ErrorClass::BUS_OFF);
+        // the mock device reports error active again when polled
+        assert_eq!(states, [BusState::ErrorActive, BusState::BusOff, BusState::ErrorActive]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_blocking_receive() -> anyhow::Result<()> {
+        let polling = max_latency(ReceiveMode::Polling)?;
+        let blocking = max_latency(ReceiveMode::Blocking { timeout_ms: 10 })?;
+        assert!(polling > Duration::from_millis(5), "polling latency: {:?}", polling);
+        assert!(blocking < Duration::from_millis(5), "blocking latency: {:?}", blocking);
+        Ok(())
+    }
+
+    #[test]
+    fn test_cyclic() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = EchoListener::default();
+        can.register_listener("echo".into(), Box::new(listener.clone()));
+        can.sync_start(100);
+
+        let mut frame = CanMessage::new(0x7DF, &hex!("02 3E 80")).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        let handle = can.add_cyclic("tester present".into(), frame, Duration::from_millis(10));
+        assert_eq!(handle.name(), "tester present");
+        sleep(Duration::from_millis(105));
+
+        // the one-shot frames are interleaved
+        let mut frame = CanMessage::new(0x7E0, &hex!("02 10 01")).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        can.sender().send(frame)?;
+
+        let count = listener.0.lock().unwrap().len();
+        assert!(can.update_cyclic(&handle, &hex!("02 3E 00")));
+        assert!(!can.update_cyclic(&handle, &[0x55; 65]));
+        sleep(Duration::from_millis(50));
+        assert!(can.remove_cyclic(&handle));
+        assert!(!can.remove_cyclic(&handle));
+        assert!(!can.update_cyclic(&handle, &hex!("02 3E 00")));
+        sleep(Duration::from_millis(20));
+        let frames = listener.0.lock().unwrap().clone();
+        can.stop();
+
+        let (cyclic, others): (Vec<_>, Vec<_>) = frames.into_iter()
+            .partition(|f| f.id().into_bits() == 0x7DF);
+        assert_eq!(others.len(), 1);
+        assert_eq!(others[0].data(), hex!("02 10 01"));
+
+        // period accuracy
+        let first = cyclic.first().unwrap().timestamp();
+        let last = cyclic.last().unwrap().timestamp();
+        let average = (last - first) as f64 / (cyclic.len() - 1) as f64;
+        assert!((average - 10.).abs() < 1., "average period: {}ms", average);
+        assert!(cyclic.windows(2).all(|v| v[1].timestamp() - v[0].timestamp() < 20));
+
+        // the update takes effect on the next cycle, the frame emitted while updating excepted
+        let updated = cyclic.iter()
+            .position(|f| f.data() == hex!("02 3E 00"))
+            .unwrap();
+        assert!(updated <= count + 1, "updated at {}, {} frames before", updated, count);
+        assert!(cyclic[..updated].iter().all(|f| f.data() == hex!("02 3E 80")));
+        assert!(cyclic[updated..].iter().all(|f| f.data() == hex!("02 3E 00")));
+        Ok(())
+    }
+
+    #[test]
+    fn test_tx_priority() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let listener = EchoListener::default();
+        can.register_listener("echo".into(), Box::new(listener.clone()));
+        can.set_tx_priority(Some(TxPriority {
+            priority: |f: &CanMessage| (f.id().into_bits() >> 26) as u8 & 0x07,
+            aging: Duration::ZERO,
+        }));
+        can.sync_start(100);
+
+        // queued while paused, transmitted by priority and FIFO in the same priority
+        can.pause(false);
+        sleep(Duration::from_millis(5));
+        let ids = [0x18FEF100, 0x1CEBFF00, 0x0CF00400, 0x1CEBFF01, 0x18FEF101, 0x0CF00401];
+        for (i, id) in ids.into_iter().enumerate() {
+            let mut frame = CanMessage::new(Id::Extended(id), &[i as u8]).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            can.sender().send(frame)?;
+        }
+        can.resume();
+        sleep(Duration::from_millis(20));
+
+        // FIFO when disabled
+        can.set_tx_priority(None);
+        can.pause(false);
+        sleep(Duration::from_millis(5));
+        for id in [0x18FEF100, 0x0CF00400] {
+            let mut frame = CanMessage::new(Id::Extended(id), &[0xFF]).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            can.sender().send(frame)?;
+        }
+        can.resume();
+        sleep(Duration::from_millis(20));
+        can.stop();
+
+        let wire = listener.0.lock().unwrap().iter()
+            .map(|f| f.id().into_bits())
+            .collect::<Vec<_>>();
+        assert_eq!(wire, [
+            0x0CF00400, 0x0CF00401, 0x18FEF100, 0x18FEF101, 0x1CEBFF00, 0x1CEBFF01,
+            0x18FEF100, 0x0CF00400,
+        ]);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_listener_panic() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+        // the client receives the flow control only
+        client_can.set_max_listener_panics(Some(1));
+        assert_eq!(client_can.max_listener_panics(), Some(1));
+
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        let server_data = DataListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_data.clone()),
+        );
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        client_can.register_listener("panic".into(), Box::new(PanicListener));
+        server_can.register_listener("server".into(), Box::new(server));
+        server_can.register_listener("panic".into(), Box::new(PanicListener));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        let request = (0..0x40).collect::<Vec<u8>>();
+        client.write(false, request.clone())?;
+        sleep(Duration::from_millis(10));
+        assert_eq!(server_data.0.lock().unwrap().take(), Some(request));
+
+        // the server keeps the panicking listener, the client unregisters it after the panic
+        assert!(server_can.listener_panics() > 1);
+        assert!(server_can.listener_names().contains(&"panic".to_string()));
+        assert_eq!(client_can.listener_panics(), 1);
+        assert_eq!(client_can.listener_names(), vec!["client".to_string()]);
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_reconnect() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let handle = a.clone();
+        let mut client_can = SyncCan::new(a);
+        let mut server_can = SyncCan::new(b);
+        client_can.set_reconnect_policy(Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(8),
+            max_attempts: None,
+        }));
+
+        let client = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF },
+            client_can.sender(),
+            Box::new(EmptyListener),
+        );
+        let server_data = DataListener::default();
+        let server = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            server_can.sender(),
+            Box::new(server_data.clone()),
+        );
+        let connection = ConnectionListener::default();
+        client_can.register_listener("client".into(), Box::new(client.clone()));
+        client_can.register_listener("connection".into(), Box::new(connection.clone()));
+        server_can.register_listener("server".into(), Box::new(server));
+        client_can.sync_start(100);
+        server_can.sync_start(100);
+
+        // the request is queued while unplugged
+        handle.set_connected(false);
+        sleep(Duration::from_millis(20));
+        assert_eq!(connection.0.lock().unwrap().as_slice(), [false]);
+        let request = (0..0x40).collect::<Vec<u8>>();
+        let data = request.clone();
+        let writer = spawn(move || client.write(false, data));
+        sleep(Duration::from_millis(20));
+        assert!(handle.is_closed());
+
+        handle.set_connected(true);
+        writer.join().unwrap()?;
+        sleep(Duration::from_millis(10));
+        assert_eq!(server_data.0.lock().unwrap().take(), Some(request));
+        assert_eq!(connection.0.lock().unwrap().as_slice(), [false, true]);
+        assert_eq!(client_can.listener_names().len(), 2);
+
+        client_can.stop();
+        server_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_reconnect_give_up() -> anyhow::Result<()> {
+        let (a, _b) = VirtualBus::pair();
+        let handle = a.clone();
+        let mut can = SyncCan::new(a);
+        can.set_reconnect_policy(Some(ReconnectPolicy {
+            initial_delay: Duration::from_millis(1),
+            max_delay: Duration::from_millis(2),
+            max_attempts: Some(3),
+        }));
+        let connection = ConnectionListener::default();
+        can.register_listener("connection".into(), Box::new(connection.clone()));
+        can.sync_start(100);
+
+        handle.set_connected(false);
+        sleep(Duration::from_millis(30));
+        handle.set_connected(true);
+        sleep(Duration::from_millis(10));
+        assert!(handle.is_closed());
+        assert_eq!(connection.0.lock().unwrap().as_slice(), [false]);
+
+        can.stop();
+        Ok(())
+    }
+}
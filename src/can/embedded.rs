@@ -0,0 +1,349 @@
+//! Interoperability with the `embedded-can` traits.
+//!
+//! `embedded-can` has no CAN-FD nor error frame, these are not supported by the conversions.
+
+use std::fmt::{Display, Formatter};
+use std::time::{SystemTime, UNIX_EPOCH};
+use embedded_can::{ExtendedId, StandardId};
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::error::Error;
+use crate::device::Channel;
+
+impl From<embedded_can::Id> for Id {
+    #[inline]
+    fn from(val: embedded_can::Id) -> Self {
+        match val {
+            embedded_can::Id::Standard(v) => Self::Standard(v.as_raw()),
+            embedded_can::Id::Extended(v) => Self::Extended(v.as_raw()),
+        }
+    }
+}
+
+impl TryFrom<Id> for embedded_can::Id {
+    type Error = Error;
+
+    fn try_from(val: Id) -> Result<Self, Self::Error> {
+        match val {
+            Id::Standard(v) => StandardId::new(v).map(Self::Standard),
+            Id::Extended(v) => ExtendedId::new(v).map(Self::Extended),
+        }
+        .ok_or(Error::InvalidParam(format!("invalid CAN id: {:?}", val)))
+    }
+}
+
+/// The default channel of [`EmbeddedFrameAdapter`], `embedded-can` frames have no channel.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct EmbeddedChannel;
+
+impl Display for EmbeddedChannel {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "0")
+    }
+}
+
+/// Adapt an `embedded-can` frame to [`Frame`].
+///
+/// The channel type `C` is a marker to distinguish the buses, [`EmbeddedChannel`] by default.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EmbeddedFrameAdapter<T, C = EmbeddedChannel> {
+    frame: T,
+    timestamp: u64,
+    channel: C,
+    direct: Direct,
+}
+
+impl<T: embedded_can::Frame, C: Default> From<T> for EmbeddedFrameAdapter<T, C> {
+    #[inline]
+    fn from(frame: T) -> Self {
+        Self {
+            frame,
+            timestamp: Default::default(),
+            channel: Default::default(),
+            direct: Default::default(),
+        }
+    }
+}
+
+impl<T, C> EmbeddedFrameAdapter<T, C> {
+    #[inline]
+    pub fn inner(&self) -> &T {
+        &self.frame
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> T {
+        self.frame
+    }
+}
+
+impl<T, C> Frame for EmbeddedFrameAdapter<T, C>
+where
+    T: embedded_can::Frame + Send + Sync,
+    C: Channel + Default,
+{
+    type Channel = C;
+
+    #[inline]
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    #[inline]
+    fn id(&self) -> Id {
+        self.frame.id().into()
+    }
+
+    #[inline]
+    fn is_can_fd(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn is_remote(&self) -> bool {
+        self.frame.is_remote_frame()
+    }
+
+    #[inline]
+    fn is_extended(&self) -> bool {
+        self.frame.is_extended()
+    }
+
+    #[inline]
+    fn direct(&self) -> Direct {
+        self.direct
+    }
+
+    #[inline]
+    fn is_bitrate_switch(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn is_error_frame(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn is_esi(&self) -> bool {
+        false
+    }
+
+    #[inline]
+    fn channel(&self) -> Self::Channel {
+        self.channel.clone()
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        if self.frame.is_remote_frame() {
+            return &[];
+        }
+
+        self.frame.data()
+    }
+
+    #[inline]
+    fn dlc(&self) -> Option<usize> {
+        Some(self.frame.dlc())
+    }
+
+    #[inline]
+    fn length(&self) -> usize {
+        self.frame.dlc()
+    }
+}
+
+impl<T, C> FrameMut for EmbeddedFrameAdapter<T, C>
+where
+    T: embedded_can::Frame + Send + Sync,
+    C: Channel + Default,
+{
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let id = embedded_can::Id::try_from(id.into()).ok()?;
+        T::new(id, data).map(Self::from)
+    }
+
+    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self> {
+        let id = embedded_can::Id::try_from(id.into()).ok()?;
+        T::new_remote(id, len).map(Self::from)
+    }
+
+    /// Set the timestamp, the current system time(ms) is used when `value` is `None`.
+    #[inline]
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self {
+        self.timestamp = value.unwrap_or_else(|| {
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|v| v.as_millis() as u64)
+                .unwrap_or_default()
+        });
+        self
+    }
+
+    /// CAN-FD is not supported, this is a no-op.
+    #[inline]
+    fn set_can_fd(&mut self, _: bool) -> &mut Self {
+        self
+    }
+
+    #[inline]
+    fn set_direct(&mut self, direct: Direct) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    /// CAN-FD is not supported, this is a no-op.
+    #[inline]
+    fn set_bitrate_switch(&mut self, _: bool) -> &mut Self {
+        self
+    }
+
+    /// Error frame is not supported, this is a no-op.
+    #[inline]
+    fn set_error_frame(&mut self, _: bool) -> &mut Self {
+        self
+    }
+
+    /// CAN-FD is not supported, this is a no-op.
+    #[inline]
+    fn set_esi(&mut self, _: bool) -> &mut Self {
+        self
+    }
+
+    #[inline]
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self {
+        self.channel = value;
+        self
+    }
+}
+
+impl<T, C> Display for EmbeddedFrameAdapter<T, C>
+where
+    T: embedded_can::Frame + Send + Sync + 'static,
+    C: Channel + Default,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        <dyn Frame<Channel = C> as Display>::fmt(self, f)
+    }
+}
+
+/// Convert a [`Frame`] to an `embedded-can` frame.
+///
+/// Returns [`Error::Unsupported`] for the CAN-FD and error frames.
+pub fn to_embedded_frame<T, F>(frame: &F) -> Result<T, Error>
+where
+    T: embedded_can::Frame,
+    F: Frame,
+{
+    if frame.is_can_fd() {
+        return Err(Error::Unsupported("CAN-FD frame in embedded-can".into()));
+    }
+    if frame.is_error_frame() {
+        return Err(Error::Unsupported("error frame in embedded-can".into()));
+    }
+
+    let id = embedded_can::Id::try_from(frame.id())?;
+    let result = if frame.is_remote() {
+        T::new_remote(id, frame.length())
+    }
+    else {
+        T::new(id, frame.data())
+    };
+
+    result.ok_or(Error::ConvertError { src: "frame", target: "embedded-can frame" })
+}
+
+#[cfg(test)]
+mod tests {
+    use embedded_can::Frame as _;
+    use hex_literal::hex;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use crate::error::Error;
+    use super::{to_embedded_frame, EmbeddedFrameAdapter};
+
+    /// A minimal `embedded-can` frame.
+    #[derive(Debug, Clone, PartialEq, Eq)]
+    struct TestFrame {
+        id: embedded_can::Id,
+        remote: bool,
+        dlc: usize,
+        data: Vec<u8>,
+    }
+
+    impl embedded_can::Frame for TestFrame {
+        fn new(id: impl Into<embedded_can::Id>, data: &[u8]) -> Option<Self> {
+            if data.len() > 8 {
+                return None;
+            }
+            Some(Self { id: id.into(), remote: false, dlc: data.len(), data: data.to_vec() })
+        }
+
+        fn new_remote(id: impl Into<embedded_can::Id>, dlc: usize) -> Option<Self> {
+            if dlc > 8 {
+                return None;
+            }
+            Some(Self { id: id.into(), remote: true, dlc, data: vec![] })
+        }
+
+        fn is_extended(&self) -> bool {
+            matches!(self.id, embedded_can::Id::Extended(_))
+        }
+
+        fn is_remote_frame(&self) -> bool {
+            self.remote
+        }
+
+        fn id(&self) -> embedded_can::Id {
+            self.id
+        }
+
+        fn dlc(&self) -> usize {
+            self.dlc
+        }
+
+        fn data(&self) -> &[u8] {
+            &self.data
+        }
+    }
+
+    #[test]
+    fn test_round_trip() -> anyhow::Result<()> {
+        // standard
+        let frame = CanMessage::new(0x7DF, &hex!("02 10 01")).unwrap();
+        let embedded: TestFrame = to_embedded_frame(&frame)?;
+        assert!(!embedded.is_extended());
+        assert_eq!(embedded.data(), hex!("02 10 01"));
+        let adapter: EmbeddedFrameAdapter<TestFrame> = embedded.into();
+        assert_eq!(adapter.id(), Id::Standard(0x7DF));
+        assert_eq!(adapter.data(), frame.data());
+
+        // extended
+        let frame = CanMessage::new(Id::Extended(0x18DA00F1), &hex!("02 3E 00")).unwrap();
+        let embedded: TestFrame = to_embedded_frame(&frame)?;
+        assert!(embedded.is_extended());
+        let adapter: EmbeddedFrameAdapter<TestFrame> = embedded.into();
+        assert_eq!(adapter.id(), Id::Extended(0x18DA00F1));
+        assert!(Frame::is_extended(&adapter));
+
+        // remote
+        let frame = CanMessage::new_remote(0x123, 4).unwrap();
+        let embedded: TestFrame = to_embedded_frame(&frame)?;
+        assert!(embedded.is_remote_frame());
+        let adapter: EmbeddedFrameAdapter<TestFrame> = embedded.into();
+        assert!(adapter.is_remote());
+        assert_eq!(adapter.length(), 4);
+        assert!(adapter.data().is_empty());
+
+        let adapter = EmbeddedFrameAdapter::<TestFrame>::new(0x7E0, &hex!("02 10 03")).unwrap();
+        assert_eq!(adapter.inner().data(), hex!("02 10 03"));
+        assert!(EmbeddedFrameAdapter::<TestFrame>::new(0x7E0, &[0x55; 12]).is_none());
+
+        // CAN-FD
+        let frame = CanMessage::new(0x7E0, &[0x55; 12]).unwrap();
+        assert_eq!(to_embedded_frame::<TestFrame, _>(&frame).err(), Some(Error::Unsupported("CAN-FD frame in embedded-can".into())));
+        Ok(())
+    }
+}
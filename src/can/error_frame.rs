@@ -0,0 +1,356 @@
+//! Decode the error frames in the SocketCAN layout(`linux/can/error.h`).
+//!
+//! The error classes are in the identifier, the details of each class are in the 8 data bytes.
+
+use alloc::{format, string::String, vec, vec::Vec};
+use core::fmt::{Display, Formatter};
+use bitflags::bitflags;
+use crate::device::BusState;
+
+bitflags! {
+    /// The error classes in the identifier of an error frame.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(transparent)]
+    pub struct ErrorClass: u32 {
+        /// TX timeout by the netdevice driver.
+        const TX_TIMEOUT = 0x0000_0001;
+        /// Lost arbitration, the bit position is in data[0].
+        const LOST_ARBITRATION = 0x0000_0002;
+        /// Controller problems, see [`ControllerError`] in data[1].
+        const CONTROLLER = 0x0000_0004;
+        /// Protocol violations, see [`ProtocolError`] in data[2] and [`ErrorLocation`] in data[3].
+        const PROTOCOL = 0x0000_0008;
+        /// Transceiver status in data[4].
+        const TRANSCEIVER = 0x0000_0010;
+        /// Received no ACK on transmission.
+        const NO_ACK = 0x0000_0020;
+        const BUS_OFF = 0x0000_0040;
+        /// Bus error, may flood!
+        const BUS_ERROR = 0x0000_0080;
+        /// The controller is restarted.
+        const RESTARTED = 0x0000_0100;
+        /// The TX and RX error counters are in data[6] and data[7].
+        const COUNTERS = 0x0000_0200;
+    }
+}
+
+bitflags! {
+    /// The controller problems in data[1].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(transparent)]
+    pub struct ControllerError: u8 {
+        const RX_OVERFLOW = 0x01;
+        const TX_OVERFLOW = 0x02;
+        /// Reached the warning level for RX errors.
+        const RX_WARNING = 0x04;
+        /// Reached the warning level for TX errors.
+        const TX_WARNING = 0x08;
+        /// Reached the error passive status for RX.
+        const RX_PASSIVE = 0x10;
+        /// Reached the error passive status for TX.
+        const TX_PASSIVE = 0x20;
+        /// Recovered to the error active state.
+        const ACTIVE = 0x40;
+    }
+}
+
+bitflags! {
+    /// The protocol violation types in data[2].
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+    #[repr(transparent)]
+    pub struct ProtocolError: u8 {
+        /// Single bit error.
+        const BIT = 0x01;
+        /// Frame format error.
+        const FORM = 0x02;
+        /// Bit stuffing error.
+        const STUFF = 0x04;
+        /// Unable to send the dominant bit.
+        const BIT0 = 0x08;
+        /// Unable to send the recessive bit.
+        const BIT1 = 0x10;
+        /// Bus overload.
+        const OVERLOAD = 0x20;
+        /// Active error announcement.
+        const ACTIVE = 0x40;
+        /// The error occurred on transmission.
+        const TX = 0x80;
+    }
+}
+
+const CONTROLLER_NAMES: [(ControllerError, &str); 7] = [
+    (ControllerError::RX_OVERFLOW, "rx-overflow"),
+    (ControllerError::TX_OVERFLOW, "tx-overflow"),
+    (ControllerError::RX_WARNING, "rx-error-warning"),
+    (ControllerError::TX_WARNING, "tx-error-warning"),
+    (ControllerError::RX_PASSIVE, "rx-error-passive"),
+    (ControllerError::TX_PASSIVE, "tx-error-passive"),
+    (ControllerError::ACTIVE, "back-to-error-active"),
+];
+
+const PROTOCOL_NAMES: [(ProtocolError, &str); 8] = [
+    (ProtocolError::BIT, "single-bit-error"),
+    (ProtocolError::FORM, "frame-format-error"),
+    (ProtocolError::STUFF, "bit-stuffing-error"),
+    (ProtocolError::BIT0, "tx-dominant-bit-error"),
+    (ProtocolError::BIT1, "tx-recessive-bit-error"),
+    (ProtocolError::OVERLOAD, "bus-overload"),
+    (ProtocolError::ACTIVE, "active-error"),
+    (ProtocolError::TX, "error-on-tx"),
+];
+
+/// The location of a protocol violation in data[3].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash)]
+pub enum ErrorLocation {
+    #[default]
+    Unspecified,
+    StartOfFrame,
+    Id28To21,
+    Id20To18,
+    SubstituteRtr,
+    IdentifierExtension,
+    Id17To13,
+    Id12To05,
+    Id04To00,
+    Rtr,
+    Reserved1,
+    Reserved0,
+    Dlc,
+    Data,
+    CrcSequence,
+    CrcDelimiter,
+    AckSlot,
+    AckDelimiter,
+    EndOfFrame,
+    Intermission,
+    Other(u8),
+}
+
+impl From<u8> for ErrorLocation {
+    fn from(value: u8) -> Self {
+        match value {
+            0x00 => Self::Unspecified,
+            0x03 => Self::StartOfFrame,
+            0x02 => Self::Id28To21,
+            0x06 => Self::Id20To18,
+            0x04 => Self::SubstituteRtr,
+            0x05 => Self::IdentifierExtension,
+            0x07 => Self::Id17To13,
+            0x0F => Self::Id12To05,
+            0x0E => Self::Id04To00,
+            0x0C => Self::Rtr,
+            0x0D => Self::Reserved1,
+            0x09 => Self::Reserved0,
+            0x0B => Self::Dlc,
+            0x0A => Self::Data,
+            0x08 => Self::CrcSequence,
+            0x18 => Self::CrcDelimiter,
+            0x19 => Self::AckSlot,
+            0x1B => Self::AckDelimiter,
+            0x1A => Self::EndOfFrame,
+            0x12 => Self::Intermission,
+            v => Self::Other(v),
+        }
+    }
+}
+
+impl Display for ErrorLocation {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let name = match self {
+            Self::Unspecified => "unspecified",
+            Self::StartOfFrame => "start-of-frame",
+            Self::Id28To21 => "id.28-to-id.21",
+            Self::Id20To18 => "id.20-to-id.18",
+            Self::SubstituteRtr => "substitute-rtr-bit",
+            Self::IdentifierExtension => "identifier-extension",
+            Self::Id17To13 => "id.17-to-id.13",
+            Self::Id12To05 => "id.12-to-id.05",
+            Self::Id04To00 => "id.04-to-id.00",
+            Self::Rtr => "rtr-bit",
+            Self::Reserved1 => "reserved-bit-1",
+            Self::Reserved0 => "reserved-bit-0",
+            Self::Dlc => "data-length-code",
+            Self::Data => "data-section",
+            Self::CrcSequence => "crc-sequence",
+            Self::CrcDelimiter => "crc-delimiter",
+            Self::AckSlot => "acknowledge-slot",
+            Self::AckDelimiter => "acknowledge-delimiter",
+            Self::EndOfFrame => "end-of-frame",
+            Self::Intermission => "intermission",
+            Self::Other(v) => return write!(f, "{:#04x}", v),
+        };
+        f.write_str(name)
+    }
+}
+
+/// The decoded error frame, the details are only set when their class is in [`ErrorInfo::class`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ErrorInfo {
+    pub class: ErrorClass,
+    /// The bit position of the lost arbitration, `None` if unspecified.
+    pub arbitration_bit: Option<u8>,
+    pub controller: ControllerError,
+    pub protocol: ProtocolError,
+    pub location: ErrorLocation,
+    /// The transceiver status defined by the driver.
+    pub transceiver: u8,
+    /// The TX and RX error counters.
+    pub counters: Option<(u8, u8)>,
+}
+
+impl ErrorInfo {
+    /// Decode the error frame, `id_bits` is the identifier with the error classes,
+    /// the missing bytes of `data` are 0.
+    pub fn parse(id_bits: u32, data: &[u8]) -> Self {
+        let class = ErrorClass::from_bits_truncate(id_bits);
+        let byte = |i: usize| data.get(i).copied().unwrap_or_default();
+        let with = |flag: ErrorClass, i: usize| if class.contains(flag) { byte(i) } else { 0 };
+
+        Self {
+            class,
+            arbitration_bit: match with(ErrorClass::LOST_ARBITRATION, 0) {
+                0 => None,
+                v => Some(v),
+            },
+            controller: ControllerError::from_bits_retain(with(ErrorClass::CONTROLLER, 1)),
+            protocol: ProtocolError::from_bits_retain(with(ErrorClass::PROTOCOL, 2)),
+            location: ErrorLocation::from(with(ErrorClass::PROTOCOL, 3)),
+            transceiver: with(ErrorClass::TRANSCEIVER, 4),
+            counters: class.contains(ErrorClass::COUNTERS)
+                .then(|| (byte(6), byte(7))),
+        }
+    }
+
+    /// The bus state indicated by the error frame, `None` if the state isn't changed.
+    pub fn bus_state(&self) -> Option<BusState> {
+        if self.class.contains(ErrorClass::BUS_OFF) {
+            Some(BusState::BusOff)
+        }
+        else if self.controller.intersects(ControllerError::RX_PASSIVE | ControllerError::TX_PASSIVE) {
+            Some(BusState::ErrorPassive)
+        }
+        else if self.class.contains(ErrorClass::RESTARTED) || self.controller.contains(ControllerError::ACTIVE) {
+            Some(BusState::ErrorActive)
+        }
+        else {
+            None
+        }
+    }
+}
+
+impl Display for ErrorInfo {
+    /// Output the error classes with the details like `candump -e`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        fn names<T: bitflags::Flags + Copy>(value: T, names: &[(T, &str)]) -> String {
+            names.iter()
+                .filter(|(flag, _)| value.contains(*flag))
+                .map(|(_, name)| *name)
+                .collect::<Vec<_>>()
+                .join(",")
+        }
+
+        let mut parts = vec![];
+        for class in self.class.iter() {
+            let part = match class {
+                ErrorClass::TX_TIMEOUT => "tx-timeout".into(),
+                ErrorClass::LOST_ARBITRATION => match self.arbitration_bit {
+                    Some(v) => format!("lost-arbitration{{at bit {}}}", v),
+                    None => "lost-arbitration".into(),
+                },
+                ErrorClass::CONTROLLER => format!("controller-problem{{{}}}", names(self.controller, &CONTROLLER_NAMES)),
+                ErrorClass::PROTOCOL => format!("protocol-violation{{{{{}}}{{{}}}}}",
+                    names(self.protocol, &PROTOCOL_NAMES), self.location),
+                ErrorClass::TRANSCEIVER => format!("transceiver-status{{{:#04x}}}", self.transceiver),
+                ErrorClass::NO_ACK => "no-acknowledgement-on-tx".into(),
+                ErrorClass::BUS_OFF => "bus-off".into(),
+                ErrorClass::BUS_ERROR => "bus-error".into(),
+                ErrorClass::RESTARTED => "restarted-after-bus-off".into(),
+                _ => match self.counters {
+                    Some((tx, rx)) => format!("error-counter-tx-rx{{{{{}}}{{{}}}}}", tx, rx),
+                    None => continue,
+                },
+            };
+            parts.push(part);
+        }
+
+        if parts.is_empty() {
+            f.write_str("no-error")
+        }
+        else {
+            f.write_str(&parts.join(", "))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::device::BusState;
+    use super::{ControllerError, ErrorClass, ErrorInfo, ErrorLocation, ProtocolError};
+
+    #[test]
+    fn test_controller() {
+        // can0  20000204   [8]  00 08 00 00 00 00 60 00
+        let info = ErrorInfo::parse(0x2000_0204, &hex!("00 08 00 00 00 00 60 00"));
+        assert_eq!(info.class, ErrorClass::CONTROLLER | ErrorClass::COUNTERS);
+        assert_eq!(info.controller, ControllerError::TX_WARNING);
+        assert_eq!(info.counters, Some((96, 0)));
+        assert_eq!(info.bus_state(), None);
+        assert_eq!(info.to_string(), "controller-problem{tx-error-warning}, error-counter-tx-rx{{96}{0}}");
+
+        // can0  20000204   [8]  00 20 00 00 00 00 80 00
+        let info = ErrorInfo::parse(0x2000_0204, &hex!("00 20 00 00 00 00 80 00"));
+        assert_eq!(info.controller, ControllerError::TX_PASSIVE);
+        assert_eq!(info.bus_state(), Some(BusState::ErrorPassive));
+
+        // can0  20000004   [8]  00 40 00 00 00 00 00 00
+        let info = ErrorInfo::parse(0x2000_0004, &hex!("00 40 00 00 00 00 00 00"));
+        assert_eq!(info.bus_state(), Some(BusState::ErrorActive));
+        assert_eq!(info.to_string(), "controller-problem{back-to-error-active}");
+    }
+
+    #[test]
+    fn test_protocol() {
+        // no other node on the bus: can0  20000088   [8]  00 00 80 19 00 08 00 00
+        let info = ErrorInfo::parse(0x2000_0088, &hex!("00 00 80 19 00 08 00 00"));
+        assert_eq!(info.class, ErrorClass::PROTOCOL | ErrorClass::BUS_ERROR);
+        assert_eq!(info.protocol, ProtocolError::TX);
+        assert_eq!(info.location, ErrorLocation::AckSlot);
+        assert_eq!(info.counters, None);
+        assert_eq!(info.to_string(), "protocol-violation{{error-on-tx}{acknowledge-slot}}, bus-error");
+
+        // can0  2000008A   [8]  00 00 04 0A 00 00 00 00
+        let info = ErrorInfo::parse(0x2000_008A, &hex!("00 00 04 0A 00 00 00 00"));
+        assert_eq!(info.class, ErrorClass::LOST_ARBITRATION | ErrorClass::PROTOCOL | ErrorClass::BUS_ERROR);
+        assert_eq!(info.arbitration_bit, None);
+        assert_eq!((info.protocol, info.location), (ProtocolError::STUFF, ErrorLocation::Data));
+        assert_eq!(info.to_string(), "lost-arbitration, protocol-violation{{bit-stuffing-error}{data-section}}, bus-error");
+    }
+
+    #[test]
+    fn test_bus_off() {
+        // can0  20000040   [8]  00 00 00 00 00 00 00 00
+        let info = ErrorInfo::parse(0x2000_0040, &[0; 8]);
+        assert_eq!(info.bus_state(), Some(BusState::BusOff));
+        assert_eq!(info.to_string(), "bus-off");
+
+        // can0  20000100   [8]  00 00 00 00 00 00 00 00
+        let info = ErrorInfo::parse(0x2000_0100, &[0; 8]);
+        assert_eq!(info.bus_state(), Some(BusState::ErrorActive));
+        assert_eq!(info.to_string(), "restarted-after-bus-off");
+    }
+
+    #[test]
+    fn test_others() {
+        // can0  20000023   [8]  07 00 00 00 00 00 00 00
+        let info = ErrorInfo::parse(0x2000_0023, &hex!("07 00 00 00 00 00 00 00"));
+        assert_eq!(info.arbitration_bit, Some(7));
+        assert_eq!(info.to_string(), "tx-timeout, lost-arbitration{at bit 7}, no-acknowledgement-on-tx");
+
+        // the details of the classes not set are ignored, the missing bytes are 0
+        let info = ErrorInfo::parse(0x2000_0010, &hex!("07 10 00 00 05"));
+        assert_eq!((info.arbitration_bit, info.controller, info.transceiver), (None, ControllerError::empty(), 0x05));
+        assert_eq!(info.to_string(), "transceiver-status{0x05}");
+        assert_eq!(ErrorInfo::parse(0x2000_0000, &[]).to_string(), "no-error");
+    }
+}
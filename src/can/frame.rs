@@ -1,6 +1,9 @@
-use std::fmt::{Debug, Display, Formatter, Write};
+use alloc::{borrow::ToOwned, format, string::String};
+use core::fmt::{Debug, Display, Formatter, Write};
+use crate::can::AddressContext;
 use crate::can::identifier::Id;
 use crate::IsoTpFrame;
+use crate::device::Channel;
 
 #[repr(C)]
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Default)]
@@ -10,141 +13,160 @@ pub enum Direct {
     Receive,
 }
 
-/// CAN 2.0
+/// The read-only view of a CAN 2.0/CAN-FD frame, it is object-safe,
+/// so the frames of different sources can be handled as `&dyn Frame`.
+///
+/// The constructors and setters are in [`FrameMut`], the implementors of the former all-in-one trait
+/// move them into an `impl FrameMut` block without other changes.
 pub trait Frame: Send + Sync {
-    type Channel: Display;
-    
-    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self>
-    where
-        Self: Sized;
-    
-    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self>
-    where
-        Self: Sized;
-
-    fn from_iso_tp(id: impl Into<Id>, frame: impl IsoTpFrame, padding: Option<u8>) -> Option<Self>
-    where
-        Self: Sized {
-        let data = frame.encode(padding);
-        Self::new(id, data.as_slice())
-    }
+    type Channel: crate::device::Channel;
 
+    /// The timestamp in milliseconds since the UNIX epoch, 0 if not available.
     fn timestamp(&self) -> u64;
-    
-    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self
-    where
-        Self: Sized;
 
     /// Prioritizes returning J1939Id if j1939 is true.
     fn id(&self) -> Id;
-    
+
     fn is_can_fd(&self) -> bool;
-    
-    fn set_can_fd(&mut self, value: bool) -> &mut Self
-    where
-        Self: Sized;
-    
+
     fn is_remote(&self) -> bool;
-    
+
     fn is_extended(&self) -> bool;
-    
+
     fn direct(&self) -> Direct;
-    
-    fn set_direct(&mut self, direct: Direct) -> &mut Self
-    where
-        Self: Sized;
-    
+
     fn is_bitrate_switch(&self) -> bool;
-    
-    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self
-    where
-        Self: Sized;
-    
+
     fn is_error_frame(&self) -> bool;
-    
-    fn set_error_frame(&mut self, value: bool) -> &mut Self
-    where
-        Self: Sized;
 
     /// Error state indicator
     fn is_esi(&self) -> bool;
 
-    /// Set error state indicator
-    fn set_esi(&mut self, value: bool) -> &mut Self
-    where
-        Self: Sized;
-    
     fn channel(&self) -> Self::Channel;
-    
-    fn set_channel(&mut self, value: Self::Channel) -> &mut Self
-    where
-        Self: Sized;
 
     /// ensure return the actual length of data.
     fn data(&self) -> &[u8];
-    
+
+    /// Returns the DLC code(0 ~ 15) of the frame, not the byte count, e.g. 9 for 12 bytes of CAN-FD,
+    /// see [`crate::can::dlc`].
     fn dlc(&self) -> Option<usize>;
-    
+
+    /// Returns the byte count of the data, the requested length of a remote frame.
     fn length(&self) -> usize;
 }
 
-impl<T: Display> Display for dyn Frame<Channel = T> {
-    /// Output Frame as `asc` String.
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
-        let data_str = if self.is_remote() {
-            " ".to_owned()
-        } else {
-            self.data()
-                .iter()
-                .fold(String::new(), |mut out, &b| {
-                    let _ = write!(out, "{b:02x} ");
-                    out
-                })
-        };
-
-        if self.is_can_fd() {
-            let mut flags = 1 << 12;
-            write!(f, "{:.3} CANFD {} {} {} {} {} {} {} {} {} {} {} {} {} {} {} {}",
-                   self.timestamp() as f64 / 1000.,
-                   self.channel(),
-                   direct(self.direct()),
-                   // if self.is_rx() { "Rx" } else { "Tx" },
-                   format!("{: >8x}", self.id().into_bits()),
-                   if self.is_bitrate_switch() {
-                       flags |= 1 << 13;
-                       1
-                   } else { 0 },
-                   if self.is_esi() {
-                       flags |= 1 << 14;
-                       1
-                   } else { 0 },
-                   format!("{: >2}", self.dlc().unwrap_or_default()),
-                   format!("{: >2}", self.length()),
-                   data_str,
-                   format!("{: >8}", 0),       // message_duration
-                   format!("{: <4}", 0),       // message_length
-                   format!("{: >8x}", flags),
-                   format!("{: >8}", 0),       // crc
-                   format!("{: >8}", 0),       // bit_timing_conf_arb
-                   format!("{: >8}", 0),       // bit_timing_conf_data
-                   format!("{: >8}", 0),       // bit_timing_conf_ext_arb
-                   format!("{: >8}", 0),       // bit_timing_conf_ext_data
-            )
+/// The constructors and builder-style setters of a [`Frame`].
+pub trait FrameMut: Frame + Sized {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self>;
+
+    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self>;
+
+    fn from_iso_tp(id: impl Into<Id>, frame: impl IsoTpFrame, padding: Option<u8>) -> Option<Self> {
+        Self::from_iso_tp_with(id, frame, &AddressContext { padding, ..Default::default() })
+    }
+
+    /// Create the frame of the ISO-TP `frame` addressed by `ctx`, e.g. with the AE byte before the PCI.
+    fn from_iso_tp_with(id: impl Into<Id>, frame: impl IsoTpFrame, ctx: &AddressContext) -> Option<Self> {
+        let data = ctx.encode(frame);
+        Self::new(id, data.as_slice())
+    }
+
+    /// Set the timestamp in milliseconds, the current system time(0 without `std`) is used when `value` is `None`.
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self;
+
+    fn set_can_fd(&mut self, value: bool) -> &mut Self;
+
+    fn set_direct(&mut self, direct: Direct) -> &mut Self;
+
+    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self;
+
+    fn set_error_frame(&mut self, value: bool) -> &mut Self;
+
+    /// Set error state indicator
+    fn set_esi(&mut self, value: bool) -> &mut Self;
+
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self;
+}
+
+impl<C: Channel> Display for dyn Frame<Channel = C> {
+    /// Output Frame as an `asc` line with the absolute timestamp in seconds,
+    /// the precision(6 by default) sets the decimals of the timestamp, e.g. `{:.3}`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        let line = asc_line(self, self.timestamp(), f.precision().unwrap_or(6), &self.channel());
+        f.write_str(&line)
+    }
+}
+
+/// The current system time in milliseconds since the UNIX epoch, 0 without the `std` feature.
+#[inline]
+pub(crate) fn now_millis() -> u64 {
+    #[cfg(feature = "std")]
+    return std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|v| v.as_millis() as u64)
+        .unwrap_or_default();
+    #[cfg(not(feature = "std"))]
+    0
+}
+
+/// The monotonic tick in milliseconds since an anchor taken by the first call, it isn't moved by
+/// the adjustments of the system time, e.g. the timeouts supervised by the local clock.
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn tick_millis() -> u64 {
+    static ANCHOR: std::sync::OnceLock<std::time::Instant> = std::sync::OnceLock::new();
+    ANCHOR.get_or_init(std::time::Instant::now)
+        .elapsed()
+        .as_millis() as u64
+}
+
+/// The timestamp of `frame`, the current system time when the frame has no timestamp.
+#[cfg(feature = "std")]
+#[inline]
+pub(crate) fn timestamp_or_now<C: Channel>(frame: &dyn Frame<Channel = C>) -> u64 {
+    match frame.timestamp() {
+        0 => now_millis(),
+        v => v,
+    }
+}
+
+/// Format `frame` as a line of the Vector `asc` log, `time` in milliseconds is written as seconds
+/// with `precision` decimals.
+pub(crate) fn asc_line<C: Channel>(frame: &dyn Frame<Channel = C>, time: u64, precision: usize, channel: &dyn Display) -> String {
+    let time = time as f64 / 1000.;
+    let id = format!("{:X}{}", frame.id().into_bits(), if frame.is_extended() { "x" } else { "" });
+    let dlc = frame.dlc().unwrap_or_default();
+    let data = frame.data()
+        .iter()
+        .fold(String::new(), |mut out, &b| {
+            let _ = write!(out, "{b:02X} ");
+            out
+        });
+    let line = if frame.is_error_frame() {
+        format!("{:>11.*} {}  ErrorFrame", precision, time, channel)
+    }
+    else if frame.is_can_fd() {
+        let mut flags = 1 << 12;
+        if frame.is_bitrate_switch() {
+            flags |= 1 << 13;
         }
-        else {
-            write!(f, "{:.3} {} {}{: <4} {} {} {} {}",
-                   self.timestamp() as f64 / 1000.,
-                   self.channel(),
-                   format!("{: >8x}", self.id().into_bits()),
-                   if self.is_extended() { "x" } else { "" },
-                   direct(self.direct()),
-                   // if self.is_rx() { "Rx" } else { "Tx" },
-                   if self.is_remote() { "r" } else { "d" },
-                   format!("{: >2}", self.length()),
-                   data_str,
-            )
+        if frame.is_esi() {
+            flags |= 1 << 14;
         }
+        // message_duration, message_length, flags, crc and the bit timing configurations follow the data
+        format!("{:>11.*} CANFD {:>3} {:<4} {:>8} {} {} {:x} {:>2} {}{:>8} {:>4} {:>8X} {:>8} {:>8} {:>8} {:>8} {:>8}",
+            precision, time, channel, direct(frame.direct()), id,
+            frame.is_bitrate_switch() as u8, frame.is_esi() as u8, dlc, frame.length(), data,
+            0, 0, flags, 0, 0, 0, 0, 0)
     }
+    else if frame.is_remote() {
+        format!("{:>11.*} {}  {:<15} {:<4} r {:x}", precision, time, channel, id, direct(frame.direct()), dlc)
+    }
+    else {
+        format!("{:>11.*} {}  {:<15} {:<4} d {:x} {}", precision, time, channel, id, direct(frame.direct()), dlc, data)
+    };
+
+    line.trim_end().to_owned()
 }
 
 #[inline]
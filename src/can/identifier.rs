@@ -1,11 +1,30 @@
-use crate::can::{EFF_MASK, SFF_MASK};
+use alloc::string::String;
+use crate::can::{EFF_MASK, IdentifierFlags, SFF_MASK};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+/// The identifier of a CAN frame.
+///
+/// The identifiers are ordered by the numeric value, a standard identifier is before
+/// the extended identifier of the same value, e.g. `Standard(0x7FF) > Extended(0x100)`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum Id {
     Standard(u16),
     Extended(u32),
 }
 
+impl PartialOrd for Id {
+    #[inline]
+    fn partial_cmp(&self, other: &Self) -> Option<core::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Id {
+    #[inline]
+    fn cmp(&self, other: &Self) -> core::cmp::Ordering {
+        (self.into_bits(), self.is_extended()).cmp(&(other.into_bits(), other.is_extended()))
+    }
+}
+
 unsafe impl Send for Id {}
 
 impl From<u32> for Id {
@@ -15,14 +34,26 @@ impl From<u32> for Id {
     }
 }
 
-impl Into<u32> for Id {
+impl From<Id> for u32 {
     #[inline]
-    fn into(self) -> u32 {
-        self.into_bits()
+    fn from(val: Id) -> Self {
+        val.into_bits()
     }
 }
 
 impl Id {
+    /// Create a standard identifier, `None` if `id` is greater than [`SFF_MASK`].
+    #[inline]
+    pub fn new_standard(id: u16) -> Option<Self> {
+        (id as u32 <= SFF_MASK).then_some(Self::Standard(id))
+    }
+
+    /// Create an extended identifier, `None` if `id` is greater than [`EFF_MASK`].
+    #[inline]
+    pub fn new_extended(id: u32) -> Option<Self> {
+        (id <= EFF_MASK).then_some(Self::Extended(id))
+    }
+
     #[inline]
     pub fn from_bits(bits: u32, extended: bool) -> Self {
         let bits = bits & EFF_MASK;
@@ -71,7 +102,7 @@ impl Id {
 
     #[inline]
     pub fn into_hex(self) -> String {
-        std::fmt::format(format_args!("{:08X}", self.into_bits()))
+        alloc::fmt::format(format_args!("{:08X}", self.into_bits()))
     }
 
     /// Returns this CAN Identifier as a raw 32-bit integer.
@@ -81,12 +112,37 @@ impl Id {
         self.into_bits()
     }
 
+    /// Returns the all-in-one 32-bit identifier of SocketCAN,
+    /// the `EXTENDED` flag is set by the format of this identifier regardless of `flags`.
+    #[inline]
+    #[must_use]
+    pub fn into_raw_with(self, mut flags: IdentifierFlags) -> u32 {
+        flags.set(IdentifierFlags::EXTENDED, self.is_extended());
+        self.into_bits() | flags.bits()
+    }
+
+    /// Split the all-in-one 32-bit identifier of SocketCAN,
+    /// the identifier is masked by [`EFF_MASK`] or [`SFF_MASK`] according to the `EXTENDED` flag.
+    #[inline]
+    #[must_use]
+    pub fn from_raw(raw: u32) -> (Self, IdentifierFlags) {
+        let flags = IdentifierFlags::from_bits_truncate(raw);
+        let id = if flags.contains(IdentifierFlags::EXTENDED) {
+            Self::Extended(raw & EFF_MASK)
+        }
+        else {
+            Self::Standard((raw & SFF_MASK) as u16)
+        };
+
+        (id, flags)
+    }
+
     /// Returns the Base ID part of this extended identifier.
     #[inline]
     #[must_use]
     pub fn standard_id(self) -> Self {
         match self {
-            Self::Standard(_) => self.clone(),
+            Self::Standard(_) => self,
             Self::Extended(v) => Self::Standard((v >> 18) as u16),     // ID-28 to ID-18
         }
     }
@@ -99,3 +155,36 @@ impl Id {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeMap, HashSet};
+    use super::Id;
+
+    #[test]
+    fn test_ord() {
+        assert!(Id::Extended(0x100) < Id::Standard(0x7FF));
+        assert!(Id::Standard(0x100) < Id::Extended(0x100));
+        assert_ne!(Id::Standard(0x100), Id::Extended(0x100));
+
+        let mut ids = vec![Id::Extended(0x18DAF110), Id::Standard(0x7E8), Id::Extended(0x7E8), Id::Extended(0x100), Id::Standard(0x7DF)];
+        ids.sort();
+        assert_eq!(ids, [Id::Extended(0x100), Id::Standard(0x7DF), Id::Standard(0x7E8), Id::Extended(0x7E8), Id::Extended(0x18DAF110)]);
+
+        let filters = ids.iter()
+            .map(|v| (*v, v.is_extended()))
+            .collect::<BTreeMap<_, _>>();
+        assert_eq!(filters.range(Id::Standard(0x700)..Id::Standard(0x7FF)).count(), 3);
+        assert_eq!(ids.iter().collect::<HashSet<_>>().len(), 5);
+    }
+
+    #[test]
+    fn test_new() {
+        assert_eq!(Id::new_standard(0x7FF), Some(Id::Standard(0x7FF)));
+        assert_eq!(Id::new_standard(0x800), None);
+        assert_eq!(Id::new_extended(0x1FFF_FFFF), Some(Id::Extended(0x1FFF_FFFF)));
+        assert_eq!(Id::new_extended(0x2000_0000), None);
+        // an extended identifier below 0x800 is kept
+        assert!(Id::new_extended(0x123).unwrap().is_extended());
+    }
+}
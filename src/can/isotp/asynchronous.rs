@@ -1,25 +1,63 @@
 mod listener;
 
-use std::sync::{Arc, mpsc::Sender, Mutex};
-use tokio::time::sleep;
+use arc_swap::ArcSwap;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::Sender, Mutex};
+use tokio::{sync::{Mutex as AsyncMutex, mpsc::{unbounded_channel, UnboundedSender}}, task::yield_now, time::{sleep, timeout}};
 use std::time::{Duration, Instant};
-use crate::{FlowControlContext, FlowControlState, IsoTpEvent, IsoTpEventListener, IsoTpFrame, IsoTpState, can::{Address, CanIsoTpFrame, isotp::context::IsoTpContext, frame::Frame}};
-use crate::constant::{P2_ISO14229, P2_STAR_ISO14229, TIMEOUT_AS_ISO15765_2, TIMEOUT_BR_ISO15765_2, TIMEOUT_CR_ISO15765_2};
+use crate::{AtomicState, FirstFramePolicy, FlowControlContext, FlowControlState, FrameType, IsoTpEvent, IsoTpEventListener, IsoTpFrame, IsoTpState, IsoTpVerbosity, SequenceStart, can::{Address, AddressContext, AddressFormat, AddressType, CanIsoTpFrame, identifier::Id, matcher::RxMatcher, driver::{DirectTransmit, TxGenerations}, isotp::{context::{IsoTpContext, RxStats, TxStats}, echo::TxEcho, pending::TxPending, retry::{RetryPolicy, TxRetry}, tap::{Direction, FrameTap, TapSlot}, trace}, frame::{Direct, FrameMut, tick_millis, timestamp_or_now}}};
+use crate::constant::{TIMEOUT_AS_ISO15765_2, TIMEOUT_BS_ISO15765_2, TIMEOUT_CR_ISO15765_2};
+use crate::device::{Channel, DriverCapabilities};
 use crate::error::Error;
 
 #[derive(Clone)]
 pub struct AsyncCanIsoTp<C, F> {
     pub(crate) channel: C,
-    pub(crate) address: Arc<Mutex<Address>>,
+    /// Read lock-free for every frame, [`update_address`](Self::update_address) is the only writer.
+    pub(crate) address: Arc<ArcSwap<Address>>,
+    pub(crate) format: AddressFormat,
+    /// Matches the received frames, its address is replaced by the current one of each batch.
+    pub(crate) rx_matcher: RxMatcher,
     pub(crate) sender: Sender<F>,
     pub(crate) context: Arc<Mutex<IsoTpContext>>,
-    pub(crate) state: Arc<Mutex<IsoTpState>>,
+    /// Read lock-free by [`state`](Self::state) for the debugging UIs.
+    pub(crate) state: Arc<AtomicState>,
     pub(crate) listener: Arc<Mutex<Box<dyn IsoTpEventListener>>>,
+    pub(crate) verbosity: IsoTpVerbosity,
+    /// Replied to the first frame and each block of the peer.
+    pub(crate) flow_ctrl: FlowControlContext,
+    pub(crate) first_frame: FirstFramePolicy,
+    /// The sequence of the first consecutive frame transmitted and received.
+    pub(crate) sequence_start: SequenceStart,
+    /// Never transmit, see [`with_listen_only`](Self::with_listen_only).
+    pub(crate) listen_only: bool,
+    pub(crate) retry: RetryPolicy,
+    /// The frame of the writing kept for the [`RetryPolicy`].
+    pub(crate) tx_retry: Arc<Mutex<TxRetry<F>>>,
+    /// The frames sent and not confirmed yet while the address is half-duplex.
+    pub(crate) tx_echo: Arc<Mutex<TxEcho>>,
+    /// The frames sent and not confirmed yet, the confirmations are matched by the content.
+    pub(crate) tx_pending: Arc<Mutex<TxPending>>,
+    /// Tag the frames sent, the ones queued by an aborted writing aren't transmitted.
+    pub(crate) generations: Option<TxGenerations>,
+    /// Accept the next writing while the previous one is awaiting its confirmation, see [`with_pipelining`](Self::with_pipelining).
+    pub(crate) pipelining: bool,
+    /// Called with each ISO-TP frame sent and received, see [`set_frame_tap`](Self::set_frame_tap).
+    pub(crate) frame_tap: TapSlot,
+    /// Transmit the flow controls bypassing the transmit loop, see [`with_direct_transmit`](Self::with_direct_transmit).
+    pub(crate) direct: Option<DirectTransmit<F>>,
+    /// See [`with_n_br_warning`](Self::with_n_br_warning).
+    pub(crate) n_br_warning: Option<Duration>,
+    /// Set by [`Listener::on_shutdown`](crate::device::Listener::on_shutdown), the writing fails fast.
+    pub(crate) shutdown: Arc<AtomicBool>,
+    /// The events of the receiving passed to the [`request`](Self::request) in progress.
+    pub(crate) responses: Arc<Mutex<Option<UnboundedSender<IsoTpEvent>>>>,
+    /// Serializes the requests of the instance and its clones.
+    pub(crate) requesting: Arc<AsyncMutex<()>>,
 }
 
 unsafe impl<C, F> Send for AsyncCanIsoTp<C, F> {}
 
-impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
+impl<C: Channel, F: FrameMut<Channel = C> + Clone + 'static> AsyncCanIsoTp<C, F> {
 
     pub fn new(channel: C,
                address: Address,
@@ -28,117 +66,692 @@ impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
     ) -> Self {
         Self {
             channel,
-            address: Arc::new(Mutex::new(address)),
+            address: Arc::new(ArcSwap::from_pointee(address)),
+            format: Default::default(),
+            rx_matcher: RxMatcher::new(address, Default::default()),
             sender,
             context: Default::default(),
             state: Default::default(),
             listener: Arc::new(Mutex::new(listener)),
+            verbosity: Default::default(),
+            flow_ctrl: FlowControlContext::ISO15765_2,
+            first_frame: Default::default(),
+            sequence_start: Default::default(),
+            listen_only: false,
+            retry: Default::default(),
+            tx_retry: Default::default(),
+            tx_echo: Default::default(),
+            tx_pending: Default::default(),
+            generations: Default::default(),
+            pipelining: false,
+            frame_tap: Default::default(),
+            direct: Default::default(),
+            n_br_warning: Default::default(),
+            shutdown: Default::default(),
+            responses: Default::default(),
+            requesting: Default::default(),
         }
     }
 
+    /// Set the address format, the [`AddressFormat::NormalFixed`] transmits the extended
+    /// identifiers and receives the frames by the source and target addresses.
     #[inline]
-    pub fn update_address(&self, address: Address) {
-        if let Ok(mut addr) = self.address.lock() {
-            *addr = address;
+    pub fn with_address_format(mut self, format: AddressFormat) -> Self {
+        self.format = format;
+        self.rx_matcher = self.rx_matcher.with_format(format);
+        self
+    }
+
+    /// Set the [`RxMatcher`] of the received frames, e.g. to accept the functional address or
+    /// a masked range. Its address and format replace the ones of the instance.
+    #[inline]
+    pub fn with_rx_matcher(mut self, matcher: RxMatcher) -> Self {
+        self.address.store(Arc::new(matcher.address()));
+        self.format = matcher.format();
+        self.rx_matcher = matcher;
+        self
+    }
+
+    /// Set the [`IsoTpVerbosity`] of the events, [`IsoTpVerbosity::Normal`] by default.
+    #[inline]
+    pub fn with_verbosity(mut self, verbosity: IsoTpVerbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Set the flow control replied to the peer, [`FlowControlContext::ISO15765_2`] by default
+    /// and [`FlowControlContext::ISO15765_4`] for OBD.
+    ///
+    /// The peer transmits the consecutive frames back to back when STmin is 0, and waits for
+    /// the next flow control after each block when the block size isn't 0.
+    #[inline]
+    pub fn with_flow_control(mut self, ctx: FlowControlContext) -> Self {
+        self.flow_ctrl = ctx;
+        self
+    }
+
+    /// Set the [`FirstFramePolicy`] of a first frame during the receiving, [`FirstFramePolicy::Restart`] by default.
+    #[inline]
+    pub fn with_first_frame_policy(mut self, policy: FirstFramePolicy) -> Self {
+        self.first_frame = policy;
+        self
+    }
+
+    /// Set the [`SequenceStart`] of the consecutive frames, [`SequenceStart::Strict`] from 1 by default.
+    #[inline]
+    pub fn with_sequence_start(mut self, start: SequenceStart) -> Self {
+        self.sequence_start = start;
+        self
+    }
+
+    /// Never transmit when `listen_only` is true, e.g. monitoring the transfers to `rx_id`.
+    ///
+    /// No flow control is replied, the data is reassembled from the consecutive frames elicited
+    /// by the flow controls of the real receiver. The writing fails with [`Error::ListenOnly`].
+    #[inline]
+    pub fn with_listen_only(mut self, listen_only: bool) -> Self {
+        self.listen_only = listen_only;
+        self
+    }
+
+    #[inline]
+    pub fn is_listen_only(&self) -> bool {
+        self.listen_only
+    }
+
+    /// Validate the configuration against the [`DriverCapabilities`] of the driver it runs on,
+    /// e.g. the CAN-FD frames of the `can-fd` feature need a driver supporting CAN-FD.
+    pub fn with_capabilities(self, capabilities: DriverCapabilities) -> Result<Self, Error> {
+        if cfg!(feature = "can-fd") && !capabilities.fd {
+            return Err(Error::Unsupported("CAN-FD frames by the driver".into()));
         }
+
+        Ok(self)
+    }
+
+    /// Set the [`RetryPolicy`] of the frames failed by the device, disabled by default.
+    #[inline]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Send the frames by the [`TxGenerations`] of the transmit loop, the frames queued by an aborted
+    /// writing are dropped instead of transmitted after the abort, see [`abort`](Self::abort).
+    #[inline]
+    pub fn with_tx_generations(mut self, generations: TxGenerations) -> Self {
+        self.generations = Some(generations);
+        self
+    }
+
+    /// Accept the next writing while the previous one is awaiting the confirmation of its last frames,
+    /// e.g. the blocks of a download, its frames are queued behind them. Disabled by default.
+    ///
+    /// It needs [`with_tx_generations`](Self::with_tx_generations): when the previous writing fails,
+    /// the frames of the next one are dropped and it fails with [`Error::Aborted`], so it never starts.
+    /// The writing isn't accepted while the previous one is waiting for the flow control. The completions
+    /// and the errors are reported in order by [`IsoTpEvent::TxCompleted`] and [`IsoTpEvent::ErrorOccurred`].
+    #[inline]
+    pub fn with_pipelining(mut self, pipelining: bool) -> Self {
+        self.pipelining = pipelining;
+        self
+    }
+
+    /// Transmit the flow controls by `direct` instead of queueing them to the transmit loop, so N_Br
+    /// isn't delayed by the frames queued before, see [`SyncCan::direct_transmit`](crate::can::driver::SyncCan::direct_transmit).
+    ///
+    /// The flow control is transmitted by the thread of the receiving and confirmed when it returns,
+    /// the other frames are still sent to the transmit loop.
+    #[inline]
+    pub fn with_direct_transmit(mut self, direct: DirectTransmit<F>) -> Self {
+        self.direct = Some(direct);
+        self
+    }
+
+    /// Report a flow control transmitted later than `warning` after the frame it replies(the first frame
+    /// or the last consecutive frame of a block) by [`IsoTpEvent::FlowControlDelayed`], disabled by default,
+    /// e.g. within the 75 ms N_Bs of an ISO 15765-4 peer. N_Br is measured by the frame timestamps,
+    /// see [`rx_stats`](Self::rx_stats).
+    #[inline]
+    pub fn with_n_br_warning(mut self, warning: Duration) -> Self {
+        self.n_br_warning = Some(warning);
+        self
+    }
+
+    /// Call `tap` with each ISO-TP frame sent and received by the instance and its clones, e.g. for
+    /// the protocol debugging, `None` removes it.
+    ///
+    /// The received frames are passed after decoded, the ones sent before queued to the transmit loop,
+    /// including the flow controls replied and the retransmitted frames. A panic of the tap is logged
+    /// and ignored.
+    pub fn set_frame_tap(&self, tap: Option<FrameTap>) {
+        self.frame_tap.set(tap);
+    }
+
+    /// The current state, e.g. `WaitFlowCtrl|Sending` when waiting for the flow control.
+    #[inline]
+    pub fn state(&self) -> IsoTpState {
+        self.state.load(Ordering::Acquire)
+    }
+
+    /// Neither writing nor receiving, and no error occurred.
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.state() == IsoTpState::Idle && self.rx_in_progress().is_none()
+    }
+
+    /// The received bytes and the length of the first frame while receiving the consecutive frames.
+    ///
+    /// The receiving without a frame for N_Cr is aborted by [`Error::Timeout`] first, the peer is gone.
+    pub fn rx_in_progress(&self) -> Option<(usize, u32)> {
+        self.expire_reception();
+        let context = self.context.lock().ok()?;
+        context.consecutive.length
+            .map(|v| (context.consecutive.buffer.len(), v))
+    }
+
+    /// The last flow control received by the writing.
+    #[inline]
+    pub fn last_flow_control(&self) -> Option<FlowControlContext> {
+        self.context.lock().ok()?.last_flow_ctrl
+    }
+
+    /// The last flow control to continue of the peer, kept after the writing until the next one,
+    /// e.g. to choose a shorter block of the application by a long STmin.
+    #[inline]
+    pub fn peer_flow_control(&self) -> Option<FlowControlContext> {
+        self.context.lock().ok()?.peer_flow_ctrl
+    }
+
+    /// The [`TxStats`] of the writing in progress or the last one.
+    #[inline]
+    pub fn tx_stats(&self) -> TxStats {
+        match self.context.lock() {
+            Ok(context) => context.tx_stats,
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// The [`RxStats`] of the receivings, N_Br of the flow controls replied.
+    #[inline]
+    pub fn rx_stats(&self) -> RxStats {
+        match self.context.lock() {
+            Ok(context) => context.rx_stats,
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// The frames retransmitted by the [`RetryPolicy`].
+    #[inline]
+    pub fn retries(&self) -> u64 {
+        match self.tx_retry.lock() {
+            Ok(v) => v.retries(),
+            Err(_) => 0,
+        }
+    }
+
+    #[inline]
+    pub fn update_address(&self, address: Address) {
+        self.address.store(Arc::new(address));
     }
 
     pub async fn write(&self, functional: bool, data: Vec<u8>) -> Result<(), Error> {
+        let address = **self.address.load();
+        let can_id = self.format.can_id(if functional { address.fid } else { address.tx_id });
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            let span = trace::write_span("async", Some(address), functional, data.len());
+            self.write_frames(can_id, None, data).instrument(span).await
+        }
+        #[cfg(not(feature = "tracing"))]
+        self.write_frames(can_id, None, data).await
+    }
+
+    /// Write the physical request and wait for its response, the response is started within `p2`,
+    /// extended to `p2_star` by a first frame or a response pending(NRC 0x78), e.g. [`P2_ISO14229`](crate::constant::P2_ISO14229)
+    /// and [`P2_STAR_ISO14229`](crate::constant::P2_STAR_ISO14229).
+    ///
+    /// The requests of the instance and its clones are serialized, the data received before the request
+    /// is discarded. The events are still reported to the listener.
+    pub async fn request(&self, request: Vec<u8>, p2: Duration, p2_star: Duration) -> Result<Vec<u8>, Error> {
+        let service = *request.first()
+            .ok_or(Error::InvalidParam("empty request".into()))?;
+        let _requesting = self.requesting.lock().await;
+        let (tx, mut rx) = unbounded_channel();
+        match self.responses.lock() {
+            Ok(mut responses) => *responses = Some(tx),
+            Err(_) => return Err(Error::ContextError("can't get `responses`".into())),
+        }
+
+        let result = async {
+            self.write(false, request).await?;
+            let mut wait = p2;
+            loop {
+                let event = match timeout(wait, rx.recv()).await {
+                    Ok(Some(v)) => v,
+                    Ok(None) => return Err(Error::ContextError("the responses are closed".into())),
+                    Err(_) => return Err(Error::Timeout { value: wait.as_millis() as u64, unit: "ms" }),
+                };
+                match event {
+                    IsoTpEvent::FirstFrameReceived { .. } => wait = p2_star,
+                    IsoTpEvent::DataReceived { data, .. } => match *data {
+                        [0x7F, sid, 0x78] if sid == service => {
+                            log::debug!("ISO-TP(CAN async) - response pending");
+                            wait = p2_star;
+                        },
+                        _ => return Ok(data.to_vec()),
+                    },
+                    IsoTpEvent::ErrorOccurred(e) | IsoTpEvent::ReceptionAborted { error: e, .. } => return Err(e),
+                    _ => {},
+                }
+            }
+        }.await;
+
+        if let Ok(mut responses) = self.responses.lock() {
+            *responses = None;
+        }
+        result
+    }
+
+    /// Write `data` to the identifier `tx_id` instead of the address, e.g. by a gateway or a fuzzer.
+    ///
+    /// The address is not changed, so it doesn't race with the receiving. The data is segmented
+    /// as by [`write`](Self::write) and the flow controls of the peer are still matched by the
+    /// `rx_id` of the address. Like `write`, an instance writes one at a time, a writing started
+    /// meanwhile resets this one.
+    pub async fn write_to(&self, tx_id: u32, extended: bool, data: Vec<u8>) -> Result<(), Error> {
+        let can_id = Id::from_bits(tx_id, extended);
+        #[cfg(feature = "tracing")]
+        {
+            use tracing::Instrument;
+            let span = trace::write_span("async", Some(Address { tx_id, ..**self.address.load() }), false, data.len());
+            self.write_frames(can_id, Some(tx_id), data).instrument(span).await
+        }
+        #[cfg(not(feature = "tracing"))]
+        self.write_frames(can_id, Some(tx_id), data).await
+    }
+
+    /// Write the frames to `can_id`, `target` is the identifier of [`write_to`](Self::write_to).
+    async fn write_frames(&self, can_id: Id, target: Option<u32>, data: Vec<u8>) -> Result<(), Error> {
+        if self.listen_only {
+            return Err(Error::ListenOnly);
+        }
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(Error::Shutdown);
+        }
+
+        let pipelined = self.pipelined() && {
+            // one writing awaits its confirmation at most
+            self.wait_previous().await?;
+            match self.context.lock() {
+                Ok(context) => context.tx.as_ref().is_some_and(|v| v.queued && v.frames > 0),
+                Err(_) => false,
+            }
+        };
+        if pipelined && self.state_contains(IsoTpState::Error) {
+            // the previous writing failed after it returned, this one isn't started
+            let e = self.last_error();
+            self.state_append(IsoTpState::Idle);
+            self.context_reset();
+            self.clear_pending();
+            return Err(Error::Aborted(format!("by the previous writing: {}", e)));
+        }
+
         self.state_append(IsoTpState::Idle);
-        self.context_reset();
-        log::debug!("ISO-TP(CAN async) - Sending: {}", hex::encode(&data));
+        let pipelined = match self.context.lock() {
+            Ok(mut context) if pipelined => context.pipeline(),
+            Ok(mut context) => {
+                context.reset();
+                false
+            },
+            Err(_) => false,
+        };
+        if !pipelined {
+            self.clear_pending();
+        }
+        if let Ok(mut tx_retry) = self.tx_retry.lock() {
+            tx_retry.clear();
+        }
+        trace::sending("async", &data);
 
-        let frames = CanIsoTpFrame::from_data(data)?;
+        let bytes = data.len();
+        let frames = CanIsoTpFrame::from_data_with_sequence(data, self.sequence_start.tx_sequence())?;
         let frame_len = frames.len();
+        if let Ok(mut context) = self.context.lock() {
+            context.start_tx(can_id.into_bits(), frame_len, bytes);
+            context.write_to = target;
+        }
 
-        let can_id = match self.address.lock() {
-            Ok(address) => if functional { Ok(address.fid) } else { Ok(address.tx_id) },
-            Err(_) => Err(Error::ContextError("can't get address context".into())),
-        }?;
+        let frames = frames.into_iter()
+            .map(|frame| {
+                let mut frame = F::from_iso_tp_with(can_id, frame, &AddressContext::new(self.format))
+                    .ok_or(Error::ConvertError {
+                        src: "iso-tp frame",
+                        target: "can-frame",
+                    })?;
+                frame.set_channel(self.channel.clone());
+                Ok(frame)
+            })
+            .collect::<Result<Vec<_>, Error>>()?
+            .into_iter();
+        let result = self.send_frames(frames, frame_len).await;
+        if result.is_err() {
+            self.supersede_queued();
+            // the error is returned, the instance is ready for the next transfer
+            if let Ok(mut context) = self.context.lock() {
+                context.tx = None;
+            }
+            self.state_append(IsoTpState::Idle);
+        }
+        else if let Ok(mut context) = self.context.lock() {
+            if let Some(tx) = context.tx.as_mut() {
+                tx.queued = true;
+            }
+        }
+        result
+    }
+
+    /// Whether the next writing is accepted while the previous one is awaiting its confirmation.
+    #[inline]
+    fn pipelined(&self) -> bool {
+        self.pipelining && self.generations.is_some()
+    }
+
+    /// Clear the frames not confirmed of the previous writings, their confirmations are ignored.
+    fn clear_pending(&self) {
+        if let Ok(mut tx_echo) = self.tx_echo.lock() {
+            tx_echo.clear();
+        }
+        if let Ok(mut tx_pending) = self.tx_pending.lock() {
+            tx_pending.clear();
+        }
+    }
+
+    /// Send the frames of the writing paced by the flow controls of the peer.
+    async fn send_frames(&self, mut frames: std::vec::IntoIter<F>, frame_len: usize) -> Result<(), Error> {
         let mut need_flow_ctrl = frame_len > 1;
         let mut index = 0;
-        for frame in frames {
-            let mut frame = F::from_iso_tp(can_id, frame, None)
-                .ok_or(Error::ConvertError {
-                    src: "iso-tp frame",
-                    target: "can-frame",
-                })?;
-            frame.set_channel(self.channel.clone());
-
+        while let Some(frame) = frames.next() {
             if need_flow_ctrl {
                 need_flow_ctrl = false;
                 self.state_append(IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
+                self.send(frame)?;
+                continue;
+            }
+
+            self.write_waiting().await?;
+            // the frames are transmitted one by one to retransmit a failed frame in order
+            let (burst, block_size) = match self.context.lock() {
+                Ok(context) => (
+                    context.burst_len(index).filter(|_| !self.retry.is_enabled()),
+                    context.flow_ctrl.as_ref().map_or(0, |v| v.block_size as usize),
+                ),
+                Err(_) => (None, 0),
+            };
+            let len = burst.map_or(1, |v| v.min(frames.len() + 1));
+            index += len;
+            // the flow control is waited for before the last frame of the block is transmitted,
+            // so a fast reply of the peer is never missed
+            if block_size != 0 && index == block_size && frames.len() >= len {
+                index = 0;
+                self.state_append(IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
             }
             else {
-                self.write_waiting(&mut index)?;
                 self.state_append(IsoTpState::Sending);
             }
-            self.sender.send(frame)
-                .map_err(|e| {
-                    log::warn!("ISO-TP(CAN async) - transmit failed: {:?}", e);
-                    Error::DeviceError
-                })?;
+            self.send(frame)?;
+            if len > 1 {
+                // STmin is 0, the frames to the end of the block are transmitted together
+                frames.by_ref()
+                    .take(len - 1)
+                    .try_for_each(|frame| self.send(frame))?;
+                // the last frames are confirmed while the next writing is queued behind them
+                if frames.len() > 0 || !self.pipelined() {
+                    self.wait_confirmed(frames.len()).await?;
+                }
+            }
+        }
+
+        if self.retry.is_enabled() {
+            // the last frame may be retransmitted
+            self.wait_confirmed(0).await?;
         }
 
         Ok(())
     }
 
+    /// Abort the writing in progress, it fails with [`Error::Aborted`]. Returns false when not writing.
+    ///
+    /// The frames sent and not transmitted yet are dropped by [`with_tx_generations`](Self::with_tx_generations),
+    /// otherwise they're still transmitted by the transmit loop.
+    pub fn abort(&self) -> bool {
+        if !self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            return false;
+        }
+
+        log::warn!("ISO-TP(CAN async) - writing aborted");
+        // the writing fails by the error state and supersedes its frames queued
+        self.on_error(Error::Aborted("by the application".into()));
+        true
+    }
+
+    /// Supersede the generation of the failed writing, its frames queued by the transmit loop are dropped.
+    fn supersede_queued(&self) {
+        let Some(generations) = &self.generations else {
+            return;
+        };
+
+        let id = match self.context.lock() {
+            Ok(context) => context.tx.as_ref().map(|v| v.id),
+            Err(_) => None,
+        };
+        if let Some(id) = id {
+            let generation = generations.advance(id);
+            if let Ok(mut tx_pending) = self.tx_pending.lock() {
+                tx_pending.supersede(id, generation);
+            }
+        }
+    }
+
+    fn send(&self, frame: F) -> Result<(), Error> {
+        if self.retry.is_enabled() {
+            if let Ok(mut tx_retry) = self.tx_retry.lock() {
+                tx_retry.sent(&frame);
+            }
+        }
+        self.transmit(frame)
+    }
+
+    fn transmit(&self, frame: F) -> Result<(), Error> {
+        if self.listen_only {
+            return Err(Error::ListenOnly);
+        }
+        if self.address.load().is_half_duplex() {
+            if let Ok(mut tx_echo) = self.tx_echo.lock() {
+                tx_echo.sent(frame.data());
+            }
+        }
+        if self.frame_tap.is_set() {
+            // the frames are sent without the address extension
+            if let Ok(frame) = CanIsoTpFrame::decode(frame.data()) {
+                self.frame_tap.tap("async", Direction::Tx, &frame);
+            }
+        }
+        let id = frame.id().into_bits();
+        let transfer = match self.context.lock() {
+            Ok(context) => context.tx.as_ref().map_or(0, |v| v.transfer),
+            Err(_) => 0,
+        };
+        if let Ok(mut tx_pending) = self.tx_pending.lock() {
+            let generation = self.generations.as_ref().map_or(0, |v| v.generation(id));
+            tx_pending.sent(id, generation, transfer, frame.data());
+        }
+        let result = match &self.generations {
+            Some(generations) => generations.send(&self.sender, frame),
+            None => self.sender.send(frame),
+        };
+        result.map_err(|e| {
+            // the loops are stopped and the receiver is dropped
+            if self.shutdown.load(Ordering::Acquire) {
+                return Error::Shutdown;
+            }
+            log::warn!("ISO-TP(CAN async) - transmit failed: {:?}", e);
+            Error::device(e)
+        })
+    }
+
     #[inline]
-    pub(crate) fn on_single_frame(&self, data: Vec<u8>) {
-        self.iso_tp_event(IsoTpEvent::DataReceived(data));
+    pub(crate) fn on_single_frame(&self, data: &[u8], r#type: AddressType, timestamp: u64) {
+        // the physical single frame terminates the receiving in progress, as ISO 15765-2
+        if r#type == AddressType::Physical {
+            self.on_reception_restarted();
+        }
+        self.iso_tp_event(IsoTpEvent::DataReceived { data: data.into(), first_frame_at: timestamp, completed_at: timestamp });
     }
 
     #[inline]
-    pub(crate) fn on_first_frame(&self, tx_id: u32, length: u32, data: Vec<u8>) {
-        self.update_consecutive(length, data);
+    pub(crate) fn on_first_frame(&self, tx_id: u32, length: u32, data: &[u8], timestamp: u64) {
+        if self.rx_in_progress().is_some() {
+            match self.first_frame {
+                FirstFramePolicy::Restart => self.on_reception_restarted(),
+                FirstFramePolicy::Ignore => {
+                    log::warn!("ISO-TP(CAN async) - first frame ignored during the receiving");
+                    return;
+                },
+            }
+        }
 
-        let iso_tp_frame = CanIsoTpFrame::default_flow_ctrl_frame();
-        match F::from_iso_tp(tx_id, iso_tp_frame, None) {
-            Some(mut frame) => {
-                frame.set_channel(self.channel.clone());
+        // the new transfer discards the stale partial data
+        self.clear_buffer();
+        self.update_consecutive(length, data, timestamp);
 
-                self.state_append(IsoTpState::Sending);
-                match self.sender.send(frame) {
-                    Ok(_) => {
-                        self.iso_tp_event(IsoTpEvent::FirstFrameReceived);
-                    },
-                    Err(e) => {
-                        log::warn!("ISO-TP(CAN async) - transmit failed: {:?}", e);
-                        self.state_append(IsoTpState::Error);
+        if self.listen_only {
+            // the flow control is replied by the real receiver
+            self.iso_tp_event(IsoTpEvent::FirstFrameReceived { at: timestamp });
+        }
+        else if let Some(ctx) = self.send_flow_ctrl(tx_id, timestamp) {
+            self.iso_tp_event(IsoTpEvent::FirstFrameReceived { at: timestamp });
+            trace::flow_control("async", "sent", &ctx);
+            self.verbose_event(IsoTpEvent::FlowControlSent(ctx));
+        }
+    }
 
-                        self.iso_tp_event(IsoTpEvent::ErrorOccurred(Error::DeviceError));
-                    },
+    #[inline]
+    pub(crate) fn on_consecutive_frame(&self, sequence: u8, data: &[u8], timestamp: u64) {
+        if self.rx_in_progress().is_none() {
+            log::debug!("ISO-TP(CAN async) - unexpected consecutive frame ignored");
+            return;
+        }
+
+        match self.append_consecutive(sequence, data, timestamp) {
+            Ok(event) => {
+                let next_block = !self.listen_only && matches!(event, IsoTpEvent::Wait) && self.block_received();
+                self.iso_tp_event(event);
+                if next_block {
+                    if let Some(ctx) = self.send_flow_ctrl(self.address.load().tx_id, timestamp) {
+                        trace::flow_control("async", "sent", &ctx);
+                        self.verbose_event(IsoTpEvent::FlowControlSent(ctx));
+                    }
                 }
             },
-            None => log::error!("ISO-TP(CAN async): convert `iso-tp frame` to `can-frame` error"),
+            Err(e) => self.on_reception_aborted(e),
         }
     }
 
-    #[inline]
-    pub(crate) fn on_consecutive_frame(&self, sequence: u8, data: Vec<u8>) {
-        match self.append_consecutive(sequence, data) {
-            Ok(event) => self.iso_tp_event(event),
+    /// Send the flow control of the instance replying the frame received at `received_at`, returns it when sent.
+    fn send_flow_ctrl(&self, tx_id: u32, received_at: u64) -> Option<FlowControlContext> {
+        let ctx = self.flow_ctrl;
+        let addressing = AddressContext::new(self.format);
+        let Some(mut frame) = F::from_iso_tp_with(addressing.can_id(tx_id), CanIsoTpFrame::FlowControlFrame(ctx), &addressing) else {
+            log::error!("ISO-TP(CAN async): convert `iso-tp frame` to `can-frame` error");
+            return None;
+        };
+        frame.set_channel(self.channel.clone());
+        if let Ok(mut context) = self.context.lock() {
+            context.flow_ctrl_due = Some(received_at);
+        }
+
+        let result = match &self.direct {
+            Some(direct) => self.transmit_direct(direct, frame),
+            None => {
+                self.state_append(IsoTpState::Sending);
+                self.transmit(frame)
+            },
+        };
+        match result {
+            Ok(_) => Some(ctx),
             Err(e) => {
-                self.state_append(IsoTpState::Error);
-                self.iso_tp_event(IsoTpEvent::ErrorOccurred(e));
+                self.on_error(e);
+                None
+            },
+        }
+    }
+
+    /// Transmit the flow control by the [`DirectTransmit`], it's confirmed when returned.
+    fn transmit_direct(&self, direct: &DirectTransmit<F>, frame: F) -> Result<(), Error> {
+        if self.frame_tap.is_set() {
+            if let Ok(frame) = CanIsoTpFrame::decode(frame.data()) {
+                self.frame_tap.tap("async", Direction::Tx, &frame);
             }
         }
+        // the echo of a half-duplex address isn't received before it's confirmed by this thread
+        let frame = direct.transmit(frame, None)?;
+        self.on_flow_ctrl_transmitted(timestamp_or_now(&frame));
+        Ok(())
+    }
+
+    /// Measure N_Br of the flow control transmitted at `timestamp`, [`IsoTpEvent::FlowControlDelayed`]
+    /// when it's longer than the warning.
+    fn on_flow_ctrl_transmitted(&self, timestamp: u64) {
+        let exceeded = match self.context.lock() {
+            Ok(mut context) => context.flow_ctrl_transmitted(timestamp, self.n_br_warning),
+            Err(_) => None,
+        };
+        if let Some(n_br) = exceeded {
+            log::warn!("ISO-TP(CAN async) - flow control transmitted {:?} after the frame replied, longer than {:?}",
+                n_br, self.n_br_warning.unwrap_or_default());
+            self.iso_tp_event(IsoTpEvent::FlowControlDelayed { n_br });
+        }
+    }
+
+    /// Count a consecutive frame of the block, returns true when the next flow control is due.
+    fn block_received(&self) -> bool {
+        match self.context.lock() {
+            Ok(mut context) => context.block_received(self.flow_ctrl.block_size()),
+            Err(_) => false,
+        }
     }
 
     #[inline]
     pub(crate) fn on_flow_ctrl_frame(&self, ctx: FlowControlContext) {
+        trace::flow_control("async", "received", &ctx);
+        if let Ok(mut context) = self.context.lock() {
+            context.last_flow_ctrl = Some(ctx);
+        }
+        if !self.state_contains(IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::debug!("ISO-TP(CAN async) - unexpected flow control ignored");
+            return;
+        }
+
+        if ctx.raw_st_min() != ctx.st_min() {
+            log::warn!("ISO-TP(CAN async) - reserved STmin received: {}", ctx);
+        }
+        self.verbose_event(IsoTpEvent::FlowControlReceived(ctx));
         match ctx.state() {
             FlowControlState::Continues => {
                 self.state_remove(IsoTpState::WaitBusy | IsoTpState::WaitFlowCtrl);
             },
             FlowControlState::Wait => {
+                if let Ok(mut context) = self.context.lock() {
+                    context.waits += 1;
+                }
                 self.state_append(IsoTpState::WaitBusy);
                 self.iso_tp_event(IsoTpEvent::Wait);
                 return;
             }
             FlowControlState::Overload => {
-                self.state_append(IsoTpState::Error);
-                self.iso_tp_event(IsoTpEvent::ErrorOccurred(Error::OverloadFlow));
+                self.on_error(Error::OverloadFlow);
                 return;
             }
         }
@@ -148,17 +761,235 @@ impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
         };
     }
 
+    /// Confirm a transmitted frame of the writing, [`IsoTpEvent::TxCompleted`] when the last one is confirmed.
+    ///
+    /// The frame is matched to a pending one sent by the instance, the others of the identifier
+    /// are ignored, and the sending is done when none is pending. A flow control is measured by `timestamp`.
+    pub(crate) fn on_transmitted(&self, id: u32, data: &[u8], timestamp: u64) {
+        let (kind, transfer, done) = match self.tx_pending.lock() {
+            Ok(mut tx_pending) => match tx_pending.confirmed(id, data) {
+                Some((kind, transfer)) => (kind, transfer, tx_pending.is_empty()),
+                None => {
+                    log::trace!("ISO-TP(CAN async) - confirmation of a frame not sent by the instance: {:04X}", id);
+                    return;
+                },
+            },
+            Err(_) => return,
+        };
+        if done {
+            self.state_remove(IsoTpState::Sending);
+        }
+        if let Ok(mut tx_echo) = self.tx_echo.lock() {
+            tx_echo.confirmed(data);
+        }
+        if kind == FrameType::FlowControl {
+            self.on_flow_ctrl_transmitted(timestamp);
+            return;
+        }
+
+        if self.retry.is_enabled() {
+            if let Ok(mut tx_retry) = self.tx_retry.lock() {
+                tx_retry.confirmed();
+            }
+        }
+        let completed = match self.context.lock() {
+            Ok(mut context) => context.confirm_tx(transfer),
+            Err(_) => None,
+        };
+        if let Some(bytes) = completed {
+            self.verbose_event(IsoTpEvent::TxCompleted { bytes });
+        }
+    }
+
+    /// Whether the frame received by a half-duplex address is its own one echoed by the driver,
+    /// by the direction or by the content of an unconfirmed frame.
+    pub(crate) fn is_echo(&self, frame: &F) -> bool {
+        if frame.direct() == Direct::Transmit {
+            log::trace!("ISO-TP(CAN async) - echo ignored: {:04X}", frame.id().into_bits());
+            return true;
+        }
+
+        match self.tx_echo.lock() {
+            Ok(mut tx_echo) => tx_echo.is_echo(frame.data()),
+            Err(_) => false,
+        }
+    }
+
+    /// Whether `id` is the target of the [`write_to`](Self::write_to) in progress.
+    pub(crate) fn is_write_target(&self, id: u32) -> bool {
+        match self.context.lock() {
+            Ok(context) => context.write_to == Some(id),
+            Err(_) => false,
+        }
+    }
+
+    /// Retransmit the failed frame of the writing by the [`RetryPolicy`], the writing fails when
+    /// the retries are exhausted.
+    pub(crate) fn on_transmit_failed(&self, id: u32, e: &Error) {
+        let attempt = match self.tx_retry.lock() {
+            Ok(mut tx_retry) if self.retry.is_enabled() => tx_retry.failed(&self.retry),
+            _ => None,
+        };
+        if let Some(v) = attempt {
+            log::warn!("ISO-TP(CAN async) - transmit failed: {}, retry {}/{} after {:?}",
+                e, v, self.retry.max_retries, self.retry.backoff);
+            return;
+        }
+
+        let transfer = match self.tx_pending.lock() {
+            Ok(mut tx_pending) => tx_pending.failed(id),
+            Err(_) => None,
+        };
+        let previous = match self.context.lock() {
+            Ok(context) => transfer.is_some_and(|v| context.is_previous(v)),
+            Err(_) => false,
+        };
+        if previous {
+            self.on_previous_failed(e.clone());
+        }
+        else {
+            self.on_error(e.clone());
+        }
+    }
+
+    /// The previous writing failed while pipelining, the frames of the writing queued behind it
+    /// are dropped before transmitted and it fails with [`Error::Aborted`].
+    ///
+    /// The aborted writing is done, the error state doesn't fail the next one again.
+    fn on_previous_failed(&self, e: Error) {
+        log::warn!("ISO-TP(CAN async) - previous writing failed: {}", e);
+        self.iso_tp_event(IsoTpEvent::ErrorOccurred(e.clone()));
+        self.supersede_queued();
+        if let Ok(mut context) = self.context.lock() {
+            context.tx_previous = None;
+            context.tx = None;
+        }
+        self.on_error(Error::Aborted(format!("by the previous writing: {}", e)));
+    }
+
+    /// Set the error state and notify the listener, the error is returned by the writing.
+    pub(crate) fn on_error(&self, e: Error) {
+        self.set_error(&e);
+        self.iso_tp_event(IsoTpEvent::ErrorOccurred(e));
+    }
+
+    /// Abort the receiving with the error, the partial data is moved to [`IsoTpEvent::ReceptionAborted`].
+    pub(crate) fn on_reception_aborted(&self, e: Error) {
+        let partial = match self.context.lock() {
+            Ok(mut context) => context.take_consecutive(),
+            Err(_) => None,
+        };
+
+        match partial {
+            // the error state isn't set, it fails the writing only
+            Some((partial, expected)) => {
+                self.iso_tp_event(IsoTpEvent::ReceptionAborted { error: e, partial, expected });
+            },
+            None => self.on_error(e),
+        }
+    }
+
+    /// Abort the receiving without a frame for N_Cr by the local clock.
+    fn expire_reception(&self) {
+        let expired = match self.context.lock() {
+            Ok(context) => context.consecutive_expired(tick_millis()),
+            Err(_) => false,
+        };
+        if expired {
+            log::warn!("ISO-TP(CAN async) - receiving expired");
+            self.on_reception_aborted(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
+        }
+    }
+
+    /// Abort the transfers in progress with [`Error::Shutdown`], the device is shut down.
+    pub(crate) fn on_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        if self.rx_in_progress().is_some() {
+            self.on_reception_aborted(Error::Shutdown);
+        }
+        if self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::warn!("ISO-TP(CAN async) - writing aborted by shutdown");
+            self.on_error(Error::Shutdown);
+        }
+    }
+
+    /// Report the partial data of the receiving terminated by a new first frame, the error state isn't set.
+    fn on_reception_restarted(&self) {
+        let partial = match self.context.lock() {
+            Ok(mut context) => context.take_consecutive(),
+            Err(_) => None,
+        };
+
+        if let Some((partial, expected)) = partial {
+            self.iso_tp_event(IsoTpEvent::ReceptionAborted { error: Error::ReceptionRestarted, partial, expected });
+        }
+    }
+
+    /// Abort the active transfers with [`Error::BusOff`].
+    pub(crate) fn on_bus_off(&self) {
+        let receiving = match self.context.lock() {
+            Ok(mut context) => {
+                let receiving = context.consecutive.length.is_some();
+                context.clear_consecutive();
+                receiving
+            },
+            Err(_) => false,
+        };
+
+        if receiving
+            || self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::warn!("ISO-TP(CAN async) - transfer aborted by bus off");
+            self.clear_buffer();
+            self.on_error(Error::BusOff);
+        }
+    }
+
+    fn set_error(&self, e: &Error) {
+        // the error before the state, the writing polling the state reads it
+        if let Ok(mut context) = self.context.lock() {
+            context.error = Some(e.clone());
+        }
+        self.state_append(IsoTpState::Error);
+    }
+
+    fn last_error(&self) -> Error {
+        match self.context.lock() {
+            Ok(context) => context.error.clone()
+                .unwrap_or(Error::ContextError("an error occurred without details".into())),
+            Err(_) => Error::ContextError("can't get `context`".into()),
+        }
+    }
+
+    fn clear_buffer(&self) {
+        match self.listener.lock() {
+            Ok(mut listener) => listener.clear_buffer(),
+            Err(_) => log::warn!("ISO-TP(CAN async): clearing buffer failed"),
+        }
+    }
+
     fn iso_tp_event(&self, event: IsoTpEvent) {
+        // no application expects an empty payload, drop it even if a frame slipped through the decoder
+        if matches!(&event, IsoTpEvent::DataReceived { data, .. } if data.is_empty()) {
+            log::warn!("ISO-TP(CAN async) - empty data received is dropped");
+            return;
+        }
+
+        if let Ok(responses) = self.responses.lock() {
+            let response = matches!(&event, IsoTpEvent::FirstFrameReceived { .. } | IsoTpEvent::DataReceived { .. }
+                | IsoTpEvent::ErrorOccurred(_) | IsoTpEvent::ReceptionAborted { .. });
+            if let Some(tx) = responses.as_ref().filter(|_| response) {
+                let _ = tx.send(event.clone());
+            }
+        }
+
         match self.listener.lock() {
             Ok(mut listener) => {
                 // println!("ISO-TP(CAN async): Sending iso-tp event: {:?}", event);
                 match &event {
-                    IsoTpEvent::DataReceived(data) => {
-                        log::debug!("ISO-TP - Received: {}", hex::encode(data));
-                    },
-                    IsoTpEvent::ErrorOccurred(_) =>
-                        log::warn!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
-                    _ => log::trace!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
+                    IsoTpEvent::DataReceived { data, .. } => trace::received("async", data),
+                    IsoTpEvent::ErrorOccurred(_) | IsoTpEvent::ReceptionAborted { .. } =>
+                        log::warn!("ISO-TP(CAN async): Sending iso-tp event: {:?}", event),
+                    _ => log::trace!("ISO-TP(CAN async): Sending iso-tp event: {:?}", event),
                 }
                 listener.on_iso_tp_event(event);
             },
@@ -166,46 +997,59 @@ impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
         }
     }
 
-    async fn write_waiting(&self, index: &mut usize) -> Result<(), Error> {
-        match self.context.lock() {
-            Ok(ctx) => {
-                if let Some(ctx) = &ctx.flow_ctrl {
-                    if ctx.block_size != 0 {
-                        if (*index + 1) == ctx.block_size as usize {
-                            *index = 0;
-                            self.state_append(IsoTpState::WaitFlowCtrl);
-                        }
-                        else {
-                            *index += 1;
-                        }
-                    }
-                    sleep(Duration::from_micros(ctx.st_min as u64)).await;
-                }
+    #[inline]
+    fn verbose_event(&self, event: IsoTpEvent) {
+        if self.verbosity == IsoTpVerbosity::Verbose {
+            self.iso_tp_event(event);
+        }
+    }
 
-                Ok(())
-            },
+    async fn write_waiting(&self) -> Result<(), Error> {
+        let flow_ctrl = match self.context.lock() {
+            Ok(ctx) => Ok(ctx.flow_ctrl.clone()),
             Err(_) => Err(Error::ContextError("can't get `context`".into()))
         }?;
+        if let Some(ctx) = flow_ctrl {
+            sleep(Duration::from_micros(ctx.st_min as u64)).await;
+        }
 
-        let start = Instant::now();
+        let mut start = Instant::now();
+        let mut waits = self.waits()?;
         loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
             if self.state_contains(IsoTpState::Error) {
-                return Err(Error::DeviceError);
+                return Err(self.last_error());
+            }
+
+            if self.retransmit()? {
+                // N_As restarts for the retransmitted frame
+                start = Instant::now();
+            }
+            let current = self.waits()?;
+            if current != waits {
+                // N_Bs restarts by each FC WAIT
+                waits = current;
+                start = Instant::now();
             }
 
             if self.state_contains(IsoTpState::Sending) {
                 if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                    trace::timeout("async", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
                     return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
                 }
             }
             else if self.state_contains(IsoTpState::WaitBusy) {
-                if start.elapsed() > Duration::from_millis(P2_STAR_ISO14229 as u64) {
-                    return Err(Error::Timeout { value: P2_STAR_ISO14229 as u64, unit: "ms" });
+                if start.elapsed() > Duration::from_millis(TIMEOUT_BS_ISO15765_2 as u64) {
+                    trace::timeout("async", IsoTpState::WaitBusy, TIMEOUT_BS_ISO15765_2 as u64);
+                    return Err(Error::Timeout { value: TIMEOUT_BS_ISO15765_2 as u64, unit: "ms" });
                 }
             }
             else if self.state_contains(IsoTpState::WaitFlowCtrl) {
-                if start.elapsed() > Duration::from_millis(TIMEOUT_CR_ISO15765_2 as u64) {
-                    return Err(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
+                if start.elapsed() > Duration::from_millis(TIMEOUT_BS_ISO15765_2 as u64) {
+                    trace::timeout("async", IsoTpState::WaitFlowCtrl, TIMEOUT_BS_ISO15765_2 as u64);
+                    return Err(Error::Timeout { value: TIMEOUT_BS_ISO15765_2 as u64, unit: "ms" });
                 }
             }
             else {
@@ -216,18 +1060,107 @@ impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
         Ok(())
     }
 
-    fn append_consecutive(&self, sequence: u8, data: Vec<u8>) -> Result<IsoTpEvent, Error> {
+    /// Wait for the confirmation of the last frames of the previous writing while pipelining,
+    /// supervised by N_As.
+    async fn wait_previous(&self) -> Result<(), Error> {
+        let start = Instant::now();
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
+
+            let previous = match self.context.lock() {
+                Ok(context) => context.tx_previous.is_some(),
+                Err(_) => return Err(Error::ContextError("can't get `context`".into())),
+            };
+            if !previous || self.state_contains(IsoTpState::Error) {
+                return Ok(());
+            }
+            if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                trace::timeout("async", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
+                return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
+            }
+            yield_now().await;
+        }
+    }
+
+    /// Wait for the confirmations of the transmitted frames until `unsent` frames of the writing are
+    /// left, each confirmation is supervised by N_As.
+    async fn wait_confirmed(&self, unsent: usize) -> Result<(), Error> {
+        let mut pending = usize::MAX;
+        let mut start = Instant::now();
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
+            if self.state_contains(IsoTpState::Error) {
+                return Err(self.last_error());
+            }
+
+            if self.retransmit()? {
+                start = Instant::now();
+            }
+
+            let current = match self.context.lock() {
+                Ok(context) => context.unconfirmed(),
+                Err(_) => return Err(Error::ContextError("can't get `context`".into())),
+            };
+            if current <= unsent {
+                return Ok(());
+            }
+
+            if current < pending {
+                pending = current;
+                start = Instant::now();
+            }
+            else if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                trace::timeout("async", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
+                return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
+            }
+            // the confirmations are delivered by the other tasks or threads
+            yield_now().await;
+        }
+    }
+
+    /// Retransmit the failed frame when its backoff elapsed, returns true when retransmitted.
+    fn retransmit(&self) -> Result<bool, Error> {
+        if !self.retry.is_enabled() {
+            return Ok(false);
+        }
+
+        let frame = match self.tx_retry.lock() {
+            Ok(mut tx_retry) => tx_retry.take_due(),
+            Err(_) => return Err(Error::ContextError("can't get `tx_retry`".into())),
+        };
+        match frame {
+            Some(frame) => self.transmit(frame).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// The FC WAIT received by the writing.
+    fn waits(&self) -> Result<usize, Error> {
+        match self.context.lock() {
+            Ok(context) => Ok(context.waits),
+            Err(_) => Err(Error::ContextError("can't get `context`".into())),
+        }
+    }
+
+    fn append_consecutive(&self, sequence: u8, data: &[u8], timestamp: u64) -> Result<IsoTpEvent, Error> {
         match self.context.lock() {
             Ok(mut context) => {
-                context.append_consecutive(sequence, data)
+                let result = context.append_consecutive(self.sequence_start, sequence, data, timestamp);
+                context.touch_consecutive(tick_millis());
+                result
             },
             Err(_) => Err(Error::ContextError("can't get `context`".into()))
         }
     }
 
-    fn update_consecutive(&self, length: u32, data: Vec<u8>) {
+    fn update_consecutive(&self, length: u32, data: &[u8], timestamp: u64) {
         if let Ok(mut context) = self.context.lock() {
-            context.update_consecutive(length, data);
+            context.update_consecutive(length, data, timestamp);
+            context.touch_consecutive(tick_millis());
         }
     }
 
@@ -239,45 +1172,28 @@ impl<C: Clone, F: Frame<Channel = C>> AsyncCanIsoTp<C, F> {
 
     #[inline]
     fn state_contains(&self, flags: IsoTpState) -> bool {
-        match self.state.lock() {
-            Ok(v) => {
-                // log::debug!("ISO-TP(CAN sync): current state(state contains): {} contains: {}", *v, flags);
-                *v & flags != IsoTpState::Idle
-            },
-            Err(_) => {
-                log::warn!("ISO-TP(CAN async): state mutex is poisoned");
-                false
-            },
-        }
+        self.state.load(Ordering::Acquire).intersects(flags)
     }
 
     #[inline]
     fn state_append(&self, flags: IsoTpState) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                if flags == IsoTpState::Idle {
-                    *v = IsoTpState::Idle;
-                } else if flags.contains(IsoTpState::Error) {
-                    *v = IsoTpState::Error;
-                }
-                else {
-                    *v |= flags;
-                }
-
-                log::debug!("ISO-TP(CAN sync): current state(state append): {}", *v);
+        let result = self.state.fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+            if flags == IsoTpState::Idle {
+                Some(IsoTpState::Idle)
+            } else if flags.contains(IsoTpState::Error) {
+                Some(IsoTpState::Error)
             }
-            Err(_) => log::warn!("ISO-TP(CAN async): state mutex is poisoned"),
-        }
+            else {
+                Some(v | flags)
+            }
+        });
+        let (Ok(v) | Err(v)) = result;
+        trace::state("async", "append", v);
     }
 
     #[inline]
     fn state_remove(&self, flags: IsoTpState) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                v.remove(flags);
-                log::debug!("ISO-TP(CAN sync): current state(state remove): {}", *v);
-            },
-            Err(_) => log::warn!("ISO-TP(CAN async): state mutex is poisoned"),
-        }
+        let (Ok(v) | Err(v)) = self.state.fetch_remove(flags, Ordering::AcqRel, Ordering::Acquire);
+        trace::state("async", "remove", v);
     }
 }
@@ -1,5 +1,11 @@
-use crate::{FlowControlContext, IsoTpEvent};
-use crate::constant::CONSECUTIVE_SEQUENCE_START;
+//! Driven by the transports, which aren't compiled without the feature `std`.
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use alloc::{boxed::Box, vec::Vec};
+use core::time::Duration;
+use crate::{FlowControlContext, IsoTpEvent, SequenceStart};
+use crate::can::CanIsoTpFrame;
+use crate::constant::TIMEOUT_CR_ISO15765_2;
 use crate::error::Error;
 
 #[derive(Debug, Default, Clone)]
@@ -8,26 +14,155 @@ pub(crate) struct FlowCtrl {
     pub(crate) block_size: u8,
 }
 
+/// The frames of the writing not confirmed on the bus yet.
+#[derive(Debug, Default, Clone, Eq, PartialEq)]
+pub(crate) struct TxProgress {
+    /// The identifier of the frames.
+    pub(crate) id: u32,
+    /// The number of the writing, the frames sent are tagged with it.
+    pub(crate) transfer: u64,
+    pub(crate) frames: usize,
+    pub(crate) bytes: usize,
+    /// All the frames are sent to the transmit loop.
+    pub(crate) queued: bool,
+}
+
+/// The statistics of a writing, see [`SyncCanIsoTp::tx_stats`](crate::can::isotp::SyncCanIsoTp::tx_stats).
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct TxStats {
+    /// The length of the data.
+    pub bytes: usize,
+    pub frames: usize,
+    /// The flow controls to continue received.
+    pub flow_controls: usize,
+    /// The block size applied, 0 when no more flow control.
+    pub block_size: u8,
+    /// The separation time applied before each consecutive frame, a reserved STmin is taken as 127 ms.
+    pub st_min: Duration,
+}
+
+/// The statistics of the receiving, see [`SyncCanIsoTp::rx_stats`](crate::can::isotp::SyncCanIsoTp::rx_stats).
+#[derive(Debug, Default, Copy, Clone, Eq, PartialEq)]
+pub struct RxStats {
+    /// The flow controls replied and confirmed.
+    pub flow_controls: usize,
+    /// N_Br of the last flow control, from the frame replied(the first frame or the last consecutive
+    /// frame of a block) received to the flow control transmitted, by the frame timestamps.
+    pub n_br: Option<Duration>,
+    /// The longest N_Br measured.
+    pub max_n_br: Duration,
+    /// The flow controls transmitted later than the N_Br warning.
+    pub n_br_exceeded: usize,
+}
+
 /// Consecutive frame data context.
 #[derive(Debug, Default, Clone, Eq, PartialEq)]
 pub(crate) struct Consecutive {
     pub(crate) sequence: Option<u8>,
     pub(crate) length: Option<u32>,
     pub(crate) buffer: Vec<u8>,
+    /// The timestamp(ms) of the first frame.
+    pub(crate) first_frame_at: u64,
+    /// The timestamp(ms) of the last frame, N_Cr is supervised by the timestamps.
+    pub(crate) last_frame_at: u64,
+    /// The monotonic tick(ms) of the local clock when the last frame is received, supplied by the
+    /// caller, the stale receiving is expired by it.
+    pub(crate) received_at: Option<u64>,
+    /// The consecutive frames received in the current block.
+    pub(crate) block: u8,
 }
 
 #[derive(Debug, Default, Clone)]
 pub struct IsoTpContext {
     pub(crate) flow_ctrl: Option<FlowCtrl>,
     pub(crate) consecutive: Consecutive,
+    pub(crate) tx: Option<TxProgress>,
+    /// The previous writing awaiting the confirmation of its last frames while pipelining.
+    pub(crate) tx_previous: Option<TxProgress>,
+    /// The number of the last writing started.
+    pub(crate) transfers: u64,
+    /// The last flow control received, kept for [`SyncCanIsoTp::last_flow_control`](crate::can::isotp::SyncCanIsoTp::last_flow_control).
+    pub(crate) last_flow_ctrl: Option<FlowControlContext>,
+    /// The last flow control to continue of the peer, kept by [`reset`](Self::reset) until the next one.
+    pub(crate) peer_flow_ctrl: Option<FlowControlContext>,
+    /// The statistics of the current or the last writing.
+    pub(crate) tx_stats: TxStats,
+    /// The statistics of the receivings, never reset.
+    pub(crate) rx_stats: RxStats,
+    /// The timestamp(ms) of the frame replied by the flow control not confirmed yet.
+    pub(crate) flow_ctrl_due: Option<u64>,
+    /// The identifier of the writing by `write_to`, the frames transmitted to it are confirmed too.
+    pub(crate) write_to: Option<u32>,
+    /// The last error, returned by the writing when the state is error.
+    pub(crate) error: Option<Error>,
+    /// The FC WAIT received by the writing, N_Bs restarts by each one.
+    pub(crate) waits: usize,
 }
 
 impl IsoTpContext {
-    /// reset st_min/consecutive/block_size
+    /// reset st_min/consecutive/block_size, the flow control of the peer and the statistics are kept
     #[inline]
     pub(crate) fn reset(&mut self) {
         self.clear_flow_ctrl();
         self.clear_consecutive();
+        self.tx = Default::default();
+        self.tx_previous = Default::default();
+        self.last_flow_ctrl = Default::default();
+        self.write_to = Default::default();
+        self.error = Default::default();
+        self.waits = Default::default();
+    }
+    #[inline]
+    pub(crate) fn start_tx(&mut self, id: u32, frames: usize, bytes: usize) {
+        self.transfers += 1;
+        self.tx = Some(TxProgress { id, transfer: self.transfers, frames, bytes, queued: false });
+        self.tx_stats = TxStats { bytes, frames, ..Default::default() };
+    }
+    /// Keep the writing awaiting the confirmation of its last frames as the previous one, the others
+    /// are reset. Returns false when there's none.
+    pub(crate) fn pipeline(&mut self) -> bool {
+        let previous = self.tx.take()
+            .filter(|v| v.queued && v.frames > 0);
+        self.reset();
+        self.tx_previous = previous;
+        self.tx_previous.is_some()
+    }
+    /// Confirm a frame of the writing `transfer`, returns the length of the data when the last one confirmed.
+    pub(crate) fn confirm_tx(&mut self, transfer: u64) -> Option<usize> {
+        let slot = match &self.tx_previous {
+            Some(v) if v.transfer == transfer => &mut self.tx_previous,
+            _ => &mut self.tx,
+        };
+        let tx = slot.as_mut().filter(|v| v.transfer == transfer)?;
+        tx.frames = tx.frames.saturating_sub(1);
+        if tx.frames > 0 {
+            return None;
+        }
+
+        slot.take().map(|v| v.bytes)
+    }
+    /// Whether `transfer` is the previous writing.
+    #[inline]
+    pub(crate) fn is_previous(&self, transfer: u64) -> bool {
+        self.tx_previous.as_ref().is_some_and(|v| v.transfer == transfer)
+    }
+    /// The frames of the writing not confirmed yet.
+    #[inline]
+    pub(crate) fn unconfirmed(&self) -> usize {
+        self.tx.as_ref().map_or(0, |v| v.frames)
+    }
+    /// The consecutive frames sent together after `index` frames of the block when STmin is 0,
+    /// to the end of the block or all of them when the block size is 0.
+    pub(crate) fn burst_len(&self, index: usize) -> Option<usize> {
+        let ctx = self.flow_ctrl.as_ref()?;
+        if ctx.st_min != 0 {
+            return None;
+        }
+
+        match ctx.block_size {
+            0 => Some(usize::MAX),
+            v => Some((v as usize).saturating_sub(index).max(1)),
+        }
     }
     #[inline]
     pub(crate) fn clear_flow_ctrl(&mut self) {
@@ -39,46 +174,432 @@ impl IsoTpContext {
             st_min: ctx.st_min_us(),
             block_size: ctx.block_size(),
         });
+        self.peer_flow_ctrl = Some(ctx);
+        self.tx_stats.flow_controls += 1;
+        self.tx_stats.block_size = ctx.block_size();
+        self.tx_stats.st_min = Duration::from_micros(ctx.st_min_us() as u64);
+    }
+    /// Measure N_Br of the flow control transmitted at `timestamp`(ms), returns it when it's longer
+    /// than `warning`.
+    pub(crate) fn flow_ctrl_transmitted(&mut self, timestamp: u64, warning: Option<Duration>) -> Option<Duration> {
+        let due = self.flow_ctrl_due.take()?;
+        let n_br = Duration::from_millis(timestamp.saturating_sub(due));
+        self.rx_stats.flow_controls += 1;
+        self.rx_stats.n_br = Some(n_br);
+        self.rx_stats.max_n_br = self.rx_stats.max_n_br.max(n_br);
+        let exceeded = warning.is_some_and(|v| n_br > v);
+        if exceeded {
+            self.rx_stats.n_br_exceeded += 1;
+        }
+        exceeded.then_some(n_br)
     }
     #[inline]
     pub(crate) fn clear_consecutive(&mut self) {
         self.consecutive.sequence = Default::default();
         self.consecutive.length = Default::default();
         self.consecutive.buffer.clear();
+        self.consecutive.first_frame_at = Default::default();
+        self.consecutive.last_frame_at = Default::default();
+        self.consecutive.received_at = Default::default();
+        self.consecutive.block = Default::default();
+    }
+    /// Validate FF_DL of a first frame, the data longer than it fits in a single frame.
+    pub(crate) fn start_consecutive(&mut self, length: u32, data: &[u8], timestamp: u64) -> Result<(), Error> {
+        #[cfg(feature = "std2004")]
+        let max_len = crate::can::ISO_TP_MAX_LENGTH_2004;
+        #[cfg(feature = "std2016")]
+        let max_len = crate::can::ISO_TP_MAX_LENGTH_2016;
+
+        if length as usize <= data.len() || length as usize > max_len {
+            return Err(Error::LengthOutOfRange(length as usize));
+        }
+
+        self.update_consecutive(length, data, timestamp);
+        Ok(())
     }
     #[inline]
-    pub(crate) fn update_consecutive(&mut self, length: u32, mut data: Vec<u8>) {
+    pub(crate) fn update_consecutive(&mut self, length: u32, data: &[u8], timestamp: u64) {
+        self.clear_consecutive();
         self.consecutive.length = Some(length);
-        self.consecutive.buffer.append(&mut data);
+        self.consecutive.first_frame_at = timestamp;
+        self.consecutive.last_frame_at = timestamp;
+        self.consecutive.buffer.extend_from_slice(data);
     }
-    pub(crate) fn append_consecutive(&mut self, sequence: u8, mut data: Vec<u8>) -> Result<IsoTpEvent, Error> {
+    /// Record the monotonic tick(`now` ms) of a frame received while receiving the consecutive frames.
+    #[inline]
+    pub(crate) fn touch_consecutive(&mut self, now: u64) {
+        if self.consecutive.length.is_some() {
+            self.consecutive.received_at = Some(now);
+        }
+    }
+    /// No frame is received for N_Cr until the monotonic tick(`now` ms).
+    #[inline]
+    pub(crate) fn consecutive_expired(&self, now: u64) -> bool {
+        self.consecutive.received_at
+            .is_some_and(|v| now.saturating_sub(v) > TIMEOUT_CR_ISO15765_2 as u64)
+    }
+    /// Count a consecutive frame of the block, returns true when `block_size` frames are received.
+    pub(crate) fn block_received(&mut self, block_size: u8) -> bool {
+        if block_size == 0 {
+            return false;
+        }
+
+        self.consecutive.block += 1;
+        if self.consecutive.block < block_size {
+            return false;
+        }
+
+        self.consecutive.block = 0;
+        true
+    }
+    /// Move the partial data and the expected length out, the consecutive context is cleared.
+    pub(crate) fn take_consecutive(&mut self) -> Option<(Vec<u8>, u32)> {
+        let length = self.consecutive.length?;
+        let buffer = core::mem::take(&mut self.consecutive.buffer);
+        self.clear_consecutive();
+
+        Some((buffer, length))
+    }
+    /// Append a consecutive frame, the first one is expected by `start`.
+    pub(crate) fn append_consecutive(&mut self, start: SequenceStart, sequence: u8, data: &[u8], timestamp: u64) -> Result<IsoTpEvent, Error> {
         if self.consecutive.length.is_none() {
             return Err(Error::MixFramesError);
         }
+        if timestamp.saturating_sub(self.consecutive.last_frame_at) > TIMEOUT_CR_ISO15765_2 as u64 {
+            return Err(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
+        }
+        self.consecutive.last_frame_at = timestamp;
 
         let target = match self.consecutive.sequence {
             Some(v) => match v {
                 ..=0x0E => v + 1,
                 _ => 0,
             },
-            None => CONSECUTIVE_SEQUENCE_START
+            None => start.rx_sequence(sequence),
         };
         self.consecutive.sequence = Some(target);
         if sequence != target {
             return Err(Error::InvalidSequence { expect: target, actual: sequence });
         }
 
-        self.consecutive.buffer.append(&mut data);
+        self.consecutive.buffer.extend_from_slice(data);
 
         let buff_len = self.consecutive.buffer.len();
         let target_len = self.consecutive.length.unwrap() as usize;
         if buff_len >= target_len {
             self.consecutive.buffer.resize(target_len, 0);
-            let data = self.consecutive.buffer.clone();
-            Ok(IsoTpEvent::DataReceived(data))
+            let data = core::mem::take(&mut self.consecutive.buffer).into();
+            let first_frame_at = self.consecutive.first_frame_at;
+            // the receiving is completed
+            self.clear_consecutive();
+            Ok(IsoTpEvent::DataReceived { data, first_frame_at, completed_at: timestamp })
         }
         else {
             Ok(IsoTpEvent::Wait)
         }
     }
 }
+
+/// Reassemble the payloads of the decoded `frames` in order, the counterpart of
+/// [`IsoTpFrame::from_data`](crate::IsoTpFrame::from_data) for the frames of a log or a test.
+///
+/// The transfers are back-to-back, the flow controls between them are skipped. A transfer
+/// interrupted by another one, an unexpected sequence or a truncated one fails with
+/// [`Error::Frame`] of the index of the frame.
+pub fn assemble(frames: impl IntoIterator<Item = CanIsoTpFrame>) -> Result<Vec<Vec<u8>>, Error> {
+    let mut context = IsoTpContext::default();
+    let mut results = Vec::new();
+    let mut last = None;
+    for (index, frame) in frames.into_iter().enumerate() {
+        let at = |source| Error::Frame { index, source: Box::new(source) };
+        last = Some(index);
+        match frame {
+            CanIsoTpFrame::SingleFrame { data } => {
+                if context.consecutive.length.is_some() {
+                    return Err(at(Error::ReceptionRestarted));
+                }
+                if data.is_empty() {
+                    return Err(at(Error::EmptyPdu));
+                }
+                results.push(data.to_vec());
+            },
+            CanIsoTpFrame::FirstFrame { length, data } => {
+                if context.consecutive.length.is_some() {
+                    return Err(at(Error::ReceptionRestarted));
+                }
+                context.start_consecutive(length, &data, 0).map_err(at)?;
+            },
+            CanIsoTpFrame::ConsecutiveFrame { sequence, data } => {
+                if let IsoTpEvent::DataReceived { data, .. } = context.append_consecutive(Default::default(), sequence, &data, 0).map_err(at)? {
+                    results.push(data.to_vec());
+                }
+            },
+            CanIsoTpFrame::FlowControlFrame(_) => {},
+        }
+    }
+
+    match (context.take_consecutive(), last) {
+        (Some((partial, length)), Some(index)) => Err(Error::Frame {
+            index,
+            source: Box::new(Error::InvalidDataLength { actual: partial.len(), expect: length as usize }),
+        }),
+        _ => Ok(results),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
+    use std::time::Duration;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use hex_literal::hex;
+    use crate::{FlowControlState, IsoTpEvent, IsoTpFrame, SequenceStart};
+    use crate::can::{CAN_FRAME_MAX_SIZE, CanIsoTpFrame, CONSECUTIVE_FRAME_SIZE, DEFAULT_PADDING, FIRST_FRAME_SIZE_2004, FramePayload, ISO_TP_MAX_LENGTH_2004};
+    use crate::can::dlc::pad_len;
+    use crate::error::Error;
+    use crate::constant::TIMEOUT_CR_ISO15765_2;
+    use super::{assemble, IsoTpContext, RxStats};
+
+    /// The max data length of the frames transmitted.
+    #[cfg(not(feature = "can-fd"))]
+    const TX_DL: usize = CAN_FRAME_MAX_SIZE;
+    #[cfg(feature = "can-fd")]
+    const TX_DL: usize = crate::can::CANFD_FRAME_MAX_SIZE;
+
+    #[cfg(feature = "std2004")]
+    const SINGLE_FRAME_SIZE: usize = crate::can::SINGLE_FRAME_SIZE_2004;
+    #[cfg(feature = "std2004")]
+    const MAX_LENGTH: usize = ISO_TP_MAX_LENGTH_2004;
+    #[cfg(feature = "std2016")]
+    const SINGLE_FRAME_SIZE: usize = crate::can::SINGLE_FRAME_SIZE_2016;
+    #[cfg(feature = "std2016")]
+    const MAX_LENGTH: usize = crate::can::ISO_TP_MAX_LENGTH_2016;
+
+    /// Segment `data`, encode and decode each frame, then reassemble them by the context.
+    fn round_trip(data: &[u8], padding: Option<u8>) {
+        let length = data.len();
+        assert_eq!(CanIsoTpFrame::single_frame(data).is_ok(), length <= SINGLE_FRAME_SIZE, "{} bytes", length);
+        let frames = match CanIsoTpFrame::from_data(data) {
+            Ok(v) => v,
+            Err(e) => {
+                assert!(length > MAX_LENGTH, "{} bytes: {}", length, e);
+                assert_eq!(e, Error::LengthOutOfRange(length));
+                return;
+            },
+        };
+
+        let expect = match length {
+            ..=SINGLE_FRAME_SIZE => 1,
+            ..=ISO_TP_MAX_LENGTH_2004 => 1 + (length - FIRST_FRAME_SIZE_2004).div_ceil(CONSECUTIVE_FRAME_SIZE),
+            #[cfg(feature = "std2016")]
+            _ => 1 + (length - crate::can::FIRST_FRAME_SIZE_2016).div_ceil(CONSECUTIVE_FRAME_SIZE),
+            #[cfg(feature = "std2004")]
+            _ => unreachable!(),
+        };
+        assert_eq!(frames.len(), expect, "{} bytes", length);
+
+        let last = frames.len() - 1;
+        let mut context = IsoTpContext::default();
+        let mut received = None;
+        for (index, frame) in frames.into_iter().enumerate() {
+            let encoded = frame.encode(padding);
+            assert!(encoded.len() <= TX_DL, "{} bytes: frame {} of {} bytes", length, index, encoded.len());
+            assert_eq!(pad_len(encoded.len()), Some(encoded.len()), "{} bytes: frame {}", length, index);
+
+            let frame = CanIsoTpFrame::decode(&encoded)
+                .unwrap_or_else(|e| panic!("{} bytes: frame {} {:02X?}: {}", length, index, encoded, e));
+            match (index, frame) {
+                (0, CanIsoTpFrame::SingleFrame { data }) => {
+                    assert!(length <= SINGLE_FRAME_SIZE, "{} bytes: single frame", length);
+                    // the escape sequence beyond SF_DL of 4 bits
+                    assert_eq!(encoded[0] == 0x00, length >= CAN_FRAME_MAX_SIZE, "{} bytes: {:02X?}", length, encoded);
+                    received = Some(data.to_vec());
+                },
+                (0, CanIsoTpFrame::FirstFrame { length: ff_dl, data }) => {
+                    assert!(length > SINGLE_FRAME_SIZE, "{} bytes: first frame", length);
+                    assert_eq!(ff_dl as usize, length);
+                    // the escape sequence beyond FF_DL of 12 bits
+                    assert_eq!(encoded[..2] == [0x10, 0x00], length > ISO_TP_MAX_LENGTH_2004, "{} bytes: {:02X?}", length, encoded);
+                    context.update_consecutive(ff_dl, &data, 0);
+                },
+                (_, CanIsoTpFrame::ConsecutiveFrame { sequence, data }) => {
+                    match context.append_consecutive(Default::default(), sequence, &data, 0) {
+                        Ok(IsoTpEvent::Wait) => assert!(index < last, "{} bytes: incomplete", length),
+                        Ok(IsoTpEvent::DataReceived { data, .. }) => {
+                            assert_eq!(index, last, "{} bytes: completed by frame {}", length, index);
+                            received = Some(data.to_vec());
+                        },
+                        v => panic!("{} bytes: frame {}: {:?}", length, index, v),
+                    }
+                },
+                (_, v) => panic!("{} bytes: unexpected frame {}: {:?}", length, index, v),
+            }
+        }
+
+        assert_eq!(received.as_deref(), Some(data), "{} bytes", length);
+    }
+
+    /// Reassemble `frames` by the context expecting the first consecutive frame by `start`.
+    fn reassemble(start: SequenceStart, frames: Vec<CanIsoTpFrame>) -> Result<Vec<u8>, Error> {
+        let mut context = IsoTpContext::default();
+        for frame in frames {
+            match frame {
+                CanIsoTpFrame::FirstFrame { length, data } => context.update_consecutive(length, &data, 0),
+                CanIsoTpFrame::ConsecutiveFrame { sequence, data } => {
+                    if let IsoTpEvent::DataReceived { data, .. } = context.append_consecutive(start, sequence, &data, 0)? {
+                        return Ok(data.to_vec());
+                    }
+                },
+                frame => panic!("unexpected {:?}", frame),
+            }
+        }
+
+        Err(Error::ContextError("not completed".into()))
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_sequence_start() {
+        // 17 consecutive frames, the sequence wraps around
+        let data = (0..(FIRST_FRAME_SIZE_2004 + 17 * CONSECUTIVE_FRAME_SIZE) as u8).collect::<Vec<_>>();
+        let frames = |start: u8| CanIsoTpFrame::from_data_with_sequence(&data, start).unwrap();
+        let sequences = |frames: &[CanIsoTpFrame]| frames.iter()
+            .filter_map(|v| match v {
+                CanIsoTpFrame::ConsecutiveFrame { sequence, .. } => Some(*sequence),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sequences(&CanIsoTpFrame::from_data(&data).unwrap()), sequences(&frames(1)));
+        assert_eq!(sequences(&frames(1))[..], [1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0, 1]);
+        assert_eq!(sequences(&frames(0))[..], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, 11, 12, 13, 14, 15, 0]);
+        assert_eq!(sequences(&frames(0x10))[..2], [0, 1]);
+
+        // strict
+        assert_eq!(reassemble(SequenceStart::default(), frames(1)), Ok(data.clone()));
+        assert_eq!(reassemble(SequenceStart::default(), frames(0)), Err(Error::InvalidSequence { expect: 1, actual: 0 }));
+        assert_eq!(reassemble(SequenceStart::Strict(0), frames(0)), Ok(data.clone()));
+        assert_eq!(reassemble(SequenceStart::Strict(0), frames(1)), Err(Error::InvalidSequence { expect: 0, actual: 1 }));
+
+        // auto detect, locked onto the first one
+        assert_eq!(reassemble(SequenceStart::AutoDetect, frames(1)), Ok(data.clone()));
+        assert_eq!(reassemble(SequenceStart::AutoDetect, frames(0)), Ok(data.clone()));
+        assert_eq!(reassemble(SequenceStart::AutoDetect, frames(2)), Err(Error::InvalidSequence { expect: 1, actual: 2 }));
+        let mut mixed = frames(1);
+        mixed[1] = frames(0).swap_remove(1);
+        assert_eq!(reassemble(SequenceStart::AutoDetect, mixed), Err(Error::InvalidSequence { expect: 1, actual: 2 }));
+
+        assert_eq!(SequenceStart::default().tx_sequence(), 1);
+        assert_eq!(SequenceStart::AutoDetect.tx_sequence(), 1);
+        assert_eq!(SequenceStart::Strict(0).tx_sequence(), 0);
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_assemble() -> anyhow::Result<()> {
+        // back-to-back transfers, a flow control after each first frame
+        let payloads = [1, SINGLE_FRAME_SIZE, SINGLE_FRAME_SIZE + 1, 100, ISO_TP_MAX_LENGTH_2004]
+            .map(|length| (0..length).map(|v| (v * 3 + length) as u8).collect::<Vec<_>>());
+        let fc = CanIsoTpFrame::flow_ctrl_frame(FlowControlState::Continues, 0, 0)?;
+        let frames = payloads.iter()
+            .flat_map(|v| {
+                let mut frames = CanIsoTpFrame::from_data(v).unwrap();
+                if frames.len() > 1 {
+                    frames.insert(1, fc.clone());
+                }
+                frames
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(assemble(frames)?, payloads);
+        assert_eq!(assemble([])?, Vec::<Vec<u8>>::new());
+
+        // a request, the flow control of the server and the responses of a log
+        let log = [
+            hex!("10 0B 2E F1 90 31 32 33"),
+            hex!("30 00 00 AA AA AA AA AA"),
+            hex!("21 34 35 36 37 38 AA AA"),
+            hex!("03 6E F1 90 AA AA AA AA"),
+            hex!("02 3E 80 AA AA AA AA AA"),
+        ];
+        let frames = log.iter()
+            .map(CanIsoTpFrame::decode)
+            .collect::<Result<Vec<_>, _>>()?;
+        assert_eq!(assemble(frames.clone())?, vec![
+            hex!("2E F1 90 31 32 33 34 35 36 37 38").to_vec(),
+            hex!("6E F1 90").to_vec(),
+            hex!("3E 80").to_vec(),
+        ]);
+
+        let at = |index, source| Err(Error::Frame { index, source: Box::new(source) });
+        let cf = |sequence: u8| CanIsoTpFrame::ConsecutiveFrame { sequence, data: FramePayload::from_slice(&[0x34; 7]) };
+        assert_eq!(assemble([frames[0].clone(), cf(2)]), at(1, Error::InvalidSequence { expect: 1, actual: 2 }));
+        assert_eq!(assemble([frames[0].clone(), frames[1].clone()]), at(1, Error::InvalidDataLength { actual: 6, expect: 11 }));
+        assert_eq!(assemble([frames[3].clone(), cf(1)]), at(1, Error::MixFramesError));
+        assert_eq!(assemble([frames[0].clone(), frames[3].clone()]), at(1, Error::ReceptionRestarted));
+        let first = CanIsoTpFrame::FirstFrame { length: 3, data: FramePayload::from_slice(&[0x2E; 6]) };
+        assert_eq!(assemble([frames[3].clone(), first]), at(1, Error::LengthOutOfRange(3)));
+        Ok(())
+    }
+
+    #[test]
+    fn test_n_br() {
+        let mut context = IsoTpContext::default();
+        let warning = Some(Duration::from_millis(50));
+        // not replying
+        assert_eq!(context.flow_ctrl_transmitted(1_000, warning), None);
+        assert_eq!(context.rx_stats, RxStats::default());
+
+        context.flow_ctrl_due = Some(1_000);
+        assert_eq!(context.flow_ctrl_transmitted(1_020, warning), None);
+        context.flow_ctrl_due = Some(2_000);
+        assert_eq!(context.flow_ctrl_transmitted(2_080, warning), Some(Duration::from_millis(80)));
+        // confirmed once
+        assert_eq!(context.flow_ctrl_transmitted(2_090, warning), None);
+        context.flow_ctrl_due = Some(3_000);
+        assert_eq!(context.flow_ctrl_transmitted(3_010, None), None);
+        assert_eq!(context.rx_stats, RxStats {
+            flow_controls: 3,
+            n_br: Some(Duration::from_millis(10)),
+            max_n_br: Duration::from_millis(80),
+            n_br_exceeded: 1,
+        });
+    }
+
+    #[test]
+    fn test_consecutive_expired() -> anyhow::Result<()> {
+        let mut context = IsoTpContext::default();
+        // not receiving
+        context.touch_consecutive(1_000);
+        assert!(!context.consecutive_expired(10_000));
+
+        // the local ticks are independent of the timestamps of the frames
+        context.update_consecutive(0x14, &hex!("01 02 03 04 05 06"), 0);
+        context.touch_consecutive(1_000);
+        assert!(!context.consecutive_expired(1_000 + TIMEOUT_CR_ISO15765_2 as u64));
+        context.append_consecutive(Default::default(), 1, &hex!("07 08 09 0A 0B 0C 0D"), 0)?;
+        context.touch_consecutive(2_000);
+        assert!(!context.consecutive_expired(2_500));
+        assert!(context.consecutive_expired(2_001 + TIMEOUT_CR_ISO15765_2 as u64));
+
+        // completed
+        context.append_consecutive(Default::default(), 2, &hex!("0E 0F 10 11 12 13 14"), 0)?;
+        context.touch_consecutive(3_000);
+        assert!(!context.consecutive_expired(10_000));
+        Ok(())
+    }
+
+    #[test]
+    fn test_segmentation_boundaries() {
+        for length in 1..=8192 {
+            let data = (0..length).map(|v| (v * 7 + length) as u8).collect::<Vec<_>>();
+            round_trip(&data, Some(DEFAULT_PADDING));
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_segmentation_round_trip(data in vec(any::<u8>(), 1..=8192), padding in any::<Option<u8>>()) {
+            round_trip(&data, padding);
+        }
+    }
+}
@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+/// The most frames kept unconfirmed, a burst of the consecutive frames at most.
+const UNCONFIRMED_MAX: usize = 0xFF;
+
+/// The frames of a half-duplex instance waiting for the confirmation, its own frames echoed
+/// by a driver without the [`Direct`](crate::can::frame::Direct) are matched by the content.
+///
+/// A frame is kept from sent to confirmed only, a driver echoing the frames after the
+/// confirmation must flag them `Direct::Transmit`.
+#[derive(Debug, Default)]
+pub(crate) struct TxEcho {
+    unconfirmed: VecDeque<Vec<u8>>,
+    /// The driver is warned once.
+    warned: bool,
+}
+
+impl TxEcho {
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.unconfirmed.clear();
+    }
+    /// Keep a copy of the data sent.
+    pub(crate) fn sent(&mut self, data: &[u8]) {
+        if self.unconfirmed.len() >= UNCONFIRMED_MAX {
+            self.unconfirmed.pop_front();
+        }
+        self.unconfirmed.push_back(data.to_vec());
+    }
+    #[inline]
+    pub(crate) fn confirmed(&mut self, data: &[u8]) {
+        self.take(data);
+    }
+    /// Whether `data` received is the echo of an unconfirmed frame, it's matched once.
+    pub(crate) fn is_echo(&mut self, data: &[u8]) -> bool {
+        if !self.take(data) {
+            return false;
+        }
+
+        if !self.warned {
+            self.warned = true;
+            log::warn!("ISO-TP(CAN) - the driver echoes the transmitted frames as received, \
+                they're matched by the content while tx_id == rx_id");
+        }
+        true
+    }
+
+    fn take(&mut self, data: &[u8]) -> bool {
+        match self.unconfirmed.iter().position(|v| v == data) {
+            Some(index) => {
+                self.unconfirmed.remove(index);
+                true
+            },
+            None => false,
+        }
+    }
+}
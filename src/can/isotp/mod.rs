@@ -1,4 +1,6 @@
+#[cfg(feature = "std")]
 mod synchronous;
+#[cfg(feature = "std")]
 pub use synchronous::SyncCanIsoTp;
 
 #[cfg(feature = "tokio")]
@@ -7,3 +9,25 @@ mod asynchronous;
 pub use asynchronous::AsyncCanIsoTp;
 
 mod context;
+pub use context::{assemble, RxStats, TxStats};
+#[cfg(feature = "std")]
+mod echo;
+mod pending;
+mod reassembler;
+pub use reassembler::{EvictionPolicy, ReassembledPdu, Reassembler};
+#[cfg(feature = "std")]
+mod retry;
+#[cfg(feature = "std")]
+pub use retry::RetryPolicy;
+#[cfg(all(feature = "std", not(feature = "async")))]
+mod session;
+#[cfg(all(feature = "std", not(feature = "async")))]
+pub use session::{EndpointConfig, IsoTpSession, SessionManager};
+#[cfg(feature = "std")]
+mod tap;
+#[cfg(feature = "std")]
+pub use tap::{Direction, FrameTap};
+#[cfg(all(test, feature = "std", not(feature = "async")))]
+pub(crate) mod testing;
+#[cfg(feature = "std")]
+mod trace;
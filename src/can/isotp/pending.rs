@@ -0,0 +1,138 @@
+//! Driven by the transports, which aren't compiled without the feature `std`.
+#![cfg_attr(not(feature = "std"), allow(dead_code))]
+
+use alloc::{collections::VecDeque, vec::Vec};
+use crate::{FrameType, IsoTpFrame};
+use crate::can::CanIsoTpFrame;
+
+/// The most frames kept pending, a burst of the consecutive frames at most.
+const PENDING_MAX: usize = 0xFF;
+
+/// A frame sent by the instance and not confirmed by the driver yet.
+#[derive(Debug, Clone)]
+struct Pending {
+    id: u32,
+    kind: FrameType,
+    /// The generation of the identifier when sent, see [`TxGenerations`](crate::can::driver::TxGenerations).
+    generation: u64,
+    /// The number of the writing sent by.
+    transfer: u64,
+    /// The sequence of a consecutive frame.
+    sequence: Option<u8>,
+    data: Vec<u8>,
+}
+
+/// The frames of the instance waiting for the confirmation, in the order sent.
+///
+/// The confirmations are matched by the identifier and the content of the echoed frame, so
+/// the frames of the same identifier not sent by the instance, e.g. a keep-alive single frame
+/// of [`SyncCan::add_cyclic`](crate::can::driver::SyncCan::add_cyclic), don't confirm its ones.
+#[derive(Debug, Default)]
+pub(crate) struct TxPending {
+    pending: VecDeque<Pending>,
+}
+
+impl TxPending {
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.pending.clear();
+    }
+    /// Keep the frame sent, a retransmitted one replaces the failed one still pending. The same frame
+    /// of the next writing queued behind it is kept too.
+    pub(crate) fn sent(&mut self, id: u32, generation: u64, transfer: u64, data: &[u8]) {
+        if self.pending.iter().any(|v| v.id == id && v.transfer == transfer && v.data == data) {
+            return;
+        }
+        let Ok(frame) = CanIsoTpFrame::decode(data) else {
+            log::debug!("ISO-TP(CAN) - not an ISO-TP frame sent: {:02X?}", data);
+            return;
+        };
+
+        if self.pending.len() >= PENDING_MAX {
+            self.pending.pop_front();
+        }
+        let sequence = match &frame {
+            CanIsoTpFrame::ConsecutiveFrame { sequence, .. } => Some(*sequence),
+            _ => None,
+        };
+        self.pending.push_back(Pending {
+            id,
+            kind: (&frame).into(),
+            generation,
+            transfer,
+            sequence,
+            data: data.to_vec(),
+        });
+    }
+    /// Take the first pending frame matched by the confirmation, returns its kind and writing.
+    ///
+    /// The echoed data may be padded to the length of the CAN FD frame by the driver.
+    pub(crate) fn confirmed(&mut self, id: u32, data: &[u8]) -> Option<(FrameType, u64)> {
+        let index = self.pending.iter()
+            .position(|v| v.id == id && data.starts_with(&v.data))?;
+        let pending = self.pending.remove(index)?;
+        if let Some(sequence) = pending.sequence {
+            log::trace!("ISO-TP(CAN) - consecutive frame {} of {:04X} confirmed", sequence, id);
+        }
+        Some((pending.kind, pending.transfer))
+    }
+    /// Take the first pending frame of `id` failed by the driver, the frames are transmitted in order.
+    /// Returns its writing.
+    pub(crate) fn failed(&mut self, id: u32) -> Option<u64> {
+        let index = self.pending.iter().position(|v| v.id == id)?;
+        self.pending.remove(index).map(|v| v.transfer)
+    }
+    /// Drop the frames of `id` superseded by `generation`, the transmit loop never transmits them.
+    pub(crate) fn supersede(&mut self, id: u32, generation: u64) {
+        self.pending.retain(|v| v.id != id || v.generation >= generation);
+    }
+    /// Whether no frame is waiting for the confirmation.
+    #[inline]
+    pub(crate) fn is_empty(&self) -> bool {
+        self.pending.is_empty()
+    }
+}
+
+#[cfg(all(test, not(feature = "can-fd")))]
+mod tests {
+    use crate::FrameType;
+    use super::TxPending;
+
+    #[test]
+    fn test_tx_pending() {
+        let mut pending = TxPending::default();
+        pending.sent(0x7E0, 0, 1, &[0x10, 0x14, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]);
+        pending.sent(0x7E0, 0, 1, &[0x21, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D]);
+
+        // the keep-alive of the same identifier isn't sent by the instance
+        assert_eq!(pending.confirmed(0x7E0, &[0x02, 0x3E, 0x80, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]), None);
+        assert_eq!(pending.confirmed(0x7E8, &[0x21, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D]), None);
+        assert_eq!(pending.confirmed(0x7E0, &[0x21, 0x07, 0x08, 0x09, 0x0A, 0x0B, 0x0C, 0x0D]), Some((FrameType::Consecutive, 1)));
+        assert!(!pending.is_empty());
+        assert_eq!(pending.confirmed(0x7E0, &[0x10, 0x14, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06]), Some((FrameType::First, 1)));
+        assert!(pending.is_empty());
+
+        // retransmitted
+        pending.sent(0x7E0, 0, 1, &[0x30, 0x00, 0x00]);
+        pending.sent(0x7E0, 0, 1, &[0x30, 0x00, 0x00]);
+        // padded by the driver
+        assert_eq!(pending.confirmed(0x7E0, &[0x30, 0x00, 0x00, 0xAA, 0xAA]), Some((FrameType::FlowControl, 1)));
+        assert!(pending.is_empty());
+
+        pending.sent(0x7E0, 0, 1, &[0x21, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]);
+        pending.sent(0x7E0, 1, 1, &[0x02, 0x10, 0x01]);
+        pending.supersede(0x7E0, 1);
+        assert_eq!(pending.confirmed(0x7E0, &[0x21, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]), None);
+        assert_eq!(pending.confirmed(0x7E0, &[0x02, 0x10, 0x01]), Some((FrameType::Single, 1)));
+
+        // the frames of the pipelined writings
+        pending.sent(0x7E0, 1, 1, &[0x21, 0x01, 0x02, 0x03, 0x04, 0x05, 0x06, 0x07]);
+        pending.sent(0x7E0, 1, 2, &[0x02, 0x10, 0x01]);
+        pending.sent(0x7E0, 1, 3, &[0x02, 0x10, 0x01]);
+        assert_eq!(pending.failed(0x7E8), None);
+        assert_eq!(pending.failed(0x7E0), Some(1));
+        assert_eq!(pending.confirmed(0x7E0, &[0x02, 0x10, 0x01]), Some((FrameType::Single, 2)));
+        assert_eq!(pending.confirmed(0x7E0, &[0x02, 0x10, 0x01]), Some((FrameType::Single, 3)));
+        assert!(pending.is_empty());
+    }
+}
@@ -0,0 +1,326 @@
+use alloc::{collections::BTreeMap, vec::Vec};
+use core::time::Duration;
+use crate::can::CanIsoTpFrame;
+use crate::constant::{CONSECUTIVE_SEQUENCE_START, TIMEOUT_CR_ISO15765_2};
+
+/// The default memory of the transfers in progress, 16 of the max length of ISO 15765-2:2004.
+const DEFAULT_MAX_MEMORY: usize = 16 * 0xFFF;
+
+/// How the [`Reassembler`] makes room for a new transfer when the memory is exhausted.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    /// Evict the transfers idle for the longest until the new one fits.
+    #[default]
+    Oldest,
+    /// Reject the new transfer, the ones in progress are kept until completed or expired.
+    RejectNew,
+}
+
+/// A payload reassembled by the [`Reassembler`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReassembledPdu {
+    /// The key of the frames, e.g. the arbitration id.
+    pub id: u32,
+    pub data: Vec<u8>,
+}
+
+#[derive(Debug)]
+struct Transfer {
+    length: usize,
+    sequence: u8,
+    buffer: Vec<u8>,
+    /// The tick(ms) of the last frame.
+    last: u64,
+}
+
+/// Reassemble the interleaved ISO-TP transfers, keyed by an arbitrary id such as the arbitration id.
+///
+/// Each key has its own reception, a transfer is discarded when no consecutive frame is received
+/// in N_Cr. The memory is the sum of the lengths of the first frames in progress, it's bounded and
+/// the [`EvictionPolicy`] decides the transfer dropped when a new one doesn't fit.
+#[derive(Debug)]
+pub struct Reassembler {
+    timeout: Duration,
+    max_memory: usize,
+    eviction: EvictionPolicy,
+    memory: usize,
+    transfers: BTreeMap<u32, Transfer>,
+}
+
+impl Default for Reassembler {
+    fn default() -> Self {
+        Self {
+            timeout: Duration::from_millis(TIMEOUT_CR_ISO15765_2 as u64),
+            max_memory: DEFAULT_MAX_MEMORY,
+            eviction: Default::default(),
+            memory: Default::default(),
+            transfers: Default::default(),
+        }
+    }
+}
+
+impl Reassembler {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set N_Cr, the max interval between the frames of a transfer.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the max memory of the transfers in progress, a first frame longer than it is ignored.
+    pub fn with_max_memory(mut self, max_memory: usize) -> Self {
+        self.max_memory = max_memory;
+        self
+    }
+
+    pub fn with_eviction(mut self, eviction: EvictionPolicy) -> Self {
+        self.eviction = eviction;
+        self
+    }
+
+    /// The transfers in progress.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.transfers.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.transfers.is_empty()
+    }
+
+    /// The memory reserved by the transfers in progress.
+    #[inline]
+    pub fn memory(&self) -> usize {
+        self.memory
+    }
+
+    /// Feed a frame of `id` received now, returns the payload when the transfer is completed.
+    #[cfg(feature = "std")]
+    #[inline]
+    pub fn push(&mut self, id: u32, frame: CanIsoTpFrame) -> Option<ReassembledPdu> {
+        self.push_at(id, frame, crate::can::frame::tick_millis())
+    }
+
+    /// Feed a frame of `id` received at the monotonic tick `now`(ms), returns the payload when
+    /// the transfer is completed.
+    pub fn push_at(&mut self, id: u32, frame: CanIsoTpFrame, now: u64) -> Option<ReassembledPdu> {
+        self.expire(now);
+
+        match frame {
+            CanIsoTpFrame::SingleFrame { data } => {
+                if self.remove(id).is_some() {
+                    log::warn!("ISO-TP(reassembler) - transfer of {:08X} aborted by a single frame", id);
+                }
+                if data.is_empty() {
+                    log::warn!("ISO-TP(reassembler) - empty single frame of {:08X} is dropped", id);
+                    return None;
+                }
+                Some(ReassembledPdu { id, data: data.to_vec() })
+            },
+            CanIsoTpFrame::FirstFrame { length, data } => {
+                if self.remove(id).is_some() {
+                    log::debug!("ISO-TP(reassembler) - transfer of {:08X} restarted", id);
+                }
+                self.start(id, length as usize, &data, now);
+                None
+            },
+            CanIsoTpFrame::ConsecutiveFrame { sequence, data } => self.append(id, sequence, &data, now),
+            CanIsoTpFrame::FlowControlFrame(_) => None,
+        }
+    }
+
+    /// Discard the transfers without a frame received in N_Cr before the tick `now`(ms), returns the count of them.
+    pub fn expire(&mut self, now: u64) -> usize {
+        let timeout = self.timeout.as_millis() as u64;
+        let expired = self.transfers.iter()
+            .filter(|(_, v)| now.saturating_sub(v.last) > timeout)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        for id in &expired {
+            log::warn!("ISO-TP(reassembler) - transfer of {:08X} timed out", id);
+            self.remove(*id);
+        }
+
+        expired.len()
+    }
+
+    fn start(&mut self, id: u32, length: usize, data: &[u8], now: u64) {
+        if length > self.max_memory {
+            log::warn!("ISO-TP(reassembler) - transfer of {:08X} length {} exceeds the memory {}", id, length, self.max_memory);
+            return;
+        }
+
+        while self.memory + length > self.max_memory {
+            match self.eviction {
+                EvictionPolicy::Oldest => {
+                    let Some(oldest) = self.transfers.iter()
+                        .min_by_key(|(_, v)| v.last)
+                        .map(|(k, _)| *k) else { break; };
+                    log::warn!("ISO-TP(reassembler) - transfer of {:08X} evicted by {:08X}", oldest, id);
+                    self.remove(oldest);
+                },
+                EvictionPolicy::RejectNew => {
+                    log::warn!("ISO-TP(reassembler) - transfer of {:08X} rejected, the memory is exhausted", id);
+                    return;
+                },
+            }
+        }
+
+        self.memory += length;
+        self.transfers.insert(id, Transfer {
+            length,
+            sequence: CONSECUTIVE_SEQUENCE_START,
+            buffer: data.to_vec(),
+            last: now,
+        });
+    }
+
+    fn append(&mut self, id: u32, sequence: u8, data: &[u8], now: u64) -> Option<ReassembledPdu> {
+        let Some(transfer) = self.transfers.get_mut(&id) else {
+            log::trace!("ISO-TP(reassembler) - consecutive frame of {:08X} without first frame", id);
+            return None;
+        };
+        if sequence != transfer.sequence {
+            log::warn!("ISO-TP(reassembler) - transfer of {:08X} expect sequence {}, got {}", id, transfer.sequence, sequence);
+            self.remove(id);
+            return None;
+        }
+
+        transfer.sequence = (transfer.sequence + 1) & 0x0F;
+        transfer.buffer.extend_from_slice(data);
+        transfer.last = now;
+        if transfer.buffer.len() < transfer.length {
+            return None;
+        }
+
+        let mut transfer = self.remove(id)?;
+        transfer.buffer.truncate(transfer.length);
+        Some(ReassembledPdu { id, data: transfer.buffer })
+    }
+
+    fn remove(&mut self, id: u32) -> Option<Transfer> {
+        let transfer = self.transfers.remove(&id)?;
+        self.memory -= transfer.length;
+        Some(transfer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
+    use std::time::Duration;
+    use crate::can::CanIsoTpFrame;
+    use crate::IsoTpFrame;
+    use super::{EvictionPolicy, ReassembledPdu, Reassembler};
+
+    fn payload(seed: u8, len: usize) -> Vec<u8> {
+        (0..len).map(|v| seed.wrapping_add(v as u8)).collect()
+    }
+
+    #[test]
+    fn test_interleaved() -> anyhow::Result<()> {
+        let payloads = [
+            (0x7E8, payload(0x10, 40)),
+            (0x7E9, payload(0x20, 150)),
+            (0x18DAF110, payload(0x30, 20)),
+        ];
+        let mut frames = payloads.iter()
+            .map(|(id, data)| Ok((*id, CanIsoTpFrame::from_data(data)?.into_iter())))
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        let mut reassembler = Reassembler::new();
+        let mut results = Vec::new();
+        // one frame of each transfer in turn
+        while frames.iter().any(|(_, v)| v.len() > 0) {
+            for (id, iter) in frames.iter_mut() {
+                if let Some(frame) = iter.next() {
+                    results.extend(reassembler.push(*id, frame));
+                }
+            }
+        }
+
+        assert_eq!(results.len(), 3);
+        for (id, data) in payloads {
+            assert!(results.contains(&ReassembledPdu { id, data }));
+        }
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.memory(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_timeout() -> anyhow::Result<()> {
+        let frames = CanIsoTpFrame::from_data(payload(0, 20))?;
+        let mut reassembler = Reassembler::new()
+            .with_timeout(Duration::from_millis(100));
+        let now = 1_000;
+
+        assert_eq!(reassembler.push_at(0x7E8, frames[0].clone(), now), None);
+        assert_eq!(reassembler.push_at(0x7E8, frames[1].clone(), now + 50), None);
+        assert_eq!(reassembler.len(), 1);
+        // N_Cr elapsed since the last frame
+        assert_eq!(reassembler.push_at(0x7E8, frames[2].clone(), now + 200), None);
+        assert!(reassembler.is_empty());
+        assert_eq!(reassembler.memory(), 0);
+
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_sequence() -> anyhow::Result<()> {
+        let frames = CanIsoTpFrame::from_data(payload(0, 20))?;
+        let mut reassembler = Reassembler::new();
+
+        assert_eq!(reassembler.push(0x7E8, frames[0].clone()), None);
+        assert_eq!(reassembler.push(0x7E8, frames[2].clone()), None);
+        assert!(reassembler.is_empty());
+        // a single frame is delivered directly
+        let single = CanIsoTpFrame::decode([0x02, 0x01, 0x02, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA])?;
+        assert_eq!(reassembler.push(0x7E8, single), Some(ReassembledPdu { id: 0x7E8, data: vec![0x01, 0x02] }));
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_eviction() -> anyhow::Result<()> {
+        let frames = CanIsoTpFrame::from_data(payload(0, 100))?;
+        let now = 1_000;
+
+        let mut reassembler = Reassembler::new()
+            .with_max_memory(250);
+        for (i, id) in [0x7E8, 0x7E9, 0x7EA].into_iter().enumerate() {
+            reassembler.push_at(id, frames[0].clone(), now + i as u64);
+        }
+        // the idle one is evicted
+        assert_eq!(reassembler.len(), 2);
+        assert_eq!(reassembler.memory(), 200);
+        assert_eq!(reassembler.push_at(0x7E8, frames[1].clone(), now), None);
+        assert_eq!(reassembler.len(), 2);
+
+        let mut reassembler = Reassembler::new()
+            .with_max_memory(250)
+            .with_eviction(EvictionPolicy::RejectNew);
+        for id in [0x7E8, 0x7E9, 0x7EA] {
+            reassembler.push_at(id, frames[0].clone(), now);
+        }
+        assert_eq!(reassembler.len(), 2);
+        let mut result = None;
+        for frame in frames.iter().skip(1) {
+            result = reassembler.push_at(0x7E8, frame.clone(), now);
+        }
+        assert_eq!(result.map(|v| v.data), Some(payload(0, 100)));
+
+        // longer than the whole memory
+        reassembler.push_at(0x7EA, CanIsoTpFrame::from_data(payload(0, 300))?.remove(0), now);
+        assert_eq!(reassembler.len(), 1);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,101 @@
+use std::time::{Duration, Instant};
+
+/// How the writing retransmits a frame failed by the device, e.g. the TX buffer of the USB adapter is full.
+///
+/// The frames are transmitted one by one while it's enabled, so the failed frame is retransmitted
+/// before the next one and the sequence and block accounting are kept.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct RetryPolicy {
+    /// The retries of a frame before the writing fails, disabled when 0.
+    pub max_retries: u32,
+    /// The delay before retransmitting the failed frame.
+    pub backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 0,
+            backoff: Duration::from_millis(10),
+        }
+    }
+}
+
+impl RetryPolicy {
+    #[inline]
+    pub fn new(max_retries: u32, backoff: Duration) -> Self {
+        Self { max_retries, backoff }
+    }
+
+    #[inline]
+    pub fn is_enabled(&self) -> bool {
+        self.max_retries > 0
+    }
+}
+
+/// The frame of the writing waiting for the confirmation, kept for retransmitting.
+#[derive(Debug)]
+pub(crate) struct TxRetry<F> {
+    frame: Option<F>,
+    /// The backoff of the failed frame elapses at.
+    retry_at: Option<Instant>,
+    /// The retries of the frame.
+    attempts: u32,
+    /// The frames retransmitted since created.
+    retries: u64,
+}
+
+impl<F> Default for TxRetry<F> {
+    fn default() -> Self {
+        Self {
+            frame: None,
+            retry_at: None,
+            attempts: 0,
+            retries: 0,
+        }
+    }
+}
+
+impl<F: Clone> TxRetry<F> {
+    #[inline]
+    pub(crate) fn clear(&mut self) {
+        self.frame = None;
+        self.retry_at = None;
+        self.attempts = 0;
+    }
+    /// Keep a copy of the new frame sent.
+    #[inline]
+    pub(crate) fn sent(&mut self, frame: &F) {
+        self.clear();
+        self.frame = Some(frame.clone());
+    }
+    #[inline]
+    pub(crate) fn confirmed(&mut self) {
+        self.clear();
+    }
+    /// Schedule retransmitting the frame, returns the attempt or `None` when the retries are exhausted.
+    pub(crate) fn failed(&mut self, policy: &RetryPolicy) -> Option<u32> {
+        if self.frame.is_none() || self.attempts >= policy.max_retries {
+            return None;
+        }
+
+        self.attempts += 1;
+        self.retry_at = Some(Instant::now() + policy.backoff);
+        Some(self.attempts)
+    }
+    /// The frame to retransmit when the backoff elapsed, it's counted as a retry.
+    pub(crate) fn take_due(&mut self) -> Option<F> {
+        match self.retry_at {
+            Some(at) if at <= Instant::now() => {},
+            _ => return None,
+        }
+
+        self.retry_at = None;
+        self.retries += 1;
+        self.frame.clone()
+    }
+    #[inline]
+    pub(crate) fn retries(&self) -> u64 {
+        self.retries
+    }
+}
@@ -0,0 +1,561 @@
+//! The ISO-TP sessions of the ECUs on a [`SyncCan`], each one is a [`SyncCanIsoTp`] registered
+//! as a listener and configured by an [`EndpointConfig`], e.g. loaded from the config file of a
+//! workshop tool.
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver, RecvTimeoutError};
+use std::time::{Duration, Instant};
+use crate::{IsoTpEvent, IsoTpEventListener, IsoTpFrame};
+use crate::can::{Address, CanIsoTpFrame};
+use crate::can::driver::{CyclicHandle, SyncCan};
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::isotp::SyncCanIsoTp;
+use crate::constant::{P2_ISO14229, P2_STAR_ISO14229};
+use crate::device::{Channel, Driver};
+use crate::error::Error;
+
+type Responses = Receiver<Result<Vec<u8>, Error>>;
+
+/// The functional TesterPresent with the response suppressed.
+const TESTER_PRESENT: [u8; 2] = [0x3E, 0x80];
+
+#[inline]
+fn default_p2_ms() -> u16 {
+    P2_ISO14229
+}
+
+#[inline]
+fn default_p2_star_ms() -> u32 {
+    P2_STAR_ISO14229
+}
+
+/// The addressing of an ECU.
+///
+/// `fd` and `standard` are checked against the features `can-fd`, `std2004` and `std2016`
+/// the crate is built with, they can't differ between the endpoints.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct EndpointConfig {
+    pub name: String,
+    /// The request identifier.
+    pub tx_id: u32,
+    /// The response identifier.
+    pub rx_id: u32,
+    /// The functional identifier, the functional requests fail without it.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fid: Option<u32>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub fd: bool,
+    /// The version of ISO 15765-2, `2004` or `2016`, the built one if `None`.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub standard: Option<u16>,
+    /// The time(ms) the response is waited.
+    #[cfg_attr(feature = "serde", serde(default = "default_p2_ms"))]
+    pub p2_ms: u16,
+    /// The time(ms) the response is waited after a response pending(NRC 0x78).
+    #[cfg_attr(feature = "serde", serde(default = "default_p2_star_ms"))]
+    pub p2_star_ms: u32,
+}
+
+impl EndpointConfig {
+    pub fn new(name: impl Into<String>, tx_id: u32, rx_id: u32, fid: Option<u32>) -> Self {
+        Self {
+            name: name.into(),
+            tx_id,
+            rx_id,
+            fid,
+            fd: cfg!(feature = "can-fd"),
+            standard: None,
+            p2_ms: default_p2_ms(),
+            p2_star_ms: default_p2_star_ms(),
+        }
+    }
+
+    /// Check the config against the build of the crate.
+    fn validate(&self) -> Result<(), Error> {
+        if self.fd != cfg!(feature = "can-fd") {
+            return Err(Error::Unsupported(format!("CAN-FD({}) of endpoint `{}` by the build", self.fd, self.name)));
+        }
+
+        let built = if cfg!(feature = "std2016") { 2016 } else { 2004 };
+        match self.standard {
+            Some(v) if v != built =>
+                Err(Error::Unsupported(format!("ISO 15765-2:{} of endpoint `{}` by the build", v, self.name))),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The handle of an endpoint of [`SessionManager`], cloned to share it between the threads.
+#[derive(Clone)]
+pub struct IsoTpSession<C, F> {
+    config: EndpointConfig,
+    iso_tp: SyncCanIsoTp<C, F>,
+    /// The received data and errors, locked by a request until its response.
+    responses: Arc<Mutex<Responses>>,
+}
+
+impl<C: Channel, F: FrameMut<Channel = C> + Clone + 'static> IsoTpSession<C, F> {
+    #[inline]
+    pub fn name(&self) -> &str {
+        &self.config.name
+    }
+
+    #[inline]
+    pub fn config(&self) -> &EndpointConfig {
+        &self.config
+    }
+
+    /// The transport of the endpoint.
+    #[inline]
+    pub fn iso_tp(&self) -> &SyncCanIsoTp<C, F> {
+        &self.iso_tp
+    }
+
+    /// Write `data` to the request or the functional identifier, see [`SyncCanIsoTp::write`].
+    pub fn write(&self, functional: bool, data: Vec<u8>) -> Result<(), Error> {
+        if functional && self.config.fid.is_none() {
+            return Err(Error::InvalidParam(format!("endpoint `{}` has no functional identifier", self.config.name)));
+        }
+
+        self.iso_tp.write(functional, data)
+    }
+
+    /// Write the physical request and wait for its final response within P2,
+    /// extended to P2* by the response pending(NRC 0x78).
+    ///
+    /// The final response is the positive one(`service + 0x40`) or the negative one(`0x7F service`),
+    /// the others, e.g. the responses of other services, are discarded without restarting the timeout.
+    /// The requests of a session are serialized, the responses left by the timed out ones are discarded.
+    pub fn request(&self, request: &[u8]) -> Result<Vec<u8>, Error> {
+        let service = *request.first()
+            .ok_or(Error::InvalidParam("empty request".into()))?;
+        let responses = self.responses.lock()
+            .map_err(|_| Error::ContextError("can't get `responses`".into()))?;
+        while responses.try_recv().is_ok() {}
+
+        self.write(false, request.to_vec())?;
+        let mut timeout = self.config.p2_ms as u64;
+        let mut deadline = Instant::now() + Duration::from_millis(timeout);
+        loop {
+            let response = match responses.recv_timeout(deadline.saturating_duration_since(Instant::now())) {
+                Ok(v) => v?,
+                Err(RecvTimeoutError::Timeout) => return Err(Error::Timeout { value: timeout, unit: "ms" }),
+                Err(RecvTimeoutError::Disconnected) =>
+                    return Err(Error::ContextError(format!("the listener of endpoint `{}` is dropped", self.config.name))),
+            };
+            match response.as_slice() {
+                [0x7F, sid, 0x78] if *sid == service => {
+                    log::debug!("Session - {} response pending", self.config.name);
+                    timeout = self.config.p2_star_ms as u64;
+                    deadline = Instant::now() + Duration::from_millis(timeout);
+                },
+                [0x7F, sid, ..] if *sid == service => return Ok(response),
+                [sid, ..] if service.checked_add(0x40) == Some(*sid) => return Ok(response),
+                _ => log::warn!("Session - {} discards the response {:02X?} to another request", self.config.name, response),
+            }
+        }
+    }
+}
+
+/// The TesterPresent broadcast by [`SessionManager::keep_alive`].
+#[derive(Default)]
+struct KeepAlive {
+    names: Vec<String>,
+    period: Duration,
+    handles: Vec<CyclicHandle>,
+}
+
+/// The endpoints of the ECUs on a channel of a [`SyncCan`], they can be added and removed
+/// while the loops are running.
+///
+/// The functional TesterPresent of the endpoints is transmitted by the cyclic frames of the
+/// [`SyncCan`], see [`Self::keep_alive`].
+pub struct SessionManager<D, C, F> {
+    can: SyncCan<D, C, F>,
+    channel: C,
+    sessions: Mutex<HashMap<String, IsoTpSession<C, F>>>,
+    keep_alive: Mutex<KeepAlive>,
+}
+
+impl<D, C, F> SessionManager<D, C, F>
+where
+    D: Driver<C = C, F = F> + Clone + 'static,
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Send + Display + 'static,
+{
+    /// The endpoints are registered to `can`, the clones of which share the listeners.
+    pub fn new(can: &SyncCan<D, C, F>, channel: C) -> Self {
+        Self {
+            can: can.clone(),
+            channel,
+            sessions: Default::default(),
+            keep_alive: Default::default(),
+        }
+    }
+
+    /// Create the manager with `endpoints`, the added ones are removed on the first invalid one.
+    pub fn with_endpoints(can: &SyncCan<D, C, F>, channel: C, endpoints: &[EndpointConfig]) -> Result<Self, Error> {
+        let result = Self::new(can, channel);
+        for config in endpoints {
+            if let Err(e) = result.add_endpoint(config.clone()) {
+                result.clear();
+                return Err(e);
+            }
+        }
+
+        Ok(result)
+    }
+
+    /// The name of the listener registered for the endpoint `name`.
+    #[inline]
+    fn listener_name(name: &str) -> String {
+        format!("session-{}", name)
+    }
+
+    /// Create the endpoint and register its transport.
+    pub fn add_endpoint(&self, config: EndpointConfig) -> Result<IsoTpSession<C, F>, Error> {
+        config.validate()?;
+        let mut sessions = self.sessions.lock()
+            .map_err(|_| Error::ContextError("can't get `sessions`".into()))?;
+        if sessions.contains_key(&config.name) {
+            return Err(Error::InvalidParam(format!("endpoint `{}` is added", config.name)));
+        }
+
+        let (tx, rx) = channel();
+        let address = Address { tx_id: config.tx_id, rx_id: config.rx_id, fid: config.fid.unwrap_or(config.tx_id) };
+        let iso_tp = SyncCanIsoTp::new(
+            self.channel.clone(),
+            address,
+            self.can.sender(),
+            <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+                let result = match event {
+                    IsoTpEvent::ErrorOccurred(e) => Err(e),
+                    event => match event.into_data() {
+                        Some(data) => Ok(data),
+                        None => return,
+                    },
+                };
+                let _ = tx.send(result);
+            }),
+        ).with_capabilities(self.can.capabilities())?;
+        log::debug!("Session - add endpoint {}: {:?}", config.name, address);
+        self.can.register_listener(Self::listener_name(&config.name), Box::new(iso_tp.clone()));
+
+        let session = IsoTpSession { config, iso_tp, responses: Arc::new(Mutex::new(rx)) };
+        sessions.insert(session.config.name.clone(), session.clone());
+        Ok(session)
+    }
+
+    /// Remove the endpoint and unregister its transport, the requests waiting time out.
+    pub fn remove_endpoint(&self, name: &str) -> bool {
+        let removed = match self.sessions.lock() {
+            Ok(mut sessions) => sessions.remove(name).is_some(),
+            Err(_) => false,
+        };
+        if removed {
+            log::debug!("Session - remove endpoint {}", name);
+            self.can.unregister_listener(Self::listener_name(name));
+            if let Ok(mut keep_alive) = self.keep_alive.lock() {
+                if keep_alive.names.iter().any(|v| v == name) {
+                    keep_alive.names.retain(|v| v != name);
+                    self.schedule(&mut keep_alive);
+                }
+            }
+        }
+
+        removed
+    }
+
+    /// Remove all the endpoints and stop the broadcast of [`Self::keep_alive`].
+    pub fn clear(&self) {
+        if let Ok(mut keep_alive) = self.keep_alive.lock() {
+            keep_alive.names.clear();
+            self.schedule(&mut keep_alive);
+        }
+        self.names().iter()
+            .for_each(|v| { self.remove_endpoint(v); });
+    }
+
+    /// The session of the endpoint `name`.
+    pub fn session(&self, name: &str) -> Option<IsoTpSession<C, F>> {
+        self.sessions.lock().ok()?
+            .get(name)
+            .cloned()
+    }
+
+    /// The names of the endpoints, sorted.
+    pub fn names(&self) -> Vec<String> {
+        let mut result = match self.sessions.lock() {
+            Ok(sessions) => sessions.keys().cloned().collect::<Vec<_>>(),
+            Err(_) => vec![],
+        };
+        result.sort();
+        result
+    }
+
+    /// Broadcast the functional TesterPresent(`3E 80`) of the endpoints `names` every `period`,
+    /// once per functional identifier, replacing the previous ones. The endpoints without
+    /// a functional identifier are skipped, the empty `names` stops the broadcast.
+    pub fn keep_alive(&self, names: &[&str], period: Duration) -> Result<(), Error> {
+        if let Some(name) = names.iter().find(|v| self.session(v).is_none()) {
+            return Err(Error::InvalidParam(format!("endpoint `{}` is not added", name)));
+        }
+
+        let mut keep_alive = self.keep_alive.lock()
+            .map_err(|_| Error::ContextError("can't get `keep_alive`".into()))?;
+        keep_alive.names = names.iter().map(|v| v.to_string()).collect();
+        keep_alive.period = period;
+        self.schedule(&mut keep_alive);
+        Ok(())
+    }
+
+    /// The functional identifiers the TesterPresent is broadcast to.
+    pub fn keep_alive_ids(&self) -> Vec<u32> {
+        match self.keep_alive.lock() {
+            Ok(keep_alive) => self.functional_ids(&keep_alive.names),
+            Err(_) => vec![],
+        }
+    }
+
+    fn functional_ids(&self, names: &[String]) -> Vec<u32> {
+        let mut result = names.iter()
+            .filter_map(|v| self.session(v)?.config.fid)
+            .collect::<Vec<_>>();
+        result.sort();
+        result.dedup();
+        result
+    }
+
+    /// Replace the cyclic frames by the ones of `keep_alive`.
+    fn schedule(&self, keep_alive: &mut KeepAlive) {
+        keep_alive.handles.drain(..)
+            .for_each(|v| { self.can.remove_cyclic(&v); });
+        for fid in self.functional_ids(&keep_alive.names) {
+            let frame = CanIsoTpFrame::single_frame(TESTER_PRESENT).ok()
+                .and_then(|v| F::from_iso_tp(Id::from(fid), v, None));
+            match frame {
+                Some(mut frame) => {
+                    frame.set_channel(self.channel.clone());
+                    let handle = self.can.add_cyclic(format!("tester-present-{:X}", fid), frame, keep_alive.period);
+                    keep_alive.handles.push(handle);
+                },
+                None => log::warn!("Session - invalid TesterPresent to {:X}", fid),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, feature = "serde"))]
+mod tests {
+    use std::any::Any;
+    use std::sync::{Arc, Mutex};
+    use std::sync::mpsc::channel;
+    use std::thread::{sleep, spawn};
+    use std::time::{Duration, Instant};
+    use serde::Deserialize;
+    use crate::{IsoTpEvent, IsoTpEventListener};
+    use crate::can::Address;
+    use crate::can::driver::{MOCK_CHANNEL, SyncCan, VirtualBus};
+    use crate::can::frame::Frame;
+    use crate::can::isotp::SyncCanIsoTp;
+    use crate::can::message::CanMessage;
+    use crate::device::Listener;
+    use crate::error::Error;
+    use super::{EndpointConfig, SessionManager};
+
+    const ENDPOINTS: &str = r#"
+        [[endpoints]]
+        name = "ecm"
+        tx_id = 0x7E0
+        rx_id = 0x7E8
+        fid = 0x7DF
+        p2_ms = 1000
+
+        [[endpoints]]
+        name = "tcm"
+        tx_id = 0x7E1
+        rx_id = 0x7E9
+        fid = 0x7DF
+        p2_ms = 1000
+
+        [[endpoints]]
+        name = "abs"
+        tx_id = 0x18DA28F1
+        rx_id = 0x18DAF128
+        fid = 0x18DB33F1
+    "#;
+
+    #[derive(Deserialize)]
+    struct Config {
+        endpoints: Vec<EndpointConfig>,
+    }
+
+    /// Records the received frames.
+    #[derive(Clone, Default)]
+    struct FrameListener(Arc<Mutex<Vec<CanMessage>>>);
+
+    impl Listener<String, u32, CanMessage> for FrameListener {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            self.0.lock().unwrap().extend_from_slice(frames);
+        }
+    }
+
+    /// An ECU answering the requests positively with its `name`.
+    fn ecu(can: &SyncCan<crate::can::driver::MockDriver, String, CanMessage>, address: Address, name: &'static str) {
+        let (tx, rx) = channel();
+        let iso_tp = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            address,
+            can.sender(),
+            <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+                if let Some(data) = event.into_data() {
+                    let _ = tx.send(data);
+                }
+            }),
+        );
+        can.register_listener(name.into(), Box::new(iso_tp.clone()));
+        spawn(move || while let Ok(request) = rx.recv() {
+            let response = [&[request[0] + 0x40], &request[1..], name.as_bytes()].concat();
+            if iso_tp.write(false, response).is_err() {
+                break;
+            }
+        });
+    }
+
+    #[test]
+    fn test_session_manager() -> anyhow::Result<()> {
+        let config: Config = toml::from_str(ENDPOINTS)?;
+        assert_eq!(config.endpoints.len(), 3);
+        assert_eq!(config.endpoints[2], EndpointConfig::new("abs", 0x18DA28F1, 0x18DAF128, Some(0x18DB33F1)));
+
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut ecu_can = SyncCan::new(b);
+        ecu(&ecu_can, Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF }, "ecm");
+        ecu(&ecu_can, Address { tx_id: 0x7E9, rx_id: 0x7E1, fid: 0x7DF }, "tcm");
+        let frames = FrameListener::default();
+        ecu_can.register_listener("frames".into(), Box::new(frames.clone()));
+
+        let manager = SessionManager::with_endpoints(&client_can, MOCK_CHANNEL.to_string(), &config.endpoints)?;
+        assert_eq!(manager.names(), ["abs", "ecm", "tcm"]);
+        client_can.sync_start(100);
+        ecu_can.sync_start(100);
+
+        // concurrently on two endpoints, the responses are longer than a single frame
+        let requests = ["ecm", "tcm"].map(|name| {
+            let session = manager.session(name).unwrap();
+            spawn(move || (0..3)
+                .map(|i| session.request(&[0x22, 0xF1, 0x90 + i]))
+                .collect::<Result<Vec<_>, _>>())
+        });
+        let [ecm, tcm] = requests.map(|v| v.join().unwrap());
+        assert_eq!(ecm?, (0..3).map(|i| [&[0x62, 0xF1, 0x90 + i], b"ecm".as_slice()].concat()).collect::<Vec<_>>());
+        assert_eq!(tcm?, (0..3).map(|i| [&[0x62, 0xF1, 0x90 + i], b"tcm".as_slice()].concat()).collect::<Vec<_>>());
+
+        // hot removing and adding
+        assert!(manager.remove_endpoint("ecm"));
+        assert!(!manager.remove_endpoint("ecm"));
+        assert!(manager.session("ecm").is_none());
+        assert!(!client_can.listener_names().contains(&"session-ecm".to_string()));
+        manager.add_endpoint(EndpointConfig { p2_ms: 1000, ..EndpointConfig::new("ecm", 0x7E0, 0x7E8, None) })?;
+        assert!(matches!(manager.add_endpoint(EndpointConfig::new("ecm", 0x7E0, 0x7E8, None)), Err(Error::InvalidParam(_))));
+        assert_eq!(manager.session("ecm").unwrap().request(&[0x10, 0x03])?, [&[0x50, 0x03], b"ecm".as_slice()].concat());
+        assert!(matches!(manager.session("ecm").unwrap().write(true, vec![0x3E, 0x00]), Err(Error::InvalidParam(_))));
+
+        // one TesterPresent per functional identifier
+        assert!(manager.keep_alive(&["unknown"], Duration::from_millis(10)).is_err());
+        manager.keep_alive(&["ecm", "tcm", "abs"], Duration::from_millis(10))?;
+        assert_eq!(manager.keep_alive_ids(), [0x7DF, 0x18DB33F1]);
+        let tester_present = |id: u32| frames.0.lock().unwrap().iter()
+            .filter(|v| v.id().as_raw() == id && v.data()[..3] == [0x02, 0x3E, 0x80])
+            .count();
+        let start = Instant::now();
+        while (tester_present(0x7DF) < 2 || tester_present(0x18DB33F1) < 2) && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+        assert!(tester_present(0x7DF) >= 2 && tester_present(0x18DB33F1) >= 2);
+
+        manager.remove_endpoint("abs");
+        assert_eq!(manager.keep_alive_ids(), [0x7DF]);
+        manager.clear();
+        assert!(manager.names().is_empty() && manager.keep_alive_ids().is_empty());
+
+        client_can.stop();
+        ecu_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_request_response() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut client_can = SyncCan::new(a);
+        let mut ecu_can = SyncCan::new(b);
+        // the responses written in order for each request
+        let (tx, rx) = channel();
+        let iso_tp = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF },
+            ecu_can.sender(),
+            <dyn IsoTpEventListener>::from_fn(move |event: IsoTpEvent| {
+                if event.data().is_some() {
+                    let _ = tx.send(());
+                }
+            }),
+        );
+        ecu_can.register_listener("ecm".into(), Box::new(iso_tp.clone()));
+        let scripts: Vec<Vec<Vec<u8>>> = vec![
+            // the late response of another request and a pending one before the positive one
+            vec![vec![0x50, 0x03], vec![0x7F, 0x10, 0x78], vec![0x7F, 0x22, 0x78], vec![0x62, 0xF1, 0x90]],
+            // the negative one is final
+            vec![vec![0x7F, 0x22, 0x31]],
+            // no response of the request
+            vec![vec![0x7F, 0x10, 0x11], vec![0x51, 0x01]],
+        ];
+        spawn(move || for script in scripts {
+            if rx.recv().is_err() {
+                break;
+            }
+            for response in script {
+                let _ = iso_tp.write(false, response);
+                sleep(Duration::from_millis(5));
+            }
+        });
+
+        let manager = SessionManager::new(&client_can, MOCK_CHANNEL.to_string());
+        let session = manager.add_endpoint(EndpointConfig { p2_ms: 100, ..EndpointConfig::new("ecm", 0x7E0, 0x7E8, None) })?;
+        client_can.sync_start(100);
+        ecu_can.sync_start(100);
+
+        assert_eq!(session.request(&[0x22, 0xF1, 0x90])?, [0x62, 0xF1, 0x90]);
+        assert_eq!(session.request(&[0x22, 0xF1, 0x90])?, [0x7F, 0x22, 0x31]);
+        let start = Instant::now();
+        assert!(matches!(session.request(&[0x22, 0xF1, 0x90]), Err(Error::Timeout { value: 100, .. })));
+        // not restarted by the discarded responses
+        assert!(start.elapsed() < Duration::from_millis(150), "{:?}", start.elapsed());
+
+        client_can.stop();
+        ecu_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_endpoint_config() {
+        let config = EndpointConfig::new("ecm", 0x7E0, 0x7E8, None);
+        assert!(config.validate().is_ok());
+        assert!(EndpointConfig { fd: !config.fd, ..config.clone() }.validate().is_err());
+        assert!(EndpointConfig { standard: Some(2004), ..config.clone() }.validate().is_ok() != cfg!(feature = "std2016"));
+        assert!(EndpointConfig { standard: Some(2016), ..config }.validate().is_ok() == cfg!(feature = "std2016"));
+    }
+}
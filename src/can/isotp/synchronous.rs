@@ -1,25 +1,59 @@
 mod listener;
 
-use std::sync::{Arc, mpsc::Sender, Mutex};
+use arc_swap::ArcSwap;
+use std::sync::{Arc, atomic::{AtomicBool, Ordering}, mpsc::Sender, Mutex};
 use std::thread::sleep;
 use std::time::{Duration, Instant};
-use crate::{FlowControlContext, FlowControlState, IsoTpEvent, IsoTpEventListener, IsoTpFrame, IsoTpState, can::{Address, CanIsoTpFrame, isotp::context::IsoTpContext, frame::Frame}};
-use crate::constant::{P2_STAR_ISO14229, TIMEOUT_AS_ISO15765_2, TIMEOUT_CR_ISO15765_2};
+use crate::{AtomicState, FirstFramePolicy, FlowControlContext, FlowControlState, FrameType, IsoTpEvent, IsoTpEventListener, IsoTpFrame, IsoTpState, IsoTpVerbosity, SequenceStart, can::{Address, AddressContext, AddressFormat, AddressType, CanIsoTpFrame, identifier::Id, matcher::RxMatcher, driver::{DirectTransmit, TxGenerations}, isotp::{context::{IsoTpContext, RxStats, TxStats}, echo::TxEcho, pending::TxPending, retry::{RetryPolicy, TxRetry}, tap::{Direction, FrameTap, TapSlot}, trace}, frame::{Direct, FrameMut, tick_millis, timestamp_or_now}}};
+use crate::constant::{TIMEOUT_AS_ISO15765_2, TIMEOUT_BS_ISO15765_2, TIMEOUT_CR_ISO15765_2};
+use crate::device::{Channel, DriverCapabilities};
 use crate::error::Error;
 
 #[derive(Clone)]
 pub struct SyncCanIsoTp<C, F> {
     pub(crate) channel: C,
-    pub(crate) address: Arc<Mutex<Address>>,
+    /// Read lock-free for every frame, [`update_address`](Self::update_address) is the only writer.
+    pub(crate) address: Arc<ArcSwap<Address>>,
+    pub(crate) format: AddressFormat,
+    /// Matches the received frames, its address is replaced by the current one of each batch.
+    pub(crate) rx_matcher: RxMatcher,
     pub(crate) sender: Sender<F>,
     pub(crate) context: Arc<Mutex<IsoTpContext>>,
-    pub(crate) state: Arc<Mutex<IsoTpState>>,
+    /// Read lock-free by [`state`](Self::state) for the debugging UIs.
+    pub(crate) state: Arc<AtomicState>,
     pub(crate) listener: Arc<Mutex<Box<dyn IsoTpEventListener>>>,
+    pub(crate) verbosity: IsoTpVerbosity,
+    /// Replied to the first frame and each block of the peer.
+    pub(crate) flow_ctrl: FlowControlContext,
+    pub(crate) first_frame: FirstFramePolicy,
+    /// The sequence of the first consecutive frame transmitted and received.
+    pub(crate) sequence_start: SequenceStart,
+    /// Never transmit, see [`with_listen_only`](Self::with_listen_only).
+    pub(crate) listen_only: bool,
+    pub(crate) retry: RetryPolicy,
+    /// The frame of the writing kept for the [`RetryPolicy`].
+    pub(crate) tx_retry: Arc<Mutex<TxRetry<F>>>,
+    /// The frames sent and not confirmed yet while the address is half-duplex.
+    pub(crate) tx_echo: Arc<Mutex<TxEcho>>,
+    /// The frames sent and not confirmed yet, the confirmations are matched by the content.
+    pub(crate) tx_pending: Arc<Mutex<TxPending>>,
+    /// Tag the frames sent, the ones queued by an aborted writing aren't transmitted.
+    pub(crate) generations: Option<TxGenerations>,
+    /// Accept the next writing while the previous one is awaiting its confirmation, see [`with_pipelining`](Self::with_pipelining).
+    pub(crate) pipelining: bool,
+    /// Called with each ISO-TP frame sent and received, see [`set_frame_tap`](Self::set_frame_tap).
+    pub(crate) frame_tap: TapSlot,
+    /// Transmit the flow controls bypassing the transmit loop, see [`with_direct_transmit`](Self::with_direct_transmit).
+    pub(crate) direct: Option<DirectTransmit<F>>,
+    /// See [`with_n_br_warning`](Self::with_n_br_warning).
+    pub(crate) n_br_warning: Option<Duration>,
+    /// Set by [`Listener::on_shutdown`](crate::device::Listener::on_shutdown), the writing fails fast.
+    pub(crate) shutdown: Arc<AtomicBool>,
 }
 
 unsafe impl<C, F> Send for SyncCanIsoTp<C, F> {}
 
-impl<C: Clone, F: Frame<Channel = C>> SyncCanIsoTp<C, F> {
+impl<C: Channel, F: FrameMut<Channel = C> + Clone + 'static> SyncCanIsoTp<C, F> {
 
     pub fn new(channel: C,
                address: Address,
@@ -28,117 +62,635 @@ impl<C: Clone, F: Frame<Channel = C>> SyncCanIsoTp<C, F> {
     ) -> Self {
         Self {
             channel,
-            address: Arc::new(Mutex::new(address)),
+            address: Arc::new(ArcSwap::from_pointee(address)),
+            format: Default::default(),
+            rx_matcher: RxMatcher::new(address, Default::default()),
             sender,
             context: Default::default(),
             state: Default::default(),
             listener: Arc::new(Mutex::new(listener)),
+            verbosity: Default::default(),
+            flow_ctrl: FlowControlContext::ISO15765_2,
+            first_frame: Default::default(),
+            sequence_start: Default::default(),
+            listen_only: false,
+            retry: Default::default(),
+            tx_retry: Default::default(),
+            tx_echo: Default::default(),
+            tx_pending: Default::default(),
+            generations: Default::default(),
+            pipelining: false,
+            frame_tap: Default::default(),
+            direct: Default::default(),
+            n_br_warning: Default::default(),
+            shutdown: Default::default(),
         }
     }
 
+    /// Set the address format, the [`AddressFormat::NormalFixed`] transmits the extended
+    /// identifiers and receives the frames by the source and target addresses.
     #[inline]
-    pub fn update_address(&self, address: Address) {
-        if let Ok(mut addr) = self.address.lock() {
-            *addr = address;
+    pub fn with_address_format(mut self, format: AddressFormat) -> Self {
+        self.format = format;
+        self.rx_matcher = self.rx_matcher.with_format(format);
+        self
+    }
+
+    /// Set the [`RxMatcher`] of the received frames, e.g. to accept the functional address or
+    /// a masked range. Its address and format replace the ones of the instance.
+    #[inline]
+    pub fn with_rx_matcher(mut self, matcher: RxMatcher) -> Self {
+        self.address.store(Arc::new(matcher.address()));
+        self.format = matcher.format();
+        self.rx_matcher = matcher;
+        self
+    }
+
+    /// Set the [`IsoTpVerbosity`] of the events, [`IsoTpVerbosity::Normal`] by default.
+    #[inline]
+    pub fn with_verbosity(mut self, verbosity: IsoTpVerbosity) -> Self {
+        self.verbosity = verbosity;
+        self
+    }
+
+    /// Set the flow control replied to the peer, [`FlowControlContext::ISO15765_2`] by default
+    /// and [`FlowControlContext::ISO15765_4`] for OBD.
+    ///
+    /// The peer transmits the consecutive frames back to back when STmin is 0, and waits for
+    /// the next flow control after each block when the block size isn't 0.
+    #[inline]
+    pub fn with_flow_control(mut self, ctx: FlowControlContext) -> Self {
+        self.flow_ctrl = ctx;
+        self
+    }
+
+    /// Set the [`FirstFramePolicy`] of a first frame during the receiving, [`FirstFramePolicy::Restart`] by default.
+    #[inline]
+    pub fn with_first_frame_policy(mut self, policy: FirstFramePolicy) -> Self {
+        self.first_frame = policy;
+        self
+    }
+
+    /// Set the [`SequenceStart`] of the consecutive frames, [`SequenceStart::Strict`] from 1 by default.
+    #[inline]
+    pub fn with_sequence_start(mut self, start: SequenceStart) -> Self {
+        self.sequence_start = start;
+        self
+    }
+
+    /// Never transmit when `listen_only` is true, e.g. monitoring the transfers to `rx_id`.
+    ///
+    /// No flow control is replied, the data is reassembled from the consecutive frames elicited
+    /// by the flow controls of the real receiver. The writing fails with [`Error::ListenOnly`].
+    #[inline]
+    pub fn with_listen_only(mut self, listen_only: bool) -> Self {
+        self.listen_only = listen_only;
+        self
+    }
+
+    #[inline]
+    pub fn is_listen_only(&self) -> bool {
+        self.listen_only
+    }
+
+    /// Validate the configuration against the [`DriverCapabilities`] of the driver it runs on,
+    /// e.g. the CAN-FD frames of the `can-fd` feature need a driver supporting CAN-FD.
+    pub fn with_capabilities(self, capabilities: DriverCapabilities) -> Result<Self, Error> {
+        if cfg!(feature = "can-fd") && !capabilities.fd {
+            return Err(Error::Unsupported("CAN-FD frames by the driver".into()));
+        }
+
+        Ok(self)
+    }
+
+    /// Set the [`RetryPolicy`] of the frames failed by the device, disabled by default.
+    #[inline]
+    pub fn with_retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Send the frames by the [`TxGenerations`] of the transmit loop, the frames queued by an aborted
+    /// writing are dropped instead of transmitted after the abort, see [`abort`](Self::abort).
+    #[inline]
+    pub fn with_tx_generations(mut self, generations: TxGenerations) -> Self {
+        self.generations = Some(generations);
+        self
+    }
+
+    /// Accept the next writing while the previous one is awaiting the confirmation of its last frames,
+    /// e.g. the blocks of a download, its frames are queued behind them. Disabled by default.
+    ///
+    /// It needs [`with_tx_generations`](Self::with_tx_generations): when the previous writing fails,
+    /// the frames of the next one are dropped and it fails with [`Error::Aborted`], so it never starts.
+    /// The writing isn't accepted while the previous one is waiting for the flow control. The completions
+    /// and the errors are reported in order by [`IsoTpEvent::TxCompleted`] and [`IsoTpEvent::ErrorOccurred`].
+    #[inline]
+    pub fn with_pipelining(mut self, pipelining: bool) -> Self {
+        self.pipelining = pipelining;
+        self
+    }
+
+    /// Transmit the flow controls by `direct` instead of queueing them to the transmit loop, so N_Br
+    /// isn't delayed by the frames queued before, see [`SyncCan::direct_transmit`](crate::can::driver::SyncCan::direct_transmit).
+    ///
+    /// The flow control is transmitted by the thread of the receiving and confirmed when it returns,
+    /// the other frames are still sent to the transmit loop.
+    #[inline]
+    pub fn with_direct_transmit(mut self, direct: DirectTransmit<F>) -> Self {
+        self.direct = Some(direct);
+        self
+    }
+
+    /// Report a flow control transmitted later than `warning` after the frame it replies(the first frame
+    /// or the last consecutive frame of a block) by [`IsoTpEvent::FlowControlDelayed`], disabled by default,
+    /// e.g. within the 75 ms N_Bs of an ISO 15765-4 peer. N_Br is measured by the frame timestamps,
+    /// see [`rx_stats`](Self::rx_stats).
+    #[inline]
+    pub fn with_n_br_warning(mut self, warning: Duration) -> Self {
+        self.n_br_warning = Some(warning);
+        self
+    }
+
+    /// Call `tap` with each ISO-TP frame sent and received by the instance and its clones, e.g. for
+    /// the protocol debugging, `None` removes it.
+    ///
+    /// The received frames are passed after decoded, the ones sent before queued to the transmit loop,
+    /// including the flow controls replied and the retransmitted frames. A panic of the tap is logged
+    /// and ignored.
+    pub fn set_frame_tap(&self, tap: Option<FrameTap>) {
+        self.frame_tap.set(tap);
+    }
+
+    /// The current state, e.g. `WaitFlowCtrl|Sending` when waiting for the flow control.
+    #[inline]
+    pub fn state(&self) -> IsoTpState {
+        self.state.load(Ordering::Acquire)
+    }
+
+    /// Neither writing nor receiving, and no error occurred.
+    #[inline]
+    pub fn is_idle(&self) -> bool {
+        self.state() == IsoTpState::Idle && self.rx_in_progress().is_none()
+    }
+
+    /// The received bytes and the length of the first frame while receiving the consecutive frames.
+    ///
+    /// The receiving without a frame for N_Cr is aborted by [`Error::Timeout`] first, the peer is gone.
+    pub fn rx_in_progress(&self) -> Option<(usize, u32)> {
+        self.expire_reception();
+        let context = self.context.lock().ok()?;
+        context.consecutive.length
+            .map(|v| (context.consecutive.buffer.len(), v))
+    }
+
+    /// The last flow control received by the writing.
+    #[inline]
+    pub fn last_flow_control(&self) -> Option<FlowControlContext> {
+        self.context.lock().ok()?.last_flow_ctrl
+    }
+
+    /// The last flow control to continue of the peer, kept after the writing until the next one,
+    /// e.g. to choose a shorter block of the application by a long STmin.
+    #[inline]
+    pub fn peer_flow_control(&self) -> Option<FlowControlContext> {
+        self.context.lock().ok()?.peer_flow_ctrl
+    }
+
+    /// The [`TxStats`] of the writing in progress or the last one.
+    #[inline]
+    pub fn tx_stats(&self) -> TxStats {
+        match self.context.lock() {
+            Ok(context) => context.tx_stats,
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// The [`RxStats`] of the receivings, N_Br of the flow controls replied.
+    #[inline]
+    pub fn rx_stats(&self) -> RxStats {
+        match self.context.lock() {
+            Ok(context) => context.rx_stats,
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// The frames retransmitted by the [`RetryPolicy`].
+    #[inline]
+    pub fn retries(&self) -> u64 {
+        match self.tx_retry.lock() {
+            Ok(v) => v.retries(),
+            Err(_) => 0,
         }
     }
 
+    #[inline]
+    pub fn update_address(&self, address: Address) {
+        self.address.store(Arc::new(address));
+    }
+
     pub fn write(&self, functional: bool, data: Vec<u8>) -> Result<(), Error> {
+        let address = **self.address.load();
+        let can_id = self.format.can_id(if functional { address.fid } else { address.tx_id });
+        #[cfg(feature = "tracing")]
+        let _span = trace::write_span("sync", Some(address), functional, data.len())
+            .entered();
+        self.write_frames(can_id, None, data)
+    }
+
+    /// Write `data` to the identifier `tx_id` instead of the address, e.g. by a gateway or a fuzzer.
+    ///
+    /// The address is not changed, so it doesn't race with the receiving. The data is segmented
+    /// as by [`write`](Self::write) and the flow controls of the peer are still matched by the
+    /// `rx_id` of the address. Like `write`, an instance writes one at a time, a writing started
+    /// meanwhile resets this one.
+    pub fn write_to(&self, tx_id: u32, extended: bool, data: Vec<u8>) -> Result<(), Error> {
+        #[cfg(feature = "tracing")]
+        let _span = trace::write_span("sync", Some(Address { tx_id, ..**self.address.load() }), false, data.len())
+            .entered();
+        self.write_frames(Id::from_bits(tx_id, extended), Some(tx_id), data)
+    }
+
+    /// Write the frames to `can_id`, `target` is the identifier of [`write_to`](Self::write_to).
+    fn write_frames(&self, can_id: Id, target: Option<u32>, data: Vec<u8>) -> Result<(), Error> {
+        if self.listen_only {
+            return Err(Error::ListenOnly);
+        }
+        if self.shutdown.load(Ordering::Acquire) {
+            return Err(Error::Shutdown);
+        }
+
+        let pipelined = self.pipelined() && {
+            // one writing awaits its confirmation at most
+            self.wait_previous()?;
+            match self.context.lock() {
+                Ok(context) => context.tx.as_ref().is_some_and(|v| v.queued && v.frames > 0),
+                Err(_) => false,
+            }
+        };
+        if pipelined && self.state_contains(IsoTpState::Error) {
+            // the previous writing failed after it returned, this one isn't started
+            let e = self.last_error();
+            self.state_append(IsoTpState::Idle);
+            self.context_reset();
+            self.clear_pending();
+            return Err(Error::Aborted(format!("by the previous writing: {}", e)));
+        }
+
         self.state_append(IsoTpState::Idle);
-        self.context_reset();
-        log::trace!("ISO-TP(CAN sync) - Sending: {}", hex::encode(&data));
+        let pipelined = match self.context.lock() {
+            Ok(mut context) if pipelined => context.pipeline(),
+            Ok(mut context) => {
+                context.reset();
+                false
+            },
+            Err(_) => false,
+        };
+        if !pipelined {
+            self.clear_pending();
+        }
+        if let Ok(mut tx_retry) = self.tx_retry.lock() {
+            tx_retry.clear();
+        }
+        trace::sending("sync", &data);
 
-        let frames = CanIsoTpFrame::from_data(data)?;
+        let bytes = data.len();
+        let frames = CanIsoTpFrame::from_data_with_sequence(data, self.sequence_start.tx_sequence())?;
         let frame_len = frames.len();
+        if let Ok(mut context) = self.context.lock() {
+            context.start_tx(can_id.into_bits(), frame_len, bytes);
+            context.write_to = target;
+        }
 
-        let can_id = match self.address.lock() {
-            Ok(address) => if functional { Ok(address.fid) } else { Ok(address.tx_id) },
-            Err(_) => Err(Error::ContextError("can't get address context".into())),
-        }?;
+        let frames = frames.into_iter()
+            .map(|frame| {
+                let mut frame = F::from_iso_tp_with(can_id, frame, &AddressContext::new(self.format))
+                    .ok_or(Error::ConvertError {
+                        src: "iso-tp frame",
+                        target: "can-frame",
+                    })?;
+                frame.set_channel(self.channel.clone());
+                Ok(frame)
+            })
+            .collect::<Result<Vec<_>, Error>>()?
+            .into_iter();
+        let result = self.send_frames(frames, frame_len);
+        if result.is_err() {
+            self.supersede_queued();
+            // the error is returned, the instance is ready for the next transfer
+            if let Ok(mut context) = self.context.lock() {
+                context.tx = None;
+            }
+            self.state_append(IsoTpState::Idle);
+        }
+        else if let Ok(mut context) = self.context.lock() {
+            if let Some(tx) = context.tx.as_mut() {
+                tx.queued = true;
+            }
+        }
+        result
+    }
+
+    /// Whether the next writing is accepted while the previous one is awaiting its confirmation.
+    #[inline]
+    fn pipelined(&self) -> bool {
+        self.pipelining && self.generations.is_some()
+    }
+
+    /// Clear the frames not confirmed of the previous writings, their confirmations are ignored.
+    fn clear_pending(&self) {
+        if let Ok(mut tx_echo) = self.tx_echo.lock() {
+            tx_echo.clear();
+        }
+        if let Ok(mut tx_pending) = self.tx_pending.lock() {
+            tx_pending.clear();
+        }
+    }
+
+    /// Send the frames of the writing paced by the flow controls of the peer.
+    fn send_frames(&self, mut frames: std::vec::IntoIter<F>, frame_len: usize) -> Result<(), Error> {
         let mut need_flow_ctrl = frame_len > 1;
         let mut index = 0;
-        for frame in frames {
-            let mut frame = F::from_iso_tp(can_id, frame, None)
-                .ok_or(Error::ConvertError {
-                    src: "iso-tp frame",
-                    target: "can-frame",
-                })?;
-            frame.set_channel(self.channel.clone());
-
+        while let Some(frame) = frames.next() {
             if need_flow_ctrl {
                 need_flow_ctrl = false;
                 self.state_append(IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
+                self.send(frame)?;
+                continue;
+            }
+
+            self.write_waiting()?;
+            // the frames are transmitted one by one to retransmit a failed frame in order
+            let (burst, block_size) = match self.context.lock() {
+                Ok(context) => (
+                    context.burst_len(index).filter(|_| !self.retry.is_enabled()),
+                    context.flow_ctrl.as_ref().map_or(0, |v| v.block_size as usize),
+                ),
+                Err(_) => (None, 0),
+            };
+            let len = burst.map_or(1, |v| v.min(frames.len() + 1));
+            index += len;
+            // the flow control is waited for before the last frame of the block is transmitted,
+            // so a fast reply of the peer is never missed
+            if block_size != 0 && index == block_size && frames.len() >= len {
+                index = 0;
+                self.state_append(IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
             }
             else {
-                self.write_waiting(&mut index)?;
                 self.state_append(IsoTpState::Sending);
             }
-            self.sender.send(frame)
-                .map_err(|e| {
-                    log::warn!("ISO-TP(CAN sync) - transmit failed: {:?}", e);
-                    Error::DeviceError
-                })?;
+            self.send(frame)?;
+            if len > 1 {
+                // STmin is 0, the frames to the end of the block are transmitted together
+                frames.by_ref()
+                    .take(len - 1)
+                    .try_for_each(|frame| self.send(frame))?;
+                // the last frames are confirmed while the next writing is queued behind them
+                if frames.len() > 0 || !self.pipelined() {
+                    self.wait_confirmed(frames.len())?;
+                }
+            }
+        }
+
+        if self.retry.is_enabled() {
+            // the last frame may be retransmitted
+            self.wait_confirmed(0)?;
         }
 
         Ok(())
     }
 
+    /// Abort the writing in progress, it fails with [`Error::Aborted`]. Returns false when not writing.
+    ///
+    /// The frames sent and not transmitted yet are dropped by [`with_tx_generations`](Self::with_tx_generations),
+    /// otherwise they're still transmitted by the transmit loop.
+    pub fn abort(&self) -> bool {
+        if !self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            return false;
+        }
+
+        log::warn!("ISO-TP(CAN sync) - writing aborted");
+        // the writing fails by the error state and supersedes its frames queued
+        self.on_error(Error::Aborted("by the application".into()));
+        true
+    }
+
+    /// Supersede the generation of the failed writing, its frames queued by the transmit loop are dropped.
+    fn supersede_queued(&self) {
+        let Some(generations) = &self.generations else {
+            return;
+        };
+
+        let id = match self.context.lock() {
+            Ok(context) => context.tx.as_ref().map(|v| v.id),
+            Err(_) => None,
+        };
+        if let Some(id) = id {
+            let generation = generations.advance(id);
+            if let Ok(mut tx_pending) = self.tx_pending.lock() {
+                tx_pending.supersede(id, generation);
+            }
+        }
+    }
+
+    fn send(&self, frame: F) -> Result<(), Error> {
+        if self.retry.is_enabled() {
+            if let Ok(mut tx_retry) = self.tx_retry.lock() {
+                tx_retry.sent(&frame);
+            }
+        }
+        self.transmit(frame)
+    }
+
+    fn transmit(&self, frame: F) -> Result<(), Error> {
+        if self.listen_only {
+            return Err(Error::ListenOnly);
+        }
+        if self.address.load().is_half_duplex() {
+            if let Ok(mut tx_echo) = self.tx_echo.lock() {
+                tx_echo.sent(frame.data());
+            }
+        }
+        if self.frame_tap.is_set() {
+            // the frames are sent without the address extension
+            if let Ok(frame) = CanIsoTpFrame::decode(frame.data()) {
+                self.frame_tap.tap("sync", Direction::Tx, &frame);
+            }
+        }
+        let id = frame.id().into_bits();
+        let transfer = match self.context.lock() {
+            Ok(context) => context.tx.as_ref().map_or(0, |v| v.transfer),
+            Err(_) => 0,
+        };
+        if let Ok(mut tx_pending) = self.tx_pending.lock() {
+            let generation = self.generations.as_ref().map_or(0, |v| v.generation(id));
+            tx_pending.sent(id, generation, transfer, frame.data());
+        }
+        let result = match &self.generations {
+            Some(generations) => generations.send(&self.sender, frame),
+            None => self.sender.send(frame),
+        };
+        result.map_err(|e| {
+            // the loops are stopped and the receiver is dropped
+            if self.shutdown.load(Ordering::Acquire) {
+                return Error::Shutdown;
+            }
+            log::warn!("ISO-TP(CAN sync) - transmit failed: {:?}", e);
+            Error::device(e)
+        })
+    }
+
     #[inline]
-    pub(crate) fn on_single_frame(&self, data: Vec<u8>) {
-        self.iso_tp_event(IsoTpEvent::DataReceived(data));
+    pub(crate) fn on_single_frame(&self, data: &[u8], r#type: AddressType, timestamp: u64) {
+        // the physical single frame terminates the receiving in progress, as ISO 15765-2
+        if r#type == AddressType::Physical {
+            self.on_reception_restarted();
+        }
+        self.iso_tp_event(IsoTpEvent::DataReceived { data: data.into(), first_frame_at: timestamp, completed_at: timestamp });
     }
 
     #[inline]
-    pub(crate) fn on_first_frame(&self, tx_id: u32, length: u32, data: Vec<u8>) {
-        self.update_consecutive(length, data);
+    pub(crate) fn on_first_frame(&self, tx_id: u32, length: u32, data: &[u8], timestamp: u64) {
+        if self.rx_in_progress().is_some() {
+            match self.first_frame {
+                FirstFramePolicy::Restart => self.on_reception_restarted(),
+                FirstFramePolicy::Ignore => {
+                    log::warn!("ISO-TP(CAN sync) - first frame ignored during the receiving");
+                    return;
+                },
+            }
+        }
 
-        let iso_tp_frame = CanIsoTpFrame::default_flow_ctrl_frame();
-        match F::from_iso_tp(tx_id, iso_tp_frame, None) {
-            Some(mut frame) => {
-                frame.set_channel(self.channel.clone());
+        // the new transfer discards the stale partial data
+        self.clear_buffer();
+        self.update_consecutive(length, data, timestamp);
 
-                self.state_append(IsoTpState::Sending);
-                match self.sender.send(frame) {
-                    Ok(_) => {
-                        self.iso_tp_event(IsoTpEvent::FirstFrameReceived);
-                    },
-                    Err(e) => {
-                        log::warn!("ISO-TP(CAN sync) - transmit failed: {:?}", e);
-                        self.state_append(IsoTpState::Error);
+        if self.listen_only {
+            // the flow control is replied by the real receiver
+            self.iso_tp_event(IsoTpEvent::FirstFrameReceived { at: timestamp });
+        }
+        else if let Some(ctx) = self.send_flow_ctrl(tx_id, timestamp) {
+            self.iso_tp_event(IsoTpEvent::FirstFrameReceived { at: timestamp });
+            trace::flow_control("sync", "sent", &ctx);
+            self.verbose_event(IsoTpEvent::FlowControlSent(ctx));
+        }
+    }
 
-                        self.iso_tp_event(IsoTpEvent::ErrorOccurred(Error::DeviceError));
-                    },
+    #[inline]
+    pub(crate) fn on_consecutive_frame(&self, sequence: u8, data: &[u8], timestamp: u64) {
+        if self.rx_in_progress().is_none() {
+            log::debug!("ISO-TP(CAN sync) - unexpected consecutive frame ignored");
+            return;
+        }
+
+        match self.append_consecutive(sequence, data, timestamp) {
+            Ok(event) => {
+                let next_block = !self.listen_only && matches!(event, IsoTpEvent::Wait) && self.block_received();
+                self.iso_tp_event(event);
+                if next_block {
+                    if let Some(ctx) = self.send_flow_ctrl(self.address.load().tx_id, timestamp) {
+                        trace::flow_control("sync", "sent", &ctx);
+                        self.verbose_event(IsoTpEvent::FlowControlSent(ctx));
+                    }
                 }
             },
-            None => log::error!("ISO-TP(CAN sync): convert `iso-tp frame` to `can-frame` error"),
+            Err(e) => self.on_reception_aborted(e),
         }
     }
 
-    #[inline]
-    pub(crate) fn on_consecutive_frame(&self, sequence: u8, data: Vec<u8>) {
-        match self.append_consecutive(sequence, data) {
-            Ok(event) => self.iso_tp_event(event),
+    /// Send the flow control of the instance replying the frame received at `received_at`, returns it when sent.
+    fn send_flow_ctrl(&self, tx_id: u32, received_at: u64) -> Option<FlowControlContext> {
+        let ctx = self.flow_ctrl;
+        let addressing = AddressContext::new(self.format);
+        let Some(mut frame) = F::from_iso_tp_with(addressing.can_id(tx_id), CanIsoTpFrame::FlowControlFrame(ctx), &addressing) else {
+            log::error!("ISO-TP(CAN sync): convert `iso-tp frame` to `can-frame` error");
+            return None;
+        };
+        frame.set_channel(self.channel.clone());
+        if let Ok(mut context) = self.context.lock() {
+            context.flow_ctrl_due = Some(received_at);
+        }
+
+        let result = match &self.direct {
+            Some(direct) => self.transmit_direct(direct, frame),
+            None => {
+                self.state_append(IsoTpState::Sending);
+                self.transmit(frame)
+            },
+        };
+        match result {
+            Ok(_) => Some(ctx),
             Err(e) => {
-                self.state_append(IsoTpState::Error);
-                self.iso_tp_event(IsoTpEvent::ErrorOccurred(e));
+                self.on_error(e);
+                None
+            },
+        }
+    }
+
+    /// Transmit the flow control by the [`DirectTransmit`], it's confirmed when returned.
+    fn transmit_direct(&self, direct: &DirectTransmit<F>, frame: F) -> Result<(), Error> {
+        if self.frame_tap.is_set() {
+            if let Ok(frame) = CanIsoTpFrame::decode(frame.data()) {
+                self.frame_tap.tap("sync", Direction::Tx, &frame);
             }
         }
+        // the echo of a half-duplex address isn't received before it's confirmed by this thread
+        let frame = direct.transmit(frame, None)?;
+        self.on_flow_ctrl_transmitted(timestamp_or_now(&frame));
+        Ok(())
+    }
+
+    /// Measure N_Br of the flow control transmitted at `timestamp`, [`IsoTpEvent::FlowControlDelayed`]
+    /// when it's longer than the warning.
+    fn on_flow_ctrl_transmitted(&self, timestamp: u64) {
+        let exceeded = match self.context.lock() {
+            Ok(mut context) => context.flow_ctrl_transmitted(timestamp, self.n_br_warning),
+            Err(_) => None,
+        };
+        if let Some(n_br) = exceeded {
+            log::warn!("ISO-TP(CAN sync) - flow control transmitted {:?} after the frame replied, longer than {:?}",
+                n_br, self.n_br_warning.unwrap_or_default());
+            self.iso_tp_event(IsoTpEvent::FlowControlDelayed { n_br });
+        }
+    }
+
+    /// Count a consecutive frame of the block, returns true when the next flow control is due.
+    fn block_received(&self) -> bool {
+        match self.context.lock() {
+            Ok(mut context) => context.block_received(self.flow_ctrl.block_size()),
+            Err(_) => false,
+        }
     }
 
     #[inline]
     pub(crate) fn on_flow_ctrl_frame(&self, ctx: FlowControlContext) {
+        trace::flow_control("sync", "received", &ctx);
+        if let Ok(mut context) = self.context.lock() {
+            context.last_flow_ctrl = Some(ctx);
+        }
+        if !self.state_contains(IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::debug!("ISO-TP(CAN sync) - unexpected flow control ignored");
+            return;
+        }
+
+        if ctx.raw_st_min() != ctx.st_min() {
+            log::warn!("ISO-TP(CAN sync) - reserved STmin received: {}", ctx);
+        }
+        self.verbose_event(IsoTpEvent::FlowControlReceived(ctx));
         match ctx.state() {
             FlowControlState::Continues => {
                 self.state_remove(IsoTpState::WaitBusy | IsoTpState::WaitFlowCtrl);
             },
             FlowControlState::Wait => {
+                if let Ok(mut context) = self.context.lock() {
+                    context.waits += 1;
+                }
                 self.state_append(IsoTpState::WaitBusy);
                 self.iso_tp_event(IsoTpEvent::Wait);
                 return;
             }
             FlowControlState::Overload => {
-                self.state_append(IsoTpState::Error);
-                self.iso_tp_event(IsoTpEvent::ErrorOccurred(Error::OverloadFlow));
+                self.on_error(Error::OverloadFlow);
                 return;
             }
         }
@@ -148,136 +700,1502 @@ impl<C: Clone, F: Frame<Channel = C>> SyncCanIsoTp<C, F> {
         };
     }
 
-    fn iso_tp_event(&self, event: IsoTpEvent) {
-        match self.listener.lock() {
-            Ok(mut listener) => {
-                // println!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event);
-                match &event {
-                    IsoTpEvent::DataReceived(data) => {
-                        log::debug!("ISO-TP - Received: {}", hex::encode(data));
-                    },
-                    IsoTpEvent::ErrorOccurred(_) =>
-                        log::warn!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
-                    _ => log::trace!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
-                }
-                listener.on_iso_tp_event(event);
+    /// Confirm a transmitted frame of the writing, [`IsoTpEvent::TxCompleted`] when the last one is confirmed.
+    ///
+    /// The frame is matched to a pending one sent by the instance, the others of the identifier
+    /// are ignored, and the sending is done when none is pending. A flow control is measured by `timestamp`.
+    pub(crate) fn on_transmitted(&self, id: u32, data: &[u8], timestamp: u64) {
+        let (kind, transfer, done) = match self.tx_pending.lock() {
+            Ok(mut tx_pending) => match tx_pending.confirmed(id, data) {
+                Some((kind, transfer)) => (kind, transfer, tx_pending.is_empty()),
+                None => {
+                    log::trace!("ISO-TP(CAN sync) - confirmation of a frame not sent by the instance: {:04X}", id);
+                    return;
+                },
             },
-            Err(_) => log::warn!("ISO-TP(CAN async): Sending event failed"),
+            Err(_) => return,
+        };
+        if done {
+            self.state_remove(IsoTpState::Sending);
+        }
+        if let Ok(mut tx_echo) = self.tx_echo.lock() {
+            tx_echo.confirmed(data);
+        }
+        if kind == FrameType::FlowControl {
+            self.on_flow_ctrl_transmitted(timestamp);
+            return;
         }
-    }
-
-    fn write_waiting(&self, index: &mut usize) -> Result<(), Error> {
-        match self.context.lock() {
-            Ok(ctx) => {
-                if let Some(ctx) = &ctx.flow_ctrl {
-                    if ctx.block_size != 0 {
-                        if (*index + 1) == ctx.block_size as usize {
-                            *index = 0;
-                            self.state_append(IsoTpState::WaitFlowCtrl);
-                        }
-                        else {
-                            *index += 1;
-                        }
-                    }
-                    sleep(Duration::from_micros(ctx.st_min as u64));
-                }
-
-                Ok(())
-            },
-            Err(_) => Err(Error::ContextError("can't get `context`".into()))
-        }?;
 
-        let start = Instant::now();
-        loop {
-            if self.state_contains(IsoTpState::Error) {
-                return Err(Error::DeviceError);
+        if self.retry.is_enabled() {
+            if let Ok(mut tx_retry) = self.tx_retry.lock() {
+                tx_retry.confirmed();
             }
+        }
+        let completed = match self.context.lock() {
+            Ok(mut context) => context.confirm_tx(transfer),
+            Err(_) => None,
+        };
+        if let Some(bytes) = completed {
+            self.verbose_event(IsoTpEvent::TxCompleted { bytes });
+        }
+    }
 
-            if self.state_contains(IsoTpState::Sending) {
-                if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
-                    return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
-                }
-            }
-            else if self.state_contains(IsoTpState::WaitBusy) {
-                if start.elapsed() > Duration::from_millis(P2_STAR_ISO14229 as u64) {
-                    return Err(Error::Timeout { value: P2_STAR_ISO14229 as u64, unit: "ms" });
-                }
-            }
-            else if self.state_contains(IsoTpState::WaitFlowCtrl) {
-                if start.elapsed() > Duration::from_millis(TIMEOUT_CR_ISO15765_2 as u64) {
-                    return Err(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
-                }
-            }
-            else {
-                break;
-            }
+    /// Whether the frame received by a half-duplex address is its own one echoed by the driver,
+    /// by the direction or by the content of an unconfirmed frame.
+    pub(crate) fn is_echo(&self, frame: &F) -> bool {
+        if frame.direct() == Direct::Transmit {
+            log::trace!("ISO-TP(CAN sync) - echo ignored: {:04X}", frame.id().into_bits());
+            return true;
         }
 
-        Ok(())
+        match self.tx_echo.lock() {
+            Ok(mut tx_echo) => tx_echo.is_echo(frame.data()),
+            Err(_) => false,
+        }
     }
 
-    fn append_consecutive(&self, sequence: u8, data: Vec<u8>) -> Result<IsoTpEvent, Error> {
+    /// Whether `id` is the target of the [`write_to`](Self::write_to) in progress.
+    pub(crate) fn is_write_target(&self, id: u32) -> bool {
         match self.context.lock() {
-            Ok(mut context) => {
-                context.append_consecutive(sequence, data)
-            },
-            Err(_) => Err(Error::ContextError("can't get `context`".into()))
+            Ok(context) => context.write_to == Some(id),
+            Err(_) => false,
         }
     }
 
-    fn update_consecutive(&self, length: u32, data: Vec<u8>) {
-        if let Ok(mut context) = self.context.lock() {
-            context.update_consecutive(length, data);
+    /// Retransmit the failed frame of the writing by the [`RetryPolicy`], the writing fails when
+    /// the retries are exhausted.
+    pub(crate) fn on_transmit_failed(&self, id: u32, e: &Error) {
+        let attempt = match self.tx_retry.lock() {
+            Ok(mut tx_retry) if self.retry.is_enabled() => tx_retry.failed(&self.retry),
+            _ => None,
+        };
+        if let Some(v) = attempt {
+            log::warn!("ISO-TP(CAN sync) - transmit failed: {}, retry {}/{} after {:?}",
+                e, v, self.retry.max_retries, self.retry.backoff);
+            return;
         }
-    }
 
-    fn context_reset(&self) {
-        if let Ok(mut context) = self.context.lock() {
-            context.reset();
+        let transfer = match self.tx_pending.lock() {
+            Ok(mut tx_pending) => tx_pending.failed(id),
+            Err(_) => None,
+        };
+        let previous = match self.context.lock() {
+            Ok(context) => transfer.is_some_and(|v| context.is_previous(v)),
+            Err(_) => false,
         };
+        if previous {
+            self.on_previous_failed(e.clone());
+        }
+        else {
+            self.on_error(e.clone());
+        }
     }
 
-    #[inline]
-    fn state_contains(&self, flags: IsoTpState) -> bool {
-        match self.state.lock() {
-            Ok(v) => {
-                // log::debug!("ISO-TP(CAN sync): current state(state contains): {} contains: {}", *v, flags);
-                *v & flags != IsoTpState::Idle
-            },
-            Err(_) => {
-                log::warn!("ISO-TP(CAN sync): state mutex is poisoned");
-                false
-            },
+    /// The previous writing failed while pipelining, the frames of the writing queued behind it
+    /// are dropped before transmitted and it fails with [`Error::Aborted`].
+    ///
+    /// The aborted writing is done, the error state doesn't fail the next one again.
+    fn on_previous_failed(&self, e: Error) {
+        log::warn!("ISO-TP(CAN sync) - previous writing failed: {}", e);
+        self.iso_tp_event(IsoTpEvent::ErrorOccurred(e.clone()));
+        self.supersede_queued();
+        if let Ok(mut context) = self.context.lock() {
+            context.tx_previous = None;
+            context.tx = None;
         }
+        self.on_error(Error::Aborted(format!("by the previous writing: {}", e)));
     }
 
-    #[inline]
-    fn state_append(&self, flags: IsoTpState) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                if flags == IsoTpState::Idle {
-                    *v = IsoTpState::Idle;
-                } else if flags.contains(IsoTpState::Error) {
-                    *v = IsoTpState::Error;
-                }
-                else {
-                    *v |= flags;
-                }
-
-                log::trace!("ISO-TP(CAN sync): current state(state append): {}", *v);
-            }
-            Err(_) => log::warn!("ISO-TP(CAN sync): state mutex is poisoned when appending"),
-        }
+    /// Set the error state and notify the listener, the error is returned by the writing.
+    pub(crate) fn on_error(&self, e: Error) {
+        self.set_error(&e);
+        self.iso_tp_event(IsoTpEvent::ErrorOccurred(e));
     }
 
-    #[inline]
+    /// Abort the receiving with the error, the partial data is moved to [`IsoTpEvent::ReceptionAborted`].
+    pub(crate) fn on_reception_aborted(&self, e: Error) {
+        let partial = match self.context.lock() {
+            Ok(mut context) => context.take_consecutive(),
+            Err(_) => None,
+        };
+
+        match partial {
+            // the error state isn't set, it fails the writing only
+            Some((partial, expected)) => {
+                self.iso_tp_event(IsoTpEvent::ReceptionAborted { error: e, partial, expected });
+            },
+            None => self.on_error(e),
+        }
+    }
+
+    /// Abort the receiving without a frame for N_Cr by the local clock.
+    fn expire_reception(&self) {
+        let expired = match self.context.lock() {
+            Ok(context) => context.consecutive_expired(tick_millis()),
+            Err(_) => false,
+        };
+        if expired {
+            log::warn!("ISO-TP(CAN sync) - receiving expired");
+            self.on_reception_aborted(Error::Timeout { value: TIMEOUT_CR_ISO15765_2 as u64, unit: "ms" });
+        }
+    }
+
+    /// Abort the transfers in progress with [`Error::Shutdown`], the device is shut down.
+    pub(crate) fn on_shutdown(&self) {
+        self.shutdown.store(true, Ordering::Release);
+        if self.rx_in_progress().is_some() {
+            self.on_reception_aborted(Error::Shutdown);
+        }
+        if self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::warn!("ISO-TP(CAN sync) - writing aborted by shutdown");
+            self.on_error(Error::Shutdown);
+        }
+    }
+
+    /// Report the partial data of the receiving terminated by a new first frame, the error state isn't set.
+    fn on_reception_restarted(&self) {
+        let partial = match self.context.lock() {
+            Ok(mut context) => context.take_consecutive(),
+            Err(_) => None,
+        };
+
+        if let Some((partial, expected)) = partial {
+            self.iso_tp_event(IsoTpEvent::ReceptionAborted { error: Error::ReceptionRestarted, partial, expected });
+        }
+    }
+
+    /// Abort the active transfers with [`Error::BusOff`].
+    pub(crate) fn on_bus_off(&self) {
+        let receiving = match self.context.lock() {
+            Ok(mut context) => {
+                let receiving = context.consecutive.length.is_some();
+                context.clear_consecutive();
+                receiving
+            },
+            Err(_) => false,
+        };
+
+        if receiving
+            || self.state_contains(IsoTpState::Sending | IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+            log::warn!("ISO-TP(CAN sync) - transfer aborted by bus off");
+            self.clear_buffer();
+            self.on_error(Error::BusOff);
+        }
+    }
+
+    fn set_error(&self, e: &Error) {
+        // the error before the state, the writing polling the state reads it
+        if let Ok(mut context) = self.context.lock() {
+            context.error = Some(e.clone());
+        }
+        self.state_append(IsoTpState::Error);
+    }
+
+    fn last_error(&self) -> Error {
+        match self.context.lock() {
+            Ok(context) => context.error.clone()
+                .unwrap_or(Error::ContextError("an error occurred without details".into())),
+            Err(_) => Error::ContextError("can't get `context`".into()),
+        }
+    }
+
+    fn clear_buffer(&self) {
+        match self.listener.lock() {
+            Ok(mut listener) => listener.clear_buffer(),
+            Err(_) => log::warn!("ISO-TP(CAN sync): clearing buffer failed"),
+        }
+    }
+
+    fn iso_tp_event(&self, event: IsoTpEvent) {
+        // no application expects an empty payload, drop it even if a frame slipped through the decoder
+        if matches!(&event, IsoTpEvent::DataReceived { data, .. } if data.is_empty()) {
+            log::warn!("ISO-TP(CAN sync) - empty data received is dropped");
+            return;
+        }
+
+        match self.listener.lock() {
+            Ok(mut listener) => {
+                // println!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event);
+                match &event {
+                    IsoTpEvent::DataReceived { data, .. } => trace::received("sync", data),
+                    IsoTpEvent::ErrorOccurred(_) | IsoTpEvent::ReceptionAborted { .. } =>
+                        log::warn!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
+                    _ => log::trace!("ISO-TP(CAN sync): Sending iso-tp event: {:?}", event),
+                }
+                listener.on_iso_tp_event(event);
+            },
+            Err(_) => log::warn!("ISO-TP(CAN sync): Sending event failed"),
+        }
+    }
+
+    #[inline]
+    fn verbose_event(&self, event: IsoTpEvent) {
+        if self.verbosity == IsoTpVerbosity::Verbose {
+            self.iso_tp_event(event);
+        }
+    }
+
+    fn write_waiting(&self) -> Result<(), Error> {
+        let flow_ctrl = match self.context.lock() {
+            Ok(ctx) => Ok(ctx.flow_ctrl.clone()),
+            Err(_) => Err(Error::ContextError("can't get `context`".into()))
+        }?;
+        if let Some(ctx) = flow_ctrl {
+            sleep(Duration::from_micros(ctx.st_min as u64));
+        }
+
+        let mut start = Instant::now();
+        let mut waits = self.waits()?;
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
+            if self.state_contains(IsoTpState::Error) {
+                return Err(self.last_error());
+            }
+
+            if self.retransmit()? {
+                // N_As restarts for the retransmitted frame
+                start = Instant::now();
+            }
+            let current = self.waits()?;
+            if current != waits {
+                // N_Bs restarts by each FC WAIT
+                waits = current;
+                start = Instant::now();
+            }
+
+            if self.state_contains(IsoTpState::Sending) {
+                if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                    trace::timeout("sync", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
+                    return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
+                }
+            }
+            else if self.state_contains(IsoTpState::WaitBusy) {
+                if start.elapsed() > Duration::from_millis(TIMEOUT_BS_ISO15765_2 as u64) {
+                    trace::timeout("sync", IsoTpState::WaitBusy, TIMEOUT_BS_ISO15765_2 as u64);
+                    return Err(Error::Timeout { value: TIMEOUT_BS_ISO15765_2 as u64, unit: "ms" });
+                }
+            }
+            else if self.state_contains(IsoTpState::WaitFlowCtrl) {
+                if start.elapsed() > Duration::from_millis(TIMEOUT_BS_ISO15765_2 as u64) {
+                    trace::timeout("sync", IsoTpState::WaitFlowCtrl, TIMEOUT_BS_ISO15765_2 as u64);
+                    return Err(Error::Timeout { value: TIMEOUT_BS_ISO15765_2 as u64, unit: "ms" });
+                }
+            }
+            else {
+                break;
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Wait for the confirmation of the last frames of the previous writing while pipelining,
+    /// supervised by N_As.
+    fn wait_previous(&self) -> Result<(), Error> {
+        let start = Instant::now();
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
+
+            let previous = match self.context.lock() {
+                Ok(context) => context.tx_previous.is_some(),
+                Err(_) => return Err(Error::ContextError("can't get `context`".into())),
+            };
+            if !previous || self.state_contains(IsoTpState::Error) {
+                return Ok(());
+            }
+            if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                trace::timeout("sync", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
+                return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
+            }
+            std::thread::yield_now();
+        }
+    }
+
+    /// Wait for the confirmations of the transmitted frames until `unsent` frames of the writing are
+    /// left, each confirmation is supervised by N_As.
+    fn wait_confirmed(&self, unsent: usize) -> Result<(), Error> {
+        let mut pending = usize::MAX;
+        let mut start = Instant::now();
+        loop {
+            if self.shutdown.load(Ordering::Acquire) {
+                return Err(Error::Shutdown);
+            }
+            if self.state_contains(IsoTpState::Error) {
+                return Err(self.last_error());
+            }
+
+            if self.retransmit()? {
+                start = Instant::now();
+            }
+
+            let current = match self.context.lock() {
+                Ok(context) => context.unconfirmed(),
+                Err(_) => return Err(Error::ContextError("can't get `context`".into())),
+            };
+            if current <= unsent {
+                return Ok(());
+            }
+
+            if current < pending {
+                pending = current;
+                start = Instant::now();
+            }
+            else if start.elapsed() > Duration::from_millis(TIMEOUT_AS_ISO15765_2 as u64) {
+                trace::timeout("sync", IsoTpState::Sending, TIMEOUT_AS_ISO15765_2 as u64);
+                return Err(Error::Timeout { value: TIMEOUT_AS_ISO15765_2 as u64, unit: "ms" });
+            }
+        }
+    }
+
+    /// Retransmit the failed frame when its backoff elapsed, returns true when retransmitted.
+    fn retransmit(&self) -> Result<bool, Error> {
+        if !self.retry.is_enabled() {
+            return Ok(false);
+        }
+
+        let frame = match self.tx_retry.lock() {
+            Ok(mut tx_retry) => tx_retry.take_due(),
+            Err(_) => return Err(Error::ContextError("can't get `tx_retry`".into())),
+        };
+        match frame {
+            Some(frame) => self.transmit(frame).map(|_| true),
+            None => Ok(false),
+        }
+    }
+
+    /// The FC WAIT received by the writing.
+    fn waits(&self) -> Result<usize, Error> {
+        match self.context.lock() {
+            Ok(context) => Ok(context.waits),
+            Err(_) => Err(Error::ContextError("can't get `context`".into())),
+        }
+    }
+
+    fn append_consecutive(&self, sequence: u8, data: &[u8], timestamp: u64) -> Result<IsoTpEvent, Error> {
+        match self.context.lock() {
+            Ok(mut context) => {
+                let result = context.append_consecutive(self.sequence_start, sequence, data, timestamp);
+                context.touch_consecutive(tick_millis());
+                result
+            },
+            Err(_) => Err(Error::ContextError("can't get `context`".into()))
+        }
+    }
+
+    fn update_consecutive(&self, length: u32, data: &[u8], timestamp: u64) {
+        if let Ok(mut context) = self.context.lock() {
+            context.update_consecutive(length, data, timestamp);
+            context.touch_consecutive(tick_millis());
+        }
+    }
+
+    fn context_reset(&self) {
+        if let Ok(mut context) = self.context.lock() {
+            context.reset();
+        };
+    }
+
+    #[inline]
+    fn state_contains(&self, flags: IsoTpState) -> bool {
+        self.state.load(Ordering::Acquire).intersects(flags)
+    }
+
+    #[inline]
+    fn state_append(&self, flags: IsoTpState) {
+        let result = self.state.fetch_update(Ordering::AcqRel, Ordering::Acquire, |v| {
+            if flags == IsoTpState::Idle {
+                Some(IsoTpState::Idle)
+            } else if flags.contains(IsoTpState::Error) {
+                Some(IsoTpState::Error)
+            }
+            else {
+                Some(v | flags)
+            }
+        });
+        let (Ok(v) | Err(v)) = result;
+        trace::state("sync", "append", v);
+    }
+
+    #[inline]
     fn state_remove(&self, flags: IsoTpState) {
-        match self.state.lock() {
-            Ok(mut v) => {
-                v.remove(flags);
-                log::trace!("ISO-TP(CAN sync): current state(state remove): {}", *v);
+        let (Ok(v) | Err(v)) = self.state.fetch_remove(flags, Ordering::AcqRel, Ordering::Acquire);
+        trace::state("sync", "remove", v);
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    #![cfg_attr(feature = "can-fd", allow(unused_imports, dead_code))]
+    use std::any::Any;
+    use std::sync::{Arc, Mutex, mpsc::{channel, Receiver, Sender}};
+    use std::thread::{sleep, spawn};
+    use std::time::{Duration, Instant};
+    use hex_literal::hex;
+    use crate::{FirstFramePolicy, FlowControlContext, FlowControlState, FrameType, IsoTpEvent, IsoTpEventListener, IsoTpFrame, IsoTpState, IsoTpVerbosity, SequenceStart};
+    use crate::error::Error;
+    use crate::can::{Address, AddressFormat, CanIsoTpFrame};
+    use crate::can::driver::{MOCK_CHANNEL, MockDriver, SyncCan, TxGenerations, VirtualBus};
+    use crate::can::frame::{Direct, Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::isotp::{Direction, RetryPolicy, SyncCanIsoTp, TxStats};
+    use crate::can::isotp::testing::{CLIENT, DataListener, EchoListener, EmptyListener, EventListener, Link, SERVER};
+    use crate::can::matcher::RxMatcher;
+    use crate::can::message::CanMessage;
+    use crate::device::{Driver, DriverCapabilities, Listener};
+
+    /// Counts the `clear_buffer` calls and records the events.
+    #[derive(Clone, Default)]
+    struct SpyListener(Arc<Mutex<(usize, Vec<IsoTpEvent>)>>);
+
+    impl IsoTpEventListener for SpyListener {
+        fn clear_buffer(&mut self) {
+            self.0.lock().unwrap().0 += 1;
+        }
+
+        fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+            self.0.lock().unwrap().1.push(event);
+        }
+    }
+
+    #[derive(Default)]
+    struct BlockRecords {
+        flow_ctrls: usize,
+        /// The consecutive frames received after the last flow control.
+        block: usize,
+        max_block: usize,
+        bytes: usize,
+    }
+
+    /// Replies the flow control with `block_size` and STmin=0 on the first frame and each full block.
+    #[derive(Clone)]
+    struct BlockPeer {
+        sender: Sender<CanMessage>,
+        block_size: u8,
+        length: usize,
+        records: Arc<Mutex<BlockRecords>>,
+    }
+
+    impl BlockPeer {
+        fn flow_ctrl(&self) {
+            let fc = CanIsoTpFrame::flow_ctrl_frame(FlowControlState::Continues, self.block_size, 0).unwrap();
+            let mut fc = CanMessage::from_iso_tp(Id::Standard(0x7E8), fc, None).unwrap();
+            fc.set_channel(MOCK_CHANNEL.into());
+            self.sender.send(fc).unwrap();
+            let mut records = self.records.lock().unwrap();
+            records.flow_ctrls += 1;
+            records.block = 0;
+        }
+    }
+
+    impl Listener<String, u32, CanMessage> for BlockPeer {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_received(&mut self, _: String, frames: &[CanMessage]) {
+            for frame in frames.iter().filter(|v| v.id().into_bits() == 0x7E0) {
+                match CanIsoTpFrame::decode(frame.data()).unwrap() {
+                    CanIsoTpFrame::FirstFrame { length, data } => {
+                        self.length = length as usize;
+                        self.records.lock().unwrap().bytes = data.len();
+                        self.flow_ctrl();
+                    },
+                    CanIsoTpFrame::ConsecutiveFrame { data, .. } => {
+                        let next_block = {
+                            let mut records = self.records.lock().unwrap();
+                            // the last consecutive frame is padded
+                            records.bytes = (records.bytes + data.len()).min(self.length);
+                            records.block += 1;
+                            records.max_block = records.max_block.max(records.block);
+                            records.block == self.block_size as usize && records.bytes < self.length
+                        };
+                        if next_block {
+                            self.flow_ctrl();
+                        }
+                    },
+                    _ => {},
+                }
+            }
+        }
+    }
+
+    /// The mock driver taking `delay` to transmit each frame, e.g. a slow adapter.
+    #[derive(Clone)]
+    struct SlowDriver(MockDriver, Duration);
+
+    impl Driver for SlowDriver {
+        type Error = Error;
+        type C = String;
+        type F = CanMessage;
+
+        fn opened_channels(&self) -> Vec<Self::C> {
+            self.0.opened_channels()
+        }
+
+        fn is_closed(&self) -> bool {
+            self.0.is_closed()
+        }
+
+        fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> Result<(), Self::Error> {
+            sleep(self.1);
+            self.0.transmit(msg, timeout)
+        }
+
+        fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+            self.0.receive(channel, timeout)
+        }
+
+        fn shutdown(&mut self) {
+            self.0.shutdown()
+        }
+    }
+
+    /// A transport without the loops, the frames sent are left in the receiver.
+    fn detached(address: Address, listener: Box<dyn IsoTpEventListener>) -> (SyncCanIsoTp<String, CanMessage>, Receiver<CanMessage>) {
+        let (sender, receiver) = channel();
+        (SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), address, sender, listener), receiver)
+    }
+
+    /// Wait until `done` or `timeout`.
+    fn wait_until(timeout: Duration, done: impl Fn() -> bool) {
+        let start = Instant::now();
+        while !done() && start.elapsed() < timeout {
+            sleep(Duration::from_millis(1));
+        }
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_normal_fixed() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let tester_data = DataListener::default();
+        let tester = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address::normal_fixed(0xF1, 0x00),
+            link.client_can.sender(),
+            Box::new(tester_data.clone()),
+        ).with_address_format(AddressFormat::NormalFixed);
+        let engine_data = DataListener::default();
+        let engine = SyncCanIsoTp::new(
+            MOCK_CHANNEL.to_string(),
+            Address::normal_fixed(0x00, 0xF1),
+            link.server_can.sender(),
+            Box::new(engine_data.clone()),
+        ).with_address_format(AddressFormat::NormalFixed);
+        let tester_echo = EchoListener::default();
+        let engine_echo = EchoListener::default();
+        link.client_can.register_listener("echo".into(), Box::new(tester_echo.clone()));
+        link.server_can.register_listener("echo".into(), Box::new(engine_echo.clone()));
+        link.start(tester.clone(), engine);
+
+        let request = (0..0x20).collect::<Vec<u8>>();
+        tester.write(false, request.clone())?;
+        sleep(Duration::from_millis(10));
+        assert_eq!(engine_data.0.lock().unwrap().take(), Some(request));
+
+        let frames = tester_echo.0.lock().unwrap().clone();
+        assert_eq!(frames.len(), 5);
+        assert!(frames.iter().all(|f| f.is_extended() && f.id().into_bits() == 0x18DA00F1));
+        let frames = engine_echo.0.lock().unwrap().clone();
+        assert_eq!(frames.len(), 1);
+        assert!(frames[0].is_extended() && frames[0].id().into_bits() == 0x18DAF100);
+
+        // the priority is ignored when matching the source and target addresses
+        for (id, data) in [(0x1CDAF100, hex!("02 50 01")), (0x18DAF117, hex!("02 50 03"))] {
+            let mut frame = CanMessage::new(Id::Extended(id), &data).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            link.bus.driver(1).transmit(frame, None)?;
+            sleep(Duration::from_millis(10));
+        }
+        assert_eq!(tester_data.0.lock().unwrap().take(), Some(hex!("50 01").to_vec()));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_verbose_events() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let client_events = EventListener::default();
+        let client = link.client(Box::new(client_events.clone()))
+            .with_verbosity(IsoTpVerbosity::Verbose);
+        let server_events = EventListener::default();
+        let server = link.server(Box::new(server_events.clone()))
+            .with_verbosity(IsoTpVerbosity::Verbose);
+        link.start(client.clone(), server.clone());
+
+        client.write(false, (0..0x20).collect())?;
+        sleep(Duration::from_millis(10));
+        let events = client_events.0.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert!(matches!(events.as_slice(), [
+            IsoTpEvent::FlowControlReceived(_),
+            IsoTpEvent::TxCompleted { bytes: 0x20 },
+        ]), "{:?}", events);
+        // the consecutive frames are reported by `Wait`
+        let events = server_events.0.lock().unwrap()
+            .drain(..)
+            .filter(|v| !matches!(v, IsoTpEvent::Wait))
+            .collect::<Vec<_>>();
+        assert!(matches!(events.as_slice(), [
+            IsoTpEvent::FirstFrameReceived { .. },
+            IsoTpEvent::FlowControlSent(_),
+            IsoTpEvent::DataReceived { .. },
+        ]), "{:?}", events);
+
+        // the flow control sent by the server doesn't complete its writing
+        server.write(false, hex!("02 50 01").to_vec())?;
+        sleep(Duration::from_millis(10));
+        let events = server_events.0.lock().unwrap().drain(..).collect::<Vec<_>>();
+        assert!(matches!(events.as_slice(), [IsoTpEvent::TxCompleted { bytes: 3 }]), "{:?}", events);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_verbosity_default() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let client_events = EventListener::default();
+        let client = link.client(Box::new(client_events.clone()));
+        let server = link.server(Box::new(EmptyListener));
+        link.start(client.clone(), server);
+
+        client.write(false, (0..0x20).collect())?;
+        sleep(Duration::from_millis(10));
+        assert!(client_events.0.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn test_clear_buffer() {
+        let spy = SpyListener::default();
+        let (iso_tp, _receiver) = detached(SERVER, Box::new(spy.clone()));
+
+        // a new first frame discards the stale partial data
+        iso_tp.on_first_frame(0x7E8, 0x0A, &hex!("01 02 03 04 05 06"), 1000);
+        iso_tp.on_first_frame(0x7E8, 0x09, &hex!("11 12 13 14 15 16"), 1002);
+        iso_tp.on_consecutive_frame(1, &hex!("17 18 19"), 1010);
+        {
+            let (count, events) = &*spy.0.lock().unwrap();
+            assert_eq!(*count, 2);
+            let event = events.last().unwrap();
+            assert!(matches!(event, IsoTpEvent::DataReceived { first_frame_at: 1002, completed_at: 1010, .. }));
+            assert_eq!(event.data(), Some(hex!("11 12 13 14 15 16 17 18 19").as_slice()));
+            assert_eq!(event.duration_ms(), Some(8));
+            // the clones share the data, copied out only by `into_data`
+            assert!(Arc::ptr_eq(&event.shared_data().unwrap(), &event.clone().shared_data().unwrap()));
+            assert_eq!(event.clone().into_data(), Some(hex!("11 12 13 14 15 16 17 18 19").to_vec()));
+        }
+
+        // aborted by bus off
+        iso_tp.on_first_frame(0x7E8, 0x0A, &hex!("01 02 03 04 05 06"), 1020);
+        iso_tp.on_bus_off();
+        let (count, events) = &*spy.0.lock().unwrap();
+        assert_eq!(*count, 4);
+        assert!(matches!(events.last(), Some(IsoTpEvent::ErrorOccurred(Error::BusOff))));
+    }
+
+    #[test]
+    fn test_update_address_concurrent() {
+        const ROUNDS: usize = 10_000;
+        let spy = SpyListener::default();
+        let (iso_tp, _receiver) = detached(CLIENT, Box::new(spy.clone()));
+
+        let updater = spawn({
+            let iso_tp = iso_tp.clone();
+            move || for i in 0..ROUNDS {
+                let rx_id = if i % 2 == 0 { 0x7E9 } else { 0x7E8 };
+                iso_tp.update_address(Address { rx_id, ..CLIENT });
+            }
+        });
+        // exactly one of the frames matches the address snapshot of each call
+        let frames = [
+            CanMessage::new(Id::Standard(0x7E8), &hex!("02 50 01")).unwrap(),
+            CanMessage::new(Id::Standard(0x7E9), &hex!("02 50 03")).unwrap(),
+        ];
+        let flooder = spawn({
+            let mut iso_tp = iso_tp.clone();
+            move || for _ in 0..ROUNDS {
+                iso_tp.on_frame_received(MOCK_CHANNEL.to_string(), &frames);
+            }
+        });
+        updater.join().unwrap();
+        flooder.join().unwrap();
+
+        let (_, events) = &*spy.0.lock().unwrap();
+        assert_eq!(events.len(), ROUNDS);
+        assert!(events.iter().all(|v| matches!(v, IsoTpEvent::DataReceived { .. })));
+        assert_eq!(iso_tp.address.load().rx_id, 0x7E8);
+    }
+
+    #[test]
+    fn test_reception_aborted() {
+        let spy = SpyListener::default();
+        let (iso_tp, _receiver) = detached(SERVER, Box::new(spy.clone()));
+
+        iso_tp.on_first_frame(0x7E8, 0x14, &hex!("01 02 03 04 05 06"), 1000);
+        iso_tp.on_consecutive_frame(1, &hex!("07 08 09 0A 0B 0C 0D"), 1001);
+        // the sequence 2 is lost
+        iso_tp.on_consecutive_frame(3, &hex!("15 16 17 18 19 1A 1B"), 1003);
+
+        let (_, events) = &*spy.0.lock().unwrap();
+        match events.last() {
+            Some(event @ IsoTpEvent::ReceptionAborted { partial, expected, .. }) => {
+                assert_eq!(partial, &hex!("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D"));
+                assert_eq!(*expected, 0x14);
+                assert_eq!(event.error(), Some(&Error::InvalidSequence { expect: 2, actual: 3 }));
             },
-            Err(_) =>log::warn!("ISO-TP(CAN sync): state mutex is poisoned when removing"),
+            v => panic!("unexpected event: {:?}", v),
+        }
+        let context = iso_tp.context.lock().unwrap();
+        assert_eq!(context.consecutive, Default::default());
+    }
+
+    #[test]
+    fn test_first_frame_policy() {
+        // the first frame and 5 consecutive frames
+        let first = (1..=0x29).collect::<Vec<u8>>();
+        let second = (0x81..=0x8D).collect::<Vec<u8>>();
+        for policy in [FirstFramePolicy::Restart, FirstFramePolicy::Ignore] {
+            let spy = SpyListener::default();
+            let (iso_tp, _receiver) = detached(SERVER, Box::new(spy.clone()));
+            let iso_tp = iso_tp.with_first_frame_policy(policy);
+
+            iso_tp.on_first_frame(0x7E8, first.len() as u32, &first[..6], 1000);
+            iso_tp.on_consecutive_frame(1, &first[6..13], 1001);
+            iso_tp.on_consecutive_frame(2, &first[13..20], 1002);
+            // a new transfer in the middle
+            iso_tp.on_first_frame(0x7E8, second.len() as u32, &second[..6], 1003);
+            match policy {
+                FirstFramePolicy::Restart => iso_tp.on_consecutive_frame(1, &second[6..], 1004),
+                FirstFramePolicy::Ignore => for (i, chunk) in first[20..].chunks(7).enumerate() {
+                    iso_tp.on_consecutive_frame(i as u8 + 3, chunk, 1004 + i as u64);
+                },
+            }
+
+            let (_, events) = &*spy.0.lock().unwrap();
+            let events = events.iter()
+                .filter(|v| !matches!(v, IsoTpEvent::FirstFrameReceived { .. } | IsoTpEvent::Wait))
+                .collect::<Vec<_>>();
+            match policy {
+                FirstFramePolicy::Restart => {
+                    assert_eq!(events.len(), 2, "{:?}", events);
+                    match events[0] {
+                        event @ IsoTpEvent::ReceptionAborted { partial, expected, .. } => {
+                            assert_eq!(partial, &first[..20]);
+                            assert_eq!(*expected, first.len() as u32);
+                            assert_eq!(event.error(), Some(&Error::ReceptionRestarted));
+                        },
+                        v => panic!("unexpected event: {:?}", v),
+                    }
+                    assert_eq!(events[1].data(), Some(second.as_slice()));
+                },
+                FirstFramePolicy::Ignore => {
+                    assert_eq!(events.len(), 1, "{:?}", events);
+                    assert_eq!(events[0].data(), Some(first.as_slice()));
+                },
+            }
+            assert!(!iso_tp.state().contains(IsoTpState::Error));
+        }
+    }
+
+    #[test]
+    fn test_half_duplex_echo() -> anyhow::Result<()> {
+        let spy = SpyListener::default();
+        let (mut iso_tp, receiver) = detached(Address { rx_id: 0x7E0, ..CLIENT }, Box::new(spy.clone()));
+
+        iso_tp.write(false, vec![0x3E, 0x00])?;
+        let mut echo = receiver.try_recv()?;
+        // flagged by the driver
+        iso_tp.on_frame_received(MOCK_CHANNEL.to_string(), &[echo.clone()]);
+        // not flagged, matched by the content of the unconfirmed frame
+        echo.set_direct(Direct::Receive);
+        iso_tp.on_frame_received(MOCK_CHANNEL.to_string(), &[echo.clone()]);
+        assert!(spy.0.lock().unwrap().1.is_empty());
+
+        // the same content from the peer
+        iso_tp.on_frame_received(MOCK_CHANNEL.to_string(), &[echo]);
+        let (_, events) = &*spy.0.lock().unwrap();
+        assert!(matches!(events.as_slice(), [v] if v.data() == Some([0x3E, 0x00].as_slice())), "{:?}", events);
+        Ok(())
+    }
+
+    #[test]
+    fn test_introspection() -> anyhow::Result<()> {
+        let (iso_tp, _receiver) = detached(SERVER, Box::new(EmptyListener));
+        assert!(iso_tp.is_idle());
+        assert_eq!(iso_tp.state().to_string(), "Idle");
+
+        iso_tp.on_first_frame(0x7E8, 0x14, &hex!("01 02 03 04 05 06"), 1000);
+        assert_eq!(iso_tp.rx_in_progress(), Some((6, 0x14)));
+        iso_tp.on_consecutive_frame(1, &hex!("07 08 09 0A 0B 0C 0D"), 1001);
+        assert_eq!(iso_tp.rx_in_progress(), Some((13, 0x14)));
+        assert!(!iso_tp.is_idle());
+        iso_tp.on_consecutive_frame(2, &hex!("0E 0F 10 11 12 13 14"), 1002);
+        assert_eq!(iso_tp.rx_in_progress(), None);
+
+        let CanIsoTpFrame::FlowControlFrame(ctx) = CanIsoTpFrame::flow_ctrl_frame(FlowControlState::Wait, 8, 0)? else {
+            unreachable!()
+        };
+        iso_tp.on_flow_ctrl_frame(ctx);
+        let last = iso_tp.last_flow_control().unwrap();
+        assert_eq!((last.state(), last.block_size()), (FlowControlState::Wait, 8));
+        // not writing, the flow control is ignored and the one replying the first frame is not confirmed
+        assert_eq!(iso_tp.state(), IsoTpState::Sending);
+        assert_eq!(iso_tp.state().to_string(), "Sending");
+        Ok(())
+    }
+
+    #[test]
+    fn test_capabilities() {
+        let (driver, _) = VirtualBus::pair();
+        let can: SyncCan<MockDriver, String, CanMessage> = SyncCan::new(driver);
+        let capabilities = can.capabilities();
+        assert!(capabilities.fd && capabilities.blocking_receive);
+
+        let (iso_tp, _receiver) = detached(SERVER, Box::new(EmptyListener));
+        assert!(iso_tp.clone().with_capabilities(capabilities).is_ok());
+        // a classic CAN driver
+        let result = iso_tp.with_capabilities(DriverCapabilities::default());
+        if cfg!(feature = "can-fd") {
+            assert!(matches!(result, Err(Error::Unsupported(_))));
+        }
+        else {
+            assert!(result.is_ok());
+        }
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_listener_from_fn() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let received = Arc::new(Mutex::new(vec![]));
+        let client = link.client(Box::new(EmptyListener));
+        let server = link.server(<dyn IsoTpEventListener>::from_fn({
+            let received = received.clone();
+            move |event| if let IsoTpEvent::DataReceived { data, .. } = event {
+                received.lock().unwrap().push(data.to_vec());
+            }
+        }));
+        link.start(client.clone(), server);
+
+        client.write(false, (0..0x20).collect())?;
+        sleep(Duration::from_millis(10));
+        assert_eq!(received.lock().unwrap().as_slice(), [(0..0x20).collect::<Vec<u8>>()]);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_block_size() -> anyhow::Result<()> {
+        // the first frame and 36 consecutive frames
+        const LENGTH: usize = 0x100;
+        for block_size in [0u8, 1, 2, 8] {
+            let mut link = Link::new();
+            let events = EventListener::default();
+            let client = link.client(Box::new(events.clone()))
+                .with_verbosity(IsoTpVerbosity::Verbose);
+            let records = Arc::new(Mutex::new(BlockRecords::default()));
+            let peer = BlockPeer { sender: link.server_can.sender(), block_size, length: 0, records: records.clone() };
+            link.start(client.clone(), peer);
+
+            client.write(false, (0..LENGTH).map(|v| v as u8).collect())?;
+            wait_until(Duration::from_secs(1), || records.lock().unwrap().bytes >= LENGTH);
+
+            let records = records.lock().unwrap();
+            assert_eq!(records.bytes, LENGTH, "block size: {}", block_size);
+            let expected = match block_size {
+                0 => (1, 36),
+                v => (36usize.div_ceil(v as usize), v as usize),
+            };
+            assert_eq!((records.flow_ctrls, records.max_block), expected, "block size: {}", block_size);
+            let events = events.0.lock().unwrap();
+            assert!(matches!(events.last(), Some(IsoTpEvent::TxCompleted { bytes: LENGTH })), "{:?}", events);
+        }
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_write_to() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let client = link.client(Box::new(EmptyListener));
+        // the servers reply the flow controls to the rx_id of the client
+        let servers = [0x7E1, 0x18DA_10F1].map(|rx_id| {
+            let events = EventListener::default();
+            let server = link.server(Box::new(events.clone()));
+            server.update_address(Address { rx_id, ..SERVER });
+            (server, events)
+        });
+        let [(first, _), (second, _)] = &servers;
+        link.server_can.register_listener("second".into(), Box::new(second.clone()));
+        link.start(client.clone(), first.clone());
+
+        let data = (0..0x40).collect::<Vec<u8>>();
+        client.write_to(0x7E1, false, data.clone())?;
+        client.write_to(0x18DA_10F1, true, data.iter().rev().copied().collect())?;
+        for ((_, server), expected) in servers.iter().zip([data.clone(), data.iter().rev().copied().collect()]) {
+            wait_until(Duration::from_secs(1), || server.0.lock().unwrap().iter().any(|v| v.data().is_some()));
+            let events = server.0.lock().unwrap();
+            let received = events.iter().filter_map(|v| v.data()).collect::<Vec<_>>();
+            assert_eq!(received, vec![expected.as_slice()]);
+        }
+        assert_eq!(client.address.load().tx_id, 0x7E0);
+        Ok(())
+    }
+
+    #[test]
+    fn test_rx_matcher() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let client = link.client(Box::new(EmptyListener));
+        // only the server accepting the functional address receives the request
+        let servers = [true, false].map(|functional| {
+            let events = EventListener::default();
+            let server = link.server(Box::new(events.clone()));
+            server.update_address(Address { tx_id: 0, rx_id: 0, fid: 0 });
+            let server = server
+                .with_rx_matcher(RxMatcher::new(SERVER, AddressFormat::Normal).with_functional(functional));
+            assert_eq!(**server.address.load(), SERVER);
+            (server, events)
+        });
+        let [(functional, _), (physical, _)] = &servers;
+        link.server_can.register_listener("physical".into(), Box::new(physical.clone()));
+        link.start(client.clone(), functional.clone());
+
+        client.write(true, vec![0x3E, 0x00])?;
+        let [(_, functional), (_, physical)] = &servers;
+        wait_until(Duration::from_secs(1), || !functional.0.lock().unwrap().is_empty());
+        sleep(Duration::from_millis(50));
+        assert_eq!(functional.0.lock().unwrap().iter().filter_map(|v| v.data()).collect::<Vec<_>>(), vec![[0x3E, 0x00].as_slice()]);
+        assert!(physical.0.lock().unwrap().is_empty());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_listen_only() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(3);
+        let mut monitor_can = SyncCan::new(bus.driver(2));
+        let mut link = Link::on(bus);
+        let client = link.client(Box::new(EmptyListener));
+        let server_events = EventListener::default();
+        let server = link.server(Box::new(server_events.clone()))
+            // a block of 2 frames, the monitor follows the flow controls of the server
+            .with_flow_control(FlowControlContext::new(FlowControlState::Continues, 2, 0)?);
+        // the sender of the monitor is checked instead of the one of the loop
+        let monitor_events = EventListener::default();
+        let (monitor, receiver) = detached(SERVER, Box::new(monitor_events.clone()));
+        let monitor = monitor.with_listen_only(true);
+        monitor_can.register_listener("monitor".into(), Box::new(monitor.clone()));
+        link.start(client.clone(), server);
+        monitor_can.sync_start(100);
+
+        let request = (0..0x40).collect::<Vec<u8>>();
+        client.write(false, request.clone())?;
+        // the server and the monitor receive the last frame concurrently
+        wait_until(Duration::from_secs(1), || [&server_events, &monitor_events].iter()
+            .all(|v| v.0.lock().unwrap().iter().any(|v| v.data().is_some())));
+        for events in [&server_events, &monitor_events] {
+            let events = events.0.lock().unwrap();
+            assert_eq!(events.iter().filter_map(|v| v.data()).collect::<Vec<_>>(), vec![request.as_slice()]);
+        }
+        assert!(monitor_events.0.lock().unwrap().iter().any(|v| matches!(v, IsoTpEvent::FirstFrameReceived { .. })));
+
+        assert_eq!(monitor.write(false, vec![0x3E, 0x00]), Err(Error::ListenOnly));
+        assert!(monitor.is_idle());
+        assert!(receiver.try_recv().is_err());
+
+        monitor_can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_tx_generations() -> anyhow::Result<()> {
+        let (a, peer) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let client = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, can.sender(), Box::new(EmptyListener))
+            .with_tx_generations(can.tx_generations());
+        can.register_listener("client".into(), Box::new(client.clone()));
+        can.sync_start(100);
+        let receive = |timeout: Duration| {
+            let mut frames = Vec::new();
+            let start = Instant::now();
+            while frames.is_empty() && start.elapsed() < timeout {
+                frames = peer.receive(MOCK_CHANNEL.into(), Some(10)).unwrap_or_default();
+            }
+            frames
+        };
+
+        // the first frame and 9 consecutive frames
+        let writer = {
+            let client = client.clone();
+            spawn(move || client.write(false, (0..0x40).collect()))
+        };
+        let frames = receive(Duration::from_secs(1));
+        assert!(matches!(frames.as_slice(), [v] if v.data()[..2] == [0x10, 0x40]));
+
+        // the consecutive frames are queued by the paused transmit loop
+        can.pause(false);
+        let mut flow_ctrl = CanMessage::new(Id::Standard(0x7E8), &[0x30, 0x00, 0x00]).unwrap();
+        flow_ctrl.set_channel(MOCK_CHANNEL.into());
+        peer.transmit(flow_ctrl, None)?;
+        wait_until(Duration::from_secs(1), || can.tx_generations().queued(0x7E0) >= 9);
+        assert_eq!(can.tx_generations().queued(0x7E0), 9);
+
+        assert!(client.abort());
+        let result = writer.join().unwrap();
+        assert!(matches!(result, Err(Error::Aborted(_))), "{:?}", result);
+        assert!(!client.abort());
+        can.resume();
+        client.write(false, vec![0x3E, 0x00])?;
+
+        // no frame of the aborted writing after the abort
+        let frames = receive(Duration::from_secs(1));
+        assert!(matches!(frames.as_slice(), [v] if v.data()[..3] == [0x02, 0x3E, 0x00]), "{:?}", frames);
+        assert!(receive(Duration::from_millis(50)).is_empty());
+        assert_eq!(can.tx_generations().generation(0x7E0), 1);
+
+        can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_confirmation_matching() -> anyhow::Result<()> {
+        let events = EventListener::default();
+        let (client, receiver) = detached(CLIENT, Box::new(events.clone()));
+        let mut client = client.with_verbosity(IsoTpVerbosity::Verbose);
+        let mut keep_alive = CanMessage::new(0x7E0, &hex!("02 3E 80")).unwrap();
+        keep_alive.set_channel(MOCK_CHANNEL.into()).set_direct(Direct::Transmit);
+        let mut fc = CanMessage::from_iso_tp(Id::Standard(0x7E8), CanIsoTpFrame::flow_ctrl_frame(FlowControlState::Continues, 1, 0)?, None).unwrap();
+        fc.set_channel(MOCK_CHANNEL.into());
+
+        // the first frame and 2 consecutive frames by the block size 1
+        let writer = client.clone();
+        let handle = spawn(move || writer.write(false, (0..20).collect()));
+        let first = receiver.recv_timeout(Duration::from_secs(1))?;
+        assert_eq!(client.state(), IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
+        // the keep-alive of the same identifier confirms nothing of the writing
+        Listener::on_frame_transmitted(&mut client, MOCK_CHANNEL.into(), &keep_alive);
+        assert_eq!(client.state(), IsoTpState::Sending | IsoTpState::WaitFlowCtrl);
+        Listener::on_frame_transmitted(&mut client, MOCK_CHANNEL.into(), &first);
+        assert_eq!(client.state(), IsoTpState::WaitFlowCtrl);
+
+        for _ in 0..2 {
+            Listener::on_frame_received(&mut client, MOCK_CHANNEL.into(), &[fc.clone()]);
+            let cf = receiver.recv_timeout(Duration::from_secs(1))?;
+            assert!(matches!(CanIsoTpFrame::decode(cf.data())?, CanIsoTpFrame::ConsecutiveFrame { .. }));
+            Listener::on_frame_transmitted(&mut client, MOCK_CHANNEL.into(), &keep_alive);
+            assert!(client.state().contains(IsoTpState::Sending));
+            assert!(!events.0.lock().unwrap().iter().any(|v| matches!(v, IsoTpEvent::TxCompleted { .. })));
+            Listener::on_frame_transmitted(&mut client, MOCK_CHANNEL.into(), &cf);
+            assert!(!client.state().contains(IsoTpState::Sending));
+            // the next block waits for the flow control
+            assert!(receiver.recv_timeout(Duration::from_millis(20)).is_err());
+        }
+        handle.join().unwrap()?;
+
+        assert_eq!(client.state(), IsoTpState::Idle);
+        let events = events.0.lock().unwrap();
+        assert!(matches!(events.last(), Some(IsoTpEvent::TxCompleted { bytes: 20 })), "{:?}", events);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_keep_alive_interleaved() -> anyhow::Result<()> {
+        // the first frame and 36 consecutive frames
+        const LENGTH: usize = 0x100;
+        let mut link = Link::new();
+        let events = EventListener::default();
+        let client = link.client(Box::new(events.clone()))
+            .with_verbosity(IsoTpVerbosity::Verbose);
+        let records = Arc::new(Mutex::new(BlockRecords::default()));
+        let peer = BlockPeer { sender: link.server_can.sender(), block_size: 4, length: 0, records: records.clone() };
+        link.start(client.clone(), peer);
+
+        // the keep-alive shares the identifier of the writing
+        let mut keep_alive = CanMessage::new(0x7E0, &hex!("02 3E 80")).unwrap();
+        keep_alive.set_channel(MOCK_CHANNEL.into());
+        let handle = link.client_can.add_cyclic("keep-alive".into(), keep_alive, Duration::from_millis(1));
+
+        client.write(false, (0..LENGTH).map(|v| v as u8).collect())?;
+        wait_until(Duration::from_secs(1), || records.lock().unwrap().bytes >= LENGTH
+            && events.0.lock().unwrap().iter().any(|v| matches!(v, IsoTpEvent::TxCompleted { .. })));
+        link.client_can.remove_cyclic(&handle);
+
+        let records = records.lock().unwrap();
+        assert_eq!(records.bytes, LENGTH);
+        assert_eq!((records.flow_ctrls, records.max_block), (9, 4));
+        let events = events.0.lock().unwrap();
+        let completed = events.iter()
+            .filter(|v| matches!(v, IsoTpEvent::TxCompleted { .. }))
+            .collect::<Vec<_>>();
+        assert!(matches!(completed[..], [IsoTpEvent::TxCompleted { bytes: LENGTH }]), "{:?}", events);
+        assert_eq!(client.tx_stats().flow_controls, 9);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_pipelining() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let events = EventListener::default();
+        let client = link.client(Box::new(events.clone()))
+            .with_verbosity(IsoTpVerbosity::Verbose)
+            .with_tx_generations(link.client_can.tx_generations())
+            .with_pipelining(true);
+        let server_events = EventListener::default();
+        let server = link.server(Box::new(server_events.clone()))
+            .with_flow_control(FlowControlContext::ISO15765_4);
+        link.start(client.clone(), server);
+
+        // the single and multi-frame blocks back to back
+        let blocks = [2, 0x20, 5, 0x40, 0x30, 3]
+            .map(|length| (0..length).map(|v| (v + length) as u8).collect::<Vec<_>>());
+        for block in &blocks {
+            client.write(false, block.clone())?;
         }
+        wait_until(Duration::from_secs(1), || server_events.0.lock().unwrap().iter()
+            .filter(|v| v.data().is_some())
+            .count() >= blocks.len());
+        wait_until(Duration::from_secs(1), || client.state() == IsoTpState::Idle);
+
+        let received = server_events.0.lock().unwrap().iter()
+            .filter_map(|v| v.data().map(|v| v.to_vec()))
+            .collect::<Vec<_>>();
+        assert_eq!(received, blocks);
+        let events = events.0.lock().unwrap();
+        assert!(!events.iter().any(|v| matches!(v, IsoTpEvent::ErrorOccurred(_))), "{:?}", events);
+        let completed = events.iter()
+            .filter_map(|v| match v {
+                IsoTpEvent::TxCompleted { bytes } => Some(*bytes),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(completed, blocks.iter().map(Vec::len).collect::<Vec<_>>());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_frame_tap() -> anyhow::Result<()> {
+        let mut link = Link::new();
+        let client = link.client(Box::new(EmptyListener));
+        let server_events = EventListener::default();
+        let server = link.server(Box::new(server_events.clone()))
+            .with_flow_control(FlowControlContext::ISO15765_4);
+        let client_pdus = Arc::new(Mutex::new(Vec::new()));
+        let server_pdus = Arc::new(Mutex::new(Vec::new()));
+        for (iso_tp, pdus) in [(&client, &client_pdus), (&server, &server_pdus)] {
+            let pdus = pdus.clone();
+            iso_tp.set_frame_tap(Some(Box::new(move |direction, frame: &CanIsoTpFrame| {
+                pdus.lock().unwrap().push((direction, FrameType::from(frame)));
+            })));
+        }
+        link.start(client.clone(), server.clone());
+
+        // the first frame and 2 consecutive frames, replied by a single frame
+        client.write(false, (0..20).collect())?;
+        wait_until(Duration::from_secs(1), || server_events.0.lock().unwrap().iter().any(|v| v.data().is_some()));
+        server.write(false, hex!("7E 00").to_vec())?;
+        wait_until(Duration::from_secs(1), || client_pdus.lock().unwrap().len() >= 5);
+
+        assert_eq!(*client_pdus.lock().unwrap(), [
+            (Direction::Tx, FrameType::First),
+            (Direction::Rx, FrameType::FlowControl),
+            (Direction::Tx, FrameType::Consecutive),
+            (Direction::Tx, FrameType::Consecutive),
+            (Direction::Rx, FrameType::Single),
+        ]);
+        assert_eq!(*server_pdus.lock().unwrap(), [
+            (Direction::Rx, FrameType::First),
+            (Direction::Tx, FrameType::FlowControl),
+            (Direction::Rx, FrameType::Consecutive),
+            (Direction::Rx, FrameType::Consecutive),
+            (Direction::Tx, FrameType::Single),
+        ]);
+        Ok(())
+    }
+
+    #[test]
+    fn test_pipelining_error() -> anyhow::Result<()> {
+        let generations = TxGenerations::default();
+        let events = EventListener::default();
+        let (client, receiver) = detached(CLIENT, Box::new(events.clone()));
+        let mut client = client
+            .with_verbosity(IsoTpVerbosity::Verbose)
+            .with_tx_generations(generations.clone())
+            .with_pipelining(true);
+        let failed = Error::device(std::io::Error::other("TX buffer full"));
+
+        // the writing 2 is queued behind the writing 1 awaiting its confirmation
+        client.write(false, hex!("10 03").to_vec())?;
+        client.write(false, hex!("11 01").to_vec())?;
+        let first = receiver.try_recv()?;
+        assert!(generations.is_current(&first));
+        Listener::on_frame_transmit_failed(&mut client, MOCK_CHANNEL.into(), 0x7E0, &failed);
+        // dropped by the transmit loop
+        let second = receiver.try_recv()?;
+        assert!(!generations.is_current(&second));
+        {
+            let events = events.0.lock().unwrap();
+            assert!(matches!(events.as_slice(), [IsoTpEvent::ErrorOccurred(e), IsoTpEvent::ErrorOccurred(Error::Aborted(_))] if e == &failed),
+                "{:?}", events);
+        }
+
+        // the writing 3 isn't failed by the aborted one
+        client.write(false, hex!("3E 00").to_vec())?;
+        let third = receiver.try_recv()?;
+        assert!(generations.is_current(&third));
+        Listener::on_frame_transmitted(&mut client, MOCK_CHANNEL.into(), &third);
+        assert!(matches!(events.0.lock().unwrap().last(), Some(IsoTpEvent::TxCompleted { bytes: 2 })));
+
+        // the writing 4 fails after it returned, the writing 5 isn't started
+        client.write(false, hex!("22 F1 90").to_vec())?;
+        let fourth = receiver.try_recv()?;
+        Listener::on_frame_transmit_failed(&mut client, MOCK_CHANNEL.into(), 0x7E0, &failed);
+        assert!(generations.is_current(&fourth));
+        assert!(matches!(client.write(false, hex!("11 01").to_vec()), Err(Error::Aborted(_))));
+        assert!(receiver.try_recv().is_err());
+        assert!(matches!(events.0.lock().unwrap().last(), Some(IsoTpEvent::ErrorOccurred(e)) if e == &failed));
+
+        client.write(false, hex!("11 01").to_vec())?;
+        assert!(receiver.try_recv().is_ok());
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_transmit_retry() -> anyhow::Result<()> {
+        // the first frame and 72 consecutive frames
+        const LENGTH: usize = 0x200;
+        let mut link = Link::new();
+        let client = link.client(Box::new(EmptyListener))
+            .with_retry_policy(RetryPolicy::new(3, Duration::from_millis(1)));
+        let events = EventListener::default();
+        let server = link.server(Box::new(events.clone()));
+        link.start(client.clone(), server);
+
+        link.bus.driver(0).set_tx_failure_rate(0.05);
+        let data = (0..LENGTH).map(|v| v as u8).collect::<Vec<_>>();
+        client.write(false, data.clone())?;
+        wait_until(Duration::from_secs(1), || events.0.lock().unwrap().iter().any(|v| v.data().is_some()));
+
+        assert!(client.retries() > 0);
+        let events = events.0.lock().unwrap();
+        assert!(!events.iter().any(|v| matches!(v, IsoTpEvent::ErrorOccurred(_))), "{:?}", events);
+        assert_eq!(events.iter().find_map(|v| v.data()), Some(data.as_slice()));
+        drop(events);
+
+        // the writing fails when the retries are exhausted
+        link.bus.driver(0).set_tx_failure_rate(1.);
+        let retries = client.retries();
+        assert!(client.write(false, vec![0x10, 0x01]).is_err());
+        assert_eq!(client.retries() - retries, 3);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_flow_control_config() -> anyhow::Result<()> {
+        // the first frame and 36 consecutive frames
+        const LENGTH: usize = 0x100;
+        let transfer = |flow_ctrl: FlowControlContext| -> anyhow::Result<(Duration, usize)> {
+            let mut link = Link::new();
+            let client = link.client(Box::new(EmptyListener));
+            let events = EventListener::default();
+            let server = link.server(Box::new(events.clone()))
+                .with_flow_control(flow_ctrl)
+                .with_verbosity(IsoTpVerbosity::Verbose);
+            link.start(client.clone(), server);
+
+            let data = (0..LENGTH).map(|v| v as u8).collect::<Vec<_>>();
+            let start = Instant::now();
+            client.write(false, data.clone())?;
+            wait_until(Duration::from_secs(2), || events.0.lock().unwrap().iter().any(|v| v.data().is_some()));
+            let elapsed = start.elapsed();
+
+            let events = events.0.lock().unwrap();
+            assert_eq!(events.iter().find_map(|v| v.data()), Some(data.as_slice()));
+            let flow_ctrls = events.iter()
+                .filter(|v| matches!(v, IsoTpEvent::FlowControlSent(_)))
+                .count();
+            Ok((elapsed, flow_ctrls))
+        };
+
+        // STmin=0 and BS=0, the consecutive frames are transmitted back to back
+        let (elapsed, flow_ctrls) = transfer(FlowControlContext::ISO15765_4)?;
+        assert_eq!(flow_ctrls, 1);
+        assert!(elapsed < Duration::from_millis(200), "{:?}", elapsed);
+
+        // STmin=10ms and BS=10, a flow control after each block and a gap before each
+        // consecutive frame except the first one
+        let (elapsed, flow_ctrls) = transfer(FlowControlContext::ISO15765_2)?;
+        assert_eq!(flow_ctrls, 4);
+        assert!(elapsed >= Duration::from_millis(35 * 10), "{:?}", elapsed);
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_sequence_start() -> anyhow::Result<()> {
+        let data = (0..0x20).collect::<Vec<u8>>();
+        // the data or the error received by the server
+        let transfer = |client_start: SequenceStart, server_start: SequenceStart| -> anyhow::Result<Result<Vec<u8>, Error>> {
+            let mut link = Link::new();
+            let client = link.client(Box::new(EmptyListener))
+                .with_sequence_start(client_start);
+            let events = EventListener::default();
+            let server = link.server(Box::new(events.clone()))
+                .with_sequence_start(server_start);
+            link.start(client.clone(), server);
+
+            client.write(false, data.clone())?;
+            let received = || events.0.lock().unwrap().iter()
+                .find_map(|v| match v {
+                    IsoTpEvent::DataReceived { data, .. } => Some(Ok(data.to_vec())),
+                    IsoTpEvent::ErrorOccurred(error)
+                    | IsoTpEvent::ReceptionAborted { error, .. } => Some(Err(error.clone())),
+                    _ => None,
+                });
+            wait_until(Duration::from_secs(1), || received().is_some());
+            Ok(received().unwrap_or(Err(Error::Timeout { value: 1000, unit: "ms" })))
+        };
+
+        // strict
+        assert_eq!(transfer(SequenceStart::default(), SequenceStart::default())?, Ok(data.clone()));
+        assert_eq!(transfer(SequenceStart::Strict(0), SequenceStart::default())?, Err(Error::InvalidSequence { expect: 1, actual: 0 }));
+        assert_eq!(transfer(SequenceStart::Strict(0), SequenceStart::Strict(0))?, Ok(data.clone()));
+        assert_eq!(transfer(SequenceStart::default(), SequenceStart::Strict(0))?, Err(Error::InvalidSequence { expect: 0, actual: 1 }));
+        // auto detect
+        assert_eq!(transfer(SequenceStart::Strict(0), SequenceStart::AutoDetect)?, Ok(data.clone()));
+        assert_eq!(transfer(SequenceStart::default(), SequenceStart::AutoDetect)?, Ok(data.clone()));
+        assert_eq!(transfer(SequenceStart::AutoDetect, SequenceStart::default())?, Ok(data));
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_peer_flow_control() -> anyhow::Result<()> {
+        let (a, peer) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let client = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, can.sender(), Box::new(EmptyListener));
+        can.register_listener("client".into(), Box::new(client.clone()));
+        can.sync_start(100);
+        let receive = |timeout: Duration| {
+            let mut frames = Vec::new();
+            let start = Instant::now();
+            while frames.is_empty() && start.elapsed() < timeout {
+                frames = peer.receive(MOCK_CHANNEL.into(), Some(10)).unwrap_or_default();
+            }
+            frames
+        };
+        // the first frame and a consecutive frame, paced by the flow control `data`
+        let transfer = |data: [u8; 3]| -> anyhow::Result<()> {
+            let writer = {
+                let client = client.clone();
+                spawn(move || client.write(false, (0..8).collect()))
+            };
+            let frames = receive(Duration::from_secs(1));
+            assert!(matches!(frames.as_slice(), [v] if v.data()[..2] == [0x10, 0x08]), "{:?}", frames);
+            let mut flow_ctrl = CanMessage::new(Id::Standard(0x7E8), &data).unwrap();
+            flow_ctrl.set_channel(MOCK_CHANNEL.into());
+            peer.transmit(flow_ctrl, None)?;
+            writer.join().unwrap()?;
+            assert_eq!(receive(Duration::from_secs(1)).len(), 1);
+            Ok(())
+        };
+        assert_eq!(client.peer_flow_control().map(|v| v.st_min()), None);
+
+        // the reserved STmin is taken as 127 ms
+        transfer([0x30, 0x00, 0xFB])?;
+        let ctx = client.peer_flow_control().unwrap();
+        assert_eq!((ctx.block_size(), ctx.st_min(), ctx.raw_st_min()), (0, 0x7F, 0xFB));
+        assert_eq!(client.tx_stats(), TxStats {
+            bytes: 8,
+            frames: 2,
+            flow_controls: 1,
+            block_size: 0,
+            st_min: Duration::from_millis(127),
+        });
+
+        // kept by the single frame without flow control
+        client.write(false, vec![0x3E, 0x00])?;
+        assert_eq!(receive(Duration::from_secs(1)).len(), 1);
+        assert!(client.last_flow_control().is_none());
+        assert_eq!(client.peer_flow_control().map(|v| v.raw_st_min()), Some(0xFB));
+        assert_eq!(client.tx_stats(), TxStats { bytes: 2, frames: 1, ..Default::default() });
+
+        // replaced by the next one
+        transfer([0x30, 0x08, 0xF5])?;
+        let ctx = client.peer_flow_control().unwrap();
+        assert_eq!((ctx.block_size(), ctx.st_min(), ctx.raw_st_min()), (8, 0xF5, 0xF5));
+        assert_eq!(client.tx_stats().st_min, Duration::from_micros(500));
+        assert_eq!(client.tx_stats().block_size, 8);
+
+        can.stop();
+        Ok(())
+    }
+
+    #[cfg(not(feature = "can-fd"))]
+    #[test]
+    fn test_n_br_warning() -> anyhow::Result<()> {
+        let warning = Duration::from_millis(50);
+        for direct in [false, true] {
+            let (a, peer) = VirtualBus::pair();
+            let mut can = SyncCan::new(SlowDriver(a, Duration::from_millis(20)));
+            let events = EventListener::default();
+            let mut server = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), SERVER, can.sender(), Box::new(events.clone()))
+                .with_n_br_warning(warning);
+            if direct {
+                server = server.with_direct_transmit(can.direct_transmit());
+            }
+            can.register_listener("server".into(), Box::new(server.clone()));
+            can.sync_start(100);
+
+            // the transmit loop is busy with the frames queued before the first frame
+            for i in 0..5 {
+                let mut frame = CanMessage::new(0x100, &[i]).unwrap();
+                frame.set_channel(MOCK_CHANNEL.into());
+                can.sender().send(frame)?;
+            }
+            let mut first = CanMessage::new(0x7E0, &hex!("10 14 01 02 03 04 05 06")).unwrap();
+            first.set_channel(MOCK_CHANNEL.into());
+            peer.transmit(first, None)?;
+            wait_until(Duration::from_secs(1), || server.rx_stats().flow_controls > 0);
+
+            let stats = server.rx_stats();
+            let n_br = stats.n_br.unwrap();
+            let delayed = events.0.lock().unwrap().iter()
+                .filter_map(|v| match v {
+                    IsoTpEvent::FlowControlDelayed { n_br } => Some(*n_br),
+                    _ => None,
+                })
+                .collect::<Vec<_>>();
+            assert_eq!(stats.flow_controls, 1);
+            assert_eq!(stats.max_n_br, n_br);
+            if direct {
+                // transmitted before the frames queued
+                assert!(n_br < warning, "{:?}", n_br);
+                assert!(delayed.is_empty());
+                assert_eq!(stats.n_br_exceeded, 0);
+            }
+            else {
+                assert!(n_br > warning, "{:?}", n_br);
+                assert_eq!(delayed, [n_br]);
+                assert_eq!(stats.n_br_exceeded, 1);
+            }
+
+            can.stop();
+        }
+        Ok(())
     }
 }
@@ -1,13 +1,14 @@
 use std::any::Any;
 use std::fmt::Display;
-use crate::{IsoTpEvent, IsoTpFrame, IsoTpState, can::CanIsoTpFrame};
-use crate::can::{isotp::SyncCanIsoTp, frame::Frame};
-use crate::device::Listener;
+use crate::{FrameType, IsoTpFrame, IsoTpState, can::CanIsoTpFrame};
+use crate::can::{isotp::{Direction, SyncCanIsoTp}, frame::{FrameMut, timestamp_or_now}};
+use crate::device::{BusState, Channel, FilterSpec, Listener};
+use crate::error::Error;
 
 impl<C, F> Listener<C, u32, F> for SyncCanIsoTp<C, F>
 where
-    C: Clone + Eq + Display + 'static,
-    F: Frame<Channel = C> + Clone + Display + 'static {
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
 
     fn as_any(&self) -> &dyn Any {
         self
@@ -17,60 +18,99 @@ where
 
     }
 
-    fn on_frame_transmitted(&mut self, channel: C, id: u32) {
-        log::trace!("ISO-TP(CAN sync) transmitted: {:04X} from {}", id, channel);
+    fn on_frame_transmitted(&mut self, channel: C, frame: &F) {
+        let id = frame.id().into_bits();
+        log::trace!("ISO-TP(CAN sync) transmitted: {:04X} from {} at {}", id, channel, frame.timestamp());
         if channel != self.channel {
             return;
         }
 
-        if let Ok(address) = self.address.lock() {
-            if id == address.tx_id ||
-                id == address.fid {
-                self.state_remove(IsoTpState::Sending);
-            }
+        let address = self.address.load();
+        if id == address.tx_id ||
+            id == address.fid ||
+            self.is_write_target(id) {
+            self.on_transmitted(id, frame.data(), timestamp_or_now(frame));
+        }
+    }
+
+    fn on_frame_transmit_failed(&mut self, channel: C, id: u32, error: &Error) {
+        if channel != self.channel {
+            return;
+        }
+
+        let address = self.address.load();
+        if id == address.tx_id || id == address.fid || self.is_write_target(id) {
+            self.on_transmit_failed(id, error);
+        }
+    }
+
+    fn on_bus_state_changed(&mut self, channel: C, state: BusState) {
+        if channel == self.channel && state == BusState::BusOff {
+            self.on_bus_off();
         }
     }
 
+    fn on_shutdown(&mut self) {
+        SyncCanIsoTp::on_shutdown(self);
+    }
+
+    fn rx_filter(&self, channel: &C) -> Option<Vec<FilterSpec>> {
+        if *channel != self.channel {
+            return Some(Vec::new());
+        }
+
+        Some(self.rx_matcher.with_address(**self.address.load()).filters())
+    }
+
     fn on_frame_received(&mut self, channel: C, frames: &[F]) {
         if channel != self.channel
             || self.state_contains(IsoTpState::Error) {
             return;
         }
 
-        let address_id = if let Ok(address) = self.address.lock() {
-            Some((address.tx_id, address.rx_id))
-        }
-        else {
-            None
-        };
+        // a snapshot, the frames are matched by the same address even if it's updated meanwhile
+        let matcher = self.rx_matcher.with_address(**self.address.load());
+        let half_duplex = matcher.address().is_half_duplex();
+        for frame in frames {
+            if let Some(r#type) = matcher.matches(frame) {
+                if half_duplex && self.is_echo(frame) {
+                    continue;
+                }
 
-        if let Some(address) = address_id {
-            for frame in frames {
-                if frame.id().into_bits() == address.1 {
-                    log::debug!("ISO-TP(CAN sync) received: {}", frame);
+                log::debug!("ISO-TP(CAN sync) received: {}", frame);
 
-                    match CanIsoTpFrame::decode(frame.data()) {
-                        Ok(frame) => match frame {
+                let timestamp = timestamp_or_now(frame);
+                match CanIsoTpFrame::decode(matcher.payload(frame.data())) {
+                    Ok(frame) => {
+                        self.frame_tap.tap("sync", Direction::Rx, &frame);
+                        match frame {
                             CanIsoTpFrame::SingleFrame { data } => {
-                                self.on_single_frame(data);
+                                self.on_single_frame(&data, r#type, timestamp);
                             }
                             CanIsoTpFrame::FirstFrame { length, data } => {
-                                self.on_first_frame(address.0, length, data);
+                                self.on_first_frame(matcher.address().tx_id, length, &data, timestamp);
                             }
                             CanIsoTpFrame::ConsecutiveFrame { sequence, data } => {
-                                self.on_consecutive_frame(sequence, data);
+                                self.on_consecutive_frame(sequence, &data, timestamp);
                             },
                             CanIsoTpFrame::FlowControlFrame(ctx) => {
                                 self.on_flow_ctrl_frame(ctx);
                             },
-                        },
-                        Err(e) => {
-                            log::warn!("ISO-TP(CAN sync) - data convert to frame failed: {}", e);
-                            self.state_append(IsoTpState::Error);
-                            self.iso_tp_event(IsoTpEvent::ErrorOccurred(e));
+                        }
+                    },
+                    Err(e) => {
+                        // an invalid flow status aborts the writing waiting for it, the other invalid
+                        // frames are ignored, as ISO 15765-2
+                        let flow_ctrl = matcher.payload(frame.data()).first()
+                            .is_some_and(|v| v & 0xF0 == FrameType::FlowControl as u8);
+                        if flow_ctrl && self.state_contains(IsoTpState::WaitFlowCtrl | IsoTpState::WaitBusy) {
+                            log::warn!("ISO-TP(CAN sync) - invalid flow control: {}", e);
+                            self.on_error(e);
 
                             break;
                         }
+
+                        log::warn!("ISO-TP(CAN sync) - data convert to frame failed, ignored: {}", e);
                     }
                 }
             }
@@ -0,0 +1,80 @@
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use arc_swap::ArcSwapOption;
+use crate::can::CanIsoTpFrame;
+
+/// The direction of a frame passed to the [`FrameTap`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Direction {
+    /// Sent by the instance, including the flow controls replied.
+    Tx,
+    /// Received and decoded by the instance.
+    Rx,
+}
+
+/// Called with each ISO-TP frame sent and received by an instance, see
+/// [`SyncCanIsoTp::set_frame_tap`](crate::can::isotp::SyncCanIsoTp::set_frame_tap).
+pub type FrameTap = Box<dyn FnMut(Direction, &CanIsoTpFrame) + Send>;
+
+/// The tap shared by the clones of an instance, it's loaded lock-free when not set.
+#[derive(Clone, Default)]
+pub(crate) struct TapSlot(Arc<ArcSwapOption<Mutex<FrameTap>>>);
+
+impl TapSlot {
+    #[inline]
+    pub(crate) fn set(&self, tap: Option<FrameTap>) {
+        self.0.store(tap.map(|v| Arc::new(Mutex::new(v))));
+    }
+    #[inline]
+    pub(crate) fn is_set(&self) -> bool {
+        self.0.load().is_some()
+    }
+    /// Pass the frame to the tap, a panic of it is logged and doesn't reach the state machine.
+    pub(crate) fn tap(&self, tag: &str, direction: Direction, frame: &CanIsoTpFrame) {
+        let guard = self.0.load();
+        let Some(tap) = guard.as_ref() else {
+            return;
+        };
+
+        // the panic is caught with the lock held, so the mutex isn't poisoned
+        let result = match tap.lock() {
+            Ok(mut tap) => catch_unwind(AssertUnwindSafe(|| tap(direction, frame))),
+            Err(_) => return,
+        };
+        if result.is_err() {
+            log::warn!("ISO-TP(CAN {}) - frame tap panicked at {:?} frame, ignored", tag, direction);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use crate::{IsoTpFrame, can::CanIsoTpFrame};
+    use super::{Direction, TapSlot};
+
+    #[test]
+    fn test_tap_panic() {
+        let slot = TapSlot::default();
+        let frame = CanIsoTpFrame::decode([0x02, 0x3E, 0x00]).unwrap();
+        slot.tap("sync", Direction::Tx, &frame);
+        assert!(!slot.is_set());
+
+        let tapped = Arc::new(Mutex::new(Vec::new()));
+        let clone = tapped.clone();
+        slot.set(Some(Box::new(move |direction, _| {
+            clone.lock().unwrap().push(direction);
+            if direction == Direction::Rx {
+                panic!("tap panicked");
+            }
+        })));
+        slot.tap("sync", Direction::Rx, &frame);
+        // still tapped after the panic
+        slot.tap("sync", Direction::Tx, &frame);
+        assert_eq!(*tapped.lock().unwrap(), [Direction::Rx, Direction::Tx]);
+
+        slot.set(None);
+        slot.tap("sync", Direction::Tx, &frame);
+        assert_eq!(tapped.lock().unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,120 @@
+//! The listeners and the setup shared by the tests of the transports and of the driver.
+#![cfg_attr(feature = "can-fd", allow(dead_code))]
+
+use std::any::Any;
+use std::sync::{Arc, Mutex};
+use crate::{IsoTpEvent, IsoTpEventListener};
+use crate::can::Address;
+use crate::can::driver::{MOCK_CHANNEL, MockDriver, SyncCan, VirtualBus};
+use crate::can::isotp::SyncCanIsoTp;
+use crate::can::message::CanMessage;
+use crate::can::testing::Collector;
+use crate::device::Listener;
+
+/// The address of the client, the server replies to its `rx_id`.
+pub(crate) const CLIENT: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+pub(crate) const SERVER: Address = Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF };
+
+/// Ignores all the ISO-TP events.
+pub(crate) struct EmptyListener;
+
+impl IsoTpEventListener for EmptyListener {
+    fn from_buffer(&mut self) -> Option<IsoTpEvent> {
+        None
+    }
+
+    fn clear_buffer(&mut self) {}
+
+    fn on_iso_tp_event(&mut self, _: IsoTpEvent) {}
+}
+
+/// Keeps the last data received.
+#[derive(Clone, Default)]
+pub(crate) struct DataListener(pub(crate) Arc<Mutex<Option<Vec<u8>>>>);
+
+impl IsoTpEventListener for DataListener {
+    fn from_buffer(&mut self) -> Option<IsoTpEvent> {
+        None
+    }
+
+    fn clear_buffer(&mut self) {}
+
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        if let IsoTpEvent::DataReceived { data, .. } = event {
+            self.0.lock().unwrap().replace(data.to_vec());
+        }
+    }
+}
+
+/// Records all the ISO-TP events.
+pub(crate) type EventListener = Collector<IsoTpEvent>;
+
+/// Records the transmitted frames.
+#[derive(Clone, Default)]
+pub(crate) struct EchoListener(pub(crate) Arc<Mutex<Vec<CanMessage>>>);
+
+impl Listener<String, u32, CanMessage> for EchoListener {
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+    fn on_frame_transmitted(&mut self, _: String, frame: &CanMessage) {
+        self.0.lock().unwrap().push(frame.clone());
+    }
+
+    fn on_frame_received(&mut self, _: String, _: &[CanMessage]) {}
+}
+
+/// The `SyncCan` of the client and of the server on the endpoints 0 and 1 of a virtual bus,
+/// stopped when dropped.
+pub(crate) struct Link {
+    pub(crate) bus: VirtualBus,
+    pub(crate) client_can: SyncCan<MockDriver, String, CanMessage>,
+    pub(crate) server_can: SyncCan<MockDriver, String, CanMessage>,
+}
+
+impl Link {
+    #[inline]
+    pub(crate) fn new() -> Self {
+        Self::on(VirtualBus::new(2))
+    }
+
+    pub(crate) fn on(bus: VirtualBus) -> Self {
+        Self {
+            client_can: SyncCan::new(bus.driver(0)),
+            server_can: SyncCan::new(bus.driver(1)),
+            bus,
+        }
+    }
+
+    /// A transport addressed by [`CLIENT`] sending by the client loop, registered by [`start`](Self::start).
+    pub(crate) fn client(&self, listener: Box<dyn IsoTpEventListener>) -> SyncCanIsoTp<String, CanMessage> {
+        SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, self.client_can.sender(), listener)
+    }
+
+    /// A transport addressed by [`SERVER`] sending by the server loop, registered by [`start`](Self::start).
+    pub(crate) fn server(&self, listener: Box<dyn IsoTpEventListener>) -> SyncCanIsoTp<String, CanMessage> {
+        SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), SERVER, self.server_can.sender(), listener)
+    }
+
+    /// Register `client` and `server` on their loops and start both.
+    pub(crate) fn start(
+        &mut self,
+        client: impl Listener<String, u32, CanMessage> + 'static,
+        server: impl Listener<String, u32, CanMessage> + 'static,
+    ) {
+        self.client_can.register_listener("client".into(), Box::new(client));
+        self.server_can.register_listener("server".into(), Box::new(server));
+        self.client_can.sync_start(100);
+        self.server_can.sync_start(100);
+    }
+}
+
+impl Drop for Link {
+    fn drop(&mut self) {
+        self.client_can.stop();
+        self.server_can.stop();
+    }
+}
@@ -0,0 +1,75 @@
+//! The diagnostics of the ISO-TP hot paths.
+//!
+//! The `log` records are kept by default, the `tracing` feature replaces them by the events with
+//! structured fields, and the payloads are hex-encoded only when the level is enabled. The `log`
+//! arguments are evaluated before the logger filters the target, hence the `log_enabled!` checks.
+
+use crate::{FlowControlContext, IsoTpState};
+
+/// The span of a writing, entered by the synchronous and instrumenting the asynchronous.
+#[cfg(feature = "tracing")]
+pub(crate) fn write_span(mode: &'static str, address: Option<crate::can::Address>, functional: bool, length: usize) -> tracing::Span {
+    match address {
+        Some(address) => tracing::debug_span!("iso_tp_write", mode, tx_id = address.tx_id, rx_id = address.rx_id, functional, length),
+        None => tracing::debug_span!("iso_tp_write", mode, functional, length),
+    }
+}
+
+/// The data of the writing.
+#[inline]
+pub(crate) fn sending(mode: &'static str, data: &[u8]) {
+    #[cfg(feature = "tracing")]
+    if tracing::enabled!(tracing::Level::TRACE) {
+        tracing::trace!(mode, data = %hex::encode(data), "sending");
+    }
+    #[cfg(not(feature = "tracing"))]
+    if log::log_enabled!(log::Level::Trace) {
+        log::trace!("ISO-TP(CAN {}) - Sending: {}", mode, hex::encode(data));
+    }
+}
+
+/// The data of a single frame or reassembled from the consecutive frames.
+#[inline]
+pub(crate) fn received(mode: &'static str, data: &[u8]) {
+    #[cfg(feature = "tracing")]
+    {
+        tracing::debug!(mode, length = data.len(), "received");
+        if tracing::enabled!(tracing::Level::TRACE) {
+            tracing::trace!(mode, data = %hex::encode(data), "received data");
+        }
+    }
+    #[cfg(not(feature = "tracing"))]
+    {
+        let _ = mode;
+        if log::log_enabled!(log::Level::Debug) {
+            log::debug!("ISO-TP - Received: {}", hex::encode(data));
+        }
+    }
+}
+
+/// The state after appending(`op` = "append") or removing(`op` = "remove") the flags.
+#[inline]
+pub(crate) fn state(mode: &'static str, op: &'static str, state: IsoTpState) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(mode, op, state = %state, "state changed");
+    #[cfg(not(feature = "tracing"))]
+    log::trace!("ISO-TP(CAN {}): current state(state {}): {}", mode, op, state);
+}
+
+/// The flow control received from or sent to the peer, `tracing` only.
+#[inline]
+pub(crate) fn flow_control(mode: &'static str, direction: &'static str, ctx: &FlowControlContext) {
+    #[cfg(feature = "tracing")]
+    tracing::debug!(mode, direction, state = ?ctx.state(), block_size = ctx.block_size(), st_min_us = ctx.st_min_us(), raw_st_min = ctx.raw_st_min(), "flow control");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (mode, direction, ctx);
+}
+
+/// The timeout of the writing in `state`, `tracing` only.
+#[inline]
+pub(crate) fn timeout(mode: &'static str, state: IsoTpState, value: u64) {
+    #[cfg(feature = "tracing")]
+    tracing::warn!(mode, state = %state, timeout_ms = value, "timeout");
+    #[cfg(not(feature = "tracing"))]
+    let _ = (mode, state, value);
+}
@@ -0,0 +1,180 @@
+//! The Acknowledgement message (PGN 59392).
+
+use crate::can::j1939::{DataField, J1939Id, Message, Pdu, Pgn};
+use crate::error::Error;
+
+/// The PGN of Acknowledgement.
+pub const PGN_ACKNOWLEDGEMENT: u32 = 0xE800;
+
+const ACK_PRIORITY: u8 = 6;
+
+/// The control byte of the Acknowledgement.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AckControl {
+    /// Positive acknowledgement.
+    Ack,
+    /// Negative acknowledgement.
+    Nack,
+    /// Access denied.
+    AccessDenied,
+    /// Cannot respond, the request is valid but the node is busy.
+    CannotRespond,
+    /// The other control bytes.
+    Other(u8),
+}
+
+impl From<u8> for AckControl {
+    fn from(value: u8) -> Self {
+        match value {
+            0 => Self::Ack,
+            1 => Self::Nack,
+            2 => Self::AccessDenied,
+            3 => Self::CannotRespond,
+            v => Self::Other(v),
+        }
+    }
+}
+
+impl From<AckControl> for u8 {
+    fn from(value: AckControl) -> Self {
+        match value {
+            AckControl::Ack => 0,
+            AckControl::Nack => 1,
+            AckControl::AccessDenied => 2,
+            AckControl::CannotRespond => 3,
+            AckControl::Other(v) => v,
+        }
+    }
+}
+
+/// The Acknowledgement message.
+///
+/// | Byte | Field                       |
+/// |------|-----------------------------|
+/// | 0    | Control byte                |
+/// | 1    | Group function value        |
+/// | 2..4 | Reserved, 0xFF              |
+/// | 4    | Address acknowledged        |
+/// | 5..8 | PGN acknowledged(LE)        |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Acknowledgement {
+    pub control: AckControl,
+    /// The group function value, 0xFF when not applicable.
+    pub group_function: u8,
+    /// The address of the node requesting the acknowledged PGN.
+    pub address: u8,
+    pub pgn: Pgn,
+}
+
+impl Acknowledgement {
+    pub fn new(control: AckControl, pgn: Pgn, address: u8) -> Self {
+        Self { control, group_function: 0xFF, address, pgn }
+    }
+
+    /// Convert to the message sent from `sa` to `da`.
+    pub fn into_message(self, sa: u8, da: u8) -> Message {
+        let id = J1939Id::new()
+            .with_priority_bits(ACK_PRIORITY)
+            .with_pdu_format_bits((PGN_ACKNOWLEDGEMENT >> 8) as u8)
+            .with_pdu_specific_bits(da)
+            .with_source_address_bits(sa);
+        let pgn = self.pgn.into_bits().to_le_bytes();
+        let data = [self.control.into(), self.group_function, 0xFF, 0xFF, self.address, pgn[0], pgn[1], pgn[2]];
+
+        Message::from_parts(id, Pdu::DataField(DataField::from_bits(u64::from_be_bytes(data))))
+    }
+}
+
+impl TryFrom<Message> for Acknowledgement {
+    type Error = Error;
+
+    fn try_from(value: Message) -> Result<Self, Self::Error> {
+        Self::try_from(&value)
+    }
+}
+
+impl TryFrom<&Message> for Acknowledgement {
+    type Error = Error;
+
+    fn try_from(value: &Message) -> Result<Self, Self::Error> {
+        let data = value.data();
+        match value.pdu() {
+            Pdu::DataField(_) | Pdu::Bytes(_)
+            if value.id().pdu_format() == (PGN_ACKNOWLEDGEMENT >> 8) as u8 && data.len() >= 8 => {
+                Ok(Self {
+                    control: data[0].into(),
+                    group_function: data[1],
+                    address: data[4],
+                    pgn: Pgn::from_bits(u32::from_le_bytes([data[5], data[6], data[7], 0])),
+                })
+            },
+            _ => Err(Error::ConvertError { src: "j1939 message", target: "acknowledgement" }),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::can::j1939::{DataField, J1939Id, Message, NameField, Pdu, Pgn};
+    use super::{AckControl, Acknowledgement};
+
+    #[test]
+    fn test_control() {
+        for v in 0..=0xFF {
+            assert_eq!(u8::from(AckControl::from(v)), v);
+        }
+        assert_eq!(AckControl::from(3), AckControl::CannotRespond);
+        assert_eq!(AckControl::from(4), AckControl::Other(4));
+    }
+
+    #[test]
+    fn test_encode() {
+        // NACK of the Component Identification(PGN 65259) requested by 0xF9, sent to global by 0x00
+        let ack = Acknowledgement::new(AckControl::Nack, Pgn::from_bits(0xFEEB), 0xF9);
+        let message = ack.into_message(0x00, 0xFF);
+        assert_eq!(message.id().into_bits(), 0x18E8FF00);
+        let Pdu::DataField(data) = message.pdu() else { panic!("unexpected pdu") };
+        assert_eq!(data.to_be_bytes(), [0x01, 0xFF, 0xFF, 0xFF, 0xF9, 0xEB, 0xFE, 0x00]);
+
+        // ACK of the proprietary PGN 61184(PDU1) with group function 0x05
+        let ack = Acknowledgement { control: AckControl::Ack, group_function: 0x05, address: 0x80, pgn: Pgn::from_bits(0xEF00) };
+        let message = ack.into_message(0x21, 0x80);
+        assert_eq!(message.id().into_bits(), 0x18E88021);
+        let Pdu::DataField(data) = message.pdu() else { panic!("unexpected pdu") };
+        assert_eq!(data.to_be_bytes(), [0x00, 0x05, 0xFF, 0xFF, 0x80, 0x00, 0xEF, 0x00]);
+    }
+
+    #[test]
+    fn test_decode() -> anyhow::Result<()> {
+        let message = Message::from_parts(
+            J1939Id::from_bits(0x18E8FF17),
+            Pdu::DataField(DataField::from_bits(0x02FFFFFFF9CAFE00)),
+        );
+        let ack = Acknowledgement::try_from(&message)?;
+        assert_eq!(ack.control, AckControl::AccessDenied);
+        assert_eq!(ack.group_function, 0xFF);
+        assert_eq!(ack.address, 0xF9);
+        assert_eq!(ack.pgn, Pgn::from_bits(0xFECA));
+        assert_eq!(ack.into_message(0x17, 0xFF), message);
+        assert_eq!(Acknowledgement::try_from(message)?, ack);
+
+        // the data page of the acknowledged PGN
+        let message = Message::from_parts(
+            J1939Id::from_bits(0x18E8FF17),
+            Pdu::DataField(DataField::from_bits(0x03FFFFFF00001001)),
+        );
+        let ack = Acknowledgement::try_from(&message)?;
+        assert_eq!(ack.control, AckControl::CannotRespond);
+        assert_eq!(ack.pgn, Pgn::from_bits(0x11000));
+
+        let message = Message::from_parts(
+            J1939Id::from_bits(0x18EAFF17),
+            Pdu::DataField(DataField::from_bits(0x02FFFFFFF9CAFE00)),
+        );
+        assert!(Acknowledgement::try_from(message).is_err());
+        let message = Message::from_parts(J1939Id::from_bits(0x18E8FF17), Pdu::NameField(NameField::from_bits(0)));
+        assert!(Acknowledgement::try_from(message).is_err());
+
+        Ok(())
+    }
+}
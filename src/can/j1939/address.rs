@@ -1,3 +1,5 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
 use std::fmt::{Display, Formatter};
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -261,6 +263,53 @@ impl Display for Address {
     }
 }
 
+/// The labels of the addresses overriding the built-in [`Address`] names,
+/// for the fleet specific assignments and the proprietary addresses.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct AddressRegistry {
+    labels: HashMap<u8, String>,
+}
+
+impl AddressRegistry {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Set the label of the address, returns the previous one.
+    pub fn register(&mut self, address: u8, label: String) -> Option<String> {
+        self.labels.insert(address, label)
+    }
+
+    /// Remove the label of the address, the built-in name is used again.
+    pub fn unregister(&mut self, address: u8) -> Option<String> {
+        self.labels.remove(&address)
+    }
+
+    /// Set the labels of the addresses in bulk.
+    pub fn load<I, S>(&mut self, labels: I)
+    where
+        I: IntoIterator<Item = (u8, S)>,
+        S: Into<String> {
+        self.labels.extend(labels.into_iter().map(|(k, v)| (k, v.into())));
+    }
+
+    /// The registered label of the address, or the built-in name of [`Address`].
+    pub fn label_of(&self, address: u8) -> Cow<'_, str> {
+        match self.labels.get(&address) {
+            Some(label) => Cow::Borrowed(label),
+            None => Cow::Owned(Address::from(address).to_string()),
+        }
+    }
+}
+
+impl<S: Into<String>> FromIterator<(u8, S)> for AddressRegistry {
+    fn from_iter<T: IntoIterator<Item = (u8, S)>>(iter: T) -> Self {
+        let mut result = Self::new();
+        result.load(iter);
+        result
+    }
+}
+
 /// Represents the source address.
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum SourceAddress {
@@ -292,6 +341,15 @@ impl SourceAddress {
             SourceAddress::None => None,
         }
     }
+
+    /// The label of the address in the `registry`.
+    #[must_use]
+    pub fn label(self, registry: &AddressRegistry) -> Option<Cow<'_, str>> {
+        match self {
+            SourceAddress::Some(value) => Some(registry.label_of(value)),
+            SourceAddress::None => None,
+        }
+    }
 }
 
 impl DestinationAddress {
@@ -307,4 +365,40 @@ impl DestinationAddress {
             DestinationAddress::None => None,
         }
     }
+
+    /// The label of the address in the `registry`.
+    #[must_use]
+    pub fn label(self, registry: &AddressRegistry) -> Option<Cow<'_, str>> {
+        match self {
+            DestinationAddress::Some(value) => Some(registry.label_of(value)),
+            DestinationAddress::None => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::can::j1939::{Address, DestinationAddress, SourceAddress};
+    use super::AddressRegistry;
+
+    #[test]
+    fn test_registry() {
+        let mut registry = AddressRegistry::new();
+        assert_eq!(registry.label_of(0x21), Address::BodyController.to_string());
+        assert_eq!(registry.label_of(0x90), "Unknown(144)");
+
+        // override a known address and label a proprietary one
+        assert_eq!(registry.register(0x21, "Body Computer".into()), None);
+        registry.load([(0x90, "Crane Controller"), (0x91, "Tail Lift")]);
+        assert_eq!(registry.label_of(0x21), "Body Computer");
+        assert_eq!(registry.label_of(0x90), "Crane Controller");
+        assert_eq!(SourceAddress::Some(0x91).label(&registry).as_deref(), Some("Tail Lift"));
+        assert_eq!(DestinationAddress::None.label(&registry), None);
+
+        assert_eq!(registry.unregister(0x21).as_deref(), Some("Body Computer"));
+        assert_eq!(registry.label_of(0x21), Address::BodyController.to_string());
+
+        let registry = [(0x00, "Engine #1".to_string())].into_iter().collect::<AddressRegistry>();
+        assert_eq!(registry.label_of(0x00), "Engine #1");
+    }
 }
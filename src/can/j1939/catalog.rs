@@ -0,0 +1,107 @@
+//! The catalogue of the common SAE assigned PGNs.
+
+/// The PGN, acronym and description, PDU1 PGNs are with the zero PDU specific bits.
+pub(crate) static PGN_CATALOG: &[(u32, &str, &str)] = &[
+    // network management and transport
+    (0x00C900, "RQST2", "Request 2"),
+    (0x00CA00, "XFER", "Transfer"),
+    (0x00E800, "ACKM", "Acknowledgment"),
+    (0x00EA00, "RQST", "Request"),
+    (0x00EB00, "TP.DT", "Transport Protocol - Data Transfer"),
+    (0x00EC00, "TP.CM", "Transport Protocol - Connection Management"),
+    (0x00EE00, "AC", "Address Claimed"),
+    (0x00FED8, "CA", "Commanded Address"),
+    // diagnostics
+    (0x00D700, "DM16", "Binary Data Transfer"),
+    (0x00D800, "DM15", "Memory Access Response"),
+    (0x00D900, "DM14", "Memory Access Request"),
+    (0x00DF00, "DM13", "Stop Start Broadcast"),
+    (0x00E300, "DM7", "Command Non-Continuously Monitored Test"),
+    (0x00FECA, "DM1", "Active Diagnostic Trouble Codes"),
+    (0x00FECB, "DM2", "Previously Active Diagnostic Trouble Codes"),
+    (0x00FECC, "DM3", "Diagnostic Data Clear/Reset of Previously Active DTCs"),
+    (0x00FECD, "DM4", "Freeze Frame Parameters"),
+    (0x00FECE, "DM5", "Diagnostic Readiness 1"),
+    (0x00FECF, "DM6", "Pending DTCs"),
+    (0x00FED0, "DM8", "Test Results for Non-Continuously Monitored Systems"),
+    (0x00FED3, "DM11", "Diagnostic Data Clear/Reset for Active DTCs"),
+    (0x00FED4, "DM12", "Emissions-Related Active DTCs"),
+    // J1939-71
+    (0x000000, "TSC1", "Torque/Speed Control 1"),
+    (0x00F001, "EBC1", "Electronic Brake Controller 1"),
+    (0x00F002, "ETC1", "Electronic Transmission Controller 1"),
+    (0x00F003, "EEC2", "Electronic Engine Controller 2"),
+    (0x00F004, "EEC1", "Electronic Engine Controller 1"),
+    (0x00F005, "ETC2", "Electronic Transmission Controller 2"),
+    (0x00F009, "VDC2", "Vehicle Dynamic Stability Control 2"),
+    (0x00FE6C, "TCO1", "Tachograph"),
+    (0x00FEAE, "AIR1", "Air Supply Pressure"),
+    (0x00FEBF, "EBC2", "Electronic Brake Controller 2"),
+    (0x00FEC1, "VDHR", "High Resolution Vehicle Distance"),
+    (0x00FEDA, "SOFT", "Software Identification"),
+    (0x00FEDF, "EEC3", "Electronic Engine Controller 3"),
+    (0x00FEE0, "VD", "Vehicle Distance"),
+    (0x00FEE3, "EC1", "Engine Configuration 1"),
+    (0x00FEE4, "SHUTDN", "Shutdown"),
+    (0x00FEE5, "HOURS", "Engine Hours, Revolutions"),
+    (0x00FEE6, "TD", "Time/Date"),
+    (0x00FEE8, "VDS", "Vehicle Direction/Speed"),
+    (0x00FEE9, "LFC", "Fuel Consumption (Liquid)"),
+    (0x00FEEA, "VW", "Vehicle Weight"),
+    (0x00FEEB, "CI", "Component Identification"),
+    (0x00FEEC, "VI", "Vehicle Identification"),
+    (0x00FEEE, "ET1", "Engine Temperature 1"),
+    (0x00FEEF, "EFL/P1", "Engine Fluid Level/Pressure 1"),
+    (0x00FEF0, "PTO", "Power Takeoff Information"),
+    (0x00FEF1, "CCVS", "Cruise Control/Vehicle Speed"),
+    (0x00FEF2, "LFE", "Fuel Economy (Liquid)"),
+    (0x00FEF3, "VP1", "Vehicle Position 1"),
+    (0x00FEF4, "TIRE", "Tire Condition"),
+    (0x00FEF5, "AMB", "Ambient Conditions"),
+    (0x00FEF6, "IC1", "Inlet/Exhaust Conditions 1"),
+    (0x00FEF7, "VEP1", "Vehicle Electrical Power 1"),
+    (0x00FEF8, "TRF1", "Transmission Fluids 1"),
+    (0x00FEFA, "B", "Brakes"),
+    (0x00FEFC, "DD", "Dash Display"),
+    (0x00FEFF, "WFI", "Water in Fuel Indicator"),
+];
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+    use crate::can::j1939::{PduAssignment, Pgn};
+    use super::PGN_CATALOG;
+
+    #[test]
+    fn test_catalog() {
+        let mut pgns = HashSet::new();
+        let mut labels = HashSet::new();
+        for &(pgn, label, _) in PGN_CATALOG {
+            assert!(pgns.insert(pgn), "duplicate PGN {:05X}", pgn);
+            assert!(labels.insert(label), "duplicate label {}", label);
+
+            let pgn = Pgn::from_bits(pgn);
+            assert!(matches!(pgn.pdu_assignment(), PduAssignment::Sae(_)));
+            assert_eq!(Pgn::from_label(label), Some(pgn));
+        }
+    }
+
+    #[test]
+    fn test_label() {
+        let pgn = Pgn::from_bits(0xF004);
+        assert_eq!(pgn.label(), Some("EEC1"));
+        assert_eq!(pgn.description(), Some("Electronic Engine Controller 1"));
+        assert_eq!(pgn.to_string(), "EEC1 – Electronic Engine Controller 1");
+
+        // the destination address of PDU1
+        assert_eq!(Pgn::from_bits(0xEA17).label(), Some("RQST"));
+        assert_eq!(Pgn::from_label("tp.cm"), Some(Pgn::from_bits(0xEC00)));
+        assert_eq!(Pgn::from_label("XYZ"), None);
+
+        // proprietary and unknown
+        assert_eq!(Pgn::from_bits(0xEF00).label(), None);
+        assert_eq!(Pgn::from_bits(0xFF12).label(), None);
+        assert_eq!(Pgn::from_bits(0xFF12).to_string(), "0FF12");
+        assert_eq!(Pgn::from_bits(0xFEFE).label(), None);
+    }
+}
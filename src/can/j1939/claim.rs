@@ -0,0 +1,444 @@
+//! J1939-81 address claim procedure.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, mpsc::Sender};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::j1939::{J1939Id, NameField, Pgn, SourceAddress, GLOBAL_ADDRESS};
+use crate::device::{Channel, Listener};
+use crate::error::Error;
+
+/// The PGN of Address Claimed.
+pub const PGN_ADDRESS_CLAIMED: u32 = 0xEE00;
+/// The PGN of Request.
+pub const PGN_REQUEST: u32 = 0xEA00;
+/// The source address of the node that cannot claim an address.
+pub const NULL_ADDRESS: u8 = 254;
+
+const CLAIM_PRIORITY: u8 = 6;
+/// The range of the arbitrary addresses.
+const ARBITRARY_ADDRESSES: std::ops::RangeInclusive<u8> = 128..=247;
+/// The time to wait for the contending claims before using the address.
+const CLAIM_TIMEOUT: Duration = Duration::from_millis(250);
+
+/// The messages of the address claim procedure.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressClaimMessage {
+    /// Address Claimed, `address` is the source address of the frame, Cannot Claim Address
+    /// when it's [`NULL_ADDRESS`].
+    AddressClaimed { address: u8, name: NameField },
+    /// Request for Address Claimed to `dest`.
+    Request { dest: u8 },
+}
+
+impl AddressClaimMessage {
+    /// Convert to the CAN id and data.
+    pub fn encode(&self) -> (Id, Vec<u8>) {
+        let (pgn, dest, source, data) = match self {
+            Self::AddressClaimed { address, name } =>
+                (PGN_ADDRESS_CLAIMED, GLOBAL_ADDRESS, *address, name.into_bits().to_le_bytes().to_vec()),
+            Self::Request { dest } =>
+                (PGN_REQUEST, *dest, NULL_ADDRESS, PGN_ADDRESS_CLAIMED.to_le_bytes()[..3].to_vec()),
+        };
+        // the PDU1 PGNs
+        let id = J1939Id::new()
+            .with_priority_bits(CLAIM_PRIORITY)
+            .with_pgn(Pgn::from_bits(pgn))
+            .with_pdu_specific_bits(dest)
+            .with_source_address(source);
+        (Id::Extended(id.into_bits()), data)
+    }
+
+    /// Parse the frame with the extended `id`, returns `None` for the other messages.
+    pub fn decode(id: J1939Id, data: &[u8]) -> Option<Self> {
+        match (id.pdu_format() as u32) << 8 {
+            PGN_ADDRESS_CLAIMED => {
+                let name = u64::from_le_bytes(data.get(..8)?.try_into().ok()?);
+                Some(Self::AddressClaimed { address: id.source_address_bits(), name: NameField::from_bits(name) })
+            },
+            PGN_REQUEST => {
+                let pgn = u32::from_le_bytes([*data.first()?, *data.get(1)?, *data.get(2)?, 0]);
+                (pgn == PGN_ADDRESS_CLAIMED).then_some(Self::Request { dest: id.pdu_specific() })
+            },
+            _ => None,
+        }
+    }
+}
+
+/// The events of [`AddressClaim`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AddressClaimEvent {
+    /// The address is claimed without contention.
+    Claimed(u8),
+    /// The claimed address is lost to a node with the lower NAME.
+    Lost(u8),
+    /// No address can be claimed, [`NULL_ADDRESS`] is used.
+    CannotClaim,
+}
+
+pub trait AddressClaimEventListener: Send {
+    fn on_claim_event(&mut self, event: AddressClaimEvent);
+}
+
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+enum ClaimState {
+    #[default]
+    Idle,
+    /// The address claimed, waiting for the contending claims.
+    Claiming { address: u8, since: Instant },
+    Claimed(u8),
+    CannotClaim,
+}
+
+/// The address claim of a node, register it as a [`Listener`] of
+/// [`SyncCan`](crate::can::driver::SyncCan) to defend the claimed address.
+#[derive(Clone)]
+pub struct AddressClaim<C, F> {
+    pub(crate) channel: C,
+    pub(crate) name: NameField,
+    pub(crate) preferred: u8,
+    pub(crate) sender: Sender<F>,
+    state: Arc<Mutex<ClaimState>>,
+    /// The addresses claimed by the other nodes.
+    claimed: Arc<Mutex<HashMap<u8, NameField>>>,
+    pub(crate) listener: Arc<Mutex<Box<dyn AddressClaimEventListener>>>,
+}
+
+impl<C: Channel, F: FrameMut<Channel = C> + 'static> AddressClaim<C, F> {
+    /// Create the address claim of the node with `name`, the `preferred` address is claimed first.
+    pub fn new(
+        channel: C,
+        name: NameField,
+        preferred: u8,
+        sender: Sender<F>,
+        listener: Box<dyn AddressClaimEventListener>,
+    ) -> Self {
+        Self {
+            channel,
+            name,
+            preferred,
+            sender,
+            state: Default::default(),
+            claimed: Default::default(),
+            listener: Arc::new(Mutex::new(listener)),
+        }
+    }
+
+    #[inline]
+    pub fn name(&self) -> NameField {
+        self.name
+    }
+
+    /// Claim the preferred address, blocked until an address is claimed or no address can be claimed.
+    pub fn claim(&self) -> Result<SourceAddress, Error> {
+        self.claim_address(self.preferred)?;
+        loop {
+            match self.refresh() {
+                ClaimState::Claiming { .. } => sleep(Duration::from_millis(1)),
+                _ => return Ok(self.source_address()),
+            }
+        }
+    }
+
+    /// The claimed source address, [`SourceAddress::None`] while claiming or cannot claim.
+    pub fn source_address(&self) -> SourceAddress {
+        match self.refresh() {
+            ClaimState::Claimed(address) => SourceAddress::Some(address),
+            _ => SourceAddress::None,
+        }
+    }
+
+    /// Whether no address can be claimed.
+    #[inline]
+    pub fn is_cannot_claim(&self) -> bool {
+        self.refresh() == ClaimState::CannotClaim
+    }
+
+    fn claim_address(&self, address: u8) -> Result<(), Error> {
+        log::debug!("J1939 - claim address {:02X}", address);
+        if let Ok(mut state) = self.state.lock() {
+            *state = ClaimState::Claiming { address, since: Instant::now() };
+        }
+        self.send(AddressClaimMessage::AddressClaimed { address, name: self.name })
+    }
+
+    /// Complete the claiming without contention in time.
+    fn refresh(&self) -> ClaimState {
+        let (state, claimed) = match self.state.lock() {
+            Ok(mut state) => match *state {
+                ClaimState::Claiming { address, since } if since.elapsed() >= CLAIM_TIMEOUT => {
+                    *state = ClaimState::Claimed(address);
+                    (*state, Some(address))
+                },
+                v => (v, None),
+            },
+            Err(_) => (ClaimState::Idle, None),
+        };
+        if let Some(address) = claimed {
+            log::info!("J1939 - address {:02X} claimed", address);
+            self.claim_event(AddressClaimEvent::Claimed(address));
+        }
+
+        state
+    }
+
+    fn send(&self, message: AddressClaimMessage) -> Result<(), Error> {
+        let (id, data) = message.encode();
+        let mut frame = F::new(id, &data)
+            .ok_or(Error::ConvertError { src: "j1939 message", target: "can-frame" })?;
+        frame.set_channel(self.channel.clone());
+
+        self.sender.send(frame)
+            .map_err(|e| {
+                log::warn!("J1939 - transmit failed: {:?}", e);
+                Error::device(e)
+            })
+    }
+
+    #[inline]
+    fn claim_event(&self, event: AddressClaimEvent) {
+        match self.listener.lock() {
+            Ok(mut listener) => listener.on_claim_event(event),
+            Err(_) => log::warn!("J1939 - address claim listener error"),
+        }
+    }
+
+    /// The current address, `None` when not claimed.
+    fn current_address(&self) -> Option<(u8, bool)> {
+        match self.refresh() {
+            ClaimState::Claiming { address, .. } => Some((address, false)),
+            ClaimState::Claimed(address) => Some((address, true)),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn on_address_claimed(&self, address: u8, name: NameField) {
+        if name == self.name {
+            return;
+        }
+        if let Ok(mut claimed) = self.claimed.lock() {
+            claimed.insert(address, name);
+        }
+
+        let Some((current, claimed)) = self.current_address() else {
+            return;
+        };
+        if address != current {
+            return;
+        }
+
+        // the lower NAME wins
+        if self.name < name {
+            log::info!("J1939 - defend address {:02X} against {:016X}", current, name.into_bits());
+            if let Err(e) = self.send(AddressClaimMessage::AddressClaimed { address: current, name: self.name }) {
+                log::warn!("J1939 - defend address failed: {}", e);
+            }
+            return;
+        }
+
+        log::warn!("J1939 - address {:02X} lost to {:016X}", current, name.into_bits());
+        if claimed {
+            self.claim_event(AddressClaimEvent::Lost(current));
+        }
+
+        let next = match self.name.is_self_configurable() {
+            true => self.claimed.lock()
+                .ok()
+                .and_then(|claimed| ARBITRARY_ADDRESSES.clone()
+                    .find(|v| *v != current && !claimed.contains_key(v))),
+            false => None,
+        };
+        let result = match next {
+            Some(address) => self.claim_address(address),
+            None => self.cannot_claim(),
+        };
+        if let Err(e) = result {
+            log::warn!("J1939 - claim address failed: {}", e);
+        }
+    }
+
+    fn cannot_claim(&self) -> Result<(), Error> {
+        log::warn!("J1939 - cannot claim address");
+        if let Ok(mut state) = self.state.lock() {
+            *state = ClaimState::CannotClaim;
+        }
+        self.claim_event(AddressClaimEvent::CannotClaim);
+        self.send(AddressClaimMessage::AddressClaimed { address: NULL_ADDRESS, name: self.name })
+    }
+
+    pub(crate) fn on_request(&self, dest: u8) {
+        let address = match self.refresh() {
+            ClaimState::Claiming { address, .. } | ClaimState::Claimed(address) => address,
+            ClaimState::CannotClaim => NULL_ADDRESS,
+            ClaimState::Idle => return,
+        };
+        if dest != GLOBAL_ADDRESS && dest != address {
+            return;
+        }
+
+        if let Err(e) = self.send(AddressClaimMessage::AddressClaimed { address, name: self.name }) {
+            log::warn!("J1939 - respond address claimed failed: {}", e);
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for AddressClaim<C, F>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        for frame in frames {
+            if !frame.is_extended() {
+                continue;
+            }
+
+            match AddressClaimMessage::decode(J1939Id::from(frame.id()), frame.data()) {
+                Some(AddressClaimMessage::AddressClaimed { address, name }) =>
+                    self.on_address_claimed(address, name),
+                Some(AddressClaimMessage::Request { dest }) => self.on_request(dest),
+                None => {},
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::thread::{sleep, spawn};
+    use std::time::Duration;
+    use crate::can::driver::{MockDriver, SyncCan, VirtualBus, MOCK_CHANNEL};
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::j1939::{J1939Id, NameField, SourceAddress};
+    use crate::can::message::CanMessage;
+    use crate::device::Driver;
+    use super::{AddressClaim, AddressClaimEvent, AddressClaimMessage, NULL_ADDRESS};
+
+    type Collector = crate::can::testing::Collector<AddressClaimEvent>;
+
+    fn name(identity: u32, arbitrary: bool) -> NameField {
+        // the arbitrary address capable bit is the MSB
+        NameField::from_bits(((arbitrary as u64) << 63) | 0x0000_8000_2440_0000 | identity as u64)
+    }
+
+    fn transmit(driver: &MockDriver, message: AddressClaimMessage) {
+        let (id, data) = message.encode();
+        let mut frame = CanMessage::new(id, &data).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        driver.transmit(frame, None).unwrap();
+    }
+
+    fn received(driver: &MockDriver) -> Vec<AddressClaimMessage> {
+        driver.receive(MOCK_CHANNEL.into(), None).unwrap()
+            .iter()
+            .filter_map(|f| AddressClaimMessage::decode(J1939Id::from(f.id()), f.data()))
+            .collect()
+    }
+
+    fn setup(name: NameField) -> (SyncCan<MockDriver, String, CanMessage>, MockDriver, AddressClaim<String, CanMessage>, Collector) {
+        let (a, b) = VirtualBus::pair();
+        let mut can = SyncCan::new(a);
+        let collector = Collector::default();
+        let claim = AddressClaim::new(MOCK_CHANNEL.to_string(), name, 0x80, can.sender(), Box::new(collector.clone()));
+        can.register_listener("claim".into(), Box::new(claim.clone()));
+        can.sync_start(100);
+        (can, b, claim, collector)
+    }
+
+    #[test]
+    fn test_message() {
+        let name = name(0x1234, true);
+        let message = AddressClaimMessage::AddressClaimed { address: 0x80, name };
+        let (id, data) = message.encode();
+        assert_eq!(id.into_bits(), 0x18EEFF80);
+        assert_eq!(data, name.into_bits().to_le_bytes());
+        assert_eq!(AddressClaimMessage::decode(J1939Id::from(id), &data), Some(message));
+
+        let message = AddressClaimMessage::Request { dest: 0xFF };
+        let (id, data) = message.encode();
+        assert_eq!(id.into_bits(), 0x18EAFFFE);
+        assert_eq!(data, [0x00, 0xEE, 0x00]);
+        assert_eq!(AddressClaimMessage::decode(J1939Id::from(id), &data), Some(message));
+        assert_eq!(AddressClaimMessage::decode(J1939Id::from(id), &[0x00, 0xEC, 0x00]), None);
+    }
+
+    #[test]
+    fn test_contention_win() -> anyhow::Result<()> {
+        let (mut can, peer, claim, collector) = setup(name(0x1000, false));
+        assert_eq!(claim.claim()?, SourceAddress::Some(0x80));
+        assert_eq!(received(&peer), [AddressClaimMessage::AddressClaimed { address: 0x80, name: claim.name() }]);
+
+        // the higher NAME contends
+        transmit(&peer, AddressClaimMessage::AddressClaimed { address: 0x80, name: name(0x2000, false) });
+        sleep(Duration::from_millis(10));
+        assert_eq!(received(&peer), [AddressClaimMessage::AddressClaimed { address: 0x80, name: claim.name() }]);
+        assert_eq!(claim.source_address(), SourceAddress::Some(0x80));
+
+        // request for address claimed
+        transmit(&peer, AddressClaimMessage::Request { dest: 0xFF });
+        sleep(Duration::from_millis(10));
+        assert_eq!(received(&peer), [AddressClaimMessage::AddressClaimed { address: 0x80, name: claim.name() }]);
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [AddressClaimEvent::Claimed(0x80)]);
+
+        can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_contention_lose() -> anyhow::Result<()> {
+        // move to an arbitrary address
+        let (mut can, peer, claim, collector) = setup(name(0x2000, true));
+        assert_eq!(claim.claim()?, SourceAddress::Some(0x80));
+        received(&peer);
+
+        transmit(&peer, AddressClaimMessage::AddressClaimed { address: 0x81, name: name(0x3000, true) });
+        transmit(&peer, AddressClaimMessage::AddressClaimed { address: 0x80, name: name(0x1000, true) });
+        sleep(Duration::from_millis(10));
+        assert_eq!(claim.source_address(), SourceAddress::None);
+        assert_eq!(received(&peer), [AddressClaimMessage::AddressClaimed { address: 0x82, name: claim.name() }]);
+        sleep(Duration::from_millis(250));
+        assert_eq!(claim.source_address(), SourceAddress::Some(0x82));
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [
+            AddressClaimEvent::Claimed(0x80),
+            AddressClaimEvent::Lost(0x80),
+            AddressClaimEvent::Claimed(0x82),
+        ]);
+        can.stop();
+
+        // cannot claim while claiming
+        let (mut can, peer, claim, collector) = setup(name(0x2000, false));
+        let task = {
+            let claim = claim.clone();
+            spawn(move || claim.claim())
+        };
+        sleep(Duration::from_millis(50));
+        transmit(&peer, AddressClaimMessage::AddressClaimed { address: 0x80, name: name(0x1000, false) });
+        assert_eq!(task.join().unwrap()?, SourceAddress::None);
+        assert!(claim.is_cannot_claim());
+        sleep(Duration::from_millis(10));
+        assert_eq!(received(&peer), [
+            AddressClaimMessage::AddressClaimed { address: 0x80, name: claim.name() },
+            AddressClaimMessage::AddressClaimed { address: NULL_ADDRESS, name: claim.name() },
+        ]);
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [AddressClaimEvent::CannotClaim]);
+
+        can.stop();
+        Ok(())
+    }
+}
@@ -0,0 +1,456 @@
+//! The diagnostic trouble codes of DM1(PGN 65226) and DM2(PGN 65227) defined by J1939-73.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use crate::can::frame::FrameMut;
+use crate::can::j1939::{DataField, J1939Id, J1939TpEvent, J1939TpEventListener, SourceAddress};
+use crate::device::{Channel, Listener};
+use crate::error::Error;
+
+/// The PGN of DM1, the active diagnostic trouble codes.
+pub const PGN_DM1: u32 = 0xFECA;
+/// The PGN of DM2, the previously active diagnostic trouble codes.
+pub const PGN_DM2: u32 = 0xFECB;
+
+/// The on/off state of a lamp.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LampState {
+    Off,
+    On,
+    Reserved,
+    NotAvailable,
+}
+
+impl From<u8> for LampState {
+    fn from(value: u8) -> Self {
+        match value & 0x03 {
+            0 => Self::Off,
+            1 => Self::On,
+            2 => Self::Reserved,
+            _ => Self::NotAvailable,
+        }
+    }
+}
+
+impl From<LampState> for u8 {
+    fn from(value: LampState) -> Self {
+        match value {
+            LampState::Off => 0,
+            LampState::On => 1,
+            LampState::Reserved => 2,
+            LampState::NotAvailable => 3,
+        }
+    }
+}
+
+/// The flash state of a lamp.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum LampFlash {
+    /// Slow flash, 1 Hz.
+    Slow,
+    /// Fast flash, 2 Hz or faster.
+    Fast,
+    Reserved,
+    /// Unavailable or do not flash.
+    Off,
+}
+
+impl From<u8> for LampFlash {
+    fn from(value: u8) -> Self {
+        match value & 0x03 {
+            0 => Self::Slow,
+            1 => Self::Fast,
+            2 => Self::Reserved,
+            _ => Self::Off,
+        }
+    }
+}
+
+impl From<LampFlash> for u8 {
+    fn from(value: LampFlash) -> Self {
+        match value {
+            LampFlash::Slow => 0,
+            LampFlash::Fast => 1,
+            LampFlash::Reserved => 2,
+            LampFlash::Off => 3,
+        }
+    }
+}
+
+/// The state and flash of a lamp.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Lamp {
+    pub state: LampState,
+    pub flash: LampFlash,
+}
+
+impl Lamp {
+    #[inline]
+    pub fn new(state: LampState, flash: LampFlash) -> Self {
+        Self { state, flash }
+    }
+}
+
+/// The lamp status of the first two bytes.
+///
+/// | Bits | Byte 0                      | Byte 1                     |
+/// |------|-----------------------------|----------------------------|
+/// | 7..6 | Malfunction indicator lamp  | Flash malfunction lamp     |
+/// | 5..4 | Red stop lamp               | Flash red stop lamp        |
+/// | 3..2 | Amber warning lamp          | Flash amber warning lamp   |
+/// | 1..0 | Protect lamp                | Flash protect lamp         |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct LampStatus {
+    pub malfunction: Lamp,
+    pub red_stop: Lamp,
+    pub amber_warning: Lamp,
+    pub protect: Lamp,
+}
+
+impl LampStatus {
+    pub fn from_bytes(bytes: [u8; 2]) -> Self {
+        let lamp = |shift: u8| Lamp::new((bytes[0] >> shift).into(), (bytes[1] >> shift).into());
+        Self {
+            malfunction: lamp(6),
+            red_stop: lamp(4),
+            amber_warning: lamp(2),
+            protect: lamp(0),
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 2] {
+        [
+            (self.malfunction, 6), (self.red_stop, 4), (self.amber_warning, 2), (self.protect, 0),
+        ].iter()
+            .fold([0; 2], |mut result, (lamp, shift)| {
+                result[0] |= u8::from(lamp.state) << shift;
+                result[1] |= u8::from(lamp.flash) << shift;
+                result
+            })
+    }
+}
+
+impl Default for LampStatus {
+    /// All lamps are off and do not flash.
+    fn default() -> Self {
+        let lamp = Lamp::new(LampState::Off, LampFlash::Off);
+        Self { malfunction: lamp, red_stop: lamp, amber_warning: lamp, protect: lamp }
+    }
+}
+
+/// The diagnostic trouble code.
+///
+/// | Byte | Bits | Field                          |
+/// |------|------|--------------------------------|
+/// | 0    | 7..0 | SPN bits 7..0                  |
+/// | 1    | 7..0 | SPN bits 15..8                 |
+/// | 2    | 7..5 | SPN bits 18..16                |
+/// | 2    | 4..0 | Failure mode identifier(FMI)   |
+/// | 3    | 7    | SPN conversion method(CM)      |
+/// | 3    | 6..0 | Occurrence count(OC)           |
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct Dtc {
+    /// The suspect parameter number, 19 bits.
+    pub spn: u32,
+    /// The failure mode identifier, 5 bits.
+    pub fmi: u8,
+    /// The occurrence count, 7 bits, 127 when not available.
+    pub occurrence: u8,
+    /// The conversion method, `false` for the current(version 4) packing.
+    pub conversion_method: bool,
+}
+
+impl Dtc {
+    pub fn new(spn: u32, fmi: u8, occurrence: u8) -> Self {
+        Self { spn: spn & 0x7FFFF, fmi: fmi & 0x1F, occurrence: occurrence & 0x7F, conversion_method: false }
+    }
+
+    pub fn from_bytes(bytes: [u8; 4]) -> Self {
+        Self {
+            spn: u32::from_le_bytes([bytes[0], bytes[1], bytes[2] >> 5, 0]),
+            fmi: bytes[2] & 0x1F,
+            occurrence: bytes[3] & 0x7F,
+            conversion_method: bytes[3] & 0x80 != 0,
+        }
+    }
+
+    pub fn to_bytes(&self) -> [u8; 4] {
+        let spn = self.spn.to_le_bytes();
+        [
+            spn[0],
+            spn[1],
+            ((spn[2] & 0x07) << 5) | (self.fmi & 0x1F),
+            ((self.conversion_method as u8) << 7) | (self.occurrence & 0x7F),
+        ]
+    }
+}
+
+/// The DM1 message, the DM2 message is in the same format.
+///
+/// The message with no DTC is sent as one DTC with all zero bits.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Dm1 {
+    pub lamps: LampStatus,
+    pub dtcs: Vec<Dtc>,
+}
+
+/// The DM2 message.
+pub type Dm2 = Dm1;
+
+impl Dm1 {
+    pub fn new(lamps: LampStatus, dtcs: Vec<Dtc>) -> Self {
+        Self { lamps, dtcs }
+    }
+
+    /// Convert to the payload, padded with 0xFF to 8 bytes if it is shorter.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut result = self.lamps.to_bytes().to_vec();
+        if self.dtcs.is_empty() {
+            result.extend([0; 4]);
+        }
+        self.dtcs.iter()
+            .for_each(|v| result.extend(v.to_bytes()));
+        if result.len() < 8 {
+            result.resize(8, 0xFF);
+        }
+
+        result
+    }
+}
+
+impl TryFrom<&[u8]> for Dm1 {
+    type Error = Error;
+
+    /// Decode the single frame or the payload reassembled by the transport protocol,
+    /// the zero and padding DTCs are skipped.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        if value.len() < 2 {
+            return Err(Error::InvalidDataLength { actual: value.len(), expect: 2 });
+        }
+
+        let dtcs = value[2..].chunks_exact(4)
+            .filter(|v| v.iter().any(|b| *b != 0) && v.iter().any(|b| *b != 0xFF))
+            .map(|v| Dtc::from_bytes([v[0], v[1], v[2], v[3]]))
+            .collect();
+
+        Ok(Self { lamps: LampStatus::from_bytes([value[0], value[1]]), dtcs })
+    }
+}
+
+impl TryFrom<DataField> for Dm1 {
+    type Error = Error;
+
+    #[inline]
+    fn try_from(value: DataField) -> Result<Self, Self::Error> {
+        Self::try_from(value.to_be_bytes().as_slice())
+    }
+}
+
+/// The decoded diagnostic messages.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DmEvent {
+    /// The active DTCs from `source`.
+    Active { source: u8, message: Dm1 },
+    /// The previously active DTCs from `source`.
+    PreviouslyActive { source: u8, message: Dm2 },
+}
+
+pub trait DmEventListener: Send {
+    fn on_dm_event(&mut self, event: DmEvent);
+}
+
+/// Decode the DM1 and DM2 messages, register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan)
+/// for the single frames and as the [`J1939TpEventListener`] of [`J1939Tp`](crate::can::j1939::J1939Tp)
+/// for the broadcast ones.
+#[derive(Clone)]
+pub struct Dm1Listener<C> {
+    pub(crate) channel: C,
+    active: Arc<Mutex<HashMap<u8, Dm1>>>,
+    pub(crate) listener: Arc<Mutex<Box<dyn DmEventListener>>>,
+}
+
+impl<C: Channel> Dm1Listener<C> {
+    pub fn new(channel: C, listener: Box<dyn DmEventListener>) -> Self {
+        Self {
+            channel,
+            active: Default::default(),
+            listener: Arc::new(Mutex::new(listener)),
+        }
+    }
+
+    /// The latest active DTCs of the source address.
+    pub fn active(&self, source: u8) -> Option<Dm1> {
+        self.active.lock().ok()?
+            .get(&source)
+            .cloned()
+    }
+
+    fn on_message(&self, pgn: u32, source: u8, data: &[u8]) {
+        let message = match Dm1::try_from(data) {
+            Ok(v) => v,
+            Err(e) => {
+                log::warn!("J1939 - invalid DM from {:02X}: {}", source, e);
+                return;
+            },
+        };
+
+        let event = match pgn {
+            PGN_DM1 => {
+                if let Ok(mut active) = self.active.lock() {
+                    active.insert(source, message.clone());
+                }
+                DmEvent::Active { source, message }
+            },
+            PGN_DM2 => DmEvent::PreviouslyActive { source, message },
+            _ => return,
+        };
+
+        log::debug!("J1939 - DM received: {:?}", event);
+        match self.listener.lock() {
+            Ok(mut listener) => listener.on_dm_event(event),
+            Err(_) => log::warn!("J1939 - DM listener error"),
+        }
+    }
+}
+
+impl<C: Channel> J1939TpEventListener for Dm1Listener<C> {
+    fn on_tp_event(&mut self, event: J1939TpEvent) {
+        if let J1939TpEvent::DataReceived(message) = event {
+            if let SourceAddress::Some(source) = message.id().source_address() {
+                self.on_message(message.id().pgn_bits(), source, message.data());
+            }
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for Dm1Listener<C>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        for frame in frames {
+            if !frame.is_extended() {
+                continue;
+            }
+
+            let id = J1939Id::from(frame.id());
+            let pgn = id.pgn_bits();
+            if pgn != PGN_DM1 && pgn != PGN_DM2 {
+                continue;
+            }
+            if let SourceAddress::Some(source) = id.source_address() {
+                self.on_message(pgn, source, frame.data());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::can::driver::MOCK_CHANNEL;
+    use crate::can::j1939::{DataField, J1939Tp, J1939TpFrame};
+    use crate::can::message::CanMessage;
+    use crate::device::Listener;
+    use crate::can::testing::frame;
+    use super::*;
+
+    type Collector = crate::can::testing::Collector<DmEvent>;
+
+    #[test]
+    fn test_dtc() {
+        // SPN 100(engine oil pressure), FMI 1, OC 3
+        let dtc = Dtc::new(100, 1, 3);
+        assert_eq!(dtc.to_bytes(), [0x64, 0x00, 0x01, 0x03]);
+        assert_eq!(Dtc::from_bytes([0x64, 0x00, 0x01, 0x03]), dtc);
+
+        // the top 3 bits of SPN 520199(0x7F007), the conversion method
+        let dtc = Dtc::from_bytes([0x07, 0xF0, 0xEF, 0x85]);
+        assert_eq!(dtc.spn, 0x7F007);
+        assert_eq!(dtc.fmi, 0x0F);
+        assert_eq!(dtc.occurrence, 5);
+        assert!(dtc.conversion_method);
+        assert_eq!(dtc.to_bytes(), [0x07, 0xF0, 0xEF, 0x85]);
+
+        let lamps = LampStatus::from_bytes([0x44, 0xFF]);
+        assert_eq!(lamps.malfunction, Lamp::new(LampState::On, LampFlash::Off));
+        assert_eq!(lamps.red_stop, Lamp::new(LampState::Off, LampFlash::Off));
+        assert_eq!(lamps.amber_warning, Lamp::new(LampState::On, LampFlash::Off));
+        assert_eq!(lamps.protect, Lamp::new(LampState::Off, LampFlash::Off));
+        assert_eq!(lamps.to_bytes(), [0x44, 0xFF]);
+    }
+
+    #[test]
+    fn test_single_frame() -> anyhow::Result<()> {
+        let data = [0x04, 0xF7, 0x64, 0x00, 0x01, 0x03, 0xFF, 0xFF];
+        let dm1 = Dm1::try_from(DataField::from_bits(u64::from_be_bytes(data)))?;
+        assert_eq!(dm1.lamps.amber_warning, Lamp::new(LampState::On, LampFlash::Fast));
+        assert_eq!(dm1.dtcs, [Dtc::new(100, 1, 3)]);
+        assert_eq!(dm1.to_bytes(), data);
+
+        // no active DTC
+        let dm1 = Dm1::try_from([0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF].as_slice())?;
+        assert!(dm1.dtcs.is_empty());
+        assert_eq!(dm1.to_bytes(), [0x00, 0xFF, 0x00, 0x00, 0x00, 0x00, 0xFF, 0xFF]);
+        assert!(Dm1::try_from([0x00].as_slice()).is_err());
+
+        let collector = Collector::default();
+        let mut listener = Dm1Listener::new(MOCK_CHANNEL.to_string(), Box::new(collector.clone()));
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut listener, MOCK_CHANNEL.into(), &[
+            frame(0x18FECA00, &data),
+            frame(0x18FECB03, &data),
+            frame(0x18FEF100, &data),
+        ]);
+        let message = Dm1::new(LampStatus::from_bytes([0x04, 0xF7]), vec![Dtc::new(100, 1, 3)]);
+        assert_eq!(collector.take(), [
+            DmEvent::Active { source: 0x00, message: message.clone() },
+            DmEvent::PreviouslyActive { source: 0x03, message: message.clone() },
+        ]);
+        assert_eq!(listener.active(0x00), Some(message));
+        assert_eq!(listener.active(0x03), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_multi_packet() -> anyhow::Result<()> {
+        let dtcs = vec![
+            Dtc::new(100, 1, 3),
+            Dtc::new(110, 0, 1),
+            Dtc::new(190, 2, 127),
+            Dtc::new(0x7F007, 15, 5),
+            Dtc::new(520_000, 31, 0),
+        ];
+        let message = Dm1::new(LampStatus::from_bytes([0x14, 0xFF]), dtcs);
+        let data = message.to_bytes();
+        assert_eq!(data.len(), 22);
+        assert_eq!(Dm1::try_from(data.as_slice())?, message);
+
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let collector = Collector::default();
+        let dm = Dm1Listener::new(MOCK_CHANNEL.to_string(), Box::new(collector.clone()));
+        let mut tp = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x20, tx, Box::new(dm.clone()));
+        for v in J1939TpFrame::from_bam(PGN_DM1, &data)? {
+            let id = 0x1C00FF00 | (v.pgn() << 8) | 0x17;
+            tp.on_frame_received(MOCK_CHANNEL.into(), &[frame(id, &v.encode())]);
+        }
+
+        assert_eq!(collector.take(), [DmEvent::Active { source: 0x17, message: message.clone() }]);
+        assert_eq!(dm.active(0x17), Some(message));
+        Ok(())
+    }
+}
@@ -0,0 +1,356 @@
+//! The NMEA 2000 fast packet, a payload up to 223 bytes in the frames of a single PGN.
+//!
+//! The first byte of each frame is the sequence id(3 bits) and the frame counter(5 bits),
+//! the frame 0 carries the total length and 6 bytes, the following frames carry 7 bytes.
+
+use std::any::Any;
+use std::collections::{HashMap, HashSet};
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::j1939::{J1939Id, J1939TpEvent, J1939TpEventListener, Message, Pdu};
+use crate::device::{Channel, Listener};
+use crate::error::Error;
+
+/// The max size of a fast packet payload.
+pub const FAST_PACKET_MAX_SIZE: usize = 6 + 31 * 7;
+const FAST_PACKET_TIMEOUT: Duration = Duration::from_millis(750);
+const FRAME_SIZE: usize = 8;
+
+/// Split the payloads into the fast packet frames with a rolling sequence id of each PGN and source.
+#[derive(Debug, Default)]
+pub struct FastPacketSender {
+    sequences: HashMap<(u32, u8), u8>,
+}
+
+impl FastPacketSender {
+    pub fn new() -> Self {
+        Default::default()
+    }
+
+    /// Returns the frames of `data` on `channel`, the last frame is padded with 0xFF.
+    pub fn frames<F: FrameMut>(&mut self, channel: F::Channel, id: J1939Id, data: &[u8]) -> Result<Vec<F>, Error> {
+        if data.is_empty() || data.len() > FAST_PACKET_MAX_SIZE {
+            return Err(Error::InvalidDataLength { actual: data.len(), expect: FAST_PACKET_MAX_SIZE });
+        }
+
+        let key = (id.pgn().normalized().into_bits(), id.source_address_bits());
+        let sequence = self.sequences.entry(key).or_default();
+        let head = *sequence << 5;
+        *sequence = (*sequence + 1) & 0x07;
+
+        let (first, rest) = data.split_at(data.len().min(6));
+        let mut payloads = vec![[&[head, data.len() as u8], first].concat()];
+        payloads.extend(rest.chunks(7)
+            .enumerate()
+            .map(|(i, v)| [&[head | (i as u8 + 1)], v].concat()));
+
+        payloads.into_iter()
+            .map(|mut v| {
+                v.resize(FRAME_SIZE, 0xFF);
+                let mut frame = F::new(Id::Extended(id.into_bits()), &v)
+                    .ok_or(Error::ConvertError { src: "fast packet", target: "can-frame" })?;
+                frame.set_channel(channel.clone());
+                Ok(frame)
+            })
+            .collect()
+    }
+}
+
+#[derive(Debug)]
+struct FastPacketSession {
+    size: usize,
+    next: u8,
+    buffer: Vec<u8>,
+    last: Instant,
+}
+
+/// Reassemble the fast packets of the registered PGNs, keyed by the PGN, the source address and the sequence id.
+///
+/// A new frame 0 replaces the incomplete packet of the same key, so the sequence id can roll over,
+/// and the packets of different sequence ids from a sender can be interleaved.
+#[derive(Debug)]
+pub struct FastPacketAssembler {
+    pgns: HashSet<u32>,
+    timeout: Duration,
+    sessions: HashMap<(u32, u8, u8), FastPacketSession>,
+}
+
+impl FastPacketAssembler {
+    /// The PGNs transferred by the fast packet, the other ones are ignored.
+    pub fn new(pgns: impl IntoIterator<Item = u32>) -> Self {
+        Self {
+            pgns: pgns.into_iter().map(normalize).collect(),
+            timeout: FAST_PACKET_TIMEOUT,
+            sessions: Default::default(),
+        }
+    }
+
+    /// Set the max interval between the frames of a packet, the incomplete packet is discarded after it.
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    #[inline]
+    pub fn is_fast_packet(&self, pgn: u32) -> bool {
+        self.pgns.contains(&normalize(pgn))
+    }
+
+    /// Feed a received frame, returns the message when the packet is completed.
+    pub fn push(&mut self, id: J1939Id, data: &[u8], now: Instant) -> Option<Message> {
+        let pgn = id.pgn().normalized().into_bits();
+        if !self.pgns.contains(&pgn) || data.len() < 2 {
+            return None;
+        }
+
+        let timeout = self.timeout;
+        self.sessions.retain(|_, v| now.duration_since(v.last) <= timeout);
+
+        let source = id.source_address_bits();
+        let (sequence, counter) = (data[0] >> 5, data[0] & 0x1F);
+        let key = (pgn, source, sequence);
+        if counter == 0 {
+            let size = data[1] as usize;
+            if size == 0 || size > FAST_PACKET_MAX_SIZE {
+                log::warn!("J1939 - invalid fast packet size {} of PGN {:05X} from {:02X}", size, pgn, source);
+                self.sessions.remove(&key);
+                return None;
+            }
+
+            let buffer = data[2..].iter().take(size).copied().collect::<Vec<_>>();
+            if buffer.len() == size {
+                self.sessions.remove(&key);
+                return Some(Message::from_parts(id, Pdu::Bytes(buffer)));
+            }
+            if self.sessions.insert(key, FastPacketSession { size, next: 1, buffer, last: now }).is_some() {
+                log::debug!("J1939 - fast packet {} of PGN {:05X} from {:02X} restarted", sequence, pgn, source);
+            }
+            return None;
+        }
+
+        let Some(session) = self.sessions.get_mut(&key) else {
+            log::trace!("J1939 - fast packet frame {} of PGN {:05X} from {:02X} without frame 0", counter, pgn, source);
+            return None;
+        };
+        if counter != session.next {
+            log::warn!("J1939 - fast packet of PGN {:05X} from {:02X} expect frame {}, got {}",
+                pgn, source, session.next, counter);
+            self.sessions.remove(&key);
+            return None;
+        }
+
+        session.buffer.extend_from_slice(&data[1..]);
+        session.next += 1;
+        session.last = now;
+        if session.buffer.len() < session.size {
+            return None;
+        }
+
+        let mut session = self.sessions.remove(&key)?;
+        session.buffer.truncate(session.size);
+        Some(Message::from_parts(id, Pdu::Bytes(session.buffer)))
+    }
+}
+
+#[inline]
+fn normalize(pgn: u32) -> u32 {
+    crate::can::j1939::Pgn::from_bits(pgn).normalized().into_bits()
+}
+
+/// Reassemble the fast packets of the channel and deliver them as [`J1939TpEvent::DataReceived`],
+/// register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan).
+#[derive(Clone)]
+pub struct FastPacketListener<C> {
+    pub(crate) channel: C,
+    assembler: Arc<Mutex<FastPacketAssembler>>,
+    listener: Arc<Mutex<Box<dyn J1939TpEventListener>>>,
+}
+
+impl<C> FastPacketListener<C> {
+    pub fn new(channel: C, assembler: FastPacketAssembler, listener: Box<dyn J1939TpEventListener>) -> Self {
+        Self {
+            channel,
+            assembler: Arc::new(Mutex::new(assembler)),
+            listener: Arc::new(Mutex::new(listener)),
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for FastPacketListener<C>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        let now = Instant::now();
+        let messages = match self.assembler.lock() {
+            Ok(mut assembler) => frames.iter()
+                .filter(|v| v.is_extended())
+                .filter_map(|v| assembler.push(J1939Id::from(v.id()), v.data(), now))
+                .collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+
+        for message in messages {
+            log::debug!("J1939 - fast packet received: {}", message);
+            match self.listener.lock() {
+                Ok(mut listener) => listener.on_tp_event(J1939TpEvent::DataReceived(message)),
+                Err(e) => log::warn!("J1939 - listener error: {:?}", e),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use hex_literal::hex;
+    use crate::can::driver::MOCK_CHANNEL;
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::j1939::{J1939Id, J1939TpEvent, Message, Pdu};
+    use crate::can::message::CanMessage;
+    use crate::can::testing::frame;
+    use crate::device::Listener;
+    use super::{FastPacketAssembler, FastPacketListener, FastPacketSender};
+
+    type Collector = crate::can::testing::Collector<J1939TpEvent>;
+
+    const PGN_GNSS_POSITION: u32 = 129029;
+    const GNSS_ID: u32 = 0x0DF80523;
+
+    /// The GNSS position data of 43 bytes captured in 7 frames.
+    const GNSS_FRAMES: [[u8; 8]; 7] = [
+        hex!("60 2B 21 99 4F 88 B2 FF"),
+        hex!("61 1A 00 5A 49 88 D9 B9"),
+        hex!("62 34 07 00 02 9E 66 BD"),
+        hex!("63 14 9F 00 20 4B BC 00"),
+        hex!("64 00 00 00 00 12 FC 0C"),
+        hex!("65 50 00 96 00 2A 12 00"),
+        hex!("66 00 00 FF FF FF FF FF"),
+    ];
+
+    fn gnss_payload() -> Vec<u8> {
+        let mut result = GNSS_FRAMES[0][2..].to_vec();
+        GNSS_FRAMES[1..].iter().for_each(|v| result.extend_from_slice(&v[1..]));
+        result.truncate(43);
+        result
+    }
+
+    #[test]
+    fn test_gnss_position() {
+        let collector = Collector::default();
+        let assembler = FastPacketAssembler::new([PGN_GNSS_POSITION]);
+        let mut listener = FastPacketListener::new(MOCK_CHANNEL.to_string(), assembler, Box::new(collector.clone()));
+        let frames = GNSS_FRAMES.map(|v| frame(GNSS_ID, &v));
+        // delivered on the last frame
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut listener, MOCK_CHANNEL.into(), &frames[..6]);
+        assert!(collector.0.lock().unwrap().is_empty());
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut listener, MOCK_CHANNEL.into(), &frames[6..]);
+
+        let events = std::mem::take(&mut *collector.0.lock().unwrap());
+        let [J1939TpEvent::DataReceived(message)] = events.as_slice() else {
+            panic!("unexpected events: {:?}", events);
+        };
+        assert_eq!(message.id().into_bits(), GNSS_ID);
+        assert_eq!(message.id().pgn().into_bits(), PGN_GNSS_POSITION);
+        let data = message.data();
+        assert_eq!(data, gnss_payload());
+        // SID, days since 1970, latitude and longitude in 1e-16 degrees
+        assert_eq!((data[0], u16::from_le_bytes([data[1], data[2]])), (0x21, 20377));
+        assert_eq!(i64::from_le_bytes(data[7..15].try_into().unwrap()), 519244201000000000);
+        assert_eq!(i64::from_le_bytes(data[15..23].try_into().unwrap()), 44777325000000000);
+
+        // the single frame PGNs are ignored
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut listener, MOCK_CHANNEL.into(), &[
+            frame(0x09F80123, &hex!("60 2B 21 99 4F 88 B2 FF")),
+        ]);
+        assert!(collector.0.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_sender() -> anyhow::Result<()> {
+        let id = J1939Id::from_bits(GNSS_ID);
+        let mut sender = FastPacketSender::new();
+        let frames: Vec<CanMessage> = sender.frames(MOCK_CHANNEL.into(), id, &gnss_payload())?;
+        // the sequence id starts from 0
+        assert_eq!(frames.len(), 7);
+        assert!(frames.iter().zip(GNSS_FRAMES).all(|(f, v)| f.data()[0] == v[0] & 0x1F && f.data()[1..] == v[1..]));
+
+        let mut assembler = FastPacketAssembler::new([PGN_GNSS_POSITION]);
+        let now = Instant::now();
+        for i in 1..=8 {
+            let frames: Vec<CanMessage> = sender.frames(MOCK_CHANNEL.into(), id, &gnss_payload())?;
+            assert_eq!(frames[0].data()[0] >> 5, i % 8);
+            let messages = frames.iter()
+                .filter_map(|f| assembler.push(id, f.data(), now))
+                .collect::<Vec<_>>();
+            assert_eq!(messages, [Message::from_parts(id, Pdu::Bytes(gnss_payload()))]);
+        }
+
+        // a single frame packet
+        let frames: Vec<CanMessage> = sender.frames(MOCK_CHANNEL.into(), id, &[1, 2, 3])?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), hex!("20 03 01 02 03 FF FF FF"));
+        assert!(sender.frames::<CanMessage>(MOCK_CHANNEL.into(), id, &[0; 224]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interleaved() {
+        let id = J1939Id::from_bits(GNSS_ID);
+        let now = Instant::now();
+        let mut assembler = FastPacketAssembler::new([PGN_GNSS_POSITION])
+            .with_timeout(Duration::from_millis(100));
+        let with_sequence = |sequence: u8| GNSS_FRAMES.map(|mut v| {
+            v[0] = (sequence << 5) | (v[0] & 0x1F);
+            v
+        });
+        let (a, b) = (with_sequence(7), with_sequence(0));
+
+        // the groups of the rolled over sequence ids are interleaved
+        let mut messages = vec![];
+        for (a, b) in a.iter().zip(b.iter()) {
+            messages.extend(assembler.push(id, a, now));
+            messages.extend(assembler.push(id, b, now));
+        }
+        assert_eq!(messages.len(), 2);
+
+        // a lost frame discards the packet
+        let messages = [0, 1, 3, 4, 5, 6].into_iter()
+            .filter_map(|i| assembler.push(id, &a[i], now))
+            .collect::<Vec<_>>();
+        assert!(messages.is_empty());
+
+        // a new frame 0 restarts the packet
+        let messages = [0, 1, 2, 0, 1, 2, 3, 4, 5, 6].into_iter()
+            .filter_map(|i| assembler.push(id, &b[i], now))
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 1);
+
+        // the timed out packet is discarded
+        let messages = (0..7)
+            .filter_map(|i| assembler.push(id, &a[i], now + Duration::from_millis(60) * i as u32))
+            .collect::<Vec<_>>();
+        assert_eq!(messages.len(), 1);
+        assert!((0..7)
+            .filter_map(|i| assembler.push(id, &a[i], now + Duration::from_millis(200) * (i as u32 + 1)))
+            .next()
+            .is_none());
+    }
+}
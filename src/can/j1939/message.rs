@@ -1,12 +1,26 @@
-use crate::can::j1939::{Conversion, {J1939Id, NameField, DataField, Pdu, PduType}};
+use std::fmt::{Display, Formatter, Write};
+use crate::can::j1939::{Conversion, {AddressRegistry, DestinationAddress, J1939Id, NameField, DataField, Pdu, PduType}};
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub struct Message {
     id: J1939Id,
     pdu: Pdu,
+    /// The bytes of the 8-byte PDUs in the transmission order.
+    raw: [u8; 8],
 }
 
 impl Message {
+    #[inline]
+    fn new(id: J1939Id, pdu: Pdu) -> Self {
+        let raw = match &pdu {
+            Pdu::NameField(name) => name.into_bits().to_le_bytes(),
+            Pdu::DataField(data) => data.to_be_bytes(),
+            Pdu::Bytes(_) => [0; 8],
+        };
+
+        Self { id, pdu, raw }
+    }
+
     /// Constructs a new Message from its parts: a 29-bit J1939 identifier and pdu containing 64 bits of generic data.
     ///
     /// # Arguments
@@ -17,7 +31,22 @@ impl Message {
     /// A new [`Message`] instance initialized with the provided parts.
     #[inline]
     pub fn from_parts(id: J1939Id, pdu: Pdu) -> Self {
-        Self { id, pdu }
+        Self::new(id, pdu)
+    }
+
+    /// Constructs a new [`Message`] from the payload in the transmission order.
+    ///
+    /// # Returns
+    /// - The message of [`Pdu::DataField`] if the payload is 8 bytes.
+    /// - The message of [`Pdu::Bytes`] otherwise.
+    #[inline]
+    pub fn from_bytes(id: J1939Id, data: &[u8]) -> Self {
+        let pdu = match <[u8; 8]>::try_from(data) {
+            Ok(bytes) => Pdu::DataField(DataField::from_bits(u64::from_be_bytes(bytes))),
+            Err(_) => Pdu::Bytes(data.to_vec()),
+        };
+
+        Self::new(id, pdu)
     }
 
     /// Destructures the [`Message`] into its parts: a 29-bit J1939 identifier and pdu containing 64 bits of generic data.
@@ -37,20 +66,12 @@ impl Message {
     pub fn try_from_bits(hex_id: u32, hex_pdu: u64, pdu_type: PduType) -> Option<Self> {
         let id = J1939Id::from_bits(hex_id);
         let pdu = match pdu_type {
-            PduType::Name => match NameField::try_from_bits(hex_pdu) {
-                Some(v) => Some(Pdu::NameField(v)),
-                None => None,
-            }
-            PduType::Data => match DataField::try_from_bits(hex_pdu) {
-                Some(v) => Some(Pdu::DataFiled(v)),
-                None => None,
-            },
+            PduType::Name => NameField::try_from_bits(hex_pdu).map(Pdu::NameField),
+            PduType::Data => DataField::try_from_bits(hex_pdu).map(Pdu::DataField),
+            PduType::Bytes => Some(Pdu::Bytes(hex_pdu.to_be_bytes().to_vec())),
         };
 
-        match pdu {
-            Some(pdu) => Some(Self { id, pdu }),
-            None => None,
-        }
+        pdu.map(|pdu| Self::new(id, pdu))
     }
 
     /// Constructs a new [`Message`] from hexadecimal string representations of its components.
@@ -60,20 +81,12 @@ impl Message {
         match id {
             Some(id) => {
                 let pdu = match pdu_type {
-                    PduType::Name => match NameField::try_from_hex(hex_pdu) {
-                        Some(v) => Some(Pdu::NameField(v)),
-                        None => None,
-                    }
-                    PduType::Data => match DataField::try_from_hex(hex_pdu) {
-                        Some(v) => Some(Pdu::DataFiled(v)),
-                        None => None,
-                    },
+                    PduType::Name => NameField::try_from_hex(hex_pdu).map(Pdu::NameField),
+                    PduType::Data => DataField::try_from_hex(hex_pdu).map(Pdu::DataField),
+                    PduType::Bytes => hex::decode(hex_pdu).ok().map(Pdu::Bytes),
                 };
 
-                match pdu {
-                    Some(pdu) => Some(Self { id, pdu }),
-                    None => None,
-                }
+                pdu.map(|pdu| Self::new(id, pdu))
 
             },
             None => None,
@@ -94,10 +107,11 @@ impl Message {
         let id = J1939Id::from_bits(hex_id);
         let pdu = match pdu_type {
             PduType::Name => Pdu::NameField(NameField::from_bits(hex_pdu)),
-            PduType::Data => Pdu::DataFiled(DataField::from_bits(hex_pdu)),
+            PduType::Data => Pdu::DataField(DataField::from_bits(hex_pdu)),
+            PduType::Bytes => Pdu::Bytes(hex_pdu.to_be_bytes().to_vec()),
         };
 
-        Self { id, pdu }
+        Self::new(id, pdu)
     }
 
     /// Constructs a new [`Message`] from hexadecimal string representations of its components.
@@ -114,10 +128,11 @@ impl Message {
         let id = J1939Id::from_hex(hex_id)?;
         let pdu = match pdu_type {
             PduType::Name => Pdu::NameField(NameField::from_hex(hex_pdu)?),
-            PduType::Data => Pdu::DataFiled(DataField::from_hex(hex_pdu)?),
+            PduType::Data => Pdu::DataField(DataField::from_hex(hex_pdu)?),
+            PduType::Bytes => Pdu::Bytes(hex::decode(hex_pdu).ok()?),
         };
 
-        Some(Self { id, pdu })
+        Some(Self::new(id, pdu))
     }
 
     /// Retrieves the 29-bit J1939 identifier from the message.
@@ -133,13 +148,134 @@ impl Message {
     /// Retrieves the pdu from the message.
     ///
     /// # Returns
-    /// The [`Pdu`] bitfield associated with the message.
+    /// The [`Pdu`] associated with the message.
+    #[inline]
+    #[must_use]
+    pub fn pdu(&self) -> &Pdu {
+        &self.pdu
+    }
+
+    /// Retrieves the payload in the transmission order, whatever the [`Pdu`] variant is.
     #[inline]
     #[must_use]
-    pub fn pdu(&self) -> Pdu {
-        self.pdu
+    pub fn data(&self) -> &[u8] {
+        match &self.pdu {
+            Pdu::Bytes(bytes) => bytes,
+            _ => &self.raw,
+        }
     }
 }
 
+impl Message {
+    /// The destination address, the global address for PDU2.
+    #[inline]
+    fn destination(&self) -> u8 {
+        match self.id.destination_address() {
+            DestinationAddress::Some(v) => v,
+            DestinationAddress::None => 0xFF,
+        }
+    }
 
+    fn fmt_payload(&self, f: &mut impl Write) -> std::fmt::Result {
+        match &self.pdu {
+            Pdu::NameField(name) => write!(f, "NAME {}", name),
+            _ => {
+                write!(f, "data")?;
+                self.data().iter()
+                    .try_for_each(|v| write!(f, " {:02X}", v))
+            },
+        }
+    }
+
+    /// Formats the message with the PGN description and the address labels of the `registry`, such as
+    /// `PGN 61444 (EEC1 – Electronic Engine Controller 1) prio 3 SA 0x00 (Engine) -> DA 0xFF (Global)  data ...`.
+    pub fn fmt_verbose(&self, registry: &AddressRegistry) -> String {
+        let pgn = self.id.pgn().normalized();
+        let (sa, da) = (self.id.source_address_bits(), self.destination());
+        let mut result = format!("PGN {}", pgn.into_bits());
+        if pgn.label().is_some() {
+            let _ = write!(result, " ({})", pgn);
+        }
+        let da_label = match da {
+            0xFF => "Global".into(),
+            _ => registry.label_of(da),
+        };
+        let _ = write!(result, " prio {} SA 0x{:02X} ({}) -> DA 0x{:02X} ({})  ",
+                       self.id.priority(), sa, registry.label_of(sa), da, da_label);
+        let _ = self.fmt_payload(&mut result);
+
+        result
+    }
+}
+
+impl Display for Message {
+    /// Formats the message such as `PGN 61444 (EEC1) prio 3 SA 0x00 -> DA 0xFF  data FF FF 82 DF 1A FF FF FF`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let pgn = self.id.pgn().normalized();
+        write!(f, "PGN {}", pgn.into_bits())?;
+        if let Some(label) = pgn.label() {
+            write!(f, " ({})", label)?;
+        }
+        write!(f, " prio {} SA 0x{:02X} -> DA 0x{:02X}  ", self.id.priority(), self.id.source_address_bits(), self.destination())?;
+        self.fmt_payload(f)
+    }
+}
 
+#[cfg(test)]
+mod tests {
+    use crate::can::j1939::{AddressRegistry, DataField, J1939Id, NameField, Pdu, PduType};
+    use super::Message;
+
+    #[test]
+    fn test_pdu() {
+        let id = J1939Id::from_bits(0x18FECA00);
+        let message = Message::from_bytes(id, &[0x04, 0xF7, 0x64, 0x00, 0x01, 0x03, 0xFF, 0xFF]);
+        assert_eq!(message.pdu(), &Pdu::DataField(DataField::from_bits(0x04F7_6400_0103_FFFF)));
+        assert_eq!(message.data(), [0x04, 0xF7, 0x64, 0x00, 0x01, 0x03, 0xFF, 0xFF]);
+
+        // the payload reassembled by the transport protocol
+        let data = (0..22).collect::<Vec<u8>>();
+        let message = Message::from_bytes(id, &data);
+        assert_eq!(message.pdu(), &Pdu::Bytes(data.clone()));
+        assert_eq!(message.data(), data);
+        assert_eq!(message.pdu().len(), 22);
+        assert_eq!(Message::from_hex("18FECA00", &hex::encode(&data), PduType::Bytes), Some(message.clone()));
+        let (_, pdu) = message.into_parts();
+        assert_eq!(pdu.to_bytes(), data);
+
+        // the NAME is in little-endian
+        let message = Message::from_parts(J1939Id::from_bits(0x18EEFF80), Pdu::NameField(NameField::from_bits(0x8000810028BFFFFF)));
+        assert_eq!(message.data(), [0xFF, 0xFF, 0xBF, 0x28, 0x00, 0x81, 0x00, 0x80]);
+
+        #[allow(deprecated)]
+        let pdu = Pdu::DataFiled(DataField::from_bits(0x1122334455667788));
+        assert_eq!(pdu, Pdu::DataField(DataField::from_bits(0x1122334455667788)));
+        assert_eq!(pdu.to_string(), "1122334455667788");
+    }
+
+    #[test]
+    fn test_display() {
+        let message = Message::from_hex("0CF00400", "FFFF82DF1AFFFFFF", PduType::Data).unwrap();
+        assert_eq!(message.to_string(), "PGN 61444 (EEC1) prio 3 SA 0x00 -> DA 0xFF  data FF FF 82 DF 1A FF FF FF");
+
+        // PDU1 to a specific destination
+        let message = Message::from_bytes(J1939Id::from_bits(0x18EA00F9), &[0xE5, 0xFE, 0x00]);
+        assert_eq!(message.to_string(), "PGN 59904 (RQST) prio 6 SA 0xF9 -> DA 0x00  data E5 FE 00");
+
+        // proprietary
+        let message = Message::from_bytes(J1939Id::from_bits(0x18FF1290), &[0x01, 0x02]);
+        assert_eq!(message.to_string(), "PGN 65298 prio 6 SA 0x90 -> DA 0xFF  data 01 02");
+        let mut registry = AddressRegistry::new();
+        registry.register(0x90, "Crane Controller".into());
+        assert_eq!(message.fmt_verbose(&registry), "PGN 65298 prio 6 SA 0x90 (Crane Controller) -> DA 0xFF (Global)  data 01 02");
+
+        let message = Message::from_hex("18EAFF00", "E5FE00FFFFFFFFFF", PduType::Data).unwrap();
+        assert_eq!(message.fmt_verbose(&registry), "PGN 59904 (RQST – Request) prio 6 SA 0x00 \
+            (Primary Engine Controller | (CPC, ECM)) -> DA 0xFF (Global)  data E5 FE 00 FF FF FF FF FF");
+
+        // the NAME of the Address Claimed
+        let message = Message::from_parts(J1939Id::from_bits(0x18EEFF80), Pdu::NameField(NameField::from_bits(0x8000810028BFFFFF)));
+        assert_eq!(message.to_string(), "PGN 60928 (AC) prio 6 SA 0x80 -> DA 0xFF  NAME 8000810028BFFFFF (arbitrary address: true, \
+            industry group: Global(0), vehicle system: 0/0, function: 129/0, ECU instance: 0, manufacturer code: 325, identity number: 2097151)");
+    }
+}
@@ -1,38 +1,115 @@
 //! Copy from [crate](https://crates.io/crates/can-types)|[Homepage](https://github.com/natkeo559/can-types)
 
+mod ack;
 mod address;
+mod catalog;
+mod claim;
+mod dm;
+#[cfg(feature = "nmea2000")]
+mod fast_packet;
 mod message;
+mod network;
 mod payload;
 mod pgn;
+mod pgns;
+mod request;
+mod router;
+mod tp;
 
+pub use ack::*;
 pub use address::*;
+pub use claim::*;
+pub use dm::*;
+#[cfg(feature = "nmea2000")]
+pub use fast_packet::*;
 pub use message::*;
+pub use network::*;
 pub use payload::*;
 pub use pgn::*;
+pub use pgns::*;
+pub use request::*;
+pub use router::*;
+pub use tp::*;
 
 use std::fmt::format;
+use std::str::FromStr;
 use bitfield_struct::bitfield;
 use crate::can::EFF_MASK;
 use crate::can::identifier::Id;
+use crate::error::Error;
+
+/// The errors of parsing the hexadecimal strings.
+#[derive(Debug, Clone, PartialEq, Eq, thiserror::Error)]
+pub enum J1939ParseError {
+    #[error("J1939 - the hex string has no digit")]
+    Empty,
+    #[error("J1939 - invalid hex character {character:?} at {index}")]
+    InvalidCharacter { character: char, index: usize },
+    #[error("J1939 - the hex string overflows {bits} bits")]
+    Overflow { bits: u32 },
+    #[error("J1939 - {value:X} is out of range, the max is {max:X}")]
+    OutOfRange { value: u64, max: u64 },
+}
+
+/// Parse the hexadecimal string leniently, the surrounding whitespaces, an optional `0x`/`0X` prefix,
+/// and the separators(whitespace, `:`, `-`, `_`, `.`) between the digits are ignored.
+pub(crate) fn hex_bits<T: TryFrom<u64>>(hex_str: &str) -> Result<T, J1939ParseError> {
+    let bits = (size_of::<T>() * 8) as u32;
+    let trimmed = hex_str.trim_start();
+    let offset = hex_str.len() - trimmed.len();
+    let (offset, digits) = match trimmed.strip_prefix("0x").or_else(|| trimmed.strip_prefix("0X")) {
+        Some(v) => (offset + 2, v),
+        None => (offset, trimmed),
+    };
+
+    let mut value = 0u64;
+    let mut count = 0;
+    for (index, character) in digits.char_indices() {
+        if character.is_whitespace() || matches!(character, ':' | '-' | '_' | '.') {
+            continue;
+        }
+        let digit = character.to_digit(16)
+            .ok_or(J1939ParseError::InvalidCharacter { character, index: offset + index })?;
+        value = value.checked_mul(16)
+            .map(|v| v | digit as u64)
+            .ok_or(J1939ParseError::Overflow { bits })?;
+        count += 1;
+    }
+    if count == 0 {
+        return Err(J1939ParseError::Empty);
+    }
+
+    T::try_from(value).map_err(|_| J1939ParseError::Overflow { bits })
+}
 
 pub trait Conversion
 where
     Self: Sized,
 {
-    type Type;
+    type Type: TryFrom<u64>;
 
     /// Convert an integer of type [`Self::Type`] into [`Self`]
     fn from_bits(bits: Self::Type) -> Self;
 
-    /// Convert a hexadecimal string slice into [`Self`]
+    /// Convert a hexadecimal string slice into [`Self`] without checking the range of the fields.
     fn from_hex(hex_str: &str) -> Option<Self>;
 
-    /// Convert an integer of type [`Self::Type`] into [`Self`]
+    /// Convert an integer of type [`Self::Type`] into [`Self`], `None` if it is out of range.
     fn try_from_bits(bits: Self::Type) -> Option<Self>;
 
-    /// Convert a hexadecimal string slice into [`Self`]
+    /// Convert a hexadecimal string slice into [`Self`], `None` if it is invalid or out of range.
     fn try_from_hex(hex_str: &str) -> Option<Self>;
 
+    /// Convert an integer of type [`Self::Type`] into [`Self`] with the reason of the failure.
+    fn validate_bits(bits: Self::Type) -> Result<Self, J1939ParseError>;
+
+    /// Convert a hexadecimal string slice into [`Self`] with the reason of the failure,
+    /// the `0x` prefix, whitespaces and separators are allowed.
+    #[inline]
+    fn parse_hex(hex_str: &str) -> Result<Self, J1939ParseError> {
+        Self::validate_bits(hex_bits(hex_str)?)
+    }
+
     /// Convert `self` into an integer of type [`Self::Type`]
     fn into_bits(self) -> Self::Type;
 
@@ -78,9 +155,9 @@ impl From<Id> for J1939Id {
     }
 }
 
-impl Into<Id> for J1939Id {
-    fn into(self) -> Id {
-        Id::from(self.into_bits())
+impl From<J1939Id> for Id {
+    fn from(val: J1939Id) -> Self {
+        Id::from(val.into_bits())
     }
 }
 
@@ -120,9 +197,7 @@ impl Conversion for J1939Id {
     /// ```
     #[inline]
     fn from_hex(hex_str: &str) -> Option<Self> {
-        let bits = u32::from_str_radix(hex_str, 16).ok()?;
-
-        Some(J1939Id(bits))
+        hex_bits(hex_str).ok().map(J1939Id)
     }
 
     /// Creates a new 29-bit J1939 identifier from a 32-bit integer.
@@ -140,10 +215,7 @@ impl Conversion for J1939Id {
     /// ```
     #[inline]
     fn try_from_bits(bits: u32) -> Option<Self> {
-        match bits {
-            0..=EFF_MASK => Some(J1939Id(bits)),
-            _ => None,
-        }
+        Self::validate_bits(bits).ok()
     }
 
     /// Creates a new 29-bit J1939 identifier from a base-16 (hex) string slice.
@@ -161,9 +233,19 @@ impl Conversion for J1939Id {
     /// ```
     #[inline]
     fn try_from_hex(hex_str: &str) -> Option<Self> {
-        let value = u32::from_str_radix(hex_str, 16).ok()?;
+        Self::parse_hex(hex_str).ok()
+    }
 
-        Self::try_from_bits(value)
+    /// Creates a new 29-bit J1939 identifier from a 32-bit integer.
+    ///
+    /// # Errors
+    /// - `J1939ParseError::OutOfRange` if it exceeds 29 bits.
+    #[inline]
+    fn validate_bits(bits: u32) -> Result<Self, J1939ParseError> {
+        match bits {
+            0..=EFF_MASK => Ok(J1939Id(bits)),
+            _ => Err(J1939ParseError::OutOfRange { value: bits as u64, max: EFF_MASK as u64 }),
+        }
     }
 
     /// Creates a new 32-bit integer from the 29-bit J1939 identifier.
@@ -199,6 +281,15 @@ impl Conversion for J1939Id {
     }
 }
 
+impl FromStr for J1939Id {
+    type Err = J1939ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_hex(s)
+    }
+}
+
 impl J1939Id {
     /// Constructs a 29-bit J1939 identifier from its raw parts.
     ///
@@ -218,7 +309,7 @@ impl J1939Id {
         source_addr: u8,
     ) -> Option<Self> {
         match priority {
-            0..=0x70 => {
+            0..=7 => {
                 let bitfield = J1939Id::new()
                     .with_priority_bits(priority)
                     .with_data_page_bits(data_page)
@@ -231,6 +322,34 @@ impl J1939Id {
         }
     }
 
+    /// Constructs a 29-bit J1939 identifier from the PGN and the addresses.
+    ///
+    /// # Errors
+    /// - `Error::InvalidParam` if the priority is out of `0..=7`, the `destination` is `None`
+    ///   for PDU1 PGNs or is given for PDU2 PGNs.
+    pub fn from_pgn(pgn: Pgn, priority: u8, source: u8, destination: Option<u8>) -> Result<Self, Error> {
+        let id = Self::new()
+            .with_priority(priority)?
+            .with_pgn(pgn)
+            .with_source_address(source);
+        match (pgn.pdu_format(), destination) {
+            (PduFormat::Pdu1(_), Some(address)) => id.with_destination_address(address),
+            (PduFormat::Pdu1(_), None) =>
+                Err(Error::InvalidParam(format!("J1939 destination address of PGN {:05X} is required", pgn.into_bits()))),
+            (PduFormat::Pdu2(_), None) => Ok(id),
+            (PduFormat::Pdu2(_), Some(address)) =>
+                Err(Error::InvalidParam(format!("J1939 destination address {:02X} of PDU2 PGN {:05X}", address, pgn.into_bits()))),
+        }
+    }
+
+    /// Destructures the identifier into the priority, PGN, source address and destination address.
+    ///
+    /// The PDU specific bits of the PDU1 PGN are zero.
+    #[must_use]
+    pub fn parts(&self) -> (u8, Pgn, SourceAddress, DestinationAddress) {
+        (self.priority(), self.pgn().normalized(), self.source_address(), self.destination_address())
+    }
+
     /// Returns the priority bits indicating the priority level.
     ///
     /// 0 = highest priority
@@ -267,5 +386,174 @@ impl J1939Id {
     pub fn source_address(&self) -> SourceAddress {
         SourceAddress::Some(self.source_address_bits())
     }
+
+    /// Returns the destination address of PDU1, [`DestinationAddress::None`] for PDU2.
+    #[inline]
+    #[must_use]
+    pub fn destination_address(&self) -> DestinationAddress {
+        self.pgn().destination_address()
+    }
+
+    /// Set the priority.
+    ///
+    /// # Errors
+    /// - `Error::InvalidParam` if the priority is out of `0..=7`.
+    pub fn with_priority(self, priority: u8) -> Result<Self, Error> {
+        match priority {
+            0..=7 => Ok(self.with_priority_bits(priority)),
+            _ => Err(Error::InvalidParam(format!("J1939 priority: {}", priority))),
+        }
+    }
+
+    /// Set the PGN, the destination address is kept for PDU1 PGNs.
+    #[must_use]
+    pub fn with_pgn(self, pgn: Pgn) -> Self {
+        let bits = pgn.into_bits();
+        let id = self
+            .with_reserved_bits(bits & 0x20000 > 0)
+            .with_data_page_bits(bits & 0x10000 > 0)
+            .with_pdu_format_bits((bits >> 8) as u8);
+        match pgn.pdu_format() {
+            PduFormat::Pdu1(_) => id,
+            PduFormat::Pdu2(_) => id.with_pdu_specific_bits(bits as u8),
+        }
+    }
+
+    /// Set the destination address of PDU1.
+    ///
+    /// # Errors
+    /// - `Error::InvalidParam` if the PGN is PDU2 that has no destination address.
+    pub fn with_destination_address(self, address: u8) -> Result<Self, Error> {
+        match self.pgn().pdu_format() {
+            PduFormat::Pdu1(_) => Ok(self.with_pdu_specific_bits(address)),
+            PduFormat::Pdu2(v) => Err(Error::InvalidParam(format!("J1939 destination address of PDU2: {:02X}", v))),
+        }
+    }
+
+    /// Set the source address.
+    #[inline]
+    #[must_use]
+    pub fn with_source_address(self, address: u8) -> Self {
+        self.with_source_address_bits(address)
+    }
 }
 
+#[cfg(test)]
+mod tests {
+    use crate::can::j1939::{Conversion, DataField, DestinationAddress, J1939Id, J1939ParseError, NameField, PduAssignment, Pgn, SourceAddress};
+
+    #[test]
+    fn test_raw_parts() {
+        let id = J1939Id::from_raw_parts(7, false, 0xEA, 0x00, 0xF9).unwrap();
+        assert_eq!(id.into_bits(), 0x1CEA00F9);
+        assert!(J1939Id::from_raw_parts(8, false, 0xEA, 0x00, 0xF9).is_none());
+        assert!(J1939Id::from_raw_parts(0x1C, false, 0xEA, 0x00, 0xF9).is_none());
+    }
+
+    #[test]
+    fn test_builder() -> anyhow::Result<()> {
+        // (priority, pgn, da, sa, bits)
+        let cases = [
+            (3, 0xF004, None, 0x00, 0x0CF00400),
+            (6, 0xFEF1, None, 0x17, 0x18FEF117),
+            (6, 0xEA00, Some(0x00), 0xF9, 0x18EA00F9),
+            (7, 0xEC00, Some(0xFF), 0x80, 0x1CECFF80),
+            (6, 0x1EF00, Some(0x21), 0x80, 0x19EF2180),
+            (0, 0x0000, Some(0x00), 0xFE, 0x000000FE),
+        ];
+        for (priority, pgn, da, sa, bits) in cases {
+            let mut id = J1939Id::new()
+                .with_priority(priority)?
+                .with_pgn(Pgn::from_bits(pgn))
+                .with_source_address(sa);
+            if let Some(da) = da {
+                id = id.with_destination_address(da)?;
+            }
+            assert_eq!(id.into_bits(), bits);
+
+            let id = J1939Id::from_bits(bits);
+            assert_eq!(id.priority(), priority);
+            assert_eq!(id.pgn_bits() & if da.is_some() { !0xFF } else { !0 }, pgn);
+            assert_eq!(id.destination_address(), da.map_or(DestinationAddress::None, DestinationAddress::Some));
+            assert_eq!(id.source_address(), SourceAddress::Some(sa));
+        }
+
+        // the destination address is kept when changing the PDU1 PGN
+        let id = J1939Id::from_bits(0x18EA00F9).with_pgn(Pgn::from_bits(0xEC00));
+        assert_eq!(id.into_bits(), 0x18EC00F9);
+        assert!(J1939Id::new().with_priority(8).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_from_pgn() -> anyhow::Result<()> {
+        // PDU1
+        let id = J1939Id::from_pgn(Pgn::from_bits(0xEA00), 6, 0xF9, Some(0x00))?;
+        assert_eq!(id.into_bits(), 0x18EA00F9);
+        assert_eq!(id.parts(), (6, Pgn::from_bits(0xEA00), SourceAddress::Some(0xF9), DestinationAddress::Some(0x00)));
+        let id = J1939Id::from_pgn(Pgn::from_bits(0x1EF00), 3, 0x80, Some(0x21))?;
+        assert_eq!(id.into_bits(), 0x0DEF2180);
+        assert_eq!(id.parts(), (3, Pgn::from_bits(0x1EF00), SourceAddress::Some(0x80), DestinationAddress::Some(0x21)));
+        assert!(J1939Id::from_pgn(Pgn::from_bits(0xEA00), 6, 0xF9, None).is_err());
+
+        // PDU2
+        let id = J1939Id::from_pgn(Pgn::from_bits(0xF004), 3, 0x00, None)?;
+        assert_eq!(id.into_bits(), 0x0CF00400);
+        assert_eq!(id.parts(), (3, Pgn::from_bits(0xF004), SourceAddress::Some(0x00), DestinationAddress::None));
+        assert!(J1939Id::from_pgn(Pgn::from_bits(0xF004), 3, 0x00, Some(0xFF)).is_err());
+        assert!(J1939Id::from_pgn(Pgn::from_bits(0xF004), 8, 0x00, None).is_err());
+        assert!(J1939Id::from_bits(0x18FEF117).with_destination_address(0x00).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn test_normalized() {
+        // the Request to 0xF9 and to global
+        let (a, b) = (Pgn::from_bits(0xEAF9), Pgn::from_bits(0xEAFF));
+        assert_ne!(a, b);
+        assert!(a.same_parameter_group(&b));
+        assert_eq!(a.normalized(), Pgn::from_bits(0xEA00));
+        assert_eq!(b.pdu_assignment(), PduAssignment::Sae(0xEA00));
+        assert_eq!(Pgn::from_bits(0xEEFF).pdu_assignment(), PduAssignment::Sae(0xEE00));
+        assert_eq!(Pgn::from_bits(0xEF21).pdu_assignment(), PduAssignment::Manufacturer(0xEF00));
+
+        // PDU2 is kept
+        assert_eq!(Pgn::from_bits(0xFEF1).normalized(), Pgn::from_bits(0xFEF1));
+        assert!(!Pgn::from_bits(0xFEF1).same_parameter_group(&Pgn::from_bits(0xFEF2)));
+        assert!(!Pgn::from_bits(0xEA00).same_parameter_group(&Pgn::from_bits(0x1EA00)));
+
+        // the PGN of the destination specific id equals the catalogue PGN after normalization
+        let id = J1939Id::from_bits(0x18EA17F9);
+        assert_ne!(id.pgn(), Pgn::from_label("RQST").unwrap());
+        assert_eq!(id.pgn().normalized(), Pgn::from_label("RQST").unwrap());
+        assert_eq!(id.pgn().label(), Some("RQST"));
+    }
+
+    #[test]
+    fn test_parse_hex() {
+        let expect = J1939Id::from_bits(0x0CF00400);
+        for hex in ["0CF00400", "0x0CF00400", "0X0cf00400", " 0c:f0:04:00\n", "0C F0 04 00", "cf0_0400"] {
+            assert_eq!(hex.parse::<J1939Id>(), Ok(expect), "{}", hex);
+            assert_eq!(J1939Id::from_hex(hex), Some(expect), "{}", hex);
+            assert_eq!(J1939Id::try_from_hex(hex), Some(expect), "{}", hex);
+        }
+
+        assert_eq!("".parse::<J1939Id>(), Err(J1939ParseError::Empty));
+        assert_eq!(" 0x ".parse::<J1939Id>(), Err(J1939ParseError::Empty));
+        assert_eq!("0x0CG0".parse::<J1939Id>(), Err(J1939ParseError::InvalidCharacter { character: 'G', index: 4 }));
+        assert_eq!("1_0000_0000".parse::<J1939Id>(), Err(J1939ParseError::Overflow { bits: 32 }));
+        assert_eq!("20000000".parse::<J1939Id>(), Err(J1939ParseError::OutOfRange { value: 0x20000000, max: 0x1FFFFFFF }));
+        // the range is not checked
+        assert_eq!(J1939Id::from_hex("20000000"), Some(J1939Id::from_bits(0x20000000)));
+
+        assert_eq!("0x00FEF1".parse::<Pgn>(), Ok(Pgn::from_bits(0xFEF1)));
+        assert_eq!("40000".parse::<Pgn>(), Err(J1939ParseError::OutOfRange { value: 0x40000, max: 0x3FFFF }));
+        assert_eq!(Pgn::validate_bits(0x3FFFF), Ok(Pgn::from_bits(0x3FFFF)));
+
+        assert_eq!("F0-7D-7D-E0-15-00-F0-7D".parse::<DataField>(), Ok(DataField::from_bits(0xF07D7DE01500F07D)));
+        assert_eq!("0x80008100_28BFFFFF".parse::<NameField>(), Ok(NameField::from_bits(0x8000810028BFFFFF)));
+        assert_eq!("1_0000_0000_0000_0000".parse::<NameField>(), Err(J1939ParseError::Overflow { bits: 64 }));
+    }
+}
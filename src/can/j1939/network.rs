@@ -0,0 +1,314 @@
+//! The owners of the source addresses on the J1939 network.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+use crate::can::j1939::{AddressClaimMessage, AddressRegistry, J1939Id, NameField, SourceAddress, NULL_ADDRESS};
+use crate::device::{Channel, Listener};
+
+/// The default time without traffic before a node is regarded as disappeared.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(3);
+
+/// The node owning a source address.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub struct NetworkNode {
+    pub name: NameField,
+    /// The time of the last claim.
+    pub claimed: Instant,
+    /// The time of the last frame from the address.
+    pub last_seen: Instant,
+}
+
+/// The changes of [`J1939NetworkMap`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum NetworkEvent {
+    /// The free address is claimed.
+    Claimed { address: u8, name: NameField },
+    /// The address changes hands.
+    Changed { address: u8, old: NameField, new: NameField },
+    /// The owner claims another address or cannot claim.
+    Released { address: u8, name: NameField },
+    /// No traffic from the address in the timeout.
+    Disappeared { address: u8, name: NameField },
+}
+
+impl NetworkEvent {
+    /// The address of the event.
+    #[inline]
+    pub fn address(&self) -> u8 {
+        match *self {
+            Self::Claimed { address, .. }
+            | Self::Changed { address, .. }
+            | Self::Released { address, .. }
+            | Self::Disappeared { address, .. } => address,
+        }
+    }
+}
+
+pub trait NetworkEventListener: Send {
+    fn on_network_event(&mut self, event: NetworkEvent);
+}
+
+/// Track the NAME of each source address from the Address Claimed messages,
+/// register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan).
+#[derive(Clone)]
+pub struct J1939NetworkMap<C> {
+    pub(crate) channel: C,
+    pub(crate) timeout: Duration,
+    pub(crate) registry: AddressRegistry,
+    nodes: Arc<Mutex<HashMap<u8, NetworkNode>>>,
+    pub(crate) listener: Arc<Mutex<Box<dyn NetworkEventListener>>>,
+}
+
+impl<C: Channel> J1939NetworkMap<C> {
+    pub fn new(channel: C, listener: Box<dyn NetworkEventListener>) -> Self {
+        Self {
+            channel,
+            timeout: DEFAULT_TIMEOUT,
+            registry: Default::default(),
+            nodes: Default::default(),
+            listener: Arc::new(Mutex::new(listener)),
+        }
+    }
+
+    /// Set the time without traffic before a node is regarded as disappeared.
+    #[inline]
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Set the labels of the addresses used in the logs.
+    #[inline]
+    pub fn with_registry(mut self, registry: AddressRegistry) -> Self {
+        self.registry = registry;
+        self
+    }
+
+    #[inline]
+    pub fn registry(&self) -> &AddressRegistry {
+        &self.registry
+    }
+
+    /// The NAME owning the address.
+    pub fn name_of(&self, address: u8) -> Option<NameField> {
+        self.nodes.lock().ok()?
+            .get(&address)
+            .map(|v| v.name)
+    }
+
+    /// The address claimed by the NAME.
+    pub fn address_of(&self, name: NameField) -> Option<u8> {
+        self.nodes.lock().ok()?
+            .iter()
+            .find_map(|(k, v)| (v.name == name).then_some(*k))
+    }
+
+    /// The nodes of the network by address.
+    pub fn snapshot(&self) -> HashMap<u8, NetworkNode> {
+        match self.nodes.lock() {
+            Ok(nodes) => nodes.clone(),
+            Err(_) => Default::default(),
+        }
+    }
+
+    /// Remove the nodes without traffic in the timeout.
+    pub fn check_timeouts(&self) {
+        self.check_timeouts_at(Instant::now())
+    }
+
+    fn check_timeouts_at(&self, now: Instant) {
+        let events = match self.nodes.lock() {
+            Ok(mut nodes) => {
+                let mut events = Vec::new();
+                nodes.retain(|&address, node| {
+                    let alive = now.saturating_duration_since(node.last_seen) < self.timeout;
+                    if !alive {
+                        log::info!("J1939 - node {:016X} at {:02X}({}) disappeared",
+                            node.name.into_bits(), address, self.registry.label_of(address));
+                        events.push(NetworkEvent::Disappeared { address, name: node.name });
+                    }
+                    alive
+                });
+                events
+            },
+            Err(_) => return,
+        };
+
+        events.into_iter()
+            .for_each(|v| self.network_event(v));
+    }
+
+    fn on_address_claimed(&self, address: u8, name: NameField, now: Instant) {
+        let events = match self.nodes.lock() {
+            Ok(mut nodes) => {
+                let mut events = Vec::new();
+                let moved = nodes.iter()
+                    .find_map(|(k, v)| (v.name == name && *k != address).then_some(*k));
+                if let Some(old) = moved {
+                    nodes.remove(&old);
+                    events.push(NetworkEvent::Released { address: old, name });
+                }
+
+                if address != NULL_ADDRESS {
+                    let node = NetworkNode { name, claimed: now, last_seen: now };
+                    match nodes.insert(address, node) {
+                        Some(old) if old.name != name =>
+                            events.push(NetworkEvent::Changed { address, old: old.name, new: name }),
+                        Some(_) => {},
+                        None => events.push(NetworkEvent::Claimed { address, name }),
+                    }
+                }
+                events
+            },
+            Err(_) => return,
+        };
+
+        events.into_iter()
+            .for_each(|v| self.network_event(v));
+    }
+
+    #[inline]
+    fn network_event(&self, event: NetworkEvent) {
+        log::debug!("J1939 - network changed at {}: {:?}", self.registry.label_of(event.address()), event);
+        match self.listener.lock() {
+            Ok(mut listener) => listener.on_network_event(event),
+            Err(_) => log::warn!("J1939 - network listener error"),
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for J1939NetworkMap<C>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        let now = Instant::now();
+        for frame in frames {
+            if !frame.is_extended() {
+                continue;
+            }
+
+            let id = J1939Id::from(frame.id());
+            if let Some(AddressClaimMessage::AddressClaimed { address, name }) = AddressClaimMessage::decode(id, frame.data()) {
+                self.on_address_claimed(address, name, now);
+            }
+            else if let SourceAddress::Some(address) = id.source_address() {
+                if let Ok(mut nodes) = self.nodes.lock() {
+                    if let Some(node) = nodes.get_mut(&address) {
+                        node.last_seen = now;
+                    }
+                }
+            }
+        }
+
+        self.check_timeouts_at(now);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::thread::sleep;
+    use std::time::Duration;
+    use crate::can::driver::MOCK_CHANNEL;
+    use crate::can::frame::FrameMut;
+    use crate::can::identifier::Id;
+    use crate::can::j1939::{AddressClaimMessage, NameField, NULL_ADDRESS};
+    use crate::can::message::CanMessage;
+    use crate::device::Listener;
+    use super::{J1939NetworkMap, NetworkEvent};
+
+    type Collector = crate::can::testing::Collector<NetworkEvent>;
+
+    fn claimed(address: u8, name: u64) -> CanMessage {
+        let (id, data) = AddressClaimMessage::AddressClaimed { address, name: NameField::from_bits(name) }.encode();
+        let mut frame = CanMessage::new(id, &data).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        frame
+    }
+
+    fn data(address: u8) -> CanMessage {
+        let mut frame = CanMessage::new(Id::Extended(0x0CF00400 | address as u32), &[0; 8]).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        frame
+    }
+
+    fn receive(map: &mut J1939NetworkMap<String>, frames: &[CanMessage]) {
+        Listener::<String, u32, CanMessage>::on_frame_received(map, MOCK_CHANNEL.into(), frames);
+    }
+
+    #[test]
+    fn test_claims() {
+        let collector = Collector::default();
+        let mut map = J1939NetworkMap::new(MOCK_CHANNEL.to_string(), Box::new(collector.clone()));
+        let (a, b, c) = (NameField::from_bits(0x1000), NameField::from_bits(0x2000), NameField::from_bits(0x3000));
+
+        receive(&mut map, &[claimed(0x80, 0x2000), claimed(0x81, 0x3000), claimed(0x80, 0x2000)]);
+        assert_eq!(collector.take(), [
+            NetworkEvent::Claimed { address: 0x80, name: b },
+            NetworkEvent::Claimed { address: 0x81, name: c },
+        ]);
+        assert_eq!(map.name_of(0x80), Some(b));
+        assert_eq!(map.address_of(c), Some(0x81));
+
+        // the lower NAME takes 0x80, the loser moves to 0x82
+        receive(&mut map, &[claimed(0x80, 0x1000), claimed(0x82, 0x2000)]);
+        assert_eq!(collector.take(), [
+            NetworkEvent::Changed { address: 0x80, old: b, new: a },
+            NetworkEvent::Claimed { address: 0x82, name: b },
+        ]);
+
+        // cannot claim
+        receive(&mut map, &[claimed(0x81, 0x1000), claimed(NULL_ADDRESS, 0x3000)]);
+        assert_eq!(collector.take(), [
+            NetworkEvent::Released { address: 0x80, name: a },
+            NetworkEvent::Changed { address: 0x81, old: c, new: a },
+        ]);
+        let snapshot = map.snapshot();
+        assert_eq!(snapshot.len(), 2);
+        assert_eq!(snapshot[&0x81].name, a);
+        assert_eq!(snapshot[&0x82].name, b);
+        assert_eq!(map.address_of(c), None);
+        assert_eq!(map.name_of(NULL_ADDRESS), None);
+    }
+
+    #[test]
+    fn test_disappeared() {
+        let collector = Collector::default();
+        let mut map = J1939NetworkMap::new(MOCK_CHANNEL.to_string(), Box::new(collector.clone()))
+            .with_timeout(Duration::from_millis(100));
+
+        receive(&mut map, &[claimed(0x80, 0x1000), claimed(0x81, 0x2000)]);
+        collector.take();
+
+        sleep(Duration::from_millis(60));
+        receive(&mut map, &[data(0x80)]);
+        assert!(collector.take().is_empty());
+
+        sleep(Duration::from_millis(60));
+        receive(&mut map, &[data(0x80)]);
+        assert_eq!(collector.take(), [NetworkEvent::Disappeared { address: 0x81, name: NameField::from_bits(0x2000) }]);
+        assert_eq!(map.name_of(0x80), Some(NameField::from_bits(0x1000)));
+
+        sleep(Duration::from_millis(110));
+        map.check_timeouts();
+        assert_eq!(collector.take(), [NetworkEvent::Disappeared { address: 0x80, name: NameField::from_bits(0x1000) }]);
+        assert!(map.snapshot().is_empty());
+    }
+}
@@ -1,6 +1,9 @@
-use std::fmt::format;
+use std::fmt::{format, Display, Formatter};
 use bitfield_struct::bitfield;
-use crate::can::j1939::Conversion;
+use crate::ByteOrder;
+use std::str::FromStr;
+use crate::can::j1939::{hex_bits, Conversion, J1939ParseError};
+use crate::error::Error;
 
 /// Bitfield representing an 8-byte data field.
 ///
@@ -49,23 +52,25 @@ impl Conversion for DataField {
     /// Creates a new [`DataField`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn from_hex(hex_str: &str) -> Option<Self> {
-        let value = u64::from_str_radix(hex_str, 16).ok()?;
-
-        Some(Self(value))
+        hex_bits(hex_str).ok().map(Self)
     }
 
     /// Creates a new [`DataField`] bitfield from a 64-bit integer.
     #[inline]
     fn try_from_bits(bits: u64) -> Option<Self> {
-        Some(Self(bits))
+        Self::validate_bits(bits).ok()
     }
 
     /// Creates a new [`DataField`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn try_from_hex(hex_str: &str) -> Option<Self> {
-        let value = u64::from_str_radix(hex_str, 16).ok()?;
+        Self::parse_hex(hex_str).ok()
+    }
 
-        Some(Self(value))
+    /// All the 64-bit integers are valid.
+    #[inline]
+    fn validate_bits(bits: u64) -> Result<Self, J1939ParseError> {
+        Ok(Self(bits))
     }
 
     /// Creates a new 64-bit integer from the [`DataField`] bitfield.
@@ -81,6 +86,15 @@ impl Conversion for DataField {
     }
 }
 
+impl FromStr for DataField {
+    type Err = J1939ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_hex(s)
+    }
+}
+
 macro_rules! field_x {
     ($($num:tt),*) => {
         paste::paste! {
@@ -126,6 +140,224 @@ impl DataField {
     pub const fn to_be(&self) -> Self {
         Self(self.into_bits().to_be())
     }
+
+    /// Extract `length` bits from `start_bit`.
+    ///
+    /// - [`ByteOrder::Little`]: the bits are numbered from the least significant bit of byte 0,
+    ///   `start_bit` is the least significant bit of the value(J1939 and Intel layout).
+    /// - [`ByteOrder::Big`]: the bits are numbered from the most significant bit of byte 0,
+    ///   `start_bit` is the most significant bit of the value.
+    ///
+    /// # Returns
+    /// - `None` if the range is empty or out of the 64 bits.
+    #[must_use]
+    pub fn bits(&self, start_bit: u16, length: u8, order: ByteOrder) -> Option<u64> {
+        let (start, length) = (start_bit as u32, length as u32);
+        if length == 0 || start + length > u64::BITS {
+            return None;
+        }
+
+        let mask = u64::MAX >> (u64::BITS - length);
+        let value = match Self::resolve(order) {
+            ByteOrder::Big => self.into_bits() >> (u64::BITS - start - length),
+            _ => u64::from_le_bytes(self.to_be_bytes()) >> start,
+        };
+
+        Some(value & mask)
+    }
+
+    /// Replace `length` bits from `start_bit` with the low bits of `value`, the bits are numbered
+    /// as [`DataField::bits`].
+    ///
+    /// # Returns
+    /// - `None` if the range is empty or out of the 64 bits.
+    #[must_use]
+    pub fn with_bits(self, start_bit: u16, length: u8, order: ByteOrder, value: u64) -> Option<Self> {
+        let (start, length) = (start_bit as u32, length as u32);
+        if length == 0 || start + length > u64::BITS {
+            return None;
+        }
+
+        let mask = u64::MAX >> (u64::BITS - length);
+        let value = value & mask;
+        match Self::resolve(order) {
+            ByteOrder::Big => {
+                let shift = u64::BITS - start - length;
+                Some(Self((self.into_bits() & !(mask << shift)) | (value << shift)))
+            },
+            _ => {
+                let bits = u64::from_le_bytes(self.to_be_bytes());
+                let bits = (bits & !(mask << start)) | (value << start);
+                Some(Self(u64::from_be_bytes(bits.to_le_bytes())))
+            },
+        }
+    }
+
+    /// Read the `u16` from the bytes starting at `byte_index`.
+    #[must_use]
+    pub fn u16_at(&self, byte_index: usize, order: ByteOrder) -> Option<u16> {
+        let bytes = self.to_be_bytes();
+        let bytes: [u8; 2] = bytes.get(byte_index..byte_index + 2)?.try_into().ok()?;
+        match Self::resolve(order) {
+            ByteOrder::Big => Some(u16::from_be_bytes(bytes)),
+            _ => Some(u16::from_le_bytes(bytes)),
+        }
+    }
+
+    /// Read the `u32` from the bytes starting at `byte_index`.
+    #[must_use]
+    pub fn u32_at(&self, byte_index: usize, order: ByteOrder) -> Option<u32> {
+        let bytes = self.to_be_bytes();
+        let bytes: [u8; 4] = bytes.get(byte_index..byte_index + 4)?.try_into().ok()?;
+        match Self::resolve(order) {
+            ByteOrder::Big => Some(u32::from_be_bytes(bytes)),
+            _ => Some(u32::from_le_bytes(bytes)),
+        }
+    }
+
+    /// Decode the SPN(Suspect Parameter Number) with the J1939-71 ranges.
+    ///
+    /// # Returns
+    /// - `SpnValue::NotAvailable` if the bits of the SPN are out of the data field.
+    #[must_use]
+    pub fn spn(&self, def: SpnDef) -> SpnValue {
+        match self.bits(def.start_bit, def.length, ByteOrder::Little) {
+            Some(raw) => SpnValue::from_raw(raw, def),
+            None => SpnValue::NotAvailable,
+        }
+    }
+
+    /// Encode the SPN with the J1939-71 ranges, the data field is unchanged if the SPN is out of it.
+    #[must_use]
+    pub fn with_spn(self, def: SpnDef, value: SpnValue) -> Self {
+        self.with_bits(def.start_bit, def.length, ByteOrder::Little, value.to_raw(def))
+            .unwrap_or(self)
+    }
+
+    #[inline]
+    fn resolve(order: ByteOrder) -> ByteOrder {
+        match order {
+            ByteOrder::Native if cfg!(target_endian = "big") => ByteOrder::Big,
+            ByteOrder::Native => ByteOrder::Little,
+            v => v,
+        }
+    }
+}
+
+/// The definition of an SPN(Suspect Parameter Number) in the data field.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct SpnDef {
+    /// The least significant bit, numbered from the least significant bit of byte 0.
+    pub start_bit: u16,
+    /// The bit length.
+    pub length: u8,
+    /// The scaling of each bit.
+    pub resolution: f64,
+    pub offset: f64,
+}
+
+impl SpnDef {
+    #[inline]
+    pub const fn new(start_bit: u16, length: u8, resolution: f64, offset: f64) -> Self {
+        Self { start_bit, length, resolution, offset }
+    }
+}
+
+/// The value of an SPN.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SpnValue {
+    /// The scaled value.
+    Valid(f64),
+    /// The parameter is not available.
+    NotAvailable,
+    /// The parameter reports an error.
+    Error,
+    /// The raw value is in the reserved range.
+    Reserved,
+}
+
+impl SpnValue {
+    /// Classify the `raw` value by the J1939-71 ranges.
+    ///
+    /// | Length | Valid          | Reserved         | Error        | Not available |
+    /// |--------|----------------|------------------|--------------|---------------|
+    /// | 2 bits | 0, 1           |                  | 2            | 3             |
+    /// | 4 bits | 0x0..=0xA      | 0xB..=0xD        | 0xE          | 0xF           |
+    /// | 8+ bits| top byte ..=0xFA | top byte 0xFB..=0xFD | top byte 0xFE | top byte 0xFF |
+    ///
+    /// The other lengths below 8 bits use the maximum for not available and the maximum - 1 for error.
+    pub fn from_raw(raw: u64, def: SpnDef) -> Self {
+        let length = def.length as u32;
+        let max = u64::MAX >> (u64::BITS - length.clamp(1, u64::BITS));
+        let state = match length {
+            1 => None,
+            4 => match raw {
+                0xB..=0xD => Some(Self::Reserved),
+                0xE => Some(Self::Error),
+                0xF => Some(Self::NotAvailable),
+                _ => None,
+            },
+            8.. => match raw >> (length - 8) {
+                0xFB..=0xFD => Some(Self::Reserved),
+                0xFE => Some(Self::Error),
+                0xFF => Some(Self::NotAvailable),
+                _ => None,
+            },
+            _ if raw == max => Some(Self::NotAvailable),
+            _ if raw == max - 1 => Some(Self::Error),
+            _ => None,
+        };
+
+        state.unwrap_or(Self::Valid(raw as f64 * def.resolution + def.offset))
+    }
+
+    /// The raw value by the J1939-71 ranges, the valid value is rounded and saturated.
+    pub fn to_raw(&self, def: SpnDef) -> u64 {
+        let length = def.length as u32;
+        let max = u64::MAX >> (u64::BITS - length.clamp(1, u64::BITS));
+        match self {
+            Self::Valid(v) => {
+                let raw = ((v - def.offset) / def.resolution).round().max(0.) as u64;
+                match length {
+                    1 => raw.min(max),
+                    4 => raw.min(0xA),
+                    8.. => raw.min((0xFB << (length - 8)) - 1),
+                    _ => raw.min(max - 2),
+                }
+            },
+            Self::NotAvailable => max,
+            Self::Error => match length {
+                1 => max,
+                4 => 0xE,
+                8.. => 0xFE << (length - 8),
+                _ => max - 1,
+            },
+            Self::Reserved => match length {
+                4 => 0xB,
+                8.. => 0xFB << (length - 8),
+                _ => max,
+            },
+        }
+    }
+
+    #[inline]
+    pub fn value(&self) -> Option<f64> {
+        match self {
+            Self::Valid(v) => Some(*v),
+            _ => None,
+        }
+    }
+}
+
+impl Display for SpnValue {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Valid(v) => write!(f, "{}", v),
+            Self::NotAvailable => write!(f, "N/A"),
+            Self::Error => write!(f, "error"),
+            Self::Reserved => write!(f, "reserved"),
+        }
+    }
 }
 
 /// Represents a Name in the SAE J1939 protocol.
@@ -146,6 +378,8 @@ impl DataField {
 /// | ECU instance bits                 | 3           |
 /// | Manufacturer code bits            | 11          |
 /// | Identity number bits              | 21          |
+///
+/// NAMEs are ordered numerically as J1939-81 address arbitration, the lower NAME wins.
 #[bitfield(u64, order = Msb)]
 #[derive(PartialEq, Eq, PartialOrd, Ord)]
 pub struct NameField {
@@ -183,23 +417,25 @@ impl Conversion for NameField {
     /// Creates a new [`NameField`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn from_hex(hex_str: &str) -> Option<Self> {
-        let bits = u64::from_str_radix(hex_str, 16).ok()?;
-
-        Some(Self(bits))
+        hex_bits(hex_str).ok().map(Self)
     }
 
     /// Creates a new [`NameField`] bitfield from a 64-bit integer.
     #[inline]
     fn try_from_bits(bits: u64) -> Option<Self> {
-        Some(Self(bits))
+        Self::validate_bits(bits).ok()
     }
 
     /// Creates a new [`NameField`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn try_from_hex(hex_str: &str) -> Option<Self> {
-        let value = u64::from_str_radix(hex_str, 16).ok()?;
+        Self::parse_hex(hex_str).ok()
+    }
 
-        Some(Self(value))
+    /// All the 64-bit integers are valid.
+    #[inline]
+    fn validate_bits(bits: u64) -> Result<Self, J1939ParseError> {
+        Ok(Self(bits))
     }
 
     /// Creates a new 64-bit integer from the [`NameField`] bitfield.
@@ -215,6 +451,15 @@ impl Conversion for NameField {
     }
 }
 
+impl FromStr for NameField {
+    type Err = J1939ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_hex(s)
+    }
+}
+
 impl NameField {
 
     /// Indicates whether the ECU/CA can negotiate an address (true = yes; false = no).
@@ -282,19 +527,158 @@ impl NameField {
     pub const fn identity_number(&self) -> u32 {
         self.identity_number_bits()
     }
+
+    /// Constructs a NAME from its parts, the reserved bit is zero.
+    ///
+    /// # Errors
+    /// - `Error::InvalidParam` if any part exceeds the width of its field.
+    #[allow(clippy::too_many_arguments)]
+    pub fn from_raw_parts(
+        arbitrary_address: bool,
+        industry_group: u8,
+        vehicle_system_instance: u8,
+        vehicle_system: u8,
+        function: u8,
+        function_instance: u8,
+        ecu_instance: u8,
+        manufacturer_code: u16,
+        identity_number: u32,
+    ) -> Result<Self, Error> {
+        let check = |name: &str, value: u32, bits: u8| {
+            match value >> bits {
+                0 => Ok(()),
+                _ => Err(Error::InvalidParam(format!("NAME {} {} exceeds {} bits", name, value, bits))),
+            }
+        };
+        check("industry group", industry_group as u32, 3)?;
+        check("vehicle system instance", vehicle_system_instance as u32, 4)?;
+        check("vehicle system", vehicle_system as u32, 7)?;
+        check("function instance", function_instance as u32, 5)?;
+        check("ECU instance", ecu_instance as u32, 3)?;
+        check("manufacturer code", manufacturer_code as u32, 11)?;
+        check("identity number", identity_number, 21)?;
+
+        Ok(Self::new()
+            .with_arbitrary_address_bits(arbitrary_address)
+            .with_industry_group_bits(industry_group)
+            .with_vehicle_system_instance_bits(vehicle_system_instance)
+            .with_vehicle_system_bits(vehicle_system)
+            .with_function_bits(function)
+            .with_function_instance_bits(function_instance)
+            .with_ecu_instance_bits(ecu_instance)
+            .with_manufacturer_code_bits(manufacturer_code)
+            .with_identity_number_bits(identity_number))
+    }
+
+    /// Whether the ECU/CA can select an address from the arbitrary range when it loses the claim.
+    #[inline]
+    #[must_use]
+    pub const fn is_self_configurable(&self) -> bool {
+        self.arbitrary_address_bits()
+    }
+
+    /// Whether the function is defined by the industry group(128..=253) instead of globally(0..=127).
+    #[inline]
+    #[must_use]
+    pub const fn is_industry_group_function(&self) -> bool {
+        matches!(self.function_bits(), 128..=253)
+    }
+
+    /// The name of the industry group.
+    #[must_use]
+    pub const fn industry_group_name(&self) -> &'static str {
+        match self.industry_group_bits() {
+            0 => "Global",
+            1 => "On-Highway",
+            2 => "Agricultural and Forestry",
+            3 => "Construction",
+            4 => "Marine",
+            5 => "Industrial-Process Control-Stationary",
+            _ => "Reserved",
+        }
+    }
+}
+
+impl Display for NameField {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{:016X} (arbitrary address: {}, industry group: {}({}), vehicle system: {}/{}, \
+            function: {}/{}, ECU instance: {}, manufacturer code: {}, identity number: {})",
+               self.into_bits(),
+               self.arbitrary_address(),
+               self.industry_group_name(),
+               self.industry_group(),
+               self.vehicle_system(),
+               self.vehicle_system_instance(),
+               self.function(),
+               self.function_instance(),
+               self.ecu_instance(),
+               self.manufacturer_code(),
+               self.identity_number(),
+        )
+    }
 }
 
 /// Represents a Protocol Data Unit (PDU) in the context of Controller Area Network (CAN).
-#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
 pub enum Pdu {
     NameField(NameField),
-    DataFiled(DataField),
+    DataField(DataField),
+    /// The payload not in 8 bytes, such as the messages reassembled by the transport protocol
+    /// and the CAN FD frames.
+    Bytes(Vec<u8>),
+}
+
+impl Pdu {
+    #[deprecated(note = "use `Pdu::DataField` instead")]
+    #[allow(non_snake_case)]
+    #[inline]
+    pub fn DataFiled(data: DataField) -> Self {
+        Self::DataField(data)
+    }
+
+    /// Returns the payload bytes in the transmission order.
+    #[must_use]
+    pub fn to_bytes(&self) -> Vec<u8> {
+        match self {
+            Self::NameField(name) => name.into_bits().to_le_bytes().to_vec(),
+            Self::DataField(data) => data.to_be_bytes().to_vec(),
+            Self::Bytes(bytes) => bytes.clone(),
+        }
+    }
+
+    /// Returns the length of the payload.
+    #[inline]
+    #[must_use]
+    pub fn len(&self) -> usize {
+        match self {
+            Self::NameField(_) | Self::DataField(_) => 8,
+            Self::Bytes(bytes) => bytes.len(),
+        }
+    }
+
+    #[inline]
+    #[must_use]
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl Display for Pdu {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::NameField(name) => write!(f, "{}", name),
+            Self::DataField(data) => write!(f, "{}", hex::encode_upper(data.to_be_bytes())),
+            Self::Bytes(bytes) => write!(f, "{}", hex::encode_upper(bytes)),
+        }
+    }
 }
 
 #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PduType {
     Name,
     Data,
+    /// The payload of any length.
+    Bytes,
 }
 
 #[cfg(test)]
@@ -326,6 +710,69 @@ mod data_tests {
         Ok(())
     }
 
+    #[test]
+    fn test_data_bits() {
+        let data = DataField::from_bits(u64::from_be_bytes([0x12, 0x34, 0x56, 0x78, 0x9A, 0xBC, 0xDE, 0xF0]));
+
+        assert_eq!(data.bits(0, 8, ByteOrder::Little), Some(0x12));
+        assert_eq!(data.bits(4, 8, ByteOrder::Little), Some(0x41));
+        assert_eq!(data.bits(8, 16, ByteOrder::Little), Some(0x5634));
+        assert_eq!(data.bits(0, 64, ByteOrder::Little), Some(0xF0DEBC9A78563412));
+        assert_eq!(data.bits(0, 8, ByteOrder::Big), Some(0x12));
+        assert_eq!(data.bits(4, 8, ByteOrder::Big), Some(0x23));
+        assert_eq!(data.bits(8, 16, ByteOrder::Big), Some(0x3456));
+        assert_eq!(data.bits(60, 4, ByteOrder::Big), Some(0x0));
+        assert_eq!(data.bits(60, 5, ByteOrder::Little), None);
+        assert_eq!(data.bits(0, 0, ByteOrder::Little), None);
+
+        assert_eq!(data.u16_at(1, ByteOrder::Little), Some(0x5634));
+        assert_eq!(data.u16_at(1, ByteOrder::Big), Some(0x3456));
+        assert_eq!(data.u16_at(7, ByteOrder::Big), None);
+        assert_eq!(data.u32_at(4, ByteOrder::Little), Some(0xF0DEBC9A));
+        assert_eq!(data.u32_at(4, ByteOrder::Big), Some(0x9ABCDEF0));
+        assert_eq!(data.u32_at(5, ByteOrder::Big), None);
+        assert_eq!(data.u32_at(0, ByteOrder::Native), Some(u32::from_ne_bytes([0x12, 0x34, 0x56, 0x78])));
+    }
+
+    #[test]
+    fn test_spn() {
+        // SPN 190 engine speed of EEC1: 18F00400 F0 7D 7D E0 15 00 F0 7D
+        const ENGINE_SPEED: SpnDef = SpnDef::new(24, 16, 0.125, 0.);
+        // SPN 513 actual engine percent torque: 1 %/bit, -125 % offset
+        const ENGINE_TORQUE: SpnDef = SpnDef::new(16, 8, 1., -125.);
+        let eec1 = DataField::from_hex("F07D7DE01500F07D").unwrap();
+        assert_eq!(eec1.spn(ENGINE_SPEED), SpnValue::Valid(700.));
+        assert_eq!(eec1.spn(ENGINE_TORQUE), SpnValue::Valid(0.));
+
+        // SPN 84 wheel-based vehicle speed of CCVS: 18FEF100 F3 00 32 C0 00 00 00 FF
+        const VEHICLE_SPEED: SpnDef = SpnDef::new(8, 16, 1. / 256., 0.);
+        // SPN 69 two speed axle switch and SPN 70 parking brake switch
+        const AXLE_SWITCH: SpnDef = SpnDef::new(0, 2, 1., 0.);
+        const PARKING_BRAKE: SpnDef = SpnDef::new(2, 2, 1., 0.);
+        let ccvs = DataField::from_hex("F30032C0000000FF").unwrap();
+        assert_eq!(ccvs.spn(VEHICLE_SPEED), SpnValue::Valid(50.));
+        assert_eq!(ccvs.spn(AXLE_SWITCH), SpnValue::NotAvailable);
+        assert_eq!(ccvs.spn(PARKING_BRAKE), SpnValue::Valid(0.));
+        assert_eq!(ccvs.spn(SpnDef::new(56, 8, 1., 0.)), SpnValue::NotAvailable);
+        assert_eq!(ccvs.spn(SpnDef::new(60, 8, 1., 0.)), SpnValue::NotAvailable);
+
+        let ccvs = DataField::from_hex("F3FFFEC0000000FF").unwrap();
+        assert_eq!(ccvs.spn(VEHICLE_SPEED), SpnValue::Error);
+        let ccvs = DataField::from_hex("F3FFFCC0000000FF").unwrap();
+        assert_eq!(ccvs.spn(VEHICLE_SPEED), SpnValue::Reserved);
+        assert_eq!(ccvs.spn(VEHICLE_SPEED).value(), None);
+        let ccvs = DataField::from_hex("F300FAC0000000FF").unwrap();
+        assert_eq!(ccvs.spn(VEHICLE_SPEED).value(), Some(250.));
+
+        let def = SpnDef::new(0, 4, 1., 0.);
+        assert_eq!(SpnValue::from_raw(0xA, def), SpnValue::Valid(10.));
+        assert_eq!(SpnValue::from_raw(0xC, def), SpnValue::Reserved);
+        assert_eq!(SpnValue::from_raw(0xE, def), SpnValue::Error);
+        assert_eq!(SpnValue::from_raw(0x2, SpnDef::new(0, 2, 1., 0.)), SpnValue::Error);
+        assert_eq!(SpnValue::from_raw(0x7, SpnDef::new(0, 3, 1., 0.)), SpnValue::NotAvailable);
+        assert_eq!(SpnValue::from_raw(0x1, SpnDef::new(0, 1, 1., 0.)), SpnValue::Valid(1.));
+    }
+
     #[test]
     fn test_name_bitfield() {
         let name_a = NameField::new()
@@ -344,5 +791,41 @@ mod data_tests {
         let name_a_bytes = name_a.into_bits().to_le_bytes();
 
         assert_eq!(bytes_a, name_a_bytes);
+        assert_eq!(NameField::from_raw_parts(true, 0, 0x5, 0x6, 0x5, 0x2, 0x1, 0x122, 0xB0309).unwrap(), name_a);
+    }
+
+    #[test]
+    fn test_name_parts() -> anyhow::Result<()> {
+        // an on-highway engine controller
+        let engine = NameField::from_raw_parts(true, 1, 0, 0, 0, 0, 0, 10, 123456)?;
+        assert_eq!(engine.into_bits(), 0x9000_0000_0141_E240);
+        assert!(engine.is_self_configurable());
+        assert!(!engine.is_industry_group_function());
+        assert_eq!(engine.to_string(), "900000000141E240 (arbitrary address: true, industry group: On-Highway(1), \
+            vehicle system: 0/0, function: 0/0, ECU instance: 0, manufacturer code: 10, identity number: 123456)");
+
+        // an off-board service tool
+        let tool = NameField::from_hex("8000810028BFFFFF").unwrap();
+        assert_eq!(tool.industry_group_name(), "Global");
+        assert_eq!(tool.function(), 0x81);
+        assert!(tool.is_industry_group_function());
+        assert_eq!(tool.manufacturer_code(), 0x145);
+        assert_eq!(tool.identity_number(), 0x1FFFFF);
+        assert_eq!(NameField::from_raw_parts(true, 0, 0, 0, 0x81, 0, 0, 0x145, 0x1FFFFF)?, tool);
+
+        // the lower NAME wins the arbitration
+        assert!(tool < engine);
+        let fixed = NameField::from_raw_parts(false, 1, 0, 0, 0, 0, 0, 10, 123456)?;
+        assert!(fixed < engine);
+        assert_eq!(fixed.max(engine), engine);
+
+        assert!(NameField::from_raw_parts(true, 8, 0, 0, 0, 0, 0, 0, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 16, 0, 0, 0, 0, 0, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 0, 128, 0, 0, 0, 0, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 0, 0, 0, 32, 0, 0, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 0, 0, 0, 0, 8, 0, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 0, 0, 0, 0, 0, 0x800, 0).is_err());
+        assert!(NameField::from_raw_parts(true, 0, 0, 0, 0, 0, 0, 0, 0x200000).is_err());
+        Ok(())
     }
 }
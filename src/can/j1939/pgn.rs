@@ -1,7 +1,9 @@
-use std::fmt::format;
+use std::fmt::{format, Display, Formatter};
 use bitfield_struct::bitfield;
-use crate::can::j1939::Conversion;
+use std::str::FromStr;
+use crate::can::j1939::{hex_bits, Conversion, J1939ParseError};
 use crate::can::j1939::{DestinationAddress, J1939Id};
+use crate::can::j1939::catalog::PGN_CATALOG;
 
 /// Represents the assignment typeof a Protocol Data Unit (PDU).
 #[derive(Debug, Clone, Copy, PartialEq, Eq)]
@@ -86,26 +88,28 @@ impl Conversion for Pgn {
     /// Creates a new [`Pgn`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn from_hex(hex_str: &str) -> Option<Self> {
-        let bits = u32::from_str_radix(hex_str, 16).ok()?;
-
-        Some(Self(bits))
+        hex_bits(hex_str).ok().map(Self)
     }
 
     /// Creates a new [`Pgn`] bitfield from a 32-bit integer.
     #[inline]
     fn try_from_bits(bits: u32) -> Option<Self> {
-        match bits {
-            0..=0x3FFFF => Some(Self(bits)),
-            _ => None,
-        }
+        Self::validate_bits(bits).ok()
     }
 
     /// Creates a new [`Pgn`] bitfield from a base-16 (hex) string slice.
     #[inline]
     fn try_from_hex(hex_str: &str) -> Option<Self> {
-        let value = u32::from_str_radix(hex_str, 16).ok()?;
+        Self::parse_hex(hex_str).ok()
+    }
 
-        Self::try_from_bits(value)
+    /// Creates a new [`Pgn`] bitfield from a 32-bit integer, the PGN is 18 bits.
+    #[inline]
+    fn validate_bits(bits: u32) -> Result<Self, J1939ParseError> {
+        match bits {
+            0..=0x3FFFF => Ok(Self(bits)),
+            _ => Err(J1939ParseError::OutOfRange { value: bits as u64, max: 0x3FFFF }),
+        }
     }
 
     /// Creates a new 32-bit integer from the [`Pgn`] bitfield.
@@ -121,6 +125,15 @@ impl Conversion for Pgn {
     }
 }
 
+impl FromStr for Pgn {
+    type Err = J1939ParseError;
+
+    #[inline]
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse_hex(s)
+    }
+}
+
 impl Pgn {
     /// Returns the PDU format based on the parsed bits.
     ///
@@ -200,7 +213,7 @@ impl Pgn {
         }
     }
 
-    /// Determines the PDU assignment based on the parsed bits.
+    /// Determines the PDU assignment based on the parsed bits, the destination address of PDU1 is ignored.
     ///
     /// # Returns
     /// - `PduAssignment::Sae(bits)` for known SAE-defined PDU assignments.
@@ -208,18 +221,78 @@ impl Pgn {
     /// - `PduAssignment::Unknown(bits)` for unrecognized PDU assignments.
     #[must_use]
     pub fn pdu_assignment(&self) -> PduAssignment {
-        match self.into_bits() {
+        match self.normalized().into_bits() {
             0x0000_0000..=0x0000_EE00
             | 0x0000_F000..=0x0000_FEFF
             | 0x0001_0000..=0x0001_EE00
-            | 0x0001_F000..=0x0001_FEFF => PduAssignment::Sae(self.into_bits()),
+            | 0x0001_F000..=0x0001_FEFF => PduAssignment::Sae(self.normalized().into_bits()),
 
             0x0000_EF00 | 0x0000_FF00..=0x0000_FFFF | 0x0001_EF00 | 0x0001_FF00..=0x0001_FFFF => {
-                PduAssignment::Manufacturer(self.into_bits())
+                PduAssignment::Manufacturer(self.normalized().into_bits())
             }
             p => PduAssignment::Unknown(p),
         }
     }
+
+    /// Returns the PGN without the destination address.
+    ///
+    /// # Returns
+    /// - The PGN with zero PDU specific bits if the PDU format is `Pdu1`.
+    /// - The PGN itself if the PDU format is `Pdu2`.
+    #[must_use]
+    pub const fn normalized(&self) -> Pgn {
+        match self.pdu_format() {
+            PduFormat::Pdu1(_) => self.with_pdu_specific_bits(0),
+            PduFormat::Pdu2(_) => *self,
+        }
+    }
+
+    /// Checks if both PGNs are the same parameter group, the destination addresses of PDU1 are ignored.
+    #[inline]
+    #[must_use]
+    pub const fn same_parameter_group(&self, other: &Pgn) -> bool {
+        self.normalized().0 == other.normalized().0
+    }
+
+    /// Returns the catalogue entry, the destination address of PDU1 is ignored.
+    fn catalog(&self) -> Option<&'static (u32, &'static str, &'static str)> {
+        match self.pdu_assignment() {
+            PduAssignment::Sae(bits) => PGN_CATALOG.iter().find(|(v, _, _)| *v == bits),
+            _ => None,
+        }
+    }
+
+    /// Returns the acronym of the SAE assigned PGN, such as `EEC1`.
+    ///
+    /// # Returns
+    /// - `None` for the proprietary and unknown PGNs.
+    #[must_use]
+    pub fn label(&self) -> Option<&'static str> {
+        self.catalog().map(|(_, label, _)| *label)
+    }
+
+    /// Returns the description of the SAE assigned PGN, such as `Electronic Engine Controller 1`.
+    #[must_use]
+    pub fn description(&self) -> Option<&'static str> {
+        self.catalog().map(|(_, _, description)| *description)
+    }
+
+    /// Lookup the PGN by the acronym, case-insensitive.
+    #[must_use]
+    pub fn from_label(label: &str) -> Option<Self> {
+        PGN_CATALOG.iter()
+            .find(|(_, v, _)| v.eq_ignore_ascii_case(label))
+            .map(|(pgn, _, _)| Self::from_bits(*pgn))
+    }
+}
+
+impl Display for Pgn {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self.catalog() {
+            Some((_, label, description)) => write!(f, "{label} – {description}"),
+            None => write!(f, "{:05X}", self.into_bits()),
+        }
+    }
 }
 
 impl J1939Id {
@@ -239,6 +312,9 @@ impl J1939Id {
 
     /// Constructs and returns a [`Pgn`] struct based on the 29-bit identifier fields.
     ///
+    /// The PDU specific bits are kept, use [`Pgn::normalized`] to compare the PDU1 PGN with
+    /// the catalogue ones.
+    ///
     /// # Returns
     /// A [`Pgn`] bitfield initialized with the 29-bit identifier fields.
     #[must_use]
@@ -0,0 +1,243 @@
+//! The typed parameter groups generated by [`define_pgn`](crate::define_pgn).
+
+/// Define a struct of the SPNs in a parameter group.
+///
+/// Each SPN is `name: spn => (start_bit, length, resolution, offset, unit)`, the `start_bit` is
+/// numbered from the least significant bit of byte 0 as [`SpnDef`](crate::can::j1939::SpnDef),
+/// `rate` is the transmission rate in milliseconds, 0 for on request.
+///
+/// The generated struct has:
+/// - the [`SpnValue`](crate::can::j1939::SpnValue) fields, the not available and error raw values
+///   are kept as is.
+/// - `PGN`, `RATE_MS` and `SPNS` constants.
+/// - `TryFrom<&DataField>` and `TryFrom<&[u8]>`, the missing bytes are not available.
+/// - `encode(&self) -> DataField`, the unused bits are 1.
+/// - `Display`.
+///
+/// # Examples
+/// ```rust
+/// use isotp_rs::define_pgn;
+/// use isotp_rs::can::j1939::{DataField, SpnValue};
+///
+/// define_pgn! {
+///     /// Engine Hours, Revolutions
+///     pub struct Hours {
+///         pgn: 0xFEE5,
+///         rate: 0,
+///         /// Engine Total Hours of Operation
+///         total_hours: 247 => (0, 32, 0.05, 0., "h"),
+///         /// Engine Total Revolutions
+///         total_revolutions: 249 => (32, 32, 1000., 0., "r"),
+///     }
+/// }
+///
+/// let hours = Hours::try_from([0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF].as_slice()).unwrap();
+/// assert_eq!(hours.total_hours, SpnValue::Valid(500.));
+/// assert_eq!(hours.total_revolutions, SpnValue::NotAvailable);
+/// assert_eq!(hours.encode().to_be_bytes(), [0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+/// assert_eq!(hours.to_string(), "HOURS total_hours: 500 h, total_revolutions: N/A");
+/// ```
+#[macro_export]
+macro_rules! define_pgn {
+    (
+        $(#[$meta:meta])*
+        $vis:vis struct $name:ident {
+            pgn: $pgn:expr,
+            rate: $rate:expr,
+            $(
+                $(#[$field_meta:meta])*
+                $field:ident: $spn:literal => ($start:expr, $length:expr, $resolution:expr, $offset:expr, $unit:expr)
+            ),+ $(,)?
+        }
+    ) => {
+        $(#[$meta])*
+        #[derive(Debug, Clone, Copy, PartialEq)]
+        $vis struct $name {
+            $(
+                $(#[$field_meta])*
+                pub $field: $crate::can::j1939::SpnValue,
+            )+
+        }
+
+        impl $name {
+            /// The PGN.
+            pub const PGN: u32 = $pgn;
+            /// The transmission rate in milliseconds, 0 for on request.
+            pub const RATE_MS: u64 = $rate;
+            /// The SPN, field name, definition and unit.
+            pub const SPNS: &'static [(u32, &'static str, $crate::can::j1939::SpnDef, &'static str)] = &[
+                $(($spn, stringify!($field), $crate::can::j1939::SpnDef::new($start, $length, $resolution, $offset), $unit),)+
+            ];
+
+            /// Encode the SPNs, the unused bits are 1.
+            pub fn encode(&self) -> $crate::can::j1939::DataField {
+                [$(self.$field),+].into_iter()
+                    .zip(Self::SPNS)
+                    .fold($crate::can::j1939::DataField::from_bits(u64::MAX), |data, (value, (_, _, def, _))| {
+                        data.with_spn(*def, value)
+                    })
+            }
+        }
+
+        impl TryFrom<&$crate::can::j1939::DataField> for $name {
+            type Error = $crate::error::Error;
+
+            fn try_from(value: &$crate::can::j1939::DataField) -> Result<Self, Self::Error> {
+                Ok(Self {
+                    $($field: value.spn($crate::can::j1939::SpnDef::new($start, $length, $resolution, $offset)),)+
+                })
+            }
+        }
+
+        impl TryFrom<&[u8]> for $name {
+            type Error = $crate::error::Error;
+
+            fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+                if value.len() > 8 {
+                    return Err($crate::error::Error::InvalidDataLength { actual: value.len(), expect: 8 });
+                }
+
+                let mut bytes = [0xFF; 8];
+                bytes[..value.len()].copy_from_slice(value);
+                Self::try_from(&$crate::can::j1939::DataField::from_bits(u64::from_be_bytes(bytes)))
+            }
+        }
+
+        impl std::fmt::Display for $name {
+            fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+                let label = $crate::can::j1939::Pgn::from_bits(Self::PGN).label();
+                write!(f, "{}", label.unwrap_or(stringify!($name)))?;
+                for (i, (value, (_, name, _, unit))) in [$(self.$field),+].into_iter().zip(Self::SPNS).enumerate() {
+                    write!(f, "{}{}: {}", if i == 0 { " " } else { ", " }, name, value)?;
+                    if value.value().is_some() && !unit.is_empty() {
+                        write!(f, " {}", unit)?;
+                    }
+                }
+                Ok(())
+            }
+        }
+    };
+}
+
+crate::define_pgn! {
+    /// Electronic Engine Controller 1
+    pub struct Eec1 {
+        pgn: 0xF004,
+        rate: 20,
+        /// Engine Torque Mode
+        torque_mode: 899 => (0, 4, 1., 0., ""),
+        /// Actual Engine - Percent Torque (Fractional)
+        actual_torque_fraction: 4154 => (4, 4, 0.125, 0., "%"),
+        /// Driver's Demand Engine - Percent Torque
+        demand_torque: 512 => (8, 8, 1., -125., "%"),
+        /// Actual Engine - Percent Torque
+        actual_torque: 513 => (16, 8, 1., -125., "%"),
+        /// Engine Speed
+        engine_speed: 190 => (24, 16, 0.125, 0., "rpm"),
+        /// Source Address of Controlling Device for Engine Control
+        controlling_address: 1483 => (40, 8, 1., 0., ""),
+        /// Engine Starter Mode
+        starter_mode: 1675 => (48, 4, 1., 0., ""),
+        /// Engine Demand - Percent Torque
+        engine_demand_torque: 2432 => (56, 8, 1., -125., "%"),
+    }
+}
+
+crate::define_pgn! {
+    /// Cruise Control/Vehicle Speed
+    pub struct Ccvs {
+        pgn: 0xFEF1,
+        rate: 100,
+        /// Two Speed Axle Switch
+        two_speed_axle: 69 => (0, 2, 1., 0., ""),
+        /// Parking Brake Switch
+        parking_brake: 70 => (2, 2, 1., 0., ""),
+        /// Cruise Control Pause Switch
+        cruise_pause: 1633 => (4, 2, 1., 0., ""),
+        /// Park Brake Release Inhibit Request
+        park_brake_inhibit: 3807 => (6, 2, 1., 0., ""),
+        /// Wheel-Based Vehicle Speed
+        vehicle_speed: 84 => (8, 16, 1. / 256., 0., "km/h"),
+        /// Cruise Control Active
+        cruise_active: 595 => (24, 2, 1., 0., ""),
+        /// Cruise Control Enable Switch
+        cruise_enable: 596 => (26, 2, 1., 0., ""),
+        /// Brake Switch
+        brake: 597 => (28, 2, 1., 0., ""),
+        /// Clutch Switch
+        clutch: 598 => (30, 2, 1., 0., ""),
+        /// Cruise Control Set Switch
+        cruise_set: 599 => (32, 2, 1., 0., ""),
+        /// Cruise Control Coast (Decelerate) Switch
+        cruise_coast: 600 => (34, 2, 1., 0., ""),
+        /// Cruise Control Resume Switch
+        cruise_resume: 601 => (36, 2, 1., 0., ""),
+        /// Cruise Control Accelerate Switch
+        cruise_accelerate: 602 => (38, 2, 1., 0., ""),
+        /// Cruise Control Set Speed
+        cruise_set_speed: 86 => (40, 8, 1., 0., "km/h"),
+        /// PTO Governor State
+        pto_state: 976 => (48, 5, 1., 0., ""),
+        /// Cruise Control States
+        cruise_states: 527 => (53, 3, 1., 0., ""),
+        /// Engine Idle Increment Switch
+        idle_increment: 968 => (56, 2, 1., 0., ""),
+        /// Engine Idle Decrement Switch
+        idle_decrement: 967 => (58, 2, 1., 0., ""),
+        /// Engine Diagnostic Test Mode Switch
+        test_mode: 966 => (60, 2, 1., 0., ""),
+        /// Engine Shutdown Override Switch
+        shutdown_override: 1237 => (62, 2, 1., 0., ""),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::can::j1939::{Conversion, DataField, SpnValue};
+    use super::{Ccvs, Eec1};
+
+    #[test]
+    fn test_eec1() -> anyhow::Result<()> {
+        let data = DataField::from_hex("F07D7DE01500F07D").unwrap();
+        let eec1 = Eec1::try_from(&data)?;
+        assert_eq!(eec1.torque_mode, SpnValue::Valid(0.));
+        assert_eq!(eec1.actual_torque_fraction, SpnValue::NotAvailable);
+        assert_eq!(eec1.demand_torque, SpnValue::Valid(0.));
+        assert_eq!(eec1.engine_speed, SpnValue::Valid(700.));
+        assert_eq!(eec1.controlling_address, SpnValue::Valid(0.));
+        assert_eq!(eec1.encode(), data);
+        assert_eq!(eec1.to_string(), "EEC1 torque_mode: 0, actual_torque_fraction: N/A, demand_torque: 0 %, \
+            actual_torque: 0 %, engine_speed: 700 rpm, controlling_address: 0, starter_mode: 0, engine_demand_torque: 0 %");
+        assert_eq!((Eec1::PGN, Eec1::RATE_MS, Eec1::SPNS.len()), (0xF004, 20, 8));
+
+        let eec1 = Eec1 { engine_speed: SpnValue::Valid(1234.5), actual_torque: SpnValue::Valid(-25.), ..eec1 };
+        assert_eq!(eec1.encode().to_be_bytes(), [0xF0, 0x7D, 0x64, 0x94, 0x26, 0x00, 0xF0, 0x7D]);
+        let eec1 = Eec1 { engine_speed: SpnValue::Error, ..eec1 };
+        assert_eq!(Eec1::try_from(eec1.encode().to_be_bytes().as_slice())?, eec1);
+
+        // the missing bytes are not available
+        let eec1 = Eec1::try_from([0xF0, 0x7D].as_slice())?;
+        assert_eq!(eec1.engine_speed, SpnValue::NotAvailable);
+        assert!(Eec1::try_from([0; 9].as_slice()).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_ccvs() -> anyhow::Result<()> {
+        let data = DataField::from_hex("F30032C0000000FF").unwrap();
+        let ccvs = Ccvs::try_from(&data)?;
+        assert_eq!(ccvs.two_speed_axle, SpnValue::NotAvailable);
+        assert_eq!(ccvs.parking_brake, SpnValue::Valid(0.));
+        assert_eq!(ccvs.vehicle_speed, SpnValue::Valid(50.));
+        assert_eq!(ccvs.cruise_active, SpnValue::Valid(0.));
+        assert_eq!(ccvs.clutch, SpnValue::NotAvailable);
+        assert_eq!(ccvs.cruise_set_speed, SpnValue::Valid(0.));
+        assert_eq!(ccvs.shutdown_override, SpnValue::NotAvailable);
+        assert_eq!(ccvs.encode(), data);
+
+        let ccvs = Ccvs { vehicle_speed: SpnValue::Valid(88.5), parking_brake: SpnValue::Valid(1.), ..ccvs };
+        assert_eq!(ccvs.encode().to_be_bytes(), [0xF7, 0x80, 0x58, 0xC0, 0x00, 0x00, 0x00, 0xFF]);
+        assert_eq!(Ccvs::try_from(&ccvs.encode())?, ccvs);
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+//! Request a PGN (PGN 59904) and correlate the responses.
+
+use std::any::Any;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, mpsc::{channel, Sender}};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::j1939::{AckControl, Acknowledgement, DestinationAddress, J1939Id, Message, Pgn, GLOBAL_ADDRESS, PGN_REQUEST};
+use crate::device::{Channel, Listener};
+use crate::error::Error;
+
+const REQUEST_PRIORITY: u8 = 6;
+
+/// The errors of the J1939 requests.
+#[derive(Debug, Clone, thiserror::Error)]
+pub enum J1939Error {
+    #[error("J1939 - PGN {pgn:05X} is not acknowledged by {address:02X}")]
+    Nack { pgn: u32, address: u8 },
+
+    #[error("J1939 - access to PGN {pgn:05X} is denied by {address:02X}")]
+    AccessDenied { pgn: u32, address: u8 },
+
+    #[error("J1939 - {address:02X} cannot respond to PGN {pgn:05X} now")]
+    CannotRespond { pgn: u32, address: u8 },
+
+    #[error("J1939 - timeout when requesting PGN {0:05X}")]
+    Timeout(u32),
+
+    #[error(transparent)]
+    Error(#[from] Error),
+}
+
+#[derive(Debug)]
+enum Response {
+    Message(Message),
+    Ack(Acknowledgement, u8),
+}
+
+#[derive(Debug)]
+struct Pending {
+    id: u64,
+    pgn: u32,
+    /// `None` for the global request.
+    dest: Option<u8>,
+    sender: Sender<Response>,
+}
+
+/// Send the Request PGN and wait for the requested PGN or the Acknowledgement,
+/// register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan).
+///
+/// Only the responses in a single frame are correlated.
+#[derive(Clone)]
+pub struct J1939Requester<C, F> {
+    pub(crate) channel: C,
+    pub(crate) address: u8,
+    pub(crate) sender: Sender<F>,
+    pending: Arc<Mutex<Vec<Pending>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<C: Channel, F: FrameMut<Channel = C> + 'static> J1939Requester<C, F> {
+    /// Create the requester with the source `address`.
+    pub fn new(channel: C, address: u8, sender: Sender<F>) -> Self {
+        Self {
+            channel,
+            address,
+            sender,
+            pending: Default::default(),
+            next_id: Default::default(),
+        }
+    }
+
+    /// Request `pgn` from `dest`, blocked until the response or the NACK is received.
+    ///
+    /// The first response is returned when `dest` is global, use [`Self::request_all`]
+    /// to collect the responses of all nodes.
+    pub fn request(&self, pgn: Pgn, dest: DestinationAddress, timeout: Duration) -> Result<Message, J1939Error> {
+        let dest = match dest {
+            DestinationAddress::Some(v) if v != GLOBAL_ADDRESS => Some(v),
+            _ => None,
+        };
+        let pgn = pgn.normalized().into_bits();
+
+        self.with_pending(pgn, dest, |receiver| {
+            let deadline = Instant::now() + timeout;
+            loop {
+                let remain = deadline.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(remain) {
+                    Ok(Response::Message(message)) => return Ok(message),
+                    Ok(Response::Ack(ack, address)) => match ack.control {
+                        AckControl::Nack => return Err(J1939Error::Nack { pgn, address }),
+                        AckControl::AccessDenied => return Err(J1939Error::AccessDenied { pgn, address }),
+                        AckControl::CannotRespond => return Err(J1939Error::CannotRespond { pgn, address }),
+                        AckControl::Ack | AckControl::Other(_) => continue,
+                    },
+                    Err(_) => return Err(J1939Error::Timeout(pgn)),
+                }
+            }
+        })
+    }
+
+    /// Request `pgn` from all nodes and collect the responses in `timeout`.
+    ///
+    /// The Acknowledgements are ignored, the nodes do not NACK a global request.
+    pub fn request_all(&self, pgn: Pgn, timeout: Duration) -> Result<Vec<Message>, J1939Error> {
+        let pgn = pgn.normalized().into_bits();
+
+        self.with_pending(pgn, None, |receiver| {
+            let deadline = Instant::now() + timeout;
+            let mut results = Vec::new();
+            loop {
+                let remain = deadline.saturating_duration_since(Instant::now());
+                match receiver.recv_timeout(remain) {
+                    Ok(Response::Message(message)) => results.push(message),
+                    Ok(Response::Ack(..)) => continue,
+                    Err(_) => return Ok(results),
+                }
+            }
+        })
+    }
+
+    /// Register the matcher while sending the request and waiting the responses.
+    fn with_pending<R>(
+        &self,
+        pgn: u32,
+        dest: Option<u8>,
+        wait: impl FnOnce(std::sync::mpsc::Receiver<Response>) -> Result<R, J1939Error>,
+    ) -> Result<R, J1939Error> {
+        let (sender, receiver) = channel();
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        self.pending.lock()
+            .map_err(|_| Error::ContextError("can't get `pending`".into()))?
+            .push(Pending { id, pgn, dest, sender });
+
+        let result = self.send_request(pgn, dest.unwrap_or(GLOBAL_ADDRESS))
+            .and_then(|_| wait(receiver));
+
+        if let Ok(mut pending) = self.pending.lock() {
+            pending.retain(|v| v.id != id);
+        }
+
+        result
+    }
+
+    fn send_request(&self, pgn: u32, dest: u8) -> Result<(), J1939Error> {
+        log::debug!("J1939 - request PGN {:05X} from {:02X}", pgn, dest);
+        let id = J1939Id::from_pgn(Pgn::from_bits(PGN_REQUEST), REQUEST_PRIORITY, self.address, Some(dest))?;
+        let mut frame = F::new(Id::Extended(id.into_bits()), &pgn.to_le_bytes()[..3])
+            .ok_or(Error::ConvertError { src: "j1939 request", target: "can-frame" })?;
+        frame.set_channel(self.channel.clone());
+
+        self.sender.send(frame)
+            .map_err(|e| {
+                log::warn!("J1939 - transmit failed: {:?}", e);
+                Error::device(e).into()
+            })
+    }
+
+    fn on_response(&self, id: J1939Id, data: &[u8]) {
+        let source = id.source_address_bits();
+        if id.pgn().is_p2p() && ![self.address, GLOBAL_ADDRESS].contains(&id.pdu_specific()) {
+            return;
+        }
+
+        let message = Message::from_bytes(id, data);
+        let (pgn, response) = match Acknowledgement::try_from(&message) {
+            Ok(ack) => (ack.pgn.normalized().into_bits(), Response::Ack(ack, source)),
+            Err(_) => (id.pgn().normalized().into_bits(), Response::Message(message)),
+        };
+
+        if let Ok(pending) = self.pending.lock() {
+            if let Some(v) = pending.iter()
+                .find(|v| v.pgn == pgn && v.dest.is_none_or(|dest| dest == source)) {
+                let _ = v.sender.send(response);
+            }
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for J1939Requester<C, F>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        frames.iter()
+            .filter(|f| f.is_extended())
+            .for_each(|f| self.on_response(J1939Id::from(f.id()), f.data()));
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::thread::{spawn, JoinHandle};
+    use std::time::{Duration, Instant};
+    use crate::can::driver::{MockDriver, SyncCan, VirtualBus, MOCK_CHANNEL};
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::j1939::{DestinationAddress, J1939Id, Pdu, Pgn};
+    use crate::can::message::CanMessage;
+    use crate::device::Driver;
+    use super::{J1939Error, J1939Requester};
+
+    const PGN_ENGINE_HOURS: u32 = 0xFEE5;
+
+    /// Wait for the request and respond with `response`.
+    fn respond(driver: MockDriver, response: Option<(u32, Vec<u8>)>) -> JoinHandle<Option<CanMessage>> {
+        spawn(move || {
+            let deadline = Instant::now() + Duration::from_millis(500);
+            while Instant::now() < deadline {
+                let frames = driver.receive(MOCK_CHANNEL.into(), Some(10)).unwrap();
+                if let Some(request) = frames.into_iter().find(|f| (f.id().into_bits() >> 16) & 0xFF == 0xEA) {
+                    if let Some((id, data)) = &response {
+                        let mut frame = CanMessage::new(Id::Extended(*id), data).unwrap();
+                        frame.set_channel(MOCK_CHANNEL.into());
+                        driver.transmit(frame, None).unwrap();
+                    }
+                    return Some(request);
+                }
+            }
+            None
+        })
+    }
+
+    fn setup(bus: &VirtualBus) -> (SyncCan<MockDriver, String, CanMessage>, J1939Requester<String, CanMessage>) {
+        let mut can = SyncCan::new(bus.driver(0));
+        let requester = J1939Requester::new(MOCK_CHANNEL.to_string(), 0xF9, can.sender());
+        can.register_listener("requester".into(), Box::new(requester.clone()));
+        can.sync_start(100);
+        (can, requester)
+    }
+
+    #[test]
+    fn test_response() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(2);
+        let (mut can, requester) = setup(&bus);
+        let peer = respond(bus.driver(1), Some((0x18FEE500, vec![0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF])));
+
+        let message = requester.request(Pgn::from_bits(PGN_ENGINE_HOURS), DestinationAddress::Some(0x00), Duration::from_millis(200))?;
+        assert_eq!(message.id().source_address_bits(), 0x00);
+        assert_eq!(message.id().pgn_bits(), PGN_ENGINE_HOURS);
+        let Pdu::DataField(data) = message.pdu() else { panic!("unexpected pdu") };
+        assert_eq!(data.to_be_bytes(), [0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]);
+
+        let request = peer.join().unwrap().unwrap();
+        assert_eq!(request.id().into_bits(), 0x18EA00F9);
+        assert_eq!(request.data(), [0xE5, 0xFE, 0x00]);
+        assert_eq!(J1939Id::from(request.id()).pdu_specific(), 0x00);
+
+        can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_nack() {
+        let bus = VirtualBus::new(2);
+        let (mut can, requester) = setup(&bus);
+        let _ = respond(bus.driver(1), Some((0x18E8FF00, vec![0x01, 0xFF, 0xFF, 0xFF, 0xF9, 0xE5, 0xFE, 0x00])));
+
+        let result = requester.request(Pgn::from_bits(PGN_ENGINE_HOURS), DestinationAddress::Some(0x00), Duration::from_millis(200));
+        assert!(matches!(result, Err(J1939Error::Nack { pgn: PGN_ENGINE_HOURS, address: 0x00 })), "{:?}", result);
+
+        let _ = respond(bus.driver(1), Some((0x18E8FF00, vec![0x02, 0xFF, 0xFF, 0xFF, 0xF9, 0xE5, 0xFE, 0x00])));
+        let result = requester.request(Pgn::from_bits(PGN_ENGINE_HOURS), DestinationAddress::Some(0x00), Duration::from_millis(200));
+        assert!(matches!(result, Err(J1939Error::AccessDenied { pgn: PGN_ENGINE_HOURS, address: 0x00 })), "{:?}", result);
+
+        can.stop();
+    }
+
+    #[test]
+    fn test_timeout() {
+        let bus = VirtualBus::new(2);
+        let (mut can, requester) = setup(&bus);
+        // the response from the other node is ignored
+        let _ = respond(bus.driver(1), Some((0x18FEE503, vec![0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF])));
+
+        let start = Instant::now();
+        let result = requester.request(Pgn::from_bits(PGN_ENGINE_HOURS), DestinationAddress::Some(0x00), Duration::from_millis(100));
+        assert!(matches!(result, Err(J1939Error::Timeout(PGN_ENGINE_HOURS))), "{:?}", result);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+
+        can.stop();
+    }
+
+    #[test]
+    fn test_global() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(4);
+        let (mut can, requester) = setup(&bus);
+        let peers = [
+            respond(bus.driver(1), Some((0x18FEE500, vec![0x01; 8]))),
+            respond(bus.driver(2), Some((0x18FEE503, vec![0x02; 8]))),
+            respond(bus.driver(3), Some((0x18E8FF0B, vec![0x01, 0xFF, 0xFF, 0xFF, 0xF9, 0xE5, 0xFE, 0x00]))),
+        ];
+
+        let messages = requester.request_all(Pgn::from_bits(PGN_ENGINE_HOURS), Duration::from_millis(200))?;
+        let mut sources = messages.iter()
+            .map(|v| v.id().source_address_bits())
+            .collect::<Vec<_>>();
+        sources.sort();
+        assert_eq!(sources, [0x00, 0x03]);
+
+        for peer in peers {
+            let request = peer.join().unwrap().unwrap();
+            assert_eq!(request.id().into_bits(), 0x18EAFFF9);
+        }
+
+        can.stop();
+        Ok(())
+    }
+}
@@ -0,0 +1,219 @@
+//! Dispatch the received J1939 messages to the handlers by PGN.
+
+use std::any::Any;
+use std::fmt::Display;
+use std::panic::{catch_unwind, AssertUnwindSafe};
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering};
+use crate::can::frame::FrameMut;
+use crate::can::j1939::{J1939Id, Message, Pgn};
+use crate::device::{Channel, Listener};
+
+/// The handler of the routed messages.
+pub type J1939Handler = Box<dyn FnMut(&Message) + Send>;
+
+/// The identifier of a registered route, used to unregister it.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct RouteId(u64);
+
+struct Route {
+    id: RouteId,
+    /// The normalized PGN.
+    pgn: u32,
+    source: Option<u8>,
+    handler: Arc<Mutex<J1939Handler>>,
+}
+
+/// Route the received messages to the handlers registered by the PGN,
+/// register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan).
+///
+/// The routes can be changed from any thread, a panic in a handler is caught and logged.
+#[derive(Clone)]
+pub struct J1939Router<C> {
+    pub(crate) channel: C,
+    routes: Arc<Mutex<Vec<Route>>>,
+    default: Arc<Mutex<Option<Arc<Mutex<J1939Handler>>>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl<C: Channel> J1939Router<C> {
+    pub fn new(channel: C) -> Self {
+        Self {
+            channel,
+            routes: Default::default(),
+            default: Default::default(),
+            next_id: Default::default(),
+        }
+    }
+
+    /// Register the handler of `pgn`, the destination address of PDU1 is ignored.
+    ///
+    /// # Arguments
+    /// - `source`: only the messages from the address are handled if it is `Some`.
+    pub fn register<H>(&self, pgn: Pgn, source: Option<u8>, handler: H) -> RouteId
+    where
+        H: FnMut(&Message) + Send + 'static {
+        let id = RouteId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        let route = Route {
+            id,
+            pgn: pgn.normalized().into_bits(),
+            source,
+            handler: Arc::new(Mutex::new(Box::new(handler))),
+        };
+        match self.routes.lock() {
+            Ok(mut routes) => routes.push(route),
+            Err(_) => log::warn!("J1939 - router error when registering PGN {:05X}", route.pgn),
+        }
+
+        id
+    }
+
+    /// Remove the route, returns false if it is not registered.
+    pub fn unregister(&self, id: RouteId) -> bool {
+        match self.routes.lock() {
+            Ok(mut routes) => {
+                let count = routes.len();
+                routes.retain(|v| v.id != id);
+                routes.len() != count
+            },
+            Err(_) => false,
+        }
+    }
+
+    /// Set the handler of the messages without any route, `None` to remove it.
+    pub fn set_default_handler(&self, handler: Option<J1939Handler>) {
+        if let Ok(mut default) = self.default.lock() {
+            *default = handler.map(|v| Arc::new(Mutex::new(v)));
+        }
+    }
+
+    fn dispatch(&self, message: &Message) {
+        let pgn = message.id().pgn().normalized().into_bits();
+        let source = message.id().source_address_bits();
+        let mut handlers = match self.routes.lock() {
+            Ok(routes) => routes.iter()
+                .filter(|v| v.pgn == pgn && v.source.is_none_or(|v| v == source))
+                .map(|v| v.handler.clone())
+                .collect::<Vec<_>>(),
+            Err(_) => return,
+        };
+        if handlers.is_empty() {
+            match self.default.lock() {
+                Ok(default) => handlers.extend(default.clone()),
+                Err(_) => return,
+            }
+        }
+
+        for handler in handlers {
+            let result = catch_unwind(AssertUnwindSafe(|| {
+                let mut handler = handler.lock()
+                    .unwrap_or_else(|e| e.into_inner());
+                handler(message)
+            }));
+            if result.is_err() {
+                log::warn!("J1939 - handler of PGN {:05X} from {:02X} panicked", pgn, source);
+            }
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for J1939Router<C>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        for frame in frames {
+            if !frame.is_extended() {
+                continue;
+            }
+
+            let message = Message::from_bytes(J1939Id::from(frame.id()), frame.data());
+            self.dispatch(&message);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::thread::spawn;
+    use crate::can::driver::MOCK_CHANNEL;
+    use crate::can::j1939::{Message, Pgn};
+    use crate::can::message::CanMessage;
+    use crate::can::testing::frame;
+    use crate::device::Listener;
+    use super::J1939Router;
+
+    type Records = Arc<Mutex<Vec<(&'static str, u32)>>>;
+
+    fn record(records: &Records, name: &'static str) -> impl FnMut(&Message) + Send + 'static {
+        let records = records.clone();
+        move |message: &Message| records.lock().unwrap().push((name, message.id().into_bits()))
+    }
+
+    #[test]
+    fn test_route() {
+        let records = Records::default();
+        let mut router = J1939Router::new(MOCK_CHANNEL.to_string());
+        router.register(Pgn::from_label("EEC1").unwrap(), None, record(&records, "eec1"));
+        router.register(Pgn::from_label("CCVS").unwrap(), Some(0x00), record(&records, "ccvs"));
+        // the destination address is ignored
+        router.register(Pgn::from_bits(0xEAFF), None, record(&records, "rqst"));
+        router.set_default_handler(Some(Box::new(record(&records, "default"))));
+        // the panic is isolated
+        router.register(Pgn::from_label("EEC1").unwrap(), Some(0x01), |_: &Message| panic!("handler panicked"));
+
+        let frames = [
+            frame(0x0CF00400, &[0xF0, 0x7D, 0x7D, 0xE0, 0x15, 0x00, 0xF0, 0x7D]),
+            frame(0x18FEF100, &[0xF3, 0x00, 0x32, 0xC0, 0x00, 0x00, 0x00, 0xFF]),
+            frame(0x18FEF117, &[0xF3, 0x00, 0x32, 0xC0, 0x00, 0x00, 0x00, 0xFF]),
+            frame(0x0CF00401, &[0xF0, 0x7D, 0x7D, 0xE0, 0x15, 0x00, 0xF0, 0x7D]),
+            frame(0x18EA00F9, &[0xE5, 0xFE, 0x00]),
+            frame(0x18FEE500, &[0x10, 0x27, 0x00, 0x00, 0xFF, 0xFF, 0xFF, 0xFF]),
+        ];
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut router, MOCK_CHANNEL.into(), &frames);
+        assert_eq!(std::mem::take(&mut *records.lock().unwrap()), [
+            ("eec1", 0x0CF00400),
+            ("ccvs", 0x18FEF100),
+            ("default", 0x18FEF117),
+            ("eec1", 0x0CF00401),
+            ("rqst", 0x18EA00F9),
+            ("default", 0x18FEE500),
+        ]);
+
+        // change the routes from another thread
+        let route = {
+            let router = router.clone();
+            let records = records.clone();
+            spawn(move || {
+                router.set_default_handler(None);
+                router.register(Pgn::from_label("HOURS").unwrap(), None, record(&records, "hours"))
+            }).join().unwrap()
+        };
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut router, MOCK_CHANNEL.into(), &frames[1..]);
+        assert_eq!(std::mem::take(&mut *records.lock().unwrap()), [
+            ("ccvs", 0x18FEF100),
+            ("eec1", 0x0CF00401),
+            ("rqst", 0x18EA00F9),
+            ("hours", 0x18FEE500),
+        ]);
+
+        assert!(router.unregister(route));
+        assert!(!router.unregister(route));
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut router, MOCK_CHANNEL.into(), &frames[5..]);
+        assert!(records.lock().unwrap().is_empty());
+    }
+}
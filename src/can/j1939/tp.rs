@@ -0,0 +1,984 @@
+//! J1939-21 transport protocol for the messages longer than 8 bytes.
+
+use std::any::Any;
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::sync::{Arc, Mutex, mpsc::Sender};
+use std::sync::atomic::{AtomicU8, Ordering};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::j1939::{J1939Id, Message, Pdu, Pgn};
+use crate::device::{Channel, Listener};
+use crate::error::Error;
+
+/// The PGN of TP.CM(connection management).
+pub const PGN_TP_CM: u32 = 0xEC00;
+/// The PGN of TP.DT(data transfer).
+pub const PGN_TP_DT: u32 = 0xEB00;
+/// The max size of a message transferred by the transport protocol(255 packets * 7 bytes).
+pub const TP_MAX_SIZE: usize = 255 * 7;
+/// The global destination address.
+pub const GLOBAL_ADDRESS: u8 = 0xFF;
+
+const CONTROL_RTS: u8 = 16;
+const CONTROL_CTS: u8 = 17;
+const CONTROL_END_OF_MSG_ACK: u8 = 19;
+const CONTROL_BAM: u8 = 32;
+const CONTROL_ABORT: u8 = 255;
+const TP_PRIORITY: u8 = 7;
+const DEFAULT_PRIORITY: u8 = 6;
+/// The min and max time between the BAM packets.
+const BAM_INTERVAL_MIN: Duration = Duration::from_millis(50);
+const BAM_INTERVAL_MAX: Duration = Duration::from_millis(200);
+/// T1, the receiver discards the session when no packet is received.
+const TIMEOUT_T1: Duration = Duration::from_millis(750);
+/// T2, the responder aborts when no packet is received after sending CTS.
+const TIMEOUT_T2: Duration = Duration::from_millis(1250);
+/// T3, the originator aborts when no CTS or EndOfMsgAck is received after the last packet.
+const TIMEOUT_T3: Duration = Duration::from_millis(1250);
+/// T4, the originator aborts when no CTS is received after the CTS to hold the connection.
+const TIMEOUT_T4: Duration = Duration::from_millis(1050);
+
+/// The connection abort reasons of J1939-21.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AbortReason {
+    /// Already in one or more connection managed sessions and cannot support another.
+    AlreadyInSession,
+    /// System resources were needed for another task so this connection managed session was terminated.
+    ResourcesNeeded,
+    /// A timeout occurred and this is the connection abort to close the session.
+    Timeout,
+    /// CTS messages received when data transfer is in progress.
+    CtsWhileTransferring,
+    /// Maximum retransmit request limit reached.
+    MaxRetransmit,
+    /// Unexpected data transfer packet.
+    UnexpectedDataTransfer,
+    /// Bad sequence number.
+    BadSequence,
+    /// Duplicate sequence number.
+    DuplicateSequence,
+    /// The other reasons.
+    Other(u8),
+}
+
+impl From<u8> for AbortReason {
+    fn from(value: u8) -> Self {
+        match value {
+            1 => Self::AlreadyInSession,
+            2 => Self::ResourcesNeeded,
+            3 => Self::Timeout,
+            4 => Self::CtsWhileTransferring,
+            5 => Self::MaxRetransmit,
+            6 => Self::UnexpectedDataTransfer,
+            7 => Self::BadSequence,
+            8 => Self::DuplicateSequence,
+            v => Self::Other(v),
+        }
+    }
+}
+
+impl From<AbortReason> for u8 {
+    fn from(value: AbortReason) -> Self {
+        match value {
+            AbortReason::AlreadyInSession => 1,
+            AbortReason::ResourcesNeeded => 2,
+            AbortReason::Timeout => 3,
+            AbortReason::CtsWhileTransferring => 4,
+            AbortReason::MaxRetransmit => 5,
+            AbortReason::UnexpectedDataTransfer => 6,
+            AbortReason::BadSequence => 7,
+            AbortReason::DuplicateSequence => 8,
+            AbortReason::Other(v) => v,
+        }
+    }
+}
+
+/// The frames of the transport protocol.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum J1939TpFrame {
+    /// TP.CM_RTS, request to send `size` bytes in `packets` packets,
+    /// at most `max_packets` packets per CTS(0xFF for no limit).
+    Rts { size: u16, packets: u8, max_packets: u8, pgn: u32 },
+    /// TP.CM_CTS, clear to send `packets` packets from the packet `next`,
+    /// 0 packets to hold the connection.
+    Cts { packets: u8, next: u8, pgn: u32 },
+    /// TP.CM_EndOfMsgAck, all the `size` bytes in `packets` packets are received.
+    EndOfMsgAck { size: u16, packets: u8, pgn: u32 },
+    /// TP.Conn_Abort.
+    Abort { reason: AbortReason, pgn: u32 },
+    /// TP.CM_BAM, announce a broadcast message of `size` bytes in `packets` packets.
+    Bam { size: u16, packets: u8, pgn: u32 },
+    /// TP.DT, the 7 bytes data of the packet `sequence`(start from 1).
+    DataTransfer { sequence: u8, data: [u8; 7] },
+}
+
+impl J1939TpFrame {
+    /// The PGN of the frame, [`PGN_TP_CM`] or [`PGN_TP_DT`].
+    #[inline]
+    pub fn pgn(&self) -> u32 {
+        match self {
+            Self::DataTransfer { .. } => PGN_TP_DT,
+            _ => PGN_TP_CM,
+        }
+    }
+
+    pub fn encode(&self) -> [u8; 8] {
+        let cm = |control: u8, args: [u8; 4], pgn: &u32| {
+            let pgn = pgn.to_le_bytes();
+            [control, args[0], args[1], args[2], args[3], pgn[0], pgn[1], pgn[2]]
+        };
+        match self {
+            Self::Rts { size, packets, max_packets, pgn } => {
+                let size = size.to_le_bytes();
+                cm(CONTROL_RTS, [size[0], size[1], *packets, *max_packets], pgn)
+            },
+            Self::Cts { packets, next, pgn } =>
+                cm(CONTROL_CTS, [*packets, *next, 0xFF, 0xFF], pgn),
+            Self::EndOfMsgAck { size, packets, pgn } => {
+                let size = size.to_le_bytes();
+                cm(CONTROL_END_OF_MSG_ACK, [size[0], size[1], *packets, 0xFF], pgn)
+            },
+            Self::Abort { reason, pgn } =>
+                cm(CONTROL_ABORT, [(*reason).into(), 0xFF, 0xFF, 0xFF], pgn),
+            Self::Bam { size, packets, pgn } => {
+                let size = size.to_le_bytes();
+                cm(CONTROL_BAM, [size[0], size[1], *packets, 0xFF], pgn)
+            },
+            Self::DataTransfer { sequence, data } => {
+                let mut result = [0xFF; 8];
+                result[0] = *sequence;
+                result[1..].copy_from_slice(data);
+                result
+            },
+        }
+    }
+
+    /// Decode the `data` of a frame with the PGN `pgn`.
+    pub fn decode(pgn: u32, data: &[u8]) -> Result<Self, Error> {
+        if data.len() != 8 {
+            return Err(Error::InvalidDataLength { actual: data.len(), expect: 8 });
+        }
+
+        match pgn {
+            PGN_TP_CM => {
+                let size = u16::from_le_bytes([data[1], data[2]]);
+                let pgn = u32::from_le_bytes([data[5], data[6], data[7], 0]);
+                match data[0] {
+                    CONTROL_RTS => Ok(Self::Rts { size, packets: data[3], max_packets: data[4], pgn }),
+                    CONTROL_CTS => Ok(Self::Cts { packets: data[1], next: data[2], pgn }),
+                    CONTROL_END_OF_MSG_ACK => Ok(Self::EndOfMsgAck { size, packets: data[3], pgn }),
+                    CONTROL_ABORT => Ok(Self::Abort { reason: data[1].into(), pgn }),
+                    CONTROL_BAM => Ok(Self::Bam { size, packets: data[3], pgn }),
+                    _ => Err(Error::InvalidPdu(data.to_vec())),
+                }
+            },
+            PGN_TP_DT => {
+                let mut result = [0; 7];
+                result.copy_from_slice(&data[1..]);
+                Ok(Self::DataTransfer { sequence: data[0], data: result })
+            },
+            _ => Err(Error::InvalidParam(format!("PGN {:05X} is not a transport protocol PGN", pgn))),
+        }
+    }
+
+    /// Split `data` into the TP.DT frames, the last packet is padded with 0xFF.
+    pub fn data_transfers(data: &[u8]) -> Result<Vec<Self>, Error> {
+        let len = data.len();
+        if !(9..=TP_MAX_SIZE).contains(&len) {
+            return Err(Error::LengthOutOfRange(len));
+        }
+
+        Ok(data.chunks(7)
+            .enumerate()
+            .map(|(i, chunk)| {
+                let mut data = [0xFF; 7];
+                data[..chunk.len()].copy_from_slice(chunk);
+                Self::DataTransfer { sequence: i as u8 + 1, data }
+            })
+            .collect())
+    }
+
+    /// Split `data` of `pgn` into a TP.CM_BAM frame and the TP.DT frames.
+    pub fn from_bam(pgn: u32, data: &[u8]) -> Result<Vec<Self>, Error> {
+        let mut results = Self::data_transfers(data)?;
+        results.insert(0, Self::Bam { size: data.len() as u16, packets: results.len() as u8, pgn });
+        Ok(results)
+    }
+}
+
+/// The events of [`J1939Tp`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum J1939TpEvent {
+    /// A message transferred by the transport protocol is received,
+    /// the priority is the default one as it is not transferred.
+    DataReceived(Message),
+    /// The connection with `address` is aborted by either side.
+    Aborted { pgn: u32, address: u8, reason: AbortReason },
+}
+
+pub trait J1939TpEventListener: Send {
+    fn on_tp_event(&mut self, event: J1939TpEvent);
+}
+
+/// A broadcast session received from a source address.
+#[derive(Debug)]
+struct BamSession {
+    pgn: u32,
+    size: usize,
+    packets: u8,
+    buffer: Vec<u8>,
+    last: Instant,
+}
+
+/// The status of a connection sent to a destination, updated by the responses.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum TxStatus {
+    /// Waiting for the CTS or EndOfMsgAck until the deadline.
+    Waiting(Instant),
+    Cts { packets: u8, next: u8 },
+    Completed,
+    Aborted(AbortReason),
+}
+
+/// A connection received from a source address.
+#[derive(Debug)]
+struct RxConnection {
+    pgn: u32,
+    size: usize,
+    packets: u8,
+    max_packets: u8,
+    /// The last packet of the current CTS window.
+    window_end: u8,
+    buffer: Vec<u8>,
+    deadline: Instant,
+}
+
+/// The J1939 transport protocol on a channel, register it as a [`Listener`] of
+/// [`SyncCan`](crate::can::driver::SyncCan) to receive the transfers.
+///
+/// The broadcast sessions and the received connections are keyed by the source address,
+/// the sent connections are keyed by the destination address, so the sessions with
+/// different nodes are tracked independently.
+///
+/// The responder timeouts are checked when the frames are received.
+#[derive(Clone)]
+pub struct J1939Tp<C, F> {
+    pub(crate) channel: C,
+    pub(crate) address: Arc<AtomicU8>,
+    pub(crate) sender: Sender<F>,
+    pub(crate) bam_interval: Duration,
+    pub(crate) packets_per_cts: u8,
+    pub(crate) max_receive_size: usize,
+    bam_sessions: Arc<Mutex<HashMap<u8, BamSession>>>,
+    tx_connections: Arc<Mutex<HashMap<u8, TxStatus>>>,
+    rx_connections: Arc<Mutex<HashMap<u8, RxConnection>>>,
+    pub(crate) listener: Arc<Mutex<Box<dyn J1939TpEventListener>>>,
+}
+
+impl<C: Channel, F: FrameMut<Channel = C> + 'static> J1939Tp<C, F> {
+    /// Create the transport protocol of the node with the source `address`.
+    pub fn new(
+        channel: C,
+        address: u8,
+        sender: Sender<F>,
+        listener: Box<dyn J1939TpEventListener>,
+    ) -> Self {
+        Self {
+            channel,
+            address: Arc::new(AtomicU8::new(address)),
+            sender,
+            bam_interval: BAM_INTERVAL_MIN,
+            packets_per_cts: 0xFF,
+            max_receive_size: TP_MAX_SIZE,
+            bam_sessions: Default::default(),
+            tx_connections: Default::default(),
+            rx_connections: Default::default(),
+            listener: Arc::new(Mutex::new(listener)),
+        }
+    }
+
+    /// Set the time between the BAM packets, clamped to 50-200ms required by J1939-21.
+    #[inline]
+    pub fn with_bam_interval(mut self, interval: Duration) -> Self {
+        self.bam_interval = interval.clamp(BAM_INTERVAL_MIN, BAM_INTERVAL_MAX);
+        self
+    }
+
+    /// Set the max packets requested by a CTS as the responder, no limit(0xFF) by default.
+    #[inline]
+    pub fn with_packets_per_cts(mut self, packets: u8) -> Self {
+        self.packets_per_cts = packets.max(1);
+        self
+    }
+
+    /// Set the max size received as the responder, the larger RTS is aborted
+    /// with [`AbortReason::ResourcesNeeded`].
+    #[inline]
+    pub fn with_max_receive_size(mut self, size: usize) -> Self {
+        self.max_receive_size = size;
+        self
+    }
+
+    /// The source address of the node.
+    #[inline]
+    pub fn address(&self) -> u8 {
+        self.address.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    pub fn update_address(&self, address: u8) {
+        self.address.store(address, Ordering::Release);
+    }
+
+    /// Broadcast `data` of `pgn` to all nodes, blocked until all packets are queued.
+    ///
+    /// The message up to 8 bytes is sent in a single frame, otherwise it's sent by BAM with
+    /// the interval of [`Self::with_bam_interval`] between the packets.
+    pub fn broadcast(&self, pgn: u32, data: &[u8]) -> Result<(), Error> {
+        if data.len() <= 8 {
+            return self.send_frame(DEFAULT_PRIORITY, pgn, GLOBAL_ADDRESS, data);
+        }
+
+        log::trace!("J1939-TP - broadcast PGN {:05X}: {}", pgn, hex::encode(data));
+        let frames = J1939TpFrame::from_bam(pgn, data)?;
+        let count = frames.len();
+        for (i, frame) in frames.into_iter().enumerate() {
+            self.send_tp_frame(&frame, GLOBAL_ADDRESS)?;
+            if i + 1 < count {
+                sleep(self.bam_interval);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Send `data` of `pgn` to `dest` by the connection mode(RTS/CTS),
+    /// blocked until the EndOfMsgAck is received or the connection is aborted.
+    ///
+    /// The message up to 8 bytes is sent in a single frame, and it's broadcast
+    /// when `dest` is [`GLOBAL_ADDRESS`].
+    pub fn send(&self, pgn: u32, dest: u8, data: &[u8]) -> Result<(), Error> {
+        if dest == GLOBAL_ADDRESS {
+            return self.broadcast(pgn, data);
+        }
+        if data.len() <= 8 {
+            return self.send_frame(DEFAULT_PRIORITY, pgn, dest, data);
+        }
+
+        log::trace!("J1939-TP - send PGN {:05X} to {:02X}: {}", pgn, dest, hex::encode(data));
+        let packets = J1939TpFrame::data_transfers(data)?;
+        {
+            let mut connections = self.tx_connections.lock()
+                .map_err(|_| Error::ContextError("can't get `tx_connections`".into()))?;
+            if connections.contains_key(&dest) {
+                return Err(Error::Aborted(format!("already in session with {:02X}", dest)));
+            }
+            connections.insert(dest, TxStatus::Waiting(Instant::now() + TIMEOUT_T3));
+        }
+
+        let result = self.send_util(pgn, dest, data.len(), &packets);
+        if let Ok(mut connections) = self.tx_connections.lock() {
+            connections.remove(&dest);
+        }
+        result
+    }
+
+    fn send_util(&self, pgn: u32, dest: u8, size: usize, packets: &[J1939TpFrame]) -> Result<(), Error> {
+        let count = packets.len() as u8;
+        self.send_tp_frame(&J1939TpFrame::Rts { size: size as u16, packets: count, max_packets: 0xFF, pgn }, dest)?;
+
+        loop {
+            let status = self.tx_connections.lock()
+                .map_err(|_| Error::ContextError("can't get `tx_connections`".into()))?
+                .get(&dest)
+                .copied();
+            match status {
+                Some(TxStatus::Waiting(deadline)) => {
+                    if Instant::now() > deadline {
+                        self.abort(pgn, dest, AbortReason::Timeout);
+                        return Err(Error::Timeout { value: TIMEOUT_T3.as_millis() as u64, unit: "ms" });
+                    }
+                    sleep(Duration::from_millis(1));
+                },
+                Some(TxStatus::Cts { packets: 0, .. }) => {
+                    // hold the connection
+                    self.set_tx_status(dest, TxStatus::Waiting(Instant::now() + TIMEOUT_T4));
+                },
+                Some(TxStatus::Cts { packets: n, next }) => {
+                    if next == 0 || next > count {
+                        self.abort(pgn, dest, AbortReason::BadSequence);
+                        return Err(Error::InvalidSequence { expect: 1, actual: next });
+                    }
+
+                    // the next CTS may be received before all packets are sent
+                    self.set_tx_status(dest, TxStatus::Waiting(Instant::now() + TIMEOUT_T3));
+                    let start = next as usize - 1;
+                    let end = (start + n as usize).min(packets.len());
+                    for packet in &packets[start..end] {
+                        self.send_tp_frame(packet, dest)?;
+                    }
+                },
+                Some(TxStatus::Completed) => return Ok(()),
+                Some(TxStatus::Aborted(reason)) =>
+                    return Err(Error::Aborted(format!("{:?} by {:02X}", reason, dest))),
+                None => return Err(Error::ContextError("connection is removed".into())),
+            }
+        }
+    }
+
+    #[inline]
+    fn set_tx_status(&self, dest: u8, status: TxStatus) {
+        if let Ok(mut connections) = self.tx_connections.lock() {
+            if let Some(v) = connections.get_mut(&dest) {
+                *v = status;
+            }
+        }
+    }
+
+    /// Abort the connection with `address` and notify the listener.
+    fn abort(&self, pgn: u32, address: u8, reason: AbortReason) {
+        log::warn!("J1939-TP - abort PGN {:05X} with {:02X}: {:?}", pgn, address, reason);
+        if let Err(e) = self.send_tp_frame(&J1939TpFrame::Abort { reason, pgn }, address) {
+            log::warn!("J1939-TP - abort failed: {}", e);
+        }
+        self.tp_event(J1939TpEvent::Aborted { pgn, address, reason });
+    }
+
+    pub(crate) fn send_tp_frame(&self, frame: &J1939TpFrame, dest: u8) -> Result<(), Error> {
+        self.send_frame(TP_PRIORITY, frame.pgn(), dest, &frame.encode())
+    }
+
+    /// Send a single frame of `pgn`, the `dest` is used by the PDU1 format only.
+    pub(crate) fn send_frame(&self, priority: u8, pgn: u32, dest: u8, data: &[u8]) -> Result<(), Error> {
+        let pgn = Pgn::from_bits(pgn);
+        let id = J1939Id::from_pgn(pgn, priority, self.address(), pgn.is_p2p().then_some(dest))?;
+        let mut frame = F::new(Id::Extended(id.into_bits()), data)
+            .ok_or(Error::ConvertError { src: "j1939 message", target: "can-frame" })?;
+        frame.set_channel(self.channel.clone());
+
+        self.sender.send(frame)
+            .map_err(|e| {
+                log::warn!("J1939-TP - transmit failed: {:?}", e);
+                Error::device(e)
+            })
+    }
+
+    #[inline]
+    pub(crate) fn tp_event(&self, event: J1939TpEvent) {
+        match self.listener.lock() {
+            Ok(mut listener) => listener.on_tp_event(event),
+            Err(_) => log::warn!("J1939-TP - listener error"),
+        }
+    }
+
+    /// Emit the reassembled message of `pgn` sent from `source` to `dest`.
+    fn data_received(&self, pgn: u32, source: u8, dest: u8, data: Vec<u8>) {
+        let pgn = Pgn::from_bits(pgn);
+        match J1939Id::from_pgn(pgn, DEFAULT_PRIORITY, source, pgn.is_p2p().then_some(dest)) {
+            Ok(id) => self.tp_event(J1939TpEvent::DataReceived(Message::from_parts(id, Pdu::Bytes(data)))),
+            Err(e) => log::warn!("J1939-TP - message from {:02X} discarded: {}", source, e),
+        }
+    }
+
+    pub(crate) fn on_bam(&self, source: u8, size: u16, packets: u8, pgn: u32) {
+        let size = size as usize;
+        if !(9..=TP_MAX_SIZE).contains(&size) || packets as usize != size.div_ceil(7) {
+            log::warn!("J1939-TP - invalid BAM from {:02X}: size {}, packets {}", source, size, packets);
+            return;
+        }
+
+        log::debug!("J1939-TP - BAM of PGN {:05X} from {:02X}: {} bytes", pgn, source, size);
+        if let Ok(mut sessions) = self.bam_sessions.lock() {
+            // a new BAM from the same source aborts the previous session
+            sessions.insert(source, BamSession {
+                pgn,
+                size,
+                packets,
+                buffer: Vec::with_capacity(packets as usize * 7),
+                last: Instant::now(),
+            });
+        }
+    }
+
+    /// Check the timeouts of the broadcast sessions and the received connections.
+    pub(crate) fn check_timeouts(&self) {
+        if let Ok(mut sessions) = self.bam_sessions.lock() {
+            sessions.retain(|source, v| {
+                let alive = v.last.elapsed() <= TIMEOUT_T1;
+                if !alive {
+                    log::warn!("J1939-TP - BAM of PGN {:05X} from {:02X} timed out", v.pgn, source);
+                }
+                alive
+            });
+        }
+
+        let now = Instant::now();
+        let expired = match self.rx_connections.lock() {
+            Ok(mut connections) => {
+                let expired = connections.iter()
+                    .filter(|(_, v)| now > v.deadline)
+                    .map(|(k, v)| (*k, v.pgn))
+                    .collect::<Vec<_>>();
+                expired.iter().for_each(|(k, _)| { connections.remove(k); });
+                expired
+            },
+            Err(_) => vec![],
+        };
+        for (source, pgn) in expired {
+            self.abort(pgn, source, AbortReason::Timeout);
+        }
+    }
+
+    /// The window of the next CTS from the packet `next`.
+    #[inline]
+    fn cts_window(&self, connection: &RxConnection, next: u8) -> u8 {
+        self.packets_per_cts
+            .min(connection.max_packets)
+            .min(connection.packets - next + 1)
+    }
+
+    pub(crate) fn on_rts(&self, source: u8, size: u16, packets: u8, max_packets: u8, pgn: u32) {
+        let size = size as usize;
+        if size < 9 || packets as usize != size.div_ceil(7) {
+            log::warn!("J1939-TP - invalid RTS from {:02X}: size {}, packets {}", source, size, packets);
+            self.abort(pgn, source, AbortReason::Other(250));
+            return;
+        }
+        if size > self.max_receive_size {
+            self.abort(pgn, source, AbortReason::ResourcesNeeded);
+            return;
+        }
+
+        log::debug!("J1939-TP - RTS of PGN {:05X} from {:02X}: {} bytes", pgn, source, size);
+        let mut connection = RxConnection {
+            pgn,
+            size,
+            packets,
+            max_packets: max_packets.max(1),
+            window_end: 0,
+            buffer: Vec::with_capacity(packets as usize * 7),
+            deadline: Instant::now() + TIMEOUT_T2,
+        };
+        let window = self.cts_window(&connection, 1);
+        connection.window_end = window;
+        if let Ok(mut connections) = self.rx_connections.lock() {
+            if connections.insert(source, connection).is_some() {
+                log::warn!("J1939-TP - the connection from {:02X} is restarted by RTS", source);
+            }
+        }
+
+        if let Err(e) = self.send_tp_frame(&J1939TpFrame::Cts { packets: window, next: 1, pgn }, source) {
+            log::warn!("J1939-TP - transmit CTS failed: {}", e);
+        }
+    }
+
+    /// Returns false if there is no connection from `source`.
+    pub(crate) fn on_rx_data(&self, source: u8, sequence: u8, data: &[u8; 7]) -> bool {
+        let Ok(mut connections) = self.rx_connections.lock() else {
+            return false;
+        };
+        let Some(connection) = connections.get_mut(&source) else {
+            return false;
+        };
+
+        let pgn = connection.pgn;
+        let expect = (connection.buffer.len() / 7) as u8 + 1;
+        let reason = if sequence < expect {
+            Some(AbortReason::DuplicateSequence)
+        }
+        else if sequence > connection.window_end {
+            Some(AbortReason::UnexpectedDataTransfer)
+        }
+        else if sequence != expect {
+            Some(AbortReason::BadSequence)
+        }
+        else {
+            None
+        };
+        if let Some(reason) = reason {
+            connections.remove(&source);
+            drop(connections);
+            self.abort(pgn, source, reason);
+            return true;
+        }
+
+        connection.buffer.extend_from_slice(data);
+        connection.deadline = Instant::now() + TIMEOUT_T1;
+        let response = if sequence == connection.packets {
+            connections.remove(&source)
+                .map(|mut v| {
+                    v.buffer.truncate(v.size);
+                    let frame = J1939TpFrame::EndOfMsgAck { size: v.size as u16, packets: v.packets, pgn };
+                    (frame, Some(v.buffer))
+                })
+        }
+        else if sequence == connection.window_end {
+            let window = self.cts_window(connection, sequence + 1);
+            connection.window_end = sequence + window;
+            connection.deadline = Instant::now() + TIMEOUT_T2;
+            Some((J1939TpFrame::Cts { packets: window, next: sequence + 1, pgn }, None))
+        }
+        else {
+            None
+        };
+        drop(connections);
+
+        if let Some((frame, data)) = response {
+            if let Err(e) = self.send_tp_frame(&frame, source) {
+                log::warn!("J1939-TP - transmit failed: {}", e);
+            }
+            if let Some(data) = data {
+                self.data_received(pgn, source, self.address(), data);
+            }
+        }
+
+        true
+    }
+
+    /// Handle the connection management frames from `source` to this node.
+    pub(crate) fn on_connection_frame(&self, source: u8, frame: J1939TpFrame) {
+        match frame {
+            J1939TpFrame::Rts { size, packets, max_packets, pgn } =>
+                self.on_rts(source, size, packets, max_packets, pgn),
+            J1939TpFrame::Cts { packets, next, .. } =>
+                self.set_tx_status(source, TxStatus::Cts { packets, next }),
+            J1939TpFrame::EndOfMsgAck { .. } =>
+                self.set_tx_status(source, TxStatus::Completed),
+            J1939TpFrame::Abort { reason, pgn } => {
+                log::warn!("J1939-TP - PGN {:05X} aborted by {:02X}: {:?}", pgn, source, reason);
+                self.set_tx_status(source, TxStatus::Aborted(reason));
+                if let Ok(mut connections) = self.rx_connections.lock() {
+                    connections.remove(&source);
+                }
+                self.tp_event(J1939TpEvent::Aborted { pgn, address: source, reason });
+            },
+            J1939TpFrame::DataTransfer { sequence, data } => {
+                if !self.on_rx_data(source, sequence, &data) {
+                    log::debug!("J1939-TP - unexpected packet from {:02X}", source);
+                }
+            },
+            J1939TpFrame::Bam { .. } =>
+                log::warn!("J1939-TP - BAM to a specific destination from {:02X}", source),
+        }
+    }
+
+    /// Returns false if there is no broadcast session of `source`.
+    pub(crate) fn on_bam_data(&self, source: u8, sequence: u8, data: &[u8; 7]) -> bool {
+        let Ok(mut sessions) = self.bam_sessions.lock() else {
+            return false;
+        };
+        let Some(session) = sessions.get_mut(&source) else {
+            return false;
+        };
+
+        let expect = (session.buffer.len() / 7) as u8 + 1;
+        if session.last.elapsed() > TIMEOUT_T1 {
+            log::warn!("J1939-TP - BAM of PGN {:05X} from {:02X} timed out", session.pgn, source);
+            sessions.remove(&source);
+            return true;
+        }
+        if sequence != expect {
+            log::warn!("J1939-TP - BAM from {:02X} discarded: {}", source,
+                Error::InvalidSequence { expect, actual: sequence });
+            sessions.remove(&source);
+            return true;
+        }
+
+        session.buffer.extend_from_slice(data);
+        session.last = Instant::now();
+        if sequence == session.packets {
+            if let Some(mut session) = sessions.remove(&source) {
+                drop(sessions);
+                session.buffer.truncate(session.size);
+                self.data_received(session.pgn, source, GLOBAL_ADDRESS, session.buffer);
+            }
+        }
+
+        true
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for J1939Tp<C, F>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, _: &F) {}
+
+    fn on_frame_received(&mut self, channel: C, frames: &[F]) {
+        if channel != self.channel {
+            return;
+        }
+
+        self.check_timeouts();
+        let address = self.address();
+        for frame in frames {
+            if !frame.is_extended() {
+                continue;
+            }
+
+            let id = J1939Id::from(frame.id());
+            let pgn = (id.pdu_format() as u32) << 8;
+            if pgn != PGN_TP_CM && pgn != PGN_TP_DT {
+                continue;
+            }
+            // the connection mode transfers to the other nodes
+            let dest = id.pdu_specific();
+            if dest != GLOBAL_ADDRESS && dest != address {
+                continue;
+            }
+
+            log::debug!("J1939-TP received: {}", frame);
+            let source = id.source_address_bits();
+            match J1939TpFrame::decode(pgn, frame.data()) {
+                Ok(frame) if dest != GLOBAL_ADDRESS => self.on_connection_frame(source, frame),
+                Ok(J1939TpFrame::Bam { size, packets, pgn }) =>
+                    self.on_bam(source, size, packets, pgn),
+                Ok(J1939TpFrame::DataTransfer { sequence, data }) => {
+                    self.on_bam_data(source, sequence, &data);
+                },
+                Ok(frame) => log::debug!("J1939-TP - ignored global frame from {:02X}: {:?}", source, frame),
+                Err(e) => log::warn!("J1939-TP - invalid frame from {:02X}: {}", source, e),
+            }
+        }
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use crate::can::driver::{SyncCan, VirtualBus, MOCK_CHANNEL};
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::j1939::{J1939Id, Message, Pdu};
+    use crate::can::message::CanMessage;
+    use crate::device::Listener;
+    use std::any::Any;
+    use std::thread::spawn;
+    use crate::error::Error;
+    use super::{AbortReason, J1939Tp, J1939TpEvent, J1939TpFrame, PGN_TP_CM, PGN_TP_DT};
+
+    type Collector = crate::can::testing::Collector<J1939TpEvent>;
+
+    /// Records the transmitted TP.CM frames.
+    #[derive(Clone, Default)]
+    struct CmRecorder(Arc<Mutex<Vec<J1939TpFrame>>>);
+
+    impl Listener<String, u32, CanMessage> for CmRecorder {
+        fn as_any(&self) -> &dyn Any {
+            self
+        }
+
+        fn on_frame_transmitting(&mut self, _: String, _: &CanMessage) {}
+
+        fn on_frame_transmitted(&mut self, _: String, frame: &CanMessage) {
+            if J1939Id::from(frame.id()).pdu_format() == 0xEC {
+                self.0.lock().unwrap().push(J1939TpFrame::decode(PGN_TP_CM, frame.data()).unwrap());
+            }
+        }
+
+        fn on_frame_received(&mut self, _: String, _: &[CanMessage]) {}
+    }
+
+    fn received(id: u32, data: Vec<u8>) -> J1939TpEvent {
+        J1939TpEvent::DataReceived(Message::from_parts(J1939Id::from_bits(id), Pdu::Bytes(data)))
+    }
+
+    fn tp_frame(source: u8, frame: &J1939TpFrame) -> CanMessage {
+        let id = J1939Id::from_raw_parts(7, false, (frame.pgn() >> 8) as u8, 0xFF, source).unwrap();
+        let mut result = CanMessage::new(Id::Extended(id.into_bits()), &frame.encode()).unwrap();
+        result.set_channel(MOCK_CHANNEL.into());
+        result
+    }
+
+    #[test]
+    fn test_frame() -> anyhow::Result<()> {
+        let data = (0..100).collect::<Vec<u8>>();
+        let frames = J1939TpFrame::from_bam(0xFEEC, &data)?;
+        assert_eq!(frames.len(), 16);
+        assert_eq!(frames[0], J1939TpFrame::Bam { size: 100, packets: 15, pgn: 0xFEEC });
+        assert_eq!(frames[0].encode(), [0x20, 0x64, 0x00, 0x0F, 0xFF, 0xEC, 0xFE, 0x00]);
+        assert_eq!(J1939TpFrame::decode(PGN_TP_CM, &frames[0].encode())?, frames[0]);
+        assert_eq!(frames[15].encode(), [0x0F, 98, 99, 0xFF, 0xFF, 0xFF, 0xFF, 0xFF]);
+        assert_eq!(J1939TpFrame::decode(PGN_TP_DT, &frames[15].encode())?, frames[15]);
+
+        assert!(J1939TpFrame::from_bam(0xFEEC, &[0; 8]).is_err());
+        assert!(J1939TpFrame::from_bam(0xFEEC, &[0; 1786]).is_err());
+        assert!(J1939TpFrame::decode(PGN_TP_CM, &[0x20, 0x64]).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn test_interleaved_bam() -> anyhow::Result<()> {
+        let (tx, _rx) = std::sync::mpsc::channel();
+        let collector = Collector::default();
+        let mut tp = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x20, tx, Box::new(collector.clone()));
+
+        let data1 = (0..20).collect::<Vec<u8>>();
+        let data2 = (100..130).collect::<Vec<u8>>();
+        let frames1 = J1939TpFrame::from_bam(0xFEEC, &data1)?;
+        let frames2 = J1939TpFrame::from_bam(0xFECA, &data2)?;
+        for (i, frame) in frames2.iter().enumerate() {
+            if let Some(frame) = frames1.get(i) {
+                tp.on_frame_received(MOCK_CHANNEL.into(), &[tp_frame(0x00, frame)]);
+            }
+            tp.on_frame_received(MOCK_CHANNEL.into(), &[tp_frame(0x03, frame)]);
+        }
+
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [
+            received(0x18FEEC00, data1),
+            received(0x18FECA03, data2),
+        ]);
+
+        // the out of sequence packet discards the session
+        let frames = J1939TpFrame::from_bam(0xFEEC, &[0x55; 20])?;
+        for frame in [&frames[0], &frames[2], &frames[1], &frames[3]] {
+            tp.on_frame_received(MOCK_CHANNEL.into(), &[tp_frame(0x00, frame)]);
+        }
+        assert_eq!(collector.0.lock().unwrap().len(), 2);
+        Ok(())
+    }
+
+    #[test]
+    fn test_bam() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut sender_can = SyncCan::new(a);
+        let mut receiver_can = SyncCan::new(b);
+
+        let sender = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x10, sender_can.sender(), Box::new(Collector::default()));
+        let collector = Collector::default();
+        let receiver = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x20, receiver_can.sender(), Box::new(collector.clone()));
+        sender_can.register_listener("tp".into(), Box::new(sender.clone()));
+        receiver_can.register_listener("tp".into(), Box::new(receiver));
+        sender_can.sync_start(100);
+        receiver_can.sync_start(100);
+
+        let data = (0..100).collect::<Vec<u8>>();
+        let start = Instant::now();
+        sender.broadcast(0xFEEC, &data)?;
+        // 15 intervals between the 16 packets
+        assert!(start.elapsed() >= Duration::from_millis(750));
+
+        let events = collector.wait(1, Duration::from_millis(100));
+        assert_eq!(events, [received(0x18FEEC10, data)]);
+
+        sender_can.stop();
+        receiver_can.stop();
+        Ok(())
+    }
+
+    #[test]
+    fn test_connection_frame() -> anyhow::Result<()> {
+        let frames = [
+            J1939TpFrame::Rts { size: 100, packets: 15, max_packets: 5, pgn: 0xEF00 },
+            J1939TpFrame::Cts { packets: 5, next: 6, pgn: 0xEF00 },
+            J1939TpFrame::EndOfMsgAck { size: 100, packets: 15, pgn: 0xEF00 },
+            J1939TpFrame::Abort { reason: AbortReason::Timeout, pgn: 0xEF00 },
+        ];
+        let encoded = [
+            [0x10, 0x64, 0x00, 0x0F, 0x05, 0x00, 0xEF, 0x00],
+            [0x11, 0x05, 0x06, 0xFF, 0xFF, 0x00, 0xEF, 0x00],
+            [0x13, 0x64, 0x00, 0x0F, 0xFF, 0x00, 0xEF, 0x00],
+            [0xFF, 0x03, 0xFF, 0xFF, 0xFF, 0x00, 0xEF, 0x00],
+        ];
+        for (frame, encoded) in frames.iter().zip(encoded) {
+            assert_eq!(frame.encode(), encoded);
+            assert_eq!(&J1939TpFrame::decode(PGN_TP_CM, &encoded)?, frame);
+        }
+        assert_eq!(AbortReason::from(250), AbortReason::Other(250));
+        Ok(())
+    }
+
+    #[test]
+    fn test_rts_cts() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(3);
+        let mut cans = (0..3).map(|i| SyncCan::new(bus.driver(i))).collect::<Vec<_>>();
+
+        let originator = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x10, cans[0].sender(), Box::new(Collector::default()));
+        cans[0].register_listener("tp".into(), Box::new(originator.clone()));
+        let mut responders = vec![];
+        for (i, address) in [(1, 0x20), (2, 0x30)] {
+            let collector = Collector::default();
+            let recorder = CmRecorder::default();
+            let responder = J1939Tp::new(MOCK_CHANNEL.to_string(), address, cans[i].sender(), Box::new(collector.clone()))
+                .with_packets_per_cts(5);
+            cans[i].register_listener("tp".into(), Box::new(responder));
+            cans[i].register_listener("recorder".into(), Box::new(recorder.clone()));
+            responders.push((address, collector, recorder));
+        }
+        cans.iter_mut().for_each(|c| c.sync_start(100));
+
+        // the simultaneous connections to different destinations
+        let data = (0..100).collect::<Vec<u8>>();
+        let tasks = responders.iter()
+            .map(|(address, _, _)| {
+                let (originator, data, address) = (originator.clone(), data.clone(), *address);
+                spawn(move || originator.send(0xEF00, address, &data))
+            })
+            .collect::<Vec<_>>();
+        for task in tasks {
+            task.join().unwrap()?;
+        }
+
+        for (address, collector, recorder) in responders {
+            let events = collector.wait(1, Duration::from_millis(100));
+            assert_eq!(events, [received(0x18EF0010 | ((address as u32) << 8), data.clone())]);
+            assert_eq!(recorder.0.lock().unwrap().as_slice(), [
+                J1939TpFrame::Cts { packets: 5, next: 1, pgn: 0xEF00 },
+                J1939TpFrame::Cts { packets: 5, next: 6, pgn: 0xEF00 },
+                J1939TpFrame::Cts { packets: 5, next: 11, pgn: 0xEF00 },
+                J1939TpFrame::EndOfMsgAck { size: 100, packets: 15, pgn: 0xEF00 },
+            ]);
+        }
+
+        cans.iter_mut().for_each(|c| c.stop());
+        Ok(())
+    }
+
+    #[test]
+    fn test_abort() -> anyhow::Result<()> {
+        let (a, b) = VirtualBus::pair();
+        let mut originator_can = SyncCan::new(a);
+        let mut responder_can = SyncCan::new(b);
+
+        let collector = Collector::default();
+        let originator = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x10, originator_can.sender(), Box::new(collector.clone()));
+        let responder = J1939Tp::new(MOCK_CHANNEL.to_string(), 0x20, responder_can.sender(), Box::new(Collector::default()))
+            .with_max_receive_size(50);
+        originator_can.register_listener("tp".into(), Box::new(originator.clone()));
+        responder_can.register_listener("tp".into(), Box::new(responder));
+        originator_can.sync_start(100);
+        responder_can.sync_start(100);
+
+        // aborted by the responder
+        let result = originator.send(0xEF00, 0x20, &[0x55; 100]);
+        assert_eq!(result, Err(Error::Aborted("ResourcesNeeded by 20".into())));
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [
+            J1939TpEvent::Aborted { pgn: 0xEF00, address: 0x20, reason: AbortReason::ResourcesNeeded },
+        ]);
+
+        // no responder
+        collector.0.lock().unwrap().clear();
+        let start = Instant::now();
+        let result = originator.send(0xEF00, 0x30, &[0x55; 20]);
+        assert_eq!(result, Err(Error::Timeout { value: 1250, unit: "ms" }));
+        assert!(start.elapsed() >= Duration::from_millis(1250));
+        assert_eq!(collector.0.lock().unwrap().as_slice(), [
+            J1939TpEvent::Aborted { pgn: 0xEF00, address: 0x30, reason: AbortReason::Timeout },
+        ]);
+
+        originator_can.stop();
+        responder_can.stop();
+        Ok(())
+    }
+}
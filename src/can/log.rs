@@ -0,0 +1,441 @@
+//! Write the frame streams to the log files of Vector(`.asc`) and PEAK(`.trc`),
+//! and read them from the candump and Vector logs.
+//!
+//! The written channels are numbered from 1 in the order of their first frame,
+//! the timestamps are relative to the measurement start and the dates are in UTC.
+
+mod reader;
+pub use reader::*;
+
+use std::any::Any;
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use crate::can::error_frame::ErrorInfo;
+use crate::can::frame::{asc_line, Direct, Frame, FrameMut};
+use crate::device::{Channel, Listener};
+
+/// A writer of the frame stream, used by [`LoggerListener`].
+pub trait FrameWriter<C: Channel>: Send {
+    fn write_frame(&mut self, frame: &dyn Frame<Channel = C>) -> std::io::Result<()>;
+    fn flush(&mut self) -> std::io::Result<()>;
+    /// Write the footer and flush, the frames written after it are ignored.
+    fn close(&mut self) -> std::io::Result<()>;
+}
+
+/// The 1-based numbers of the channels in the order of their first frame.
+#[derive(Debug, Default)]
+struct ChannelNumbers(Vec<String>);
+
+impl ChannelNumbers {
+    fn number(&mut self, channel: &impl Display) -> usize {
+        let name = channel.to_string();
+        match self.0.iter().position(|v| *v == name) {
+            Some(v) => v + 1,
+            None => {
+                self.0.push(name);
+                self.0.len()
+            },
+        }
+    }
+}
+
+/// The date and time in UTC: (year, month, day, weekday(0 = Sunday), hour, minute, second, millisecond).
+fn utc_parts(time: SystemTime) -> (i64, u32, u32, u32, u32, u32, u32, u32) {
+    let millis = time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as i64)
+        .unwrap_or_default();
+    let (days, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000) as u32);
+    // the civil date from days, see http://howardhinnant.github.io/date_algorithms.html
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    let weekday = (days + 4).rem_euclid(7) as u32;
+
+    (year, month, day, weekday, millis / 3_600_000, millis / 60_000 % 60, millis / 1000 % 60, millis % 1000)
+}
+
+#[inline]
+fn millis_of(time: SystemTime) -> u64 {
+    time.duration_since(UNIX_EPOCH)
+        .map(|v| v.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+#[inline]
+fn hex_data(data: &[u8]) -> String {
+    data.iter()
+        .map(|v| format!("{:02X}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+#[inline]
+fn direct(direct: Direct) -> &'static str {
+    match direct {
+        Direct::Transmit => "Tx",
+        Direct::Receive => "Rx",
+    }
+}
+
+/// Write the frames in the Vector ASC format opened by CANoe/CANalyzer.
+pub struct AscWriter<W: Write> {
+    writer: W,
+    start: u64,
+    channels: ChannelNumbers,
+    closed: bool,
+}
+
+impl AscWriter<BufWriter<File>> {
+    /// Create the file at `path`, see [`Self::new`].
+    pub fn create(path: impl AsRef<Path>, start: SystemTime) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), start)
+    }
+}
+
+impl<W: Write> AscWriter<W> {
+    /// Write the header with the measurement `start`, the frame timestamps are relative to it.
+    pub fn new(mut writer: W, start: SystemTime) -> std::io::Result<Self> {
+        const WEEKDAYS: [&str; 7] = ["Sun", "Mon", "Tue", "Wed", "Thu", "Fri", "Sat"];
+        const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+        let (year, month, day, weekday, hour, minute, second, millis) = utc_parts(start);
+        let date = format!("{} {} {:02} {:02}:{:02}:{:02}.{:03} {} {}",
+            WEEKDAYS[weekday as usize], MONTHS[month as usize - 1], day,
+            (hour + 11) % 12 + 1, minute, second, millis,
+            if hour < 12 { "am" } else { "pm" }, year);
+
+        writeln!(writer, "date {}", date)?;
+        writeln!(writer, "base hex  timestamps absolute")?;
+        writeln!(writer, "internal events logged")?;
+        writeln!(writer, "// version 9.0.0")?;
+        writeln!(writer, "Begin Triggerblock {}", date)?;
+        writeln!(writer, "{:>11.6} Start of measurement", 0.)?;
+
+        Ok(Self { writer, start: millis_of(start), channels: Default::default(), closed: false })
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        self.closed = true;
+        writeln!(self.writer, "End TriggerBlock")?;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for AscWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<W: Write + Send, C: Channel> FrameWriter<C> for AscWriter<W> {
+    fn write_frame(&mut self, frame: &dyn Frame<Channel = C>) -> std::io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        let time = frame.timestamp().saturating_sub(self.start);
+        let channel = self.channels.number(&frame.channel());
+        writeln!(self.writer, "{}", asc_line(frame, time, 6, &channel))
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.finish()
+    }
+}
+
+/// Write the frames in the PEAK TRC format(version 2.1) opened by PCAN-View.
+pub struct TrcWriter<W: Write> {
+    writer: W,
+    start: u64,
+    channels: ChannelNumbers,
+    count: usize,
+    closed: bool,
+}
+
+impl TrcWriter<BufWriter<File>> {
+    /// Create the file at `path`, see [`Self::new`].
+    pub fn create(path: impl AsRef<Path>, start: SystemTime) -> std::io::Result<Self> {
+        Self::new(BufWriter::new(File::create(path)?), start)
+    }
+}
+
+impl<W: Write> TrcWriter<W> {
+    /// Write the header with the measurement `start`, the frame timestamps are relative to it.
+    pub fn new(mut writer: W, start: SystemTime) -> std::io::Result<Self> {
+        // the OLE automation date, days since 1899-12-30
+        let millis = millis_of(start);
+        let ole_date = millis as f64 / 86_400_000. + 25_569.;
+        let (year, month, day, _, hour, minute, second, ms) = utc_parts(start);
+
+        writeln!(writer, ";$FILEVERSION=2.1")?;
+        writeln!(writer, ";$STARTTIME={:.10}", ole_date)?;
+        writeln!(writer, ";$COLUMNS=N,O,T,B,I,d,R,L,D")?;
+        writeln!(writer, ";")?;
+        writeln!(writer, ";   Start time: {:02}.{:02}.{} {:02}:{:02}:{:02}.{:03}.0", day, month, year, hour, minute, second, ms)?;
+        writeln!(writer, ";   Generated by isotp-rs")?;
+        writeln!(writer, ";-------------------------------------------------------------------------------")?;
+        writeln!(writer, ";   Message   Time    Type    ID     Rx/Tx")?;
+        writeln!(writer, ";   Number    Offset  |  Bus  [hex]  |  Reserved")?;
+        writeln!(writer, ";   |         [ms]    |  |    |      |  |  Data Length Code")?;
+        writeln!(writer, ";   |         |       |  |    |      |  |  |    Data [hex] ...")?;
+        writeln!(writer, ";   |         |       |  |    |      |  |  |    |")?;
+        writeln!(writer, ";---+-- ------+------ +- +- --+----- +- +- +--- +- -- -- -- -- -- -- --")?;
+
+        Ok(Self { writer, start: millis, channels: Default::default(), count: 0, closed: false })
+    }
+
+    fn finish(&mut self) -> std::io::Result<()> {
+        self.closed = true;
+        self.writer.flush()
+    }
+}
+
+impl<W: Write> Drop for TrcWriter<W> {
+    fn drop(&mut self) {
+        let _ = self.finish();
+    }
+}
+
+impl<W: Write + Send, C: Channel> FrameWriter<C> for TrcWriter<W> {
+    fn write_frame(&mut self, frame: &dyn Frame<Channel = C>) -> std::io::Result<()> {
+        if self.closed {
+            return Ok(());
+        }
+
+        self.count += 1;
+        let time = frame.timestamp().saturating_sub(self.start) as f64;
+        let channel = self.channels.number(&frame.channel());
+        let kind = match (frame.is_error_frame(), frame.is_can_fd(), frame.is_bitrate_switch(), frame.is_esi()) {
+            (true, ..) => "ER",
+            (false, true, false, false) => "FD",
+            (false, true, true, false) => "FB",
+            (false, true, false, true) => "FE",
+            (false, true, true, true) => "BI",
+            _ if frame.is_remote() => "RR",
+            _ => "DT",
+        };
+        let id = match (frame.is_error_frame(), frame.is_extended()) {
+            (true, _) => "-".to_owned(),
+            (false, true) => format!("{:08X}", frame.id().into_bits()),
+            (false, false) => format!("{:04X}", frame.id().into_bits()),
+        };
+        let data = if frame.is_remote() { String::new() } else { hex_data(frame.data()) };
+        let line = format!("{:>7} {:>13.3} {} {:>2} {:>8} {} -  {:<4} {}",
+            self.count, time, kind, channel, id, direct(frame.direct()), frame.dlc().unwrap_or_default(), data);
+
+        writeln!(self.writer, "{}", line.trim_end())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.writer.flush()
+    }
+
+    fn close(&mut self) -> std::io::Result<()> {
+        self.finish()
+    }
+}
+
+/// Write all the received and transmitted frames to the writer,
+/// register it as a [`Listener`] of [`SyncCan`](crate::can::driver::SyncCan).
+#[derive(Clone)]
+pub struct LoggerListener<C> {
+    writer: Arc<Mutex<Box<dyn FrameWriter<C>>>>,
+}
+
+impl<C: Channel> LoggerListener<C> {
+    pub fn new(writer: impl FrameWriter<C> + 'static) -> Self {
+        Self { writer: Arc::new(Mutex::new(Box::new(writer))) }
+    }
+
+    pub fn flush(&self) -> std::io::Result<()> {
+        match self.writer.lock() {
+            Ok(mut writer) => writer.flush(),
+            Err(e) => e.into_inner().flush(),
+        }
+    }
+
+    /// Write the footer and flush, the frames after it are not logged.
+    pub fn close(&self) -> std::io::Result<()> {
+        match self.writer.lock() {
+            Ok(mut writer) => writer.close(),
+            Err(e) => e.into_inner().close(),
+        }
+    }
+
+    fn write(&self, frame: &dyn Frame<Channel = C>) {
+        let result = match self.writer.lock() {
+            Ok(mut writer) => writer.write_frame(frame),
+            Err(e) => e.into_inner().write_frame(frame),
+        };
+        if let Err(e) = result {
+            log::warn!("CAN-LOG - write failed: {}", e);
+        }
+    }
+}
+
+impl<C, F> Listener<C, u32, F> for LoggerListener<C>
+where
+    C: Channel,
+    F: FrameMut<Channel = C> + Clone + Display + 'static {
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn on_frame_transmitting(&mut self, _: C, _: &F) {}
+
+    fn on_frame_transmitted(&mut self, _: C, frame: &F) {
+        self.write(frame);
+    }
+
+    fn on_frame_received(&mut self, _: C, frames: &[F]) {
+        for frame in frames {
+            let mut frame = frame.clone();
+            frame.set_direct(Direct::Receive);
+            self.write(&frame);
+        }
+    }
+
+    fn on_error_frame(&mut self, _: C, frame: &F, _: &ErrorInfo) {
+        let mut frame = frame.clone();
+        frame.set_direct(Direct::Receive);
+        self.write(&frame);
+    }
+}
+
+#[cfg(all(test, not(feature = "async")))]
+mod tests {
+    use std::io::Write;
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, SystemTime, UNIX_EPOCH};
+    use hex_literal::hex;
+    use crate::can::driver::ReplayDriver;
+    use crate::can::frame::{Direct, Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use crate::device::{Driver, Listener};
+    use super::{AscWriter, FrameWriter, LoggerListener, TrcWriter};
+
+    const GOLDEN_ASC: &str = include_str!("../../resources/frames.asc");
+    const GOLDEN_TRC: &str = include_str!("../../resources/frames.trc");
+    const START_MS: u64 = 1_700_000_000_000;
+
+    #[derive(Clone, Default)]
+    struct SharedBuffer(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for SharedBuffer {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl SharedBuffer {
+        fn content(&self) -> String {
+            String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+        }
+    }
+
+    fn start() -> SystemTime {
+        UNIX_EPOCH + Duration::from_millis(START_MS)
+    }
+
+    /// The classic, extended, remote, error and FD frames on 2 channels.
+    fn frames() -> Vec<CanMessage> {
+        let mut frames = vec![
+            CanMessage::new(0x7E0, &hex!("03 22 F1 90 AA AA AA AA")).unwrap(),
+            CanMessage::new(Id::Extended(0x18DAF110), &hex!("03 62 F1 90")).unwrap(),
+            CanMessage::new_remote(0x123, 8).unwrap(),
+            CanMessage::new(0, &[]).unwrap(),
+            CanMessage::new(Id::Extended(0x18DA10F1), &hex!("10 0A 62 F1 90 31 32 33 34 35 36 37")).unwrap(),
+            CanMessage::new(0x7E8, &[0x55; 16]).unwrap(),
+        ];
+        let channels = ["can0", "can0", "can1", "can0", "can1", "can0"];
+        let offsets = [0, 10, 11, 20, 30, 1_234_567];
+        let directs = [Direct::Transmit, Direct::Receive, Direct::Receive, Direct::Receive, Direct::Transmit, Direct::Receive];
+        for (i, frame) in frames.iter_mut().enumerate() {
+            frame.set_channel(channels[i].into())
+                .set_timestamp(Some(START_MS + offsets[i]))
+                .set_direct(directs[i]);
+        }
+        frames[3].set_error_frame(true);
+        frames[4].set_bitrate_switch(true);
+        frames[5].set_esi(true);
+        frames
+    }
+
+    #[test]
+    fn test_asc() -> anyhow::Result<()> {
+        let buffer = SharedBuffer::default();
+        let mut writer = AscWriter::new(buffer.clone(), start())?;
+        for frame in frames() {
+            writer.write_frame(&frame)?;
+        }
+        FrameWriter::<String>::close(&mut writer)?;
+        drop(writer);
+        assert_eq!(buffer.content(), GOLDEN_ASC);
+
+        // read back by the replay driver, the error frame excepted
+        let driver = ReplayDriver::from_asc(GOLDEN_ASC)?;
+        let expected = frames();
+        let replayed = driver.receive("1".into(), None)?;
+        assert_eq!(replayed.len(), 3);
+        assert_eq!(replayed.iter().map(|v| v.data()).collect::<Vec<_>>(),
+                   [expected[0].data(), expected[1].data(), expected[5].data()]);
+        let replayed = driver.receive("2".into(), None)?;
+        assert!(replayed[0].is_remote());
+        assert!(replayed[1].is_can_fd() && replayed[1].is_bitrate_switch());
+        assert_eq!(replayed[1].id(), expected[4].id());
+        Ok(())
+    }
+
+    #[test]
+    fn test_trc() -> anyhow::Result<()> {
+        let buffer = SharedBuffer::default();
+        let mut writer = TrcWriter::new(buffer.clone(), start())?;
+        for frame in frames() {
+            writer.write_frame(&frame)?;
+        }
+        drop(writer);
+        assert_eq!(buffer.content(), GOLDEN_TRC);
+        Ok(())
+    }
+
+    #[test]
+    fn test_logger_listener() -> anyhow::Result<()> {
+        let buffer = SharedBuffer::default();
+        let mut logger = LoggerListener::new(TrcWriter::new(buffer.clone(), start())?);
+        let frames = frames();
+        Listener::<String, u32, CanMessage>::on_frame_transmitted(&mut logger, "can0".into(), &frames[0]);
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut logger, "can0".into(), &frames[1..2]);
+        logger.close()?;
+        Listener::<String, u32, CanMessage>::on_frame_received(&mut logger, "can0".into(), &frames[1..2]);
+
+        let content = buffer.content();
+        let lines = content.lines()
+            .filter(|v| !v.starts_with(';'))
+            .collect::<Vec<_>>();
+        assert_eq!(lines, GOLDEN_TRC.lines().filter(|v| !v.starts_with(';')).take(2).collect::<Vec<_>>());
+        Ok(())
+    }
+}
@@ -0,0 +1,351 @@
+//! Read the frames from the log files of candump(`candump -l`) and Vector(`.asc`).
+
+use std::fs::File;
+use std::io::{BufRead, BufReader, Lines};
+use std::path::Path;
+use crate::can::frame::{Direct, Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::can::message::CanMessage;
+
+/// The errors of reading a log file, an invalid line doesn't stop the reader.
+#[derive(Debug, thiserror::Error)]
+pub enum LogReadError {
+    #[error("CAN-LOG - read failed: {0}")]
+    Io(#[from] std::io::Error),
+    #[error("CAN-LOG - invalid line {line}: {content}")]
+    InvalidLine { line: usize, content: String },
+}
+
+/// A frame read from a log file: the timestamp(μs), the channel and the frame.
+pub type LogItem = (u64, String, CanMessage);
+
+/// The lines of a log file with their 1-based numbers.
+struct NumberedLines<R> {
+    lines: Lines<R>,
+    number: usize,
+}
+
+impl<R: BufRead> NumberedLines<R> {
+    fn new(reader: R) -> Self {
+        Self { lines: reader.lines(), number: 0 }
+    }
+
+    /// Returns the next non-empty line parsed by `parser`, the lines parsed to `None` are skipped.
+    fn next_item<P>(&mut self, mut parser: P) -> Option<Result<LogItem, LogReadError>>
+    where
+        P: FnMut(&str) -> Result<Option<(u64, CanMessage)>, ()> {
+        loop {
+            let line = match self.lines.next()? {
+                Ok(v) => v,
+                Err(e) => return Some(Err(e.into())),
+            };
+            self.number += 1;
+            let line = line.trim();
+            if line.is_empty() {
+                continue;
+            }
+
+            match parser(line) {
+                Ok(Some((timestamp, frame))) => return Some(Ok((timestamp, frame.channel(), frame))),
+                Ok(None) => continue,
+                Err(_) => return Some(Err(LogReadError::InvalidLine { line: self.number, content: line.to_owned() })),
+            }
+        }
+    }
+}
+
+/// Iterate the frames of a candump log, e.g. `(1651135234.123456) can0 1A2#DEADBEEF`.
+///
+/// The remote(`id#R[len]`) and CAN-FD(`id##<flags>data`) frames are supported,
+/// the timestamp is optional(0 if missing) and the comment lines start with `#`.
+pub struct CandumpReader<R> {
+    lines: NumberedLines<R>,
+}
+
+impl CandumpReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> CandumpReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: NumberedLines::new(reader) }
+    }
+}
+
+impl<R: BufRead> Iterator for CandumpReader<R> {
+    type Item = Result<LogItem, LogReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.lines.next_item(parse_candump)
+    }
+}
+
+/// Iterate the frames of a Vector ASC file, the channel is the channel number of the file.
+///
+/// The header, the events and the error frames are skipped, the ids are decimal after `base dec`.
+pub struct AscReader<R> {
+    lines: NumberedLines<R>,
+    radix: u32,
+}
+
+impl AscReader<BufReader<File>> {
+    pub fn open(path: impl AsRef<Path>) -> std::io::Result<Self> {
+        Ok(Self::new(BufReader::new(File::open(path)?)))
+    }
+}
+
+impl<R: BufRead> AscReader<R> {
+    pub fn new(reader: R) -> Self {
+        Self { lines: NumberedLines::new(reader), radix: 16 }
+    }
+}
+
+impl<R: BufRead> Iterator for AscReader<R> {
+    type Item = Result<LogItem, LogReadError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let radix = &mut self.radix;
+        self.lines.next_item(|line| {
+            if let Some(base) = line.strip_prefix("base ") {
+                *radix = if base.starts_with("dec") { 10 } else { 16 };
+                return Ok(None);
+            }
+            parse_asc(line, *radix)
+        })
+    }
+}
+
+/// Convert the timestamp in seconds(e.g. `1436509052.249713`) to μs.
+fn parse_seconds(value: &str) -> Option<u64> {
+    let (secs, frac) = value.split_once('.').unwrap_or((value, "0"));
+    let frac = format!("{:0<6}", frac);
+    Some(secs.parse::<u64>().ok()? * 1_000_000 + frac.get(..6)?.parse::<u64>().ok()?)
+}
+
+fn new_frame(id: Id, data: &[u8], channel: &str, timestamp: u64) -> Option<CanMessage> {
+    let mut frame = CanMessage::new(id, data)?;
+    frame.set_channel(channel.into())
+        .set_direct(Direct::Receive)
+        .set_timestamp(Some(timestamp / 1000));
+    Some(frame)
+}
+
+#[inline]
+fn parse_id(id: &str, radix: u32, extended: bool) -> Result<Id, ()> {
+    let bits = u32::from_str_radix(id, radix).map_err(|_| ())?;
+    Id::try_from_bits(bits, extended).ok_or(())
+}
+
+/// `[(timestamp)] channel id#data [R|T]`, `id#R[len]` for remote and `id##<flags>data` for CAN-FD.
+fn parse_candump(line: &str) -> Result<Option<(u64, CanMessage)>, ()> {
+    if line.starts_with('#') {
+        return Ok(None);
+    }
+
+    let mut items = line.split_whitespace().peekable();
+    let timestamp = match items.peek() {
+        Some(v) if v.starts_with('(') => items.next()
+            .and_then(|v| v.strip_prefix('('))
+            .and_then(|v| v.strip_suffix(')'))
+            .and_then(parse_seconds)
+            .ok_or(())?,
+        _ => 0,
+    };
+    let channel = items.next().ok_or(())?;
+    let (id, data) = items.next()
+        .and_then(|v| v.split_once('#'))
+        .ok_or(())?;
+    let id = parse_id(id, 16, id.len() > 3)?;
+
+    let mut frame = if let Some(remote) = data.strip_prefix('R').or(data.strip_prefix('r')) {
+        let len = if remote.is_empty() { 0 } else { remote.parse().map_err(|_| ())? };
+        let mut frame = CanMessage::new_remote(id, len).ok_or(())?;
+        frame.set_channel(channel.into())
+            .set_direct(Direct::Receive)
+            .set_timestamp(Some(timestamp / 1000));
+        frame
+    }
+    else if let Some(fd) = data.strip_prefix('#') {
+        let flags = fd.get(..1)
+            .and_then(|v| u8::from_str_radix(v, 16).ok())
+            .ok_or(())?;
+        let data = hex::decode(&fd[1..]).map_err(|_| ())?;
+        let mut frame = new_frame(id, &data, channel, timestamp).ok_or(())?;
+        frame.set_can_fd(true)
+            .set_bitrate_switch(flags & 0x01 > 0)
+            .set_esi(flags & 0x02 > 0);
+        frame
+    }
+    else {
+        let data = hex::decode(data).map_err(|_| ())?;
+        new_frame(id, &data, channel, timestamp).ok_or(())?
+    };
+    // the direction of `candump -x`
+    match items.next() {
+        Some("T") => { frame.set_direct(Direct::Transmit); },
+        Some("R") | None => {},
+        Some(_) => return Err(()),
+    }
+
+    Ok(Some((timestamp, frame)))
+}
+
+/// The classic `timestamp channel id[x] Rx|Tx d|r len data..` and
+/// `timestamp CANFD channel Rx|Tx id brs esi dlc len data..` lines, others lines are ignored.
+fn parse_asc(line: &str, radix: u32) -> Result<Option<(u64, CanMessage)>, ()> {
+    let items = line.split_whitespace().collect::<Vec<_>>();
+    let Some(timestamp) = items.first().and_then(|v| parse_seconds(v)) else {
+        // the header lines
+        return Ok(None);
+    };
+
+    let (fd, items) = match items.get(1) {
+        Some(&"CANFD") => (true, &items[2..]),
+        _ => (false, &items[1..]),
+    };
+    // the channel must be a number, e.g. `1`, the event lines(e.g. `Start of measurement`) are ignored
+    match items.first() {
+        Some(v) if v.parse::<u32>().is_ok() => {},
+        _ => return Ok(None),
+    }
+    let channel = items[0];
+
+    let frame = if fd {
+        let [_, direct, id, brs, esi, _, len, data @ ..] = items else {
+            return Err(());
+        };
+        let (id, extended) = id.strip_suffix('x').map_or((*id, false), |v| (v, true));
+        let id = parse_id(id, radix, extended)?;
+        let len = len.parse::<usize>().map_err(|_| ())?;
+        let data = data.iter().take(len)
+            .map(|v| u8::from_str_radix(v, 16))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| ())?;
+        if data.len() != len {
+            return Err(());
+        }
+        let mut frame = new_frame(id, &data, channel, timestamp).ok_or(())?;
+        frame.set_can_fd(true)
+            .set_bitrate_switch(*brs == "1")
+            .set_esi(*esi == "1")
+            .set_direct(asc_direct(direct));
+        frame
+    }
+    else {
+        let [_, id, direct, kind, len, data @ ..] = items else {
+            // e.g. the error frame lines
+            return Ok(None);
+        };
+        let (id, extended) = id.strip_suffix('x').map_or((*id, false), |v| (v, true));
+        let id = parse_id(id, radix, extended)?;
+        let len = usize::from_str_radix(len, 16).map_err(|_| ())?;
+        let mut frame = match *kind {
+            "r" | "R" => {
+                let mut frame = CanMessage::new_remote(id, len).ok_or(())?;
+                frame.set_channel(channel.into())
+                    .set_timestamp(Some(timestamp / 1000));
+                frame
+            },
+            "d" | "D" => {
+                let data = data.iter().take(len)
+                    .map(|v| u8::from_str_radix(v, 16))
+                    .collect::<Result<Vec<_>, _>>()
+                    .map_err(|_| ())?;
+                if data.len() != len {
+                    return Err(());
+                }
+                new_frame(id, &data, channel, timestamp).ok_or(())?
+            },
+            _ => return Err(()),
+        };
+        frame.set_direct(asc_direct(direct));
+        frame
+    };
+
+    Ok(Some((timestamp, frame)))
+}
+
+#[inline]
+fn asc_direct(value: &str) -> Direct {
+    if value.eq_ignore_ascii_case("tx") { Direct::Transmit } else { Direct::Receive }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::can::frame::{Direct, Frame};
+    use crate::can::identifier::Id;
+    use super::{AscReader, CandumpReader, LogReadError};
+
+    const CANDUMP: &str = include_str!("../../../resources/frames.log");
+    const ASC: &str = include_str!("../../../resources/frames.asc");
+
+    #[test]
+    fn test_candump() {
+        let items = CandumpReader::new(CANDUMP.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(items.len(), 7);
+
+        let frames = items.iter()
+            .filter_map(|v| v.as_ref().ok())
+            .collect::<Vec<_>>();
+        // classic
+        let (timestamp, channel, frame) = frames[0];
+        assert_eq!((*timestamp, channel.as_str()), (1651135234123456, "can0"));
+        assert_eq!(frame.id(), Id::Standard(0x1A2));
+        assert_eq!(frame.data(), hex!("DE AD BE EF"));
+        assert_eq!(frame.timestamp(), 1651135234123);
+        // extended
+        assert_eq!(frames[1].2.id(), Id::Extended(0x18DAF110));
+        assert_eq!(frames[1].2.direct(), Direct::Transmit);
+        // remote
+        assert!(frames[2].2.is_remote());
+        assert_eq!(frames[2].2.length(), 8);
+        // CAN-FD
+        let frame = &frames[3].2;
+        assert!(frame.is_can_fd() && frame.is_bitrate_switch() && !frame.is_esi());
+        assert_eq!(frame.length(), 12);
+        // CAN-FD without timestamp
+        let (timestamp, channel, frame) = frames[4];
+        assert_eq!((*timestamp, channel.as_str()), (0, "can1"));
+        assert!(frame.is_can_fd() && frame.is_bitrate_switch());
+        assert_eq!(frame.data(), hex!("DE AD BE EF"));
+
+        // the malformed lines are reported with the line numbers
+        let errors = items.iter()
+            .filter_map(|v| match v {
+                Err(LogReadError::InvalidLine { line, .. }) => Some(*line),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(errors, [7, 9]);
+    }
+
+    #[test]
+    fn test_asc() -> anyhow::Result<()> {
+        let items = AscReader::new(ASC.as_bytes()).collect::<Result<Vec<_>, _>>()?;
+        // the error frame is skipped
+        assert_eq!(items.len(), 5);
+        assert_eq!(items.iter().map(|(t, c, _)| (*t, c.as_str())).collect::<Vec<_>>(), [
+            (0, "1"), (10_000, "1"), (11_000, "2"), (30_000, "2"), (1_234_567_000, "1"),
+        ]);
+        assert_eq!(items[1].2.id(), Id::Extended(0x18DAF110));
+        assert_eq!(items[1].2.data(), hex!("03 62 F1 90"));
+        assert!(items[2].2.is_remote());
+        let frame = &items[4].2;
+        assert!(frame.is_can_fd() && frame.is_esi());
+        assert_eq!(frame.data(), [0x55; 16]);
+
+        // decimal ids and malformed lines
+        let content = "base dec  timestamps absolute\n\
+            0.010000 1  2016            Tx   d 3 02 10 01\n\
+            0.020000 1  2024            Rx   d 8 06 50 01\n\
+            0.030000 1  2024            Rx   d 3 06 50 01\n";
+        let items = AscReader::new(content.as_bytes()).collect::<Vec<_>>();
+        assert_eq!(items[0].as_ref().unwrap().2.id(), Id::Standard(0x7E0));
+        assert!(matches!(items[1], Err(LogReadError::InvalidLine { line: 3, .. })));
+        assert_eq!(items[2].as_ref().unwrap().2.id(), Id::Standard(0x7E8));
+        Ok(())
+    }
+}
@@ -0,0 +1,296 @@
+//! The matching of the frames received by an ISO-TP address.
+
+use alloc::{vec, vec::Vec};
+use crate::can::{Address, AddressFormat, AddressType, EFF_MASK, NORMAL_FIXED_FUNCTIONAL, NORMAL_FIXED_PHYSICAL, SFF_MASK};
+use crate::can::frame::Frame;
+use crate::device::FilterSpec;
+
+/// The bits of the normal fixed identifiers compared, the priority is ignored.
+const NORMAL_FIXED_MASK: u32 = 0x03FF_FFFF;
+
+/// Match the received frames against an [`Address`], shared by the ISO-TP listeners.
+///
+/// By default only `rx_id` is accepted and the extended flag of the frame is ignored,
+/// as the listeners did before.
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+pub struct RxMatcher {
+    address: Address,
+    format: AddressFormat,
+    mask: u32,
+    functional: bool,
+    extended: Option<bool>,
+    extension: Option<u8>,
+}
+
+impl RxMatcher {
+    pub fn new(address: Address, format: AddressFormat) -> Self {
+        Self {
+            address,
+            format,
+            mask: u32::MAX,
+            functional: false,
+            extended: None,
+            extension: None,
+        }
+    }
+
+    /// Replace the address, the config is kept.
+    #[inline]
+    pub fn with_address(mut self, address: Address) -> Self {
+        self.address = address;
+        self
+    }
+
+    /// Set the address format, the [`AddressFormat::NormalFixed`] matches the source and target
+    /// addresses and ignores the priority.
+    #[inline]
+    pub fn with_format(mut self, format: AddressFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Set the bits of the identifier compared, all by default. It isn't applied to
+    /// [`AddressFormat::NormalFixed`].
+    #[inline]
+    pub fn with_mask(mut self, mask: u32) -> Self {
+        self.mask = mask;
+        self
+    }
+
+    /// Accept the frames to the functional address `fid` too.
+    #[inline]
+    pub fn with_functional(mut self, functional: bool) -> Self {
+        self.functional = functional;
+        self
+    }
+
+    /// Accept the extended frames only by `Some(true)` or the standard frames only by `Some(false)`.
+    #[inline]
+    pub fn with_extended(mut self, extended: Option<bool>) -> Self {
+        self.extended = extended;
+        self
+    }
+
+    /// Accept the frames whose first byte is the address extension only,
+    /// see [`payload`](Self::payload).
+    #[inline]
+    pub fn with_address_extension(mut self, extension: Option<u8>) -> Self {
+        self.extension = extension;
+        self
+    }
+
+    #[inline]
+    pub fn address(&self) -> Address {
+        self.address
+    }
+
+    #[inline]
+    pub fn format(&self) -> AddressFormat {
+        self.format
+    }
+
+    /// Returns the address type the frame is received by, `None` if it isn't for the address.
+    pub fn matches(&self, frame: &impl Frame) -> Option<AddressType> {
+        if self.extended.is_some_and(|v| v != frame.is_extended()) {
+            return None;
+        }
+        if self.extension.is_some() && frame.data().first().copied() != self.extension {
+            return None;
+        }
+
+        self.matches_id(frame.id().into_bits())
+    }
+
+    /// Returns the address type of the identifier only, the frame flags and data aren't checked.
+    pub fn matches_id(&self, id: u32) -> Option<AddressType> {
+        let physical = match self.format {
+            AddressFormat::NormalFixed => self.address.is_rx(id, self.format),
+            _ => self.is_masked(id, self.address.rx_id),
+        };
+        if physical {
+            return Some(AddressType::Physical);
+        }
+        if self.functional && self.is_functional(id) {
+            return Some(AddressType::Functional);
+        }
+
+        None
+    }
+
+    /// The acceptance filters of the frames matched by the identifier, see [`Driver::set_filters`](crate::device::Driver::set_filters).
+    ///
+    /// The frames by both formats are accepted when the identifier fits a standard one and the
+    /// format isn't configured by [`with_extended`](Self::with_extended).
+    pub fn filters(&self) -> Vec<FilterSpec> {
+        let mut ids = vec![self.address.rx_id];
+        if self.functional {
+            ids.push(self.address.fid);
+        }
+
+        match self.format {
+            AddressFormat::NormalFixed => ids.into_iter()
+                .zip([NORMAL_FIXED_PHYSICAL, NORMAL_FIXED_FUNCTIONAL])
+                .map(|(id, base)| FilterSpec::new(base & NORMAL_FIXED_MASK | id & 0xFFFF, NORMAL_FIXED_MASK, true))
+                .collect(),
+            _ => ids.into_iter()
+                .flat_map(|id| {
+                    let extended = match self.extended {
+                        Some(v) => vec![v],
+                        None if id > SFF_MASK => vec![true],
+                        None => vec![false, true],
+                    };
+                    extended.into_iter()
+                        .map(move |v| {
+                            let mask = self.mask & if v { EFF_MASK } else { SFF_MASK };
+                            FilterSpec::new(id & mask, mask, v)
+                        })
+                })
+                .collect(),
+        }
+    }
+
+    /// The ISO-TP data of a matched frame, the address extension is skipped when it's configured.
+    #[inline]
+    pub fn payload<'a>(&self, data: &'a [u8]) -> &'a [u8] {
+        match self.extension {
+            Some(_) => data.get(1..).unwrap_or_default(),
+            None => data,
+        }
+    }
+
+    #[inline]
+    fn is_masked(&self, id: u32, target: u32) -> bool {
+        id & self.mask == target & self.mask
+    }
+
+    fn is_functional(&self, id: u32) -> bool {
+        match self.format {
+            AddressFormat::NormalFixed => match (Address::parse_normal_fixed(id), Address::parse_normal_fixed(self.address.fid)) {
+                (Some((source, target, AddressType::Functional)), Some((f_source, f_target, _))) =>
+                    source == f_source && target == f_target,
+                _ => false,
+            },
+            _ => self.is_masked(id, self.address.fid),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::can::{Address, AddressFormat, AddressType};
+    use crate::can::frame::FrameMut;
+    use crate::can::identifier::Id;
+    use crate::can::message::CanMessage;
+    use crate::device::FilterSpec;
+    use super::RxMatcher;
+
+    const ADDRESS: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+
+    fn frame(id: Id, data: &[u8]) -> CanMessage {
+        CanMessage::new(id, data).unwrap()
+    }
+
+    #[test]
+    fn test_physical() {
+        let matcher = RxMatcher::new(ADDRESS, AddressFormat::Normal);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &[0x02, 0x10, 0x01])), Some(AddressType::Physical));
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E9), &[0x02, 0x10, 0x01])), None);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E0), &[0x02, 0x10, 0x01])), None);
+        // the functional address is accepted by the config only
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7DF), &[0x02, 0x10, 0x01])), None);
+        let matcher = matcher.with_functional(true);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7DF), &[0x02, 0x10, 0x01])), Some(AddressType::Functional));
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &[0x02, 0x10, 0x01])), Some(AddressType::Physical));
+    }
+
+    #[test]
+    fn test_extended() {
+        // the standard and the extended identifiers of the same bits
+        let standard = frame(Id::Standard(0x7E8), &[0x02, 0x10, 0x01]);
+        let extended = frame(Id::Extended(0x7E8), &[0x02, 0x10, 0x01]);
+
+        let matcher = RxMatcher::new(ADDRESS, AddressFormat::Normal);
+        assert_eq!(matcher.matches(&standard), Some(AddressType::Physical));
+        assert_eq!(matcher.matches(&extended), Some(AddressType::Physical));
+
+        let matcher = matcher.with_extended(Some(false));
+        assert_eq!(matcher.matches(&standard), Some(AddressType::Physical));
+        assert_eq!(matcher.matches(&extended), None);
+
+        let matcher = matcher.with_extended(Some(true));
+        assert_eq!(matcher.matches(&standard), None);
+        assert_eq!(matcher.matches(&extended), Some(AddressType::Physical));
+    }
+
+    #[test]
+    fn test_mask() {
+        let matcher = RxMatcher::new(ADDRESS, AddressFormat::Normal)
+            .with_mask(0x7F0);
+        for id in 0x7E0..=0x7EF {
+            assert_eq!(matcher.matches_id(id), Some(AddressType::Physical), "{:03X}", id);
+        }
+        assert_eq!(matcher.matches_id(0x7F0), None);
+        assert_eq!(matcher.matches_id(0x7DF), None);
+
+        let matcher = matcher.with_functional(true);
+        assert_eq!(matcher.matches_id(0x7D0), Some(AddressType::Functional));
+        assert_eq!(matcher.matches_id(0x7E3), Some(AddressType::Physical));
+    }
+
+    #[test]
+    fn test_normal_fixed() {
+        // tester 0xF1 to engine 0x00
+        let matcher = RxMatcher::new(Address::normal_fixed(0xF1, 0x00), AddressFormat::NormalFixed)
+            .with_mask(0);
+        assert_eq!(matcher.matches_id(0x18DAF100), Some(AddressType::Physical));
+        // the priority is ignored
+        assert_eq!(matcher.matches_id(0x1CDAF100), Some(AddressType::Physical));
+        // the mask isn't applied
+        assert_eq!(matcher.matches_id(0x18DAF117), None);
+        assert_eq!(matcher.matches_id(0x18DB00F1), None);
+
+        let matcher = matcher.with_functional(true);
+        assert_eq!(matcher.matches_id(0x18DB00F1), Some(AddressType::Functional));
+        assert_eq!(matcher.matches_id(0x18DA00F1), None);
+        assert_eq!(matcher.matches_id(0x18DB17F1), None);
+    }
+
+    #[test]
+    fn test_address_extension() {
+        let matcher = RxMatcher::new(ADDRESS, AddressFormat::ExtendMixed)
+            .with_address_extension(Some(0xF1));
+        let data = [0xF1, 0x02, 0x10, 0x01];
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &data)), Some(AddressType::Physical));
+        assert_eq!(matcher.payload(&data), &data[1..]);
+        // AE mismatch
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &[0xF2, 0x02, 0x10, 0x01])), None);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &[])), None);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E9), &data)), None);
+
+        let matcher = matcher.with_address_extension(None);
+        assert_eq!(matcher.matches(&frame(Id::Standard(0x7E8), &[0xF2, 0x02, 0x10, 0x01])), Some(AddressType::Physical));
+        assert_eq!(matcher.payload(&data), &data);
+    }
+
+    #[test]
+    fn test_filters() {
+        let matcher = RxMatcher::new(ADDRESS, AddressFormat::Normal);
+        assert_eq!(matcher.filters(), [FilterSpec::new(0x7E8, 0x7FF, false), FilterSpec::new(0x7E8, 0x1FFF_FFFF, true)]);
+        let matcher = matcher.with_extended(Some(false))
+            .with_functional(true)
+            .with_mask(0x7F0);
+        assert_eq!(matcher.filters(), [FilterSpec::new(0x7E0, 0x7F0, false), FilterSpec::new(0x7D0, 0x7F0, false)]);
+
+        // the priority is ignored
+        let matcher = RxMatcher::new(Address::normal_fixed(0xF1, 0x00), AddressFormat::NormalFixed)
+            .with_functional(true);
+        let filters = matcher.filters();
+        assert_eq!(filters, [FilterSpec::new(0x00DA_F100, 0x03FF_FFFF, true), FilterSpec::new(0x00DB_00F1, 0x03FF_FFFF, true)]);
+        for id in [0x18DA_F100, 0x1CDA_F100, 0x18DB_00F1] {
+            assert!(filters.iter().any(|v| v.matches(id, true)), "{:08X}", id);
+            assert!(matcher.matches_id(id).is_some(), "{:08X}", id);
+        }
+        assert!(!filters.iter().any(|v| v.matches(0x18DA_00F1, true)));
+        assert!(!filters.iter().any(|v| v.matches(0x18DA_F100, false)));
+    }
+}
@@ -0,0 +1,551 @@
+//! Concrete CAN frame type.
+
+use alloc::{format, string::String, vec::Vec};
+use core::fmt::{Display, Formatter};
+use crate::can::constant::{CAN_FRAME_MAX_SIZE, CANFD_FRAME_MAX_SIZE, IdentifierFlags};
+use crate::can::dlc::{len_to_dlc, pad_len};
+use crate::can::frame::{Direct, Frame, FrameMut, now_millis};
+use crate::can::identifier::Id;
+use crate::error::Error;
+
+/// A generic CAN 2.0/CAN-FD frame that implements [`Frame`].
+///
+/// The channel is identified by its name (e.g. `can0`).
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CanMessage {
+    timestamp: u64,
+    arbitration_id: u32,
+    is_extended_id: bool,
+    is_remote_frame: bool,
+    is_error_frame: bool,
+    channel: String,
+    length: usize,
+    data: Vec<u8>,
+    is_fd: bool,
+    direct: Direct,
+    bitrate_switch: bool,
+    error_state_indicator: bool,
+}
+
+impl CanMessage {
+    /// Create a frame by [`CanMessageBuilder`], the combination is validated by [`CanMessageBuilder::build`].
+    #[inline]
+    pub fn builder() -> CanMessageBuilder {
+        Default::default()
+    }
+
+    /// Replace the data, the length of a CAN 2.0 frame is 0 ~ 8, see [`FrameMut::set_can_fd`],
+    /// and the length of a CAN-FD frame is one of the CAN-FD lengths.
+    pub fn set_data(&mut self, data: &[u8]) -> Result<(), Error> {
+        if self.is_remote_frame {
+            return Err(Error::InvalidParam("the remote frame has no data".into()));
+        }
+        check_length(data.len(), self.is_fd)?;
+
+        self.length = data.len();
+        self.data = data.to_vec();
+        Ok(())
+    }
+
+    /// Create a data frame with `data` padded by `padding` to the next valid length,
+    /// `None` if `data` is longer than 64 bytes.
+    pub fn new_padded(id: impl Into<Id>, data: &[u8], padding: u8) -> Option<Self> {
+        let mut data = data.to_vec();
+        data.resize(pad_len(data.len())?, padding);
+        Self::new(id, &data)
+    }
+
+    /// Returns the all-in-one 32-bit identifier of SocketCAN with the frame type flags.
+    pub fn to_socketcan_id(&self) -> u32 {
+        let mut flags = IdentifierFlags::empty();
+        flags.set(IdentifierFlags::REMOTE, self.is_remote_frame);
+        flags.set(IdentifierFlags::ERROR, self.is_error_frame);
+        self.id().into_raw_with(flags)
+    }
+
+    /// Create a frame from the all-in-one 32-bit identifier of SocketCAN,
+    /// the length of a remote request is the length of `data`.
+    pub fn from_socketcan_id(raw: u32, data: &[u8]) -> Option<Self> {
+        let (id, flags) = Id::from_raw(raw);
+        let mut frame = if flags.contains(IdentifierFlags::REMOTE) {
+            Self::new_remote(id, data.len())?
+        }
+        else {
+            Self::new(id, data)?
+        };
+        frame.set_error_frame(flags.contains(IdentifierFlags::ERROR));
+
+        Some(frame)
+    }
+}
+
+/// The builder of [`CanMessage`], see [`CanMessage::builder`].
+#[derive(Debug, Clone, Default)]
+pub struct CanMessageBuilder {
+    id: Option<Id>,
+    extended: Option<bool>,
+    data: Vec<u8>,
+    fd: Option<bool>,
+    brs: bool,
+    remote: Option<usize>,
+    channel: String,
+    timestamp: u64,
+}
+
+impl CanMessageBuilder {
+    /// The identifier, required.
+    #[inline]
+    pub fn id(mut self, id: impl Into<Id>) -> Self {
+        self.id = Some(id.into());
+        self
+    }
+
+    /// Force the standard or extended identifier, decided by the identifier by default.
+    #[inline]
+    pub fn extended(mut self, value: bool) -> Self {
+        self.extended = Some(value);
+        self
+    }
+
+    #[inline]
+    pub fn data(mut self, data: &[u8]) -> Self {
+        self.data = data.to_vec();
+        self
+    }
+
+    /// CAN-FD or CAN 2.0, decided by the length of the data by default.
+    #[inline]
+    pub fn fd(mut self, value: bool) -> Self {
+        self.fd = Some(value);
+        self
+    }
+
+    /// Bitrate switch, CAN-FD only.
+    #[inline]
+    pub fn brs(mut self, value: bool) -> Self {
+        self.brs = value;
+        self
+    }
+
+    /// Create a remote frame requesting `len` bytes, CAN 2.0 only.
+    #[inline]
+    pub fn remote(mut self, len: usize) -> Self {
+        self.remote = Some(len);
+        self
+    }
+
+    #[inline]
+    pub fn channel(mut self, channel: impl Into<String>) -> Self {
+        self.channel = channel.into();
+        self
+    }
+
+    /// The timestamp in milliseconds.
+    #[inline]
+    pub fn timestamp(mut self, value: u64) -> Self {
+        self.timestamp = value;
+        self
+    }
+
+    /// Create the frame, [`Error::InvalidParam`] if the identifier is missing or out of range,
+    /// the remote frame has data or is CAN-FD, or the bitrate switch is set without CAN-FD,
+    /// [`Error::InvalidDataLength`] if the length isn't valid.
+    pub fn build(self) -> Result<CanMessage, Error> {
+        let id = self.id
+            .ok_or_else(|| Error::InvalidParam("the id is required".into()))?;
+        let id = match self.extended {
+            Some(true) => Id::new_extended(id.into_bits()),
+            Some(false) => u16::try_from(id.into_bits()).ok().and_then(Id::new_standard),
+            None => Some(id),
+        }
+        .ok_or_else(|| Error::InvalidParam(format!("the id: {:X} is out of range", id.into_bits())))?;
+
+        let is_fd = self.fd.unwrap_or(self.data.len() > CAN_FRAME_MAX_SIZE);
+        if self.brs && !is_fd {
+            return Err(Error::InvalidParam("the bitrate switch requires CAN-FD".into()));
+        }
+
+        let mut frame = match self.remote {
+            Some(len) => {
+                if !self.data.is_empty() {
+                    return Err(Error::InvalidParam("the remote frame has no data".into()));
+                }
+                if is_fd {
+                    return Err(Error::InvalidParam("CAN-FD has no remote frame".into()));
+                }
+                CanMessage::new_remote(id, len)
+                    .ok_or(Error::InvalidDataLength { actual: len, expect: CAN_FRAME_MAX_SIZE })?
+            },
+            None => {
+                check_length(self.data.len(), is_fd)?;
+                let mut frame = CanMessage::new(id, &self.data)
+                    .ok_or(Error::InvalidDataLength { actual: self.data.len(), expect: CAN_FRAME_MAX_SIZE })?;
+                frame.set_can_fd(is_fd)
+                    .set_bitrate_switch(self.brs);
+                frame
+            },
+        };
+        frame.set_timestamp(Some(self.timestamp))
+            .set_channel(self.channel);
+
+        Ok(frame)
+    }
+}
+
+/// Check the data length of a CAN 2.0 or CAN-FD frame.
+fn check_length(length: usize, is_fd: bool) -> Result<(), Error> {
+    if !is_fd {
+        if length > CAN_FRAME_MAX_SIZE {
+            return Err(Error::InvalidDataLength { actual: length, expect: CAN_FRAME_MAX_SIZE });
+        }
+    }
+    else if len_to_dlc(length).is_none() {
+        let expect = pad_len(length).unwrap_or(CANFD_FRAME_MAX_SIZE);
+        return Err(Error::InvalidDataLength { actual: length, expect });
+    }
+
+    Ok(())
+}
+
+impl Frame for CanMessage {
+    type Channel = String;
+
+    #[inline]
+    fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    #[inline]
+    fn id(&self) -> Id {
+        Id::from_bits(self.arbitration_id, self.is_extended_id)
+    }
+
+    #[inline]
+    fn is_can_fd(&self) -> bool {
+        self.is_fd
+    }
+
+    #[inline]
+    fn is_remote(&self) -> bool {
+        self.is_remote_frame
+    }
+
+    #[inline]
+    fn is_extended(&self) -> bool {
+        self.is_extended_id
+    }
+
+    #[inline]
+    fn direct(&self) -> Direct {
+        self.direct
+    }
+
+    #[inline]
+    fn is_bitrate_switch(&self) -> bool {
+        self.bitrate_switch
+    }
+
+    #[inline]
+    fn is_error_frame(&self) -> bool {
+        self.is_error_frame
+    }
+
+    #[inline]
+    fn is_esi(&self) -> bool {
+        self.error_state_indicator
+    }
+
+    #[inline]
+    fn channel(&self) -> Self::Channel {
+        self.channel.clone()
+    }
+
+    #[inline]
+    fn data(&self) -> &[u8] {
+        self.data.as_slice()
+    }
+
+    #[inline]
+    fn dlc(&self) -> Option<usize> {
+        len_to_dlc(self.length).map(usize::from)
+    }
+
+    #[inline]
+    fn length(&self) -> usize {
+        self.length
+    }
+}
+
+impl FrameMut for CanMessage {
+    /// Create a data frame, `None` if the length of `data` isn't a valid length of CAN 2.0 or CAN-FD,
+    /// see [`CanMessage::new_padded`].
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        let id: Id = id.into();
+        let length = data.len();
+        len_to_dlc(length)?;
+        let is_fd = length > CAN_FRAME_MAX_SIZE;
+
+        Some(Self {
+            arbitration_id: id.into_bits(),
+            is_extended_id: id.is_extended(),
+            length,
+            data: data.to_vec(),
+            is_fd,
+            ..Default::default()
+        })
+    }
+
+    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self> {
+        if len > CAN_FRAME_MAX_SIZE {
+            return None;
+        }
+
+        let id: Id = id.into();
+        Some(Self {
+            arbitration_id: id.into_bits(),
+            is_extended_id: id.is_extended(),
+            is_remote_frame: true,
+            length: len,
+            ..Default::default()
+        })
+    }
+
+    /// Set the timestamp, the current system time(ms) is used when `value` is `None`.
+    #[inline]
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self {
+        self.timestamp = value.unwrap_or_else(now_millis);
+        self
+    }
+
+    #[inline]
+    fn set_can_fd(&mut self, value: bool) -> &mut Self {
+        self.is_fd = value;
+        self
+    }
+
+    #[inline]
+    fn set_direct(&mut self, direct: Direct) -> &mut Self {
+        self.direct = direct;
+        self
+    }
+
+    #[inline]
+    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self {
+        self.bitrate_switch = value;
+        self
+    }
+
+    #[inline]
+    fn set_error_frame(&mut self, value: bool) -> &mut Self {
+        self.is_error_frame = value;
+        self
+    }
+
+    #[inline]
+    fn set_esi(&mut self, value: bool) -> &mut Self {
+        self.error_state_indicator = value;
+        self
+    }
+
+    #[inline]
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self {
+        self.channel = value;
+        self
+    }
+}
+
+impl Display for CanMessage {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        <dyn Frame<Channel = String> as Display>::fmt(self, f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex_literal::hex;
+    use crate::can::{AddressContext, AddressFormat, CanIsoTpFrame, IdentifierFlags};
+    use crate::can::frame::{Direct, Frame, FrameMut};
+    use crate::can::identifier::Id;
+    use crate::error::Error;
+    use super::CanMessage;
+
+    #[test]
+    fn test_new() {
+        let msg = CanMessage::new(0x7DF, &hex!("02 10 01")).unwrap();
+        assert_eq!(msg.id(), Id::Standard(0x7DF));
+        assert_eq!(msg.data(), hex!("02 10 01"));
+        assert_eq!(msg.length(), 3);
+        assert!(!msg.is_can_fd());
+
+        let msg = CanMessage::new(Id::Extended(0x100), &[0x55; 12]).unwrap();
+        assert!(msg.is_extended());
+        assert!(msg.is_can_fd());
+        assert_eq!(msg.dlc(), Some(9));
+
+        assert!(CanMessage::new(0x7DF, &[0x55; 65]).is_none());
+        // the lengths between the CAN-FD lengths
+        assert!(CanMessage::new(0x7DF, &[0x55; 10]).is_none());
+
+        let msg = CanMessage::new_padded(0x7DF, &[0x55; 10], 0xAA).unwrap();
+        assert_eq!(msg.length(), 12);
+        assert_eq!(msg.dlc(), Some(9));
+        assert_eq!(&msg.data()[10..], [0xAA; 2]);
+        let msg = CanMessage::new_padded(0x7DF, &[0x55; 3], 0xAA).unwrap();
+        assert_eq!(msg.data(), [0x55; 3]);
+        assert!(CanMessage::new_padded(0x7DF, &[0x55; 65], 0xAA).is_none());
+    }
+
+    #[test]
+    fn test_from_iso_tp_with() {
+        let frame = CanIsoTpFrame::ConsecutiveFrame { sequence: 1, data: hex!("01 02 03 04 05 06").as_slice().try_into().unwrap() };
+        let ctx = AddressContext::new(AddressFormat::Extend)
+            .with_extension(0xF1)
+            .with_padding(0x55);
+        let msg = CanMessage::from_iso_tp_with(0x7E0, frame.clone(), &ctx).unwrap();
+        // the AE byte at offset 0 and the PCI at offset 1, the padded length is kept
+        assert_eq!(msg.data(), hex!("F1 21 01 02 03 04 05 06"));
+
+        // without the AE byte as `from_iso_tp`
+        let ctx = AddressContext::default().with_padding(0x55);
+        let msg = CanMessage::from_iso_tp_with(0x7E0, frame.clone(), &ctx).unwrap();
+        assert_eq!(msg.data(), hex!("21 01 02 03 04 05 06 55"));
+        assert_eq!(CanMessage::from_iso_tp(0x7E0, frame, Some(0x55)), Some(msg));
+    }
+
+    #[test]
+    fn test_new_remote() {
+        let msg = CanMessage::new_remote(0x123, 8).unwrap();
+        assert!(msg.is_remote());
+        assert!(msg.data().is_empty());
+        assert_eq!(msg.length(), 8);
+
+        assert!(CanMessage::new_remote(0x123, 9).is_none());
+    }
+
+    #[test]
+    fn test_socketcan_id() {
+        // standard
+        let msg = CanMessage::new(0x7DF, &hex!("02 10 01")).unwrap();
+        assert_eq!(msg.to_socketcan_id(), 0x0000_07DF);
+        assert_eq!(CanMessage::from_socketcan_id(0x0000_07DF, msg.data()), Some(msg));
+
+        // extended, the bits beyond EFF_MASK are dropped
+        let msg = CanMessage::new(Id::Extended(0x18DA_F110), &hex!("02 10 01")).unwrap();
+        assert_eq!(msg.to_socketcan_id(), 0x98DA_F110);
+        assert_eq!(CanMessage::from_socketcan_id(0x98DA_F110, msg.data()), Some(msg));
+        assert_eq!(Id::from_raw(0x1000_07DF), (Id::Standard(0x7DF), IdentifierFlags::empty()));
+
+        // extended below 0x800
+        let msg = CanMessage::new(Id::Extended(0x123), &[]).unwrap();
+        assert_eq!(msg.to_socketcan_id(), 0x8000_0123);
+        let (id, flags) = Id::from_raw(0x8000_0123);
+        assert_eq!((id, flags), (Id::Extended(0x123), IdentifierFlags::EXTENDED));
+        assert_eq!(id.into_raw_with(flags), 0x8000_0123);
+        assert_eq!(Id::Standard(0x123).into_raw_with(IdentifierFlags::EXTENDED), 0x0000_0123);
+
+        // remote request
+        let msg = CanMessage::new_remote(0x123, 8).unwrap();
+        assert_eq!(msg.to_socketcan_id(), 0x4000_0123);
+        let remote = CanMessage::from_socketcan_id(0x4000_0123, &[0; 8]).unwrap();
+        assert!(remote.is_remote() && remote.data().is_empty());
+        assert_eq!(remote, msg);
+
+        // error frame
+        let mut msg = CanMessage::new(0x004, &hex!("00 04 00 00 00 00 00 00")).unwrap();
+        msg.set_error_frame(true);
+        assert_eq!(msg.to_socketcan_id(), 0x2000_0004);
+        let error = CanMessage::from_socketcan_id(0x2000_0004, msg.data()).unwrap();
+        assert!(error.is_error_frame());
+        assert_eq!(error, msg);
+    }
+
+    #[test]
+    fn test_display() {
+        // classic
+        let mut msg = CanMessage::new(0x7E0, &hex!("02 10 01")).unwrap();
+        msg.set_timestamp(Some(1_700_000_000_123))
+            .set_channel("can0".into());
+        assert_eq!(msg.to_string(), "1700000000.123000 can0  7E0             Tx   d 3 02 10 01");
+        assert_eq!(format!("{:.3}", msg), "1700000000.123 can0  7E0             Tx   d 3 02 10 01");
+
+        // CAN-FD with BRS
+        let mut msg = CanMessage::new(Id::Extended(0x18DA10F1), &hex!("10 0A 62 F1 90 31 32 33 34 35 36 37")).unwrap();
+        msg.set_timestamp(Some(30))
+            .set_bitrate_switch(true)
+            .set_direct(Direct::Receive)
+            .set_channel("can1".into());
+        assert_eq!(msg.to_string(), concat!("   0.030000 CANFD can1 Rx   18DA10F1x 1 0 9 12 10 0A 62 F1 90 31 32 33 34 35 36 37",
+            "        0    0     3000        0        0        0        0        0"));
+
+        // remote
+        let mut msg = CanMessage::new_remote(0x123, 8).unwrap();
+        msg.set_timestamp(Some(11))
+            .set_channel("can0".into());
+        assert_eq!(msg.to_string(), "   0.011000 can0  123             Tx   r 8");
+
+        // error frame
+        let mut msg = CanMessage::new(0x004, &hex!("00 04 00 00 00 00 00 00")).unwrap();
+        msg.set_timestamp(Some(1_020))
+            .set_error_frame(true)
+            .set_channel("can0".into());
+        assert_eq!(msg.to_string(), "   1.020000 can0  ErrorFrame");
+    }
+
+    #[test]
+    fn test_builder() {
+        let msg = CanMessage::builder()
+            .id(0x7E0)
+            .data(&hex!("02 10 01"))
+            .channel("can0")
+            .timestamp(10)
+            .build()
+            .unwrap();
+        let mut expect = CanMessage::new(0x7E0, &hex!("02 10 01")).unwrap();
+        expect.set_channel("can0".into())
+            .set_timestamp(Some(10));
+        assert_eq!(msg, expect);
+
+        // the extended identifier below 0x800
+        let msg = CanMessage::builder().id(0x123).extended(true).build().unwrap();
+        assert_eq!(msg.id(), Id::Extended(0x123));
+        assert_eq!(CanMessage::builder().id(0x18DAF110).extended(false).build(), Err(Error::InvalidParam("the id: 18DAF110 is out of range".into())));
+
+        // CAN-FD
+        let msg = CanMessage::builder().id(0x7E0).data(&[0x55; 3]).fd(true).brs(true).build().unwrap();
+        assert!(msg.is_can_fd() && msg.is_bitrate_switch());
+        assert!(CanMessage::builder().id(0x7E0).data(&[0x55; 12]).build().unwrap().is_can_fd());
+        assert_eq!(CanMessage::builder().id(0x7E0).data(&[0x55; 12]).fd(false).build(),
+            Err(Error::InvalidDataLength { actual: 12, expect: 8 }));
+        assert_eq!(CanMessage::builder().id(0x7E0).data(&[0x55; 10]).build(),
+            Err(Error::InvalidDataLength { actual: 10, expect: 12 }));
+        assert_eq!(CanMessage::builder().id(0x7E0).brs(true).build(), Err(Error::InvalidParam("the bitrate switch requires CAN-FD".into())));
+
+        // remote
+        let msg = CanMessage::builder().id(0x123).remote(8).build().unwrap();
+        assert_eq!(msg, CanMessage::new_remote(0x123, 8).unwrap());
+        assert_eq!(CanMessage::builder().id(0x123).remote(8).data(&[0x01]).build(), Err(Error::InvalidParam("the remote frame has no data".into())));
+        assert_eq!(CanMessage::builder().id(0x123).remote(8).fd(true).build(), Err(Error::InvalidParam("CAN-FD has no remote frame".into())));
+        assert_eq!(CanMessage::builder().id(0x123).remote(9).build(), Err(Error::InvalidDataLength { actual: 9, expect: 8 }));
+
+        assert_eq!(CanMessage::builder().data(&[0x01]).build(), Err(Error::InvalidParam("the id is required".into())));
+    }
+
+    #[test]
+    fn test_set_data() -> anyhow::Result<()> {
+        let mut msg = CanMessage::new(0x7E0, &hex!("02 10 01")).unwrap();
+        msg.set_data(&hex!("02 10 03"))?;
+        assert_eq!(msg.data(), hex!("02 10 03"));
+        msg.set_data(&[])?;
+        assert_eq!((msg.length(), msg.dlc()), (0, Some(0)));
+        assert!(msg.set_data(&[0x55; 12]).is_err());
+
+        msg.set_can_fd(true);
+        msg.set_data(&[0x55; 12])?;
+        assert_eq!((msg.length(), msg.dlc()), (12, Some(9)));
+        assert!(msg.set_data(&[0x55; 13]).is_err());
+
+        let mut msg = CanMessage::new_remote(0x123, 8).unwrap();
+        assert!(msg.set_data(&[0x01]).is_err());
+        Ok(())
+    }
+}
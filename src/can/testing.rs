@@ -0,0 +1,126 @@
+//! The event collector and the frames shared by the tests of the drivers, of the transports and of J1939.
+// used by the tests of the features enabled
+#![allow(dead_code)]
+
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use crate::{IsoTpEvent, IsoTpEventListener};
+use crate::can::driver::MOCK_CHANNEL;
+use crate::can::frame::FrameMut;
+use crate::can::identifier::Id;
+use crate::can::message::CanMessage;
+use crate::error::Error;
+
+/// Records the events of a listener.
+#[derive(Clone)]
+pub(crate) struct Collector<E>(pub(crate) Arc<Mutex<Vec<E>>>);
+
+impl<E> Default for Collector<E> {
+    fn default() -> Self {
+        Self(Default::default())
+    }
+}
+
+impl<E> Collector<E> {
+    #[inline]
+    fn push(&self, event: E) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    /// Take all the events recorded.
+    pub(crate) fn take(&self) -> Vec<E> {
+        std::mem::take(&mut self.0.lock().unwrap())
+    }
+
+    /// Wait `timeout` for the first event mapped by `f`, it's removed with the events before it.
+    fn wait_map<R>(&self, timeout: Duration, mut f: impl FnMut(&E) -> Option<R>) -> Option<R> {
+        let start = Instant::now();
+        while start.elapsed() < timeout {
+            {
+                let mut events = self.0.lock().unwrap();
+                if let Some((index, result)) = events.iter().enumerate().find_map(|(i, v)| f(v).map(|r| (i, r))) {
+                    events.drain(..=index);
+                    return Some(result);
+                }
+            }
+            sleep(Duration::from_millis(1));
+        }
+        None
+    }
+}
+
+impl<E: Clone> Collector<E> {
+    /// Wait `timeout` for `count` events at least, returns all the events recorded.
+    pub(crate) fn wait(&self, count: usize, timeout: Duration) -> Vec<E> {
+        let start = Instant::now();
+        while self.0.lock().unwrap().len() < count && start.elapsed() < timeout {
+            sleep(Duration::from_millis(1));
+        }
+        self.0.lock().unwrap().clone()
+    }
+}
+
+impl Collector<IsoTpEvent> {
+    /// Wait `timeout` for the data received.
+    pub(crate) fn wait_data(&self, timeout: Duration) -> Option<Vec<u8>> {
+        self.wait_map(timeout, |v| v.data().map(<[u8]>::to_vec))
+    }
+
+    /// Wait `timeout` for the error of the writing or of the receiving.
+    pub(crate) fn wait_error(&self, timeout: Duration) -> Option<Error> {
+        self.wait_map(timeout, |v| match v {
+            IsoTpEvent::ErrorOccurred(e) | IsoTpEvent::ReceptionAborted { error: e, .. } => Some(e.clone()),
+            _ => None,
+        })
+    }
+}
+
+impl IsoTpEventListener for Collector<IsoTpEvent> {
+    fn from_buffer(&mut self) -> Option<IsoTpEvent> {
+        None
+    }
+
+    fn clear_buffer(&mut self) {}
+
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        self.push(event);
+    }
+}
+
+#[cfg(feature = "j1939")]
+mod j1939 {
+    use crate::can::j1939::*;
+    use super::Collector;
+
+    impl J1939TpEventListener for Collector<J1939TpEvent> {
+        fn on_tp_event(&mut self, event: J1939TpEvent) {
+            self.push(event);
+        }
+    }
+
+    impl NetworkEventListener for Collector<NetworkEvent> {
+        fn on_network_event(&mut self, event: NetworkEvent) {
+            self.push(event);
+        }
+    }
+
+    impl DmEventListener for Collector<DmEvent> {
+        fn on_dm_event(&mut self, event: DmEvent) {
+            self.push(event);
+        }
+    }
+
+    impl AddressClaimEventListener for Collector<AddressClaimEvent> {
+        fn on_claim_event(&mut self, event: AddressClaimEvent) {
+            self.push(event);
+        }
+    }
+}
+
+/// A frame of `id` on [`MOCK_CHANNEL`], the id above 0x7FF is extended.
+pub(crate) fn frame(id: impl Into<Id>, data: &[u8]) -> CanMessage {
+    let mut frame = CanMessage::new(id, data).unwrap();
+    frame.set_channel(MOCK_CHANNEL.into());
+    frame
+}
@@ -10,22 +10,33 @@ mod std2016;
 pub(crate) use std2016::*;
 
 
-use crate::can::CanIsoTpFrame;
-use crate::can::constant::{CAN_FRAME_MAX_SIZE, CONSECUTIVE_FRAME_SIZE};
+use alloc::{vec, vec::Vec};
+use crate::can::{CanIsoTpFrame, FramePayload};
+use crate::can::constant::{CAN_FRAME_MAX_SIZE, CONSECUTIVE_FRAME_SIZE, DEFAULT_PADDING};
+#[cfg(feature = "can-fd")]
+use crate::can::dlc::pad_len;
+use crate::FrameType;
 
-#[inline]
-fn can_fd_resize(length: usize) -> Option<usize> {
-    match length {
-        ..=CAN_FRAME_MAX_SIZE => Some(length),
-        9..=12 =>  Some(12),
-        13..=16 => Some(16),
-        17..=20 => Some(20),
-        21..=24 => Some(24),
-        25..=32 => Some(32),
-        33..=48 => Some(48),
-        49..=64 => Some(64),
-        _ => None,
-    }
+/// Pad the frame to 8 bytes, or to the shortest CAN-FD length holds it.
+pub(crate) fn pad(result: &mut Vec<u8>, padding: Option<u8>) {
+    #[cfg(not(feature = "can-fd"))]
+    let len = CAN_FRAME_MAX_SIZE;
+    #[cfg(feature = "can-fd")]
+    let len = pad_len(result.len().max(CAN_FRAME_MAX_SIZE)).unwrap_or(result.len());
+    result.resize(len, padding.unwrap_or(DEFAULT_PADDING));
+}
+
+pub(crate) fn encode_single(data: FramePayload, padding: Option<u8>) -> Vec<u8> {
+    let length = data.len();
+    // SF_DL of 4 bits up to 7, the escape sequence for the longer ones of CAN-FD
+    let mut result = match length {
+        ..CAN_FRAME_MAX_SIZE => vec![FrameType::Single as u8 | length as u8],
+        _ => vec![FrameType::Single as u8, length as u8],
+    };
+    result.extend_from_slice(&data);
+    pad(&mut result, padding);
+
+    result
 }
 
 fn parse<const FIRST_FRAME_SIZE: usize>(data: &[u8],
@@ -40,7 +51,7 @@ fn parse<const FIRST_FRAME_SIZE: usize>(data: &[u8],
                 *offset += FIRST_FRAME_SIZE;
                 let frame = CanIsoTpFrame::FirstFrame {
                     length: length as u32,
-                    data: Vec::from(&data[..*offset])
+                    data: FramePayload::from_slice(&data[..*offset])
                 };
                 results.push(frame);
 
@@ -50,7 +61,7 @@ fn parse<const FIRST_FRAME_SIZE: usize>(data: &[u8],
                 if *offset + CONSECUTIVE_FRAME_SIZE >= length {
                     let frame = CanIsoTpFrame::ConsecutiveFrame {
                         sequence: *sequence,
-                        data: Vec::from(&data[*offset..length])
+                        data: FramePayload::from_slice(&data[*offset..length])
                     };
                     results.push(frame);
                     break;
@@ -58,7 +69,7 @@ fn parse<const FIRST_FRAME_SIZE: usize>(data: &[u8],
 
                 let frame = CanIsoTpFrame::ConsecutiveFrame {
                     sequence: *sequence,
-                    data: Vec::from(&data[*offset..*offset + CONSECUTIVE_FRAME_SIZE])
+                    data: FramePayload::from_slice(&data[*offset..*offset + CONSECUTIVE_FRAME_SIZE])
                 };
                 *offset += CONSECUTIVE_FRAME_SIZE;
                 if *sequence >= 0x0F {
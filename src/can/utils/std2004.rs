@@ -1,12 +1,10 @@
-use crate::can::CanIsoTpFrame;
+use alloc::{vec, vec::Vec};
+use crate::can::{CanIsoTpFrame, FramePayload};
 use crate::can::utils::parse;
-use crate::can::constant::{CAN_FRAME_MAX_SIZE, CANFD_FRAME_MAX_SIZE, CONSECUTIVE_FRAME_SIZE, DEFAULT_PADDING, ISO_TP_MAX_LENGTH_2004, SINGLE_FRAME_SIZE_2004, FIRST_FRAME_SIZE_2004};
+use crate::can::constant::{CAN_FRAME_MAX_SIZE, CANFD_FRAME_MAX_SIZE, ISO_TP_MAX_LENGTH_2004, SINGLE_FRAME_SIZE_2004, FIRST_FRAME_SIZE_2004};
 use crate::error::Error;
 use crate::FrameType;
 
-#[cfg(feature = "can-fd")]
-use crate::can::utils::can_fd_resize;
-
 pub(crate) fn decode_single(data: &[u8],
                             byte0: u8,
                             length: usize
@@ -20,12 +18,17 @@ pub(crate) fn decode_single(data: &[u8],
         return Err(Error::LengthOutOfRange(length));
     }
 
-    let pdu_len = byte0 & 0x0F;
-    if length < pdu_len as usize + 1 {
+    // SF_DL 0 is reserved, except the escape sequence of ISO 15765-2:2016 by the CAN-FD frames
+    let (offset, pdu_len) = match byte0 & 0x0F {
+        #[cfg(feature = "can-fd")]
+        0 if length > CAN_FRAME_MAX_SIZE => (2, data[1] as usize),
+        v => (1, v as usize),
+    };
+    if pdu_len == 0 || length < pdu_len + offset {
         return Err(Error::InvalidPdu(data.to_vec()));
     }
 
-    Ok(CanIsoTpFrame::SingleFrame { data: Vec::from(&data[1..=pdu_len as usize]) })
+    Ok(CanIsoTpFrame::SingleFrame { data: FramePayload::from_slice(&data[offset..offset + pdu_len]) })
 }
 
 pub(crate) fn decode_first(data: &[u8],
@@ -41,29 +44,20 @@ pub(crate) fn decode_first(data: &[u8],
         return Err(Error::InvalidDataLength { actual: length, expect: CANFD_FRAME_MAX_SIZE })
     }
 
+    // the data fits in a single frame is invalid
     let pdu_len = (byte0 as u16 & 0x0F) << 8 | data[1] as u16;
-    Ok(CanIsoTpFrame::FirstFrame { length: pdu_len as u32, data: Vec::from(&data[2..]) })
-}
-
-pub(crate) fn encode_single(mut data: Vec<u8>, padding: Option<u8>) -> Vec<u8> {
-    let length = data.len();
-    let mut result = vec![FrameType::Single as u8 | length as u8];
-    result.append(&mut data);
-    #[cfg(not(feature = "can-fd"))]
-    result.resize(CAN_FRAME_MAX_SIZE, padding.unwrap_or(DEFAULT_PADDING));
-    #[cfg(feature = "can-fd")]
-    if let Some(resize) = can_fd_resize(length) {
-        result.resize(resize, padding.unwrap_or(DEFAULT_PADDING));
+    if pdu_len as usize <= SINGLE_FRAME_SIZE_2004 {
+        return Err(Error::InvalidPdu(data.to_vec()));
     }
 
-    result
+    Ok(CanIsoTpFrame::FirstFrame { length: pdu_len as u32, data: FramePayload::from_slice(&data[2..]) })
 }
 
-pub(crate) fn encode_first(length: u32, mut data: Vec<u8>) -> Vec<u8> {
+pub(crate) fn encode_first(length: u32, data: FramePayload) -> Vec<u8> {
     let len_h = ((length & 0x0F00) >> 8) as u8;
     let len_l = (length & 0x00FF) as u8;
     let mut result = vec![FrameType::First as u8 | len_h, len_l];
-    result.append(&mut data);
+    result.extend_from_slice(&data);
     result
 }
 
@@ -73,23 +67,21 @@ pub(crate) fn new_single<T: AsRef<[u8]>>(data: T) -> Result<CanIsoTpFrame, Error
     match length {
         0 => Err(Error::EmptyPdu),
         1..=SINGLE_FRAME_SIZE_2004 => {
-            let mut result = vec![FrameType::Single as u8 | length as u8];
-            result.append(&mut data.to_vec());
-            result.resize(SINGLE_FRAME_SIZE_2004, DEFAULT_PADDING);
-            Ok(CanIsoTpFrame::SingleFrame { data: result })
+            Ok(CanIsoTpFrame::SingleFrame { data: FramePayload::from_slice(data) })
         },
         v => Err(Error::LengthOutOfRange(v)),
     }
 }
 
-pub(crate) fn from_data(data: &[u8]) -> Result<Vec<CanIsoTpFrame>, Error> {
+#[allow(clippy::match_overlapping_arm)]
+pub(crate) fn from_data(data: &[u8], sequence: u8) -> Result<Vec<CanIsoTpFrame>, Error> {
     let length = data.len();
     match length {
         0 => Err(Error::EmptyPdu),
-        1..=CONSECUTIVE_FRAME_SIZE => Ok(vec![CanIsoTpFrame::SingleFrame { data: data.to_vec() }]),
+        1..=SINGLE_FRAME_SIZE_2004 => Ok(vec![CanIsoTpFrame::SingleFrame { data: FramePayload::from_slice(data) }]),
         ..=ISO_TP_MAX_LENGTH_2004 => {
             let mut offset = 0;
-            let mut sequence = 1;
+            let mut sequence = sequence;
             let mut results = Vec::new();
 
             parse::<FIRST_FRAME_SIZE_2004>(data, &mut offset, &mut sequence, &mut results, length);
@@ -1,29 +1,285 @@
 //! Uniform Device Driver trait
 
-use std::any::Any;
+use alloc::vec::Vec;
+use core::any::Any;
+use core::fmt::Display;
+use core::hash::Hash;
+use crate::can::error_frame::ErrorInfo;
+use crate::error::Error;
 
+/// Channel configuration used by [`Driver::open_channel`].
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct ChannelConfig {
+    /// The arbitration(nominal) bitrate in bit/s.
+    pub bitrate: u32,
+    /// The data phase bitrate in bit/s, only used when `fd` is enabled.
+    pub data_bitrate: Option<u32>,
+    /// The sample point of the arbitration phase(0.0 ~ 1.0), `None` to use the device default.
+    pub sample_point: Option<f32>,
+    /// The sample point of the data phase(0.0 ~ 1.0), `None` to use the device default.
+    pub data_sample_point: Option<f32>,
+    /// Enable CAN-FD.
+    pub fd: bool,
+    /// Listen-only(silent) mode, the channel never transmits nor acknowledges.
+    pub listen_only: bool,
+}
+
+impl Default for ChannelConfig {
+    fn default() -> Self {
+        Self::new(500_000)
+    }
+}
+
+impl ChannelConfig {
+    /// Classic CAN configuration with `bitrate`.
+    #[inline]
+    pub fn new(bitrate: u32) -> Self {
+        Self {
+            bitrate,
+            data_bitrate: Default::default(),
+            sample_point: Default::default(),
+            data_sample_point: Default::default(),
+            fd: false,
+            listen_only: false,
+        }
+    }
+
+    /// CAN-FD configuration with `bitrate` and `data_bitrate`.
+    #[inline]
+    pub fn new_fd(bitrate: u32, data_bitrate: u32) -> Self {
+        Self {
+            data_bitrate: Some(data_bitrate),
+            fd: true,
+            ..Self::new(bitrate)
+        }
+    }
+}
+
+/// The features supported by a [`Driver`], see [`Driver::capabilities`].
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub struct DriverCapabilities {
+    /// CAN-FD frames.
+    pub fd: bool,
+    /// The bitrate switch of the CAN-FD frames.
+    pub brs: bool,
+    /// The frames received are timestamped by the device hardware, the software timestamps of the kernel don't count.
+    pub hardware_timestamps: bool,
+    /// [`Driver::receive`] blocks until frames are received or the timeout elapsed.
+    pub blocking_receive: bool,
+    /// The error frames are received.
+    pub error_frames: bool,
+    /// The channels are opened in the listen-only mode by [`ChannelConfig::listen_only`].
+    pub listen_only: bool,
+    /// The frames transmitted are received too by [`Driver::set_loopback`].
+    pub loopback: bool,
+}
+
+/// An acceptance filter of the received frames, see [`Driver::set_filters`].
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+pub struct FilterSpec {
+    pub id: u32,
+    /// The bits of the identifier compared.
+    pub mask: u32,
+    /// Accept the extended frames only by `true`, the standard frames only by `false`.
+    pub extended: bool,
+}
+
+impl FilterSpec {
+    #[inline]
+    pub fn new(id: u32, mask: u32, extended: bool) -> Self {
+        Self { id, mask, extended }
+    }
+
+    /// Whether the frame with `id` is accepted.
+    #[inline]
+    pub fn matches(&self, id: u32, extended: bool) -> bool {
+        self.extended == extended && id & self.mask == self.id & self.mask
+    }
+}
+
+/// The error state of a CAN controller on a channel.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum BusState {
+    /// The controller takes part in the bus communication normally.
+    ErrorActive,
+    /// The error counters are above the warning limit, the controller sends passive error flags.
+    ErrorPassive,
+    /// The controller is disconnected from the bus.
+    BusOff,
+    /// The state is not reported by the device.
+    #[default]
+    Unknown,
+}
+
+/// The channel of the devices, the frames and the transports, e.g. the interface name `"can0"`
+/// of SocketCAN or the index of a USB adapter.
+///
+/// It's implemented for all the types with the bounds, e.g. `String`, `&'static str` and the integers.
+/// A user-defined channel derives `Clone, PartialEq, Eq, Hash` and implements `Display`:
+///
+/// ```
+/// use core::fmt::{Display, Formatter};
+/// use isotp_rs::device::Channel;
+///
+/// #[derive(Debug, Clone, PartialEq, Eq, Hash)]
+/// enum Bus {
+///     Powertrain,
+///     Body,
+/// }
+///
+/// impl Display for Bus {
+///     fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+///         write!(f, "{:?}", self)
+///     }
+/// }
+///
+/// fn assert_channel<C: Channel>(_: C) {}
+/// assert_channel(Bus::Body);
+/// ```
+pub trait Channel: Clone + Eq + Hash + Display + Send + Sync + 'static {}
 
-pub trait Listener<Channel, Id, Frame>: Any + Send {
+impl<T: Clone + Eq + Hash + Display + Send + Sync + 'static> Channel for T {}
+
+pub trait Listener<C: Channel, Id, Frame>: Any + Send {
     fn as_any(&self) -> &dyn Any;
     /// Callback when frame transmitting.
-    fn on_frame_transmitting(&mut self, channel: Channel, frame: &Frame);
-    /// Callback when frame transmit success.
-    fn on_frame_transmitted(&mut self, channel: Channel, id: Id);
+    fn on_frame_transmitting(&mut self, channel: C, frame: &Frame);
+    /// Callback when frame transmit success, `frame` is the transmitted frame with the
+    /// timestamp(ms) of the transmission.
+    ///
+    /// Migrating from the version that passed the `id` only: use `frame.id()` instead.
+    fn on_frame_transmitted(&mut self, channel: C, frame: &Frame);
     /// Callback when frames received.
-    fn on_frame_received(&mut self, channel: Channel, frames: &[Frame]);
+    fn on_frame_received(&mut self, channel: C, frames: &[Frame]);
+    /// Callback with the frames received from all channels in one receiving cycle,
+    /// the channels without frames are not included.
+    ///
+    /// The default implementation calls [`Listener::on_frame_received`] for each channel,
+    /// override it to handle all channels in one call.
+    fn on_frames_received(&mut self, frames: &[(C, Vec<Frame>)])
+    {
+        frames.iter()
+            .for_each(|(channel, frames)| self.on_frame_received(channel.clone(), frames));
+    }
+    /// Callback when frame transmit failed, `error` carries the error of the device.
+    fn on_frame_transmit_failed(&mut self, channel: C, id: Id, error: &Error) {
+        let _ = (channel, id, error);
+    }
+    /// Callback when the bus state of the channel changed.
+    fn on_bus_state_changed(&mut self, channel: C, state: BusState) {
+        let _ = (channel, state);
+    }
+    /// Callback when an error frame received, `info` is decoded from `frame`.
+    ///
+    /// The error frames received by `SyncCan` are passed here instead of [`Listener::on_frame_received`].
+    fn on_error_frame(&mut self, channel: C, frame: &Frame, info: &ErrorInfo) {
+        let _ = (channel, frame, info);
+    }
+    /// Callback when the device is disconnected(`false`) and reconnected(`true`)
+    /// by the reconnect policy of the loops.
+    fn on_connection_changed(&mut self, connected: bool) {
+        let _ = connected;
+    }
+    /// Callback when the loops are stopped and the device is about to shut down, no frame is
+    /// transmitted nor received after it.
+    fn on_shutdown(&mut self) {}
+    /// The frames of `channel` received by the listener, `SyncCan::auto_filter` sets the union of
+    /// the listeners to the driver. An empty one receives none of `channel`.
+    ///
+    /// The default implementation returns `None` to receive all the frames.
+    fn rx_filter(&self, channel: &C) -> Option<Vec<FilterSpec>> {
+        let _ = channel;
+        None
+    }
 }
 
 pub trait Driver: Send {
-    type Error;
-    type C;
+    type Error: core::error::Error + From<Error> + Send + Sync + 'static;
+    type C: Channel;
     type F;
 
     /// get all channels that has opened
     fn opened_channels(&self) -> Vec<Self::C>;
 
+    /// Open `channel` with `config`, an opened channel is reconfigured.
+    ///
+    /// The default implementation returns [`Error::Unsupported`] for the backends
+    /// that are configured externally.
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        let _ = (channel, config);
+        Err(Error::Unsupported("open channel".into()).into())
+    }
+
+    /// Close `channel`.
+    ///
+    /// The default implementation returns [`Error::Unsupported`].
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        let _ = channel;
+        Err(Error::Unsupported("close channel".into()).into())
+    }
+
     /// closed flag.
     fn is_closed(&self) -> bool;
 
+    /// Reopen the closed device with the opened channels, e.g. the USB adapter is plugged again.
+    ///
+    /// The default implementation returns [`Error::Unsupported`].
+    fn reopen(&mut self) -> Result<(), Self::Error> {
+        Err(Error::Unsupported("reopen device".into()).into())
+    }
+
+    /// The bus state of `channel`.
+    ///
+    /// The default implementation returns [`BusState::Unknown`].
+    fn bus_state(&self, channel: Self::C) -> BusState {
+        let _ = channel;
+        BusState::Unknown
+    }
+
+    /// Whether [`Driver::receive`] blocks until frames are received or the timeout elapsed.
+    ///
+    /// The default implementation returns `false`, the receiving is polled on a fixed interval.
+    fn is_blocking_receive(&self) -> bool {
+        false
+    }
+
+    /// Receive the frames of `channel` matched by any of `filters` only, all the frames by an empty one.
+    /// The hardware filters of the adapter save delivering the irrelevant frames on a busy bus.
+    ///
+    /// The default implementation returns [`Error::Unsupported`].
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error> {
+        let _ = (channel, filters);
+        Err(Error::Unsupported("acceptance filters".into()).into())
+    }
+
+    /// Receive the frames transmitted on `channel` too, e.g. to check the device is alive.
+    ///
+    /// The default implementation returns [`Error::Unsupported`].
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error> {
+        let _ = (channel, enabled);
+        Err(Error::Unsupported("loopback".into()).into())
+    }
+
+    /// Whether the frames transmitted on `channel` are received too, see [`Driver::set_loopback`].
+    ///
+    /// The default implementation returns `false`.
+    fn is_loopback(&self, channel: Self::C) -> bool {
+        let _ = channel;
+        false
+    }
+
+    /// The features supported by the driver, the higher layers validate their configuration by them.
+    ///
+    /// The default implementation supports the blocking receive of [`Driver::is_blocking_receive`] only.
+    fn capabilities(&self) -> DriverCapabilities {
+        DriverCapabilities {
+            blocking_receive: self.is_blocking_receive(),
+            ..Default::default()
+        }
+    }
+
     /// Transmit a CAN or CAN-FD Frame.
     #[cfg(not(feature = "async"))]
     fn transmit(
@@ -36,7 +292,21 @@ pub trait Driver: Send {
         &self,
         msg: Self::F,
         timeout: Option<u32>,
-    ) -> impl std::future::Future<Output = Result<(), Self::Error>>;
+    ) -> impl core::future::Future<Output = Result<(), Self::Error>>;
+    /// Transmit CAN and CAN-FD Frames in one call, returns the result of each frame in order.
+    ///
+    /// The transmit loop of `SyncCan` hands the queued frames together, e.g. a block of ISO-TP
+    /// consecutive frames. The default implementation transmits them one by one.
+    #[cfg(not(feature = "async"))]
+    fn transmit_batch(
+        &self,
+        frames: Vec<Self::F>,
+        timeout: Option<u32>,
+    ) -> Vec<Result<(), Self::Error>> {
+        frames.into_iter()
+            .map(|f| self.transmit(f, timeout))
+            .collect()
+    }
     /// Receive CAN and CAN-FD Frames.
     #[cfg(not(feature = "async"))]
     fn receive(
@@ -49,11 +319,273 @@ pub trait Driver: Send {
         &self,
         channel: Self::C,
         timeout: Option<u32>,
-    ) -> impl std::future::Future<Output = Result<Vec<Self::F>, Self::Error>>;
+    ) -> impl core::future::Future<Output = Result<Vec<Self::F>, Self::Error>>;
+    /// Receive CAN and CAN-FD Frames of `channel` appended to `buf`, returns the count of them.
+    ///
+    /// The receive loops of `SyncCan` reuse `buf` for each poll. The default implementation appends
+    /// the frames of [`Driver::receive`], the backends override it to avoid the allocation.
+    #[cfg(not(feature = "async"))]
+    fn receive_into(
+        &self,
+        channel: Self::C,
+        timeout: Option<u32>,
+        buf: &mut Vec<Self::F>,
+    ) -> Result<usize, Self::Error> {
+        let frames = self.receive(channel, timeout)?;
+        let count = frames.len();
+        buf.extend(frames);
+        Ok(count)
+    }
     /// Close CAN device.
     #[cfg(not(feature = "async"))]
     fn shutdown(&mut self);
     #[cfg(feature = "async")]
-    fn shutdown(&mut self) -> impl std::future::Future<Output = ()>;
+    fn shutdown(&mut self) -> impl core::future::Future<Output = ()>;
+
+}
+
+/// The boxed future returned by [`DynDriver`].
+#[cfg(feature = "async")]
+pub type BoxFuture<'a, T> = core::pin::Pin<alloc::boxed::Box<dyn core::future::Future<Output = T> + 'a>>;
+
+/// The object-safe [`Driver`] with the boxed futures, so the backend can be selected at runtime.
+///
+/// It's implemented for all the [`Driver`]s, and `Box<dyn DynDriver>` implements [`Driver`] again,
+/// so it can be passed where a concrete driver is expected. The futures are not `Send` because
+/// the futures of [`Driver`] are not required to be.
+#[cfg(feature = "async")]
+pub trait DynDriver: Send {
+    type Error: core::error::Error + From<Error> + Send + Sync + 'static;
+    type C: Channel;
+    type F;
+
+    /// See [`Driver::opened_channels`].
+    fn opened_channels(&self) -> Vec<Self::C>;
+    /// See [`Driver::open_channel`].
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error>;
+    /// See [`Driver::close_channel`].
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error>;
+    /// See [`Driver::is_closed`].
+    fn is_closed(&self) -> bool;
+    /// See [`Driver::reopen`].
+    fn reopen(&mut self) -> Result<(), Self::Error>;
+    /// See [`Driver::bus_state`].
+    fn bus_state(&self, channel: Self::C) -> BusState;
+    /// See [`Driver::is_blocking_receive`].
+    fn is_blocking_receive(&self) -> bool;
+    /// See [`Driver::set_filters`].
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error>;
+    /// See [`Driver::set_loopback`].
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error>;
+    /// See [`Driver::is_loopback`].
+    fn is_loopback(&self, channel: Self::C) -> bool;
+    /// See [`Driver::capabilities`].
+    fn capabilities(&self) -> DriverCapabilities;
+    /// See [`Driver::transmit`].
+    fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> BoxFuture<'_, Result<(), Self::Error>>;
+    /// See [`Driver::receive`].
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> BoxFuture<'_, Result<Vec<Self::F>, Self::Error>>;
+    /// See [`Driver::shutdown`].
+    fn shutdown(&mut self) -> BoxFuture<'_, ()>;
+}
+
+#[cfg(feature = "async")]
+impl<D: Driver> DynDriver for D {
+    type Error = D::Error;
+    type C = D::C;
+    type F = D::F;
+
+    #[inline]
+    fn opened_channels(&self) -> Vec<Self::C> {
+        Driver::opened_channels(self)
+    }
+    #[inline]
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        Driver::open_channel(self, channel, config)
+    }
+    #[inline]
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        Driver::close_channel(self, channel)
+    }
+    #[inline]
+    fn is_closed(&self) -> bool {
+        Driver::is_closed(self)
+    }
+    #[inline]
+    fn reopen(&mut self) -> Result<(), Self::Error> {
+        Driver::reopen(self)
+    }
+    #[inline]
+    fn bus_state(&self, channel: Self::C) -> BusState {
+        Driver::bus_state(self, channel)
+    }
+    #[inline]
+    fn is_blocking_receive(&self) -> bool {
+        Driver::is_blocking_receive(self)
+    }
+    #[inline]
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error> {
+        Driver::set_filters(self, channel, filters)
+    }
+    #[inline]
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error> {
+        Driver::set_loopback(self, channel, enabled)
+    }
+    #[inline]
+    fn is_loopback(&self, channel: Self::C) -> bool {
+        Driver::is_loopback(self, channel)
+    }
+    #[inline]
+    fn capabilities(&self) -> DriverCapabilities {
+        Driver::capabilities(self)
+    }
+    #[inline]
+    fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> BoxFuture<'_, Result<(), Self::Error>> {
+        alloc::boxed::Box::pin(Driver::transmit(self, msg, timeout))
+    }
+    #[inline]
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> BoxFuture<'_, Result<Vec<Self::F>, Self::Error>> {
+        alloc::boxed::Box::pin(Driver::receive(self, channel, timeout))
+    }
+    #[inline]
+    fn shutdown(&mut self) -> BoxFuture<'_, ()> {
+        alloc::boxed::Box::pin(Driver::shutdown(self))
+    }
+}
+
+#[cfg(feature = "async")]
+impl<C, F, E> Driver for alloc::boxed::Box<dyn DynDriver<C = C, F = F, Error = E>>
+where
+    C: Channel,
+    E: core::error::Error + From<Error> + Send + Sync + 'static,
+{
+    type Error = E;
+    type C = C;
+    type F = F;
+
+    #[inline]
+    fn opened_channels(&self) -> Vec<Self::C> {
+        (**self).opened_channels()
+    }
+    #[inline]
+    fn open_channel(&mut self, channel: Self::C, config: ChannelConfig) -> Result<(), Self::Error> {
+        (**self).open_channel(channel, config)
+    }
+    #[inline]
+    fn close_channel(&mut self, channel: Self::C) -> Result<(), Self::Error> {
+        (**self).close_channel(channel)
+    }
+    #[inline]
+    fn is_closed(&self) -> bool {
+        (**self).is_closed()
+    }
+    #[inline]
+    fn reopen(&mut self) -> Result<(), Self::Error> {
+        (**self).reopen()
+    }
+    #[inline]
+    fn bus_state(&self, channel: Self::C) -> BusState {
+        (**self).bus_state(channel)
+    }
+    #[inline]
+    fn is_blocking_receive(&self) -> bool {
+        (**self).is_blocking_receive()
+    }
+    #[inline]
+    fn set_filters(&mut self, channel: Self::C, filters: &[FilterSpec]) -> Result<(), Self::Error> {
+        (**self).set_filters(channel, filters)
+    }
+    #[inline]
+    fn set_loopback(&mut self, channel: Self::C, enabled: bool) -> Result<(), Self::Error> {
+        (**self).set_loopback(channel, enabled)
+    }
+    #[inline]
+    fn is_loopback(&self, channel: Self::C) -> bool {
+        (**self).is_loopback(channel)
+    }
+    #[inline]
+    fn capabilities(&self) -> DriverCapabilities {
+        (**self).capabilities()
+    }
+    #[inline]
+    fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> impl core::future::Future<Output = Result<(), Self::Error>> {
+        (**self).transmit(msg, timeout)
+    }
+    #[inline]
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> impl core::future::Future<Output = Result<Vec<Self::F>, Self::Error>> {
+        (**self).receive(channel, timeout)
+    }
+    #[inline]
+    fn shutdown(&mut self) -> impl core::future::Future<Output = ()> {
+        (**self).shutdown()
+    }
+}
+
+#[cfg(all(test, feature = "async"))]
+mod tests {
+    use std::future::Future;
+    use std::pin::pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll, Wake, Waker};
+    use crate::can::driver::{ReplayDriver, VirtualBus, MOCK_CHANNEL};
+    use crate::can::frame::{Frame, FrameMut};
+    use crate::can::message::CanMessage;
+    use crate::error::Error;
+    use super::{Driver, DynDriver};
+
+    type BoxDriver = Box<dyn DynDriver<C = String, F = CanMessage, Error = Error>>;
+
+    struct Noop;
+
+    impl Wake for Noop {
+        fn wake(self: Arc<Self>) {}
+    }
+
+    /// Poll `future` until ready, the futures of the tested drivers are ready at once.
+    fn block_on<T>(future: impl Future<Output = T>) -> T {
+        let waker = Waker::from(Arc::new(Noop));
+        let mut context = Context::from_waker(&waker);
+        let mut future = pin!(future);
+        loop {
+            if let Poll::Ready(v) = future.as_mut().poll(&mut context) {
+                return v;
+            }
+        }
+    }
+
+    /// Open the driver by the configuration like `mock:0` or `replay:<path>`.
+    fn open(bus: &VirtualBus, config: &str) -> Result<BoxDriver, Error> {
+        match config.split_once(':') {
+            Some(("mock", index)) => {
+                let index = index.parse()
+                    .map_err(|_| Error::InvalidParam(format!("invalid mock endpoint `{}`", index)))?;
+                Ok(Box::new(bus.driver(index)))
+            },
+            Some(("replay", path)) => Ok(Box::new(ReplayDriver::open(path)?)),
+            _ => Err(Error::InvalidParam(format!("unknown driver `{}`", config))),
+        }
+    }
+
+    /// Generic over the [`Driver`], the boxed drivers are accepted too.
+    async fn ping<D: Driver<C = String, F = CanMessage>>(driver: &D) -> Result<(), D::Error> {
+        let mut frame = CanMessage::new(0x7DF, &[0x02, 0x3E, 0x00]).unwrap();
+        frame.set_channel(MOCK_CHANNEL.into());
+        driver.transmit(frame, None).await
+    }
+
+    #[test]
+    fn test_dyn_driver() -> anyhow::Result<()> {
+        let bus = VirtualBus::new(2);
+        let a = open(&bus, "mock:0")?;
+        let b = open(&bus, "mock:1")?;
+        block_on(ping(&a))?;
+        let frames = block_on(Driver::receive(&b, MOCK_CHANNEL.into(), None))?;
+        assert_eq!(frames.len(), 1);
+        assert_eq!(frames[0].data(), [0x02, 0x3E, 0x00]);
 
+        let replay = open(&bus, concat!("replay:", env!("CARGO_MANIFEST_DIR"), "/resources/uds_session.log"))?;
+        assert!(!Driver::is_closed(&replay));
+        assert!(matches!(open(&bus, "slcan"), Err(Error::InvalidParam(_))));
+        Ok(())
+    }
 }
@@ -1,8 +1,15 @@
+use alloc::{boxed::Box, format, string::{String, ToString}, vec::Vec};
+use alloc::sync::Arc;
+
 #[derive(Debug, Clone, thiserror::Error)]
 pub enum Error {
+    #[deprecated(note = "use `Error::Device` that carries the source error")]
     #[error("ISO-TP - device error")]
     DeviceError,
 
+    #[error("ISO-TP - device error: {}", chain(source.as_ref()))]
+    Device { source: Arc<dyn core::error::Error + Send + Sync> },
+
     #[error("ISO-TP - the pdu(protocol data unit) is empty")]
     EmptyPdu,
 
@@ -21,12 +28,21 @@ pub enum Error {
     #[error("ISO-TP - invalid st_min: {0:02X}")]
     InvalidStMin(u8),
 
+    #[error("ISO-TP - invalid flow status: {0:X}")]
+    InvalidFlowStatus(u8),
+
     #[error("ISO-TP - invalid sequence: {actual}, expect: {expect}")]
     InvalidSequence{ actual: u8, expect: u8, },
 
     #[error("ISO-TP - mixed frames")]
     MixFramesError,
 
+    #[error("ISO-TP - the reception is restarted by a new first frame")]
+    ReceptionRestarted,
+
+    #[error("ISO-TP - frame {index}: {source}")]
+    Frame { index: usize, source: Box<Error> },
+
     #[error("ISO-TP - timeout when time({value}{unit})")]
     Timeout { value: u64, unit: &'static str },
 
@@ -38,4 +54,266 @@ pub enum Error {
 
     #[error("ISO-TP - context error when {0}")]
     ContextError(String),
+
+    #[error("ISO-TP - unsupported operation: {0}")]
+    Unsupported(String),
+
+    #[error("ISO-TP - the bus is off")]
+    BusOff,
+
+    #[error("ISO-TP - the transfer is aborted: {0}")]
+    Aborted(String),
+
+    #[error("ISO-TP - the instance is listen-only")]
+    ListenOnly,
+
+    #[error("ISO-TP - the device is shut down")]
+    Shutdown,
+}
+
+/// The stable codes of the variants, see [`Error::code`].
+///
+/// The codes are grouped by the category: `1xx` device, `2xx` protocol, `3xx` parameter and `4xx` transfer,
+/// the code of a variant is never changed or reused.
+pub mod code {
+    pub const DEVICE_ERROR: u16 = 100;
+    pub const DEVICE: u16 = 101;
+    pub const BUS_OFF: u16 = 102;
+
+    pub const EMPTY_PDU: u16 = 200;
+    pub const INVALID_PDU: u16 = 201;
+    pub const INVALID_ST_MIN: u16 = 202;
+    pub const INVALID_SEQUENCE: u16 = 203;
+    pub const MIX_FRAMES: u16 = 204;
+    pub const OVERLOAD_FLOW: u16 = 205;
+    pub const INVALID_DATA_LENGTH: u16 = 206;
+    pub const LENGTH_OUT_OF_RANGE: u16 = 207;
+    pub const RECEPTION_RESTARTED: u16 = 208;
+    pub const INVALID_FLOW_STATUS: u16 = 209;
+    pub const FRAME: u16 = 210;
+
+    pub const INVALID_PARAM: u16 = 300;
+    pub const CONVERT: u16 = 301;
+    pub const UNSUPPORTED: u16 = 302;
+    pub const CONTEXT: u16 = 303;
+    pub const LISTEN_ONLY: u16 = 304;
+
+    pub const TIMEOUT: u16 = 400;
+    pub const ABORTED: u16 = 401;
+    pub const SHUTDOWN: u16 = 402;
+}
+
+impl Error {
+    /// The stable code of the variant, see [`code`].
+    #[allow(deprecated)]
+    pub fn code(&self) -> u16 {
+        match self {
+            Self::DeviceError => code::DEVICE_ERROR,
+            Self::Device { .. } => code::DEVICE,
+            Self::BusOff => code::BUS_OFF,
+            Self::EmptyPdu => code::EMPTY_PDU,
+            Self::InvalidPdu(_) => code::INVALID_PDU,
+            Self::InvalidStMin(_) => code::INVALID_ST_MIN,
+            Self::InvalidFlowStatus(_) => code::INVALID_FLOW_STATUS,
+            Self::InvalidSequence { .. } => code::INVALID_SEQUENCE,
+            Self::MixFramesError => code::MIX_FRAMES,
+            Self::ReceptionRestarted => code::RECEPTION_RESTARTED,
+            Self::Frame { .. } => code::FRAME,
+            Self::OverloadFlow => code::OVERLOAD_FLOW,
+            Self::InvalidDataLength { .. } => code::INVALID_DATA_LENGTH,
+            Self::LengthOutOfRange(_) => code::LENGTH_OUT_OF_RANGE,
+            Self::InvalidParam(_) => code::INVALID_PARAM,
+            Self::ConvertError { .. } => code::CONVERT,
+            Self::Unsupported(_) => code::UNSUPPORTED,
+            Self::ContextError(_) => code::CONTEXT,
+            Self::ListenOnly => code::LISTEN_ONLY,
+            Self::Timeout { .. } => code::TIMEOUT,
+            Self::Aborted(_) => code::ABORTED,
+            Self::Shutdown => code::SHUTDOWN,
+        }
+    }
+
+    #[inline]
+    pub fn is_timeout(&self) -> bool {
+        matches!(self, Self::Timeout { .. })
+    }
+
+    /// The errors of the device and the bus.
+    #[inline]
+    pub fn is_device(&self) -> bool {
+        (100..200).contains(&self.code())
+    }
+
+    /// The violations of the ISO-TP protocol by the peer.
+    #[inline]
+    pub fn is_protocol(&self) -> bool {
+        (200..300).contains(&self.code())
+    }
+
+    /// Wrap an error of the device layer, an [`Error`] is returned as it is.
+    pub fn device<E>(e: E) -> Self
+    where
+        E: core::error::Error + Send + Sync + 'static,
+    {
+        let source: Box<dyn core::error::Error + Send + Sync> = Box::new(e);
+        match source.downcast::<Self>() {
+            Ok(e) => *e,
+            Err(source) => Self::Device { source: source.into() },
+        }
+    }
+}
+
+/// The structural equality, the [`Error::Device`] errors are compared by the code only.
+impl PartialEq for Error {
+    fn eq(&self, other: &Self) -> bool {
+        match (self, other) {
+            (Self::InvalidPdu(a), Self::InvalidPdu(b)) => a == b,
+            (Self::InvalidParam(a), Self::InvalidParam(b))
+            | (Self::ContextError(a), Self::ContextError(b))
+            | (Self::Unsupported(a), Self::Unsupported(b))
+            | (Self::Aborted(a), Self::Aborted(b)) => a == b,
+            (Self::InvalidDataLength { actual: a, expect: b }, Self::InvalidDataLength { actual: c, expect: d }) =>
+                (a, b) == (c, d),
+            (Self::LengthOutOfRange(a), Self::LengthOutOfRange(b)) => a == b,
+            (Self::InvalidStMin(a), Self::InvalidStMin(b))
+            | (Self::InvalidFlowStatus(a), Self::InvalidFlowStatus(b)) => a == b,
+            (Self::InvalidSequence { actual: a, expect: b }, Self::InvalidSequence { actual: c, expect: d }) =>
+                (a, b) == (c, d),
+            (Self::Timeout { value: a, unit: b }, Self::Timeout { value: c, unit: d }) => (a, b) == (c, d),
+            (Self::Frame { index: a, source: b }, Self::Frame { index: c, source: d }) => (a, b) == (c, d),
+            (Self::ConvertError { src: a, target: b }, Self::ConvertError { src: c, target: d }) => (a, b) == (c, d),
+            // the unit variants and `Device`
+            _ => self.code() == other.code(),
+        }
+    }
+}
+
+impl Eq for Error {}
+
+/// Reconstruct the unit variants from their codes.
+impl TryFrom<u16> for Error {
+    type Error = Error;
+
+    #[allow(deprecated)]
+    fn try_from(value: u16) -> Result<Self, Self::Error> {
+        match value {
+            code::DEVICE_ERROR => Ok(Self::DeviceError),
+            code::BUS_OFF => Ok(Self::BusOff),
+            code::EMPTY_PDU => Ok(Self::EmptyPdu),
+            code::MIX_FRAMES => Ok(Self::MixFramesError),
+            code::RECEPTION_RESTARTED => Ok(Self::ReceptionRestarted),
+            code::OVERLOAD_FLOW => Ok(Self::OverloadFlow),
+            code::LISTEN_ONLY => Ok(Self::ListenOnly),
+            code::SHUTDOWN => Ok(Self::Shutdown),
+            _ => Err(Self::InvalidParam(format!("not a code of the unit error: {}", value))),
+        }
+    }
+}
+
+/// Format an error with all of its sources like `error: source: source of source`.
+fn chain(e: &(dyn core::error::Error + 'static)) -> String {
+    let mut result = e.to_string();
+    let mut source = e.source();
+    while let Some(e) = source {
+        result.push_str(": ");
+        result.push_str(&e.to_string());
+        source = e.source();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{code, Error};
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("bus-off")]
+    struct BusOff;
+
+    #[derive(Debug, thiserror::Error)]
+    #[error("USB transfer failed")]
+    struct Usb(#[source] BusOff);
+
+    #[test]
+    fn test_device_source_chain() {
+        let error = Error::device(Usb(BusOff));
+        assert_eq!(error.to_string(), "ISO-TP - device error: USB transfer failed: bus-off");
+
+        let source = core::error::Error::source(&error).unwrap();
+        assert_eq!(source.to_string(), "USB transfer failed");
+        match &error {
+            Error::Device { source } => assert!(source.downcast_ref::<Usb>().is_some()),
+            _ => panic!("unexpected error: {:?}", error),
+        }
+
+        assert_eq!(Error::device(Error::OverloadFlow), Error::OverloadFlow);
+    }
+
+    #[allow(deprecated)]
+    fn variants() -> Vec<Error> {
+        vec![
+            Error::DeviceError,
+            Error::device(BusOff),
+            Error::EmptyPdu,
+            Error::InvalidPdu(vec![0x30]),
+            Error::InvalidParam("param".into()),
+            Error::InvalidDataLength { actual: 9, expect: 8 },
+            Error::LengthOutOfRange(0x1000),
+            Error::InvalidStMin(0xFA),
+            Error::InvalidFlowStatus(0x03),
+            Error::InvalidSequence { actual: 2, expect: 1 },
+            Error::MixFramesError,
+            Error::ReceptionRestarted,
+            Error::Frame { index: 2, source: Box::new(Error::MixFramesError) },
+            Error::Timeout { value: 1000, unit: "ms" },
+            Error::ConvertError { src: "u32", target: "Id" },
+            Error::OverloadFlow,
+            Error::ContextError("context".into()),
+            Error::Unsupported("unsupported".into()),
+            Error::BusOff,
+            Error::Aborted("aborted".into()),
+            Error::ListenOnly,
+            Error::Shutdown,
+        ]
+    }
+
+    #[test]
+    fn test_code() {
+        let variants = variants();
+        let codes = variants.iter()
+            .map(Error::code)
+            .collect::<std::collections::HashSet<_>>();
+        assert_eq!(codes.len(), variants.len());
+
+        for error in &variants {
+            match Error::try_from(error.code()) {
+                Ok(v) => assert_eq!(&v, error),
+                Err(e) => assert_eq!(e.code(), code::INVALID_PARAM),
+            }
+        }
+        assert_eq!(Error::try_from(code::BUS_OFF), Ok(Error::BusOff));
+        assert!(Error::try_from(0).is_err());
+
+        assert!(Error::Timeout { value: 1, unit: "ms" }.is_timeout());
+        assert!(Error::InvalidSequence { actual: 2, expect: 1 }.is_protocol());
+        assert!(!Error::InvalidParam("param".into()).is_protocol());
+        assert!(Error::BusOff.is_device() && Error::device(BusOff).is_device());
+    }
+
+    #[test]
+    fn test_eq() {
+        let variants = variants();
+        for (i, a) in variants.iter().enumerate() {
+            for (j, b) in variants.iter().enumerate() {
+                assert_eq!(a == b, i == j, "{:?} == {:?}", a, b);
+            }
+        }
+
+        assert_eq!(Error::device(BusOff), Error::device(std::io::Error::other("USB")));
+        assert_ne!(Error::InvalidParam("a".into()), Error::InvalidParam("b".into()));
+        assert_ne!(Error::InvalidSequence { actual: 2, expect: 1 }, Error::InvalidSequence { actual: 3, expect: 1 });
+        assert_ne!(Error::Frame { index: 2, source: Box::new(Error::MixFramesError) },
+                   Error::Frame { index: 3, source: Box::new(Error::MixFramesError) });
+    }
 }
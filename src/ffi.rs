@@ -0,0 +1,707 @@
+//! C ABI of the synchronous ISO-TP stack, the header `include/isotp_rs.h` is generated by cbindgen.
+//!
+//! A handle wraps [`SyncCan`] and [`SyncCanIsoTp`] over the driver selected by [`IsoTpDriverKind`].
+//! The functions return 0(or the length of the data) on success and the negative [`Error::code`] on failure.
+//!
+//! The identifiers of the frames passed through the ABI carry [`ISOTP_EFF_FLAG`] for the extended frames.
+
+use std::collections::VecDeque;
+use std::ffi::{c_char, c_int, c_void, CStr};
+use std::fmt::Display;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+use crate::{IsoTpEvent, IsoTpEventListener, IsoTpVerbosity};
+use crate::can::Address;
+use crate::can::driver::SyncCan;
+#[cfg(feature = "mock")]
+use crate::can::driver::{MockDriver, VirtualBus, MOCK_CHANNEL};
+use crate::can::frame::{Frame, FrameMut};
+use crate::can::identifier::Id;
+use crate::can::isotp::SyncCanIsoTp;
+use crate::can::message::CanMessage;
+use crate::device::Driver;
+use crate::error::{code, Error};
+
+pub const ISOTP_EVENT_WAIT: c_int = 0;
+pub const ISOTP_EVENT_FIRST_FRAME: c_int = 1;
+/// `data` and `len` are the received data.
+pub const ISOTP_EVENT_DATA_RECEIVED: c_int = 2;
+/// `data` is NULL and `len` is the [`Error::code`].
+pub const ISOTP_EVENT_ERROR: c_int = 3;
+/// `data` is NULL and `len` is the count of bytes written, verbose only.
+pub const ISOTP_EVENT_TX_COMPLETED: c_int = 4;
+/// Verbose only.
+pub const ISOTP_EVENT_FLOW_CONTROL_RECEIVED: c_int = 5;
+/// Verbose only.
+pub const ISOTP_EVENT_FLOW_CONTROL_SENT: c_int = 6;
+/// `data` and `len` are the data received before the error, which is reported by the failed `isotp_request`.
+pub const ISOTP_EVENT_RECEPTION_ABORTED: c_int = 7;
+/// `data` is NULL and `len` is N_Br of the delayed flow control in milliseconds.
+pub const ISOTP_EVENT_FLOW_CONTROL_DELAYED: c_int = 8;
+
+/// The flag of the extended identifiers.
+pub const ISOTP_EFF_FLAG: u32 = 0x8000_0000;
+
+/// The channel of the handles when [`IsoTpConfig::channel`] is NULL, except the mock driver.
+const DEFAULT_CHANNEL: &str = "can0";
+
+/// Transmit a frame by the user, returns 0 on success.
+pub type IsoTpTransmitFn = Option<extern "C" fn(id: u32, data: *const u8, len: usize, user: *mut c_void) -> c_int>;
+
+/// Receive an event, see the `ISOTP_EVENT_*` codes.
+pub type IsoTpEventFn = Option<extern "C" fn(event: c_int, data: *const u8, len: usize, user: *mut c_void)>;
+
+/// The driver of a handle.
+#[repr(C)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum IsoTpDriverKind {
+    /// The frames are transmitted by [`IsoTpConfig::transmit`] and received by [`isotp_receive_frame`].
+    Callback = 0,
+    /// The endpoint [`IsoTpConfig::mock_endpoint`] of [`IsoTpConfig::mock_bus`], requires the `mock` feature.
+    Mock = 1,
+    /// The SocketCAN interface [`IsoTpConfig::channel`], requires the `socketcan` feature.
+    SocketCan = 2,
+}
+
+/// The configuration of [`isotp_create`].
+#[repr(C)]
+pub struct IsoTpConfig {
+    pub driver: IsoTpDriverKind,
+    /// The channel name, the default channel is used when NULL.
+    pub channel: *const c_char,
+    pub tx_id: u32,
+    pub rx_id: u32,
+    pub fid: u32,
+    /// Whether the transmission and flow control events are reported.
+    pub verbose: bool,
+    pub transmit: IsoTpTransmitFn,
+    pub transmit_user: *mut c_void,
+    pub mock_bus: *const IsoTpMockBus,
+    pub mock_endpoint: usize,
+}
+
+/// The virtual bus of the mock driver.
+pub struct IsoTpMockBus {
+    #[cfg(feature = "mock")]
+    bus: VirtualBus,
+}
+
+/// The ISO-TP stack created by [`isotp_create`].
+pub struct IsoTpHandle {
+    transport: Box<dyn Transport>,
+    inbox: Option<Arc<Inbox>>,
+    channel: String,
+    shared: Arc<Shared>,
+}
+
+/// The user data passed back to C, the C side guarantees it's usable from the threads of the stack.
+#[derive(Debug, Copy, Clone)]
+struct User(*mut c_void);
+
+unsafe impl Send for User {}
+unsafe impl Sync for User {}
+
+impl Default for User {
+    fn default() -> Self {
+        Self(std::ptr::null_mut())
+    }
+}
+
+/// The state shared by the handle and its [`IsoTpEventListener`].
+#[derive(Default)]
+struct Shared {
+    callback: Mutex<(IsoTpEventFn, User)>,
+    response: Mutex<Option<Result<Arc<[u8]>, Error>>>,
+    notify: Condvar,
+}
+
+struct FfiListener(Arc<Shared>);
+
+impl FfiListener {
+    fn callback(&self, event: c_int, data: &[u8], len: usize) {
+        if let Ok(callback) = self.0.callback.lock() {
+            if let (Some(callback), user) = *callback {
+                let ptr = if data.is_empty() { std::ptr::null() } else { data.as_ptr() };
+                callback(event, ptr, len, user.0);
+            }
+        }
+    }
+
+    fn respond(&self, response: Result<Arc<[u8]>, Error>) {
+        if let Ok(mut v) = self.0.response.lock() {
+            *v = Some(response);
+            self.0.notify.notify_all();
+        }
+    }
+}
+
+impl IsoTpEventListener for FfiListener {
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        match event {
+            IsoTpEvent::Wait => self.callback(ISOTP_EVENT_WAIT, &[], 0),
+            IsoTpEvent::FirstFrameReceived { .. } => self.callback(ISOTP_EVENT_FIRST_FRAME, &[], 0),
+            IsoTpEvent::DataReceived { data, .. } => {
+                self.callback(ISOTP_EVENT_DATA_RECEIVED, &data, data.len());
+                self.respond(Ok(data));
+            },
+            IsoTpEvent::ErrorOccurred(e) => {
+                self.callback(ISOTP_EVENT_ERROR, &[], e.code() as usize);
+                self.respond(Err(e));
+            },
+            IsoTpEvent::ReceptionAborted { error, partial, .. } => {
+                self.callback(ISOTP_EVENT_RECEPTION_ABORTED, &partial, partial.len());
+                self.respond(Err(error));
+            },
+            IsoTpEvent::TxCompleted { bytes } => self.callback(ISOTP_EVENT_TX_COMPLETED, &[], bytes),
+            IsoTpEvent::FlowControlReceived(_) => self.callback(ISOTP_EVENT_FLOW_CONTROL_RECEIVED, &[], 0),
+            IsoTpEvent::FlowControlSent(_) => self.callback(ISOTP_EVENT_FLOW_CONTROL_SENT, &[], 0),
+            IsoTpEvent::FlowControlDelayed { n_br } =>
+                self.callback(ISOTP_EVENT_FLOW_CONTROL_DELAYED, &[], n_br.as_millis() as usize),
+        }
+    }
+}
+
+/// The frames pushed by [`isotp_receive_frame`].
+#[derive(Debug, Default)]
+struct Inbox {
+    frames: Mutex<VecDeque<CanMessage>>,
+    notify: Condvar,
+    closed: AtomicBool,
+}
+
+/// The [`Driver`] transmitting by the user callback.
+#[derive(Clone)]
+struct CallbackDriver {
+    channel: String,
+    transmit: extern "C" fn(u32, *const u8, usize, *mut c_void) -> c_int,
+    user: User,
+    inbox: Arc<Inbox>,
+}
+
+impl CallbackDriver {
+    fn transmit_util(&self, msg: CanMessage) -> Result<(), Error> {
+        let id = match msg.is_extended() {
+            true => msg.id().into_bits() | ISOTP_EFF_FLAG,
+            false => msg.id().into_bits(),
+        };
+        let data = msg.data();
+        match (self.transmit)(id, data.as_ptr(), data.len(), self.user.0) {
+            0 => Ok(()),
+            v => Err(Error::device(std::io::Error::other(format!("transmit callback returned {}", v)))),
+        }
+    }
+
+    fn receive_util(&self, channel: String, timeout: Option<u32>) -> Result<Vec<CanMessage>, Error> {
+        if channel != self.channel {
+            return Ok(vec![]);
+        }
+
+        let timeout = Duration::from_millis(timeout.unwrap_or_default() as u64);
+        let frames = self.inbox.frames.lock()
+            .map_err(|_| Error::ContextError("can't get `frames`".into()))?;
+        let (mut frames, _) = self.inbox.notify
+            .wait_timeout_while(frames, timeout, |v| v.is_empty() && !self.is_closed())
+            .map_err(|_| Error::ContextError("can't get `frames`".into()))?;
+
+        Ok(frames.drain(..).collect())
+    }
+
+    fn shutdown_util(&mut self) {
+        self.inbox.closed.store(true, Ordering::Release);
+        self.inbox.notify.notify_all();
+    }
+}
+
+impl Driver for CallbackDriver {
+    type Error = Error;
+    type C = String;
+    type F = CanMessage;
+
+    #[inline]
+    fn opened_channels(&self) -> Vec<Self::C> {
+        vec![self.channel.clone()]
+    }
+
+    #[inline]
+    fn is_closed(&self) -> bool {
+        self.inbox.closed.load(Ordering::Acquire)
+    }
+
+    #[inline]
+    fn is_blocking_receive(&self) -> bool {
+        true
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+    #[cfg(feature = "async")]
+    async fn transmit(&self, msg: Self::F, _: Option<u32>) -> Result<(), Self::Error> {
+        self.transmit_util(msg)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel, timeout)
+    }
+    #[cfg(feature = "async")]
+    async fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        self.receive_util(channel, timeout)
+    }
+
+    #[cfg(not(feature = "async"))]
+    fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+    #[cfg(feature = "async")]
+    async fn shutdown(&mut self) {
+        self.shutdown_util()
+    }
+}
+
+/// The driver independent part of the handle.
+trait Transport {
+    fn write(&self, functional: bool, data: Vec<u8>) -> Result<(), Error>;
+    fn stop(&mut self);
+}
+
+struct Stack<D, F> {
+    can: SyncCan<D, String, F>,
+    iso_tp: SyncCanIsoTp<String, F>,
+}
+
+impl<D, F> Transport for Stack<D, F>
+where
+    D: Driver<C = String, F = F> + Clone + 'static,
+    F: FrameMut<Channel = String> + Clone + Send + Display + 'static,
+{
+    #[inline]
+    fn write(&self, functional: bool, data: Vec<u8>) -> Result<(), Error> {
+        self.iso_tp.write(functional, data)
+    }
+
+    #[inline]
+    fn stop(&mut self) {
+        self.can.stop();
+    }
+}
+
+fn start<D, F>(device: D, channel: String, address: Address, verbosity: IsoTpVerbosity, shared: Arc<Shared>) -> Box<dyn Transport>
+where
+    D: Driver<C = String, F = F> + Clone + 'static,
+    F: FrameMut<Channel = String> + Clone + Send + Display + 'static,
+{
+    let mut can = SyncCan::new(device);
+    let iso_tp = SyncCanIsoTp::new(channel, address, can.sender(), Box::new(FfiListener(shared)))
+        .with_verbosity(verbosity);
+    can.register_listener("isotp-ffi".into(), Box::new(iso_tp.clone()));
+    can.sync_start(100);
+
+    Box::new(Stack { can, iso_tp })
+}
+
+#[inline]
+fn error_code(e: &Error) -> c_int {
+    -(e.code() as c_int)
+}
+
+/// # Safety
+///
+/// `data` must be valid for `len` bytes when not NULL.
+unsafe fn to_vec(data: *const u8, len: usize) -> Option<Vec<u8>> {
+    match (data.is_null(), len) {
+        (_, 0) => Some(vec![]),
+        (true, _) => None,
+        (false, _) => Some(std::slice::from_raw_parts(data, len).to_vec()),
+    }
+}
+
+/// Create a handle with `config`, NULL when the config is invalid or the driver fails to open.
+///
+/// # Safety
+///
+/// `config` must point to a valid [`IsoTpConfig`] and its `channel` must be NULL or a NUL-terminated string.
+#[no_mangle]
+pub unsafe extern "C" fn isotp_create(config: *const IsoTpConfig) -> *mut IsoTpHandle {
+    let Some(config) = config.as_ref() else {
+        return std::ptr::null_mut();
+    };
+    let channel = match config.channel.is_null() {
+        true => None,
+        false => match CStr::from_ptr(config.channel).to_str() {
+            Ok(v) => Some(v.to_owned()),
+            Err(_) => return std::ptr::null_mut(),
+        },
+    };
+    let address = Address { tx_id: config.tx_id, rx_id: config.rx_id, fid: config.fid };
+    let verbosity = if config.verbose { IsoTpVerbosity::Verbose } else { IsoTpVerbosity::Normal };
+    let shared = Arc::new(Shared::default());
+
+    let (transport, inbox, channel) = match config.driver {
+        IsoTpDriverKind::Callback => {
+            let Some(transmit) = config.transmit else {
+                log::warn!("ISO-TP(FFI) - the transmit callback is NULL");
+                return std::ptr::null_mut();
+            };
+            let channel = channel.unwrap_or(DEFAULT_CHANNEL.into());
+            let inbox = Arc::new(Inbox::default());
+            let device = CallbackDriver {
+                channel: channel.clone(),
+                transmit,
+                user: User(config.transmit_user),
+                inbox: inbox.clone(),
+            };
+            (start(device, channel.clone(), address, verbosity, shared.clone()), Some(inbox), channel)
+        },
+        #[cfg(feature = "mock")]
+        IsoTpDriverKind::Mock => {
+            let Some(bus) = config.mock_bus.as_ref() else {
+                log::warn!("ISO-TP(FFI) - the mock bus is NULL");
+                return std::ptr::null_mut();
+            };
+            if config.mock_endpoint >= bus.bus.len() {
+                log::warn!("ISO-TP(FFI) - mock endpoint: {} out of range", config.mock_endpoint);
+                return std::ptr::null_mut();
+            }
+            let channel = channel.unwrap_or(MOCK_CHANNEL.into());
+            let device: MockDriver = bus.bus.driver(config.mock_endpoint)
+                .with_channel(channel.clone());
+            (start(device, channel.clone(), address, verbosity, shared.clone()), None, channel)
+        },
+        #[cfg(all(target_os = "linux", feature = "socketcan"))]
+        IsoTpDriverKind::SocketCan => {
+            let channel = channel.unwrap_or(DEFAULT_CHANNEL.into());
+            let device = match crate::can::driver::SocketCanDriver::open(&[&channel]) {
+                Ok(v) => v,
+                Err(e) => {
+                    log::warn!("ISO-TP(FFI) - open `{}` failed: {}", channel, e);
+                    return std::ptr::null_mut();
+                },
+            };
+            (start(device, channel.clone(), address, verbosity, shared.clone()), None, channel)
+        },
+        #[allow(unreachable_patterns)]
+        driver => {
+            log::warn!("ISO-TP(FFI) - the driver {:?} is not enabled", driver);
+            return std::ptr::null_mut();
+        },
+    };
+
+    Box::into_raw(Box::new(IsoTpHandle { transport, inbox, channel, shared }))
+}
+
+/// Write `data`, returns 0 when all frames are sent.
+///
+/// # Safety
+///
+/// `handle` must be created by [`isotp_create`] and `data` must be valid for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn isotp_write(handle: *mut IsoTpHandle, data: *const u8, len: usize, functional: bool) -> c_int {
+    let (Some(handle), Some(data)) = (handle.as_ref(), to_vec(data, len)) else {
+        return -(code::INVALID_PARAM as c_int);
+    };
+
+    match handle.transport.write(functional, data) {
+        Ok(_) => 0,
+        Err(e) => error_code(&e),
+    }
+}
+
+/// Write the request `req` and wait for the response up to `timeout_ms`,
+/// returns the length of the response copied to `resp_buf`.
+///
+/// It must not be called from the event callback.
+///
+/// # Safety
+///
+/// `handle` must be created by [`isotp_create`], `req` must be valid for `req_len` bytes
+/// and `resp_buf` must be valid for `resp_cap` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn isotp_request(handle: *mut IsoTpHandle,
+                                       req: *const u8,
+                                       req_len: usize,
+                                       resp_buf: *mut u8,
+                                       resp_cap: usize,
+                                       timeout_ms: u32,
+) -> c_int {
+    let (Some(handle), Some(req)) = (handle.as_ref(), to_vec(req, req_len)) else {
+        return -(code::INVALID_PARAM as c_int);
+    };
+    if resp_buf.is_null() && resp_cap > 0 {
+        return -(code::INVALID_PARAM as c_int);
+    }
+
+    let shared = &handle.shared;
+    match shared.response.lock() {
+        Ok(mut v) => *v = None,
+        Err(_) => return -(code::CONTEXT as c_int),
+    }
+    if let Err(e) = handle.transport.write(false, req) {
+        return error_code(&e);
+    }
+
+    let deadline = Instant::now() + Duration::from_millis(timeout_ms as u64);
+    let Ok(mut response) = shared.response.lock() else {
+        return -(code::CONTEXT as c_int);
+    };
+    let response = loop {
+        if let Some(v) = response.take() {
+            break v;
+        }
+        let now = Instant::now();
+        if now >= deadline {
+            return error_code(&Error::Timeout { value: timeout_ms as u64, unit: "ms" });
+        }
+        response = match shared.notify.wait_timeout(response, deadline - now) {
+            Ok((v, _)) => v,
+            Err(_) => return -(code::CONTEXT as c_int),
+        };
+    };
+
+    match response {
+        Ok(data) if data.len() > resp_cap => error_code(&Error::LengthOutOfRange(data.len())),
+        Ok(data) => {
+            if !data.is_empty() {
+                std::ptr::copy_nonoverlapping(data.as_ptr(), resp_buf, data.len());
+            }
+            data.len() as c_int
+        },
+        Err(e) => error_code(&e),
+    }
+}
+
+/// Set the event `callback` with the `user` data, a NULL callback removes it.
+///
+/// The callback is called from the receiving thread of the handle.
+///
+/// # Safety
+///
+/// `handle` must be created by [`isotp_create`].
+#[no_mangle]
+pub unsafe extern "C" fn isotp_set_listener(handle: *mut IsoTpHandle, callback: IsoTpEventFn, user: *mut c_void) -> c_int {
+    let Some(handle) = handle.as_ref() else {
+        return -(code::INVALID_PARAM as c_int);
+    };
+
+    match handle.shared.callback.lock() {
+        Ok(mut v) => {
+            *v = (callback, User(user));
+            0
+        },
+        Err(_) => -(code::CONTEXT as c_int),
+    }
+}
+
+/// Pass a frame received by the user to a handle of [`IsoTpDriverKind::Callback`].
+///
+/// # Safety
+///
+/// `handle` must be created by [`isotp_create`] and `data` must be valid for `len` bytes.
+#[no_mangle]
+pub unsafe extern "C" fn isotp_receive_frame(handle: *mut IsoTpHandle, id: u32, data: *const u8, len: usize) -> c_int {
+    let (Some(handle), Some(data)) = (handle.as_ref(), to_vec(data, len)) else {
+        return -(code::INVALID_PARAM as c_int);
+    };
+    let Some(inbox) = &handle.inbox else {
+        return -(code::UNSUPPORTED as c_int);
+    };
+
+    let id = Id::from_bits(id & !ISOTP_EFF_FLAG, id & ISOTP_EFF_FLAG != 0);
+    let Some(mut frame) = CanMessage::new(id, &data) else {
+        return error_code(&Error::InvalidDataLength { actual: len, expect: 64 });
+    };
+    frame.set_channel(handle.channel.clone())
+        .set_timestamp(None);
+
+    match inbox.frames.lock() {
+        Ok(mut frames) => {
+            frames.push_back(frame);
+            inbox.notify.notify_all();
+            0
+        },
+        Err(_) => -(code::CONTEXT as c_int),
+    }
+}
+
+/// Stop and free a handle, NULL is ignored.
+///
+/// # Safety
+///
+/// `handle` must be created by [`isotp_create`] and not used after.
+#[no_mangle]
+pub unsafe extern "C" fn isotp_destroy(handle: *mut IsoTpHandle) {
+    if handle.is_null() {
+        return;
+    }
+
+    let mut handle = Box::from_raw(handle);
+    if let Some(inbox) = &handle.inbox {
+        inbox.closed.store(true, Ordering::Release);
+        inbox.notify.notify_all();
+    }
+    handle.transport.stop();
+}
+
+/// Create a virtual bus with `endpoints` endpoints for [`IsoTpDriverKind::Mock`].
+#[cfg(feature = "mock")]
+#[no_mangle]
+pub extern "C" fn isotp_mock_bus_create(endpoints: usize) -> *mut IsoTpMockBus {
+    Box::into_raw(Box::new(IsoTpMockBus { bus: VirtualBus::new(endpoints) }))
+}
+
+/// Free a virtual bus after the handles on it are destroyed, NULL is ignored.
+///
+/// # Safety
+///
+/// `bus` must be created by [`isotp_mock_bus_create`] and not used after.
+#[cfg(feature = "mock")]
+#[no_mangle]
+pub unsafe extern "C" fn isotp_mock_bus_destroy(bus: *mut IsoTpMockBus) {
+    if !bus.is_null() {
+        drop(Box::from_raw(bus));
+    }
+}
+
+#[cfg(all(test, feature = "mock", not(feature = "async")))]
+mod tests {
+    use std::ffi::{c_int, c_void};
+    use std::sync::Mutex;
+    use std::thread::sleep;
+    use std::time::Duration;
+    use crate::error::code;
+    use super::*;
+
+    /// The data received by the server, echoed back reversed in a single frame.
+    #[derive(Default)]
+    struct Server {
+        handle: Option<*mut IsoTpHandle>,
+        received: Mutex<Vec<Vec<u8>>>,
+    }
+
+    extern "C" fn on_server_event(event: c_int, data: *const u8, len: usize, user: *mut c_void) {
+        let server = unsafe { &*(user as *const Server) };
+        if event == ISOTP_EVENT_DATA_RECEIVED {
+            let data = unsafe { std::slice::from_raw_parts(data, len) }.to_vec();
+            let mut response = data.clone();
+            response.reverse();
+            // a multi-frame writing waits for the flow control received by the blocked thread
+            response.truncate(7);
+            server.received.lock().unwrap().push(data);
+            unsafe { isotp_write(server.handle.unwrap(), response.as_ptr(), response.len(), false) };
+        }
+    }
+
+    fn config(bus: *const IsoTpMockBus, endpoint: usize, tx_id: u32, rx_id: u32) -> IsoTpConfig {
+        IsoTpConfig {
+            driver: IsoTpDriverKind::Mock,
+            channel: std::ptr::null(),
+            tx_id,
+            rx_id,
+            fid: 0x7DF,
+            verbose: false,
+            transmit: None,
+            transmit_user: std::ptr::null_mut(),
+            mock_bus: bus,
+            mock_endpoint: endpoint,
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let bus = isotp_mock_bus_create(2);
+        let client = unsafe { isotp_create(&config(bus, 0, 0x7E0, 0x7E8)) };
+        let server_handle = unsafe { isotp_create(&config(bus, 1, 0x7E8, 0x7E0)) };
+        assert!(!client.is_null() && !server_handle.is_null());
+
+        let server = Box::new(Server { handle: Some(server_handle), ..Default::default() });
+        let user = &*server as *const Server as *mut c_void;
+        assert_eq!(unsafe { isotp_set_listener(server_handle, Some(on_server_event), user) }, 0);
+
+        let request = [0x22, 0xF1, 0x90];
+        let mut response = [0u8; 8];
+        let len = unsafe {
+            isotp_request(client, request.as_ptr(), request.len(), response.as_mut_ptr(), response.len(), 1000)
+        };
+        assert_eq!(len, 3);
+        assert_eq!(&response[..3], [0x90, 0xF1, 0x22]);
+
+        let data: Vec<u8> = (0..0x20).collect();
+        assert_eq!(unsafe { isotp_write(client, data.as_ptr(), data.len(), false) }, 0);
+        sleep(Duration::from_millis(10));
+        assert_eq!(server.received.lock().unwrap().last(), Some(&data));
+
+        // the response is too large for the buffer
+        let len = unsafe {
+            isotp_request(client, data.as_ptr(), data.len(), response.as_mut_ptr(), 2, 1000)
+        };
+        assert_eq!(len, -(code::LENGTH_OUT_OF_RANGE as c_int));
+
+        unsafe {
+            isotp_destroy(client);
+            isotp_destroy(server_handle);
+            isotp_mock_bus_destroy(bus);
+        }
+    }
+
+    #[test]
+    fn test_invalid() {
+        unsafe {
+            assert!(isotp_create(std::ptr::null()).is_null());
+            assert!(isotp_create(&config(std::ptr::null(), 0, 0x7E0, 0x7E8)).is_null());
+            assert_eq!(isotp_write(std::ptr::null_mut(), std::ptr::null(), 0, false), -(code::INVALID_PARAM as c_int));
+        }
+
+        let bus = isotp_mock_bus_create(1);
+        let client = unsafe { isotp_create(&config(bus, 0, 0x7E0, 0x7E8)) };
+        let mut response = [0u8; 8];
+        let len = unsafe {
+            isotp_request(client, [0x3E, 0x00].as_ptr(), 2, response.as_mut_ptr(), response.len(), 50)
+        };
+        assert_eq!(len, -(code::TIMEOUT as c_int));
+        assert_eq!(unsafe { isotp_receive_frame(client, 0x7E8, [0x01, 0x3E].as_ptr(), 2) }, -(code::UNSUPPORTED as c_int));
+
+        unsafe {
+            isotp_destroy(client);
+            isotp_mock_bus_destroy(bus);
+        }
+    }
+
+    extern "C" fn loopback(id: u32, data: *const u8, len: usize, user: *mut c_void) -> c_int {
+        let peer = unsafe { *(user as *const *mut IsoTpHandle) };
+        unsafe { isotp_receive_frame(peer, id, data, len) }
+    }
+
+    #[test]
+    fn test_callback_driver() {
+        let mut peers: Box<[*mut IsoTpHandle; 2]> = Box::new([std::ptr::null_mut(); 2]);
+        let mut callback_config = |index: usize, tx_id: u32, rx_id: u32| IsoTpConfig {
+            driver: IsoTpDriverKind::Callback,
+            transmit: Some(loopback),
+            // the frames are passed to the other handle
+            transmit_user: &mut peers[1 - index] as *mut _ as *mut c_void,
+            ..config(std::ptr::null(), 0, tx_id, rx_id)
+        };
+        let (client_config, server_config) = (callback_config(0, 0x7E0, 0x7E8), callback_config(1, 0x7E8, 0x7E0));
+        peers[0] = unsafe { isotp_create(&client_config) };
+        peers[1] = unsafe { isotp_create(&server_config) };
+
+        let server = Box::new(Server { handle: Some(peers[1]), ..Default::default() });
+        let user = &*server as *const Server as *mut c_void;
+        unsafe { isotp_set_listener(peers[1], Some(on_server_event), user) };
+
+        let request: Vec<u8> = (0..0x10).collect();
+        let mut response = [0u8; 0x20];
+        let len = unsafe {
+            isotp_request(peers[0], request.as_ptr(), request.len(), response.as_mut_ptr(), response.len(), 1000)
+        };
+        assert_eq!(len, 7);
+        assert_eq!(&response[..7], (0x09..0x10).rev().collect::<Vec<u8>>());
+
+        unsafe {
+            isotp_destroy(peers[0]);
+            isotp_destroy(peers[1]);
+        }
+    }
+}
@@ -1,10 +1,19 @@
+#![cfg_attr(not(feature = "std"), no_std)]
+
+extern crate alloc;
+
 pub mod constant;
 pub mod error;
 pub mod can;
 pub mod device;
+pub mod prelude;
+#[cfg(feature = "ffi")]
+pub mod ffi;
 
-use std::fmt::{Debug, Display, Formatter};
-use std::sync::atomic::{AtomicU8, Ordering};
+use alloc::{boxed::Box, format, sync::Arc, vec::Vec};
+use core::fmt::{Debug, Display, Formatter};
+use core::sync::atomic::{AtomicU8, Ordering};
+use core::time::Duration;
 use bitflags::bitflags;
 use crate::error::Error;
 
@@ -28,9 +37,10 @@ bitflags! {
     }
 }
 
+/// The names of the flags joined by `|`, e.g. `WaitFlowCtrl|Sending`, `Idle` when none.
 impl Display for IsoTpState {
     #[allow(deprecated)]
-    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
         let mut idle = true;
         let mut first = true;
         if self.contains(IsoTpState::WaitSingle) {
@@ -39,37 +49,37 @@ impl Display for IsoTpState {
             first = false;
         }
         if self.contains(IsoTpState::WaitFirst) {
-            write!(f, "{}", format!("{}WaitFirst", if first { "" } else { " | " }))?;
+            write!(f, "{}WaitFirst", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::WaitFlowCtrl) {
-            write!(f, "{}", format!("{}WaitFlowCtrl", if first { "" } else { " | " }))?;
+            write!(f, "{}WaitFlowCtrl", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::WaitData) {
-            write!(f, "{}", format!("{}WaitData", if first { "" } else { " | " }))?;
+            write!(f, "{}WaitData", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::WaitBusy) {
-            write!(f, "{}", format!("{}WaitBusy", if first { "" } else { " | " }))?;
+            write!(f, "{}WaitBusy", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::ResponsePending) {
-            write!(f, "{}", format!("{}ResponsePending", if first { "" } else { " | " }))?;
+            write!(f, "{}ResponsePending", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::Sending) {
-            write!(f, "{}", format!("{}Sending", if first { "" } else { " | " }))?;
+            write!(f, "{}Sending", if first { "" } else { "|" })?;
             idle = false;
             first = false;
         }
         if self.contains(IsoTpState::Error) {
-            write!(f, "{}", format!("{}Error", if first { "" } else { " | " }))?;
+            write!(f, "{}Error", if first { "" } else { "|" })?;
             idle = false;
         }
         if idle {
@@ -145,20 +155,173 @@ impl AtomicState {
     }
 }
 
+/// The event of ISO-TP, the timestamps are in milliseconds since the UNIX epoch.
+///
+/// The timestamps are taken from the received frames, the current system time when not available.
 #[derive(Debug, Clone)]
 pub enum IsoTpEvent {
     Wait,
-    FirstFrameReceived,
-    DataReceived(Vec<u8>),
+    FirstFrameReceived { at: u64 },
+    /// The data of a single frame or reassembled from the consecutive frames,
+    /// both timestamps are the single frame's for a single frame.
+    ///
+    /// The data is shared, cloning the event doesn't copy it. A `Vec<u8>` converts to it by
+    /// `into()`, and [`IsoTpEvent::into_data`] copies it out.
+    DataReceived { data: Arc<[u8]>, first_frame_at: u64, completed_at: u64 },
     ErrorOccurred(Error),
+    /// The receiving is aborted by the error, `partial` is the data received before
+    /// and `expected` is the length of the first frame.
+    ReceptionAborted { error: Error, partial: Vec<u8>, expected: u32 },
+    /// The last frame of the writing is confirmed on the bus, [`IsoTpVerbosity::Verbose`] only.
+    TxCompleted { bytes: usize },
+    /// The flow control of the peer is received, [`IsoTpVerbosity::Verbose`] only.
+    FlowControlReceived(FlowControlContext),
+    /// The flow control replying the first frame is sent, [`IsoTpVerbosity::Verbose`] only.
+    FlowControlSent(FlowControlContext),
+    /// The flow control is transmitted `n_br` after the frame it replies, longer than the N_Br warning
+    /// of the instance, e.g. the transmitting is delayed by the frames queued before it.
+    FlowControlDelayed { n_br: Duration },
 }
 
-pub trait IsoTpEventListener {
-    fn from_buffer(&mut self) -> Option<IsoTpEvent>;
-    fn clear_buffer(&mut self);
+impl IsoTpEvent {
+    /// The received data, `None` for the other events.
+    #[inline]
+    pub fn data(&self) -> Option<&[u8]> {
+        match self {
+            Self::DataReceived { data, .. } => Some(data),
+            _ => None,
+        }
+    }
+    /// The shared received data without copying, `None` for the other events.
+    #[inline]
+    pub fn shared_data(&self) -> Option<Arc<[u8]>> {
+        match self {
+            Self::DataReceived { data, .. } => Some(Arc::clone(data)),
+            _ => None,
+        }
+    }
+    /// Copy the received data out, `None` for the other events.
+    #[inline]
+    pub fn into_data(self) -> Option<Vec<u8>> {
+        match self {
+            Self::DataReceived { data, .. } => Some(data.to_vec()),
+            _ => None,
+        }
+    }
+    /// The error of [`IsoTpEvent::ErrorOccurred`] and [`IsoTpEvent::ReceptionAborted`].
+    #[inline]
+    pub fn error(&self) -> Option<&Error> {
+        match self {
+            Self::ErrorOccurred(e) | Self::ReceptionAborted { error: e, .. } => Some(e),
+            _ => None,
+        }
+    }
+    /// The milliseconds from the first frame to the completion of the receiving.
+    #[inline]
+    pub fn duration_ms(&self) -> Option<u64> {
+        match self {
+            Self::DataReceived { first_frame_at, completed_at, .. } =>
+                Some(completed_at.saturating_sub(*first_frame_at)),
+            _ => None,
+        }
+    }
+}
+
+/// The events reported to the [`IsoTpEventListener`].
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum IsoTpVerbosity {
+    /// The receiving, waiting and error events.
+    #[default]
+    Normal,
+    /// Also the transmission and flow control events.
+    Verbose,
+}
+
+/// How a first frame received during the receiving of the consecutive frames is handled.
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq)]
+pub enum FirstFramePolicy {
+    /// Abort the receiving by [`Error::ReceptionRestarted`] and start the new one, as ISO 15765-2.
+    #[default]
+    Restart,
+    /// Ignore the new first frame until the receiving is completed or aborted, e.g. for the robustness tests.
+    Ignore,
+}
+
+/// The sequence number of the first consecutive frame of a transfer.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum SequenceStart {
+    /// Transmit and expect the sequence(the low 4 bits), 1 by default as ISO 15765-2,
+    /// e.g. 0 of some legacy ECUs.
+    Strict(u8),
+    /// Accept 0 or 1 for the first consecutive frame and expect the following ones from it,
+    /// the transmitting starts at 1.
+    AutoDetect,
+}
+
+impl Default for SequenceStart {
+    #[inline]
+    fn default() -> Self {
+        Self::Strict(constant::CONSECUTIVE_SEQUENCE_START)
+    }
+}
+
+impl SequenceStart {
+    /// The sequence of the first consecutive frame transmitted.
+    #[inline]
+    pub fn tx_sequence(&self) -> u8 {
+        match self {
+            Self::Strict(v) => v & 0x0F,
+            Self::AutoDetect => constant::CONSECUTIVE_SEQUENCE_START,
+        }
+    }
+
+    /// The sequence expected of the first consecutive frame received, `sequence` is the received one.
+    #[inline]
+    pub fn rx_sequence(&self, sequence: u8) -> u8 {
+        match self {
+            Self::Strict(v) => v & 0x0F,
+            Self::AutoDetect if sequence <= constant::CONSECUTIVE_SEQUENCE_START => sequence,
+            Self::AutoDetect => constant::CONSECUTIVE_SEQUENCE_START,
+        }
+    }
+}
+
+/// The receiver of the [`IsoTpEvent`], a closure is boxed by [`from_fn`](<dyn IsoTpEventListener>::from_fn).
+///
+/// Only [`IsoTpEventListener::on_iso_tp_event`] is required, the other callbacks are optional.
+pub trait IsoTpEventListener: Send {
+    /// Take an event buffered by the listener, `None` by default.
+    #[allow(clippy::wrong_self_convention)]
+    fn from_buffer(&mut self) -> Option<IsoTpEvent> {
+        None
+    }
+    /// Discard the partial data buffered by the listener, no-op by default.
+    ///
+    /// Called when a new first frame is received and when the transfer is aborted by bus off.
+    fn clear_buffer(&mut self) {}
     fn on_iso_tp_event(&mut self, event: IsoTpEvent);
 }
 
+impl dyn IsoTpEventListener {
+    /// Box a closure as the listener.
+    pub fn from_fn<F>(f: F) -> Box<dyn IsoTpEventListener>
+    where
+        F: FnMut(IsoTpEvent) + Send + 'static,
+    {
+        Box::new(FnEventListener(f))
+    }
+}
+
+/// The [`IsoTpEventListener`] calling a closure, see [`from_fn`](<dyn IsoTpEventListener>::from_fn).
+pub struct FnEventListener<F>(pub F);
+
+impl<F: FnMut(IsoTpEvent) + Send> IsoTpEventListener for FnEventListener<F> {
+    #[inline]
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        (self.0)(event)
+    }
+}
+
 /// ISO-TP timeout type define.
 /// The unit of value is ms.
 #[derive(Debug, Copy, Clone)]
@@ -191,10 +354,10 @@ pub enum FrameType {
     FlowControl = 0x30,
 }
 
-impl Into<u8> for FrameType {
+impl From<FrameType> for u8 {
     #[inline]
-    fn into(self) -> u8 {
-        self as u8
+    fn from(val: FrameType) -> Self {
+        val as u8
     }
 }
 
@@ -229,15 +392,15 @@ impl TryFrom<u8> for FlowControlState {
             0x00 => Ok(Self::Continues),
             0x01 => Ok(Self::Wait),
             0x02 => Ok(Self::Overload),
-            v => Err(Error::InvalidParam(format!("`state` ({})", v))),
+            v => Err(Error::InvalidFlowStatus(v)),
         }
     }
 }
 
-impl Into<u8> for FlowControlState {
+impl From<FlowControlState> for u8 {
     #[inline]
-    fn into(self) -> u8 {
-        self as u8
+    fn from(val: FlowControlState) -> Self {
+        val as u8
     }
 }
 
@@ -247,16 +410,34 @@ pub struct FlowControlContext {
     state: FlowControlState,
     block_size: u8,
     /// Use milliseconds (ms) for values in the range 00 to 7F (0 ms to 127 ms).
-    /// If st_min is 0, set to default value. See [`constant::ST_MIN_ISO15765_2`]
-    /// and [`constant::ST_MIN_ISO15765_4`]
+    /// 0 means no delay between the consecutive frames, the defaults are
+    /// [`constant::ST_MIN_ISO15765_2`] and [`constant::ST_MIN_ISO15765_4`](OBD).
     ///
     /// Use microseconds (μs) for values in the range F1 to F9 (100 μs to 900 μs).
     ///
     /// Values in the ranges 80 to F0 and FA to FF are reserved.
     st_min: u8,
+    /// The STmin byte received, the reserved one is kept here and [`st_min`](Self::st_min) is
+    /// [`constant::MAX_ST_MIN`].
+    raw_st_min: u8,
 }
 
 impl FlowControlContext {
+    /// [`constant::BS_ISO15765_2`] and [`constant::ST_MIN_ISO15765_2`].
+    pub const ISO15765_2: Self = Self {
+        state: FlowControlState::Continues,
+        block_size: constant::BS_ISO15765_2,
+        st_min: constant::ST_MIN_ISO15765_2,
+        raw_st_min: constant::ST_MIN_ISO15765_2,
+    };
+    /// [`constant::BS_ISO15765_4`] and [`constant::ST_MIN_ISO15765_4`] of OBD.
+    pub const ISO15765_4: Self = Self {
+        state: FlowControlState::Continues,
+        block_size: constant::BS_ISO15765_4,
+        st_min: constant::ST_MIN_ISO15765_4,
+        raw_st_min: constant::ST_MIN_ISO15765_4,
+    };
+
     #[inline]
     pub fn new(
         state: FlowControlState,
@@ -266,9 +447,25 @@ impl FlowControlContext {
         match st_min {
             0x80..=0xF0 |
             0xFA..=0xFF => Err(Error::InvalidStMin(st_min)),
-            v => Ok(Self { state, block_size, st_min: v }),
+            v => Ok(Self { state, block_size, st_min: v, raw_st_min: v }),
         }
     }
+    /// The flow control received from the peer, a reserved `st_min` is taken as
+    /// [`constant::MAX_ST_MIN`] as ISO 15765-2 and kept by [`raw_st_min`](Self::raw_st_min).
+    #[inline]
+    pub fn from_raw(
+        state: FlowControlState,
+        block_size: u8,
+        st_min: u8,
+    ) -> Self {
+        let raw_st_min = st_min;
+        let st_min = match st_min {
+            0x80..=0xF0 |
+            0xFA..=0xFF => constant::MAX_ST_MIN,
+            v => v,
+        };
+        Self { state, block_size, st_min, raw_st_min }
+    }
     #[inline]
     pub fn state(&self) -> FlowControlState {
         self.state
@@ -281,20 +478,31 @@ impl FlowControlContext {
     pub fn st_min(&self) -> u8 {
         self.st_min
     }
+    /// The STmin byte as received, differs from [`st_min`](Self::st_min) when it's reserved.
+    #[inline]
+    pub fn raw_st_min(&self) -> u8 {
+        self.raw_st_min
+    }
+    /// The separation time of the pacing.
     #[inline]
     pub fn st_min_us(&self) -> u32 {
         match self.st_min {
-            // 0x00 => 1000 * 10,
+            // 0x00 is no delay
             ..=0x7F => 1000 * (self.st_min as u32),
-            0x80..=0xF0 |
-            0xFA..=0xFF => {
-                // should not enter
-                let message = format!("ISO-TP: got an invalid st_min: {}", self.st_min);
-                log::error!("{}" ,message);
-                panic!("{}", message)   // panic is dangerous
-            },
             0xF1..=0xF9 => 100 * (self.st_min & 0x0F) as u32,
+            // the reserved one is taken as the max
+            _ => 1000 * (constant::MAX_ST_MIN as u32),
+        }
+    }
+}
+
+impl Display for FlowControlContext {
+    fn fmt(&self, f: &mut Formatter<'_>) -> core::fmt::Result {
+        write!(f, "{:?} BS: {} STmin: {:02X}", self.state, self.block_size, self.st_min)?;
+        if self.raw_st_min != self.st_min {
+            write!(f, "(raw: {:02X})", self.raw_st_min)?;
         }
+        Ok(())
     }
 }
 
@@ -376,12 +584,13 @@ pub trait IsoTpFrame: Send {
     where
         Self: Sized;
 
+    /// The flow control of [`FlowControlContext::ISO15765_2`].
     #[inline]
     fn default_flow_ctrl_frame() -> Self
     where
         Self: Sized
     {
-        Self::flow_ctrl_frame(FlowControlState::Continues, 0x00, 10)
+        Self::flow_ctrl_frame(FlowControlState::Continues, constant::BS_ISO15765_2, constant::ST_MIN_ISO15765_2)
             .unwrap()
     }
 }
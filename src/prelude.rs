@@ -0,0 +1,24 @@
+//! The commonly needed items of the frames, the drivers and the ISO-TP transports,
+//! imported at once by `use isotp_rs::prelude::*;`.
+//!
+//! ```
+//! use isotp_rs::prelude::*;
+//!
+//! let frames = CanIsoTpFrame::from_data([0x10, 0x03])?;
+//! assert_eq!(frames[0].clone().encode(None), [0x02, 0x10, 0x03, 0xAA, 0xAA, 0xAA, 0xAA, 0xAA]);
+//! # Ok::<(), Error>(())
+//! ```
+
+pub use crate::{IsoTpEvent, IsoTpEventListener, IsoTpFrame};
+pub use crate::can::{Address, AddressContext, AddressFormat, AddressType, CanIsoTpFrame};
+pub use crate::can::frame::{Frame, FrameMut};
+pub use crate::can::message::CanMessage;
+pub use crate::device::{Channel, Driver, Listener};
+pub use crate::error::Error;
+
+#[cfg(feature = "std")]
+pub use crate::can::isotp::SyncCanIsoTp;
+#[cfg(feature = "tokio")]
+pub use crate::can::isotp::AsyncCanIsoTp;
+#[cfg(all(feature = "std", not(feature = "async")))]
+pub use crate::can::driver::SyncCan;
@@ -0,0 +1,175 @@
+//! A user-defined channel enum through the frames, the driver, `SyncCan` and the ISO-TP transports,
+//! the mock frames and driver are wrapped to be on the channel `Bus::Powertrain`.
+#![cfg(not(feature = "async"))]
+
+use std::fmt::{Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use isotp_rs::{IsoTpEvent, IsoTpEventListener};
+use isotp_rs::can::Address;
+use isotp_rs::can::driver::{MockDriver, SyncCan, VirtualBus, MOCK_CHANNEL};
+use isotp_rs::can::frame::{Direct, Frame, FrameMut};
+use isotp_rs::can::identifier::Id;
+use isotp_rs::can::isotp::SyncCanIsoTp;
+use isotp_rs::can::message::CanMessage;
+use isotp_rs::device::{Channel, Driver};
+use isotp_rs::error::Error;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
+enum Bus {
+    Powertrain,
+    Body,
+}
+
+impl Display for Bus {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Powertrain => write!(f, "PT-CAN"),
+            Self::Body => write!(f, "B-CAN"),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+struct BusFrame {
+    inner: CanMessage,
+    bus: Bus,
+}
+
+impl Frame for BusFrame {
+    type Channel = Bus;
+
+    fn timestamp(&self) -> u64 { self.inner.timestamp() }
+    fn id(&self) -> Id { self.inner.id() }
+    fn is_can_fd(&self) -> bool { self.inner.is_can_fd() }
+    fn is_remote(&self) -> bool { self.inner.is_remote() }
+    fn is_extended(&self) -> bool { self.inner.is_extended() }
+    fn direct(&self) -> Direct { self.inner.direct() }
+    fn is_bitrate_switch(&self) -> bool { self.inner.is_bitrate_switch() }
+    fn is_error_frame(&self) -> bool { self.inner.is_error_frame() }
+    fn is_esi(&self) -> bool { self.inner.is_esi() }
+    fn channel(&self) -> Self::Channel { self.bus }
+    fn data(&self) -> &[u8] { self.inner.data() }
+    fn dlc(&self) -> Option<usize> { self.inner.dlc() }
+    fn length(&self) -> usize { self.inner.length() }
+}
+
+impl FrameMut for BusFrame {
+    fn new(id: impl Into<Id>, data: &[u8]) -> Option<Self> {
+        CanMessage::new(id, data).map(|inner| Self { inner, bus: Bus::Body })
+    }
+
+    fn new_remote(id: impl Into<Id>, len: usize) -> Option<Self> {
+        CanMessage::new_remote(id, len).map(|inner| Self { inner, bus: Bus::Body })
+    }
+
+    fn set_timestamp(&mut self, value: Option<u64>) -> &mut Self { self.inner.set_timestamp(value); self }
+    fn set_can_fd(&mut self, value: bool) -> &mut Self { self.inner.set_can_fd(value); self }
+    fn set_direct(&mut self, direct: Direct) -> &mut Self { self.inner.set_direct(direct); self }
+    fn set_bitrate_switch(&mut self, value: bool) -> &mut Self { self.inner.set_bitrate_switch(value); self }
+    fn set_error_frame(&mut self, value: bool) -> &mut Self { self.inner.set_error_frame(value); self }
+    fn set_esi(&mut self, value: bool) -> &mut Self { self.inner.set_esi(value); self }
+    fn set_channel(&mut self, value: Self::Channel) -> &mut Self { self.bus = value; self }
+}
+
+impl Display for BusFrame {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        <dyn Frame<Channel = Bus> as Display>::fmt(self, f)
+    }
+}
+
+/// The mock driver on the channel `Bus::Powertrain`.
+#[derive(Clone)]
+struct BusDriver(MockDriver);
+
+impl Driver for BusDriver {
+    type Error = Error;
+    type C = Bus;
+    type F = BusFrame;
+
+    fn opened_channels(&self) -> Vec<Self::C> {
+        match self.0.opened_channels().is_empty() {
+            true => vec![],
+            false => vec![Bus::Powertrain],
+        }
+    }
+
+    fn is_closed(&self) -> bool {
+        self.0.is_closed()
+    }
+
+    fn transmit(&self, msg: Self::F, timeout: Option<u32>) -> Result<(), Self::Error> {
+        if msg.bus != Bus::Powertrain {
+            return Err(Error::InvalidParam(format!("channel `{}` is not opened", msg.bus)));
+        }
+        let mut inner = msg.inner;
+        inner.set_channel(MOCK_CHANNEL.into());
+        self.0.transmit(inner, timeout)
+    }
+
+    fn receive(&self, channel: Self::C, timeout: Option<u32>) -> Result<Vec<Self::F>, Self::Error> {
+        Ok(self.0.receive(MOCK_CHANNEL.into(), timeout)?
+            .into_iter()
+            .map(|inner| BusFrame { inner, bus: channel })
+            .collect())
+    }
+
+    fn shutdown(&mut self) {
+        self.0.shutdown()
+    }
+}
+
+#[derive(Clone, Default)]
+struct Received(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl IsoTpEventListener for Received {
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        if let IsoTpEvent::DataReceived { data, .. } = event {
+            self.0.lock().unwrap().push(data.to_vec());
+        }
+    }
+}
+
+fn new_iso_tp(can: &SyncCan<BusDriver, Bus, BusFrame>, address: Address, received: &Received) -> SyncCanIsoTp<Bus, BusFrame> {
+    let iso_tp = SyncCanIsoTp::new(Bus::Powertrain, address, can.sender(), Box::new(received.clone()));
+    can.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+    iso_tp
+}
+
+#[test]
+fn test_custom_channel() -> anyhow::Result<()> {
+    fn assert_channel<C: Channel>(_: C) {}
+    assert_channel(Bus::Body);
+    assert_channel("can0");
+    assert_channel(0_u8);
+
+    let (a, b) = VirtualBus::pair();
+    let mut client_can = SyncCan::new(BusDriver(a));
+    let mut server_can = SyncCan::new(BusDriver(b));
+    let client_received = Received::default();
+    let server_received = Received::default();
+    let client = new_iso_tp(&client_can, Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF }, &client_received);
+    let server = new_iso_tp(&server_can, Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF }, &server_received);
+    client_can.sync_start(100);
+    server_can.sync_start(100);
+
+    let request = (0..0x20).collect::<Vec<u8>>();
+    client.write(false, request.clone())?;
+    let start = Instant::now();
+    while server_received.0.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(1) {
+        sleep(Duration::from_millis(1));
+    }
+    assert_eq!(server_received.0.lock().unwrap().as_slice(), [request]);
+
+    server.write(false, vec![0x50, 0x03])?;
+    let start = Instant::now();
+    while client_received.0.lock().unwrap().is_empty() && start.elapsed() < Duration::from_secs(1) {
+        sleep(Duration::from_millis(1));
+    }
+    assert_eq!(client_received.0.lock().unwrap().as_slice(), [vec![0x50, 0x03]]);
+
+    client_can.stop();
+    server_can.stop();
+    Ok(())
+}
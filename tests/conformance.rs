@@ -0,0 +1,432 @@
+//! The conformance of `SyncCanIsoTp` to ISO 15765-2, the scripted frames of each scenario are fed
+//! to the instance with their timestamps, the frames transmitted and the events are matched exactly.
+//!
+//! A frame is written in hex, `..` pads it to 8 bytes by `AA`. The events are written compactly:
+//! `FF`, `Wait`, `Data <hex>`, `TxDone <bytes>`, `Error <error>`, `Aborted <error> <partial>/<expected>`,
+//! `FC< <hex>` received and `FC> <hex>` sent.
+#![cfg(not(feature = "async"))]
+
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{channel, Receiver};
+use std::thread::{sleep, spawn, JoinHandle};
+use std::time::{Duration, Instant};
+use isotp_rs::{FlowControlContext, FlowControlState, IsoTpEvent, IsoTpEventListener, IsoTpVerbosity};
+use isotp_rs::can::Address;
+use isotp_rs::can::frame::{Direct, Frame, FrameMut};
+use isotp_rs::can::identifier::Id;
+use isotp_rs::can::isotp::SyncCanIsoTp;
+use isotp_rs::can::message::CanMessage;
+use isotp_rs::device::Listener;
+use isotp_rs::error::Error;
+
+const CHANNEL: &str = "can0";
+const ADDRESS: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+/// The timestamp(ms) of the offset 0.
+const EPOCH: u64 = 1_000_000;
+
+#[derive(Debug, Clone, Copy)]
+enum Step {
+    /// A frame of the peer received at the offset(ms).
+    Rx(u64, &'static str),
+    /// A frame transmitted by the instance and confirmed on the bus.
+    Tx(&'static str),
+    /// A frame transmitted by the instance and never confirmed.
+    TxLost(&'static str),
+    /// Write the data by a thread.
+    Write(&'static str),
+    /// Wait for the writing, `Ok` or the error.
+    Written(&'static str),
+    Sleep(u64),
+}
+use Step::*;
+
+struct Scenario {
+    name: &'static str,
+    /// The block size and STmin replied by the instance.
+    flow_ctrl: (u8, u8),
+    steps: &'static [Step],
+    events: &'static [&'static str],
+    /// The duration(ms) of the writing, STmin separates the consecutive frames and the first one
+    /// may be transmitted on the flow control.
+    duration: Range<u64>,
+}
+
+impl Scenario {
+    const fn new(name: &'static str, steps: &'static [Step], events: &'static [&'static str]) -> Self {
+        Self { name, flow_ctrl: (0, 0), steps, events, duration: 0..u64::MAX }
+    }
+
+    const fn flow_ctrl(mut self, block_size: u8, st_min: u8) -> Self {
+        self.flow_ctrl = (block_size, st_min);
+        self
+    }
+
+    const fn duration(mut self, duration: Range<u64>) -> Self {
+        self.duration = duration;
+        self
+    }
+}
+
+/// Records the events compactly.
+#[derive(Clone, Default)]
+struct Events(Arc<Mutex<Vec<String>>>);
+
+impl IsoTpEventListener for Events {
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        let event = match event {
+            IsoTpEvent::Wait => "Wait".into(),
+            IsoTpEvent::FirstFrameReceived { .. } => "FF".into(),
+            IsoTpEvent::DataReceived { data, .. } => format!("Data {}", hex(&data)),
+            IsoTpEvent::ErrorOccurred(e) => format!("Error {}", error(&e)),
+            IsoTpEvent::ReceptionAborted { error: e, partial, expected } =>
+                format!("Aborted {} {}/{}", error(&e), partial.len(), expected),
+            IsoTpEvent::TxCompleted { bytes } => format!("TxDone {}", bytes),
+            IsoTpEvent::FlowControlReceived(ctx) => format!("FC< {}", flow_ctrl(&ctx)),
+            IsoTpEvent::FlowControlSent(ctx) => format!("FC> {}", flow_ctrl(&ctx)),
+            IsoTpEvent::FlowControlDelayed { n_br } => format!("FC> late {:?}", n_br),
+        };
+        self.0.lock().unwrap().push(event);
+    }
+}
+
+fn hex(data: &[u8]) -> String {
+    data.iter()
+        .map(|v| format!("{:02X}", v))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn bytes(data: &str) -> Vec<u8> {
+    let (data, padded) = match data.strip_suffix("..") {
+        Some(v) => (v, true),
+        None => (data, false),
+    };
+    let mut result = data.split_whitespace()
+        .map(|v| u8::from_str_radix(v, 16).unwrap())
+        .collect::<Vec<_>>();
+    if padded {
+        result.resize(8, 0xAA);
+    }
+    result
+}
+
+/// The name of the error variant.
+fn error(e: &Error) -> String {
+    let name = format!("{:?}", e);
+    name.split([' ', '(', '{']).next().unwrap().to_string()
+}
+
+fn flow_ctrl(ctx: &FlowControlContext) -> String {
+    hex(&[0x30 | u8::from(ctx.state()), ctx.block_size(), ctx.raw_st_min()])
+}
+
+fn transmitted(receiver: &Receiver<CanMessage>, expected: &str, name: &str) -> CanMessage {
+    let frame = receiver.recv_timeout(Duration::from_secs(2))
+        .unwrap_or_else(|_| panic!("{}: `{}` is not transmitted", name, expected));
+    assert_eq!(frame.id().into_bits(), ADDRESS.tx_id, "{}", name);
+    assert_eq!(hex(frame.data()), hex(&bytes(expected)), "{}", name);
+    frame
+}
+
+fn run(scenario: &Scenario) {
+    let name = scenario.name;
+    let (sender, receiver) = channel();
+    let events = Events::default();
+    let (block_size, st_min) = scenario.flow_ctrl;
+    let mut iso_tp: SyncCanIsoTp<String, CanMessage> = SyncCanIsoTp::new(
+        CHANNEL.to_string(),
+        ADDRESS,
+        sender,
+        Box::new(events.clone()),
+    )
+        .with_verbosity(IsoTpVerbosity::Verbose)
+        .with_flow_control(FlowControlContext::new(FlowControlState::Continues, block_size, st_min).unwrap());
+
+    let mut writer: Option<JoinHandle<(Result<(), Error>, Duration)>> = None;
+    for step in scenario.steps {
+        match *step {
+            Rx(offset, data) => {
+                let mut frame = CanMessage::new(Id::Standard(ADDRESS.rx_id as u16), &bytes(data)).unwrap();
+                frame.set_timestamp(Some(EPOCH + offset))
+                    .set_direct(Direct::Receive);
+                iso_tp.on_frame_received(CHANNEL.to_string(), &[frame]);
+            },
+            Tx(data) => {
+                let frame = transmitted(&receiver, data, name);
+                iso_tp.on_frame_transmitted(CHANNEL.to_string(), &frame);
+            },
+            TxLost(data) => {
+                transmitted(&receiver, data, name);
+            },
+            Write(data) => {
+                let iso_tp = iso_tp.clone();
+                let data = bytes(data);
+                writer = Some(spawn(move || {
+                    let start = Instant::now();
+                    let result = iso_tp.write(false, data);
+                    (result, start.elapsed())
+                }));
+            },
+            Written(expected) => {
+                let (result, elapsed) = writer.take()
+                    .unwrap_or_else(|| panic!("{}: not writing", name))
+                    .join()
+                    .unwrap();
+                let result = match result {
+                    Ok(()) => "Ok".to_string(),
+                    Err(e) => error(&e),
+                };
+                assert_eq!(result, expected, "{}", name);
+                let elapsed = elapsed.as_millis() as u64;
+                assert!(scenario.duration.contains(&elapsed), "{}: written in {}ms", name, elapsed);
+            },
+            Sleep(ms) => sleep(Duration::from_millis(ms)),
+        }
+    }
+
+    assert!(writer.is_none(), "{}: the writing is not waited", name);
+    if let Ok(frame) = receiver.try_recv() {
+        panic!("{}: unexpected frame transmitted: {}", name, hex(frame.data()));
+    }
+    assert_eq!(*events.0.lock().unwrap(), scenario.events, "{}", name);
+}
+
+const RECEIVING: &[Scenario] = &[
+    Scenario::new("SF", &[
+        Rx(0, "02 3E 00 .."),
+    ], &["Data 3E 00"]),
+    Scenario::new("SF of 7 bytes", &[
+        Rx(0, "07 01 02 03 04 05 06 07"),
+    ], &["Data 01 02 03 04 05 06 07"]),
+    #[cfg(feature = "std2016")]
+    Scenario::new("SF escape of 2016", &[
+        Rx(0, "00 03 01 02 03 .."),
+    ], &["Data 01 02 03"]),
+    #[cfg(not(feature = "std2016"))]
+    Scenario::new("SF escape ignored by 2004", &[
+        Rx(0, "00 03 01 02 03 .."),
+    ], &[]),
+    Scenario::new("FF and CF", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "21 07 08 09 0A 0B 0C 0D"),
+        Rx(2, "22 0E 0F 10 11 12 13 14"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Wait",
+        "Data 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14",
+    ]),
+    #[cfg(feature = "std2016")]
+    Scenario::new("FF escape of 2016", &[
+        Rx(0, "10 00 00 00 10 00 01 02"),
+        Tx("30 00 00 .."),
+    ], &["FF", "FC> 30 00 00"]),
+    #[cfg(not(feature = "std2016"))]
+    Scenario::new("FF escape ignored by 2004", &[
+        Rx(0, "10 00 00 00 10 00 01 02"),
+    ], &[]),
+    Scenario::new("BS boundary", &[
+        Rx(0, "10 1E 01 02 03 04 05 06"),
+        Tx("30 02 00 .."),
+        Rx(1, "21 07 08 09 0A 0B 0C 0D"),
+        Rx(2, "22 0E 0F 10 11 12 13 14"),
+        Tx("30 02 00 .."),
+        Rx(3, "23 15 16 17 18 19 1A 1B"),
+        Rx(4, "24 1C 1D 1E .."),
+    ], &[
+        "FF", "FC> 30 02 00",
+        "Wait",
+        "Wait", "FC> 30 02 00",
+        "Wait",
+        "Data 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14 15 16 17 18 19 1A 1B 1C 1D 1E",
+    ]).flow_ctrl(2, 0),
+    Scenario::new("STmin replied", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 F5 .."),
+    ], &["FF", "FC> 30 00 F5"]).flow_ctrl(0, 0xF5),
+];
+
+const UNEXPECTED: &[Scenario] = &[
+    Scenario::new("CF while idle ignored", &[
+        Rx(0, "21 01 02 03 04 05 06 07"),
+        Rx(1, "02 3E 00 .."),
+    ], &["Data 3E 00"]),
+    Scenario::new("FC while idle ignored", &[
+        Rx(0, "30 00 00 .."),
+        Rx(1, "32 00 00 .."),
+        Rx(2, "02 3E 00 .."),
+    ], &["Data 3E 00"]),
+    Scenario::new("unknown PCI ignored", &[
+        Rx(0, "10 0D 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "40 AA AA AA AA AA AA AA"),
+        Rx(2, "21 07 08 09 0A 0B 0C 0D"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Data 01 02 03 04 05 06 07 08 09 0A 0B 0C 0D",
+    ]),
+    Scenario::new("SF terminates the receiving", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "21 07 08 09 0A 0B 0C 0D"),
+        Rx(2, "02 3E 00 .."),
+        Rx(3, "22 0E 0F 10 11 12 13 14"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Wait",
+        "Aborted ReceptionRestarted 13/20",
+        "Data 3E 00",
+    ]),
+    Scenario::new("FF restarts the receiving", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "10 0D 81 82 83 84 85 86"),
+        Tx("30 00 00 .."),
+        Rx(2, "21 87 88 89 8A 8B 8C 8D"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Aborted ReceptionRestarted 6/20",
+        "FF", "FC> 30 00 00",
+        "Data 81 82 83 84 85 86 87 88 89 8A 8B 8C 8D",
+    ]),
+    Scenario::new("wrong SN", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "22 07 08 09 0A 0B 0C 0D"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Aborted InvalidSequence 6/20",
+    ]),
+];
+
+const SENDING: &[Scenario] = &[
+    Scenario::new("SF", &[
+        Write("3E 00"),
+        Tx("02 3E 00 .."),
+        Written("Ok"),
+    ], &["TxDone 2"]),
+    Scenario::new("FF and CF", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "30 00 00 .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Written("Ok"),
+    ], &["FC< 30 00 00", "TxDone 20"]),
+    Scenario::new("BS boundary", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14 15 16 17 18 19 1A 1B 1C 1D 1E"),
+        Tx("10 1E 01 02 03 04 05 06"),
+        Rx(0, "30 02 00 .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Rx(1, "30 02 00 .."),
+        Tx("23 15 16 17 18 19 1A 1B"),
+        Tx("24 1C 1D 1E .."),
+        Written("Ok"),
+    ], &["FC< 30 02 00", "FC< 30 02 00", "TxDone 30"]),
+    Scenario::new("STmin of ms", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "30 00 14 .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Written("Ok"),
+    ], &["FC< 30 00 14", "TxDone 20"]).duration(20..1000),
+    Scenario::new("STmin of μs", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14 15 16 17 18 19 1A 1B"),
+        Tx("10 1B 01 02 03 04 05 06"),
+        Rx(0, "30 00 F9 .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Tx("23 15 16 17 18 19 1A 1B"),
+        Written("Ok"),
+    ], &["FC< 30 00 F9", "TxDone 27"]).duration(1..1000),
+    Scenario::new("reserved STmin taken as 7F", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "30 00 FB .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Written("Ok"),
+    ], &["FC< 30 00 FB", "TxDone 20"]).duration(127..1000),
+    Scenario::new("FC WAIT", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "31 00 00 .."),
+        Rx(1, "30 00 00 .."),
+        Tx("21 07 08 09 0A 0B 0C 0D"),
+        Tx("22 0E 0F 10 11 12 13 14"),
+        Written("Ok"),
+    ], &["FC< 31 00 00", "Wait", "FC< 30 00 00", "TxDone 20"]),
+    Scenario::new("FC overload", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "32 00 00 .."),
+        Written("OverloadFlow"),
+    ], &["FC< 32 00 00", "Error OverloadFlow"]),
+    Scenario::new("invalid FS", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Rx(0, "33 00 00 .."),
+        Written("InvalidFlowStatus"),
+    ], &["Error InvalidFlowStatus"]),
+];
+
+const TIMEOUTS: &[Scenario] = &[
+    Scenario::new("N_As", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        TxLost("10 14 01 02 03 04 05 06"),
+        Written("Timeout"),
+    ], &[]).duration(1000..3000),
+    Scenario::new("N_Bs", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Written("Timeout"),
+    ], &[]).duration(1000..3000),
+    Scenario::new("N_Bs restarted by FC WAIT", &[
+        Write("01 02 03 04 05 06 07 08 09 0A 0B 0C 0D 0E 0F 10 11 12 13 14"),
+        Tx("10 14 01 02 03 04 05 06"),
+        Sleep(600),
+        Rx(600, "31 00 00 .."),
+        Written("Timeout"),
+    ], &["FC< 31 00 00", "Wait"]).duration(1600..3000),
+    Scenario::new("N_Cr", &[
+        Rx(0, "10 14 01 02 03 04 05 06"),
+        Tx("30 00 00 .."),
+        Rx(1, "21 07 08 09 0A 0B 0C 0D"),
+        Rx(1002, "22 0E 0F 10 11 12 13 14"),
+    ], &[
+        "FF", "FC> 30 00 00",
+        "Wait",
+        "Aborted Timeout 13/20",
+    ]),
+];
+
+#[test]
+fn test_receiving() {
+    RECEIVING.iter().for_each(run);
+}
+
+#[test]
+fn test_unexpected_pdu() {
+    UNEXPECTED.iter().for_each(run);
+}
+
+#[test]
+fn test_sending() {
+    SENDING.iter().for_each(run);
+}
+
+#[test]
+fn test_timeouts() {
+    // the timeouts of the sender run in real time, each scenario by a thread
+    let threads = TIMEOUTS.iter()
+        .map(|v| spawn(move || run(v)))
+        .collect::<Vec<_>>();
+    for thread in threads {
+        if let Err(e) = thread.join() {
+            std::panic::resume_unwind(e);
+        }
+    }
+}
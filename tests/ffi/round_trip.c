@@ -0,0 +1,91 @@
+/*
+ * Round trip of the C ABI against the mock bus.
+ *
+ *   cargo rustc --lib --features ffi,mock --crate-type staticlib
+ *   cc -DISOTP_RS_MOCK -Iinclude tests/ffi/round_trip.c target/debug/libisotp_rs.a -lpthread -ldl -lm -o target/round_trip
+ *   ./target/round_trip
+ */
+#include <assert.h>
+#include <stdio.h>
+#include <string.h>
+#include <unistd.h>
+
+#include "isotp_rs.h"
+
+typedef struct Server {
+    IsoTpHandle *handle;
+    uint8_t received[64];
+    size_t received_len;
+} Server;
+
+/* echo the request reversed in a single frame */
+static void on_server_event(int event, const uint8_t *data, size_t len, void *user) {
+    Server *server = (Server *)user;
+    if (event != ISOTP_EVENT_DATA_RECEIVED || len > sizeof(server->received)) {
+        return;
+    }
+
+    uint8_t response[7];
+    size_t response_len = len < sizeof(response) ? len : sizeof(response);
+    for (size_t i = 0; i < response_len; i++) {
+        response[i] = data[len - 1 - i];
+    }
+    memcpy(server->received, data, len);
+    server->received_len = len;
+    isotp_write(server->handle, response, response_len, false);
+}
+
+static IsoTpConfig config(const IsoTpMockBus *bus, size_t endpoint, uint32_t tx_id, uint32_t rx_id) {
+    IsoTpConfig config = {
+        .driver = IsoTpDriverKind_Mock,
+        .channel = NULL,
+        .tx_id = tx_id,
+        .rx_id = rx_id,
+        .fid = 0x7DF,
+        .verbose = false,
+        .transmit = NULL,
+        .transmit_user = NULL,
+        .mock_bus = bus,
+        .mock_endpoint = endpoint,
+    };
+    return config;
+}
+
+int main(void) {
+    IsoTpMockBus *bus = isotp_mock_bus_create(2);
+    IsoTpConfig client_config = config(bus, 0, 0x7E0, 0x7E8);
+    IsoTpConfig server_config = config(bus, 1, 0x7E8, 0x7E0);
+    IsoTpHandle *client = isotp_create(&client_config);
+    Server server = { .handle = isotp_create(&server_config), .received_len = 0 };
+    assert(client != NULL && server.handle != NULL);
+    assert(isotp_set_listener(server.handle, on_server_event, &server) == 0);
+
+    /* single frame */
+    const uint8_t request[] = { 0x22, 0xF1, 0x90 };
+    uint8_t response[8];
+    int len = isotp_request(client, request, sizeof(request), response, sizeof(response), 1000);
+    assert(len == 3);
+    assert(response[0] == 0x90 && response[1] == 0xF1 && response[2] == 0x22);
+
+    /* multi frames */
+    uint8_t data[0x20];
+    for (size_t i = 0; i < sizeof(data); i++) {
+        data[i] = (uint8_t)i;
+    }
+    len = isotp_request(client, data, sizeof(data), response, sizeof(response), 1000);
+    assert(len == 7);
+    assert(server.received_len == sizeof(data) && memcmp(server.received, data, sizeof(data)) == 0);
+    assert(response[0] == 0x1F && response[6] == 0x19);
+
+    /* no response */
+    isotp_set_listener(server.handle, NULL, NULL);
+    len = isotp_request(client, request, sizeof(request), response, sizeof(response), 50);
+    assert(len == -ISOTP_ERROR_TIMEOUT);
+
+    isotp_destroy(client);
+    isotp_destroy(server.handle);
+    isotp_mock_bus_destroy(bus);
+
+    printf("round trip ok\n");
+    return 0;
+}
@@ -0,0 +1,136 @@
+//! The sync and async ISO-TP stacks run the same round trip against a `SyncCanIsoTp` server over
+//! the mock bus, both are registered to `SyncCan` as `Listener<String, u32, CanMessage>`.
+#![cfg(not(feature = "async"))]
+
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::Sender;
+use std::thread::sleep;
+use std::time::{Duration, Instant};
+use isotp_rs::{IsoTpEvent, IsoTpEventListener};
+use isotp_rs::can::Address;
+use isotp_rs::can::driver::{SyncCan, VirtualBus, MOCK_CHANNEL};
+use isotp_rs::can::isotp::SyncCanIsoTp;
+use isotp_rs::can::message::CanMessage;
+use isotp_rs::device::Listener;
+use isotp_rs::error::Error;
+
+const CLIENT: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+const SERVER: Address = Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF };
+
+/// Records the received data.
+#[derive(Clone, Default)]
+struct Received(Arc<Mutex<Vec<Vec<u8>>>>);
+
+impl Received {
+    fn wait(&self, count: usize) -> Vec<Vec<u8>> {
+        let start = Instant::now();
+        while self.0.lock().unwrap().len() < count && start.elapsed() < Duration::from_secs(1) {
+            sleep(Duration::from_millis(1));
+        }
+        self.0.lock().unwrap().drain(..).collect()
+    }
+}
+
+impl IsoTpEventListener for Received {
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        if let IsoTpEvent::DataReceived { data, .. } = event {
+            self.0.lock().unwrap().push(data.to_vec());
+        }
+    }
+}
+
+/// A multi-frame request by the client and a single frame response by the server.
+fn round_trip<L>(
+    new_client: impl FnOnce(Sender<CanMessage>, Received) -> L,
+    write: impl Fn(&L, Vec<u8>) -> Result<(), Error>,
+) -> anyhow::Result<()>
+where
+    L: Listener<String, u32, CanMessage> + Clone,
+{
+    let (a, b) = VirtualBus::pair();
+    let mut client_can = SyncCan::new(a);
+    let mut server_can = SyncCan::new(b);
+    let client_received = Received::default();
+    let client = new_client(client_can.sender(), client_received.clone());
+    let server_received = Received::default();
+    let server = SyncCanIsoTp::new(
+        MOCK_CHANNEL.to_string(),
+        SERVER,
+        server_can.sender(),
+        Box::new(server_received.clone()),
+    );
+    client_can.register_listener("client".into(), Box::new(client.clone()));
+    server_can.register_listener("server".into(), Box::new(server.clone()));
+    client_can.sync_start(100);
+    server_can.sync_start(100);
+
+    let request = (0..0x40).collect::<Vec<u8>>();
+    write(&client, request.clone())?;
+    assert_eq!(server_received.wait(1), vec![request]);
+
+    server.write(false, vec![0x50, 0x01])?;
+    assert_eq!(client_received.wait(1), vec![vec![0x50, 0x01]]);
+
+    client_can.stop();
+    server_can.stop();
+    Ok(())
+}
+
+#[test]
+fn test_sync_round_trip() -> anyhow::Result<()> {
+    round_trip(
+        |sender, listener| SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, sender, Box::new(listener)),
+        |client, data| client.write(false, data),
+    )
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_async_round_trip() -> anyhow::Result<()> {
+    use isotp_rs::can::isotp::AsyncCanIsoTp;
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    round_trip(
+        |sender, listener| AsyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, sender, Box::new(listener)),
+        |client, data| runtime.block_on(client.write(false, data)),
+    )
+}
+
+#[cfg(feature = "tokio")]
+#[test]
+fn test_async_request() -> anyhow::Result<()> {
+    use isotp_rs::can::isotp::AsyncCanIsoTp;
+
+    let (a, b) = VirtualBus::pair();
+    let mut client_can = SyncCan::new(a);
+    let mut server_can = SyncCan::new(b);
+    let client = AsyncCanIsoTp::new(MOCK_CHANNEL.to_string(), CLIENT, client_can.sender(), Box::new(Received::default()));
+    let server_received = Received::default();
+    let server = SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), SERVER, server_can.sender(), Box::new(server_received.clone()));
+    client_can.register_listener("client".into(), Box::new(client.clone()));
+    server_can.register_listener("server".into(), Box::new(server.clone()));
+    client_can.sync_start(100);
+    server_can.sync_start(100);
+
+    let runtime = tokio::runtime::Runtime::new()?;
+    let (p2, p2_star) = (Duration::from_millis(50), Duration::from_millis(500));
+    // not answered within P2
+    let result = runtime.block_on(client.request(vec![0x22, 0xF1, 0x90], p2, p2_star));
+    assert!(matches!(result, Err(Error::Timeout { value: 50, .. })), "{:?}", result);
+    assert_eq!(server_received.wait(1), vec![vec![0x22, 0xF1, 0x90]]);
+
+    // the response pending extends the time to P2*
+    let responder = std::thread::spawn(move || {
+        assert_eq!(server_received.wait(1), vec![vec![0x22, 0xF1, 0x90]]);
+        server.write(false, vec![0x7F, 0x22, 0x78]).unwrap();
+        sleep(Duration::from_millis(100));
+        server.write(false, (0..0x20).collect()).unwrap();
+    });
+    let response = runtime.block_on(client.request(vec![0x22, 0xF1, 0x90], p2, p2_star))?;
+    assert_eq!(response, (0..0x20).collect::<Vec<u8>>());
+    responder.join().unwrap();
+
+    client_can.stop();
+    server_can.stop();
+    Ok(())
+}
@@ -0,0 +1,245 @@
+//! The soak test of two ISO-TP endpoints over the mock bus, ignored by default:
+//!
+//! `cargo test --features mock --test soak -- --ignored --nocapture`
+//!
+//! The client writes the requests of random sizes and the server replies them, the flow controls
+//! of both are announced randomly by each epoch. The faults(lost and duplicated frames) are injected
+//! periodically and a tester writes the functional single frames concurrently. The invariants are
+//! asserted after each transfer. It runs for `ISOTP_SOAK_SECS`(60 by default) in real time.
+#![cfg(not(feature = "async"))]
+
+use std::sync::{Arc, Mutex};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::thread::{sleep, spawn};
+use std::time::{Duration, Instant};
+use isotp_rs::{FlowControlContext, FlowControlState, IsoTpEvent, IsoTpEventListener};
+use isotp_rs::can::{Address, AddressFormat};
+use isotp_rs::can::driver::{LinkConfig, MockDriver, SyncCan, VirtualBus, MOCK_CHANNEL};
+use isotp_rs::can::frame::FrameMut;
+use isotp_rs::can::identifier::Id;
+use isotp_rs::can::isotp::SyncCanIsoTp;
+use isotp_rs::can::matcher::RxMatcher;
+use isotp_rs::can::message::CanMessage;
+use isotp_rs::device::Driver;
+
+const CLIENT: Address = Address { tx_id: 0x7E0, rx_id: 0x7E8, fid: 0x7DF };
+const SERVER: Address = Address { tx_id: 0x7E8, rx_id: 0x7E0, fid: 0x7DF };
+/// The data of the functional single frames by the tester.
+const FUNCTIONAL: [u8; 2] = [0x3E, 0x80];
+/// The first byte of the physical data, so it's never taken as the functional one.
+const PHYSICAL: u8 = 0x36;
+const EPOCH: usize = 16;
+/// Each transfer of the period is faulty.
+const FAULT_PERIOD: usize = 5;
+
+/// The data received and the receptions aborted.
+#[derive(Clone, Default)]
+struct Received {
+    data: Arc<Mutex<Vec<Vec<u8>>>>,
+    functional: Arc<AtomicUsize>,
+    aborted: Arc<AtomicUsize>,
+}
+
+impl Received {
+    fn take(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut *self.data.lock().unwrap())
+    }
+}
+
+impl IsoTpEventListener for Received {
+    fn on_iso_tp_event(&mut self, event: IsoTpEvent) {
+        match event {
+            IsoTpEvent::DataReceived { data, .. } if *data == FUNCTIONAL => {
+                self.functional.fetch_add(1, Ordering::Relaxed);
+            },
+            IsoTpEvent::DataReceived { data, .. } => self.data.lock().unwrap().push(data.to_vec()),
+            IsoTpEvent::ReceptionAborted { .. } => {
+                self.aborted.fetch_add(1, Ordering::Relaxed);
+            },
+            _ => {},
+        }
+    }
+}
+
+/// xorshift
+struct Rng(u64);
+
+impl Rng {
+    fn next(&mut self, bound: u64) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0 % bound
+    }
+
+    fn data(&mut self, max: u64) -> Vec<u8> {
+        let len = 1 + self.next(max) as usize;
+        let mut data = (0..len).map(|_| self.next(0x100) as u8).collect::<Vec<_>>();
+        data[0] = PHYSICAL;
+        data
+    }
+
+    /// A random flow control, STmin of 0 ~ 2ms or 100 ~ 900μs.
+    fn flow_ctrl(&mut self) -> FlowControlContext {
+        let st_min = match self.next(4) {
+            0 => 0xF1 + self.next(9) as u8,
+            _ => self.next(3) as u8,
+        };
+        FlowControlContext::new(FlowControlState::Continues, self.next(9) as u8, st_min).unwrap()
+    }
+}
+
+struct Endpoint {
+    can: SyncCan<MockDriver, String, CanMessage>,
+    iso_tp: SyncCanIsoTp<String, CanMessage>,
+    received: Received,
+}
+
+impl Endpoint {
+    fn new(driver: MockDriver, address: Address, flow_ctrl: FlowControlContext) -> Self {
+        let mut can = SyncCan::new(driver);
+        can.sync_start(100);
+        let received = Received::default();
+        let iso_tp = Self::iso_tp(&can, address, flow_ctrl, &received);
+        can.register_listener("iso-tp".into(), Box::new(iso_tp.clone()));
+        Self { can, iso_tp, received }
+    }
+
+    fn iso_tp(
+        can: &SyncCan<MockDriver, String, CanMessage>,
+        address: Address,
+        flow_ctrl: FlowControlContext,
+        received: &Received,
+    ) -> SyncCanIsoTp<String, CanMessage> {
+        SyncCanIsoTp::new(MOCK_CHANNEL.to_string(), address, can.sender(), Box::new(received.clone()))
+            .with_flow_control(flow_ctrl)
+            .with_rx_matcher(RxMatcher::new(address, AddressFormat::Normal).with_functional(true))
+            .with_tx_generations(can.tx_generations())
+    }
+
+    /// Replace the ISO-TP instance with the one announcing `flow_ctrl`, the loops keep running.
+    fn renew(&mut self, address: Address, flow_ctrl: FlowControlContext) {
+        self.iso_tp = Self::iso_tp(&self.can, address, flow_ctrl, &self.received);
+        self.can.register_listener("iso-tp".into(), Box::new(self.iso_tp.clone()));
+    }
+
+    /// Wait for the instance to be idle, the receiving left by a lost frame expires after N_Cr.
+    fn wait_idle(&self, name: &str) {
+        let start = Instant::now();
+        while !self.iso_tp.is_idle() {
+            assert!(start.elapsed() < Duration::from_secs(3),
+                "{} is not idle: {} {:?}", name, self.iso_tp.state(), self.iso_tp.rx_in_progress());
+            sleep(Duration::from_millis(1));
+        }
+    }
+}
+
+fn received_within(received: &Received, timeout: Duration) -> Vec<Vec<u8>> {
+    let start = Instant::now();
+    while received.data.lock().unwrap().is_empty() && start.elapsed() < timeout {
+        sleep(Duration::from_millis(1));
+    }
+    received.take()
+}
+
+#[test]
+#[ignore]
+fn test_soak() {
+    let secs = std::env::var("ISOTP_SOAK_SECS").ok()
+        .and_then(|v| v.parse().ok())
+        .unwrap_or(60);
+    let bus = VirtualBus::new(3);
+    bus.set_seed(0x1925_0409);
+    let mut rng = Rng(0x0DDB_1A5E_5BAD_5EED);
+    let mut client = Endpoint::new(bus.driver(0), CLIENT, rng.flow_ctrl());
+    let mut server = Endpoint::new(bus.driver(1), SERVER, rng.flow_ctrl());
+
+    // the functional single frames of the tester
+    let running = Arc::new(AtomicBool::new(true));
+    let functional = Arc::new(AtomicUsize::new(0));
+    let tester = {
+        let (running, functional, driver) = (running.clone(), functional.clone(), bus.driver(2));
+        spawn(move || while running.load(Ordering::Relaxed) {
+            let mut frame = CanMessage::new(Id::Standard(CLIENT.fid as u16), &[0x02, FUNCTIONAL[0], FUNCTIONAL[1]]).unwrap();
+            frame.set_channel(MOCK_CHANNEL.into());
+            if driver.transmit(frame, None).is_ok() {
+                functional.fetch_add(1, Ordering::Relaxed);
+            }
+            sleep(Duration::from_millis(7));
+        })
+    };
+
+    let (mut transfers, mut faulty, mut failed) = (0, 0, 0);
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(secs) {
+        if transfers > 0 && transfers % EPOCH == 0 {
+            client.renew(CLIENT, rng.flow_ctrl());
+            server.renew(SERVER, rng.flow_ctrl());
+        }
+
+        let fault = transfers % FAULT_PERIOD == FAULT_PERIOD - 1;
+        let link = match fault {
+            true => LinkConfig { loss_rate: 0.02, duplicate_rate: 0.05, ..Default::default() },
+            false => LinkConfig::default(),
+        };
+        bus.set_link(0, 1, link);
+        bus.set_link(1, 0, link);
+        transfers += 1;
+        faulty += fault as usize;
+
+        let request = rng.data(600);
+        let response = rng.data(300);
+        match client.iso_tp.write(false, request.clone()) {
+            Ok(()) => {
+                let received = received_within(&server.received, Duration::from_secs(1));
+                if !fault {
+                    assert_eq!(received, vec![request.clone()], "transfer {}", transfers);
+                }
+                // a duplicated single frame is received twice
+                assert!(received.iter().all(|v| *v == request), "transfer {}", transfers);
+
+                match server.iso_tp.write(false, response.clone()) {
+                    Ok(()) => {
+                        let received = received_within(&client.received, Duration::from_secs(1));
+                        if !fault {
+                            assert_eq!(received, vec![response.clone()], "transfer {}", transfers);
+                        }
+                        assert!(received.iter().all(|v| *v == response), "transfer {}", transfers);
+                    },
+                    Err(e) => {
+                        assert!(fault, "transfer {}: {}", transfers, e);
+                        failed += 1;
+                    },
+                }
+            },
+            Err(e) => {
+                assert!(fault, "transfer {}: {}", transfers, e);
+                failed += 1;
+            },
+        }
+
+        client.wait_idle("client");
+        server.wait_idle("server");
+        client.received.take();
+        server.received.take();
+        // the queues are drained
+        for (name, endpoint, driver, id) in [("client", &client, bus.driver(0), CLIENT.tx_id), ("server", &server, bus.driver(1), SERVER.tx_id)] {
+            let queued = endpoint.can.tx_generations().queued(id);
+            assert!(queued == 0, "{}: {} frames queued after transfer {}", name, queued, transfers);
+            assert!(driver.pending() < 64, "{}: {} frames pending after transfer {}", name, driver.pending(), transfers);
+        }
+    }
+
+    running.store(false, Ordering::Relaxed);
+    tester.join().unwrap();
+    sleep(Duration::from_millis(20));
+    let functional_received = server.received.functional.load(Ordering::Relaxed);
+    let aborted = client.received.aborted.load(Ordering::Relaxed) + server.received.aborted.load(Ordering::Relaxed);
+    println!("soak: {} transfers, {} faulty, {} failed, {} receptions aborted, {}/{} functional frames",
+        transfers, faulty, failed, aborted, functional_received, functional.load(Ordering::Relaxed));
+    // the functional frames are never lost nor terminated by the transfers
+    assert_eq!(functional_received, functional.load(Ordering::Relaxed));
+
+    client.can.stop();
+    server.can.stop();
+}
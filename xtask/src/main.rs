@@ -0,0 +1,38 @@
+//! The tasks of the repository, run by `cargo xtask <task>`:
+//!
+//! - `header`: generate the C header of the `ffi` module to `include/isotp_rs.h`.
+
+use std::path::{Path, PathBuf};
+
+fn main() {
+    match std::env::args().nth(1).as_deref() {
+        Some("header") => header(),
+        _ => {
+            eprintln!("usage: cargo xtask header");
+            std::process::exit(1);
+        },
+    }
+}
+
+/// Generate the C header of the `ffi` module to `include/isotp_rs.h`, it's committed with the changes of the C ABI.
+fn header() {
+    let root = root();
+    let config = cbindgen::Config::from_file(root.join("cbindgen.toml"))
+        .expect("cbindgen.toml");
+    // the error codes are exported with the items of `ffi`
+    let bindings = cbindgen::Builder::new()
+        .with_config(config)
+        .with_src(root.join("src/ffi.rs"))
+        .with_src(root.join("src/error.rs"))
+        .generate()
+        .expect("generate the C header");
+    bindings.write_to_file(root.join("include/isotp_rs.h"));
+}
+
+/// The directory of the crate `isotp-rs`.
+fn root() -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR"))
+        .parent()
+        .expect("the parent of xtask")
+        .to_path_buf()
+}